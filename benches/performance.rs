@@ -1,11 +1,13 @@
-use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use criterion::{
+    BatchSize, BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main,
+};
 use md_crdt::core::{OpId, Sequence, SequenceOp, StateVector};
 use md_crdt::doc::{
     Block, BlockKind, ColumnAlignment, ColumnDef, Document, EquivalenceMode, Parser, TextUnit,
     block_id_from_op, units_from_str_at,
 };
 use md_crdt::filesync::VaultSession;
-use md_crdt::sync::{Operation, SyncState};
+use md_crdt::sync::{ChangeMessage, Operation, SyncState};
 use md_crdt::{
     BlockDraft, CheckpointRequest, CodeFenceStyle, CollaborativeDocument, DocumentTombstonePolicy,
     EditBatch, ListItemDraft, ListStyle, ProjectionFields, ProjectionRequest, StructuredEditLimits,
@@ -132,11 +134,72 @@ fn state_vector(c: &mut Criterion) {
     group.finish();
 }
 
+/// 100k operations from 10 peers in 10k-op messages: in order, newest first,
+/// and in order except that one peer's first op comes last, so its other ops
+/// sit in the pending buffer while everyone else's apply.
+fn change_ingestion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("change_ingestion");
+    group.sample_size(10);
+    let ops: Vec<Operation> = (0..100_000usize)
+        .map(|index| Operation {
+            id: op((index / 10) as u64 + 1, (index % 10) as u64 + 1),
+            payload: vec![0; 8].into(),
+        })
+        .collect();
+    let mut backfill = ops.clone();
+    backfill.reverse();
+    let mut stalled_peer = ops.clone();
+    let first = stalled_peer.remove(0);
+    stalled_peer.push(first);
+    group.throughput(Throughput::Elements(ops.len() as u64));
+    let orders = [
+        ("in_order", ops),
+        ("backfill", backfill),
+        ("stalled_peer", stalled_peer),
+    ];
+    for (name, order) in orders {
+        group.bench_function(BenchmarkId::new(name, 100_000), |b| {
+            b.iter_batched(
+                || order.clone(),
+                |ops| {
+                    let mut sync = SyncState::new();
+                    for chunk in ops.chunks(10_000) {
+                        sync.apply_changes(ChangeMessage {
+                            since: StateVector::new(),
+                            ops: chunk.to_vec(),
+                            gaps: Vec::new(),
+                        })
+                        .unwrap();
+                    }
+                    assert_eq!(sync.pending_count(), 0);
+                    sync
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
 fn encode_changes(c: &mut Criterion) {
     let mut group = c.benchmark_group("encode_changes_since");
     for payload_size in [32usize, 1_024] {
         let sync = sync_with_ops(10_000, 10, payload_size);
         let since = StateVector::new();
+        // Payloads are shared with the op log, so a full-sync message allocates
+        // its operation list but none of the payload bytes.
+        let (message, message_allocated) =
+            allocated_bytes(|| sync.encode_changes_since(&since).unwrap());
+        let (_, applied_allocated) = allocated_bytes(|| sync.applied_ops());
+        let payload_bytes = 10_000 * payload_size;
+        assert_eq!(message.ops.len(), 10_000);
+        if payload_size >= 1_024 {
+            assert!(message_allocated.saturating_mul(4) <= payload_bytes);
+            assert!(applied_allocated.saturating_mul(4) <= payload_bytes);
+        }
+        println!(
+            "encode_changes_memory payload_size={payload_size} payload_bytes={payload_bytes} message_allocated={message_allocated} applied_ops_allocated={applied_allocated}"
+        );
         group.throughput(Throughput::Bytes((10_000 * payload_size) as u64));
         group.bench_with_input(
             BenchmarkId::from_parameter(payload_size),
@@ -215,6 +278,67 @@ fn nested_text_insert(c: &mut Criterion) {
     group.finish();
 }
 
+/// Blockquotes nested `depth` deep, each holding `siblings` paragraphs before the
+/// next level. Returns the document and the deepest paragraph's block id.
+fn nested_quotes(depth: usize, siblings: usize) -> (Document, md_crdt::BlockId) {
+    let mut document = Document::new();
+    let mut counter = 0;
+    let mut next_id = || {
+        counter += 1;
+        op(counter, 1)
+    };
+    let mut parent = None;
+    let mut deepest = None;
+    for _ in 0..depth {
+        let mut after = None;
+        for _ in 0..siblings {
+            let id = next_id();
+            let block = Block::new(BlockKind::paragraph(&"x".repeat(32), next_id()), id);
+            deepest = Some(block.id);
+            document.insert_block_at(parent, after, id, block, None);
+            after = Some(id);
+            for _ in 0..32 {
+                next_id();
+            }
+        }
+        let quote = next_id();
+        let children = Sequence::new();
+        let block = Block::new(BlockKind::BlockQuote { children }, quote);
+        document.insert_block_at(parent, after, quote, block, None);
+        parent = Some(quote);
+    }
+    (document, deepest.unwrap())
+}
+
+fn nested_container_edit(c: &mut Criterion) {
+    // Sixteen keystrokes into the deepest paragraph.
+    let mut group = c.benchmark_group("nested_container_edit");
+    for depth in [4usize, 32] {
+        let (base, block_id) = nested_quotes(depth, 64);
+        // Index the tree once so only the edit is measured.
+        base.find_block_by_id(block_id);
+        group.throughput(Throughput::Elements(16));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, _| {
+            b.iter_custom(|iterations| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iterations {
+                    let mut document = base.clone();
+                    document.find_block_by_id(block_id);
+                    let start = Instant::now();
+                    for counter in 0..16 {
+                        let id = op(1_000_000 + counter, 2);
+                        black_box(document.insert_text(block_id, 16, "y", id).unwrap());
+                    }
+                    elapsed += start.elapsed();
+                    black_box(document);
+                }
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
 fn session_insert_text(c: &mut Criterion) {
     let mut group = c.benchmark_group("session_insert_text");
     for count in [1_000usize, 10_000] {
@@ -253,6 +377,48 @@ fn document_serialize(c: &mut Criterion) {
     group.finish();
 }
 
+/// One edited block out of 5k: `cached` re-renders only that block, `cold` renders
+/// a fresh clone whose render cache is empty.
+fn document_serialize_after_edit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("document_serialize_after_edit");
+    let source = (0..5_000)
+        .map(|index| format!("Paragraph {index} with *some* inline `code`."))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let mut document = Parser::parse(&source);
+    let target = document.blocks_in_order()[2_500].id;
+    document.serialize(EquivalenceMode::Structural);
+    let mut counter = 1_000_000;
+    group.bench_function("cached_5000", |b| {
+        b.iter_custom(|iterations| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iterations {
+                counter += 1;
+                document
+                    .insert_text(target, 0, "y", op(counter, 9))
+                    .unwrap();
+                let start = Instant::now();
+                black_box(document.serialize(EquivalenceMode::Structural));
+                elapsed += start.elapsed();
+            }
+            elapsed
+        });
+    });
+    group.bench_function("cold_5000", |b| {
+        b.iter_custom(|iterations| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iterations {
+                let cold = document.clone();
+                let start = Instant::now();
+                black_box(cold.serialize(EquivalenceMode::Structural));
+                elapsed += start.elapsed();
+            }
+            elapsed
+        });
+    });
+    group.finish();
+}
+
 fn traverse_descriptors(vault: &mut VaultSession, path: &str, limit: usize) -> usize {
     let mut stack = vec![None];
     let mut visited = 0usize;
@@ -1126,11 +1292,14 @@ criterion_group!(
     benches,
     block_lookup,
     state_vector,
+    change_ingestion,
     encode_changes,
     sequence_insert_middle,
     nested_text_insert,
+    nested_container_edit,
     session_insert_text,
     document_serialize,
+    document_serialize_after_edit,
     workspace_hierarchy,
     checkpoint_history,
     workspace_edit_replay,
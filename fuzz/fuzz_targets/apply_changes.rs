@@ -23,6 +23,7 @@ fuzz_target!(|data: &[u8]| {
     let message = ChangeMessage {
         since: StateVector::new(),
         ops,
+        gaps: Vec::new(),
     };
     let mut doc = SyncState::new();
     let _ = doc.apply_changes(message);
@@ -1,10 +1,22 @@
 #![no_main]
 
+//! Fuzz target for the Markdown parser.
+//!
+//! Raw bytes are decoded lossily, so invalid UTF-8 reaches the parser as
+//! replacement characters. The oracles are: parsing never panics, serialization
+//! terminates, and structural serialization is a fixed point — re-parsing the
+//! output and serializing again yields the same text.
+
 use libfuzzer_sys::fuzz_target;
 use md_crdt::doc::{EquivalenceMode, Parser};
 
 fuzz_target!(|data: &[u8]| {
     let input = String::from_utf8_lossy(data);
     let doc = Parser::parse(&input);
-    let _ = doc.serialize(EquivalenceMode::Structural);
+    let once = doc.serialize(EquivalenceMode::Structural);
+    let twice = Parser::parse(&once).serialize(EquivalenceMode::Structural);
+    assert_eq!(
+        once, twice,
+        "structural round-trip diverged for input {input:?}"
+    );
 });
@@ -1,6 +1,6 @@
 //! A naive, simple oracle implementation for differential testing.
 use md_crdt::core::{OpId, SequenceOp, StateVector};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Element<T> {
@@ -13,12 +13,20 @@ struct Element<T> {
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Sequence<T> {
     elements: Vec<Element<T>>,
+    /// Inserts whose anchor or right origin has not been integrated yet.
+    waiting: Vec<Element<T>>,
+    /// Delete targets that had not arrived when they were deleted.
+    early_deletes: BTreeSet<OpId>,
+    ranges: Vec<(OpId, OpId, StateVector)>,
 }
 
 impl<T: Clone> Sequence<T> {
     pub fn new() -> Self {
         Self {
             elements: Vec::new(),
+            waiting: Vec::new(),
+            early_deletes: BTreeSet::new(),
+            ranges: Vec::new(),
         }
     }
 
@@ -31,94 +39,115 @@ impl<T: Clone> Sequence<T> {
                 right_origin,
             } => self.insert(after, value, id, right_origin),
             SequenceOp::Delete { target, .. } => self.delete(target),
+            SequenceOp::DeleteRange {
+                from, to, observed, ..
+            } => self.ranges.push((from, to, observed)),
         }
+        self.apply_ranges();
     }
 
     pub fn insert(&mut self, after: Option<OpId>, value: T, id: OpId, right_origin: Option<OpId>) {
-        if self.elements.iter().any(|elem| elem.id == id) {
+        let known = |elem: &Element<T>| elem.id == id;
+        if self.elements.iter().any(known) || self.waiting.iter().any(known) {
             return;
         }
-
-        self.elements.insert(
-            0,
-            Element {
-                id,
-                value: Some(value),
-                after,
-                right_origin,
-            },
-        );
-        self.rebuild_order();
-    }
-
-    pub fn delete(&mut self, target: OpId) {
-        if let Some(elem) = self.elements.iter_mut().find(|elem| elem.id == target) {
-            elem.value = None;
+        self.waiting.push(Element {
+            id,
+            value: Some(value),
+            after,
+            right_origin,
+        });
+        while let Some(ready) = self.waiting.iter().position(|elem| {
+            [elem.after, elem.right_origin]
+                .into_iter()
+                .flatten()
+                .all(|dependency| self.position(dependency).is_some())
+        }) {
+            let elem = self.waiting.remove(ready);
+            self.integrate(elem);
         }
     }
 
-    pub fn elements(&self) -> Vec<T> {
-        self.elements
-            .iter()
-            .filter_map(|elem| elem.value.clone())
-            .collect()
+    fn position(&self, id: OpId) -> Option<usize> {
+        self.elements.iter().position(|elem| elem.id == id)
     }
 
-    fn rebuild_order(&mut self) {
-        let mut element_map: BTreeMap<OpId, Element<T>> = BTreeMap::new();
-        for elem in self.elements.drain(..) {
-            element_map.insert(elem.id, elem);
+    /// YATA integration as the Yjs reference implementation writes it, with
+    /// explicit sets instead of position comparisons. Higher ids go first.
+    fn integrate(&mut self, mut item: Element<T>) {
+        if self.early_deletes.remove(&item.id) {
+            item.value = None;
         }
-
-        let mut children: BTreeMap<Option<OpId>, Vec<OpId>> = BTreeMap::new();
-        for elem in element_map.values() {
-            children.entry(elem.after).or_default().push(elem.id);
-        }
-
-        for ids in children.values_mut() {
-            ids.sort_by(|a, b| {
-                let elem_a = element_map
-                    .get(a)
-                    .expect("child id must exist in element map during rebuild");
-                let elem_b = element_map
-                    .get(b)
-                    .expect("child id must exist in element map during rebuild");
-                match (elem_a.right_origin, elem_b.right_origin) {
-                    (Some(ra), Some(rb)) => {
-                        if ra == rb {
-                            b.cmp(a)
-                        } else {
-                            ra.cmp(&rb)
-                        }
-                    }
-                    (Some(_), None) => std::cmp::Ordering::Less,
-                    (None, Some(_)) => std::cmp::Ordering::Greater,
-                    (None, None) => b.cmp(a),
+        let origin = item.after.and_then(|id| self.position(id));
+        let start = origin.map_or(0, |origin| origin + 1);
+        let right = item
+            .right_origin
+            .and_then(|id| self.position(id))
+            .filter(|&right| right >= start)
+            .unwrap_or(self.elements.len());
+
+        let mut left = origin;
+        let mut items_before_origin = BTreeSet::new();
+        let mut conflicting_items = BTreeSet::new();
+        for scan in start..right {
+            let other = &self.elements[scan];
+            items_before_origin.insert(other.id);
+            conflicting_items.insert(other.id);
+            if other.after == item.after {
+                if other.id > item.id {
+                    left = Some(scan);
+                    conflicting_items.clear();
+                } else if other.right_origin == item.right_origin {
+                    break;
                 }
-            });
+            } else if let Some(other_origin) = other.after
+                && items_before_origin.contains(&other_origin)
+            {
+                if !conflicting_items.contains(&other_origin) {
+                    left = Some(scan);
+                    conflicting_items.clear();
+                }
+            } else {
+                break;
+            }
         }
+        self.elements.insert(left.map_or(0, |left| left + 1), item);
+    }
 
-        let mut ordered_ids = Vec::with_capacity(element_map.len());
-        Self::walk_children(None, &children, &mut ordered_ids);
-
-        self.elements = ordered_ids
-            .into_iter()
-            .filter_map(|id| element_map.remove(&id))
-            .collect();
+    pub fn delete(&mut self, target: OpId) {
+        if let Some(elem) = self
+            .elements
+            .iter_mut()
+            .chain(self.waiting.iter_mut())
+            .find(|elem| elem.id == target)
+        {
+            elem.value = None;
+        } else {
+            self.early_deletes.insert(target);
+        }
     }
 
-    fn walk_children(
-        parent: Option<OpId>,
-        children: &BTreeMap<Option<OpId>, Vec<OpId>>,
-        out: &mut Vec<OpId>,
-    ) {
-        if let Some(kids) = children.get(&parent) {
-            for id in kids {
-                out.push(*id);
-                Self::walk_children(Some(*id), children, out);
+    fn apply_ranges(&mut self) {
+        for (from, to, observed) in &self.ranges {
+            let start = self.elements.iter().position(|elem| elem.id == *from);
+            let end = self.elements.iter().position(|elem| elem.id == *to);
+            let (Some(start), Some(end)) = (start, end) else {
+                continue;
+            };
+            for elem in &mut self.elements[start.min(end)..=start.max(end)] {
+                if observed.get(elem.id.peer).unwrap_or(0) >= elem.id.counter {
+                    elem.value = None;
+                }
             }
         }
     }
+
+    pub fn elements(&self) -> Vec<T> {
+        self.elements
+            .iter()
+            .filter_map(|elem| elem.value.clone())
+            .collect()
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -1,7 +1,26 @@
 use clap::{Parser, Subcommand};
-use md_crdt::filesync::{Vault, VaultSession};
+use md_crdt::StateVector;
+use md_crdt::codec::{JsonOpCodec, OpBody, OpCodec};
+use md_crdt::core::{ElementInfo, OpId, Sequence};
+use md_crdt::doc::{Block, BlockKind, paragraph_visible_string};
+use md_crdt::filesync::{
+    AuditIssue, FileStatus, IngestReport, STATE_FORMAT_VERSION, Vault, VaultError, VaultSession,
+    VaultWatcher, WatchEvent,
+};
 use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Exit status for errors that may clear up on retry (`EX_TEMPFAIL`).
+const EXIT_RETRYABLE: i32 = 75;
+/// Ingest attempts before a retryable error is reported.
+const INGEST_ATTEMPTS: u32 = 3;
+/// Read and write timeout for `watch --remote` pushes.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Parser)]
 #[command(
@@ -21,7 +40,9 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Show fingerprint tracking status for all Markdown files
+    /// Show, per Markdown file, whether it is tracked and how it changed since
+    /// the last flush. Exits 1 when a file is untracked or modified and 3 when one
+    /// also changed in its collaborative session
     Status {
         #[arg(long)]
         json: bool,
@@ -34,12 +55,114 @@ enum Commands {
     Ingest,
     /// Ingest all Markdown files and report whether operations were emitted
     Sync,
+    /// Cross-check recorded state against the Markdown files: orphaned,
+    /// missing, and damaged state. Exits 1 when problems remain
+    Audit {
+        /// Remove orphaned state and re-record missing or damaged state
+        #[arg(long)]
+        fix: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Upgrade recorded state written by older versions to the current format
+    Migrate {
+        /// Report what would be upgraded without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Summarize the operation history of one Markdown file, per peer
+    Log {
+        /// Vault-relative path of the Markdown file
+        file: PathBuf,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the stored CRDT state of one Markdown file: its block tree,
+    /// element ordering with tombstones, marks, and operations. Every section
+    /// is printed unless flags pick some
+    Inspect {
+        /// Vault-relative path of the Markdown file
+        file: PathBuf,
+        /// Block tree with element ids and kinds
+        #[arg(long)]
+        blocks: bool,
+        /// Applied, pending and deferred operations
+        #[arg(long)]
+        ops: bool,
+        /// Mark intervals, active and removed
+        #[arg(long)]
+        marks: bool,
+        /// Block element ordering, tombstones and insertion origins included
+        #[arg(long)]
+        tombstones: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Ingest every Markdown file, then keep ingesting files as they change,
+    /// printing one JSON event per line until interrupted
+    Watch {
+        /// Run the initial pass only, then exit
+        #[arg(long)]
+        once: bool,
+        /// Quiet period after the last change before ingesting
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce_ms: u64,
+        /// Interval between scans of the vault
+        #[arg(long, value_name = "MS", default_value_t = 250)]
+        poll_ms: u64,
+        /// `http://host[:port]/path` endpoint that is POSTed each file's new
+        /// operations as `{"path": ..., "message": ...}`; defaults to
+        /// `sync.remote` in `.mdcrdt/config.toml`. There is no TLS: for a
+        /// remote host, point this at a TLS-terminating proxy on localhost
+        #[arg(long, value_name = "URL")]
+        remote: Option<String>,
+    },
 }
 
+/// `status` exit code when some file is untracked or modified.
+const EXIT_DIRTY: i32 = 1;
+/// `status` exit code when some file changed on disk and in its session.
+const EXIT_CONFLICTED: i32 = 3;
+
 #[derive(Serialize)]
-struct FileStatus {
+struct StatusEntry {
     path: String,
-    status: String,
+    /// `Untracked`, `Tracked`, `Modified` or `Conflicted`.
+    status: &'static str,
+    tracked: bool,
+    modified: bool,
+    conflicted: bool,
+    changed_blocks: usize,
+    /// Seconds since the Unix epoch.
+    last_flush: Option<u64>,
+}
+
+impl From<&FileStatus> for StatusEntry {
+    fn from(file: &FileStatus) -> Self {
+        let status = if !file.tracked {
+            "Untracked"
+        } else if file.conflicted {
+            "Conflicted"
+        } else if file.modified {
+            "Modified"
+        } else {
+            "Tracked"
+        };
+        Self {
+            path: file.path.to_string_lossy().into_owned(),
+            status,
+            tracked: file.tracked,
+            modified: file.modified,
+            conflicted: file.conflicted,
+            changed_blocks: file.changed_blocks,
+            last_flush: file
+                .last_flush
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+        }
+    }
 }
 
 fn main() {
@@ -51,45 +174,89 @@ fn main() {
         Commands::Flush => flush_command(&cli.vault),
         Commands::Ingest => ingest_command(&cli.vault),
         Commands::Sync => sync_command(&cli.vault),
+        Commands::Audit { fix, json } => audit_command(&cli.vault, *fix, *json),
+        Commands::Migrate { dry_run, json } => migrate_command(&cli.vault, *dry_run, *json),
+        Commands::Log { file, json } => log_command(&cli.vault, file, *json),
+        Commands::Inspect {
+            file,
+            blocks,
+            ops,
+            marks,
+            tombstones,
+            json,
+        } => {
+            let all = !(*blocks || *ops || *marks || *tombstones);
+            let sections = InspectSections {
+                blocks: *blocks || all,
+                ops: *ops || all,
+                marks: *marks || all,
+                tombstones: *tombstones || all,
+            };
+            inspect_command(&cli.vault, file, sections, *json)
+        }
+        Commands::Watch {
+            once,
+            debounce_ms,
+            poll_ms,
+            remote,
+        } => watch_command(
+            &cli.vault,
+            *once,
+            Duration::from_millis(*debounce_ms),
+            Duration::from_millis(*poll_ms),
+            remote.as_deref(),
+        ),
+    }
+}
+
+/// Report `err` and exit, with [`EXIT_RETRYABLE`] when running again may succeed.
+fn exit_with(err: &VaultError) -> ! {
+    eprintln!("Error: {err}");
+    if err.is_retryable() {
+        eprintln!("The failure looks transient; retrying may succeed.");
+        std::process::exit(EXIT_RETRYABLE);
+    }
+    std::process::exit(1);
+}
+
+/// Ingest, retrying with a short backoff while the failure is retryable (e.g. an
+/// editor still writing a file).
+fn ingest_with_retries(session: &mut VaultSession) -> Result<IngestReport, VaultError> {
+    let mut attempt = 1;
+    loop {
+        match session.ingest_all() {
+            Err(err) if err.is_retryable() && attempt < INGEST_ATTEMPTS => {
+                std::thread::sleep(Duration::from_millis(100 * u64::from(attempt)));
+                attempt += 1;
+            }
+            result => return result,
+        }
     }
 }
 
 fn status_command(vault_root: &Path, json: bool) {
     let vault = match Vault::open(vault_root) {
         Ok(vault) => vault,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
-    };
-    let files = vault.files();
-    let state_root = vault.path.join(".mdcrdt").join("state");
-
-    let mut statuses = Vec::new();
-    for file in files {
-        let relative_path = file
-            .strip_prefix(&vault.path)
-            .unwrap_or(&file)
-            .to_string_lossy()
-            .into_owned();
-        let mut state_path = state_root.join(&relative_path);
-        state_path.set_extension("mdcrdt");
-        let status = if state_path.exists() {
-            "Tracked"
-        } else {
-            "Untracked"
-        };
-        statuses.push(FileStatus {
-            path: relative_path,
-            status: status.to_string(),
-        });
-    }
-
-    let is_dirty = statuses.iter().any(|s| s.status == "Untracked");
+        Err(err) => exit_with(&err),
+    };
+    let files = match vault.status() {
+        Ok(files) => files,
+        Err(err) => exit_with(&err),
+    };
+    let code = if files.iter().any(|file| file.conflicted) {
+        EXIT_CONFLICTED
+    } else if files.iter().any(FileStatus::is_dirty) {
+        EXIT_DIRTY
+    } else {
+        0
+    };
 
     if json {
+        let entries: Vec<StatusEntry> = files.iter().map(StatusEntry::from).collect();
         let output = serde_json::json!({
-            "files": statuses,
+            "files": entries,
+            "dirty": code != 0,
+            "conflicted": code == EXIT_CONFLICTED,
         });
         // serde_json::to_string_pretty only fails for non-serializable types,
         // which is impossible for json! macro output
@@ -100,34 +267,37 @@ fn status_command(vault_root: &Path, json: bool) {
                 std::process::exit(1);
             }
         }
-        if is_dirty {
-            std::process::exit(1);
-        }
-        std::process::exit(0);
-    } else if !is_dirty {
+    } else if code == 0 {
         println!("Vault is clean.");
-        std::process::exit(0);
     } else {
-        for file in statuses {
-            if file.status == "Untracked" {
-                println!("Untracked: {}", file.path);
+        for file in files.iter().filter(|file| file.is_dirty()) {
+            let entry = StatusEntry::from(file);
+            if !file.tracked {
+                println!("Untracked: {}", entry.path);
+                continue;
             }
+            let age = file
+                .last_flush
+                .and_then(|time| SystemTime::now().duration_since(time).ok())
+                .map_or_else(String::new, |age| {
+                    format!(", last flush {}s ago", age.as_secs())
+                });
+            println!(
+                "{}: {} ({} changed block(s){age})",
+                entry.status, entry.path, file.changed_blocks
+            );
         }
-        std::process::exit(1);
     }
+    std::process::exit(code);
 }
 
 fn init_command(vault_root: &Path) {
     let vault = match Vault::open(vault_root) {
         Ok(vault) => vault,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
     if let Err(err) = vault.init() {
-        eprintln!("Error: {err}");
-        std::process::exit(1);
+        exit_with(&err);
     }
     println!("Initialized vault");
 }
@@ -135,14 +305,10 @@ fn init_command(vault_root: &Path) {
 fn flush_command(vault_root: &Path) {
     let vault = match Vault::open(vault_root) {
         Ok(vault) => vault,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
     if let Err(err) = vault.flush() {
-        eprintln!("Error: {err}");
-        std::process::exit(1);
+        exit_with(&err);
     }
     println!("Flushed state");
 }
@@ -150,17 +316,11 @@ fn flush_command(vault_root: &Path) {
 fn ingest_command(vault_root: &Path) {
     let mut session = match VaultSession::open(vault_root) {
         Ok(s) => s,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
-    let report = match session.ingest_all() {
+    let report = match ingest_with_retries(&mut session) {
         Ok(r) => r,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
     if report.files_changed == 0 {
         println!("Ingest complete: no changes");
@@ -170,24 +330,27 @@ fn ingest_command(vault_root: &Path) {
             report.files_changed, report.ops_emitted
         );
     }
+    print_conflict_files(&report);
+}
+
+/// List the conflict files an ingest wrote, if any.
+fn print_conflict_files(report: &IngestReport) {
+    for path in &report.conflict_files {
+        println!("Conflict: both versions kept in {}", path.display());
+    }
 }
 
 fn sync_command(vault_root: &Path) {
     let mut session = match VaultSession::open(vault_root) {
         Ok(s) => s,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
-    let report = match session.ingest_all() {
+    let report = match ingest_with_retries(&mut session) {
         Ok(r) => r,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
-        }
+        Err(err) => exit_with(&err),
     };
 
+    print_conflict_files(&report);
     if report.files_changed == 0 {
         println!("Sync complete: clean");
         std::process::exit(0);
@@ -199,3 +362,701 @@ fn sync_command(vault_root: &Path) {
         std::process::exit(2);
     }
 }
+
+fn audit_command(vault_root: &Path, fix: bool, json: bool) {
+    let vault = match Vault::open(vault_root) {
+        Ok(vault) => vault,
+        Err(err) => exit_with(&err),
+    };
+    let audit = match vault.audit() {
+        Ok(audit) => audit,
+        Err(err) => exit_with(&err),
+    };
+    let fixed = if fix {
+        match vault.fix_audit(&audit) {
+            Ok(fixed) => fixed,
+            Err(err) => exit_with(&err),
+        }
+    } else {
+        0
+    };
+    let remaining = audit.findings.len() - fixed;
+
+    if json {
+        let output = serde_json::json!({
+            "checked": audit.checked,
+            "findings": audit.findings,
+            "fixed": fixed,
+        });
+        match serde_json::to_string_pretty(&output) {
+            Ok(pretty) => println!("{pretty}"),
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+    } else if audit.is_clean() {
+        println!("Audit clean: {} path(s) checked", audit.checked);
+    } else {
+        for finding in &audit.findings {
+            let issue = match &finding.issue {
+                AuditIssue::OrphanedState => "orphaned state".to_string(),
+                AuditIssue::MissingState => "missing state".to_string(),
+                AuditIssue::ChecksumMismatch { reason } => format!("checksum mismatch ({reason})"),
+                AuditIssue::CorruptSnapshot { reason } => format!("corrupt snapshot ({reason})"),
+            };
+            println!("{}: {issue}", finding.path.display());
+        }
+        if fix {
+            println!("Fixed {fixed} finding(s)");
+        }
+    }
+    if remaining > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn migrate_command(vault_root: &Path, dry_run: bool, json: bool) {
+    let vault = match Vault::open(vault_root) {
+        Ok(vault) => vault,
+        Err(err) => exit_with(&err),
+    };
+    let migration = match vault.migrate_state_formats(dry_run) {
+        Ok(migration) => migration,
+        Err(err) => exit_with(&err),
+    };
+
+    if json {
+        let migrated: Vec<_> = migration
+            .migrated
+            .iter()
+            .map(|state| {
+                serde_json::json!({
+                    "path": state.path.to_string_lossy(),
+                    "from": state.from,
+                    "to": state.to,
+                })
+            })
+            .collect();
+        let output = serde_json::json!({
+            "dry_run": dry_run,
+            "format_version": STATE_FORMAT_VERSION,
+            "migrated": migrated,
+            "current": migration.current,
+            "skipped": migration.skipped,
+        });
+        match serde_json::to_string_pretty(&output) {
+            Ok(pretty) => println!("{pretty}"),
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let verb = if dry_run { "Would migrate" } else { "Migrated" };
+        for state in &migration.migrated {
+            println!(
+                "{verb}: {} (format {} -> {})",
+                state.path.display(),
+                state.from,
+                state.to
+            );
+        }
+        for path in &migration.skipped {
+            println!("Skipped unreadable state: {}", path.display());
+        }
+        if migration.is_empty() {
+            println!(
+                "State is current: {} file(s) at format {STATE_FORMAT_VERSION}",
+                migration.current
+            );
+        }
+    }
+}
+
+fn log_command(vault_root: &Path, file: &Path, json: bool) {
+    let mut session = match VaultSession::open(vault_root) {
+        Ok(s) => s,
+        Err(err) => exit_with(&err),
+    };
+    let entries = session
+        .state_vector(file)
+        .and_then(|until| session.changelog(file, &StateVector::new(), &until));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(err) => exit_with(&err),
+    };
+
+    if json {
+        let output = serde_json::json!({
+            "path": file.to_string_lossy(),
+            "entries": entries,
+        });
+        match serde_json::to_string_pretty(&output) {
+            Ok(pretty) => println!("{pretty}"),
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+    } else if entries.is_empty() {
+        println!("No history for {}", file.display());
+    } else {
+        for entry in entries {
+            println!("{}", entry.summary);
+        }
+    }
+}
+
+/// Sections `inspect` prints; all of them when no flag picks one.
+#[derive(Clone, Copy)]
+struct InspectSections {
+    blocks: bool,
+    ops: bool,
+    marks: bool,
+    tombstones: bool,
+}
+
+#[derive(Serialize)]
+struct InspectBlock {
+    depth: usize,
+    id: String,
+    elem: String,
+    kind: String,
+    text: Option<String>,
+}
+
+#[derive(Serialize)]
+struct InspectElement {
+    /// Element of the block holding the sequence; `None` at the top level.
+    container: Option<String>,
+    #[serde(flatten)]
+    info: ElementInfo,
+}
+
+#[derive(Serialize)]
+struct InspectMark {
+    id: String,
+    block: String,
+    kind: String,
+    start: String,
+    end: String,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct InspectOp {
+    id: String,
+    /// `applied`, `pending` or `deferred`.
+    state: &'static str,
+    /// Wire operation name, e.g. `InsertBlock`.
+    op: String,
+    bytes: usize,
+}
+
+#[derive(Serialize)]
+struct Inspection {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocks: Option<Vec<InspectBlock>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elements: Option<Vec<InspectElement>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    marks: Option<Vec<InspectMark>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ops: Option<Vec<InspectOp>>,
+}
+
+/// `peer@counter`, as [`md_crdt::Sequence::debug_dump`] writes ids.
+fn op_label(id: OpId) -> String {
+    format!("{}@{}", id.peer, id.counter)
+}
+
+fn block_kind_label(kind: &BlockKind) -> String {
+    match kind {
+        BlockKind::Paragraph { .. } => "paragraph".into(),
+        BlockKind::Heading { level, .. } => format!("heading {level}"),
+        BlockKind::List { style, .. } if style.ordered => "ordered list".into(),
+        BlockKind::List { .. } => "bullet list".into(),
+        BlockKind::CodeFence { info, .. } => match info {
+            Some(info) => format!("code {info}"),
+            None => "code".into(),
+        },
+        BlockKind::BlockQuote { .. } => "blockquote".into(),
+        BlockKind::RawBlock { .. } => "raw".into(),
+        BlockKind::Table { .. } => "table".into(),
+    }
+}
+
+/// Walk `blocks` depth first, filling the sections `inspection` asks for.
+fn inspect_blocks(
+    blocks: &Sequence<Block>,
+    container: Option<OpId>,
+    depth: usize,
+    inspection: &mut Inspection,
+) {
+    if let Some(elements) = inspection.elements.as_mut() {
+        elements.extend(blocks.inspect().map(|info| InspectElement {
+            container: container.map(op_label),
+            info,
+        }));
+    }
+    for block in blocks.iter() {
+        if let Some(rows) = inspection.blocks.as_mut() {
+            let text = match &block.kind {
+                BlockKind::Paragraph { text } | BlockKind::Heading { text, .. } => {
+                    Some(paragraph_visible_string(text))
+                }
+                BlockKind::CodeFence { text, .. } => Some(text.clone()),
+                BlockKind::RawBlock { raw } => Some(raw.clone()),
+                _ => None,
+            };
+            rows.push(InspectBlock {
+                depth,
+                id: block.id.to_string(),
+                elem: op_label(block.elem_id),
+                kind: block_kind_label(&block.kind),
+                text,
+            });
+        }
+        if let Some(marks) = inspection.marks.as_mut() {
+            marks.extend(
+                block
+                    .marks
+                    .iter_all_intervals()
+                    .map(|interval| InspectMark {
+                        id: op_label(interval.id),
+                        block: op_label(block.elem_id),
+                        kind: interval.kind.as_str().to_string(),
+                        start: op_label(interval.start.elem_id),
+                        end: op_label(interval.end.elem_id),
+                        active: block.marks.is_active(&interval.id),
+                    }),
+            );
+        }
+        match &block.kind {
+            BlockKind::BlockQuote { children } => {
+                inspect_blocks(children, Some(block.elem_id), depth + 1, inspection);
+            }
+            BlockKind::List { items, .. } => {
+                for item in items.iter() {
+                    inspect_blocks(&item.children, Some(item.elem_id), depth + 1, inspection);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Name of the wire operation in `payload`, or `undecodable`.
+fn op_name(payload: &[u8]) -> String {
+    let Ok(envelope) = JsonOpCodec.decode(payload) else {
+        return "undecodable".into();
+    };
+    let OpBody::Doc(op) = envelope.body;
+    match serde_json::to_value(op) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(serde_json::Value::Object(fields)) => fields.keys().next().cloned().unwrap_or_default(),
+        _ => "undecodable".into(),
+    }
+}
+
+fn inspect_command(vault_root: &Path, file: &Path, sections: InspectSections, json: bool) {
+    let mut session = match VaultSession::open(vault_root) {
+        Ok(s) => s,
+        Err(err) => exit_with(&err),
+    };
+    let document = match session.session_mut(file) {
+        Ok(document) => document,
+        Err(err) => exit_with(&err),
+    };
+    let mut inspection = Inspection {
+        path: file.to_string_lossy().into_owned(),
+        blocks: sections.blocks.then(Vec::new),
+        elements: sections.tombstones.then(Vec::new),
+        marks: sections.marks.then(Vec::new),
+        ops: None,
+    };
+    inspect_blocks(document.document().blocks(), None, 0, &mut inspection);
+    if sections.ops {
+        let snapshot = match document.save_snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        };
+        let logs = [
+            ("applied", &snapshot.ops),
+            ("pending", &snapshot.pending),
+            ("deferred", &snapshot.deferred),
+        ];
+        inspection.ops = Some(
+            logs.into_iter()
+                .flat_map(|(state, log)| {
+                    log.iter().map(move |(id, payload)| InspectOp {
+                        id: op_label(*id),
+                        state,
+                        op: op_name(payload),
+                        bytes: payload.len(),
+                    })
+                })
+                .collect(),
+        );
+    }
+
+    if json {
+        match serde_json::to_string_pretty(&inspection) {
+            Ok(pretty) => println!("{pretty}"),
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(blocks) = &inspection.blocks {
+        println!("blocks:");
+        for block in blocks {
+            let indent = "  ".repeat(block.depth + 1);
+            match &block.text {
+                Some(text) => println!("{indent}{} {} {text:?}", block.elem, block.kind),
+                None => println!("{indent}{} {}", block.elem, block.kind),
+            }
+        }
+    }
+    if let Some(elements) = &inspection.elements {
+        println!("elements:");
+        let mut container = None;
+        for element in elements {
+            if element.container != container {
+                container = element.container.clone();
+                if let Some(label) = &container {
+                    println!("  in {label}:");
+                }
+            }
+            let info = &element.info;
+            let origin = |id: Option<OpId>| id.map_or_else(|| "-".to_string(), op_label);
+            println!(
+                "  #{} {} after={} right={}{}",
+                info.visible_index
+                    .map_or_else(|| "-".to_string(), |index| index.to_string()),
+                op_label(info.id),
+                origin(info.after),
+                origin(info.right_origin),
+                if info.is_tombstone { " deleted" } else { "" }
+            );
+        }
+    }
+    if let Some(marks) = &inspection.marks {
+        println!("marks:");
+        for mark in marks {
+            println!(
+                "  {} {} on {} {}..{} {}",
+                mark.id,
+                mark.kind,
+                mark.block,
+                mark.start,
+                mark.end,
+                if mark.active { "active" } else { "removed" }
+            );
+        }
+    }
+    if let Some(ops) = &inspection.ops {
+        println!("ops:");
+        for op in ops {
+            println!("  {} {} {} {}B", op.state, op.id, op.op, op.bytes);
+        }
+    }
+}
+
+/// Set by Ctrl-C; `watch` finishes its current pass and exits.
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Set [`STOP_REQUESTED`] on Ctrl-C instead of exiting at once.
+fn stop_on_ctrl_c() -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()?;
+    // `ctrl_c` installs its handler when first polled, so poll it once here
+    // rather than leave a window in which Ctrl-C still kills the process.
+    let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
+    runtime.block_on(async {
+        tokio::select! {
+            biased;
+            result = &mut ctrl_c => result,
+            () = std::future::ready(()) => Ok(()),
+        }
+    })?;
+    std::thread::spawn(move || {
+        if runtime.block_on(ctrl_c).is_ok() {
+            STOP_REQUESTED.store(true, Ordering::SeqCst);
+        }
+    });
+    Ok(())
+}
+
+/// Print one `watch` event as a JSON line.
+fn emit(event: serde_json::Value) {
+    println!("{event}");
+}
+
+fn watch_command(
+    vault_root: &Path,
+    once: bool,
+    debounce: Duration,
+    poll: Duration,
+    remote: Option<&str>,
+) {
+    let mut session = match VaultSession::open(vault_root) {
+        Ok(session) => session,
+        Err(err) => exit_with(&err),
+    };
+    let remote = remote.or(session.vault.config.remote.as_deref());
+    let remote = match remote.map(Remote::parse).transpose() {
+        Ok(remote) => remote,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        }
+    };
+    if let Some(remote) = &remote
+        && !remote.is_loopback()
+    {
+        eprintln!(
+            "Warning: pushing to {} over plain HTTP; operations carry note contents unencrypted",
+            remote.authority()
+        );
+    }
+    if let Err(err) = stop_on_ctrl_c() {
+        eprintln!("Error: cannot handle Ctrl-C: {err}");
+        std::process::exit(1);
+    }
+    let mut watcher = VaultWatcher::new(&session.vault);
+    let mut agent = WatchAgent {
+        remote,
+        unpushed: BTreeMap::new(),
+    };
+
+    let all: BTreeSet<PathBuf> = session
+        .vault
+        .files()
+        .filter_map(|file| {
+            file.strip_prefix(&session.vault.path)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .collect();
+    agent.sync(&mut session, all);
+    if !once {
+        emit(serde_json::json!({ "event": "watching" }));
+        let mut changed = BTreeSet::new();
+        let mut last_change = Instant::now();
+        while !STOP_REQUESTED.load(Ordering::SeqCst) {
+            std::thread::sleep(poll);
+            let events = watcher.poll();
+            if !events.is_empty() {
+                last_change = Instant::now();
+            }
+            for event in events {
+                let path = event.path().to_string_lossy().into_owned();
+                match event {
+                    WatchEvent::Changed(file) => {
+                        emit(serde_json::json!({ "event": "changed", "path": path }));
+                        changed.insert(file);
+                    }
+                    WatchEvent::Removed(file) => {
+                        emit(serde_json::json!({ "event": "removed", "path": path }));
+                        changed.remove(&file);
+                    }
+                }
+            }
+            if !changed.is_empty() && last_change.elapsed() >= debounce {
+                agent.sync(&mut session, std::mem::take(&mut changed));
+            }
+        }
+    }
+    emit(serde_json::json!({ "event": "stopped" }));
+}
+
+struct WatchAgent {
+    remote: Option<Remote>,
+    /// Per file, the state already pushed to `remote`, for files with newer operations.
+    unpushed: BTreeMap<PathBuf, StateVector>,
+}
+
+impl WatchAgent {
+    /// Ingest `paths`, then push every file with operations the remote lacks.
+    fn sync(&mut self, session: &mut VaultSession, paths: BTreeSet<PathBuf>) {
+        for rel in paths {
+            let path = rel.to_string_lossy().into_owned();
+            let before = session.state_vector(&rel);
+            match before.and_then(|before| Ok((before, session.ingest_markdown(&rel, None, None)?)))
+            {
+                Ok((before, outcome)) => {
+                    if outcome.changed {
+                        emit(serde_json::json!({
+                            "event": "ingested",
+                            "path": path,
+                            "ops": outcome.changes.operation_count,
+                        }));
+                        if let Some(conflict_file) = &outcome.conflict_file {
+                            emit(serde_json::json!({
+                                "event": "conflict",
+                                "path": path,
+                                "conflict_file": conflict_file,
+                                "conflicts": outcome.conflicts,
+                            }));
+                        }
+                        if self.remote.is_some() {
+                            self.unpushed.entry(rel).or_insert(before);
+                        }
+                    }
+                }
+                Err(err) => emit(serde_json::json!({
+                    "event": "error",
+                    "path": path,
+                    "error": err.to_string(),
+                    "retryable": err.is_retryable(),
+                })),
+            }
+        }
+        if let Some(remote) = &self.remote {
+            self.unpushed
+                .retain(|rel, since| !push(session, remote, rel, since));
+        }
+    }
+}
+
+/// Push `rel`'s operations after `since`; returns whether the remote has them all.
+fn push(session: &mut VaultSession, remote: &Remote, rel: &Path, since: &mut StateVector) -> bool {
+    let path = rel.to_string_lossy().into_owned();
+    let result = session
+        .encode_changes_since(rel, since)
+        .and_then(|message| {
+            let until = session.state_vector(rel)?;
+            Ok((message, until))
+        });
+    let (message, until) = match result {
+        Ok(result) => result,
+        Err(err) => {
+            emit(serde_json::json!({ "event": "error", "path": path, "error": err.to_string() }));
+            return false;
+        }
+    };
+    let ops = message.ops.len();
+    let body = serde_json::json!({ "path": path, "message": message }).to_string();
+    match remote.post(body.as_bytes()) {
+        Ok(()) => {
+            emit(serde_json::json!({ "event": "pushed", "path": path, "ops": ops }));
+            *since = until;
+            true
+        }
+        Err(err) => {
+            emit(serde_json::json!({
+                "event": "push_failed",
+                "path": path,
+                "error": err.to_string(),
+            }));
+            false
+        }
+    }
+}
+
+/// A plain-HTTP endpoint for `watch --remote`; nothing is encrypted.
+struct Remote {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Remote {
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("remote {url} is not an http:// URL"))?;
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |at| rest.split_at(at));
+        let (host, port) = match authority.strip_prefix('[') {
+            // An IPv6 literal: `[::1]` or `[::1]:8080`.
+            Some(bracketed) => {
+                let (host, after) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| format!("remote {url} has an unclosed IPv6 address"))?;
+                let port = match after {
+                    "" => None,
+                    after => Some(
+                        after
+                            .strip_prefix(':')
+                            .ok_or_else(|| format!("remote {url} has an invalid port"))?,
+                    ),
+                };
+                (host, port)
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, _)) if host.contains(':') => {
+                    return Err(format!("remote {url} has an IPv6 address outside brackets"));
+                }
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = port.map_or(Ok(80), |port| {
+            port.parse()
+                .map_err(|_| format!("remote {url} has an invalid port"))
+        })?;
+        if host.is_empty() {
+            return Err(format!("remote {url} has no host"));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Whether `host` names this machine, where plain HTTP does not leave it.
+    fn is_loopback(&self) -> bool {
+        self.host.eq_ignore_ascii_case("localhost")
+            || self
+                .host
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    }
+
+    /// `host:port`, with an IPv6 host in brackets.
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// POST `body` as JSON, succeeding on any 2xx status.
+    fn post(&self, body: &[u8]) -> io::Result<()> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(PUSH_TIMEOUT))?;
+        stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.authority(),
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        let code = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok());
+        match code {
+            Some(200..=299) => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "remote answered {:?}",
+                status.trim_end()
+            ))),
+        }
+    }
+}
@@ -7,10 +7,13 @@ mod wire;
 
 pub use wire::{
     BlockKindSkeleton, BlockSkeleton, BlockSkeletonInsert, ColumnAlignmentWire, DocOp, Envelope,
-    ListItemSkeleton, MAX_WIRE_NEST_DEPTH, MovedBlockWire, MovedTextUnitWire, OpBody,
-    TableCellWire, TextBlockKindWire, TextUnitWire, WIRE_VERSION, insert_block_paragraph_is_empty,
+    ListItemSkeleton, MAX_METADATA_LABEL_BYTES, MAX_METADATA_SIGNATURE_BYTES, MAX_WIRE_NEST_DEPTH,
+    MIN_WIRE_VERSION, MovedBlockWire, MovedTextUnitWire, OpBody, OpMetadata, TableCellWire,
+    TextBlockKindWire, TextUnitWire, WIRE_VERSION, insert_block_paragraph_is_empty,
 };
 
+use crate::core::{CounterDelta, OpId, RegisterWrite};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use thiserror::Error;
 
 /// Errors from encoding or decoding wire envelopes.
@@ -56,10 +59,41 @@ impl OpCodec for JsonOpCodec {
                 CodecError::Serde(msg)
             }
         })?;
-        if envelope.version != WIRE_VERSION {
+        if !(MIN_WIRE_VERSION..=WIRE_VERSION).contains(&envelope.version) {
             return Err(CodecError::UnknownVersion(envelope.version));
         }
         wire::validate_envelope_structure(&envelope)?;
         Ok(envelope)
     }
 }
+
+/// Update to a standalone scalar CRDT, carried as a sync `Operation` payload
+/// outside the document envelope.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalarOp<T> {
+    Counter(CounterDelta),
+    Register(RegisterWrite<T>),
+}
+
+impl<T> ScalarOp<T> {
+    /// Operation id to log the payload under.
+    pub fn id(&self) -> OpId {
+        match self {
+            ScalarOp::Counter(delta) => delta.id,
+            ScalarOp::Register(write) => write.id,
+        }
+    }
+}
+
+impl JsonOpCodec {
+    pub fn encode_scalar<T: Serialize>(&self, op: &ScalarOp<T>) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(op).map_err(|e| CodecError::Serde(e.to_string()))
+    }
+
+    pub fn decode_scalar<T: DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ScalarOp<T>, CodecError> {
+        serde_json::from_slice(bytes).map_err(|e| CodecError::Serde(e.to_string()))
+    }
+}
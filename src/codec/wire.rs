@@ -6,13 +6,19 @@
 
 use crate::core::mark::{Anchor, MarkKind, MarkValue};
 use crate::core::{OpId, StateVector};
-use crate::doc::Frontmatter;
+use crate::doc::{BlockAcl, Frontmatter};
 use crate::doc::{BlockId, CodeFenceStyle, ColumnId, ListStyle, RowId, TaskState};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 /// Current wire protocol version for [`Envelope`].
-pub const WIRE_VERSION: u16 = 4;
+///
+/// v5: mark kinds are written as their stable names (`bold`, `app:comment`).
+pub const WIRE_VERSION: u16 = 5;
+
+/// Oldest [`Envelope`] version still decoded: v4 differs only in how mark kinds
+/// are spelled, and [`crate::core::MarkKind`] reads the old spelling.
+pub const MIN_WIRE_VERSION: u16 = 4;
 
 /// Maximum nested BlockQuote / structure depth accepted on encode and decode.
 ///
@@ -21,13 +27,52 @@ pub const WIRE_VERSION: u16 = 4;
 /// each quote level costs several enum layers in JSON).
 pub const MAX_WIRE_NEST_DEPTH: u32 = 16;
 
+/// Longest author label or client version accepted in [`OpMetadata`], in bytes.
+pub const MAX_METADATA_LABEL_BYTES: usize = 256;
+
+/// Longest [`OpMetadata::signature`] accepted, in bytes.
+pub const MAX_METADATA_SIGNATURE_BYTES: usize = 1024;
+
 /// Versioned operation envelope carried as `sync::Operation` payload bytes.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Envelope {
     pub version: u16,
+    /// Who issued the operation and when. Informational only: integration and
+    /// ordering never read it, and envelopes without it encode as before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<OpMetadata>,
     pub body: OpBody,
 }
 
+impl Envelope {
+    /// Bytes an [`OpMetadata::signature`] covers: the version, the body and the
+    /// metadata without its signature, so a signature also vouches for the
+    /// operation it was issued with.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let meta = self.meta.as_ref().map(|meta| OpMetadata {
+            signature: None,
+            ..meta.clone()
+        });
+        serde_json::to_vec(&(self.version, &self.body, meta)).expect("envelopes always serialize")
+    }
+}
+
+/// Wall-clock and authorship details stamped on an operation by its issuer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpMetadata {
+    /// Milliseconds since the Unix epoch on the issuer's clock.
+    pub timestamp_ms: u64,
+    /// Display name of the author.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Name and version of the issuing client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<String>,
+    /// Application signature over [`Envelope::signing_bytes`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>,
+}
+
 /// Top-level body tag for an envelope.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OpBody {
@@ -45,6 +90,106 @@ pub struct TextUnitWire {
     pub grapheme: String,
 }
 
+/// Run encoding of [`DocOp::InsertText`] units.
+///
+/// Units that chain — each after the previous one, with the next counter and no
+/// right origin, as a paste or a coalesced typing run produces — are sent as the
+/// first unit's placement plus the run's text. Any other list, or one whose text
+/// would segment into different graphemes, keeps the per-unit form; both decode.
+mod text_units {
+    use super::TextUnitWire;
+    use crate::core::{OpId, SegmentationRules};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    #[derive(Serialize, Deserialize)]
+    struct TextRunWire {
+        id: OpId,
+        after: Option<OpId>,
+        right_origin: Option<OpId>,
+        text: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TextUnitsWire {
+        Run(TextRunWire),
+        Units(Vec<TextUnitWire>),
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        units: &[TextUnitWire],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match as_run(units) {
+            Some(run) => run.serialize(serializer),
+            None => units.serialize(serializer),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<TextUnitWire>, D::Error> {
+        match TextUnitsWire::deserialize(deserializer)? {
+            TextUnitsWire::Units(units) => Ok(units),
+            TextUnitsWire::Run(run) => {
+                let mut units: Vec<TextUnitWire> = Vec::new();
+                let graphemes = SegmentationRules::CURRENT.graphemes(&run.text);
+                for (offset, grapheme) in graphemes.enumerate() {
+                    let counter = run
+                        .id
+                        .counter
+                        .checked_add(offset as u64)
+                        .ok_or_else(|| D::Error::custom("text run overflows its counter"))?;
+                    let (after, right_origin) = match units.last() {
+                        Some(previous) => (Some(previous.id), None),
+                        None => (run.after, run.right_origin),
+                    };
+                    units.push(TextUnitWire {
+                        id: OpId {
+                            counter,
+                            peer: run.id.peer,
+                        },
+                        after,
+                        right_origin,
+                        grapheme: grapheme.to_string(),
+                    });
+                }
+                Ok(units)
+            }
+        }
+    }
+
+    fn as_run(units: &[TextUnitWire]) -> Option<TextRunWire> {
+        let first = units.first()?;
+        let chained = units.windows(2).all(|pair| {
+            pair[1].id.peer == pair[0].id.peer
+                && pair[0].id.counter.checked_add(1) == Some(pair[1].id.counter)
+                && pair[1].after == Some(pair[0].id)
+                && pair[1].right_origin.is_none()
+        });
+        if !chained {
+            return None;
+        }
+        // A run is re-split on decode, so it is only sent when every rule
+        // version splits it back into these units; otherwise units go one by one.
+        let text: String = units.iter().map(|unit| unit.grapheme.as_str()).collect();
+        let splits_back = SegmentationRules::ALL.iter().all(|rules| {
+            rules
+                .graphemes(&text)
+                .eq(units.iter().map(|unit| unit.grapheme.as_str()))
+        });
+        if !splits_back {
+            return None;
+        }
+        Some(TextRunWire {
+            id: first.id,
+            after: first.after,
+            right_origin: first.right_origin,
+            text,
+        })
+    }
+}
+
 /// A text unit transferred between blocks without changing its identity when possible.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MovedTextUnitWire {
@@ -116,11 +261,24 @@ pub enum DocOp {
         block_id: BlockId,
         id: OpId,
     },
+    /// Tombstone every block of a container the deleting peer had observed, as
+    /// one range delete from `from` to `to`.
+    DeleteBlockRange {
+        /// Container block elem_id; `None` = top-level document.
+        #[serde(default)]
+        parent: Option<OpId>,
+        from: OpId,
+        to: OpId,
+        id: OpId,
+        observed: StateVector,
+    },
     /// Nested RGA inserts into a paragraph body (one element per grapheme).
     InsertText {
         block_elem: OpId,
         block_id: BlockId,
-        /// Contiguous paste: units listed in left-to-right insert order.
+        /// Contiguous paste: units listed in left-to-right insert order. Sent as
+        /// one text run when the units chain.
+        #[serde(with = "text_units")]
         units: Vec<TextUnitWire>,
     },
     /// Tombstone unit element ids inside a paragraph body.
@@ -150,11 +308,15 @@ pub enum DocOp {
         id: OpId,
         observed: StateVector,
     },
-    /// LWW update/delete of one supported top-level frontmatter key.
+    /// Update/delete of one supported top-level frontmatter key: LWW for scalars,
+    /// add-wins for the items of a flow list.
     SetFrontmatterField {
         id: OpId,
         key: String,
         value: Option<String>,
+        /// Frontier the write observed; empty from peers that predate it.
+        #[serde(default)]
+        observed: StateVector,
     },
     /// Establish the lossless frontmatter base on first ingest.
     InitializeFrontmatter { id: OpId, frontmatter: Frontmatter },
@@ -165,6 +327,11 @@ pub enum DocOp {
         blocks: Vec<MovedBlockWire>,
     },
     /// Split one text-bearing block, transferring the visible suffix to a new sibling.
+    ///
+    /// Concurrent splits of one block may claim the same units. A unit stays in
+    /// the last block in document order whose split claimed it and is
+    /// tombstoned in every other, so splits at different offsets partition the
+    /// text and two at the same offset leave the earlier new block empty.
     SplitBlock {
         #[serde(default)]
         parent: Option<OpId>,
@@ -333,6 +500,31 @@ pub enum DocOp {
         raw: String,
         observed: StateVector,
     },
+    /// LWW update of one block's access control list; `None` clears it.
+    SetBlockAcl {
+        block_elem: OpId,
+        block_id: BlockId,
+        id: OpId,
+        acl: Option<BlockAcl>,
+        observed: StateVector,
+    },
+    /// Insert one line into a code fence, against the fence text written by `base`.
+    InsertCodeLine {
+        block_id: BlockId,
+        base: OpId,
+        after: Option<OpId>,
+        right_origin: Option<OpId>,
+        id: OpId,
+        line: String,
+        observed: StateVector,
+    },
+    DeleteCodeLine {
+        block_id: BlockId,
+        base: OpId,
+        target: OpId,
+        id: OpId,
+        observed: StateVector,
+    },
 }
 
 /// Serializable block creation payload — no Sequence maps.
@@ -421,6 +613,7 @@ pub fn insert_block_paragraph_is_empty(envelope: &Envelope) -> bool {
         OpBody::Doc(
             DocOp::DeleteBlock { .. }
             | DocOp::DeleteBlockById { .. }
+            | DocOp::DeleteBlockRange { .. }
             | DocOp::InsertText { .. }
             | DocOp::DeleteText { .. }
             | DocOp::SetMark { .. }
@@ -446,13 +639,33 @@ pub fn insert_block_paragraph_is_empty(envelope: &Envelope) -> bool {
             | DocOp::SetListItemTask { .. }
             | DocOp::SetCodeFence { .. }
             | DocOp::ConvertTextBlock { .. }
-            | DocOp::ReplaceRawBlock { .. },
+            | DocOp::ReplaceRawBlock { .. }
+            | DocOp::SetBlockAcl { .. }
+            | DocOp::InsertCodeLine { .. }
+            | DocOp::DeleteCodeLine { .. },
         ) => true,
     }
 }
 
 /// Validate structural limits on an envelope (nest depth). Does not enforce unit-mode rules.
 pub(crate) fn validate_envelope_structure(envelope: &Envelope) -> Result<(), super::CodecError> {
+    if let Some(meta) = &envelope.meta {
+        let labels = [&meta.author, &meta.client_version];
+        if labels
+            .into_iter()
+            .flatten()
+            .any(|label| label.len() > MAX_METADATA_LABEL_BYTES)
+        {
+            return Err(super::CodecError::Invalid("metadata label too long"));
+        }
+        if meta
+            .signature
+            .as_ref()
+            .is_some_and(|signature| signature.len() > MAX_METADATA_SIGNATURE_BYTES)
+        {
+            return Err(super::CodecError::Invalid("metadata signature too long"));
+        }
+    }
     match &envelope.body {
         OpBody::Doc(DocOp::InsertBlock { block, .. }) => {
             check_kind_depth(&block.kind, 0)?;
@@ -460,6 +673,7 @@ pub(crate) fn validate_envelope_structure(envelope: &Envelope) -> Result<(), sup
         OpBody::Doc(
             DocOp::DeleteBlock { .. }
             | DocOp::DeleteBlockById { .. }
+            | DocOp::DeleteBlockRange { .. }
             | DocOp::InsertText { .. }
             | DocOp::DeleteText { .. }
             | DocOp::SetMark { .. }
@@ -485,7 +699,10 @@ pub(crate) fn validate_envelope_structure(envelope: &Envelope) -> Result<(), sup
             | DocOp::SetListItemTask { .. }
             | DocOp::SetCodeFence { .. }
             | DocOp::ConvertTextBlock { .. }
-            | DocOp::ReplaceRawBlock { .. },
+            | DocOp::ReplaceRawBlock { .. }
+            | DocOp::SetBlockAcl { .. }
+            | DocOp::InsertCodeLine { .. }
+            | DocOp::DeleteCodeLine { .. },
         ) => {}
     }
     Ok(())
@@ -527,6 +744,7 @@ mod tests {
     fn empty_paragraph_predicate() {
         let empty = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertBlock {
                 parent: None,
                 after: None,
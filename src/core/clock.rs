@@ -0,0 +1,156 @@
+//! Counter reservation for one peer's OpIds.
+//!
+//! Bulk work (importing a large file, applying an external diff) needs thousands of
+//! consecutive ids. Reserving them up front as an [`OpIdRange`] keeps interleaved
+//! allocation — live edits, or another import on a different thread — from ever
+//! handing out the same counter twice.
+
+use super::{OpId, PeerId};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ClockError {
+    #[error("peer {peer} cannot reserve {requested} ids: only {available} counters left")]
+    ClockExhausted {
+        peer: PeerId,
+        requested: u64,
+        available: u64,
+    },
+    #[error("reserved range has {remaining} ids left, {requested} needed")]
+    RangeExhausted { requested: u64, remaining: u64 },
+}
+
+/// Next-counter source for one peer, safe to share between threads.
+#[derive(Debug)]
+pub struct PeerClock {
+    peer: PeerId,
+    next: AtomicU64,
+}
+
+impl PeerClock {
+    /// Counters start at 1; sync rejects counter 0.
+    pub fn new(peer: PeerId) -> Self {
+        Self::starting_at(peer, 1)
+    }
+
+    pub fn starting_at(peer: PeerId, next: u64) -> Self {
+        Self {
+            peer,
+            next: AtomicU64::new(next.max(1)),
+        }
+    }
+
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    /// The id the next allocation would return.
+    pub fn peek(&self) -> OpId {
+        OpId {
+            counter: self.next.load(Ordering::Acquire),
+            peer: self.peer,
+        }
+    }
+
+    pub fn next_id(&self) -> Result<OpId, ClockError> {
+        let mut range = self.reserve(1)?;
+        range.next_id()
+    }
+
+    /// Claim `n` consecutive counters. Concurrent reservations never overlap.
+    pub fn reserve(&self, n: u64) -> Result<OpIdRange, ClockError> {
+        self.next
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |next| {
+                next.checked_add(n)
+            })
+            .map(|start| OpIdRange {
+                peer: self.peer,
+                next: start,
+                end: start + n,
+            })
+            .map_err(|next| ClockError::ClockExhausted {
+                peer: self.peer,
+                requested: n,
+                available: u64::MAX - next,
+            })
+    }
+
+    /// Never allocate `counter` or anything below it (e.g. after loading own ops).
+    pub fn observe(&self, counter: u64) {
+        self.next
+            .fetch_max(counter.saturating_add(1), Ordering::AcqRel);
+    }
+}
+
+/// Unused part of a reservation: counters `next..end` of one peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpIdRange {
+    peer: PeerId,
+    next: u64,
+    end: u64,
+}
+
+impl OpIdRange {
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    /// First counter not yet handed out.
+    pub fn start(&self) -> u64 {
+        self.next
+    }
+
+    /// One past the last reserved counter.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.end - self.next
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next == self.end
+    }
+
+    pub fn contains(&self, id: OpId) -> bool {
+        id.peer == self.peer && (self.next..self.end).contains(&id.counter)
+    }
+
+    pub fn next_id(&mut self) -> Result<OpId, ClockError> {
+        Ok(OpId {
+            counter: self.split_off(1)?.next,
+            peer: self.peer,
+        })
+    }
+
+    /// Split off the first `n` ids as their own range.
+    pub fn split_off(&mut self, n: u64) -> Result<OpIdRange, ClockError> {
+        if n > self.remaining() {
+            return Err(ClockError::RangeExhausted {
+                requested: n,
+                remaining: self.remaining(),
+            });
+        }
+        let taken = OpIdRange {
+            peer: self.peer,
+            next: self.next,
+            end: self.next + n,
+        };
+        self.next += n;
+        Ok(taken)
+    }
+}
+
+impl Iterator for OpIdRange {
+    type Item = OpId;
+
+    fn next(&mut self) -> Option<OpId> {
+        self.next_id().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = usize::try_from(self.remaining()).unwrap_or(usize::MAX);
+        (remaining, usize::try_from(self.remaining()).ok())
+    }
+}
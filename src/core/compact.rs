@@ -0,0 +1,131 @@
+//! Compact operation ids for snapshots and message framing.
+//!
+//! A full [`OpId`] repeats its 64-bit peer everywhere it appears, yet a
+//! document rarely has more than a handful of peers. A [`PeerTable`] interns
+//! peers in first-seen order so each id can be written as a small peer index
+//! plus its counter ([`CompactOpId`]); binary framings write both as LEB128
+//! varints ([`write_varint`], [`read_varint`]).
+
+use super::{OpId, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Errors decoding compact ids or varint-framed bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CompactError {
+    #[error("input ends inside a field")]
+    Truncated,
+    #[error("varint does not fit in 64 bits")]
+    VarintOverflow,
+    #[error("peer index {0} is not in the peer table")]
+    UnknownPeerIndex(u64),
+    #[error("peer {0} appears twice in the peer table")]
+    DuplicatePeer(PeerId),
+    #[error("not a compact message")]
+    BadMagic,
+    #[error("compact format version {0} is unsupported")]
+    UnsupportedVersion(u64),
+    #[error("{0} trailing bytes after the last field")]
+    TrailingBytes(usize),
+}
+
+/// An [`OpId`] whose peer is an index into a [`PeerTable`].
+///
+/// Serializes as the pair `[peer_index, counter]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactOpId(pub u32, pub u64);
+
+/// Peers in first-seen order, indexed both ways.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerTable {
+    peers: Vec<PeerId>,
+    index: BTreeMap<PeerId, u32>,
+}
+
+impl PeerTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild a table from its [`Self::peers`] list.
+    pub fn from_peers(peers: Vec<PeerId>) -> Result<Self, CompactError> {
+        let mut table = Self::new();
+        for peer in peers {
+            if table.index.contains_key(&peer) {
+                return Err(CompactError::DuplicatePeer(peer));
+            }
+            table.intern(peer);
+        }
+        Ok(table)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Peers in index order.
+    pub fn peers(&self) -> &[PeerId] {
+        &self.peers
+    }
+
+    /// Index of `peer`, adding it to the table if new.
+    pub fn intern(&mut self, peer: PeerId) -> u32 {
+        if let Some(index) = self.index.get(&peer) {
+            return *index;
+        }
+        let index = self.peers.len() as u32;
+        self.peers.push(peer);
+        self.index.insert(peer, index);
+        index
+    }
+
+    pub fn peer(&self, index: u32) -> Option<PeerId> {
+        self.peers.get(index as usize).copied()
+    }
+
+    pub fn compact(&mut self, id: OpId) -> CompactOpId {
+        CompactOpId(self.intern(id.peer), id.counter)
+    }
+
+    pub fn expand(&self, id: CompactOpId) -> Result<OpId, CompactError> {
+        let peer = self
+            .peer(id.0)
+            .ok_or(CompactError::UnknownPeerIndex(u64::from(id.0)))?;
+        Ok(OpId {
+            counter: id.1,
+            peer,
+        })
+    }
+}
+
+/// Append `value` as an unsigned LEB128 varint (1 byte below 128, at most 10).
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint from the front of `input`, advancing it.
+pub fn read_varint(input: &mut &[u8]) -> Result<u64, CompactError> {
+    let mut value = 0u64;
+    for (position, byte) in input.iter().enumerate() {
+        let shift = 7 * position as u32;
+        let bits = u64::from(byte & 0x7f);
+        if shift >= 64 || (shift == 63 && bits > 1) {
+            return Err(CompactError::VarintOverflow);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            *input = &input[position + 1..];
+            return Ok(value);
+        }
+    }
+    Err(CompactError::Truncated)
+}
@@ -0,0 +1,74 @@
+//! Element-level view of a [`Sequence`] for debugging ordering.
+//!
+//! [`Sequence::iter_all`] yields raw elements; [`Sequence::inspect`] adds each
+//! element's visible index and tombstone flag next to its integration origins,
+//! and [`Sequence::debug_dump`] renders the same as one line per element.
+
+use super::{OpId, Sequence};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// One element of a sequence, tombstones included, as [`Sequence::inspect`]
+/// reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElementInfo {
+    /// Position among visible elements; `None` for a tombstone.
+    pub visible_index: Option<usize>,
+    pub id: OpId,
+    /// Left origin the element was inserted after; `None` at the start.
+    pub after: Option<OpId>,
+    /// Right origin recorded at insertion; `None` at the end.
+    pub right_origin: Option<OpId>,
+    pub is_tombstone: bool,
+}
+
+impl<T: Clone> Sequence<T> {
+    /// Every element in document order with its visible index and origins.
+    pub fn inspect(&self) -> impl Iterator<Item = ElementInfo> + '_ {
+        let mut visible = 0;
+        self.iter_all().map(move |element| {
+            let is_tombstone = element.value.is_none();
+            let visible_index = (!is_tombstone).then(|| {
+                visible += 1;
+                visible - 1
+            });
+            ElementInfo {
+                visible_index,
+                id: element.id,
+                after: element.after,
+                right_origin: element.right_origin,
+                is_tombstone,
+            }
+        })
+    }
+
+    /// [`Self::inspect`] as text, one element per line:
+    /// `#0 1@3 after=1@2 right=-`, with `#-` and a trailing `deleted` for
+    /// tombstones. Ids read `peer@counter`.
+    pub fn debug_dump(&self) -> String {
+        let id = |id: Option<OpId>| {
+            id.map_or_else(
+                || "-".to_string(),
+                |id| format!("{}@{}", id.peer, id.counter),
+            )
+        };
+        let mut dump = String::new();
+        for info in self.inspect() {
+            let index = info
+                .visible_index
+                .map_or_else(|| "-".to_string(), |index| index.to_string());
+            let _ = write!(
+                dump,
+                "#{index} {} after={} right={}",
+                id(Some(info.id)),
+                id(info.after),
+                id(info.right_origin)
+            );
+            if info.is_tombstone {
+                dump.push_str(" deleted");
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+}
@@ -3,19 +3,176 @@
 //! This module provides a CRDT-based mark system for rich text formatting,
 //! supporting operations like bold, italic, links, and custom marks.
 
-use super::{LwwRegister, OpId, StateVector};
+use super::merge::{self, Mergeable};
+use super::{CausalOrd, LwwRegister, OffsetMap, OpId, StateVector};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// Kind of a mark interval.
+///
+/// Every kind has a stable string name, which is also its wire and snapshot
+/// encoding: `bold`, `italic`, `code`, `link` and `strikethrough` for the
+/// built-in kinds, and `namespace:name` (say `app:comment`) for custom ones.
+/// The namespace keeps one application's kinds from colliding with another's
+/// or with kinds added here later; built-in names have none. Both parts use
+/// ASCII letters, digits, `-`, `_` and `.`, and the namespace is lowercase.
+/// Build custom kinds with [`MarkKind::custom`] or by parsing their name; a
+/// [`MarkKind::Custom`] holding anything else fails to serialize.
+///
+/// The Markdown serializer writes a custom mark's text wrapped in its string
+/// `delimiter` attribute when it has one (`==text==` for `==`), and bare
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MarkKind {
     Bold,
     Italic,
     Code,
     Link,
+    Strikethrough,
+    /// Full `namespace:name` of an application-defined kind.
     Custom(String),
 }
 
+/// A mark kind name that is neither built in nor `namespace:name`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("mark kind `{0}` is neither built in nor of the form `namespace:name`")]
+pub struct InvalidMarkKind(pub String);
+
+impl MarkKind {
+    /// The custom kind `namespace:name`.
+    pub fn custom(namespace: &str, name: &str) -> Result<Self, InvalidMarkKind> {
+        format!("{namespace}:{name}").parse()
+    }
+
+    /// Stable name, as written on the wire and in snapshots.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Bold => "bold",
+            Self::Italic => "italic",
+            Self::Code => "code",
+            Self::Link => "link",
+            Self::Strikethrough => "strikethrough",
+            Self::Custom(name) => name,
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        !matches!(self, Self::Custom(_))
+    }
+
+    /// Namespace of a custom kind; `None` for built-in kinds.
+    pub fn namespace(&self) -> Option<&str> {
+        self.custom_parts().map(|(namespace, _)| namespace)
+    }
+
+    /// Name within the namespace for custom kinds, the full name otherwise.
+    pub fn local_name(&self) -> &str {
+        self.custom_parts().map_or(self.as_str(), |(_, name)| name)
+    }
+
+    /// Check that a [`MarkKind::Custom`] holds a well-formed `namespace:name`.
+    pub fn validate(&self) -> Result<(), InvalidMarkKind> {
+        match self {
+            Self::Custom(name) if split_custom(name).is_none() => {
+                Err(InvalidMarkKind(name.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn custom_parts(&self) -> Option<(&str, &str)> {
+        match self {
+            Self::Custom(name) => split_custom(name),
+            _ => None,
+        }
+    }
+}
+
+fn split_custom(name: &str) -> Option<(&str, &str)> {
+    let (namespace, local) = name.split_once(':')?;
+    let valid = |part: &str, lowercase: bool| {
+        !part.is_empty()
+            && part.chars().all(|ch| {
+                (ch.is_ascii_alphanumeric() && !(lowercase && ch.is_ascii_uppercase()))
+                    || matches!(ch, '-' | '_' | '.')
+            })
+    };
+    (valid(namespace, true) && valid(local, false)).then_some((namespace, local))
+}
+
+impl std::str::FromStr for MarkKind {
+    type Err = InvalidMarkKind;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let kind = match name {
+            "bold" => Self::Bold,
+            "italic" => Self::Italic,
+            "code" => Self::Code,
+            "link" => Self::Link,
+            "strikethrough" => Self::Strikethrough,
+            _ => Self::Custom(name.to_string()),
+        };
+        kind.validate()?;
+        Ok(kind)
+    }
+}
+
+impl std::fmt::Display for MarkKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for MarkKind {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.validate().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Namespace given to unnamespaced custom kinds read from older data.
+pub const LEGACY_MARK_NAMESPACE: &str = "legacy";
+
+/// Also reads the encoding used before kinds had stable names: variant names
+/// (`"Bold"`) for built-in kinds and `{"Custom": name}` for custom ones. A
+/// legacy custom name that is not `namespace:name` moves into
+/// [`LEGACY_MARK_NAMESPACE`].
+impl<'de> Deserialize<'de> for MarkKind {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Encoded {
+            Name(String),
+            Legacy {
+                #[serde(rename = "Custom")]
+                custom: String,
+            },
+        }
+
+        let kind = match Encoded::deserialize(deserializer)? {
+            Encoded::Name(name) => match name.as_str() {
+                "Bold" => Ok(Self::Bold),
+                "Italic" => Ok(Self::Italic),
+                "Code" => Ok(Self::Code),
+                "Link" => Ok(Self::Link),
+                "Strikethrough" => Ok(Self::Strikethrough),
+                _ => name.parse(),
+            },
+            Encoded::Legacy { custom } => match split_custom(&custom) {
+                Some(_) => Ok(Self::Custom(custom)),
+                None => Self::custom(LEGACY_MARK_NAMESPACE, &custom),
+            },
+        };
+        kind.map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum MarkValue {
     String(String),
@@ -46,12 +203,64 @@ pub struct MarkInterval {
     pub op_id: OpId,
 }
 
+impl MarkInterval {
+    pub fn attr(&self, key: &str) -> Option<&MarkValue> {
+        self.attrs.get(key).map(LwwRegister::get_ref)
+    }
+
+    /// `key`'s value if it is a string; `None` when absent or a bool.
+    pub fn string_attr(&self, key: &str) -> Option<&str> {
+        match self.attr(key)? {
+            MarkValue::String(value) => Some(value),
+            MarkValue::Bool(_) => None,
+        }
+    }
+
+    pub fn bool_attr(&self, key: &str) -> Option<bool> {
+        match self.attr(key)? {
+            MarkValue::Bool(value) => Some(*value),
+            MarkValue::String(_) => None,
+        }
+    }
+
+    /// Link target; only meaningful on [`MarkKind::Link`].
+    pub fn href(&self) -> Option<&str> {
+        self.string_attr("href")
+    }
+
+    /// Current attribute values, without their LWW metadata.
+    pub fn attr_values(&self) -> BTreeMap<String, MarkValue> {
+        self.attrs
+            .iter()
+            .map(|(key, register)| (key.clone(), register.get()))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RemoveMark {
     pub observed: StateVector,
     pub op_id: OpId,
 }
 
+/// Replicated update to a [`MarkSet`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkOp {
+    Set {
+        interval_id: MarkIntervalId,
+        kind: MarkKind,
+        start: Anchor,
+        end: Anchor,
+        attrs: BTreeMap<String, MarkValue>,
+        op_id: OpId,
+    },
+    Remove {
+        interval_id: MarkIntervalId,
+        observed: StateVector,
+        op_id: OpId,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MarkSet {
     intervals: BTreeMap<MarkIntervalId, MarkInterval>,
@@ -134,7 +343,7 @@ impl MarkSet {
                 op_id,
             });
 
-        if op_id >= entry.op_id {
+        if CausalOrd::supersedes(op_id, entry.op_id) {
             entry.kind = kind;
             entry.start = start;
             entry.end = end;
@@ -150,9 +359,28 @@ impl MarkSet {
         }
     }
 
+    /// Apply a local or remote update. Commutative and idempotent.
+    pub fn apply(&mut self, op: MarkOp) {
+        match op {
+            MarkOp::Set {
+                interval_id,
+                kind,
+                start,
+                end,
+                attrs,
+                op_id,
+            } => self.set_mark(interval_id, kind, start, end, attrs, op_id),
+            MarkOp::Remove {
+                interval_id,
+                observed,
+                op_id,
+            } => self.remove_mark(interval_id, observed, op_id),
+        }
+    }
+
     pub fn remove_mark(&mut self, interval_id: MarkIntervalId, observed: StateVector, op_id: OpId) {
         match self.removes.get(&interval_id) {
-            Some(existing) if existing.op_id >= op_id => {}
+            Some(existing) if !CausalOrd::wins(op_id, existing.op_id) => {}
             _ => {
                 self.removes
                     .insert(interval_id, RemoveMark { observed, op_id });
@@ -160,11 +388,40 @@ impl MarkSet {
         }
     }
 
+    /// Drop removed intervals, and their remove records, once `min_state_vector`
+    /// (what every replica has observed) covers both the interval and its removal.
+    /// Remove records left without an interval are dropped on the same terms.
+    /// Returns how many intervals were dropped.
+    ///
+    /// Rendering is unchanged: only inactive intervals go, and a late concurrent
+    /// remove of a dropped interval leaves a record that the next pass drops.
+    pub fn gc(&mut self, min_state_vector: &StateVector) -> usize {
+        let covers = |id: OpId| min_state_vector.get(id.peer).unwrap_or(0) >= id.counter;
+        let collectable: Vec<MarkIntervalId> = self
+            .removes
+            .iter()
+            .filter(|(interval_id, remove)| {
+                covers(**interval_id)
+                    && covers(remove.op_id)
+                    && self.intervals.get(interval_id).is_none_or(|interval| {
+                        covers(interval.op_id) && !self.is_active(interval_id)
+                    })
+            })
+            .map(|(interval_id, _)| *interval_id)
+            .collect();
+        let mut dropped = 0;
+        for interval_id in collectable {
+            self.removes.remove(&interval_id);
+            dropped += usize::from(self.intervals.remove(&interval_id).is_some());
+        }
+        dropped
+    }
+
     /// Merge mark history from another text block when its units are appended here.
     pub(crate) fn merge_from(&mut self, other: &Self) {
         for (id, interval) in &other.intervals {
             match self.intervals.get(id) {
-                Some(existing) if existing.op_id >= interval.op_id => {}
+                Some(existing) if !CausalOrd::wins(interval.op_id, existing.op_id) => {}
                 _ => {
                     self.intervals.insert(*id, interval.clone());
                 }
@@ -172,7 +429,7 @@ impl MarkSet {
         }
         for (id, remove) in &other.removes {
             match self.removes.get(id) {
-                Some(existing) if existing.op_id >= remove.op_id => {}
+                Some(existing) if !CausalOrd::wins(remove.op_id, existing.op_id) => {}
                 _ => {
                     self.removes.insert(*id, remove.clone());
                 }
@@ -212,7 +469,8 @@ impl MarkSet {
             .filter(|interval| self.is_active(&interval.id))
     }
 
-    pub(crate) fn iter_all_intervals(&self) -> impl Iterator<Item = &MarkInterval> {
+    /// Every interval, removed ones included; see [`Self::is_active`].
+    pub fn iter_all_intervals(&self) -> impl Iterator<Item = &MarkInterval> {
         self.intervals.values()
     }
 
@@ -221,15 +479,16 @@ impl MarkSet {
     }
 
     pub fn render_spans(&self, element_order: &[OpId], visible_len: usize) -> Vec<Span> {
-        let mut index_map: BTreeMap<OpId, usize> = BTreeMap::new();
-        for (visible_index, id) in element_order.iter().enumerate() {
-            index_map.insert(*id, visible_index);
-        }
+        let offsets: OffsetMap = element_order.iter().copied().collect();
+        self.render_spans_in(&offsets, visible_len)
+    }
 
+    /// [`Self::render_spans`] against an already built [`OffsetMap`].
+    pub fn render_spans_in(&self, offsets: &OffsetMap, visible_len: usize) -> Vec<Span> {
         let mut marks_at: Vec<Vec<MarkIntervalId>> = vec![Vec::new(); visible_len + 1];
         for interval in self.iter_active_intervals() {
-            let start = resolve_anchor(&interval.start, &index_map, visible_len);
-            let end = resolve_anchor(&interval.end, &index_map, visible_len);
+            let start = resolve_anchor(&interval.start, offsets, visible_len);
+            let end = resolve_anchor(&interval.end, offsets, visible_len);
             let (from, to) = if start <= end {
                 (start, end)
             } else {
@@ -270,16 +529,16 @@ impl MarkSet {
 
     /// Active intervals resolved to half-open visible grapheme ranges.
     pub fn resolved_intervals(&self, element_order: &[OpId]) -> Vec<(&MarkInterval, usize, usize)> {
-        let index_map: BTreeMap<OpId, usize> = element_order
-            .iter()
-            .enumerate()
-            .map(|(index, id)| (*id, index))
-            .collect();
-        let len = element_order.len();
+        self.resolved_intervals_in(&element_order.iter().copied().collect())
+    }
+
+    /// [`Self::resolved_intervals`] against an already built [`OffsetMap`].
+    pub fn resolved_intervals_in(&self, offsets: &OffsetMap) -> Vec<(&MarkInterval, usize, usize)> {
+        let len = offsets.len();
         self.iter_active_intervals()
             .map(|interval| {
-                let start = resolve_anchor(&interval.start, &index_map, len);
-                let end = resolve_anchor(&interval.end, &index_map, len);
+                let start = resolve_anchor(&interval.start, offsets, len);
+                let end = resolve_anchor(&interval.end, offsets, len);
                 (interval, start.min(end), start.max(end))
             })
             .collect()
@@ -292,8 +551,237 @@ impl Default for MarkSet {
     }
 }
 
-fn resolve_anchor(anchor: &Anchor, index_map: &BTreeMap<OpId, usize>, len: usize) -> usize {
-    let base = index_map.get(&anchor.elem_id).copied().unwrap_or(0);
+impl Mergeable for MarkSet {
+    type Op = MarkOp;
+
+    fn apply_op(&mut self, op: MarkOp) {
+        self.apply(op);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        let mut vector = StateVector::new();
+        for interval in self.intervals.values() {
+            let attrs = interval.attrs.values().map(LwwRegister::op_id);
+            for id in [interval.id, interval.op_id].into_iter().chain(attrs) {
+                merge::observe(&mut vector, id);
+            }
+        }
+        for remove in self.removes.values() {
+            merge::observe(&mut vector, remove.op_id);
+        }
+        vector
+    }
+
+    /// One set per unseen write still visible in an interval, carrying the
+    /// attributes that write won, and the unseen removes. Sets from older
+    /// writes carry the interval's current kind and anchors, which the newest
+    /// write overrides wherever it lands.
+    fn ops_since(&self, since: &StateVector) -> Vec<MarkOp> {
+        let mut ops = Vec::new();
+        for interval in self.intervals.values() {
+            let mut writes: BTreeMap<OpId, BTreeMap<String, MarkValue>> = BTreeMap::new();
+            writes.entry(interval.op_id).or_default();
+            for (key, register) in &interval.attrs {
+                writes
+                    .entry(register.op_id())
+                    .or_default()
+                    .insert(key.clone(), register.get());
+            }
+            for (op_id, attrs) in writes {
+                if merge::covers(since, op_id) {
+                    continue;
+                }
+                ops.push(MarkOp::Set {
+                    interval_id: interval.id,
+                    kind: interval.kind.clone(),
+                    start: interval.start,
+                    end: interval.end,
+                    attrs,
+                    op_id,
+                });
+            }
+        }
+        for (interval_id, remove) in &self.removes {
+            if !merge::covers(since, remove.op_id) {
+                ops.push(MarkOp::Remove {
+                    interval_id: *interval_id,
+                    observed: remove.observed.clone(),
+                    op_id: remove.op_id,
+                });
+            }
+        }
+        ops
+    }
+}
+
+/// Type of a [`MarkValue`], as declared in a [`MarkSchema`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkAttrType {
+    String,
+    Bool,
+}
+
+impl MarkAttrType {
+    pub fn of(value: &MarkValue) -> Self {
+        match value {
+            MarkValue::String(_) => Self::String,
+            MarkValue::Bool(_) => Self::Bool,
+        }
+    }
+}
+
+impl std::fmt::Display for MarkAttrType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::String => "string",
+            Self::Bool => "bool",
+        })
+    }
+}
+
+/// How a [`MarkSchema`] treats attributes its kind does not declare.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// Every attribute must be declared; a kind with no entry declares none.
+    Strict,
+    /// Undeclared attributes pass, so peers can add keys this replica does not
+    /// know yet. Declared attributes are still required and type checked.
+    #[default]
+    Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MarkSchemaError {
+    #[error(transparent)]
+    InvalidKind(#[from] InvalidMarkKind),
+    #[error("{kind:?} mark requires attribute `{key}`")]
+    MissingAttribute { kind: MarkKind, key: String },
+    #[error("{kind:?} mark attribute `{key}` must be a {expected}")]
+    WrongType {
+        kind: MarkKind,
+        key: String,
+        expected: MarkAttrType,
+    },
+    #[error("{kind:?} mark does not declare attribute `{key}`")]
+    UndeclaredAttribute { kind: MarkKind, key: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AttrSpec {
+    ty: MarkAttrType,
+    required: bool,
+}
+
+/// Attribute constraints per [`MarkKind`].
+///
+/// Marks are free-form on the wire; a schema lets an application reject a link
+/// without an `href` when it is added locally, or find one that arrived from a
+/// peer before serializing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkSchema {
+    mode: SchemaMode,
+    kinds: BTreeMap<MarkKind, BTreeMap<String, AttrSpec>>,
+}
+
+impl MarkSchema {
+    /// A schema that declares nothing.
+    pub fn new(mode: SchemaMode) -> Self {
+        Self {
+            mode,
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    /// The attributes the Markdown parser and serializer use: links require a
+    /// string `href`, and every built-in kind may carry a string `delimiter`.
+    pub fn markdown(mode: SchemaMode) -> Self {
+        let mut schema = Self::new(mode).require(MarkKind::Link, "href", MarkAttrType::String);
+        for kind in [
+            MarkKind::Bold,
+            MarkKind::Italic,
+            MarkKind::Code,
+            MarkKind::Link,
+            MarkKind::Strikethrough,
+        ] {
+            schema = schema.allow(kind, "delimiter", MarkAttrType::String);
+        }
+        schema
+    }
+
+    pub fn mode(&self) -> SchemaMode {
+        self.mode
+    }
+
+    pub fn require(self, kind: MarkKind, key: &str, ty: MarkAttrType) -> Self {
+        self.declare(kind, key, AttrSpec { ty, required: true })
+    }
+
+    /// Declare an optional attribute.
+    pub fn allow(self, kind: MarkKind, key: &str, ty: MarkAttrType) -> Self {
+        self.declare(
+            kind,
+            key,
+            AttrSpec {
+                ty,
+                required: false,
+            },
+        )
+    }
+
+    fn declare(mut self, kind: MarkKind, key: &str, spec: AttrSpec) -> Self {
+        self.kinds
+            .entry(kind)
+            .or_default()
+            .insert(key.to_string(), spec);
+        self
+    }
+
+    pub fn validate(
+        &self,
+        kind: &MarkKind,
+        attrs: &BTreeMap<String, MarkValue>,
+    ) -> Result<(), MarkSchemaError> {
+        kind.validate()?;
+        let declared = self.kinds.get(kind);
+        for (key, spec) in declared.into_iter().flatten() {
+            match attrs.get(key) {
+                None if spec.required => {
+                    return Err(MarkSchemaError::MissingAttribute {
+                        kind: kind.clone(),
+                        key: key.clone(),
+                    });
+                }
+                Some(value) if MarkAttrType::of(value) != spec.ty => {
+                    return Err(MarkSchemaError::WrongType {
+                        kind: kind.clone(),
+                        key: key.clone(),
+                        expected: spec.ty,
+                    });
+                }
+                _ => {}
+            }
+        }
+        if self.mode == SchemaMode::Strict
+            && let Some(key) = attrs
+                .keys()
+                .find(|key| declared.is_none_or(|declared| !declared.contains_key(*key)))
+        {
+            return Err(MarkSchemaError::UndeclaredAttribute {
+                kind: kind.clone(),
+                key: key.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn validate_interval(&self, interval: &MarkInterval) -> Result<(), MarkSchemaError> {
+        self.validate(&interval.kind, &interval.attr_values())
+    }
+}
+
+/// Anchors on elements missing from `offsets` resolve as if on the first one.
+pub(crate) fn resolve_anchor(anchor: &Anchor, offsets: &OffsetMap, len: usize) -> usize {
+    let base = offsets.offset_of(anchor.elem_id).unwrap_or(0);
     match anchor.bias {
         AnchorBias::Before => base,
         AnchorBias::After => (base + 1).min(len),
@@ -367,4 +855,38 @@ mod tests {
             op(10)
         );
     }
+
+    #[test]
+    fn gc_drops_observed_removals_and_orphaned_remove_records() {
+        let mut observed = StateVector::new();
+        observed.set(1, 2);
+        let mut marks = MarkSet::new();
+        marks.set_mark(
+            op(1),
+            MarkKind::Bold,
+            anchor(10),
+            anchor(11),
+            BTreeMap::new(),
+            op(1),
+        );
+        marks.remove_mark(op(1), observed.clone(), op(3));
+
+        // Not every replica has seen the removal yet.
+        assert_eq!(marks.gc(&observed), 0);
+        assert!(marks.interval(&op(1)).is_some());
+
+        observed.set(1, 3);
+        assert_eq!(marks.gc(&observed), 1);
+        assert!(marks.interval(&op(1)).is_none());
+        assert!(marks.removes.is_empty());
+
+        // A late concurrent remove of the collected interval leaves a bare record
+        // that the next pass drops once it is observed too.
+        marks.remove_mark(op(1), observed.clone(), op(4));
+        assert_eq!(marks.gc(&observed), 0);
+        assert_eq!(marks.removes.len(), 1);
+        observed.set(1, 4);
+        assert_eq!(marks.gc(&observed), 0);
+        assert!(marks.removes.is_empty());
+    }
 }
@@ -0,0 +1,89 @@
+//! One interface over the CRDT containers.
+//!
+//! [`Mergeable`] lets the sync and storage layers move state between replicas
+//! of any container without knowing its type: ship [`Mergeable::ops_since`] a
+//! peer's [`StateVector`] and have it [`Mergeable::apply_op`] each one, or join
+//! two whole replicas with [`Mergeable::merge_state`].
+//!
+//! Containers keep deletions as tombstones, not as the operations that made
+//! them, so `ops_since` resends every deletion a receiver might be missing,
+//! whatever its age. Applying one twice is harmless.
+
+use super::{OpId, StateVector};
+use std::collections::BTreeSet;
+
+/// A replicated container that can catch another replica up.
+pub trait Mergeable {
+    /// What one replica ships to another.
+    type Op: Clone;
+
+    /// Apply a local or remote operation. Replays are idempotent, and an op
+    /// that arrives ahead of what it depends on waits for it.
+    fn apply_op(&mut self, op: Self::Op);
+
+    /// Join `other`'s state into this replica.
+    fn merge_state(&mut self, other: &Self) {
+        for op in other.ops_since(&StateVector::new()) {
+            self.apply_op(op);
+        }
+    }
+
+    /// Highest counter applied per peer.
+    fn state_vector(&self) -> StateVector;
+
+    /// Operations that bring a replica at `since` up to this one, dependencies
+    /// first.
+    fn ops_since(&self, since: &StateVector) -> Vec<Self::Op>;
+}
+
+/// Whether `since` includes `id`.
+pub(crate) fn covers(since: &StateVector, id: OpId) -> bool {
+    since.get(id.peer).unwrap_or(0) >= id.counter
+}
+
+/// Raise `vector`'s entry for `id.peer` to `id.counter`.
+pub(crate) fn observe(vector: &mut StateVector, id: OpId) {
+    if vector.get(id.peer).unwrap_or(0) < id.counter {
+        vector.set(id.peer, id.counter);
+    }
+}
+
+/// Raise each of `vector`'s entries to `other`'s.
+pub(crate) fn join(vector: &mut StateVector, other: &StateVector) {
+    for (peer, counter) in other.iter() {
+        observe(vector, OpId { counter, peer });
+    }
+}
+
+/// `items` reordered so each follows the items it depends on. `deps` names
+/// the positions in `items` an item waits for; dependencies form no cycles.
+pub(crate) fn dependency_order<T>(items: Vec<T>, deps: impl Fn(&T) -> Vec<usize>) -> Vec<T> {
+    let mut order = Vec::with_capacity(items.len());
+    let mut visited = BTreeSet::new();
+    for root in 0..items.len() {
+        if !visited.insert(root) {
+            continue;
+        }
+        // Iterative post-order walk: anchor chains can be as long as the text.
+        let mut stack = vec![(root, deps(&items[root]))];
+        while let Some((item, waiting)) = stack.last_mut() {
+            match waiting.pop() {
+                Some(dep) => {
+                    if visited.insert(dep) {
+                        let next = deps(&items[dep]);
+                        stack.push((dep, next));
+                    }
+                }
+                None => {
+                    order.push(*item);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    let mut slots: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .map(|index| slots[index].take().expect("each item ordered once"))
+        .collect()
+}
@@ -4,31 +4,93 @@
 //! collaborative editing:
 //!
 //! - [`OpId`] - Unique operation identifiers using Lamport timestamps
+//! - [`PeerClock`] - Thread-safe counter reservation as [`OpIdRange`]s
 //! - [`StateVector`] - Version vector for tracking peer state
-//! - [`Sequence`] - RGA-based ordered sequence with tombstones
+//! - [`CounterRanges`] - Counter sets as disjoint ranges, for frontiers with gaps
+//! - [`PeerTable`] - Peer interning for compact ids in snapshots and message framing
+//! - [`Sequence`] - Ordered sequence with tombstones, integrated YATA-style ([`SequenceStats`] sizes it,
+//!   [`ElementInfo`] inspects it)
+//! - [`PagedSequence`] - [`Sequence`] that pages cold tombstones out to a [`PageStore`]
+//! - [`RunSequence`] - Run-length encoded text variant of [`Sequence`]
+//! - [`Text`] - Grapheme-addressed collaborative text with marks
+//! - [`SegmentationRules`] - Grapheme cluster rules pinned to a Unicode version
 //! - [`LwwRegister`] - Last-writer-wins register for single values
-//! - [`Map`] - LWW-based key-value map
+//! - [`MultiValueRegister`] - Register that keeps concurrent values side by side
+//! - [`Counter`] - Grow/shrink (PN) counter
+//! - [`ValueTree`] - Nested JSON-like values that merge at every level
+//! - [`Map`] - LWW-based key-value map with observed-remove deletion
+//! - [`OrSet`] - Observed-remove set where a concurrent add survives a remove
+//! - [`OffsetMap`] - Visible offsets of sequence elements, indexed both ways
+//! - [`CausalOrd`] - The total order every conflict between writes is settled by
+//! - [`Mergeable`] - Catching one replica of any container up with another
 //! - [`mark`] - Rich causal mark/formatting CRDT (`MarkSet`, spans)
+//! - [`pending`] - Summaries and expiry of operations buffered on missing dependencies
 
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
+pub mod clock;
+pub mod compact;
+pub mod inspect;
 pub mod mark;
-
+pub mod merge;
+pub mod offsets;
+pub mod order;
+pub mod orset;
+pub mod paged;
+pub mod pending;
+pub mod ranges;
+pub mod runs;
+pub mod segmentation;
+pub mod text;
+pub mod value;
+
+pub use clock::{ClockError, OpIdRange, PeerClock};
+pub use compact::{CompactError, CompactOpId, PeerTable};
+pub use inspect::ElementInfo;
+pub use merge::Mergeable;
 // Unified mark API (rich causal remove-wins). Generic LWW mark types were removed.
 pub use mark::{
-    Anchor, AnchorBias, MarkInterval, MarkIntervalId, MarkKind, MarkSet, MarkValue, RemoveMark,
-    Span,
+    Anchor, AnchorBias, InvalidMarkKind, LEGACY_MARK_NAMESPACE, MarkAttrType, MarkInterval,
+    MarkIntervalId, MarkKind, MarkOp, MarkSchema, MarkSchemaError, MarkSet, MarkValue, RemoveMark,
+    SchemaMode, Span,
+};
+pub use offsets::{OffsetMap, VisibleText};
+pub use order::CausalOrd;
+pub use orset::{OrSet, OrSetOp};
+pub use paged::{MemoryPageStore, PageId, PageStore, PagedSequence, PagingError};
+pub use pending::{AwaitedDependency, ExpiredOp, PendingSummary};
+pub use ranges::CounterRanges;
+pub use runs::{RunOp, RunSequence, TextRun};
+pub use segmentation::SegmentationRules;
+pub use text::{Text, TextOp};
+pub use value::{
+    PathKey, PathStep, Scalar, Value, ValueInit, ValueList, ValueMap, ValueOp, ValueTree,
 };
 
 pub type PeerId = u64;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+/// Ordered by [`CausalOrd`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct OpId {
     pub counter: u64,
     pub peer: PeerId,
 }
 
+impl Ord for OpId {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        CausalOrd(*self).cmp(&CausalOrd(*other))
+    }
+}
+
+impl PartialOrd for OpId {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StateVector {
     peers: BTreeMap<PeerId, u64>,
@@ -58,7 +120,7 @@ impl StateVector {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Element<T> {
     pub id: OpId,
     pub value: Option<T>,
@@ -66,7 +128,7 @@ pub struct Element<T> {
     pub right_origin: Option<OpId>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SequenceOp<T> {
     Insert {
         after: Option<OpId>,
@@ -78,6 +140,87 @@ pub enum SequenceOp<T> {
         target: OpId,
         id: OpId,
     },
+    /// Tombstone every element between `from` and `to` (inclusive, in sequence
+    /// order) that the deleting peer had observed.
+    ///
+    /// Elements inserted concurrently inside the range are not covered by
+    /// `observed` and stay visible; covered elements that arrive after the range
+    /// delete are tombstoned on insert.
+    DeleteRange {
+        from: OpId,
+        to: OpId,
+        id: OpId,
+        observed: StateVector,
+    },
+}
+
+impl<T> SequenceOp<T> {
+    pub fn id(&self) -> OpId {
+        match self {
+            Self::Insert { id, .. } | Self::Delete { id, .. } | Self::DeleteRange { id, .. } => *id,
+        }
+    }
+}
+
+/// What [`Sequence`] ships through [`Mergeable`]: an element with its placement,
+/// which lets a tombstone travel without the value it no longer holds, or an
+/// operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceDelta<T> {
+    Element(Element<T>),
+    Op(SequenceOp<T>),
+}
+
+/// Applied [`SequenceOp::DeleteRange`] kept so late-arriving covered inserts
+/// are tombstoned too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RangeTombstone {
+    from: OpId,
+    to: OpId,
+    id: OpId,
+    observed: StateVector,
+}
+
+impl RangeTombstone {
+    fn covers(&self, id: OpId) -> bool {
+        self.observed.get(id.peer).unwrap_or(0) >= id.counter
+    }
+}
+
+/// Element and overhead counts of a [`Sequence`], from [`Sequence::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub visible: usize,
+    pub tombstones: usize,
+    /// Operations buffered until their causal predecessors arrive.
+    pub pending_ops: usize,
+    pub range_tombstones: usize,
+    /// Bytes held by the sequence itself, not by heap data its values own.
+    pub estimated_bytes: usize,
+}
+
+impl SequenceStats {
+    pub fn elements(&self) -> usize {
+        self.visible + self.tombstones
+    }
+
+    /// Share of elements that are tombstones; 0 for an empty sequence.
+    pub fn tombstone_ratio(&self) -> f64 {
+        match self.elements() {
+            0 => 0.0,
+            elements => self.tombstones as f64 / elements as f64,
+        }
+    }
+}
+
+impl std::ops::AddAssign for SequenceStats {
+    fn add_assign(&mut self, other: Self) {
+        self.visible += other.visible;
+        self.tombstones += other.tombstones;
+        self.pending_ops += other.pending_ops;
+        self.range_tombstones += other.range_tombstones;
+        self.estimated_bytes += other.estimated_bytes;
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -86,6 +229,93 @@ pub struct Sequence<T> {
     index: BTreeMap<OpId, usize>,
     pending_inserts: BTreeMap<OpId, Vec<SequenceOp<T>>>,
     pending_deletes: BTreeMap<OpId, Vec<SequenceOp<T>>>,
+    range_tombstones: Vec<RangeTombstone>,
+    versions: ValueVersions,
+    buffered_since: pending::BufferedSince,
+    /// Floor of the tombstones [`Sequence::drop_tombstones`] removed. An origin
+    /// or target at or below it that is not held was dropped, so operations
+    /// naming it are placed without it rather than buffered.
+    compacted: StateVector,
+}
+
+static NEXT_VALUE_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_value_version() -> u64 {
+    NEXT_VALUE_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Stamps behind [`Sequence::value_version`]. Stamps come from one process-wide
+/// counter and are copied by `Clone`, so an `(element, stamp)` pair always names
+/// the same value. Replica state only: ignored by equality and serde.
+#[derive(Debug, Clone)]
+struct ValueVersions {
+    /// Version of every element whose value was not mutated since construction.
+    epoch: u64,
+    changed: BTreeMap<OpId, u64>,
+}
+
+impl ValueVersions {
+    fn get(&self, id: &OpId) -> u64 {
+        self.changed.get(id).copied().unwrap_or(self.epoch)
+    }
+
+    fn bump(&mut self, id: OpId) {
+        self.changed.insert(id, next_value_version());
+    }
+}
+
+impl Default for ValueVersions {
+    fn default() -> Self {
+        Self {
+            epoch: next_value_version(),
+            changed: BTreeMap::new(),
+        }
+    }
+}
+
+impl PartialEq for ValueVersions {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ValueVersions {}
+
+/// Serialized form of [`Sequence`]: ordered elements (tombstones included) and
+/// the operations [`Sequence::pending_ops`] reports; the index is rebuilt.
+#[derive(Serialize, Deserialize)]
+struct SequenceSerde<T> {
+    elements: Vec<Element<T>>,
+    pending: Vec<SequenceOp<T>>,
+    #[serde(default, skip_serializing_if = "StateVector::is_empty")]
+    compacted: StateVector,
+}
+
+impl<T: Clone + Serialize> Serialize for Sequence<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        SequenceSerde {
+            elements: self.elements.clone(),
+            pending: self.pending_ops(),
+            compacted: self.compacted.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for Sequence<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = SequenceSerde::deserialize(deserializer)?;
+        Ok(
+            Self::from_elements_and_pending(value.elements, value.pending)
+                .with_compacted(value.compacted),
+        )
+    }
 }
 
 impl<T: Clone> Sequence<T> {
@@ -95,12 +325,18 @@ impl<T: Clone> Sequence<T> {
             index: BTreeMap::new(),
             pending_inserts: BTreeMap::new(),
             pending_deletes: BTreeMap::new(),
+            range_tombstones: Vec::new(),
+            versions: ValueVersions::default(),
+            buffered_since: pending::BufferedSince::default(),
+            compacted: StateVector::new(),
         }
     }
 
-    /// Whether this build uses sibling-local sequence ordering updates.
+    /// Whether inserts are placed without reordering the whole sequence. Always
+    /// true since integration became incremental; the `sequence_incremental`
+    /// feature that used to switch it is accepted and has no effect.
     pub const fn incremental_ordering_enabled() -> bool {
-        cfg!(feature = "sequence_incremental")
+        true
     }
 
     pub fn insert(&mut self, after: Option<OpId>, value: T, id: OpId) {
@@ -117,12 +353,118 @@ impl<T: Clone> Sequence<T> {
         self.apply(SequenceOp::Delete { target, id });
     }
 
+    /// Delete the elements between `from` and `to` (inclusive) in one pass.
+    ///
+    /// The range covers what this replica has observed, as given by `observed`.
+    pub fn delete_range(&mut self, from: OpId, to: OpId, id: OpId, observed: StateVector) {
+        self.apply(SequenceOp::DeleteRange {
+            from,
+            to,
+            id,
+            observed,
+        });
+    }
+
+    /// Delete every element `observed` covers with one [`SequenceOp::DeleteRange`]
+    /// from the first element to the last, tombstones included. Returns the op,
+    /// or `None` when the sequence holds no elements.
+    pub fn delete_range_all(&mut self, id: OpId, observed: StateVector) -> Option<SequenceOp<T>> {
+        let from = self.elements.first()?.id;
+        let to = self.elements.last()?.id;
+        let op = SequenceOp::DeleteRange {
+            from,
+            to,
+            id,
+            observed,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
     pub fn apply(&mut self, op: SequenceOp<T>) {
         if let Some(inserted_id) = self.apply_now(op) {
-            self.process_pending(inserted_id);
+            let inserted = self.process_pending(inserted_id);
+            self.cover_late_inserts(&inserted);
         }
-        #[cfg(feature = "sequence_incremental")]
-        self.debug_assert_incremental_order();
+    }
+
+    /// Join `other`'s full state into this replica without replaying its
+    /// operations: the union of both replicas' elements, tombstones, range
+    /// deletes and buffered operations. The result is the state applying every
+    /// operation either replica applied would reach, so the join is
+    /// commutative, associative and idempotent.
+    ///
+    /// Elements this replica lacks are integrated anchors first. One whose
+    /// anchor neither replica holds waits like a remote insert would; one this
+    /// replica dropped as a tombstone (see [`Sequence::drop_tombstones`]) is not
+    /// brought back.
+    pub fn merge_state(&mut self, other: &Sequence<T>) {
+        let missing: Vec<&Element<T>> = other
+            .elements
+            .iter()
+            .filter(|elem| !self.is_resolved(elem.id))
+            .collect();
+        let positions: BTreeMap<OpId, usize> = missing
+            .iter()
+            .enumerate()
+            .map(|(position, elem)| (elem.id, position))
+            .collect();
+        let missing = merge::dependency_order(missing, |elem| {
+            [elem.after, elem.right_origin]
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| positions.get(&dependency).copied())
+                .collect()
+        });
+
+        let mut placed = Vec::new();
+        for elem in missing {
+            if self.is_resolved(elem.id) {
+                continue;
+            }
+            let ready = [elem.after, elem.right_origin]
+                .into_iter()
+                .flatten()
+                .all(|dependency| self.is_resolved(dependency));
+            if !ready {
+                Mergeable::apply_op(self, SequenceDelta::Element(elem.clone()));
+                continue;
+            }
+            self.integrate(elem.clone());
+            placed.extend(self.process_pending(elem.id));
+        }
+        for elem in other.elements.iter().filter(|elem| elem.value.is_none()) {
+            self.apply_delete(elem.id);
+        }
+        merge::join(&mut self.compacted, &other.compacted);
+
+        for range in &other.range_tombstones {
+            if self
+                .range_tombstones
+                .iter()
+                .any(|known| known.id == range.id)
+            {
+                continue;
+            }
+            if let Err((anchor, range)) = self.apply_delete_range(range.clone()) {
+                self.buffer_delete_range(anchor, range);
+            }
+        }
+        for (awaited, ops) in other.pending_inserts.iter().chain(&other.pending_deletes) {
+            for op in ops {
+                let known = self
+                    .pending_inserts
+                    .get(awaited)
+                    .into_iter()
+                    .chain(self.pending_deletes.get(awaited))
+                    .flatten()
+                    .any(|pending| pending.id() == op.id());
+                if !known {
+                    self.apply(op.clone());
+                }
+            }
+        }
+        self.cover_late_inserts(&placed);
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &T> {
@@ -155,6 +497,99 @@ impl<T: Clone> Sequence<T> {
             .count()
     }
 
+    /// Visible element ids indexed by offset and back.
+    pub fn offset_map(&self) -> OffsetMap {
+        self.elements
+            .iter()
+            .filter(|elem| elem.value.is_some())
+            .map(|elem| elem.id)
+            .collect()
+    }
+
+    /// Remove tombstones inserted at or below `floor` that no remaining element
+    /// uses as an origin, that no range delete ends on, and that are not in
+    /// `keep` (e.g. mark anchors). Returns how many were removed.
+    ///
+    /// The sequence remembers `floor`: a later insert whose right origin was
+    /// removed is bounded by the end of its anchor's run instead, one anchored
+    /// on a removed tombstone goes just before its right origin, and a delete of
+    /// one is already done. A peer that still holds the tombstones places such
+    /// inserts the same way as long as nothing was inserted next to them after
+    /// `floor`, so pick a floor every peer has seen.
+    pub fn drop_tombstones(&mut self, floor: &StateVector, keep: &BTreeSet<OpId>) -> usize {
+        let mut references: BTreeMap<OpId, usize> = BTreeMap::new();
+        for elem in &self.elements {
+            for origin in [elem.after, elem.right_origin].into_iter().flatten() {
+                *references.entry(origin).or_default() += 1;
+            }
+        }
+        let pinned = |id: &OpId| {
+            keep.contains(id)
+                || self
+                    .range_tombstones
+                    .iter()
+                    .any(|range| range.from == *id || range.to == *id)
+        };
+        let droppable = |elem: &Element<T>| {
+            elem.value.is_none()
+                && floor.get(elem.id.peer).unwrap_or(0) >= elem.id.counter
+                && !pinned(&elem.id)
+        };
+        let mut queue: Vec<OpId> = self
+            .elements
+            .iter()
+            .filter(|elem| droppable(elem) && !references.contains_key(&elem.id))
+            .map(|elem| elem.id)
+            .collect();
+        let mut dropped = BTreeSet::new();
+        while let Some(id) = queue.pop() {
+            if !dropped.insert(id) {
+                continue;
+            }
+            let elem = &self.elements[self.index[&id]];
+            for origin in [elem.after, elem.right_origin].into_iter().flatten() {
+                let Some(count) = references.get_mut(&origin) else {
+                    continue;
+                };
+                *count -= 1;
+                if *count == 0
+                    && let Some(index) = self.index.get(&origin)
+                    && droppable(&self.elements[*index])
+                {
+                    queue.push(origin);
+                }
+            }
+        }
+        if !dropped.is_empty() {
+            self.elements.retain(|elem| !dropped.contains(&elem.id));
+            self.rebuild_index();
+            merge::join(&mut self.compacted, floor);
+        }
+        dropped.len()
+    }
+
+    pub fn stats(&self) -> SequenceStats {
+        use std::mem::size_of;
+        let visible = self.len_visible();
+        let pending_ops = self
+            .pending_inserts
+            .values()
+            .chain(self.pending_deletes.values())
+            .map(Vec::len)
+            .sum();
+        SequenceStats {
+            visible,
+            tombstones: self.elements.len() - visible,
+            pending_ops,
+            range_tombstones: self.range_tombstones.len(),
+            estimated_bytes: size_of::<Self>()
+                + self.elements.capacity() * size_of::<Element<T>>()
+                + self.index.len() * (size_of::<OpId>() + size_of::<usize>())
+                + pending_ops * size_of::<SequenceOp<T>>()
+                + self.range_tombstones.len() * size_of::<RangeTombstone>(),
+        }
+    }
+
     pub(crate) fn visible_at_physical(&self, index: usize) -> Option<&T> {
         self.elements.get(index)?.value.as_ref()
     }
@@ -179,6 +614,7 @@ impl<T: Clone> Sequence<T> {
             && let Some(elem) = self.elements.get_mut(index)
         {
             elem.value = Some(value);
+            self.versions.bump(id);
         }
     }
 
@@ -189,6 +625,7 @@ impl<T: Clone> Sequence<T> {
         let index = *self.index.get(&id)?;
         let elem = self.elements.get_mut(index)?;
         let value = elem.value.as_mut()?;
+        self.versions.bump(id);
         Some(f(value))
     }
 
@@ -200,7 +637,16 @@ impl<T: Clone> Sequence<T> {
     /// Direct mutable access to a live element's value (`None` if unknown/tombstoned).
     pub fn value_mut(&mut self, id: OpId) -> Option<&mut T> {
         let index = *self.index.get(&id)?;
-        self.elements.get_mut(index)?.value.as_mut()
+        let value = self.elements.get_mut(index)?.value.as_mut()?;
+        self.versions.bump(id);
+        Some(value)
+    }
+
+    /// Stamp that changes whenever element `id`'s value may have been mutated
+    /// through [`Self::update_value`], [`Self::with_value_mut`] or
+    /// [`Self::value_mut`]; callers key caches of per-element derived data on it.
+    pub fn value_version(&self, id: OpId) -> u64 {
+        self.versions.get(&id)
     }
 
     pub fn apply_op(&mut self, op: (OpId, T)) {
@@ -227,6 +673,10 @@ impl<T: Clone> Sequence<T> {
             index,
             pending_inserts: BTreeMap::new(),
             pending_deletes: BTreeMap::new(),
+            range_tombstones: Vec::new(),
+            versions: ValueVersions::default(),
+            buffered_since: pending::BufferedSince::default(),
+            compacted: StateVector::new(),
         }
     }
 
@@ -244,18 +694,34 @@ impl<T: Clone> Sequence<T> {
             index,
             pending_inserts: BTreeMap::new(),
             pending_deletes: BTreeMap::new(),
+            range_tombstones: Vec::new(),
+            versions: ValueVersions::default(),
+            buffered_since: pending::BufferedSince::default(),
+            compacted: StateVector::new(),
         }
     }
 
-    /// Pending operations whose cross-peer anchor or target has not arrived yet.
+    /// Pending operations whose cross-peer anchor or target has not arrived yet,
+    /// followed by applied range deletes that still cover future inserts.
     ///
     /// Session snapshots persist these independently from the compacted operation log.
+    /// Replaying an applied range delete is idempotent.
     pub(crate) fn pending_ops(&self) -> Vec<SequenceOp<T>> {
         self.pending_inserts
             .values()
             .chain(self.pending_deletes.values())
             .flatten()
             .cloned()
+            .chain(
+                self.range_tombstones
+                    .iter()
+                    .map(|range| SequenceOp::DeleteRange {
+                        from: range.from,
+                        to: range.to,
+                        id: range.id,
+                        observed: range.observed.clone(),
+                    }),
+            )
             .collect()
     }
 
@@ -271,143 +737,181 @@ impl<T: Clone> Sequence<T> {
         sequence
     }
 
+    /// The floor [`Sequence::drop_tombstones`] removed tombstones at.
+    pub fn compacted(&self) -> &StateVector {
+        &self.compacted
+    }
+
+    /// Restore the floor of [`Self::compacted`], e.g. from a snapshot.
+    pub fn with_compacted(mut self, compacted: StateVector) -> Self {
+        self.compacted = compacted;
+        self
+    }
+
+    /// Whether `id` is held, or was held and removed as a tombstone.
+    fn is_resolved(&self, id: OpId) -> bool {
+        self.index.contains_key(&id) || merge::covers(&self.compacted, id)
+    }
+
     pub fn element_ids(&self) -> Vec<OpId> {
         self.elements.iter().map(|elem| elem.id).collect()
     }
 
-    fn apply_insert(
-        &mut self,
-        after: Option<OpId>,
-        id: &OpId,
-        value: &T,
-        right_origin: Option<OpId>,
-    ) -> bool {
-        self.apply_insert_internal(after, id, value, right_origin, true)
+    /// Buffered inserts and deletes grouped by the anchor, right origin or target
+    /// they wait for, with ages measured at `now`.
+    pub fn pending_summary(&self, now: Instant) -> PendingSummary {
+        pending::summarize(
+            self.pending_inserts
+                .iter()
+                .chain(&self.pending_deletes)
+                .flat_map(|(awaited, ops)| {
+                    ops.iter()
+                        .map(move |op| (*awaited, self.buffered_since.get(&op.id(), now)))
+                }),
+            now,
+        )
+    }
+
+    /// Drop buffered operations that have waited at least `ttl` as of `now`.
+    pub fn expire_pending(&mut self, now: Instant, ttl: Duration) -> Vec<ExpiredOp> {
+        let mut expired = Vec::new();
+        for buffer in [&mut self.pending_inserts, &mut self.pending_deletes] {
+            buffer.retain(|awaited, ops| {
+                ops.retain(|op| {
+                    let id = op.id();
+                    let age = now.saturating_duration_since(self.buffered_since.get(&id, now));
+                    if age < ttl {
+                        return true;
+                    }
+                    expired.push(ExpiredOp {
+                        id,
+                        awaited: *awaited,
+                        age,
+                    });
+                    false
+                });
+                !ops.is_empty()
+            });
+        }
+        for op in &expired {
+            self.buffered_since.clear(&op.id);
+        }
+        expired
     }
 
-    fn apply_insert_internal(
+    /// Integrate an insert, or return the anchor or right origin it waits for.
+    fn apply_insert(
         &mut self,
         after: Option<OpId>,
         id: &OpId,
         value: &T,
         right_origin: Option<OpId>,
-        rebuild: bool,
-    ) -> bool {
-        if self.index.contains_key(id) {
-            return true;
+    ) -> Result<(), OpId> {
+        // A dropped tombstone stays deleted.
+        if self.is_resolved(*id) {
+            return Ok(());
         }
-
-        if let Some(anchor) = after
-            && !self.index.contains_key(&anchor)
+        if let Some(missing) = [after, right_origin]
+            .into_iter()
+            .flatten()
+            .find(|dependency| !self.is_resolved(*dependency))
         {
-            return false;
+            return Err(missing);
         }
-
-        let element = Element {
+        self.integrate(Element {
             id: *id,
             value: Some(value.clone()),
             after,
             right_origin,
-        };
-
-        #[cfg(feature = "sequence_incremental")]
-        if rebuild {
-            self.insert_incrementally(element);
-            return true;
-        }
-
-        self.elements.push(element);
-        if rebuild {
-            self.rebuild_order();
-        } else {
-            let idx = self.elements.len() - 1;
-            self.index.insert(*id, idx);
-        }
-        true
+        });
+        Ok(())
     }
 
-    #[cfg(feature = "sequence_incremental")]
-    fn insert_incrementally(&mut self, element: Element<T>) {
-        let insert_at = self
-            .elements
-            .iter()
-            .enumerate()
-            .find(|(_, sibling)| {
-                sibling.after == element.after && Self::compare_siblings(&element, sibling).is_lt()
-            })
-            .map(|(index, _)| index)
-            .unwrap_or_else(|| self.subtree_end(element.after));
-
-        self.elements.insert(insert_at, element);
-        for index in insert_at..self.elements.len() {
-            self.index.insert(self.elements[index].id, index);
+    fn apply_delete(&mut self, target: OpId) -> bool {
+        let Some(index) = self.index.get(&target).copied() else {
+            return false;
+        };
+        if let Some(elem) = self.elements.get_mut(index) {
+            elem.value = None;
         }
+        true
     }
 
-    #[cfg(feature = "sequence_incremental")]
-    fn subtree_end(&self, parent: Option<OpId>) -> usize {
-        let Some(parent) = parent else {
-            return self.elements.len();
+    /// Tombstone the observed elements between `from` and `to`, or hand the range
+    /// back with the missing endpoint so the caller can buffer on it.
+    fn apply_delete_range(&mut self, range: RangeTombstone) -> Result<(), (OpId, RangeTombstone)> {
+        let Some(start) = self.index.get(&range.from).copied() else {
+            return Err((range.from, range));
         };
-        let start = self.index[&parent] + 1;
-        self.elements[start..]
-            .iter()
-            .position(|candidate| !self.is_descendant_of(candidate, parent))
-            .map_or(self.elements.len(), |offset| start + offset)
-    }
-
-    #[cfg(feature = "sequence_incremental")]
-    fn is_descendant_of(&self, candidate: &Element<T>, ancestor: OpId) -> bool {
-        let mut cursor = candidate.after;
-        while let Some(id) = cursor {
-            if id == ancestor {
-                return true;
+        let Some(end) = self.index.get(&range.to).copied() else {
+            return Err((range.to, range));
+        };
+        let (start, end) = (start.min(end), start.max(end));
+        for elem in &mut self.elements[start..=end] {
+            if range.covers(elem.id) {
+                elem.value = None;
             }
-            cursor = self.get_element(&id).and_then(|element| element.after);
         }
-        false
+        if !self
+            .range_tombstones
+            .iter()
+            .any(|known| known.id == range.id)
+        {
+            self.range_tombstones.push(range);
+        }
+        Ok(())
     }
 
-    fn compare_siblings(a: &Element<T>, b: &Element<T>) -> std::cmp::Ordering {
-        match (a.right_origin, b.right_origin) {
-            (Some(ra), Some(rb)) => {
-                if ra == rb {
-                    b.id.cmp(&a.id)
-                } else {
-                    ra.cmp(&rb)
+    /// Tombstone newly placed elements that fall inside an applied range delete
+    /// which had already observed them.
+    fn cover_late_inserts(&mut self, inserted: &[OpId]) {
+        if self.range_tombstones.is_empty() {
+            return;
+        }
+        for id in inserted {
+            let Some(position) = self.index.get(id).copied() else {
+                continue;
+            };
+            let covered = self.range_tombstones.iter().any(|range| {
+                if !range.covers(*id) {
+                    return false;
                 }
+                let (Some(from), Some(to)) =
+                    (self.index.get(&range.from), self.index.get(&range.to))
+                else {
+                    return false;
+                };
+                (*from.min(to)..=*from.max(to)).contains(&position)
+            });
+            if covered {
+                self.elements[position].value = None;
             }
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => b.id.cmp(&a.id),
         }
     }
 
-    #[cfg(all(feature = "sequence_incremental", debug_assertions))]
-    fn debug_assert_incremental_order(&self) {
-        let mut rebuilt = self.clone();
-        rebuilt.rebuild_order();
-        debug_assert_eq!(
-            self.element_ids(),
-            rebuilt.element_ids(),
-            "incremental sequence order diverged from full rebuild"
+    fn buffer_delete_range(&mut self, anchor: OpId, range: RangeTombstone) {
+        self.buffer(
+            anchor,
+            SequenceOp::DeleteRange {
+                from: range.from,
+                to: range.to,
+                id: range.id,
+                observed: range.observed,
+            },
         );
     }
 
-    #[cfg(all(feature = "sequence_incremental", not(debug_assertions)))]
-    fn debug_assert_incremental_order(&self) {}
-
-    fn apply_delete(&mut self, target: OpId) -> bool {
-        let Some(index) = self.index.get(&target).copied() else {
-            return false;
+    /// Hold `op` until `awaited` is placed.
+    fn buffer(&mut self, awaited: OpId, op: SequenceOp<T>) {
+        self.buffered_since.stamp(op.id());
+        let buffer = match op {
+            SequenceOp::Insert { .. } => &mut self.pending_inserts,
+            SequenceOp::Delete { .. } | SequenceOp::DeleteRange { .. } => &mut self.pending_deletes,
         };
-        if let Some(elem) = self.elements.get_mut(index) {
-            elem.value = None;
-        }
-        true
+        buffer.entry(awaited).or_default().push(op);
     }
 
-    /// Right-neighbor id used for RGA concurrent-insert ordering at `after`.
+    /// Right-neighbor id that bounds where an insert at `after` integrates.
     ///
     /// Exposed so the session layer can stamp wire `right_origin` (N4) without
     /// reimplementing sequence layout.
@@ -419,52 +923,64 @@ impl<T: Clone> Sequence<T> {
         self.elements.get(position).map(|elem| elem.id)
     }
 
-    fn rebuild_order(&mut self) {
-        use std::collections::BTreeMap;
-
-        let mut element_map: BTreeMap<OpId, Element<T>> = BTreeMap::new();
-        for elem in self.elements.drain(..) {
-            element_map.insert(elem.id, elem);
-        }
-
-        let mut children: BTreeMap<Option<OpId>, Vec<OpId>> = BTreeMap::new();
-        for elem in element_map.values() {
-            children.entry(elem.after).or_default().push(elem.id);
+    /// Place `element`, whose anchor and right origin are present, by YATA
+    /// integration (Nicolaescu et al., 2016).
+    ///
+    /// The element goes somewhere between its anchor and its right origin; what
+    /// lies in between was inserted concurrently. Scanning that region left to
+    /// right, `left` advances past each element that must precede the new one:
+    /// a sibling on the same anchor with a higher id, or an element anchored
+    /// inside the region to the left of every element the new one already
+    /// precedes. A sibling with a lower id and the same right origin ends the
+    /// scan, as does an element anchored before the region. The result does not
+    /// depend on the order concurrent inserts arrive in, and a run typed by one
+    /// peer stays contiguous. A right origin at or before the anchor, which no
+    /// replica computes, bounds nothing.
+    ///
+    /// A right origin removed by [`Sequence::drop_tombstones`] bounds nothing;
+    /// an element whose anchor was removed goes just before its right origin.
+    fn integrate(&mut self, element: Element<T>) {
+        let position_of = |id: Option<OpId>| id.and_then(|id| self.index.get(&id).copied());
+        if element.after.is_some() && position_of(element.after).is_none() {
+            let position = position_of(element.right_origin).unwrap_or(self.elements.len());
+            self.place(position, element);
+            return;
         }
-
-        for ids in children.values_mut() {
-            ids.sort_by(|a, b| {
-                let elem_a = element_map
-                    .get(a)
-                    .expect("child id must exist in element map during rebuild");
-                let elem_b = element_map
-                    .get(b)
-                    .expect("child id must exist in element map during rebuild");
-                Self::compare_siblings(elem_a, elem_b)
-            });
+        let anchor = position_of(element.after);
+        let start = anchor.map_or(0, |anchor| anchor + 1);
+        let end = position_of(element.right_origin)
+            .filter(|&right| right >= start)
+            .unwrap_or(self.elements.len());
+
+        // Positions as `Option<usize>`: `None` sits before every element.
+        let mut left = anchor;
+        for scan in start..end {
+            let other = &self.elements[scan];
+            if other.after == element.after {
+                if CausalOrd::wins(other.id, element.id) {
+                    left = Some(scan);
+                } else if other.right_origin == element.right_origin {
+                    break;
+                }
+            } else {
+                let other_anchor = position_of(other.after);
+                if other_anchor <= anchor {
+                    break;
+                }
+                if other_anchor <= left {
+                    left = Some(scan);
+                }
+            }
         }
 
-        let mut ordered_ids = Vec::with_capacity(element_map.len());
-        Self::walk_children(None, &children, &mut ordered_ids);
+        let position = left.map_or(0, |left| left + 1);
+        self.place(position, element);
+    }
 
-        // Use remove() instead of get().cloned() to move elements without cloning
-        self.elements = ordered_ids
-            .into_iter()
-            .filter_map(|id| element_map.remove(&id))
-            .collect();
-        self.rebuild_index();
-    }
-
-    fn walk_children(
-        parent: Option<OpId>,
-        children: &BTreeMap<Option<OpId>, Vec<OpId>>,
-        out: &mut Vec<OpId>,
-    ) {
-        if let Some(kids) = children.get(&parent) {
-            for id in kids {
-                out.push(*id);
-                Self::walk_children(Some(*id), children, out);
-            }
+    fn place(&mut self, position: usize, element: Element<T>) {
+        self.elements.insert(position, element);
+        for index in position..self.elements.len() {
+            self.index.insert(self.elements[index].id, index);
         }
     }
 
@@ -482,44 +998,56 @@ impl<T: Clone> Sequence<T> {
                 id,
                 value,
                 right_origin,
-            } => {
-                if self.apply_insert(after, &id, &value, right_origin) {
-                    Some(id)
-                } else {
-                    if let Some(anchor) = after {
-                        self.pending_inserts
-                            .entry(anchor)
-                            .or_default()
-                            .push(SequenceOp::Insert {
-                                after: Some(anchor),
-                                id,
-                                value,
-                                right_origin,
-                            });
-                    }
+            } => match self.apply_insert(after, &id, &value, right_origin) {
+                Ok(()) => Some(id),
+                Err(missing) => {
+                    self.buffer(
+                        missing,
+                        SequenceOp::Insert {
+                            after,
+                            id,
+                            value,
+                            right_origin,
+                        },
+                    );
                     None
                 }
-            }
+            },
             SequenceOp::Delete { target, id } => {
-                if self.apply_delete(target) {
-                    None
-                } else {
-                    self.pending_deletes
-                        .entry(target)
-                        .or_default()
-                        .push(SequenceOp::Delete { target, id });
-                    None
+                if !self.apply_delete(target) && !self.is_resolved(target) {
+                    self.buffer(target, SequenceOp::Delete { target, id });
                 }
+                None
+            }
+            SequenceOp::DeleteRange {
+                from,
+                to,
+                id,
+                observed,
+            } => {
+                let range = RangeTombstone {
+                    from,
+                    to,
+                    id,
+                    observed,
+                };
+                if let Err((anchor, range)) = self.apply_delete_range(range) {
+                    self.buffer_delete_range(anchor, range);
+                }
+                None
             }
         }
     }
 
-    fn process_pending(&mut self, inserted_id: OpId) {
+    /// Drain operations unblocked by `inserted_id`, returning every id placed.
+    fn process_pending(&mut self, inserted_id: OpId) -> Vec<OpId> {
         use std::collections::VecDeque;
         let mut queue = VecDeque::new();
         self.enqueue_pending(inserted_id, &mut queue);
 
-        let mut inserted = false;
+        let mut placed = vec![inserted_id];
+        // Range deletes are positional, so they wait until every insert is placed.
+        let mut ranges = Vec::new();
         while let Some(op) = queue.pop_front() {
             match op {
                 SequenceOp::Insert {
@@ -527,42 +1055,51 @@ impl<T: Clone> Sequence<T> {
                     id,
                     value,
                     right_origin,
-                } => {
-                    if self.apply_insert_internal(
-                        after,
-                        &id,
-                        &value,
-                        right_origin,
-                        cfg!(feature = "sequence_incremental"),
-                    ) {
-                        inserted = true;
+                } => match self.apply_insert(after, &id, &value, right_origin) {
+                    Ok(()) => {
+                        self.buffered_since.clear(&id);
+                        placed.push(id);
                         self.enqueue_pending(id, &mut queue);
-                    } else if let Some(anchor) = after {
-                        self.pending_inserts
-                            .entry(anchor)
-                            .or_default()
-                            .push(SequenceOp::Insert {
-                                after: Some(anchor),
-                                id,
-                                value,
-                                right_origin,
-                            });
                     }
-                }
+                    Err(missing) => self.buffer(
+                        missing,
+                        SequenceOp::Insert {
+                            after,
+                            id,
+                            value,
+                            right_origin,
+                        },
+                    ),
+                },
                 SequenceOp::Delete { target, id } => {
-                    if !self.apply_delete(target) {
-                        self.pending_deletes
-                            .entry(target)
-                            .or_default()
-                            .push(SequenceOp::Delete { target, id });
+                    if self.apply_delete(target) || self.is_resolved(target) {
+                        self.buffered_since.clear(&id);
+                    } else {
+                        self.buffer(target, SequenceOp::Delete { target, id });
                     }
                 }
+                SequenceOp::DeleteRange {
+                    from,
+                    to,
+                    id,
+                    observed,
+                } => ranges.push(RangeTombstone {
+                    from,
+                    to,
+                    id,
+                    observed,
+                }),
             }
         }
 
-        if inserted && !cfg!(feature = "sequence_incremental") {
-            self.rebuild_order();
+        for range in ranges {
+            let id = range.id;
+            match self.apply_delete_range(range) {
+                Ok(()) => self.buffered_since.clear(&id),
+                Err((anchor, range)) => self.buffer_delete_range(anchor, range),
+            }
         }
+        placed
     }
 
     fn enqueue_pending(&mut self, id: OpId, queue: &mut std::collections::VecDeque<SequenceOp<T>>) {
@@ -575,6 +1112,145 @@ impl<T: Clone> Sequence<T> {
     }
 }
 
+impl<T: Clone> Mergeable for Sequence<T> {
+    type Op = SequenceDelta<T>;
+
+    /// A tombstone whose anchor or right origin is missing cannot be placed;
+    /// it is kept as a delete of its element, applied once the element arrives.
+    fn apply_op(&mut self, op: SequenceDelta<T>) {
+        let element = match op {
+            SequenceDelta::Op(op) => return self.apply(op),
+            SequenceDelta::Element(Element {
+                id,
+                value: Some(value),
+                after,
+                right_origin,
+            }) => {
+                return self.apply(SequenceOp::Insert {
+                    after,
+                    id,
+                    value,
+                    right_origin,
+                });
+            }
+            SequenceDelta::Element(element) => element,
+        };
+        let id = element.id;
+        let ready = [element.after, element.right_origin]
+            .into_iter()
+            .flatten()
+            .all(|dependency| self.is_resolved(dependency));
+        if self.is_resolved(id) || !ready {
+            self.apply(SequenceOp::Delete { target: id, id });
+            return;
+        }
+        self.integrate(element);
+        let inserted = self.process_pending(id);
+        self.cover_late_inserts(&inserted);
+    }
+
+    fn merge_state(&mut self, other: &Self) {
+        Sequence::merge_state(self, other);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        let mut vector = StateVector::new();
+        let ids = self.elements.iter().map(|elem| elem.id);
+        for id in ids.chain(self.range_tombstones.iter().map(|range| range.id)) {
+            merge::observe(&mut vector, id);
+        }
+        vector
+    }
+
+    /// Unseen elements, tombstones included; a delete of every tombstone the
+    /// receiver already holds, carrying its target's id since the delete's own
+    /// is not kept; then unseen buffered operations and range deletes.
+    fn ops_since(&self, since: &StateVector) -> Vec<SequenceDelta<T>> {
+        let unseen: Vec<&Element<T>> = self
+            .elements
+            .iter()
+            .filter(|elem| !merge::covers(since, elem.id))
+            .collect();
+        let positions: BTreeMap<OpId, usize> = unseen
+            .iter()
+            .enumerate()
+            .map(|(position, elem)| (elem.id, position))
+            .collect();
+        let unseen = merge::dependency_order(unseen, |elem| {
+            [elem.after, elem.right_origin]
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| positions.get(&dependency).copied())
+                .collect()
+        });
+        let deletes = self
+            .elements
+            .iter()
+            .filter(|elem| elem.value.is_none() && merge::covers(since, elem.id))
+            .map(|elem| {
+                SequenceDelta::Op(SequenceOp::Delete {
+                    target: elem.id,
+                    id: elem.id,
+                })
+            });
+        let pending = self
+            .pending_ops()
+            .into_iter()
+            .filter(|op| !merge::covers(since, op.id()))
+            .map(SequenceDelta::Op);
+        unseen
+            .into_iter()
+            .map(|elem| SequenceDelta::Element(elem.clone()))
+            .chain(deletes)
+            .chain(pending)
+            .collect()
+    }
+}
+
+impl<T: Clone + VisibleText> Sequence<T> {
+    /// Concatenated text of the visible elements.
+    pub fn visible_string(&self) -> String {
+        let mut out = String::new();
+        for value in self.iter() {
+            value.push_visible(&mut out);
+        }
+        out
+    }
+}
+
+/// An insert whose id is already held with a different value.
+#[cfg(feature = "duplicate-checks")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("operation {id:?} was already inserted with a different value")]
+pub struct DuplicateOpMismatch {
+    pub id: OpId,
+}
+
+#[cfg(feature = "duplicate-checks")]
+impl<T: Clone + PartialEq> Sequence<T> {
+    /// [`Self::apply`], except that an insert repeating a placed or buffered id
+    /// with a different value is refused instead of silently ignored. Tombstoned
+    /// elements no longer hold a value and accept any repeat.
+    pub fn apply_checked(&mut self, op: SequenceOp<T>) -> Result<(), DuplicateOpMismatch> {
+        if let SequenceOp::Insert { id, value, .. } = &op {
+            let placed = self
+                .get_element(id)
+                .map(|element| element.value.as_ref().is_some_and(|known| known != value));
+            let buffered = || {
+                self.pending_inserts.values().flatten().any(|pending| {
+                    matches!(pending, SequenceOp::Insert { id: known_id, value: known, .. }
+                        if known_id == id && known != value)
+                })
+            };
+            if placed.unwrap_or_else(buffered) {
+                return Err(DuplicateOpMismatch { id: *id });
+            }
+        }
+        self.apply(op);
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LwwRegister<T> {
     value: T,
@@ -587,7 +1263,7 @@ impl<T: Clone> LwwRegister<T> {
     }
 
     pub fn set(&mut self, value: T, op_id: OpId) {
-        if op_id >= self.op_id {
+        if CausalOrd::supersedes(op_id, self.op_id) {
             self.value = value;
             self.op_id = op_id;
         }
@@ -609,9 +1285,255 @@ impl<T: Clone> LwwRegister<T> {
     }
 }
 
+impl<T: Clone> Mergeable for LwwRegister<T> {
+    type Op = (OpId, T);
+
+    fn apply_op(&mut self, (op_id, value): (OpId, T)) {
+        self.set(value, op_id);
+    }
+
+    fn merge_state(&mut self, other: &Self) {
+        self.set(other.value.clone(), other.op_id);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        let mut vector = StateVector::new();
+        merge::observe(&mut vector, self.op_id);
+        vector
+    }
+
+    fn ops_since(&self, since: &StateVector) -> Vec<(OpId, T)> {
+        if merge::covers(since, self.op_id) {
+            Vec::new()
+        } else {
+            vec![(self.op_id, self.value.clone())]
+        }
+    }
+}
+
+/// Grow/shrink counter (PN-counter) with one increment and decrement total per peer.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counter {
+    increments: BTreeMap<PeerId, u64>,
+    decrements: BTreeMap<PeerId, u64>,
+}
+
+/// One peer's cumulative counter totals after the write `id`.
+///
+/// Applying a delta keeps the per-peer maximum, so replays and reordering are harmless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterDelta {
+    pub id: OpId,
+    pub increments: u64,
+    pub decrements: u64,
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn value(&self) -> i64 {
+        let up: u64 = self.increments.values().sum();
+        let down: u64 = self.decrements.values().sum();
+        (i128::from(up) - i128::from(down)).clamp(i64::MIN.into(), i64::MAX.into()) as i64
+    }
+
+    /// Add `amount` on behalf of `op_id.peer`, returning the delta to ship.
+    pub fn increment(&mut self, amount: u64, op_id: OpId) -> CounterDelta {
+        let total = Self::total(&self.increments, op_id.peer).saturating_add(amount);
+        Self::raise(&mut self.increments, op_id.peer, total);
+        self.delta(op_id)
+    }
+
+    /// Subtract `amount` on behalf of `op_id.peer`, returning the delta to ship.
+    pub fn decrement(&mut self, amount: u64, op_id: OpId) -> CounterDelta {
+        let total = Self::total(&self.decrements, op_id.peer).saturating_add(amount);
+        Self::raise(&mut self.decrements, op_id.peer, total);
+        self.delta(op_id)
+    }
+
+    pub fn apply(&mut self, delta: &CounterDelta) {
+        Self::raise(&mut self.increments, delta.id.peer, delta.increments);
+        Self::raise(&mut self.decrements, delta.id.peer, delta.decrements);
+    }
+
+    /// State-based join with another replica.
+    pub fn merge(&mut self, other: &Self) {
+        for (peer, total) in &other.increments {
+            Self::raise(&mut self.increments, *peer, *total);
+        }
+        for (peer, total) in &other.decrements {
+            Self::raise(&mut self.decrements, *peer, *total);
+        }
+    }
+
+    fn total(totals: &BTreeMap<PeerId, u64>, peer: PeerId) -> u64 {
+        totals.get(&peer).copied().unwrap_or(0)
+    }
+
+    /// Keep the larger total; zero totals are never stored so equal states compare equal.
+    fn raise(totals: &mut BTreeMap<PeerId, u64>, peer: PeerId, total: u64) {
+        if total > Self::total(totals, peer) {
+            totals.insert(peer, total);
+        }
+    }
+
+    fn delta(&self, id: OpId) -> CounterDelta {
+        CounterDelta {
+            id,
+            increments: Self::total(&self.increments, id.peer),
+            decrements: Self::total(&self.decrements, id.peer),
+        }
+    }
+}
+
+/// A write to a [`MultiValueRegister`], superseding every value `observed` covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterWrite<T> {
+    pub id: OpId,
+    pub value: T,
+    pub observed: StateVector,
+}
+
+/// Multi-value register: concurrent writes are all kept until a later write observes them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiValueRegister<T> {
+    /// Surviving writes in `OpId` order.
+    writes: Vec<RegisterWrite<T>>,
+}
+
+impl<T> Default for MultiValueRegister<T> {
+    fn default() -> Self {
+        Self { writes: Vec::new() }
+    }
+}
+
+impl<T: Clone> MultiValueRegister<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write `value` as of `observed`, returning the write to ship.
+    pub fn set(&mut self, value: T, op_id: OpId, observed: StateVector) -> RegisterWrite<T> {
+        let write = RegisterWrite {
+            id: op_id,
+            value,
+            observed,
+        };
+        self.apply(write.clone());
+        write
+    }
+
+    pub fn apply(&mut self, write: RegisterWrite<T>) {
+        let covers =
+            |observed: &StateVector, id: OpId| observed.get(id.peer).unwrap_or(0) >= id.counter;
+        // Already present, or superseded by a write that saw it.
+        if self
+            .writes
+            .iter()
+            .any(|kept| kept.id == write.id || covers(&kept.observed, write.id))
+        {
+            return;
+        }
+        self.writes.retain(|kept| !covers(&write.observed, kept.id));
+        let position = self.writes.partition_point(|kept| kept.id < write.id);
+        self.writes.insert(position, write);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for write in &other.writes {
+            self.apply(write.clone());
+        }
+    }
+
+    /// Concurrent values in `OpId` order; empty before the first write.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.writes.iter().map(|write| &write.value)
+    }
+
+    pub fn writes(&self) -> &[RegisterWrite<T>] {
+        &self.writes
+    }
+
+    /// Whether concurrent writes are waiting to be resolved by a later write.
+    pub fn is_conflicted(&self) -> bool {
+        self.writes.len() > 1
+    }
+}
+
+/// Replicated update to a [`Map`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapOp<K, V> {
+    Set {
+        key: K,
+        value: V,
+        op_id: OpId,
+    },
+    /// Remove `key` as of the winning write `observed`; later writes survive it.
+    Remove {
+        key: K,
+        observed: OpId,
+        op_id: OpId,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct MapEntry<V> {
+    /// `None` when a remove arrived before any write for the key.
+    value: Option<LwwRegister<V>>,
+    /// Highest write id observed by a remove of this key.
+    removed: Option<OpId>,
+}
+
+impl<V: Clone> MapEntry<V> {
+    fn live(&self) -> Option<&LwwRegister<V>> {
+        self.value.as_ref().filter(|register| {
+            self.removed
+                .is_none_or(|removed| register.op_id() > removed)
+        })
+    }
+}
+
+/// LWW map with observed-remove deletion: a remove only deletes the write it saw,
+/// so a concurrent later write keeps the key alive.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Map<K, V> {
-    entries: BTreeMap<K, LwwRegister<V>>,
+    entries: BTreeMap<K, MapEntry<V>>,
+}
+
+/// Entries serialize as a list so keys need not be strings in JSON.
+#[derive(Serialize, Deserialize)]
+struct MapSerde<K, V> {
+    entries: Vec<(K, MapEntry<V>)>,
+}
+
+impl<K: Clone + Serialize, V: Clone + Serialize> Serialize for Map<K, V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        MapSerde {
+            entries: self
+                .entries
+                .iter()
+                .map(|(key, entry)| (key.clone(), entry.clone()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K: Ord + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for Map<K, V> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = MapSerde::deserialize(deserializer)?;
+        Ok(Self {
+            entries: value.entries.into_iter().collect(),
+        })
+    }
 }
 
 impl<K: Ord + Clone, V: Clone> Map<K, V> {
@@ -622,20 +1544,145 @@ impl<K: Ord + Clone, V: Clone> Map<K, V> {
     }
 
     pub fn set(&mut self, key: K, value: V, op_id: OpId) {
-        self.entries
-            .entry(key)
-            .and_modify(|register| register.set(value.clone(), op_id))
-            .or_insert_with(|| LwwRegister::new(value, op_id));
+        let entry = self.entries.entry(key).or_insert(MapEntry {
+            value: None,
+            removed: None,
+        });
+        match entry.value.as_mut() {
+            Some(register) => register.set(value, op_id),
+            None => entry.value = Some(LwwRegister::new(value, op_id)),
+        }
+    }
+
+    /// Remove the live value for `key`, returning the op to ship (`None` if absent).
+    pub fn remove(&mut self, key: &K, op_id: OpId) -> Option<MapOp<K, V>> {
+        let observed = self.entries.get(key)?.live()?.op_id();
+        let op = MapOp::Remove {
+            key: key.clone(),
+            observed,
+            op_id,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Apply a local or remote update. Commutative and idempotent.
+    pub fn apply(&mut self, op: MapOp<K, V>) {
+        match op {
+            MapOp::Set { key, value, op_id } => self.set(key, value, op_id),
+            MapOp::Remove { key, observed, .. } => self.apply_remove(key, observed),
+        }
+    }
+
+    /// State-based join with another replica's map.
+    pub fn merge(&mut self, other: &Self) {
+        for (key, entry) in &other.entries {
+            if let Some(register) = &entry.value {
+                self.set(key.clone(), register.get(), register.op_id());
+            }
+            if let Some(observed) = entry.removed {
+                self.apply_remove(key.clone(), observed);
+            }
+        }
     }
 
     /// Returns a reference to the value (zero-cost). Use `get_cloned()` if you need ownership.
     #[inline]
     pub fn get(&self, key: &K) -> Option<&V> {
-        self.entries.get(key).map(|register| register.get_ref())
+        self.entries
+            .get(key)
+            .and_then(MapEntry::live)
+            .map(|register| register.get_ref())
     }
 
     /// Returns a clone of the value. Prefer `get()` when a reference suffices.
     pub fn get_cloned(&self, key: &K) -> Option<V> {
-        self.entries.get(key).map(|register| register.get())
+        self.get(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Live entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| Some((key, entry.live()?.get_ref())))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Number of live entries.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    fn apply_remove(&mut self, key: K, observed: OpId) {
+        let entry = self.entries.entry(key).or_insert(MapEntry {
+            value: None,
+            removed: None,
+        });
+        if entry.removed.is_none_or(|removed| observed > removed) {
+            entry.removed = Some(observed);
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Mergeable for Map<K, V> {
+    type Op = MapOp<K, V>;
+
+    fn apply_op(&mut self, op: MapOp<K, V>) {
+        self.apply(op);
+    }
+
+    fn merge_state(&mut self, other: &Self) {
+        self.merge(other);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        let mut vector = StateVector::new();
+        for entry in self.entries.values() {
+            let ids = entry.value.as_ref().map(LwwRegister::op_id);
+            for id in ids.into_iter().chain(entry.removed) {
+                merge::observe(&mut vector, id);
+            }
+        }
+        vector
+    }
+
+    /// Unseen winning writes, and every remove: a remove keeps only the write
+    /// it observed, whose id it carries as its own.
+    fn ops_since(&self, since: &StateVector) -> Vec<MapOp<K, V>> {
+        let mut ops = Vec::new();
+        for (key, entry) in &self.entries {
+            if let Some(register) = &entry.value
+                && !merge::covers(since, register.op_id())
+            {
+                ops.push(MapOp::Set {
+                    key: key.clone(),
+                    value: register.get(),
+                    op_id: register.op_id(),
+                });
+            }
+            if let Some(observed) = entry.removed {
+                ops.push(MapOp::Remove {
+                    key: key.clone(),
+                    observed,
+                    op_id: observed,
+                });
+            }
+        }
+        ops
     }
 }
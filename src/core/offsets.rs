@@ -0,0 +1,93 @@
+//! Visible offsets of sequence elements.
+//!
+//! An [`OffsetMap`] is a snapshot of a sequence's live elements in document
+//! order. It translates a visible offset to the element id at that offset in
+//! O(1) and an element id back to its offset in O(log n), so the doc layer,
+//! anchor resolution and mark rendering share one index instead of each
+//! scanning `element_ids()`.
+
+use super::OpId;
+use super::mark::{Anchor, AnchorBias};
+use std::collections::BTreeMap;
+
+/// Element values that contribute text to a visible string.
+pub trait VisibleText {
+    fn push_visible(&self, out: &mut String);
+}
+
+impl VisibleText for char {
+    fn push_visible(&self, out: &mut String) {
+        out.push(*self);
+    }
+}
+
+impl VisibleText for String {
+    fn push_visible(&self, out: &mut String) {
+        out.push_str(self);
+    }
+}
+
+/// Live element ids in document order, indexed both ways.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OffsetMap {
+    ids: Vec<OpId>,
+    offsets: BTreeMap<OpId, usize>,
+}
+
+impl OffsetMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of visible elements.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Visible element ids in document order.
+    pub fn ids(&self) -> &[OpId] {
+        &self.ids
+    }
+
+    /// Element at visible `offset`.
+    pub fn id_at(&self, offset: usize) -> Option<OpId> {
+        self.ids.get(offset).copied()
+    }
+
+    /// Visible offset of `id`, or `None` if it is tombstoned or unknown.
+    pub fn offset_of(&self, id: OpId) -> Option<usize> {
+        self.offsets.get(&id).copied()
+    }
+
+    /// Left anchor for an insert at `offset` (`None` = start of the sequence).
+    pub fn after_for_offset(&self, offset: usize) -> Option<OpId> {
+        offset.checked_sub(1).and_then(|prev| self.id_at(prev))
+    }
+
+    /// Offset an anchor points at: before or just after its element.
+    ///
+    /// Anchors whose element is not visible resolve to `None`.
+    pub fn anchor_offset(&self, anchor: &Anchor) -> Option<usize> {
+        let base = self.offset_of(anchor.elem_id)?;
+        Some(match anchor.bias {
+            AnchorBias::Before => base,
+            AnchorBias::After => base + 1,
+        })
+    }
+}
+
+impl FromIterator<OpId> for OffsetMap {
+    fn from_iter<I: IntoIterator<Item = OpId>>(iter: I) -> Self {
+        let ids: Vec<OpId> = iter.into_iter().collect();
+        let offsets = ids
+            .iter()
+            .enumerate()
+            .map(|(offset, id)| (*id, offset))
+            .collect();
+        Self { ids, offsets }
+    }
+}
@@ -0,0 +1,62 @@
+//! The one total order that settles conflicts between writes.
+//!
+//! Replicas converge only if every one of them picks the same winner between
+//! two concurrent writes, whatever order the writes arrive in. Every such
+//! decision in the crate goes through [`CausalOrd`]: last-writer-wins registers,
+//! mark intervals and their removals, block ACLs, table cells and column
+//! alignment, block kind and task state, value-tree slots, and the order of
+//! concurrent siblings in a [`super::Sequence`]. [`OpId`]'s own `Ord` is
+//! defined by it too, so sorted collections of ids agree with it.
+//!
+//! The order compares counters first and peer ids second. A higher counter
+//! usually means a write made after seeing more history; the peer id only
+//! breaks ties between writes that share a counter, which are always
+//! concurrent.
+
+use super::OpId;
+use std::cmp::Ordering;
+
+/// An [`OpId`] ordered for conflict resolution: higher counter wins, and equal
+/// counters fall to the higher peer id. The order is total and antisymmetric,
+/// so exactly one of two distinct ids wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CausalOrd(pub OpId);
+
+impl Ord for CausalOrd {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .counter
+            .cmp(&other.0.counter)
+            .then_with(|| self.0.peer.cmp(&other.0.peer))
+    }
+}
+
+impl PartialOrd for CausalOrd {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl CausalOrd {
+    /// Whether a write stamped `a` beats a concurrent write stamped `b`.
+    pub fn wins(a: OpId, b: OpId) -> bool {
+        Self(a) > Self(b)
+    }
+
+    /// Whether a write stamped `incoming` replaces the one stamped `current`:
+    /// it wins, or it is the same write applied again, which rewrites the same
+    /// value.
+    pub fn supersedes(incoming: OpId, current: OpId) -> bool {
+        Self(incoming) >= Self(current)
+    }
+
+    /// The winner of `a` and `b`.
+    pub fn winner(a: OpId, b: OpId) -> OpId {
+        if Self::wins(b, a) { b } else { a }
+    }
+
+    /// The loser of `a` and `b`.
+    pub fn loser(a: OpId, b: OpId) -> OpId {
+        if Self::wins(b, a) { a } else { b }
+    }
+}
@@ -0,0 +1,309 @@
+//! Observed-remove set: concurrent add and remove of an element keep the add.
+//!
+//! Every addition is tagged with the [`OpId`] of the write that made it, and a
+//! removal deletes only the additions it observed. An addition the remover had
+//! not seen survives, so "add `x`" and "remove `x`" issued concurrently leave
+//! `x` in the set on every replica, in any delivery order. Removed additions
+//! are kept with the write that removed them, which also lets a later write ask
+//! whether an element was present as of the frontier it observed
+//! ([`OrSet::contains_as_of`]).
+
+use super::merge::Mergeable;
+use super::{OpId, StateVector};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Replicated update to an [`OrSet`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrSetOp<T> {
+    Add {
+        element: T,
+        op_id: OpId,
+    },
+    /// Remove the additions of `element` listed in `observed`.
+    Remove {
+        element: T,
+        observed: BTreeSet<OpId>,
+        op_id: OpId,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct OrSetEntry {
+    added: BTreeSet<OpId>,
+    /// `(addition, removing write)` pairs; may name additions not yet seen.
+    removed: BTreeSet<(OpId, OpId)>,
+}
+
+impl OrSetEntry {
+    fn is_removed(&self, tag: OpId) -> bool {
+        self.removers(tag).next().is_some()
+    }
+
+    fn removers(&self, tag: OpId) -> impl Iterator<Item = OpId> + '_ {
+        self.removed
+            .iter()
+            .filter(move |(removed, _)| *removed == tag)
+            .map(|(_, remover)| *remover)
+    }
+
+    fn live(&self) -> impl Iterator<Item = OpId> + '_ {
+        self.added
+            .iter()
+            .copied()
+            .filter(|tag| !self.is_removed(*tag))
+    }
+}
+
+/// Observed-remove (add-wins) set of `T`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrSet<T> {
+    entries: BTreeMap<T, OrSetEntry>,
+}
+
+/// Entries serialize as a list so elements need not be strings in JSON.
+#[derive(Serialize, Deserialize)]
+struct OrSetSerde<T> {
+    entries: Vec<(T, OrSetEntry)>,
+}
+
+impl<T: Clone + Serialize> Serialize for OrSet<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        OrSetSerde {
+            entries: self
+                .entries
+                .iter()
+                .map(|(element, entry)| (element.clone(), entry.clone()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Ord + Deserialize<'de>> Deserialize<'de> for OrSet<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = OrSetSerde::deserialize(deserializer)?;
+        Ok(Self {
+            entries: value.entries.into_iter().collect(),
+        })
+    }
+}
+
+impl<T> Default for OrSet<T> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord + Clone> OrSet<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `element` as the write `op_id`, returning the op to ship.
+    pub fn add(&mut self, element: T, op_id: OpId) -> OrSetOp<T> {
+        let op = OrSetOp::Add { element, op_id };
+        self.apply(op.clone());
+        op
+    }
+
+    /// Remove every addition of `element` this replica has seen, returning the
+    /// op to ship (`None` if absent).
+    pub fn remove(&mut self, element: &T, op_id: OpId) -> Option<OrSetOp<T>> {
+        let observed: BTreeSet<OpId> = self.entries.get(element)?.live().collect();
+        if observed.is_empty() {
+            return None;
+        }
+        let op = OrSetOp::Remove {
+            element: element.clone(),
+            observed,
+            op_id,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Remove the additions of `element` that a write which observed
+    /// `observed` had seen, returning the op to ship (`None` if it saw none).
+    pub fn remove_as_of(
+        &mut self,
+        element: &T,
+        observed: &StateVector,
+        op_id: OpId,
+    ) -> Option<OrSetOp<T>> {
+        let seen: BTreeSet<OpId> = self
+            .entries
+            .get(element)?
+            .added
+            .iter()
+            .copied()
+            .filter(|tag| covers(observed, *tag))
+            .collect();
+        if seen.is_empty() {
+            return None;
+        }
+        let op = OrSetOp::Remove {
+            element: element.clone(),
+            observed: seen,
+            op_id,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Apply a local or remote update. Commutative and idempotent.
+    pub fn apply(&mut self, op: OrSetOp<T>) {
+        match op {
+            OrSetOp::Add { element, op_id } => {
+                self.entries.entry(element).or_default().added.insert(op_id);
+            }
+            OrSetOp::Remove {
+                element,
+                observed,
+                op_id,
+            } => {
+                let entry = self.entries.entry(element).or_default();
+                entry
+                    .removed
+                    .extend(observed.into_iter().map(|tag| (tag, op_id)));
+            }
+        }
+    }
+
+    /// State-based join with another replica's set.
+    pub fn merge(&mut self, other: &Self) {
+        for (element, entry) in &other.entries {
+            let mine = self.entries.entry(element.clone()).or_default();
+            mine.added.extend(entry.added.iter().copied());
+            mine.removed.extend(entry.removed.iter().copied());
+        }
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.entries
+            .get(element)
+            .is_some_and(|entry| entry.live().next().is_some())
+    }
+
+    /// Whether `element` was in the set for a write that observed `observed`:
+    /// it had seen an addition and no removal of that addition.
+    pub fn contains_as_of(&self, element: &T, observed: &StateVector) -> bool {
+        self.entries.get(element).is_some_and(|entry| {
+            entry.added.iter().any(|tag| {
+                covers(observed, *tag)
+                    && !entry
+                        .removers(*tag)
+                        .any(|remover| covers(observed, remover))
+            })
+        })
+    }
+
+    /// Surviving additions of `element`, in `OpId` order.
+    pub fn additions(&self, element: &T) -> impl Iterator<Item = OpId> + '_ {
+        self.entries
+            .get(element)
+            .into_iter()
+            .flat_map(OrSetEntry::live)
+    }
+
+    /// Present elements in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.live().next().is_some())
+            .map(|(element, _)| element)
+    }
+
+    /// Every element ever added or removed, present or not.
+    pub fn known(&self) -> impl Iterator<Item = &T> {
+        self.entries.keys()
+    }
+
+    /// Number of present elements.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// The frontier of every addition and removal in the set: a write as of it
+    /// has seen them all.
+    pub fn frontier(&self) -> StateVector {
+        let mut frontier = StateVector::new();
+        let ids = self.entries.values().flat_map(|entry| {
+            entry.added.iter().copied().chain(
+                entry
+                    .removed
+                    .iter()
+                    .flat_map(|(tag, remover)| [*tag, *remover]),
+            )
+        });
+        for id in ids {
+            if frontier.get(id.peer).unwrap_or(0) < id.counter {
+                frontier.set(id.peer, id.counter);
+            }
+        }
+        frontier
+    }
+}
+
+impl<T: Ord + Clone> Mergeable for OrSet<T> {
+    type Op = OrSetOp<T>;
+
+    fn apply_op(&mut self, op: OrSetOp<T>) {
+        self.apply(op);
+    }
+
+    fn merge_state(&mut self, other: &Self) {
+        self.merge(other);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        self.frontier()
+    }
+
+    /// Unseen additions, then unseen removes, one per removing write.
+    fn ops_since(&self, since: &StateVector) -> Vec<OrSetOp<T>> {
+        let mut adds = Vec::new();
+        let mut removes = Vec::new();
+        for (element, entry) in &self.entries {
+            for tag in entry.added.iter().filter(|tag| !covers(since, **tag)) {
+                adds.push(OrSetOp::Add {
+                    element: element.clone(),
+                    op_id: *tag,
+                });
+            }
+            let mut by_remover: BTreeMap<OpId, BTreeSet<OpId>> = BTreeMap::new();
+            for (tag, remover) in &entry.removed {
+                if !covers(since, *remover) {
+                    by_remover.entry(*remover).or_default().insert(*tag);
+                }
+            }
+            removes.extend(
+                by_remover
+                    .into_iter()
+                    .map(|(op_id, observed)| OrSetOp::Remove {
+                        element: element.clone(),
+                        observed,
+                        op_id,
+                    }),
+            );
+        }
+        adds.extend(removes);
+        adds
+    }
+}
+
+fn covers(observed: &StateVector, id: OpId) -> bool {
+    observed.get(id.peer).unwrap_or(0) >= id.counter
+}
@@ -0,0 +1,343 @@
+//! Paging cold tombstones of a [`Sequence`] out of memory.
+//!
+//! A long-lived sequence (a meeting log edited for months) accumulates tombstones
+//! that matter only for placing remote operations anchored on them.
+//! [`PagedSequence`] moves fully tombstoned subtrees of the insertion tree into
+//! pages of a [`PageStore`] and reads them back when an operation names one of
+//! their ids.
+//!
+//! Each archived subtree keeps its root resident, so sibling order and right
+//! origins computed from the resident elements match the full sequence. Only the
+//! root's descendants are paged out, and only when nothing resident is anchored
+//! inside them.
+
+use super::{Element, OpId, PeerId, Sequence, SequenceOp};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::convert::Infallible;
+
+pub type PageId = u64;
+
+/// Backing store for archived element pages.
+pub trait PageStore {
+    type Error;
+
+    /// Persist `bytes` as a new page and return its id.
+    fn write_page(&mut self, bytes: &[u8]) -> Result<PageId, Self::Error>;
+
+    fn read_page(&mut self, page: PageId) -> Result<Vec<u8>, Self::Error>;
+
+    /// Drop a page once its elements are resident again.
+    fn remove_page(&mut self, page: PageId) -> Result<(), Self::Error>;
+}
+
+/// Pages kept in memory; for tests and for callers that swap pages out themselves.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryPageStore {
+    pages: BTreeMap<PageId, Vec<u8>>,
+    next: PageId,
+}
+
+impl MemoryPageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+impl PageStore for MemoryPageStore {
+    type Error = Infallible;
+
+    fn write_page(&mut self, bytes: &[u8]) -> Result<PageId, Infallible> {
+        let page = self.next;
+        self.next += 1;
+        self.pages.insert(page, bytes.to_vec());
+        Ok(page)
+    }
+
+    fn read_page(&mut self, page: PageId) -> Result<Vec<u8>, Infallible> {
+        Ok(self.pages.get(&page).cloned().unwrap_or_default())
+    }
+
+    fn remove_page(&mut self, page: PageId) -> Result<(), Infallible> {
+        self.pages.remove(&page);
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PagingError<E> {
+    #[error("page store error: {0}")]
+    Store(E),
+    #[error("cannot encode page: {0}")]
+    Encode(String),
+    #[error("page {page} is corrupt: {reason}")]
+    CorruptPage { page: PageId, reason: String },
+}
+
+/// Archived subtree: the resident root and the counter runs of its paged-out
+/// descendants.
+#[derive(Debug, Clone)]
+struct PageEntry {
+    root: OpId,
+    runs: Vec<(PeerId, u64)>,
+    len: usize,
+}
+
+/// [`Sequence`] whose cold tombstones can live in a [`PageStore`].
+///
+/// Apply every operation through [`PagedSequence::apply`] so archived anchors are
+/// paged in first; the resident [`Sequence`] alone would buffer such operations
+/// as pending.
+#[derive(Debug)]
+pub struct PagedSequence<T, S> {
+    resident: Sequence<T>,
+    store: S,
+    /// `(peer, first counter)` → one past the last counter and the page holding
+    /// that run of archived ids.
+    archived: BTreeMap<(PeerId, u64), (u64, PageId)>,
+    /// Resident subtree root → page with its archived descendants.
+    roots: BTreeMap<OpId, PageId>,
+    pages: BTreeMap<PageId, PageEntry>,
+}
+
+impl<T, S> PagedSequence<T, S>
+where
+    T: Clone + Serialize + DeserializeOwned,
+    S: PageStore,
+{
+    pub fn new(sequence: Sequence<T>, store: S) -> Self {
+        Self {
+            resident: sequence,
+            store,
+            archived: BTreeMap::new(),
+            roots: BTreeMap::new(),
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// The resident elements. Visible values are complete; only tombstones can be
+    /// missing.
+    pub fn sequence(&self) -> &Sequence<T> {
+        &self.resident
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn resident_len(&self) -> usize {
+        self.resident.elements.len()
+    }
+
+    pub fn archived_len(&self) -> usize {
+        self.pages.values().map(|entry| entry.len).sum()
+    }
+
+    pub fn is_archived(&self, id: OpId) -> bool {
+        self.page_of(id).is_some()
+    }
+
+    /// Page out the descendants of every fully tombstoned subtree with at least
+    /// `min_elements` of them, one page per subtree. Returns how many elements were
+    /// archived.
+    pub fn spill_cold(&mut self, min_elements: usize) -> Result<usize, PagingError<S::Error>> {
+        let elements = &self.resident.elements;
+        let pinned: HashSet<OpId> = self
+            .resident
+            .range_tombstones
+            .iter()
+            .flat_map(|range| [range.from, range.to])
+            .collect();
+
+        // Children follow their parent in storage order, so one reverse pass sees
+        // every subtree before its root.
+        let mut cold = vec![false; elements.len()];
+        let mut size = vec![1usize; elements.len()];
+        let mut hot_parents = HashSet::new();
+        for position in (0..elements.len()).rev() {
+            let element = &elements[position];
+            cold[position] = element.value.is_none()
+                && !pinned.contains(&element.id)
+                && !hot_parents.contains(&element.id);
+            if let Some(parent) = element.after {
+                if !cold[position] {
+                    hot_parents.insert(parent);
+                }
+                if let Some(&parent_position) = self.resident.index.get(&parent) {
+                    size[parent_position] += size[position];
+                }
+            }
+        }
+
+        let mut pages = Vec::new();
+        let mut position = 0;
+        while position < elements.len() {
+            let element = &elements[position];
+            let parent_cold = element
+                .after
+                .and_then(|parent| self.resident.index.get(&parent))
+                .is_some_and(|&parent| cold[parent]);
+            let descendants = size[position] - 1;
+            if !cold[position] || parent_cold || descendants < min_elements.max(1) {
+                position += 1;
+                continue;
+            }
+            let page: Vec<Element<T>> = elements[position + 1..position + size[position]].to_vec();
+            pages.push((element.id, page));
+            position += size[position];
+        }
+
+        // Elements leave memory only once their page is written.
+        let mut spilled = HashSet::new();
+        let mut outcome = Ok(());
+        for (root, page) in pages {
+            if let Err(error) = self.write_page(root, &page) {
+                outcome = Err(error);
+                break;
+            }
+            spilled.extend(page.iter().map(|element| element.id));
+        }
+        if !spilled.is_empty() {
+            self.resident
+                .elements
+                .retain(|element| !spilled.contains(&element.id));
+            self.resident.rebuild_index();
+        }
+        outcome.map(|()| spilled.len())
+    }
+
+    fn write_page(&mut self, root: OpId, page: &[Element<T>]) -> Result<(), PagingError<S::Error>> {
+        let bytes = serde_json::to_vec(page).map_err(|e| PagingError::Encode(e.to_string()))?;
+        let page_id = self.store.write_page(&bytes).map_err(PagingError::Store)?;
+        let runs = counter_runs(page.iter().map(|element| element.id));
+        for &(peer, start, end) in &runs {
+            self.archived.insert((peer, start), (end, page_id));
+        }
+        self.roots.insert(root, page_id);
+        self.pages.insert(
+            page_id,
+            PageEntry {
+                root,
+                runs: runs.iter().map(|&(peer, start, _)| (peer, start)).collect(),
+                len: page.len(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Apply `op`, first paging in any archived element it names.
+    pub fn apply(&mut self, op: SequenceOp<T>) -> Result<(), PagingError<S::Error>> {
+        match &op {
+            SequenceOp::Insert {
+                after,
+                id,
+                right_origin,
+                ..
+            } => {
+                self.ensure_resident(*id)?;
+                if let Some(anchor) = *after {
+                    self.ensure_resident(anchor)?;
+                    self.page_in_root(anchor)?;
+                }
+                if let Some(right) = *right_origin {
+                    self.ensure_resident(right)?;
+                }
+            }
+            SequenceOp::Delete { target, .. } => self.ensure_resident(*target)?,
+            SequenceOp::DeleteRange { from, to, .. } => {
+                self.ensure_resident(*from)?;
+                self.ensure_resident(*to)?;
+            }
+        }
+        self.resident.apply(op);
+        Ok(())
+    }
+
+    /// Right origin for a local insert after `after`, as [`Sequence`] computes it
+    /// over the full element set.
+    pub fn compute_right_origin(
+        &mut self,
+        after: Option<OpId>,
+    ) -> Result<Option<OpId>, PagingError<S::Error>> {
+        if let Some(anchor) = after {
+            self.page_in_root(anchor)?;
+        }
+        Ok(self.resident.compute_right_origin(after))
+    }
+
+    /// Page everything back in and return the full sequence.
+    pub fn into_inner(mut self) -> Result<Sequence<T>, PagingError<S::Error>> {
+        while let Some(&page) = self.pages.keys().next() {
+            self.page_in(page)?;
+        }
+        Ok(self.resident)
+    }
+
+    fn page_of(&self, id: OpId) -> Option<PageId> {
+        let (&(peer, _), &(end, page)) =
+            self.archived.range(..=(id.peer, id.counter)).next_back()?;
+        (peer == id.peer && id.counter < end).then_some(page)
+    }
+
+    fn ensure_resident(&mut self, id: OpId) -> Result<(), PagingError<S::Error>> {
+        match self.page_of(id) {
+            Some(page) => self.page_in(page),
+            None => Ok(()),
+        }
+    }
+
+    fn page_in_root(&mut self, root: OpId) -> Result<(), PagingError<S::Error>> {
+        match self.roots.get(&root) {
+            Some(&page) => self.page_in(page),
+            None => Ok(()),
+        }
+    }
+
+    fn page_in(&mut self, page: PageId) -> Result<(), PagingError<S::Error>> {
+        let bytes = self.store.read_page(page).map_err(PagingError::Store)?;
+        let elements: Vec<Element<T>> =
+            serde_json::from_slice(&bytes).map_err(|e| PagingError::CorruptPage {
+                page,
+                reason: e.to_string(),
+            })?;
+        let expected = self.pages[&page].len;
+        if elements.len() != expected {
+            return Err(PagingError::CorruptPage {
+                page,
+                reason: format!("{} elements, expected {expected}", elements.len()),
+            });
+        }
+        let entry = self.pages.remove(&page).expect("page was just indexed");
+        for run in &entry.runs {
+            self.archived.remove(run);
+        }
+        self.roots.remove(&entry.root);
+        // A subtree stays contiguous under integration, directly after its root.
+        let at = self.resident.index[&entry.root] + 1;
+        self.resident.elements.splice(at..at, elements);
+        self.resident.rebuild_index();
+        self.store.remove_page(page).map_err(PagingError::Store)
+    }
+}
+
+/// Consecutive-counter runs `(peer, start, end)` covering `ids`.
+fn counter_runs(ids: impl Iterator<Item = OpId>) -> Vec<(PeerId, u64, u64)> {
+    let sorted: BTreeSet<(PeerId, u64)> = ids.map(|id| (id.peer, id.counter)).collect();
+    let mut runs: Vec<(PeerId, u64, u64)> = Vec::new();
+    for (peer, counter) in sorted {
+        match runs.last_mut() {
+            Some((last_peer, _, end)) if *last_peer == peer && *end == counter => *end += 1,
+            _ => runs.push((peer, counter, counter + 1)),
+        }
+    }
+    runs
+}
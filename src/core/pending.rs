@@ -0,0 +1,113 @@
+//! Introspection and expiry of operations buffered on a missing dependency.
+//!
+//! [`Sequence`](super::Sequence) buffers an insert until its anchor and right
+//! origin arrive and a delete until its target does;
+//! [`SyncState`](crate::sync::SyncState) buffers an operation until the earlier
+//! counters of its peer are applied. A buggy or departed peer can leave such
+//! operations waiting forever. The summaries below name what is awaited, and
+//! expiry drops operations buffered longer than a TTL, reporting each as an
+//! [`ExpiredOp`] so the caller can ask a peer to retransmit the awaited id.
+//!
+//! Buffering times are replica-local: they are not compared, serialized or
+//! restored, so a restored replica ages its pending operations from the restore.
+
+use super::OpId;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Buffered operations grouped by the id they wait for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingSummary {
+    /// One entry per awaited id, in id order.
+    pub awaited: Vec<AwaitedDependency>,
+    /// Buffered operations in total.
+    pub blocked: usize,
+}
+
+impl PendingSummary {
+    pub fn is_empty(&self) -> bool {
+        self.blocked == 0
+    }
+
+    /// Age of the longest-waiting operation.
+    pub fn oldest_age(&self) -> Option<Duration> {
+        self.awaited
+            .iter()
+            .map(|dependency| dependency.oldest_age)
+            .max()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwaitedDependency {
+    pub id: OpId,
+    /// Operations blocked on `id`.
+    pub blocked: usize,
+    /// How long the oldest of them has waited.
+    pub oldest_age: Duration,
+}
+
+/// An operation dropped from a pending buffer after waiting longer than the TTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiredOp {
+    pub id: OpId,
+    /// The dependency it was still waiting for.
+    pub awaited: OpId,
+    pub age: Duration,
+}
+
+/// Summarize `(awaited, buffered_at)` pairs, one per buffered operation.
+pub(crate) fn summarize(
+    entries: impl IntoIterator<Item = (OpId, Instant)>,
+    now: Instant,
+) -> PendingSummary {
+    let mut awaited: BTreeMap<OpId, AwaitedDependency> = BTreeMap::new();
+    let mut blocked = 0;
+    for (id, buffered_at) in entries {
+        let age = now.saturating_duration_since(buffered_at);
+        let entry = awaited.entry(id).or_insert(AwaitedDependency {
+            id,
+            blocked: 0,
+            oldest_age: age,
+        });
+        entry.blocked += 1;
+        entry.oldest_age = entry.oldest_age.max(age);
+        blocked += 1;
+    }
+    PendingSummary {
+        awaited: awaited.into_values().collect(),
+        blocked,
+    }
+}
+
+/// When each buffered operation entered its buffer. Equality always holds so the
+/// owning structure compares by content alone.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BufferedSince {
+    since: BTreeMap<OpId, Instant>,
+}
+
+impl BufferedSince {
+    /// Record `id` as buffered now unless it already was; a re-buffered
+    /// operation keeps its original age.
+    pub(crate) fn stamp(&mut self, id: OpId) {
+        self.since.entry(id).or_insert_with(Instant::now);
+    }
+
+    pub(crate) fn clear(&mut self, id: &OpId) {
+        self.since.remove(id);
+    }
+
+    /// When `id` was buffered, or `now` if it never was.
+    pub(crate) fn get(&self, id: &OpId, now: Instant) -> Instant {
+        self.since.get(id).copied().unwrap_or(now)
+    }
+}
+
+impl PartialEq for BufferedSince {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for BufferedSince {}
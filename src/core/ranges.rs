@@ -0,0 +1,76 @@
+//! Sets of counters stored as disjoint inclusive ranges.
+//!
+//! A replica that tolerates counter gaps cannot summarize what it has applied
+//! from a peer with one frontier counter: it may hold `1..=3` and `7..=9` with
+//! nothing in between. [`CounterRanges`] keeps such a set compact, merging
+//! ranges that touch.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// Set of counters, kept as disjoint, non-adjacent inclusive ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterRanges {
+    /// Range start to range end.
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl CounterRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Add every counter in `start..=end`; an empty range adds nothing.
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start > end {
+            return;
+        }
+        let mut start = start;
+        let mut end = end;
+        // Merge with a range that overlaps or ends right before `start`.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..=start).next_back()
+            && prev_end.saturating_add(1) >= start
+        {
+            start = prev_start;
+            end = end.max(prev_end);
+        }
+        // Absorb every range that begins inside or right after the new one.
+        let absorbed: Vec<(u64, u64)> = self
+            .ranges
+            .range(start..=end.saturating_add(1))
+            .map(|(s, e)| (*s, *e))
+            .collect();
+        for (s, e) in absorbed {
+            self.ranges.remove(&s);
+            end = end.max(e);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn contains(&self, counter: u64) -> bool {
+        self.ranges
+            .range(..=counter)
+            .next_back()
+            .is_some_and(|(_, end)| *end >= counter)
+    }
+
+    /// Highest counter in the set.
+    pub fn max(&self) -> Option<u64> {
+        self.ranges.values().next_back().copied()
+    }
+
+    /// Number of counters in the set.
+    pub fn len(&self) -> u64 {
+        self.ranges.iter().map(|(start, end)| end - start + 1).sum()
+    }
+
+    /// The ranges in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = RangeInclusive<u64>> + '_ {
+        self.ranges.iter().map(|(start, end)| *start..=*end)
+    }
+}
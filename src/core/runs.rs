@@ -0,0 +1,561 @@
+//! Run-length encoded text sequence.
+//!
+//! [`RunSequence`] keeps contiguous grapheme insertions by one peer as a single
+//! [`TextRun`] instead of one [`Element`](super::Element) per grapheme. Unit `i` of
+//! a run starting at `id` has id `(id.peer, id.counter + i)`, is anchored after
+//! unit `i - 1`, and shares the run's `right_origin`, so the resulting order is
+//! the one a [`Sequence`](super::Sequence) would produce from the same units.
+//!
+//! Runs are split lazily: an insert anchored inside a run, or a delete of some
+//! of its units, cuts it at that point. Typing at the end of a run extends it in
+//! place without reordering.
+//!
+//! This is a type of its own rather than a mode of `Sequence`: only string
+//! payloads form runs, and `Sequence<T>` stays one element per id because block
+//! text in [`Document`](crate::doc::Document) is addressed unit by unit, by mark
+//! anchors, moves, paging and the snapshot and wire formats. [`Text`](super::Text)
+//! and text values in a [`ValueTree`](super::ValueTree) are stored here.
+
+use super::merge::{self, Mergeable};
+use super::{OffsetMap, OpId, PeerId, StateVector};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunOp {
+    /// Insert `text` as graphemes with consecutive ids starting at `id`.
+    Insert {
+        after: Option<OpId>,
+        id: OpId,
+        text: String,
+        right_origin: Option<OpId>,
+    },
+    Delete {
+        target: OpId,
+        id: OpId,
+    },
+}
+
+/// Consecutive units inserted by one peer, stored as one string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextRun {
+    pub id: OpId,
+    pub after: Option<OpId>,
+    pub right_origin: Option<OpId>,
+    len: u64,
+    /// Live text and the byte end of each unit; `None` once tombstoned.
+    text: Option<RunText>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RunText {
+    text: String,
+    ends: Vec<usize>,
+}
+
+impl RunText {
+    fn from_units<'a>(units: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut run = Self {
+            text: String::new(),
+            ends: Vec::new(),
+        };
+        for unit in units {
+            run.push(unit);
+        }
+        run
+    }
+
+    fn push(&mut self, unit: &str) {
+        self.text.push_str(unit);
+        self.ends.push(self.text.len());
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        let byte = self.ends[at - 1];
+        let text = self.text.split_off(byte);
+        let ends = self
+            .ends
+            .split_off(at)
+            .into_iter()
+            .map(|end| end - byte)
+            .collect();
+        Self { text, ends }
+    }
+
+    fn unit(&self, offset: usize) -> &str {
+        let start = offset.checked_sub(1).map_or(0, |prev| self.ends[prev]);
+        &self.text[start..self.ends[offset]]
+    }
+}
+
+impl TextRun {
+    /// Number of units in the run, tombstoned or not.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.text.is_none()
+    }
+
+    /// Concatenated text of a live run.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_ref().map(|run| run.text.as_str())
+    }
+
+    pub fn unit_id(&self, offset: u64) -> OpId {
+        OpId {
+            counter: self.id.counter + offset,
+            peer: self.id.peer,
+        }
+    }
+
+    pub fn last_id(&self) -> OpId {
+        self.unit_id(self.len - 1)
+    }
+
+    /// Offset of `id` within this run.
+    pub fn offset_of(&self, id: OpId) -> Option<u64> {
+        (id.peer == self.id.peer
+            && id.counter >= self.id.counter
+            && id.counter - self.id.counter < self.len)
+            .then(|| id.counter - self.id.counter)
+    }
+
+    /// Live units as `(id, grapheme)` pairs.
+    pub fn units(&self) -> impl Iterator<Item = (OpId, &str)> {
+        self.text.iter().flat_map(move |run| {
+            (0..run.ends.len()).map(move |offset| (self.unit_id(offset as u64), run.unit(offset)))
+        })
+    }
+
+    /// Cut the run before unit `at`; the tail is anchored on the new last unit.
+    fn split_off(&mut self, at: u64) -> TextRun {
+        let tail = TextRun {
+            id: self.unit_id(at),
+            after: Some(self.unit_id(at - 1)),
+            right_origin: self.right_origin,
+            len: self.len - at,
+            text: self.text.as_mut().map(|run| run.split_off(at as usize)),
+        };
+        self.len = at;
+        tail
+    }
+}
+
+/// Text sequence storing [`TextRun`]s in document order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RunSequence {
+    runs: Vec<TextRun>,
+    /// Run position keyed by `(peer, first counter)`.
+    index: BTreeMap<(PeerId, u64), usize>,
+    /// Operations waiting on a missing anchor or target unit.
+    pending: BTreeMap<(PeerId, u64), Vec<RunOp>>,
+}
+
+impl RunSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert `text` after `after`, allocating one id per grapheme from `id`.
+    pub fn insert(&mut self, after: Option<OpId>, text: &str, id: OpId) {
+        let right_origin = self.compute_right_origin(after);
+        self.apply(RunOp::Insert {
+            after,
+            id,
+            text: text.to_string(),
+            right_origin,
+        });
+    }
+
+    pub fn delete(&mut self, target: OpId, id: OpId) {
+        self.apply(RunOp::Delete { target, id });
+    }
+
+    pub fn apply(&mut self, op: RunOp) {
+        let mut queue = VecDeque::from([op]);
+        while let Some(op) = queue.pop_front() {
+            if let Some((peer, counters)) = self.apply_now(op) {
+                let ready: Vec<_> = self
+                    .pending
+                    .range((peer, counters.start)..(peer, counters.end))
+                    .map(|(key, _)| *key)
+                    .collect();
+                for key in ready {
+                    queue.extend(self.pending.remove(&key).unwrap_or_default());
+                }
+            }
+        }
+    }
+
+    pub fn runs(&self) -> &[TextRun] {
+        &self.runs
+    }
+
+    /// Live text in document order.
+    pub fn text(&self) -> String {
+        self.visible_string()
+    }
+
+    /// Live text in document order, as [`Sequence::visible_string`](super::Sequence::visible_string).
+    pub fn visible_string(&self) -> String {
+        self.runs.iter().filter_map(TextRun::text).collect()
+    }
+
+    /// Live unit ids indexed by grapheme offset and back.
+    pub fn offset_map(&self) -> OffsetMap {
+        self.units().map(|(id, _)| id).collect()
+    }
+
+    /// Live units as `(id, grapheme)` pairs in document order.
+    pub fn units(&self) -> impl Iterator<Item = (OpId, &str)> {
+        self.runs.iter().flat_map(TextRun::units)
+    }
+
+    pub fn len_visible(&self) -> usize {
+        self.runs
+            .iter()
+            .filter_map(|run| run.text.as_ref())
+            .map(|run| run.ends.len())
+            .sum()
+    }
+
+    /// Every unit id, tombstones included, in document order.
+    pub fn unit_ids(&self) -> Vec<OpId> {
+        self.runs
+            .iter()
+            .flat_map(|run| (0..run.len).map(|offset| run.unit_id(offset)))
+            .collect()
+    }
+
+    pub fn contains(&self, id: OpId) -> bool {
+        self.locate(id).is_some()
+    }
+
+    /// Right-neighbor unit that bounds where an insert at `after` integrates.
+    pub fn compute_right_origin(&self, after: Option<OpId>) -> Option<OpId> {
+        let next_run = match after {
+            None => 0,
+            Some(anchor) => match self.locate(anchor) {
+                Some((position, offset)) if offset + 1 < self.runs[position].len => {
+                    return Some(self.runs[position].unit_id(offset + 1));
+                }
+                Some((position, _)) => position + 1,
+                None => 0,
+            },
+        };
+        self.runs.get(next_run).map(|run| run.id)
+    }
+
+    fn locate(&self, id: OpId) -> Option<(usize, u64)> {
+        let (_, position) = self
+            .index
+            .range((id.peer, 0)..=(id.peer, id.counter))
+            .next_back()?;
+        let offset = self.runs[*position].offset_of(id)?;
+        Some((*position, offset))
+    }
+
+    /// Apply one operation, returning the unit counters it placed.
+    fn apply_now(&mut self, op: RunOp) -> Option<(PeerId, std::ops::Range<u64>)> {
+        match op {
+            RunOp::Insert {
+                after,
+                id,
+                text,
+                right_origin,
+            } => {
+                let units = RunText::from_units(text.graphemes(true));
+                if units.ends.is_empty() || self.contains(id) {
+                    return None;
+                }
+                let anchor = match after {
+                    Some(anchor) => match self.locate(anchor) {
+                        Some(position) => Some(position),
+                        None => {
+                            self.buffer(
+                                anchor,
+                                RunOp::Insert {
+                                    after,
+                                    id,
+                                    text,
+                                    right_origin,
+                                },
+                            );
+                            return None;
+                        }
+                    },
+                    None => None,
+                };
+                if let Some(right) = right_origin
+                    && !self.contains(right)
+                {
+                    self.buffer(
+                        right,
+                        RunOp::Insert {
+                            after,
+                            id,
+                            text,
+                            right_origin,
+                        },
+                    );
+                    return None;
+                }
+
+                let len = units.ends.len() as u64;
+                let placed = Some((id.peer, id.counter..id.counter + len));
+                let position = match self.integration_left(anchor, after, id, right_origin) {
+                    None => 0,
+                    Some((position, offset)) => {
+                        let run = &self.runs[position];
+                        if offset + 1 < run.len {
+                            self.split_run(position, offset + 1);
+                        } else if after == Some(run.last_id())
+                            && self.try_extend(position, id, right_origin, &units)
+                        {
+                            return placed;
+                        }
+                        position + 1
+                    }
+                };
+                self.runs.insert(
+                    position,
+                    TextRun {
+                        id,
+                        after,
+                        right_origin,
+                        len,
+                        text: Some(units),
+                    },
+                );
+                self.rebuild_index();
+                placed
+            }
+            RunOp::Delete { target, id } => {
+                let Some((mut position, offset)) = self.locate(target) else {
+                    self.buffer(target, RunOp::Delete { target, id });
+                    return None;
+                };
+                if self.runs[position].is_deleted() {
+                    return None;
+                }
+                if offset > 0 {
+                    self.split_run(position, offset);
+                    position += 1;
+                }
+                if self.runs[position].len > 1 {
+                    self.split_run(position, 1);
+                }
+                self.runs[position].text = None;
+                None
+            }
+        }
+    }
+
+    fn buffer(&mut self, waiting_on: OpId, op: RunOp) {
+        self.pending
+            .entry((waiting_on.peer, waiting_on.counter))
+            .or_default()
+            .push(op);
+    }
+
+    /// Unit the first unit of an insert integrates after, as `(run, offset)`;
+    /// `None` places it first. This is [`Sequence`](super::Sequence)'s YATA scan
+    /// over units, where unit `i > 0` of a run is anchored on unit `i - 1` and
+    /// every unit carries the run's right origin.
+    fn integration_left(
+        &self,
+        anchor: Option<(usize, u64)>,
+        after: Option<OpId>,
+        id: OpId,
+        right_origin: Option<OpId>,
+    ) -> Option<(usize, u64)> {
+        let next = |(position, offset): (usize, u64)| {
+            if offset + 1 < self.runs[position].len {
+                (position, offset + 1)
+            } else {
+                (position + 1, 0)
+            }
+        };
+        let start = anchor.map_or((0, 0), next);
+        let end = right_origin
+            .and_then(|right| self.locate(right))
+            .filter(|&right| right >= start)
+            .unwrap_or((self.runs.len(), 0));
+
+        let mut left = anchor;
+        let mut scan = start;
+        while scan < end {
+            let run = &self.runs[scan.0];
+            let other_after = match scan.1 {
+                0 => run.after,
+                offset => Some(run.unit_id(offset - 1)),
+            };
+            if other_after == after {
+                if run.unit_id(scan.1) > id {
+                    left = Some(scan);
+                } else if run.right_origin == right_origin {
+                    break;
+                }
+            } else {
+                let other_anchor = other_after.and_then(|other| self.locate(other));
+                if other_anchor <= anchor {
+                    break;
+                }
+                if other_anchor <= left {
+                    left = Some(scan);
+                }
+            }
+            scan = next(scan);
+        }
+        left
+    }
+
+    /// Append `units` to the run at `position`, whose last unit they integrate
+    /// directly after, when they continue it exactly.
+    fn try_extend(
+        &mut self,
+        position: usize,
+        id: OpId,
+        right_origin: Option<OpId>,
+        units: &RunText,
+    ) -> bool {
+        let run = &self.runs[position];
+        let continues = run.id.peer == id.peer
+            && run.id.counter + run.len == id.counter
+            && run.right_origin == right_origin
+            && !run.is_deleted();
+        if !continues {
+            return false;
+        }
+        let run = &mut self.runs[position];
+        if let Some(text) = run.text.as_mut() {
+            for offset in 0..units.ends.len() {
+                text.push(units.unit(offset));
+            }
+        }
+        run.len += units.ends.len() as u64;
+        true
+    }
+
+    /// Split the run at `position` before unit `at`; order is unchanged.
+    fn split_run(&mut self, position: usize, at: u64) {
+        let tail = self.runs[position].split_off(at);
+        self.runs.insert(position + 1, tail);
+        self.rebuild_index();
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        for (position, run) in self.runs.iter().enumerate() {
+            self.index.insert((run.id.peer, run.id.counter), position);
+        }
+    }
+}
+
+/// Stand-in grapheme for the units of a tombstoned run, which no longer hold
+/// their text; they are deleted right after they are placed.
+const TOMBSTONE_UNIT: &str = "\u{fffd}";
+
+impl Mergeable for RunSequence {
+    type Op = RunOp;
+
+    fn apply_op(&mut self, op: RunOp) {
+        self.apply(op);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        let mut vector = StateVector::new();
+        for run in &self.runs {
+            merge::observe(&mut vector, run.last_id());
+        }
+        vector
+    }
+
+    /// Unseen units as inserts, tombstoned ones with stand-in text; then a
+    /// delete of every tombstoned unit, carrying its target's id since the
+    /// delete's own is not kept; then unseen buffered operations.
+    fn ops_since(&self, since: &StateVector) -> Vec<RunOp> {
+        let mut inserts = Vec::new();
+        for run in &self.runs {
+            let seen = since
+                .get(run.id.peer)
+                .unwrap_or(0)
+                .saturating_add(1)
+                .saturating_sub(run.id.counter)
+                .min(run.len);
+            if seen == run.len {
+                continue;
+            }
+            let text = match &run.text {
+                Some(text) => (seen as usize..text.ends.len())
+                    .map(|offset| text.unit(offset))
+                    .collect(),
+                None => TOMBSTONE_UNIT.repeat((run.len - seen) as usize),
+            };
+            inserts.push(RunOp::Insert {
+                after: match seen {
+                    0 => run.after,
+                    seen => Some(run.unit_id(seen - 1)),
+                },
+                id: run.unit_id(seen),
+                text,
+                right_origin: run.right_origin,
+            });
+        }
+
+        // Unit ranges of the inserts, keyed by `(peer, first counter)`.
+        let ranges: BTreeMap<(PeerId, u64), (usize, u64)> = inserts
+            .iter()
+            .enumerate()
+            .filter_map(|(position, op)| match op {
+                RunOp::Insert { id, text, .. } => Some((
+                    (id.peer, id.counter),
+                    (position, id.counter + text.graphemes(true).count() as u64),
+                )),
+                RunOp::Delete { .. } => None,
+            })
+            .collect();
+        let holder = |unit: OpId| {
+            let (&(peer, _), &(position, end)) =
+                ranges.range(..=(unit.peer, unit.counter)).next_back()?;
+            (peer == unit.peer && unit.counter < end).then_some(position)
+        };
+        let inserts = merge::dependency_order(inserts, |op| match op {
+            RunOp::Insert {
+                after,
+                right_origin,
+                ..
+            } => [*after, *right_origin]
+                .into_iter()
+                .flatten()
+                .filter_map(holder)
+                .collect(),
+            RunOp::Delete { .. } => Vec::new(),
+        });
+
+        let deletes = self
+            .runs
+            .iter()
+            .filter(|run| run.is_deleted())
+            .flat_map(|run| (0..run.len).map(|offset| run.unit_id(offset)))
+            .map(|target| RunOp::Delete { target, id: target });
+        let pending = self.pending.values().flatten().filter(|op| {
+            let id = match op {
+                RunOp::Insert { id, .. } | RunOp::Delete { id, .. } => *id,
+            };
+            !merge::covers(since, id)
+        });
+        inserts
+            .into_iter()
+            .chain(deletes)
+            .chain(pending.cloned())
+            .collect()
+    }
+}
@@ -0,0 +1,213 @@
+//! Versioned grapheme cluster rules.
+//!
+//! Text units are grapheme clusters, and remote edits name byte offsets that
+//! must fall on a cluster boundary. Cluster boundaries change between Unicode
+//! versions: Unicode 9 joined emoji ZWJ sequences and skin tone modifiers,
+//! Unicode 15.1 joined Indic conjuncts across a virama. Two peers segmenting
+//! with different tables split the same text into different units, and one
+//! rejects offsets the other produced.
+//!
+//! [`SegmentationRules`] names the rules a document segments with, so every
+//! replica of it agrees whatever `unicode-segmentation` release it was built
+//! against. [`SegmentationRules::CURRENT`] is that release's extended cluster
+//! segmentation; older versions are derived from it by splitting or joining
+//! clusters where the older rules differ for emoji, regional indicator flags
+//! and conjuncts. Other, rarer differences between versions are not shimmed.
+//!
+//! Documents carry their rules; the standalone [`super::Text`] always
+//! segments with the current ones.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+
+// `CURRENT` is whatever `unicode-segmentation` implements; a release on another
+// Unicode version needs its own variant before the dependency moves to it.
+const _: () = assert!(matches!(unicode_segmentation::UNICODE_VERSION, (16, 0, 0)));
+
+const ZWJ: char = '\u{200D}';
+const VIRAMA_KA: &str = "\u{915}\u{94D}";
+
+/// Grapheme cluster rules of one Unicode version.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum SegmentationRules {
+    /// Unicode 8.0 and earlier: ZWJ sequences and skin tone modifiers split
+    /// into separate clusters and any run of regional indicators is one cluster.
+    Unicode8,
+    /// Unicode 11.0 through 15.0: no conjunct clusters across a virama.
+    Unicode15,
+    /// Unicode 15.1 and 16.0.
+    #[default]
+    Unicode16,
+}
+
+impl SegmentationRules {
+    /// The rules `unicode-segmentation` implements, which need no shim.
+    pub const CURRENT: SegmentationRules = SegmentationRules::Unicode16;
+
+    /// Every supported version, oldest first.
+    pub const ALL: [SegmentationRules; 3] = [
+        SegmentationRules::Unicode8,
+        SegmentationRules::Unicode15,
+        SegmentationRules::Unicode16,
+    ];
+
+    /// The `(major, minor)` Unicode version the rules are named for.
+    pub fn version(self) -> (u8, u8) {
+        match self {
+            SegmentationRules::Unicode8 => (8, 0),
+            SegmentationRules::Unicode15 => (15, 0),
+            SegmentationRules::Unicode16 => (16, 0),
+        }
+    }
+
+    /// Clusters of `text` in order.
+    pub fn graphemes(self, text: &str) -> Graphemes<'_> {
+        match self {
+            SegmentationRules::Unicode16 => Graphemes::Current(text.graphemes(true)),
+            legacy => Graphemes::Legacy(legacy.legacy_clusters(text).into_iter()),
+        }
+    }
+
+    /// Clusters of `text` with their byte offsets.
+    pub fn grapheme_indices(self, text: &str) -> impl Iterator<Item = (usize, &str)> {
+        let base = text.as_ptr() as usize;
+        self.graphemes(text)
+            .map(move |cluster| (cluster.as_ptr() as usize - base, cluster))
+    }
+
+    /// Number of clusters in `text`.
+    pub fn count(self, text: &str) -> usize {
+        self.graphemes(text).count()
+    }
+
+    /// Whether `byte_offset` falls between two clusters of `text` (or at either end).
+    pub fn is_boundary(self, text: &str, byte_offset: usize) -> bool {
+        if byte_offset == 0 || byte_offset == text.len() {
+            return true;
+        }
+        self.grapheme_indices(text)
+            .any(|(index, _)| index == byte_offset)
+    }
+
+    /// Byte offset of the cluster at `grapheme_offset`, `text.len()` just past
+    /// the last one, `None` beyond that.
+    pub fn byte_offset(self, text: &str, grapheme_offset: usize) -> Option<usize> {
+        self.grapheme_indices(text)
+            .map(|(index, _)| index)
+            .chain(std::iter::once(text.len()))
+            .nth(grapheme_offset)
+    }
+
+    fn legacy_clusters(self, text: &str) -> Vec<&str> {
+        let mut clusters: Vec<&str> = Vec::new();
+        for cluster in text.graphemes(true) {
+            let mut start = 0;
+            let mut previous: Option<char> = None;
+            for (index, c) in cluster.char_indices() {
+                if let Some(before) = previous
+                    && self.splits(before, c)
+                {
+                    clusters.push(&cluster[start..index]);
+                    start = index;
+                }
+                previous = Some(c);
+            }
+            clusters.push(&cluster[start..]);
+        }
+        if self == SegmentationRules::Unicode8 {
+            join_regional_indicators(text, &mut clusters);
+        }
+        clusters
+    }
+
+    /// Whether these rules break between `before` and `c`, two characters the
+    /// current rules keep in one cluster.
+    fn splits(self, before: char, c: char) -> bool {
+        let conjunct = is_conjunct_consonant(c) && !is_prepend(before);
+        match self {
+            SegmentationRules::Unicode16 => false,
+            SegmentationRules::Unicode15 => conjunct,
+            SegmentationRules::Unicode8 => {
+                conjunct || is_emoji_modifier(c) || (before == ZWJ && !is_extend(c))
+            }
+        }
+    }
+}
+
+impl fmt::Display for SegmentationRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor) = self.version();
+        write!(f, "Unicode {major}.{minor} grapheme clusters")
+    }
+}
+
+/// Iterator over the clusters of a string under some [`SegmentationRules`].
+pub enum Graphemes<'a> {
+    Current(unicode_segmentation::Graphemes<'a>),
+    Legacy(std::vec::IntoIter<&'a str>),
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            Graphemes::Current(clusters) => clusters.next(),
+            Graphemes::Legacy(clusters) => clusters.next(),
+        }
+    }
+}
+
+impl fmt::Debug for Graphemes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Graphemes").finish_non_exhaustive()
+    }
+}
+
+/// Join neighbouring clusters that meet between two regional indicators.
+fn join_regional_indicators<'a>(text: &'a str, clusters: &mut Vec<&'a str>) {
+    let base = text.as_ptr() as usize;
+    let mut joined: Vec<&'a str> = Vec::with_capacity(clusters.len());
+    for cluster in clusters.drain(..) {
+        if let Some(last) = joined.last_mut()
+            && last.chars().next_back().is_some_and(is_regional_indicator)
+            && cluster.chars().next().is_some_and(is_regional_indicator)
+        {
+            let start = last.as_ptr() as usize - base;
+            let end = cluster.as_ptr() as usize - base + cluster.len();
+            *last = &text[start..end];
+            continue;
+        }
+        joined.push(cluster);
+    }
+    *clusters = joined;
+}
+
+fn single_cluster(text: &str) -> bool {
+    text.graphemes(true).nth(1).is_none()
+}
+
+/// Extend or spacing mark: joins whatever precedes it under every version.
+fn is_extend(c: char) -> bool {
+    single_cluster(&format!("a{c}"))
+}
+
+/// Consonant that Unicode 15.1 joins to a preceding virama.
+fn is_conjunct_consonant(c: char) -> bool {
+    !is_extend(c) && single_cluster(&format!("{VIRAMA_KA}{c}"))
+}
+
+fn is_prepend(c: char) -> bool {
+    single_cluster(&format!("{c}a"))
+}
+
+fn is_emoji_modifier(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
@@ -0,0 +1,320 @@
+//! Standalone collaborative text.
+//!
+//! [`Text`] pairs a [`RunSequence`] with a [`MarkSet`] and a peer clock, giving
+//! plain text fields (titles, frontmatter values, comments) grapheme-offset
+//! editing without the block-level [`Document`](crate::doc::Document) model.
+//! Every local edit returns the [`TextOp`]s to ship to other replicas, which
+//! feed them to [`Text::apply`].
+
+use super::mark::{Anchor, AnchorBias, MarkIntervalId, MarkKind, MarkOp, MarkSet, MarkValue, Span};
+use super::merge::Mergeable;
+use super::runs::{RunOp, RunSequence};
+use super::{OpId, PeerId, StateVector};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextOp {
+    Run(RunOp),
+    SetMark {
+        interval_id: MarkIntervalId,
+        kind: MarkKind,
+        start: Anchor,
+        end: Anchor,
+        attrs: BTreeMap<String, MarkValue>,
+        op_id: OpId,
+    },
+    RemoveMark {
+        interval_id: MarkIntervalId,
+        observed: StateVector,
+        op_id: OpId,
+    },
+}
+
+impl TextOp {
+    /// Highest operation id carried by this op (a run insert spans one id per unit).
+    fn last_id(&self) -> OpId {
+        match self {
+            TextOp::Run(RunOp::Insert { id, text, .. }) => {
+                let units = text.graphemes(true).count() as u64;
+                OpId {
+                    counter: id.counter + units.saturating_sub(1),
+                    peer: id.peer,
+                }
+            }
+            TextOp::Run(RunOp::Delete { id, .. })
+            | TextOp::SetMark { op_id: id, .. }
+            | TextOp::RemoveMark { op_id: id, .. } => *id,
+        }
+    }
+}
+
+impl From<MarkOp> for TextOp {
+    fn from(op: MarkOp) -> Self {
+        match op {
+            MarkOp::Set {
+                interval_id,
+                kind,
+                start,
+                end,
+                attrs,
+                op_id,
+            } => TextOp::SetMark {
+                interval_id,
+                kind,
+                start,
+                end,
+                attrs,
+                op_id,
+            },
+            MarkOp::Remove {
+                interval_id,
+                observed,
+                op_id,
+            } => TextOp::RemoveMark {
+                interval_id,
+                observed,
+                op_id,
+            },
+        }
+    }
+}
+
+/// Collaborative plain text addressed by visible grapheme offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    peer: PeerId,
+    next_counter: u64,
+    runs: RunSequence,
+    marks: MarkSet,
+    observed: StateVector,
+}
+
+impl Text {
+    pub fn new(peer: PeerId) -> Self {
+        Self {
+            peer,
+            next_counter: 1,
+            runs: RunSequence::new(),
+            marks: MarkSet::new(),
+            observed: StateVector::new(),
+        }
+    }
+
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    /// Highest counter applied per peer, local edits included.
+    pub fn observed(&self) -> &StateVector {
+        &self.observed
+    }
+
+    pub fn len_graphemes(&self) -> usize {
+        self.runs.len_visible()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_graphemes() == 0
+    }
+
+    /// Visible text for the grapheme `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is reversed or ends past [`Text::len_graphemes`].
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.check_range(&range);
+        self.runs
+            .units()
+            .skip(range.start)
+            .take(range.len())
+            .map(|(_, unit)| unit)
+            .collect()
+    }
+
+    pub fn runs(&self) -> &RunSequence {
+        &self.runs
+    }
+
+    pub fn marks(&self) -> &MarkSet {
+        &self.marks
+    }
+
+    /// Insert `text` before the grapheme at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is past [`Text::len_graphemes`].
+    pub fn insert(&mut self, offset: usize, text: &str) -> Vec<TextOp> {
+        let len = self.len_graphemes();
+        assert!(
+            offset <= len,
+            "insert offset {offset} out of bounds ({len})"
+        );
+        let units = text.graphemes(true).count();
+        if units == 0 {
+            return Vec::new();
+        }
+        let after = self.runs.offset_map().after_for_offset(offset);
+        let id = self.allocate(units);
+        let op = TextOp::Run(RunOp::Insert {
+            after,
+            id,
+            text: text.to_string(),
+            right_origin: self.runs.compute_right_origin(after),
+        });
+        self.apply(op.clone());
+        vec![op]
+    }
+
+    /// Delete the graphemes in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is reversed or ends past [`Text::len_graphemes`].
+    pub fn delete(&mut self, range: Range<usize>) -> Vec<TextOp> {
+        self.check_range(&range);
+        let targets = self.runs.offset_map().ids()[range].to_vec();
+        targets
+            .into_iter()
+            .map(|target| {
+                let op = TextOp::Run(RunOp::Delete {
+                    target,
+                    id: self.allocate(1),
+                });
+                self.apply(op.clone());
+                op
+            })
+            .collect()
+    }
+
+    /// Mark the graphemes in `range`; the mark follows its anchor units as text changes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty, reversed, or ends past [`Text::len_graphemes`].
+    pub fn mark(
+        &mut self,
+        range: Range<usize>,
+        kind: MarkKind,
+        attrs: BTreeMap<String, MarkValue>,
+    ) -> Vec<TextOp> {
+        self.check_range(&range);
+        assert!(!range.is_empty(), "cannot mark an empty range");
+        let offsets = self.runs.offset_map();
+        let op_id = self.allocate(1);
+        let op = TextOp::SetMark {
+            interval_id: op_id,
+            kind,
+            start: Anchor {
+                elem_id: offsets.ids()[range.start],
+                bias: AnchorBias::Before,
+            },
+            end: Anchor {
+                elem_id: offsets.ids()[range.end - 1],
+                bias: AnchorBias::After,
+            },
+            attrs,
+            op_id,
+        };
+        self.apply(op.clone());
+        vec![op]
+    }
+
+    /// Remove a mark interval as of everything this replica has observed.
+    pub fn unmark(&mut self, interval_id: MarkIntervalId) -> Vec<TextOp> {
+        let op_id = self.allocate(1);
+        let op = TextOp::RemoveMark {
+            interval_id,
+            observed: self.observed.clone(),
+            op_id,
+        };
+        self.apply(op.clone());
+        vec![op]
+    }
+
+    /// Visible span boundaries with their active marks, in grapheme offsets.
+    pub fn spans(&self) -> Vec<Span> {
+        self.marks
+            .render_spans_in(&self.runs.offset_map(), self.len_graphemes())
+    }
+
+    /// Apply a local or remote operation. Replays are idempotent.
+    pub fn apply(&mut self, op: TextOp) {
+        let last = op.last_id();
+        if self.observed.get(last.peer).unwrap_or(0) < last.counter {
+            self.observed.set(last.peer, last.counter);
+        }
+        if last.peer == self.peer {
+            self.next_counter = self.next_counter.max(last.counter + 1);
+        }
+        match op {
+            TextOp::Run(op) => self.runs.apply(op),
+            TextOp::SetMark {
+                interval_id,
+                kind,
+                start,
+                end,
+                attrs,
+                op_id,
+            } => self
+                .marks
+                .set_mark(interval_id, kind, start, end, attrs, op_id),
+            TextOp::RemoveMark {
+                interval_id,
+                observed,
+                op_id,
+            } => self.marks.remove_mark(interval_id, observed, op_id),
+        }
+    }
+
+    fn allocate(&mut self, units: usize) -> OpId {
+        let id = OpId {
+            counter: self.next_counter,
+            peer: self.peer,
+        };
+        self.next_counter += units as u64;
+        id
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        let len = self.len_graphemes();
+        assert!(
+            range.start <= range.end && range.end <= len,
+            "range {range:?} out of bounds ({len})"
+        );
+    }
+}
+
+impl Mergeable for Text {
+    type Op = TextOp;
+
+    fn apply_op(&mut self, op: TextOp) {
+        self.apply(op);
+    }
+
+    fn state_vector(&self) -> StateVector {
+        self.observed.clone()
+    }
+
+    fn ops_since(&self, since: &StateVector) -> Vec<TextOp> {
+        let runs = self.runs.ops_since(since).into_iter().map(TextOp::Run);
+        let marks = self.marks.ops_since(since).into_iter().map(TextOp::from);
+        runs.chain(marks).collect()
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for run in self.runs.runs() {
+            if let Some(text) = run.text() {
+                f.write_str(text)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,567 @@
+//! Nested JSON-like CRDT values.
+//!
+//! A [`ValueTree`] holds a root [`ValueMap`] whose entries are [`Value`]s: scalars,
+//! nested maps, lists, or run-length text. Every level merges on its own terms:
+//!
+//! - map keys are last-writer-wins assignments with observed-remove deletion, as
+//!   in [`Map`](super::Map);
+//! - lists are RGA [`Sequence`]s whose items keep their insert id;
+//! - text is a [`RunSequence`].
+//!
+//! Operations address their container by a [`PathStep`] path that names the id
+//! of each container on the way down. An op aimed at a container that lost a
+//! concurrent assignment, or was removed, is dropped; an op aimed at a container
+//! that has not arrived yet waits in the tree until it does.
+
+use super::runs::{RunOp, RunSequence};
+use super::{CausalOrd, OpId, Sequence, SequenceOp};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scalar {
+    Null,
+    Bool(bool),
+    Int(i64),
+    String(String),
+}
+
+/// Initial content of a newly assigned map entry or list item.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValueInit {
+    Scalar(Scalar),
+    Map,
+    List,
+    Text,
+}
+
+/// One hop from a container to a nested container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathStep {
+    /// Map entry `key`, as assigned by `id`.
+    Key { key: String, id: OpId },
+    /// List item inserted by this id.
+    Item(OpId),
+}
+
+/// Caller-facing path segment resolved against live values by [`ValueTree::path`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKey<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValueOp {
+    Set {
+        target: Vec<PathStep>,
+        key: String,
+        value: ValueInit,
+        id: OpId,
+    },
+    /// Remove `key` as of the assignment `observed`; later assignments survive.
+    Remove {
+        target: Vec<PathStep>,
+        key: String,
+        observed: OpId,
+        id: OpId,
+    },
+    Insert {
+        target: Vec<PathStep>,
+        after: Option<OpId>,
+        right_origin: Option<OpId>,
+        value: ValueInit,
+        id: OpId,
+    },
+    Delete {
+        target: Vec<PathStep>,
+        item: OpId,
+        id: OpId,
+    },
+    Text {
+        target: Vec<PathStep>,
+        op: RunOp,
+    },
+}
+
+impl ValueOp {
+    fn target(&self) -> &[PathStep] {
+        match self {
+            ValueOp::Set { target, .. }
+            | ValueOp::Remove { target, .. }
+            | ValueOp::Insert { target, .. }
+            | ValueOp::Delete { target, .. }
+            | ValueOp::Text { target, .. } => target,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Scalar(Scalar),
+    Map(ValueMap),
+    List(ValueList),
+    Text(RunSequence),
+}
+
+impl Value {
+    fn from_init(init: ValueInit) -> Self {
+        match init {
+            ValueInit::Scalar(scalar) => Value::Scalar(scalar),
+            ValueInit::Map => Value::Map(ValueMap::default()),
+            ValueInit::List => Value::List(ValueList::default()),
+            ValueInit::Text => Value::Text(RunSequence::new()),
+        }
+    }
+
+    pub fn as_scalar(&self) -> Option<&Scalar> {
+        match self {
+            Value::Scalar(scalar) => Some(scalar),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&ValueMap> {
+        match self {
+            Value::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&ValueList> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&RunSequence> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Materialize the live tree as plain JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Scalar(Scalar::Null) => serde_json::Value::Null,
+            Value::Scalar(Scalar::Bool(value)) => (*value).into(),
+            Value::Scalar(Scalar::Int(value)) => (*value).into(),
+            Value::Scalar(Scalar::String(value)) => value.as_str().into(),
+            Value::Map(map) => map.to_json(),
+            Value::List(list) => list.iter().map(Value::to_json).collect(),
+            Value::Text(text) => text.text().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Slot {
+    /// Winning assignment; `None` when a remove arrived before any assignment.
+    value: Option<(OpId, Value)>,
+    /// Highest assignment observed by a remove of this key.
+    removed: Option<OpId>,
+}
+
+impl Slot {
+    fn live(&self) -> Option<(OpId, &Value)> {
+        let (id, value) = self.value.as_ref()?;
+        self.removed
+            .is_none_or(|removed| CausalOrd::wins(*id, removed))
+            .then_some((*id, value))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValueMap {
+    slots: BTreeMap<String, Slot>,
+}
+
+impl ValueMap {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.slots.get(key)?.live().map(|(_, value)| value)
+    }
+
+    /// Id of the live assignment at `key`, used to build a [`PathStep::Key`].
+    pub fn assignment_id(&self, key: &str) -> Option<OpId> {
+        self.slots.get(key)?.live().map(|(id, _)| id)
+    }
+
+    /// Live entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.slots
+            .iter()
+            .filter_map(|(key, slot)| Some((key.as_str(), slot.live()?.1)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.iter()
+                .map(|(key, value)| (key.to_string(), value.to_json()))
+                .collect(),
+        )
+    }
+
+    fn slot_mut(&mut self, key: String) -> &mut Slot {
+        self.slots.entry(key).or_insert(Slot {
+            value: None,
+            removed: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueList {
+    items: Sequence<Value>,
+}
+
+impl Default for ValueList {
+    fn default() -> Self {
+        Self {
+            items: Sequence::new(),
+        }
+    }
+}
+
+impl ValueList {
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.items.iter().nth(index)
+    }
+
+    /// Insert id of the live item at `index`, used to build a [`PathStep::Item`].
+    pub fn item_id(&self, index: usize) -> Option<OpId> {
+        self.live_ids().nth(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len_visible()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn live_ids(&self) -> impl Iterator<Item = OpId> + '_ {
+        self.items
+            .iter_all()
+            .filter(|element| element.value.is_some())
+            .map(|element| element.id)
+    }
+}
+
+/// Why an operation's target could not be reached.
+enum Unreachable {
+    /// The container has not arrived yet; retry later.
+    Missing,
+    /// The container lost a concurrent assignment or was removed; drop the op.
+    Stale,
+}
+
+/// Root of a nested CRDT value tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueTree {
+    root: Value,
+    /// Operations whose target container has not arrived yet.
+    pending: Vec<ValueOp>,
+}
+
+impl Default for ValueTree {
+    fn default() -> Self {
+        Self {
+            root: Value::Map(ValueMap::default()),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl ValueTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn root(&self) -> &ValueMap {
+        self.root.as_map().expect("value tree root is always a map")
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        self.root.to_json()
+    }
+
+    /// Number of operations waiting on a container that has not arrived.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Resolve keys and list indexes against live values into an addressable path.
+    pub fn path(&self, keys: &[PathKey<'_>]) -> Option<Vec<PathStep>> {
+        let mut current = &self.root;
+        let mut path = Vec::with_capacity(keys.len());
+        for key in keys {
+            match (key, current) {
+                (PathKey::Key(key), Value::Map(map)) => {
+                    path.push(PathStep::Key {
+                        key: key.to_string(),
+                        id: map.assignment_id(key)?,
+                    });
+                    current = map.get(key)?;
+                }
+                (PathKey::Index(index), Value::List(list)) => {
+                    path.push(PathStep::Item(list.item_id(*index)?));
+                    current = list.get(*index)?;
+                }
+                _ => return None,
+            }
+        }
+        Some(path)
+    }
+
+    pub fn get(&self, target: &[PathStep]) -> Option<&Value> {
+        let mut current = &self.root;
+        for step in target {
+            current = match (step, current) {
+                (PathStep::Key { key, id }, Value::Map(map)) => {
+                    let (live, value) = map.slots.get(key)?.live()?;
+                    if live != *id {
+                        return None;
+                    }
+                    value
+                }
+                (PathStep::Item(id), Value::List(list)) => {
+                    list.items.get_element(id)?.value.as_ref()?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Assign `value` at `key` in the map at `target`.
+    pub fn set(&mut self, target: Vec<PathStep>, key: &str, value: ValueInit, id: OpId) -> ValueOp {
+        let op = ValueOp::Set {
+            target,
+            key: key.to_string(),
+            value,
+            id,
+        };
+        self.apply(op.clone());
+        op
+    }
+
+    /// Remove the live `key` from the map at `target` (`None` if absent).
+    pub fn remove(&mut self, target: Vec<PathStep>, key: &str, id: OpId) -> Option<ValueOp> {
+        let observed = self.get(&target)?.as_map()?.assignment_id(key)?;
+        let op = ValueOp::Remove {
+            target,
+            key: key.to_string(),
+            observed,
+            id,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Insert `value` before live item `index` of the list at `target`.
+    pub fn insert(
+        &mut self,
+        target: Vec<PathStep>,
+        index: usize,
+        value: ValueInit,
+        id: OpId,
+    ) -> Option<ValueOp> {
+        let list = self.get(&target)?.as_list()?;
+        if index > list.len() {
+            return None;
+        }
+        let after = index.checked_sub(1).and_then(|prev| list.item_id(prev));
+        let right_origin = list.items.compute_right_origin(after);
+        let op = ValueOp::Insert {
+            target,
+            after,
+            right_origin,
+            value,
+            id,
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Delete live item `index` of the list at `target`.
+    pub fn delete(&mut self, target: Vec<PathStep>, index: usize, id: OpId) -> Option<ValueOp> {
+        let item = self.get(&target)?.as_list()?.item_id(index)?;
+        let op = ValueOp::Delete { target, item, id };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Insert `text` before grapheme `offset` of the text at `target`.
+    pub fn insert_text(
+        &mut self,
+        target: Vec<PathStep>,
+        offset: usize,
+        text: &str,
+        id: OpId,
+    ) -> Option<ValueOp> {
+        let runs = self.get(&target)?.as_text()?;
+        if offset > runs.len_visible() {
+            return None;
+        }
+        let after = offset
+            .checked_sub(1)
+            .and_then(|prev| runs.units().nth(prev))
+            .map(|(unit, _)| unit);
+        let op = ValueOp::Text {
+            target,
+            op: RunOp::Insert {
+                after,
+                id,
+                text: text.to_string(),
+                right_origin: runs.compute_right_origin(after),
+            },
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Delete grapheme `offset` of the text at `target`.
+    pub fn delete_text(
+        &mut self,
+        target: Vec<PathStep>,
+        offset: usize,
+        id: OpId,
+    ) -> Option<ValueOp> {
+        let (unit, _) = self.get(&target)?.as_text()?.units().nth(offset)?;
+        let op = ValueOp::Text {
+            target,
+            op: RunOp::Delete { target: unit, id },
+        };
+        self.apply(op.clone());
+        Some(op)
+    }
+
+    /// Apply a local or remote operation. Commutative and idempotent.
+    pub fn apply(&mut self, op: ValueOp) {
+        if !self.apply_now(op) {
+            return;
+        }
+        // A newly reachable container can unblock buffered ops, which can unblock more.
+        loop {
+            let mut progressed = false;
+            for op in std::mem::take(&mut self.pending) {
+                progressed |= self.apply_now(op);
+            }
+            if !progressed {
+                break;
+            }
+        }
+    }
+
+    /// Apply `op` if its target is reachable, returning whether the tree changed.
+    fn apply_now(&mut self, op: ValueOp) -> bool {
+        let container = match Self::resolve_mut(&mut self.root, op.target()) {
+            Ok(container) => container,
+            Err(Unreachable::Missing) => {
+                if !self.pending.contains(&op) {
+                    self.pending.push(op);
+                }
+                return false;
+            }
+            Err(Unreachable::Stale) => return false,
+        };
+        match (op, container) {
+            (ValueOp::Set { key, value, id, .. }, Value::Map(map)) => {
+                let slot = map.slot_mut(key);
+                if slot
+                    .value
+                    .as_ref()
+                    .is_none_or(|(current, _)| CausalOrd::wins(id, *current))
+                {
+                    slot.value = Some((id, Value::from_init(value)));
+                }
+            }
+            (ValueOp::Remove { key, observed, .. }, Value::Map(map)) => {
+                let slot = map.slot_mut(key);
+                if slot.removed.is_none_or(|removed| observed > removed) {
+                    slot.removed = Some(observed);
+                }
+            }
+            (
+                ValueOp::Insert {
+                    after,
+                    right_origin,
+                    value,
+                    id,
+                    ..
+                },
+                Value::List(list),
+            ) => list.items.apply(SequenceOp::Insert {
+                after,
+                id,
+                value: Value::from_init(value),
+                right_origin,
+            }),
+            (ValueOp::Delete { item, id, .. }, Value::List(list)) => {
+                list.items.apply(SequenceOp::Delete { target: item, id })
+            }
+            (ValueOp::Text { op, .. }, Value::Text(text)) => text.apply(op),
+            // The op was built against a container of another kind.
+            _ => return false,
+        }
+        true
+    }
+
+    fn resolve_mut<'a>(
+        mut current: &'a mut Value,
+        target: &[PathStep],
+    ) -> Result<&'a mut Value, Unreachable> {
+        for step in target {
+            current = match (step, current) {
+                (PathStep::Key { key, id }, Value::Map(map)) => {
+                    let Some(slot) = map.slots.get_mut(key) else {
+                        return Err(Unreachable::Missing);
+                    };
+                    if slot
+                        .removed
+                        .is_some_and(|removed| !CausalOrd::wins(*id, removed))
+                    {
+                        return Err(Unreachable::Stale);
+                    }
+                    match slot.value.as_mut() {
+                        Some((current, value)) if current == id => value,
+                        // A higher assignment already won; the target can never come back.
+                        Some((current, _)) if CausalOrd::wins(*current, *id) => {
+                            return Err(Unreachable::Stale);
+                        }
+                        _ => return Err(Unreachable::Missing),
+                    }
+                }
+                (PathStep::Item(id), Value::List(list)) => match list.items.get_element(id) {
+                    None => return Err(Unreachable::Missing),
+                    Some(element) if element.value.is_none() => return Err(Unreachable::Stale),
+                    Some(_) => list
+                        .items
+                        .value_mut(*id)
+                        .expect("live list item has a value"),
+                },
+                _ => return Err(Unreachable::Stale),
+            };
+        }
+        Ok(current)
+    }
+}
@@ -0,0 +1,170 @@
+//! Per-block access control.
+//!
+//! A [`BlockAcl`] is document metadata keyed by [`BlockId`], kept beside the block
+//! tree as a last-writer-wins register so it survives moves and merges like any
+//! other field. Storing an ACL does not enforce it: [`Document::raw_apply_op`]
+//! always checks, and a collaborative session checks local and remote operations
+//! once enforcement is switched on.
+
+use super::{BlockContainerPath, BlockId, Document, EditError};
+use crate::core::{CausalOrd, OpId, PeerId, StateVector};
+use crate::sync::SemanticConflict;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Who may edit a block and its descendants, and who may change that.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockAcl {
+    /// Peers allowed to edit and to change the ACL; empty means every peer.
+    pub owners: BTreeSet<PeerId>,
+    /// Rejects content edits from everyone, owners included, until an owner
+    /// clears it.
+    pub read_only: bool,
+}
+
+impl BlockAcl {
+    pub fn owned_by(owners: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            owners: owners.into_iter().collect(),
+            read_only: false,
+        }
+    }
+
+    /// Read-only for everyone; only `owners` may unlock it.
+    pub fn locked(owners: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            read_only: true,
+            ..Self::owned_by(owners)
+        }
+    }
+
+    pub fn permits_edit(&self, peer: PeerId) -> bool {
+        !self.read_only && self.permits_change(peer)
+    }
+
+    pub fn permits_change(&self, peer: PeerId) -> bool {
+        self.owners.is_empty() || self.owners.contains(&peer)
+    }
+}
+
+/// LWW register for one block's ACL; `acl == None` is a cleared ACL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AclEntry {
+    pub(crate) acl: Option<BlockAcl>,
+    pub(crate) op_id: OpId,
+    /// Causal frontier the winning write observed, to tell concurrent writes apart.
+    pub(crate) observed: StateVector,
+}
+
+impl Document {
+    pub fn block_acl(&self, block_id: BlockId) -> Option<&BlockAcl> {
+        self.acls.get(&block_id)?.acl.as_ref()
+    }
+
+    /// Winning write of `block_id`'s ACL, if it was ever set.
+    pub fn block_acl_op(&self, block_id: BlockId) -> Option<OpId> {
+        self.acls.get(&block_id).map(|entry| entry.op_id)
+    }
+
+    /// Merge one ACL write; the higher `op_id` wins. Reports a conflict when the
+    /// write and the current winner did not observe each other.
+    pub fn apply_block_acl(
+        &mut self,
+        block_id: BlockId,
+        acl: Option<BlockAcl>,
+        op_id: OpId,
+        observed: StateVector,
+    ) -> Option<SemanticConflict> {
+        let Some(current) = self.acls.get_mut(&block_id) else {
+            self.acls.insert(
+                block_id,
+                AclEntry {
+                    acl,
+                    op_id,
+                    observed,
+                },
+            );
+            return None;
+        };
+        if current.op_id == op_id {
+            return None;
+        }
+        let covers =
+            |frontier: &StateVector, id: OpId| frontier.get(id.peer).unwrap_or(0) >= id.counter;
+        let concurrent = !covers(&observed, current.op_id) && !covers(&current.observed, op_id);
+        let (winner, loser) = (
+            CausalOrd::winner(current.op_id, op_id),
+            CausalOrd::loser(current.op_id, op_id),
+        );
+        if CausalOrd::wins(op_id, current.op_id) {
+            *current = AclEntry {
+                acl,
+                op_id,
+                observed,
+            };
+        }
+        concurrent.then_some(SemanticConflict::AclConflict {
+            block_id,
+            winner,
+            loser,
+        })
+    }
+
+    pub(crate) fn acl_entries(&self) -> &BTreeMap<BlockId, AclEntry> {
+        &self.acls
+    }
+
+    pub(crate) fn set_acl_entries(&mut self, acls: BTreeMap<BlockId, AclEntry>) {
+        self.acls = acls;
+    }
+
+    /// Whether `peer` may edit `block_id`: its own ACL and those of every block
+    /// containing it must all permit the edit.
+    pub fn check_block_edit(&self, block_id: BlockId, peer: PeerId) -> Result<(), EditError> {
+        self.check_block_edit_with(block_id, peer, &BTreeMap::new())
+    }
+
+    /// [`Self::check_block_edit`] with `pending` ACL writes taking precedence over
+    /// the stored ones, for checking a batch that changes ACLs before editing.
+    pub(crate) fn check_block_edit_with(
+        &self,
+        block_id: BlockId,
+        peer: PeerId,
+        pending: &BTreeMap<BlockId, Option<BlockAcl>>,
+    ) -> Result<(), EditError> {
+        if self.acls.is_empty() && pending.is_empty() {
+            return Ok(());
+        }
+        let locked = self.block_lineage(block_id).into_iter().any(|id| {
+            let acl = match pending.get(&id) {
+                Some(acl) => acl.as_ref(),
+                None => self.block_acl(id),
+            };
+            acl.is_some_and(|acl| !acl.permits_edit(peer))
+        });
+        if locked {
+            return Err(EditError::BlockLocked { block_id, peer });
+        }
+        Ok(())
+    }
+
+    /// `block_id` and the blocks containing it, innermost first.
+    fn block_lineage(&self, block_id: BlockId) -> Vec<BlockId> {
+        self.ensure_block_index();
+        let containers = self
+            .block_index_read()
+            .as_ref()
+            .and_then(|cached| cached.index.by_block_id.get(&block_id))
+            .map(|path| path.containers.clone())
+            .unwrap_or_default();
+        let mut lineage = vec![block_id];
+        for container in containers.iter().rev() {
+            let elem_id = match *container {
+                BlockContainerPath::BlockQuote(elem_id) => elem_id,
+                BlockContainerPath::ListItem { list, .. } => list,
+            };
+            lineage.extend(self.find_block(elem_id).map(|block| block.id));
+        }
+        lineage
+    }
+}
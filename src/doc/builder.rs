@@ -0,0 +1,180 @@
+//! Building documents in code without picking OpIds by hand.
+//!
+//! [`DocumentBuilder`] appends blocks in order and takes every id it needs
+//! (block elements, text units, table columns and rows) from a [`PeerClock`].
+//! [`DocumentBuilder::build`] returns the document together with the
+//! [`BlockInsert`]s that made it; replaying them in order with
+//! [`BlockInsert::apply`] builds an equal document on another replica.
+
+use super::{Block, BlockKind, CodeFenceStyle, ColumnAlignment, Document, Table};
+use crate::core::{ClockError, OpId, PeerClock, Sequence};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// One block appended by a [`DocumentBuilder`], as [`Document::insert_block_at`]
+/// takes it. Container blocks are inserted empty and their children follow
+/// with `parent` set to the container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInsert {
+    pub parent: Option<OpId>,
+    pub after: Option<OpId>,
+    pub id: OpId,
+    pub block: Block,
+}
+
+impl BlockInsert {
+    /// Insert the block into `document`; `false` if its parent is missing.
+    pub fn apply(&self, document: &mut Document) -> bool {
+        document.insert_block_at(self.parent, self.after, self.id, self.block.clone(), None)
+    }
+}
+
+/// Appends blocks to a new [`Document`], allocating ids from a shared clock.
+///
+/// ```
+/// use md_crdt::core::PeerClock;
+/// use md_crdt::doc::{DocumentBuilder, EquivalenceMode};
+///
+/// let clock = PeerClock::new(7);
+/// let (document, ops) = DocumentBuilder::new(&clock)
+///     .heading(1, "Notes")
+///     .quote(|quote| quote.paragraph("quoted"))
+///     .code("rust", "fn main() {}")
+///     .build()
+///     .unwrap();
+/// assert_eq!(ops.len(), 4);
+/// assert_eq!(
+///     document.serialize(EquivalenceMode::Structural),
+///     "# Notes\n\n> quoted\n\n```rust\nfn main() {}\n```"
+/// );
+/// ```
+#[derive(Debug)]
+pub struct DocumentBuilder<'a> {
+    clock: &'a PeerClock,
+    document: Document,
+    ops: Vec<BlockInsert>,
+    parent: Option<OpId>,
+    after: Option<OpId>,
+    error: Option<ClockError>,
+}
+
+impl<'a> DocumentBuilder<'a> {
+    pub fn new(clock: &'a PeerClock) -> Self {
+        Self {
+            clock,
+            document: Document::new(),
+            ops: Vec::new(),
+            parent: None,
+            after: None,
+            error: None,
+        }
+    }
+
+    pub fn paragraph(self, text: &str) -> Self {
+        self.text_block(text, BlockKind::paragraph)
+    }
+
+    /// A heading; `level` is clamped to 1–6.
+    pub fn heading(self, level: u8, text: &str) -> Self {
+        self.text_block(text, |text, start| BlockKind::heading(level, text, start))
+    }
+
+    /// A fenced code block; an empty `lang` leaves the info string out.
+    pub fn code(self, lang: &str, text: &str) -> Self {
+        let kind = BlockKind::CodeFence {
+            style: CodeFenceStyle::default(),
+            info: (!lang.is_empty()).then(|| lang.to_string()),
+            text: text.to_string(),
+        };
+        self.block(0, |_| kind)
+    }
+
+    /// A blockquote whose children `children` appends.
+    pub fn quote(self, children: impl FnOnce(Self) -> Self) -> Self {
+        let kind = BlockKind::BlockQuote {
+            children: Sequence::new(),
+        };
+        let mut builder = self.block(0, |_| kind);
+        let Some(quote) = builder.after.filter(|_| builder.error.is_none()) else {
+            return builder;
+        };
+        let outer = builder.parent.replace(quote);
+        builder.after = None;
+        let mut builder = children(builder);
+        builder.parent = outer;
+        builder.after = Some(quote);
+        builder
+    }
+
+    /// A table with left-aligned columns; rows shorter than `header` leave
+    /// their last cells empty, and longer ones are cut to it.
+    pub fn table(self, header: &[&str], rows: &[&[&str]]) -> Self {
+        let ids = (header.len() + rows.len()) as u64;
+        self.block(ids, |range| {
+            let elem_id = range[0];
+            let mut table = Table::new(super::block_id_from_op(elem_id), elem_id, elem_id);
+            let (columns, row_ids) = range[1..].split_at(header.len());
+            let mut after = None;
+            for (title, &column) in header.iter().zip(columns) {
+                table.insert_column(after, ColumnAlignment::Left, title.to_string(), column);
+                after = Some(column);
+            }
+            let mut after = None;
+            for (cells, &row) in rows.iter().zip(row_ids) {
+                let cells = table
+                    .columns_in_order()
+                    .iter()
+                    .zip(cells.iter())
+                    .map(|(column, cell)| (column.id, cell.to_string()))
+                    .collect();
+                table.insert_row(after, cells, row);
+                after = Some(row);
+            }
+            BlockKind::Table {
+                table: Box::new(table),
+            }
+        })
+    }
+
+    /// The document and the inserts that built it, or the clock error that
+    /// stopped the first block it could not allocate for.
+    pub fn build(self) -> Result<(Document, Vec<BlockInsert>), ClockError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok((self.document, self.ops)),
+        }
+    }
+
+    fn text_block(self, text: &str, kind: impl FnOnce(&str, OpId) -> BlockKind) -> Self {
+        let units = text.graphemes(true).count() as u64;
+        self.block(units, |ids| {
+            kind(text, ids.get(1).copied().unwrap_or(ids[0]))
+        })
+    }
+
+    /// Reserve the block's element id plus `extra` more and append the block
+    /// `kind` makes from them, element id first.
+    fn block(mut self, extra: u64, kind: impl FnOnce(&[OpId]) -> BlockKind) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let mut range = match self.clock.reserve(extra + 1) {
+            Ok(range) => range,
+            Err(error) => {
+                self.error = Some(error);
+                return self;
+            }
+        };
+        let ids: Vec<OpId> = std::iter::from_fn(|| range.next_id().ok()).collect();
+        let id = ids[0];
+        let insert = BlockInsert {
+            parent: self.parent,
+            after: self.after,
+            id,
+            block: Block::new(kind(&ids), id),
+        };
+        insert.apply(&mut self.document);
+        self.ops.push(insert);
+        self.after = Some(id);
+        self
+    }
+}
@@ -0,0 +1,255 @@
+//! Line-level editing of code fences.
+//!
+//! A fence's text stays a whole-string LWW register, replaced by
+//! `DocOp::SetCodeFence`. Line edits merge through a [`Sequence`] of lines
+//! layered over the text one such write produced, its *base* (the block's
+//! `kind_op`), so peers editing different lines both keep their changes. Base
+//! lines take ids under a reserved peer, identical on every replica holding the
+//! same base. A later whole-text write replaces the base, and line edits made
+//! against the old one are dropped just as the old text is.
+
+use super::{BlockId, BlockKind, Document, EditError, EditOp};
+use crate::core::{OpId, PeerId, Sequence, SequenceOp};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Peer id of the synthetic ids given to base lines; never issued to a replica.
+const BASE_LINE_PEER: PeerId = PeerId::MAX;
+
+/// Lines of one code fence, over the text written by `base`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CodeLines {
+    pub(crate) base: OpId,
+    pub(crate) lines: Sequence<String>,
+}
+
+impl CodeLines {
+    fn from_text(base: OpId, text: &str) -> Self {
+        let lines = split_lines(text)
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let id = OpId {
+                    peer: BASE_LINE_PEER,
+                    counter: index as u64 + 1,
+                };
+                (id, line.to_string())
+            })
+            .collect();
+        Self {
+            base,
+            lines: Sequence::from_ordered(lines),
+        }
+    }
+
+    fn visible_ids(&self) -> Vec<OpId> {
+        self.lines
+            .iter_all()
+            .filter(|element| element.value.is_some())
+            .map(|element| element.id)
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        self.lines.to_vec().join("\n")
+    }
+}
+
+/// An empty fence has no lines; otherwise every `\n` separates two lines.
+fn split_lines(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split('\n').collect()
+    }
+}
+
+impl Document {
+    /// Ids of the visible lines of code fence `block_id`, in order.
+    pub fn code_line_ids(&self, block_id: BlockId) -> Option<Vec<OpId>> {
+        Some(self.current_code_lines(block_id)?.visible_ids())
+    }
+
+    /// Insert `line` so it becomes line `index` of code fence `block_id`.
+    pub fn insert_code_line(
+        &mut self,
+        block_id: BlockId,
+        index: usize,
+        line: &str,
+        op_id: OpId,
+    ) -> Result<EditOp, EditError> {
+        self.check_block_edit(block_id, op_id.peer)?;
+        let op = self.plan_code_line_insert(block_id, index, line, op_id)?;
+        self.apply_code_line_op(&op);
+        Ok(op)
+    }
+
+    /// Delete line `index` of code fence `block_id`.
+    pub fn delete_code_line(
+        &mut self,
+        block_id: BlockId,
+        index: usize,
+        op_id: OpId,
+    ) -> Result<EditOp, EditError> {
+        self.check_block_edit(block_id, op_id.peer)?;
+        let op = self.plan_code_line_delete(block_id, index, op_id)?;
+        self.apply_code_line_op(&op);
+        Ok(op)
+    }
+
+    /// The [`EditOp::InsertCodeLine`] that [`Self::insert_code_line`] would apply,
+    /// without ACL checks.
+    pub(crate) fn plan_code_line_insert(
+        &self,
+        block_id: BlockId,
+        index: usize,
+        line: &str,
+        op_id: OpId,
+    ) -> Result<EditOp, EditError> {
+        if line.contains('\n') {
+            return Err(EditError::InvalidCodeLine { block_id });
+        }
+        let lines = self.code_lines_for_edit(block_id)?;
+        let ids = lines.visible_ids();
+        if index > ids.len() {
+            return Err(EditError::InvalidOffset {
+                block_id,
+                offset: index,
+                len: ids.len(),
+            });
+        }
+        let after = index.checked_sub(1).map(|previous| ids[previous]);
+        Ok(EditOp::InsertCodeLine {
+            block_id,
+            base: lines.base,
+            after,
+            right_origin: lines.lines.compute_right_origin(after),
+            line: line.to_string(),
+            op_id,
+        })
+    }
+
+    /// The [`EditOp::DeleteCodeLine`] that [`Self::delete_code_line`] would apply,
+    /// without ACL checks.
+    pub(crate) fn plan_code_line_delete(
+        &self,
+        block_id: BlockId,
+        index: usize,
+        op_id: OpId,
+    ) -> Result<EditOp, EditError> {
+        let lines = self.code_lines_for_edit(block_id)?;
+        let ids = lines.visible_ids();
+        let Some(&target) = ids.get(index) else {
+            return Err(EditError::InvalidOffset {
+                block_id,
+                offset: index,
+                len: ids.len(),
+            });
+        };
+        Ok(EditOp::DeleteCodeLine {
+            block_id,
+            base: lines.base,
+            target,
+            op_id,
+        })
+    }
+
+    fn code_lines_for_edit(&self, block_id: BlockId) -> Result<Cow<'_, CodeLines>, EditError> {
+        if self.find_block_by_id(block_id).is_none() {
+            return Err(EditError::BlockNotFound { block_id });
+        }
+        self.current_code_lines(block_id)
+            .ok_or(EditError::NotCodeFence { block_id })
+    }
+
+    /// Line state over the fence's current base, built from its text when no
+    /// line edit has touched that base yet.
+    fn current_code_lines(&self, block_id: BlockId) -> Option<Cow<'_, CodeLines>> {
+        let (base, text) = self.code_fence_base(block_id)?;
+        Some(match self.code_lines.get(&block_id) {
+            Some(lines) if lines.base == base => Cow::Borrowed(lines),
+            _ => Cow::Owned(CodeLines::from_text(base, text)),
+        })
+    }
+
+    /// Merge a line insert or delete. Returns whether the fence changed; an edit
+    /// against a superseded base, or aimed at a missing fence, is dropped.
+    pub(crate) fn apply_code_line_op(&mut self, op: &EditOp) -> bool {
+        let (block_id, base, sequence_op) = match op {
+            EditOp::InsertCodeLine {
+                block_id,
+                base,
+                after,
+                right_origin,
+                line,
+                op_id,
+            } => (
+                *block_id,
+                *base,
+                SequenceOp::Insert {
+                    after: *after,
+                    id: *op_id,
+                    value: line.clone(),
+                    right_origin: *right_origin,
+                },
+            ),
+            EditOp::DeleteCodeLine {
+                block_id,
+                base,
+                target,
+                op_id,
+            } => (
+                *block_id,
+                *base,
+                SequenceOp::Delete {
+                    target: *target,
+                    id: *op_id,
+                },
+            ),
+            _ => return false,
+        };
+        if matches!(&sequence_op, SequenceOp::Insert { value, .. } if value.contains('\n')) {
+            return false;
+        }
+        let Some(mut lines) = self.current_code_lines(block_id).map(Cow::into_owned) else {
+            return false;
+        };
+        if lines.base != base {
+            return false;
+        }
+        lines.lines.apply(sequence_op);
+        let rendered = lines.text();
+        self.code_lines.insert(block_id, lines);
+        let Some(elem_id) = self.block_elem_id(block_id) else {
+            return false;
+        };
+        self.with_block_mut(elem_id, |block| {
+            if let BlockKind::CodeFence { text, .. } = &mut block.kind {
+                *text = rendered;
+            }
+        })
+        .is_some()
+    }
+
+    /// Drop line state once a whole-text write replaces the fence's base.
+    pub(crate) fn reset_code_lines(&mut self, block_id: BlockId) {
+        self.code_lines.remove(&block_id);
+    }
+
+    pub(crate) fn code_line_entries(&self) -> &BTreeMap<BlockId, CodeLines> {
+        &self.code_lines
+    }
+
+    pub(crate) fn set_code_line_entries(&mut self, code_lines: BTreeMap<BlockId, CodeLines>) {
+        self.code_lines = code_lines;
+    }
+
+    fn code_fence_base(&self, block_id: BlockId) -> Option<(OpId, &str)> {
+        let block = self.find_block_by_id(block_id)?;
+        match &block.kind {
+            BlockKind::CodeFence { text, .. } => Some((block.kind_op, text)),
+            _ => None,
+        }
+    }
+}
@@ -1,4 +1,14 @@
-use crate::core::{LwwRegister, OpId};
+//! Top-level YAML frontmatter as typed, mergeable fields.
+//!
+//! Simple `key: value` frontmatter stays structured: every key is a
+//! last-writer-wins register holding the value's YAML text, read back as a
+//! [`FrontmatterValue`] by [`Frontmatter::value`]. Flow lists such as
+//! `tags: [a, b]` merge as an add-wins [`OrSet`] instead of by LWW: a write removes
+//! only the items it observed, so an item a concurrent write added survives.
+//! Anything else (block lists, nested maps, block scalars) leaves the
+//! frontmatter opaque and read-only.
+
+use crate::core::{CausalOrd, LwwRegister, OpId, OrSet, StateVector};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -6,6 +16,9 @@ use std::collections::{BTreeMap, BTreeSet};
 pub struct Frontmatter {
     raw: String,
     fields: BTreeMap<String, LwwRegister<Option<String>>>,
+    /// Keys that ever held a list, with their add-wins item state.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    lists: BTreeMap<String, ListField>,
     original: BTreeMap<String, String>,
     dirty: BTreeSet<String>,
     structured: bool,
@@ -19,10 +32,97 @@ pub enum FrontmatterError {
     InvalidKey,
 }
 
+/// A frontmatter value by the type its YAML text reads as.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrontmatterValue {
+    String(String),
+    /// The number as written, e.g. `3` or `-0.5`; see [`Self::as_f64`].
+    Number(String),
+    Bool(bool),
+    /// A calendar date, `YYYY-MM-DD`.
+    Date(String),
+    /// A flow list of strings, `[a, b]`. Merges as an add-wins set.
+    List(Vec<String>),
+}
+
+impl FrontmatterValue {
+    /// Read one YAML scalar or flow list. Quoted scalars are always strings.
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        if let Some(inner) = raw
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            return Self::List(
+                split_flow_items(inner)
+                    .into_iter()
+                    .map(unquote)
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+            );
+        }
+        match raw {
+            "true" => return Self::Bool(true),
+            "false" => return Self::Bool(false),
+            _ => {}
+        }
+        if is_quoted(raw) {
+            Self::String(unquote(raw))
+        } else if is_number(raw) {
+            Self::Number(raw.to_string())
+        } else if is_date(raw) {
+            Self::Date(raw.to_string())
+        } else {
+            Self::String(raw.to_string())
+        }
+    }
+
+    /// The value as YAML text that [`Self::parse`] reads back unchanged,
+    /// quoting strings that would otherwise read as another type or break the
+    /// line.
+    pub fn to_yaml(&self) -> String {
+        match self {
+            Self::String(text) => quote_if_needed(text, false),
+            Self::Number(number) => number.clone(),
+            Self::Bool(value) => value.to_string(),
+            Self::Date(date) => date.clone(),
+            Self::List(items) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| quote_if_needed(item, true))
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Items of a list field as an add-wins set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ListField {
+    items: OrSet<String>,
+    /// The field's value as the present items render it.
+    merged: Option<String>,
+}
+
+/// Write id of values parsed from the file, observed by every write.
+const SEED: OpId = OpId {
+    counter: 0,
+    peer: 0,
+};
+
 impl Frontmatter {
     pub fn parse(raw: String) -> Self {
         let mut fields = BTreeMap::new();
         let mut original = BTreeMap::new();
+        let mut lists = BTreeMap::new();
         let mut structured = true;
         for line in raw.lines() {
             let trimmed = line.trim();
@@ -46,16 +146,23 @@ impl Frontmatter {
             if matches!(value.as_str(), "|" | ">" | "|-" | ">-") {
                 structured = false;
             }
-            let seed = OpId {
-                counter: 0,
-                peer: 0,
-            };
-            fields.insert(key.to_string(), LwwRegister::new(Some(value.clone()), seed));
+            if let FrontmatterValue::List(items) = FrontmatterValue::parse(&value) {
+                let mut list = ListField {
+                    items: OrSet::new(),
+                    merged: Some(value.clone()),
+                };
+                for item in items {
+                    list.items.add(item, SEED);
+                }
+                lists.insert(key.to_string(), list);
+            }
+            fields.insert(key.to_string(), LwwRegister::new(Some(value.clone()), SEED));
             original.insert(key.to_string(), value);
         }
         Self {
             raw,
             fields,
+            lists,
             original,
             dirty: BTreeSet::new(),
             structured,
@@ -74,21 +181,72 @@ impl Frontmatter {
         !self.dirty.is_empty()
     }
 
+    /// YAML text of `key`'s value; for a list, the merged items.
     pub fn get(&self, key: &str) -> Option<&str> {
+        if let Some(list) = self.lists.get(key)
+            && self.holds_list(key)
+        {
+            return list.merged.as_deref();
+        }
         self.fields.get(key)?.get_ref().as_deref()
     }
 
+    /// `key`'s value read by type.
+    pub fn value(&self, key: &str) -> Option<FrontmatterValue> {
+        self.get(key).map(FrontmatterValue::parse)
+    }
+
+    /// Winning write of `key`, if it was ever set.
+    pub(crate) fn field_op_id(&self, key: &str) -> Option<OpId> {
+        self.fields.get(key).map(LwwRegister::op_id)
+    }
+
     pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
-        self.fields
-            .iter()
-            .map(|(key, value)| (key.as_str(), value.get_ref().as_deref()))
+        self.fields.keys().map(|key| (key.as_str(), self.get(key)))
     }
 
+    /// Write `key` as a write that observed every current value: list items
+    /// missing from `value` are removed.
     pub fn set(
         &mut self,
         key: String,
         value: Option<String>,
         op_id: OpId,
+    ) -> Result<(), FrontmatterError> {
+        self.write(key, value, op_id, None)
+    }
+
+    /// [`Self::set`] with a typed value, written as [`FrontmatterValue::to_yaml`].
+    pub fn set_value(
+        &mut self,
+        key: String,
+        value: Option<FrontmatterValue>,
+        op_id: OpId,
+    ) -> Result<(), FrontmatterError> {
+        self.set(key, value.map(|value| value.to_yaml()), op_id)
+    }
+
+    /// Merge a remote write of `key` made after observing `observed`. Scalars
+    /// resolve by LWW; a list write removes only the items whose additions it
+    /// observed, so concurrent additions survive. An empty `observed`, from
+    /// peers that predate it, counts as having observed everything.
+    pub fn merge(
+        &mut self,
+        key: String,
+        value: Option<String>,
+        op_id: OpId,
+        observed: &StateVector,
+    ) -> Result<(), FrontmatterError> {
+        let observed = (!observed.is_empty()).then_some(observed);
+        self.write(key, value, op_id, observed)
+    }
+
+    fn write(
+        &mut self,
+        key: String,
+        value: Option<String>,
+        op_id: OpId,
+        observed: Option<&StateVector>,
     ) -> Result<(), FrontmatterError> {
         if !self.structured {
             return Err(FrontmatterError::Opaque);
@@ -96,14 +254,43 @@ impl Frontmatter {
         if !valid_key(&key) {
             return Err(FrontmatterError::InvalidKey);
         }
+        let written = match value.as_deref().map(FrontmatterValue::parse) {
+            Some(FrontmatterValue::List(items)) => Some(items),
+            _ => None,
+        };
         self.fields
             .entry(key.clone())
             .and_modify(|register| register.set(value.clone(), op_id))
             .or_insert_with(|| LwwRegister::new(value, op_id));
+        if written.is_some() || self.lists.contains_key(&key) {
+            let list = self.lists.entry(key.clone()).or_default();
+            list.apply(written.unwrap_or_default(), op_id, observed);
+            let order = match self.fields[&key].get_ref().as_deref() {
+                Some(raw) => match FrontmatterValue::parse(raw) {
+                    FrontmatterValue::List(items) => items,
+                    _ => Vec::new(),
+                },
+                None => Vec::new(),
+            };
+            list.merged = list.render(&order);
+        }
         self.dirty.insert(key);
         Ok(())
     }
 
+    /// Whether `key` shows its list items: its winning write is a list, or a
+    /// deletion that concurrent additions outlived.
+    fn holds_list(&self, key: &str) -> bool {
+        match self
+            .fields
+            .get(key)
+            .and_then(|register| register.get_ref().as_deref())
+        {
+            Some(raw) => matches!(FrontmatterValue::parse(raw), FrontmatterValue::List(_)),
+            None => true,
+        }
+    }
+
     pub fn render(&self) -> String {
         if self.dirty.is_empty() {
             return self.raw.clone();
@@ -121,25 +308,18 @@ impl Frontmatter {
                 continue;
             }
             emitted.insert(key.to_string());
-            if let Some(value) = self
-                .fields
-                .get(key)
-                .and_then(|register| register.get_ref().as_ref())
-            {
+            if let Some(value) = self.get(key) {
                 let comment = inline_comment(rest).unwrap_or_default();
                 let spacing = if rest.starts_with(' ') { " " } else { "" };
                 output.push(format!("{key_part}:{spacing}{value}{comment}"));
             }
         }
+        // New keys follow the original lines in key order.
         for key in &self.dirty {
             if emitted.contains(key) || self.original.contains_key(key) {
                 continue;
             }
-            if let Some(value) = self
-                .fields
-                .get(key)
-                .and_then(|register| register.get_ref().as_ref())
-            {
+            if let Some(value) = self.get(key) {
                 output.push(format!("{key}: {value}"));
             }
         }
@@ -147,6 +327,51 @@ impl Frontmatter {
     }
 }
 
+impl ListField {
+    /// Apply a write whose list is `written` (empty for a scalar or deletion),
+    /// made as of `observed` (`None`: everything). The write adds the items it
+    /// lists but did not see, and removes the additions it saw of the items it
+    /// leaves out.
+    fn apply(&mut self, written: Vec<String>, op_id: OpId, observed: Option<&StateVector>) {
+        let observed = observed.cloned().unwrap_or_else(|| self.items.frontier());
+        let written: BTreeSet<String> = written.into_iter().collect();
+        let dropped: Vec<String> = self
+            .items
+            .known()
+            .filter(|item| !written.contains(*item))
+            .cloned()
+            .collect();
+        for item in dropped {
+            self.items.remove_as_of(&item, &observed, op_id);
+        }
+        for item in written {
+            if !self.items.contains_as_of(&item, &observed) {
+                self.items.add(item, op_id);
+            }
+        }
+    }
+
+    /// Present items in the winning write's `order`, then the ones it did not
+    /// list by their first surviving addition. `None` when no item is present.
+    fn render(&self, order: &[String]) -> Option<String> {
+        let mut items: Vec<String> = Vec::new();
+        for item in order {
+            if self.items.contains(item) && !items.contains(item) {
+                items.push(item.clone());
+            }
+        }
+        let mut rest: Vec<(CausalOrd, &String)> = self
+            .items
+            .iter()
+            .filter(|item| !items.contains(item))
+            .filter_map(|item| Some((self.items.additions(item).map(CausalOrd).min()?, item)))
+            .collect();
+        rest.sort();
+        items.extend(rest.into_iter().map(|(_, item)| item.clone()));
+        (!items.is_empty()).then(|| FrontmatterValue::List(items).to_yaml())
+    }
+}
+
 fn valid_key(key: &str) -> bool {
     !key.is_empty()
         && key
@@ -176,3 +401,123 @@ fn value_without_comment(value: &str) -> &str {
         .unwrap_or(value)
         .trim_end()
 }
+
+/// Split the inside of a flow list at commas outside quotes.
+fn split_flow_items(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut single, mut double, mut start) = (false, false, 0);
+    let mut escaped = false;
+    for (index, ch) in inner.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if double => escaped = true,
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            ',' if !single && !double => {
+                items.push(inner[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(inner[start..].trim());
+    items
+}
+
+fn is_quoted(raw: &str) -> bool {
+    raw.len() >= 2
+        && ((raw.starts_with('"') && raw.ends_with('"'))
+            || (raw.starts_with('\'') && raw.ends_with('\'')))
+}
+
+fn unquote(raw: &str) -> String {
+    let raw = raw.trim();
+    if !is_quoted(raw) {
+        return raw.to_string();
+    }
+    let inner = &raw[1..raw.len() - 1];
+    if raw.starts_with('\'') {
+        return inner.replace("''", "'");
+    }
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some(other) => text.push(other),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+fn is_number(raw: &str) -> bool {
+    let digits = raw.strip_prefix(['-', '+']).unwrap_or(raw);
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (digits, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    !whole.is_empty()
+        && all_digits(whole)
+        && all_digits(fraction)
+        && (!mantissa.contains('.') || !fraction.is_empty())
+        && exponent.is_none_or(|exponent| {
+            let exponent = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+            !exponent.is_empty() && all_digits(exponent)
+        })
+}
+
+fn is_date(raw: &str) -> bool {
+    let bytes = raw.as_bytes();
+    let number = |range: std::ops::Range<usize>| {
+        raw.get(range)
+            .filter(|part| part.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|part| part.parse::<u32>().ok())
+    };
+    bytes.len() == 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && number(0..4).is_some()
+        && number(5..7).is_some_and(|month| (1..=12).contains(&month))
+        && number(8..10).is_some_and(|day| (1..=31).contains(&day))
+}
+
+/// `text` as a YAML scalar, double-quoted when it would read back as another
+/// value; `in_list` also quotes flow-list separators.
+fn quote_if_needed(text: &str, in_list: bool) -> String {
+    let plain = !text.is_empty()
+        && text.trim() == text
+        && !text.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ])
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.ends_with(':')
+        && !text.chars().any(char::is_control)
+        && !(in_list && text.contains([',', '[', ']', '{', '}']))
+        && (in_list || FrontmatterValue::parse(text) == FrontmatterValue::String(text.into()));
+    if plain {
+        return text.to_string();
+    }
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
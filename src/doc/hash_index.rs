@@ -0,0 +1,82 @@
+//! Per-block content hashes for comparing replicas without serializing them.
+//!
+//! [`Document::block_hash_index`] lists every top-level block with a hash of
+//! its default rendering and its position. Hashes are cached by the block's
+//! [`Sequence::value_version`](crate::core::Sequence::value_version), so after
+//! an edit only the changed blocks are rendered again. Two replicas compare by
+//! exchanging indexes: [`diverged_blocks`] names the blocks whose content
+//! differs, and equal indexes mean equal documents up to frontmatter. The hash
+//! is FNV-1a over the rendering, the same as the vault's file content hash.
+
+use super::parser::{FNV_OFFSET, fnv1a};
+use super::{BlockId, Document, serialize_block};
+use crate::core::OpId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// One top-level block in a [`Document::block_hash_index`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHash {
+    pub block_id: BlockId,
+    /// Hash of the block's rendering, nested blocks included.
+    pub content_hash: u64,
+    /// Position among visible top-level blocks.
+    pub order_index: usize,
+}
+
+/// Content hashes by element id, with the value version each was taken at.
+pub(super) type HashCache = HashMap<OpId, (u64, u64)>;
+
+impl Document {
+    /// Every visible top-level block, in order, with its content hash.
+    pub fn block_hash_index(&self) -> Vec<BlockHash> {
+        let mut cache = self
+            .hash_cache
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut previous = std::mem::take(&mut *cache);
+        let visible = self
+            .blocks
+            .iter_all()
+            .filter_map(|element| Some((element.id, element.value.as_ref()?)));
+        visible
+            .enumerate()
+            .map(|(order_index, (elem_id, block))| {
+                let version = self.blocks.value_version(elem_id);
+                let content_hash = match previous.remove(&elem_id) {
+                    Some((cached, hash)) if cached == version => hash,
+                    _ => fnv1a(FNV_OFFSET, serialize_block(block).as_bytes()),
+                };
+                cache.insert(elem_id, (version, content_hash));
+                BlockHash {
+                    block_id: block.id,
+                    content_hash,
+                    order_index,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Blocks whose content differs between two indexes, including blocks present
+/// in only one of them, in `local` order followed by blocks only `remote` has.
+/// Order changes alone are not reported; compare the `order_index` fields for
+/// those.
+pub fn diverged_blocks(local: &[BlockHash], remote: &[BlockHash]) -> Vec<BlockId> {
+    let mut remote_hashes: BTreeMap<BlockId, u64> = remote
+        .iter()
+        .map(|entry| (entry.block_id, entry.content_hash))
+        .collect();
+    let mut diverged: Vec<BlockId> = local
+        .iter()
+        .filter(|entry| remote_hashes.remove(&entry.block_id) != Some(entry.content_hash))
+        .map(|entry| entry.block_id)
+        .collect();
+    diverged.extend(
+        remote
+            .iter()
+            .filter(|entry| remote_hashes.contains_key(&entry.block_id))
+            .map(|entry| entry.block_id),
+    );
+    diverged
+}
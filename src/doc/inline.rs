@@ -1,4 +1,8 @@
-use super::{Block, BlockKind, TextUnit, grapheme_count, paragraph_visible_ids};
+use super::parser::IdAllocator;
+use super::{
+    Block, BlockKind, ParserOptions, SerializerRegistry, TextUnit, grapheme_count,
+    paragraph_visible_ids,
+};
 use crate::core::mark::{Anchor, AnchorBias, MarkInterval, MarkKind, MarkValue};
 use crate::core::{OpId, Sequence};
 use std::collections::BTreeMap;
@@ -20,17 +24,18 @@ pub(super) fn parse_text_block(
     kind: impl FnOnce(Sequence<TextUnit>) -> BlockKind,
     markdown: &str,
     elem_id: OpId,
-    counter: &mut u64,
+    allocator: &mut IdAllocator,
+    options: &ParserOptions,
 ) -> Block {
-    let (visible, parsed_marks) = parse_fragment(markdown);
-    let text = super::units_from_str(&visible, counter, 0);
+    let (visible, parsed_marks) = parse_fragment(markdown, options);
+    let text = allocator.units(&visible);
     let ids = paragraph_visible_ids(&text);
     let mut block = Block::new(kind(text), elem_id);
     for parsed in parsed_marks {
         if parsed.start >= parsed.end || parsed.end > ids.len() {
             continue;
         }
-        let mark_id = super::parser::next_op_id(counter);
+        let mark_id = allocator.next_id();
         block.marks.set_mark(
             mark_id,
             parsed.kind,
@@ -49,7 +54,7 @@ pub(super) fn parse_text_block(
     block
 }
 
-fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
+fn parse_fragment(markdown: &str, options: &ParserOptions) -> (String, Vec<ParsedMark>) {
     let mut visible = String::new();
     let mut marks = Vec::new();
     let mut cursor = 0usize;
@@ -61,7 +66,7 @@ fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
             cursor += run;
             continue;
         }
-        if let Some((open, close, kind)) = delimiter_at(rest)
+        if let Some((open, close, kind)) = delimiter_at(rest, options)
             && let Some(relative_end) = find_closing_delimiter(&rest[open.len()..], close, &kind)
         {
             let inner_start = cursor + open.len();
@@ -70,7 +75,7 @@ fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
             if kind == MarkKind::Code {
                 visible.push_str(&markdown[inner_start..inner_end]);
             } else {
-                let (inner, nested) = parse_fragment(&markdown[inner_start..inner_end]);
+                let (inner, nested) = parse_fragment(&markdown[inner_start..inner_end], options);
                 visible.push_str(&inner);
                 marks.extend(nested.into_iter().map(|mut mark| {
                     mark.start += start;
@@ -90,6 +95,15 @@ fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
             cursor = inner_end + close.len();
             continue;
         }
+        // A footnote reference is never a link label.
+        if options.footnotes
+            && rest.starts_with("[^")
+            && let Some(label_end) = rest.find(']')
+        {
+            visible.push_str(&rest[..=label_end]);
+            cursor += label_end + 1;
+            continue;
+        }
         if rest.starts_with('[')
             && let Some(label_end) = rest.find("](")
             && let Some(target_end) = find_link_target_end(&rest[label_end + 2..])
@@ -97,7 +111,7 @@ fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
             let label = &rest[1..label_end];
             let target = &rest[label_end + 2..label_end + 2 + target_end];
             let start = grapheme_count(&visible);
-            let (inner, mut nested) = parse_fragment(label);
+            let (inner, mut nested) = parse_fragment(label, options);
             visible.push_str(&inner);
             let end = grapheme_count(&visible);
             for mark in &mut nested {
@@ -124,8 +138,13 @@ fn parse_fragment(markdown: &str) -> (String, Vec<ParsedMark>) {
     (visible, marks)
 }
 
-fn delimiter_at(input: &str) -> Option<(&'static str, &'static str, MarkKind)> {
-    if input.starts_with("**") {
+fn delimiter_at(
+    input: &str,
+    options: &ParserOptions,
+) -> Option<(&'static str, &'static str, MarkKind)> {
+    if options.strikethrough && input.starts_with("~~") {
+        Some(("~~", "~~", MarkKind::Strikethrough))
+    } else if input.starts_with("**") {
         Some(("**", "**", MarkKind::Bold))
     } else if input.starts_with('*') {
         Some(("*", "*", MarkKind::Italic))
@@ -138,7 +157,7 @@ fn delimiter_at(input: &str) -> Option<(&'static str, &'static str, MarkKind)> {
 
 fn find_closing_delimiter(input: &str, close: &str, kind: &MarkKind) -> Option<usize> {
     match kind {
-        MarkKind::Code => find_unescaped(input, close, 0),
+        MarkKind::Code | MarkKind::Strikethrough => find_unescaped(input, close, 0),
         MarkKind::Bold => {
             let position = find_unescaped(input, close, 0)?;
             if input[position..].starts_with("***") && has_unclosed_single_star(&input[..position])
@@ -225,7 +244,11 @@ fn find_link_target_end(input: &str) -> Option<usize> {
     None
 }
 
-pub(super) fn serialize_text(block: &Block, text: &Sequence<TextUnit>) -> String {
+pub(super) fn serialize_text(
+    block: &Block,
+    text: &Sequence<TextUnit>,
+    registry: &SerializerRegistry,
+) -> String {
     let ids = paragraph_visible_ids(text);
     let graphemes: Vec<&str> = text.iter().map(|unit| unit.grapheme.as_str()).collect();
     let resolved = block.marks.resolved_intervals(&ids);
@@ -273,8 +296,8 @@ pub(super) fn serialize_text(block: &Block, text: &Sequence<TextUnit>) -> String
         groups
             .entry((
                 interval.kind.clone(),
-                open_delimiter(interval),
-                close_delimiter(interval),
+                open_delimiter(interval, registry),
+                close_delimiter(interval, registry),
             ))
             .or_default()
             .push((interval, start, end));
@@ -330,10 +353,10 @@ pub(super) fn serialize_text(block: &Block, text: &Sequence<TextUnit>) -> String
                 .take_while(|(left, right)| left.id == right.id)
                 .count();
             for interval in open_stack[shared..].iter().rev() {
-                output.push_str(&close_delimiter(interval));
+                output.push_str(&close_delimiter(interval, registry));
             }
             for interval in &desired[shared..] {
-                output.push_str(&open_delimiter(interval));
+                output.push_str(&open_delimiter(interval, registry));
             }
             open_stack = desired;
         }
@@ -349,47 +372,43 @@ fn mark_nesting_rank(kind: &MarkKind) -> u8 {
         MarkKind::Link => 0,
         MarkKind::Bold => 1,
         MarkKind::Italic => 2,
-        MarkKind::Code => 3,
-        MarkKind::Custom(_) => 4,
+        MarkKind::Strikethrough => 3,
+        MarkKind::Code => 4,
+        MarkKind::Custom(_) => 5,
     }
 }
 
 fn delimiter_attr(interval: &crate::core::mark::MarkInterval) -> Option<String> {
-    interval
-        .attrs
-        .get("delimiter")
-        .and_then(|value| match value.get_ref() {
-            MarkValue::String(value) => Some(value.clone()),
-            MarkValue::Bool(_) => None,
-        })
+    interval.string_attr("delimiter").map(str::to_string)
 }
 
-fn open_delimiter(interval: &crate::core::mark::MarkInterval) -> String {
+fn open_delimiter(interval: &MarkInterval, registry: &SerializerRegistry) -> String {
+    if let Some((open, _)) = registry.mark_delimiters(interval) {
+        return open;
+    }
     match &interval.kind {
         MarkKind::Bold => delimiter_attr(interval).unwrap_or_else(|| "**".into()),
         MarkKind::Italic => delimiter_attr(interval).unwrap_or_else(|| "*".into()),
         MarkKind::Code => delimiter_attr(interval).unwrap_or_else(|| "`".into()),
+        MarkKind::Strikethrough => delimiter_attr(interval).unwrap_or_else(|| "~~".into()),
         MarkKind::Link => "[".into(),
-        MarkKind::Custom(_) => String::new(),
+        MarkKind::Custom(_) => delimiter_attr(interval).unwrap_or_default(),
     }
 }
 
-fn close_delimiter(interval: &crate::core::mark::MarkInterval) -> String {
+fn close_delimiter(interval: &MarkInterval, registry: &SerializerRegistry) -> String {
+    if let Some((_, close)) = registry.mark_delimiters(interval) {
+        return close;
+    }
     match &interval.kind {
         MarkKind::Bold => delimiter_attr(interval).unwrap_or_else(|| "**".into()),
         MarkKind::Italic => delimiter_attr(interval).unwrap_or_else(|| "*".into()),
         MarkKind::Code => delimiter_attr(interval).unwrap_or_else(|| "`".into()),
+        MarkKind::Strikethrough => delimiter_attr(interval).unwrap_or_else(|| "~~".into()),
         MarkKind::Link => {
-            let href = interval
-                .attrs
-                .get("href")
-                .and_then(|value| match value.get_ref() {
-                    MarkValue::String(value) => Some(value.as_str()),
-                    MarkValue::Bool(_) => None,
-                })
-                .unwrap_or_default();
+            let href = interval.href().unwrap_or_default();
             format!("]({href})")
         }
-        MarkKind::Custom(_) => String::new(),
+        MarkKind::Custom(_) => delimiter_attr(interval).unwrap_or_default(),
     }
 }
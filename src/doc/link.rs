@@ -0,0 +1,130 @@
+//! Typed targets for [`MarkKind::Link`](crate::core::mark::MarkKind::Link) marks.
+//!
+//! A link mark stores its target as the free-form `href` attribute. [`LinkTarget`]
+//! classifies it as an external URL, an anchor within the same note, or a link to
+//! another note of the vault (the wikilink case), and renders it back.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// Anything with a URL scheme (`https:`, `mailto:`) or a `//host` prefix.
+    External(String),
+    /// `#anchor` within the linking note.
+    Heading(String),
+    /// Another note, relative to the linking note's directory unless `path` starts
+    /// with `/`, optionally at a heading anchor.
+    Note {
+        path: String,
+        heading: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LinkError {
+    #[error("link target is empty")]
+    Empty,
+    #[error("link target contains {0:?}, which a Markdown link cannot carry")]
+    InvalidCharacter(char),
+    #[error("link target has unbalanced parentheses")]
+    UnbalancedParentheses,
+    #[error("link target has an empty heading anchor")]
+    EmptyAnchor,
+}
+
+impl LinkTarget {
+    /// Classify and validate an `href`.
+    pub fn parse(href: &str) -> Result<Self, LinkError> {
+        if href.is_empty() {
+            return Err(LinkError::Empty);
+        }
+        if let Some(ch) = href
+            .chars()
+            .find(|ch| ch.is_whitespace() || ch.is_control())
+        {
+            return Err(LinkError::InvalidCharacter(ch));
+        }
+        let mut depth = 0usize;
+        for ch in href.chars() {
+            match ch {
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or(LinkError::UnbalancedParentheses)?
+                }
+                _ => {}
+            }
+        }
+        if depth != 0 {
+            return Err(LinkError::UnbalancedParentheses);
+        }
+
+        if href.starts_with("//") || has_scheme(href) {
+            return Ok(Self::External(href.to_string()));
+        }
+        let (path, heading) = match href.split_once('#') {
+            Some((_, "")) => return Err(LinkError::EmptyAnchor),
+            Some((path, heading)) => (path, Some(heading.to_string())),
+            None => (href, None),
+        };
+        Ok(match (path, heading) {
+            ("", Some(heading)) => Self::Heading(heading),
+            (path, heading) => Self::Note {
+                path: path.to_string(),
+                heading,
+            },
+        })
+    }
+
+    /// The heading anchor this target points at, in the linking note or another.
+    pub fn heading(&self) -> Option<&str> {
+        match self {
+            Self::External(_) => None,
+            Self::Heading(heading) => Some(heading),
+            Self::Note { heading, .. } => heading.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for LinkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::External(url) => f.write_str(url),
+            Self::Heading(heading) => write!(f, "#{heading}"),
+            Self::Note {
+                path,
+                heading: None,
+            } => f.write_str(path),
+            Self::Note {
+                path,
+                heading: Some(heading),
+            } => write!(f, "{path}#{heading}"),
+        }
+    }
+}
+
+/// RFC 3986 scheme: a letter, then letters, digits, `+`, `-`, or `.`, then `:`.
+fn has_scheme(href: &str) -> bool {
+    let Some((scheme, _)) = href.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|ch| ch.is_ascii_alphabetic())
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+}
+
+/// The anchor a heading gets: lowercase, spaces as `-`, other punctuation dropped.
+pub fn heading_anchor(heading: &str) -> String {
+    heading
+        .trim()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' => Some('-'),
+            '-' | '_' => Some(ch),
+            ch if ch.is_alphanumeric() => Some(ch),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
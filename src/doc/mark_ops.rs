@@ -3,8 +3,8 @@
 //! This module provides operations for manipulating marks (formatting) on text,
 //! including expansion during insert and splitting during remove.
 
-use crate::core::mark::{Anchor, AnchorBias, MarkInterval, MarkIntervalId, MarkSet};
-use crate::core::{LwwRegister, OpId};
+use crate::core::mark::{Anchor, MarkInterval, MarkIntervalId, MarkSet, resolve_anchor};
+use crate::core::{LwwRegister, OffsetMap, OpId};
 use std::collections::BTreeMap;
 
 pub fn expand_marks_for_insert(
@@ -17,8 +17,9 @@ pub fn expand_marks_for_insert(
     if !expand {
         return Vec::new();
     }
-    let spans = mark_set.render_spans(element_order, visible_len);
-    let index = resolve_anchor(&anchor, element_order, visible_len);
+    let offsets: OffsetMap = element_order.iter().copied().collect();
+    let spans = mark_set.render_spans_in(&offsets, visible_len);
+    let index = resolve_anchor(&anchor, &offsets, visible_len);
     for span in spans {
         if index >= span.start && index < span.end {
             return span.marks;
@@ -52,8 +53,8 @@ pub fn lower_remove_mark_range(
 
     // Compare anchors by visible text position, not raw OpId: RGA can order
     // concurrently-inserted units so that OpId order differs from visible order.
-    let len = element_order.len();
-    let pos = |a: &Anchor| resolve_anchor(a, element_order, len);
+    let offsets: OffsetMap = element_order.iter().copied().collect();
+    let pos = |a: &Anchor| resolve_anchor(a, &offsets, offsets.len());
     let left_needed = pos(&interval.start) < pos(&remove_start);
     let right_needed = pos(&remove_end) < pos(&interval.end);
 
@@ -99,14 +100,3 @@ pub fn lower_remove_mark_range(
 
     (new_intervals, removed)
 }
-
-fn resolve_anchor(anchor: &Anchor, order: &[OpId], len: usize) -> usize {
-    let idx = order
-        .iter()
-        .position(|id| *id == anchor.elem_id)
-        .unwrap_or(0);
-    match anchor.bias {
-        AnchorBias::Before => idx,
-        AnchorBias::After => (idx + 1).min(len),
-    }
-}
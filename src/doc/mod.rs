@@ -3,33 +3,65 @@
 //! This module provides a block-based document model for markdown content,
 //! with support for collaborative editing operations.
 
-use crate::core::mark::{Anchor, MarkIntervalId, MarkKind, MarkSet, MarkValue};
-use crate::core::{OpId, Sequence, SequenceOp, StateVector};
-use std::collections::{BTreeMap, HashMap};
+use crate::core::mark::{
+    Anchor, MarkInterval, MarkIntervalId, MarkKind, MarkSchema, MarkSchemaError, MarkSet, MarkValue,
+};
+use crate::core::{CausalOrd, OpId, SegmentationRules, Sequence, SequenceOp, StateVector};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::{Deref, DerefMut};
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use unicode_segmentation::UnicodeSegmentation;
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use uuid::Uuid;
 
+mod acl;
+mod builder;
+mod code;
 pub mod frontmatter;
+mod hash_index;
 mod inline;
+pub mod link;
 pub mod mark_ops;
 mod parser;
+mod path;
+mod pending;
+mod registry;
+mod render;
+mod replace;
+mod resolution;
 mod serialize;
 mod source;
+mod spans;
+mod stats;
+mod structure;
 pub mod text;
+mod whitespace;
 
+pub(crate) use acl::AclEntry;
+pub(crate) use code::CodeLines;
+pub(crate) use pending::PendingEdits;
+pub(crate) use serialize::serialize_block;
 pub(crate) use source::DocumentSource;
 
-pub use frontmatter::{Frontmatter, FrontmatterError};
-pub use parser::Parser;
-use serialize::{
-    grapheme_offset_to_byte, is_grapheme_boundary, normalize_structural, serialize_block,
-};
+pub use acl::BlockAcl;
+pub use builder::{BlockInsert, DocumentBuilder};
+pub use frontmatter::{Frontmatter, FrontmatterError, FrontmatterValue};
+pub use hash_index::{BlockHash, diverged_blocks};
+pub use link::{LinkError, LinkTarget, heading_anchor};
+pub use parser::{ParseError, ParseLimit, Parser, ParserLimits, ParserOptions};
+pub use path::{BlockPath, BlockRef};
+pub use registry::SerializerRegistry;
+pub use render::StyledRun;
+pub use replace::{BlockRangeDelete, ReplaceAll};
+pub(crate) use resolution::RegisterHeads;
+pub use resolution::{RegisterConflict, RegisterKey, ResolutionPolicy, ResolutionTarget};
+use serialize::normalize_structural;
+pub use stats::{BlockCounts, DocumentStats};
 pub use text::{
     TextUnit, after_for_grapheme_offset, grapheme_count, insert_graphemes, paragraph_visible_ids,
     paragraph_visible_string, units_from_str, units_from_str_at,
 };
+pub(crate) use text::{insert_graphemes_with, units_from_str_with};
+use whitespace::{FenceTracker, trim_trailing};
+pub use whitespace::{FinalNewline, LineEndings, TrailingSpaces, WhitespacePolicy};
 
 pub type BlockId = Uuid;
 
@@ -45,10 +77,21 @@ pub fn block_id_from_op(op: OpId) -> BlockId {
 pub struct Document {
     pub frontmatter: Option<Frontmatter>,
     pub blocks: IndexedBlocks,
+    acls: BTreeMap<BlockId, AclEntry>,
+    code_lines: BTreeMap<BlockId, CodeLines>,
     source: Option<DocumentSource>,
+    pending_edits: PendingEdits,
+    segmentation: SegmentationRules,
     block_index: RwLock<Option<CachedBlockIndex>>,
+    render_cache: Mutex<RenderCache>,
+    span_cache: Mutex<spans::SpanCache>,
+    hash_cache: Mutex<hash_index::HashCache>,
 }
 
+/// Rendered top-level blocks from the last serialization, by element id, with
+/// the [`Sequence::value_version`] each was rendered at.
+type RenderCache = HashMap<OpId, (u64, String)>;
+
 /// Top-level block sequence that invalidates the document index on mutation.
 #[derive(Debug)]
 pub struct IndexedBlocks {
@@ -67,6 +110,12 @@ impl IndexedBlocks {
     fn generation(&self) -> u64 {
         self.generation
     }
+
+    /// Mutable access for edits inside existing blocks (text, marks) that add,
+    /// remove, and move no block or list item, so the document index stays valid.
+    fn content_mut(&mut self) -> &mut Sequence<Block> {
+        &mut self.sequence
+    }
 }
 
 impl Clone for IndexedBlocks {
@@ -105,15 +154,17 @@ enum BlockContainerPath {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct BlockPath {
+struct TreePath {
     containers: Vec<BlockContainerPath>,
     elem_id: OpId,
 }
 
 #[derive(Debug, Default)]
 struct BlockIndex {
-    by_block_id: HashMap<BlockId, BlockPath>,
-    by_elem_id: HashMap<OpId, BlockPath>,
+    by_block_id: HashMap<BlockId, TreePath>,
+    by_elem_id: HashMap<OpId, TreePath>,
+    /// Containers down to each list item's children, the item's own step last.
+    items: HashMap<OpId, Vec<BlockContainerPath>>,
 }
 
 #[derive(Debug)]
@@ -127,8 +178,15 @@ impl Clone for Document {
         Self {
             frontmatter: self.frontmatter.clone(),
             blocks: self.blocks.clone(),
+            acls: self.acls.clone(),
+            code_lines: self.code_lines.clone(),
             source: self.source.clone(),
+            pending_edits: self.pending_edits.clone(),
+            segmentation: self.segmentation,
             block_index: RwLock::new(None),
+            render_cache: Mutex::default(),
+            span_cache: Mutex::default(),
+            hash_cache: Mutex::default(),
         }
     }
 }
@@ -137,7 +195,10 @@ impl PartialEq for Document {
     fn eq(&self, other: &Self) -> bool {
         self.frontmatter == other.frontmatter
             && self.blocks == other.blocks
+            && self.acls == other.acls
+            && self.code_lines == other.code_lines
             && self.source == other.source
+            && self.segmentation == other.segmentation
     }
 }
 
@@ -199,6 +260,18 @@ impl Default for ListStyle {
 pub enum FenceMarker {
     Backtick,
     Tilde,
+    /// `$$` display math, recognized with [`ParserOptions::math_blocks`].
+    Dollar,
+}
+
+impl FenceMarker {
+    pub(crate) fn symbol(self) -> char {
+        match self {
+            FenceMarker::Backtick => '`',
+            FenceMarker::Tilde => '~',
+            FenceMarker::Dollar => '$',
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -343,7 +416,7 @@ fn index_block_sequence(
         let Some(block) = element.value.as_ref() else {
             continue;
         };
-        let path = BlockPath {
+        let path = TreePath {
             containers: containers.to_vec(),
             elem_id: element.id,
         };
@@ -370,6 +443,7 @@ fn index_block_sequence(
                         item: item_element.id,
                     });
                     index_block_sequence(&item.children, &nested, index);
+                    index.items.entry(item.elem_id).or_insert(nested);
                 }
             }
             _ => {}
@@ -377,36 +451,43 @@ fn index_block_sequence(
     }
 }
 
-fn block_at_path<'a>(sequence: &'a Sequence<Block>, path: &BlockPath) -> Option<&'a Block> {
+/// The children sequence reached through `containers` from `sequence`.
+fn children_at_path<'a>(
+    sequence: &'a Sequence<Block>,
+    containers: &[BlockContainerPath],
+) -> Option<&'a Sequence<Block>> {
     let mut current = sequence;
-    for container in &path.containers {
-        match *container {
+    for container in containers {
+        current = match *container {
             BlockContainerPath::BlockQuote(id) => {
                 let block = current.get_element(&id)?.value.as_ref()?;
                 let BlockKind::BlockQuote { children } = &block.kind else {
                     return None;
                 };
-                current = children;
+                children
             }
             BlockContainerPath::ListItem { list, item } => {
-                let block = current.get_element(&list)?.value.as_ref()?;
-                let BlockKind::List { items, .. } = &block.kind else {
-                    return None;
-                };
-                current = &items.get_element(&item)?.value.as_ref()?.children;
+                &list_item_in(current, list, item)?.children
             }
-        }
+        };
     }
-    current.get_element(&path.elem_id)?.value.as_ref()
+    Some(current)
 }
 
-fn block_at_path_mut<'a>(
+fn list_item_in(sequence: &Sequence<Block>, list: OpId, item: OpId) -> Option<&ListItem> {
+    let block = sequence.get_element(&list)?.value.as_ref()?;
+    let BlockKind::List { items, .. } = &block.kind else {
+        return None;
+    };
+    items.get_element(&item)?.value.as_ref()
+}
+
+fn children_at_path_mut<'a>(
     sequence: &'a mut Sequence<Block>,
     containers: &[BlockContainerPath],
-    elem_id: OpId,
-) -> Option<&'a mut Block> {
+) -> Option<&'a mut Sequence<Block>> {
     let Some((container, rest)) = containers.split_first() else {
-        return sequence.value_mut(elem_id);
+        return Some(sequence);
     };
     match *container {
         BlockContainerPath::BlockQuote(id) => {
@@ -414,19 +495,33 @@ fn block_at_path_mut<'a>(
             let BlockKind::BlockQuote { children } = &mut block.kind else {
                 return None;
             };
-            block_at_path_mut(children, rest, elem_id)
+            children_at_path_mut(children, rest)
         }
         BlockContainerPath::ListItem { list, item } => {
             let block = sequence.value_mut(list)?;
             let BlockKind::List { items, .. } = &mut block.kind else {
                 return None;
             };
-            let item = items.value_mut(item)?;
-            block_at_path_mut(&mut item.children, rest, elem_id)
+            children_at_path_mut(&mut items.value_mut(item)?.children, rest)
         }
     }
 }
 
+fn block_at_path<'a>(sequence: &'a Sequence<Block>, path: &TreePath) -> Option<&'a Block> {
+    children_at_path(sequence, &path.containers)?
+        .get_element(&path.elem_id)?
+        .value
+        .as_ref()
+}
+
+fn block_at_path_mut<'a>(
+    sequence: &'a mut Sequence<Block>,
+    containers: &[BlockContainerPath],
+    elem_id: OpId,
+) -> Option<&'a mut Block> {
+    children_at_path_mut(sequence, containers)?.value_mut(elem_id)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EquivalenceMode {
     Exact,
@@ -437,6 +532,9 @@ pub enum EquivalenceMode {
 pub struct SerializeConfig {
     pub equivalence: EquivalenceMode,
     pub prefer_raw_source: bool,
+    /// Trailing whitespace, final newline and line endings of the output;
+    /// `None` leaves them to `equivalence`. Set with [`Self::with_whitespace`].
+    whitespace: Option<WhitespacePolicy>,
 }
 
 impl SerializeConfig {
@@ -444,6 +542,7 @@ impl SerializeConfig {
         Self {
             equivalence: EquivalenceMode::Exact,
             prefer_raw_source: true,
+            whitespace: None,
         }
     }
 
@@ -451,8 +550,18 @@ impl SerializeConfig {
         Self {
             equivalence: EquivalenceMode::Structural,
             prefer_raw_source: false,
+            whitespace: None,
         }
     }
+
+    pub fn with_whitespace(mut self, policy: WhitespacePolicy) -> Self {
+        self.whitespace = Some(policy);
+        self
+    }
+
+    pub fn whitespace(&self) -> Option<WhitespacePolicy> {
+        self.whitespace
+    }
 }
 
 impl Default for SerializeConfig {
@@ -530,10 +639,7 @@ impl TableCell {
     }
 
     pub fn set(&mut self, value: CellContent, op_id: OpId, observed: StateVector) {
-        let incoming_observed_current =
-            observed.get(self.op_id.peer).unwrap_or(0) >= self.op_id.counter;
-        let current_observed_incoming = self.observed.get(op_id.peer).unwrap_or(0) >= op_id.counter;
-        if incoming_observed_current || (!current_observed_incoming && op_id >= self.op_id) {
+        if causal_write_wins(self.op_id, &self.observed, op_id, &observed) {
             self.value = value;
             self.op_id = op_id;
             self.observed = observed;
@@ -551,7 +657,8 @@ fn causal_write_wins(
         incoming_observed.get(current_id.peer).unwrap_or(0) >= current_id.counter;
     let current_observed_incoming =
         current_observed.get(incoming_id.peer).unwrap_or(0) >= incoming_id.counter;
-    incoming_observed_current || (!current_observed_incoming && incoming_id >= current_id)
+    incoming_observed_current
+        || (!current_observed_incoming && CausalOrd::supersedes(incoming_id, current_id))
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -724,12 +831,12 @@ impl Table {
             });
             return;
         };
-        let current_id = updated.alignment.op_id();
-        let incoming_observed_current =
-            observed.get(current_id.peer).unwrap_or(0) >= current_id.counter;
-        let current_observed_incoming =
-            updated.alignment_observed.get(op_id.peer).unwrap_or(0) >= op_id.counter;
-        if incoming_observed_current || (!current_observed_incoming && op_id >= current_id) {
+        if causal_write_wins(
+            updated.alignment.op_id(),
+            &updated.alignment_observed,
+            op_id,
+            &observed,
+        ) {
             updated.alignment = crate::core::LwwRegister::new(alignment, op_id);
             updated.alignment_observed = observed;
             self.columns.update_value(updated.elem_id, updated);
@@ -787,7 +894,7 @@ impl Table {
             row.placement_observed.get(id.peer).unwrap_or(0) >= id.counter;
         if already_moved
             && !incoming_observed_current
-            && (current_observed_incoming || row.elem_id >= id)
+            && (current_observed_incoming || CausalOrd::supersedes(row.elem_id, id))
         {
             if self.rows.get_element(&id).is_none() {
                 row.elem_id = id;
@@ -868,7 +975,7 @@ impl Table {
             column.placement_observed.get(id.peer).unwrap_or(0) >= id.counter;
         if already_moved
             && !incoming_observed_current
-            && (current_observed_incoming || column.elem_id >= id)
+            && (current_observed_incoming || CausalOrd::supersedes(column.elem_id, id))
         {
             if self.columns.get_element(&id).is_none() {
                 column.elem_id = id;
@@ -950,7 +1057,7 @@ impl Table {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct InsertTextRun {
     pub block_id: BlockId,
     pub grapheme_offset: usize,
@@ -959,7 +1066,7 @@ pub struct InsertTextRun {
     pub op_id: OpId,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum EditOp {
     InsertText(InsertTextRun),
     /// Apply a rich mark interval on a block (anchors are text-unit OpIds).
@@ -979,16 +1086,193 @@ pub enum EditOp {
         observed: StateVector,
         op_id: OpId,
     },
+    /// Insert one code fence line after line `after` (`None`: first) of the fence
+    /// text written by `base`.
+    InsertCodeLine {
+        block_id: BlockId,
+        base: OpId,
+        after: Option<OpId>,
+        right_origin: Option<OpId>,
+        line: String,
+        op_id: OpId,
+    },
+    DeleteCodeLine {
+        block_id: BlockId,
+        base: OpId,
+        target: OpId,
+        op_id: OpId,
+    },
+}
+
+impl EditOp {
+    /// The block the edit is addressed to and the id of the edit itself.
+    pub fn target(&self) -> (BlockId, OpId) {
+        match self {
+            EditOp::InsertText(run) => (run.block_id, run.op_id),
+            EditOp::SetMark {
+                block_id, op_id, ..
+            }
+            | EditOp::RemoveMark {
+                block_id, op_id, ..
+            }
+            | EditOp::InsertCodeLine {
+                block_id, op_id, ..
+            }
+            | EditOp::DeleteCodeLine {
+                block_id, op_id, ..
+            } => (*block_id, *op_id),
+        }
+    }
 }
 
+/// Rejected [`Document`] edit. Every variant names the block it was aimed at.
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
 pub enum EditError {
-    #[error("block not found")]
-    BlockNotFound,
-    #[error("invalid offset")]
-    InvalidOffset,
-    #[error("invalid grapheme boundary")]
-    InvalidGraphemeBoundary,
+    #[error("block {block_id} not found")]
+    BlockNotFound { block_id: BlockId },
+    #[error("block {block_id} has no text body")]
+    NotTextBlock { block_id: BlockId },
+    #[error("block {block_id} is not a code fence")]
+    NotCodeFence { block_id: BlockId },
+    #[error("code line for block {block_id} contains a line break")]
+    InvalidCodeLine { block_id: BlockId },
+    /// `len` is in the unit of `offset`: graphemes, or bytes for byte-addressed runs.
+    #[error("offset {offset} is past the end of block {block_id} (length {len})")]
+    InvalidOffset {
+        block_id: BlockId,
+        offset: usize,
+        len: usize,
+    },
+    #[error("range {start}..{end} is empty or past the end of block {block_id} (length {len})")]
+    InvalidRange {
+        block_id: BlockId,
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("byte offset {offset} in block {block_id} is not a grapheme boundary")]
+    InvalidGraphemeBoundary { block_id: BlockId, offset: usize },
+    #[error("mark interval {interval_id:?} not found in block {block_id}")]
+    MarkNotFound {
+        block_id: BlockId,
+        interval_id: MarkIntervalId,
+    },
+    /// The block or one containing it has a [`BlockAcl`] that does not let `peer` edit.
+    #[error("block {block_id} is locked against edits by peer {peer}")]
+    BlockLocked {
+        block_id: BlockId,
+        peer: crate::core::PeerId,
+    },
+    /// A multi-block range whose end block comes before its start block `block_id`.
+    #[error("range from block {block_id} ends in earlier block {end_block}")]
+    InvertedRange {
+        block_id: BlockId,
+        end_block: BlockId,
+    },
+}
+
+impl EditError {
+    pub fn block_id(&self) -> BlockId {
+        match self {
+            EditError::BlockNotFound { block_id }
+            | EditError::NotTextBlock { block_id }
+            | EditError::NotCodeFence { block_id }
+            | EditError::InvalidCodeLine { block_id }
+            | EditError::InvalidOffset { block_id, .. }
+            | EditError::InvalidRange { block_id, .. }
+            | EditError::InvalidGraphemeBoundary { block_id, .. }
+            | EditError::MarkNotFound { block_id, .. }
+            | EditError::BlockLocked { block_id, .. }
+            | EditError::InvertedRange { block_id, .. } => *block_id,
+        }
+    }
+
+    /// Whether the same edit may succeed later unchanged: a missing block can still
+    /// arrive from another peer, while a bad offset or range will not fix itself.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, EditError::BlockNotFound { .. })
+    }
+}
+
+/// An active mark that fails a [`MarkSchema`], found by [`Document::validate_marks`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("mark interval {interval_id:?} in block {block_id}: {error}")]
+pub struct InvalidMark {
+    pub block_id: BlockId,
+    pub interval_id: MarkIntervalId,
+    pub error: MarkSchemaError,
+}
+
+/// Every live block, nested ones included, in document order.
+fn collect_blocks<'a>(blocks: &'a Sequence<Block>, out: &mut Vec<&'a Block>) {
+    for block in blocks.iter() {
+        out.push(block);
+        match &block.kind {
+            BlockKind::BlockQuote { children } => collect_blocks(children, out),
+            BlockKind::List { items, .. } => {
+                for item in items.iter() {
+                    collect_blocks(&item.children, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn gc_block_marks(blocks: &mut Sequence<Block>, min_state_vector: &StateVector) -> usize {
+    let mut dropped = 0;
+    for id in blocks.ids() {
+        let Some(block) = blocks.value_mut(id) else {
+            continue;
+        };
+        dropped += block.marks.gc(min_state_vector);
+        match &mut block.kind {
+            BlockKind::BlockQuote { children } => {
+                dropped += gc_block_marks(children, min_state_vector);
+            }
+            BlockKind::List { items, .. } => {
+                for item_id in items.ids() {
+                    if let Some(item) = items.value_mut(item_id) {
+                        dropped += gc_block_marks(&mut item.children, min_state_vector);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    dropped
+}
+
+fn drop_block_tombstones(blocks: &mut Sequence<Block>, floor: &StateVector) -> usize {
+    let mut dropped = 0;
+    for id in blocks.ids() {
+        let Some(block) = blocks.value_mut(id) else {
+            continue;
+        };
+        let anchors: BTreeSet<OpId> = block
+            .marks
+            .iter_all_intervals()
+            .flat_map(|interval| [interval.start.elem_id, interval.end.elem_id])
+            .collect();
+        match &mut block.kind {
+            BlockKind::Paragraph { text } | BlockKind::Heading { text, .. } => {
+                dropped += text.drop_tombstones(floor, &anchors);
+            }
+            BlockKind::BlockQuote { children } => {
+                dropped += drop_block_tombstones(children, floor);
+            }
+            BlockKind::List { items, .. } => {
+                for item_id in items.ids() {
+                    if let Some(item) = items.value_mut(item_id) {
+                        dropped += drop_block_tombstones(&mut item.children, floor);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    dropped
 }
 
 impl Document {
@@ -996,8 +1280,15 @@ impl Document {
         Self {
             frontmatter: None,
             blocks: IndexedBlocks::new(Sequence::new()),
+            acls: BTreeMap::new(),
+            code_lines: BTreeMap::new(),
             source: None,
+            pending_edits: PendingEdits::new(),
+            segmentation: SegmentationRules::default(),
             block_index: RwLock::new(None),
+            render_cache: Mutex::default(),
+            span_cache: Mutex::default(),
+            hash_cache: Mutex::default(),
         }
     }
 
@@ -1048,6 +1339,18 @@ impl Document {
         self.find_block_by_id(block_id).map(|block| block.elem_id)
     }
 
+    /// Grapheme cluster rules that text edits are split into units with.
+    pub fn segmentation(&self) -> SegmentationRules {
+        self.segmentation
+    }
+
+    /// Pin the cluster rules edits are segmented with from now on; text
+    /// already in the document keeps its units. Every replica of a document
+    /// must use the same rules, so set them before it is shared.
+    pub fn set_segmentation(&mut self, rules: SegmentationRules) {
+        self.segmentation = rules;
+    }
+
     pub fn set_raw_source(&mut self, source: String) {
         let parsed = Parser::parse(&source);
         self.adopt_source_from(&parsed);
@@ -1061,8 +1364,8 @@ impl Document {
         self.source.clone()
     }
 
-    pub(crate) fn has_source_state(&self) -> bool {
-        self.source.is_some()
+    pub(crate) fn source(&self) -> Option<&DocumentSource> {
+        self.source.as_ref()
     }
 
     pub(crate) fn source_region_bytes(&self, block_id: BlockId) -> Option<usize> {
@@ -1116,7 +1419,7 @@ impl Document {
         }
     }
 
-    fn mark_source_elem_dirty(&mut self, elem_id: OpId) {
+    pub(crate) fn mark_source_elem_dirty(&mut self, elem_id: OpId) {
         if let Some(source) = &mut self.source {
             source.mark_elem_dirty(elem_id);
         }
@@ -1155,30 +1458,38 @@ impl Document {
 
     /// Find a list item by its `elem_id` anywhere in the tree.
     pub fn find_list_item(&self, elem_id: OpId) -> Option<&ListItem> {
-        fn walk(seq: &Sequence<Block>, id: OpId) -> Option<&ListItem> {
-            for e in seq.iter_all() {
-                if let Some(b) = e.value.as_ref() {
-                    if let BlockKind::List { items, .. } = &b.kind {
-                        for ie in items.iter_all() {
-                            if let Some(item) = ie.value.as_ref() {
-                                if item.elem_id == id {
-                                    return Some(item);
-                                }
-                                if let Some(f) = walk(&item.children, id) {
-                                    return Some(f);
-                                }
-                            }
-                        }
-                    } else if let BlockKind::BlockQuote { children } = &b.kind
-                        && let Some(f) = walk(children, id)
-                    {
-                        return Some(f);
-                    }
+        let containers = self.list_item_path(elem_id)?;
+        let (BlockContainerPath::ListItem { list, item }, outer) = containers.split_last()? else {
+            return None;
+        };
+        list_item_in(children_at_path(&self.blocks, outer)?, *list, *item)
+    }
+
+    /// Containers down to the children of list item `elem_id`, from the index.
+    fn list_item_path(&self, elem_id: OpId) -> Option<Vec<BlockContainerPath>> {
+        self.container_path(elem_id).filter(|containers| {
+            matches!(containers.last(), Some(BlockContainerPath::ListItem { .. }))
+        })
+    }
+
+    /// Containers down to the children of `container_elem`, a blockquote block
+    /// or a list item; `None` if it is neither.
+    fn container_path(&self, container_elem: OpId) -> Option<Vec<BlockContainerPath>> {
+        self.ensure_block_index();
+        let containers = {
+            let cached = self.block_index_read();
+            let index = &cached.as_ref()?.index;
+            match index.by_elem_id.get(&container_elem) {
+                Some(path) => {
+                    let mut containers = path.containers.clone();
+                    containers.push(BlockContainerPath::BlockQuote(container_elem));
+                    containers
                 }
+                None => index.items.get(&container_elem)?.clone(),
             }
-            None
-        }
-        walk(&self.blocks, elem_id)
+        };
+        // A block that is not a blockquote has no children to reach.
+        children_at_path(&self.blocks, &containers).map(|_| containers)
     }
 
     /// Find a list item by stable logical identity anywhere in the tree.
@@ -1228,70 +1539,14 @@ impl Document {
     }
 
     /// Apply `f` to the children of the container with `container_elem` (a blockquote block
-    /// or a list item), searching the whole tree. `None` if no such container exists.
+    /// or a list item), wherever it is nested. `None` if no such container exists.
     fn with_container_children_mut<R>(
         &mut self,
         container_elem: OpId,
         f: impl FnOnce(&mut Sequence<Block>) -> R,
     ) -> Option<R> {
-        fn walk<R, F: FnOnce(&mut Sequence<Block>) -> R>(
-            seq: &mut Sequence<Block>,
-            target: OpId,
-            f: &mut Option<F>,
-        ) -> Option<R> {
-            for bid in seq.ids() {
-                if seq.value_mut(bid).is_some_and(|b| {
-                    b.elem_id == target && matches!(b.kind, BlockKind::BlockQuote { .. })
-                }) {
-                    let func = f.take()?;
-                    return seq.value_mut(bid).and_then(|b| match &mut b.kind {
-                        BlockKind::BlockQuote { children } => Some(func(children)),
-                        _ => None,
-                    });
-                }
-                let has_item = seq.value_mut(bid).is_some_and(|b| match &b.kind {
-                    BlockKind::List { items, .. } => items.iter().any(|it| it.elem_id == target),
-                    _ => false,
-                });
-                if has_item {
-                    let func = f.take()?;
-                    if let Some(b) = seq.value_mut(bid)
-                        && let BlockKind::List { items, .. } = &mut b.kind
-                    {
-                        for iid in items.ids() {
-                            if items.value_mut(iid).is_some_and(|it| it.elem_id == target) {
-                                return items.value_mut(iid).map(|it| func(&mut it.children));
-                            }
-                        }
-                    }
-                    return None;
-                }
-            }
-            for bid in seq.ids() {
-                if let Some(b) = seq.value_mut(bid) {
-                    match &mut b.kind {
-                        BlockKind::BlockQuote { children } => {
-                            if let Some(r) = walk(children, target, f) {
-                                return Some(r);
-                            }
-                        }
-                        BlockKind::List { items, .. } => {
-                            for iid in items.ids() {
-                                if let Some(item) = items.value_mut(iid)
-                                    && let Some(r) = walk(&mut item.children, target, f)
-                                {
-                                    return Some(r);
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            None
-        }
-        let mut f = Some(f);
-        walk(&mut self.blocks, container_elem, &mut f)
+        let containers = self.container_path(container_elem)?;
+        children_at_path_mut(&mut self.blocks, &containers).map(f)
     }
 
     /// Insert a block into `parent`'s children (top-level when `parent` is `None`).
@@ -1307,7 +1562,7 @@ impl Document {
         if let Some(parent) = parent {
             self.mark_source_elem_dirty(parent);
         }
-        match parent {
+        let inserted = match parent {
             None => {
                 self.blocks.apply(SequenceOp::Insert {
                     after,
@@ -1327,7 +1582,11 @@ impl Document {
                     });
                 })
                 .is_some(),
+        };
+        if inserted {
+            self.replay_pending_edits();
         }
+        inserted
     }
 
     /// Delete a block from `parent`'s children (top-level when `parent` is `None`).
@@ -1353,17 +1612,7 @@ impl Document {
     pub fn container_children(&self, parent: Option<OpId>) -> Option<&Sequence<Block>> {
         match parent {
             None => Some(&self.blocks),
-            Some(p) => {
-                if let Some(Block {
-                    kind: BlockKind::BlockQuote { children },
-                    ..
-                }) = self.find_block(p)
-                {
-                    Some(children)
-                } else {
-                    self.find_list_item(p).map(|it| &it.children)
-                }
-            }
+            Some(p) => children_at_path(&self.blocks, &self.container_path(p)?),
         }
     }
 
@@ -1711,18 +1960,24 @@ impl Document {
         id: OpId,
         observed: StateVector,
     ) -> bool {
-        self.with_block_mut(block_elem, |block| {
+        let replaced = self.with_block_mut(block_elem, |block| {
             if !causal_write_wins(block.kind_op, &block.kind_observed, id, &observed)
                 || !matches!(block.kind, BlockKind::CodeFence { .. })
             {
-                return false;
+                return None;
             }
             block.kind = BlockKind::CodeFence { style, info, text };
             block.kind_op = id;
             block.kind_observed = observed;
-            true
-        })
-        .unwrap_or(false)
+            Some(block.id)
+        });
+        match replaced.flatten() {
+            Some(block_id) => {
+                self.reset_code_lines(block_id);
+                true
+            }
+            None => false,
+        }
     }
 
     pub(crate) fn convert_text_block(
@@ -1846,7 +2101,7 @@ impl Document {
                 return false;
             }
             let already_moved = block_id_from_op(block.elem_id) != block.id;
-            if already_moved && block.elem_id >= moved.id {
+            if already_moved && CausalOrd::supersedes(block.elem_id, moved.id) {
                 loses = true;
             }
             let Some(parent) = self.block_parent(block.id) else {
@@ -1911,95 +2166,91 @@ impl Document {
         block_at_path(&self.blocks, &path).filter(|block| block.id == block_id)
     }
 
+    /// Insert `text` at a grapheme offset of a text block at any depth, named by
+    /// id or [`BlockPath`].
     pub fn insert_text(
         &mut self,
-        block_id: BlockId,
+        block: impl Into<BlockRef>,
         grapheme_offset: usize,
         text: &str,
         op_id: OpId,
     ) -> Result<Vec<EditOp>, EditError> {
-        let elem_id = self
-            .block_elem_id(block_id)
-            .ok_or(EditError::BlockNotFound)?;
-
-        // Get element via O(1) lookup and clone block for modification
-        let Some(existing) = self.blocks.get_element(&elem_id) else {
-            return Err(EditError::BlockNotFound);
-        };
-        let Some(block) = existing.value.as_ref() else {
-            return Err(EditError::BlockNotFound);
-        };
-
-        let mut updated = block.clone();
-        let Some(body) = block_text_seq_mut(&mut updated.kind) else {
-            return Err(EditError::InvalidOffset);
-        };
-
-        let visible = paragraph_visible_string(body);
-        let byte_offset =
-            grapheme_offset_to_byte(&visible, grapheme_offset).ok_or(EditError::InvalidOffset)?;
-        insert_graphemes(body, grapheme_offset, text, op_id).ok_or(EditError::InvalidOffset)?;
-
-        self.blocks.update_value(elem_id, updated);
+        let block_id = self.resolve_block_ref(block.into())?;
+        self.check_block_edit(block_id, op_id.peer)?;
+        let rules = self.segmentation;
+        let run = self.edit_block(block_id, |block| {
+            block.insert_text(grapheme_offset, text, op_id, rules)
+        })?;
+        Ok(vec![EditOp::InsertText(run)])
+    }
+
+    /// Edit block `block_id` in place, wherever it is nested. `edit` must check
+    /// everything before changing the block: an error leaves it as it was. Only
+    /// the block's text and marks may change, so the block index is kept.
+    fn edit_block<R>(
+        &mut self,
+        block_id: BlockId,
+        edit: impl FnOnce(&mut Block) -> Result<R, EditError>,
+    ) -> Result<R, EditError> {
+        let not_found = EditError::BlockNotFound { block_id };
+        let elem_id = self.block_elem_id(block_id).ok_or(not_found.clone())?;
+        let path = self
+            .block_index_read()
+            .as_ref()
+            .and_then(|cached| cached.index.by_elem_id.get(&elem_id).cloned())
+            .ok_or(not_found.clone())?;
+        let block = block_at_path_mut(self.blocks.content_mut(), &path.containers, elem_id)
+            .ok_or(not_found)?;
+        let edited = edit(block)?;
         self.mark_source_block_dirty(block_id);
-
-        Ok(vec![EditOp::InsertText(InsertTextRun {
-            block_id,
-            grapheme_offset,
-            byte_offset,
-            text: text.to_string(),
-            op_id,
-        })])
+        Ok(edited)
     }
 
+    /// Apply one edit, rejecting it when a [`BlockAcl`] locks the block against
+    /// the peer that issued it.
     pub fn raw_apply_op(
         &mut self,
         op: EditOp,
         validate_grapheme_boundaries: bool,
     ) -> Result<(), EditError> {
+        let (block_id, op_id) = op.target();
+        self.check_block_edit(block_id, op_id.peer)?;
+        let rules = self.segmentation;
         match op {
-            EditOp::InsertText(run) => {
-                // Find block's elem_id by block_id
-                let elem_id = self
-                    .blocks
-                    .iter_asc()
-                    .find(|block| block.id == run.block_id)
-                    .map(|block| block.elem_id)
-                    .ok_or(EditError::BlockNotFound)?;
-
-                // Get element via O(1) lookup and clone block for modification
-                let Some(existing) = self.blocks.get_element(&elem_id) else {
-                    return Err(EditError::BlockNotFound);
-                };
-                let Some(block) = existing.value.as_ref() else {
-                    return Err(EditError::BlockNotFound);
-                };
-
-                let mut updated = block.clone();
-                let Some(body) = block_text_seq_mut(&mut updated.kind) else {
-                    return Err(EditError::InvalidOffset);
+            EditOp::InsertText(run) => self.edit_block(run.block_id, |block| {
+                let block_id = run.block_id;
+                let Some(body) = block_text_seq_mut(&mut block.kind) else {
+                    return Err(EditError::NotTextBlock { block_id });
                 };
 
                 let visible = paragraph_visible_string(body);
                 let byte_offset = run.byte_offset;
                 if byte_offset > visible.len() {
-                    return Err(EditError::InvalidOffset);
-                }
-                if !visible.is_char_boundary(byte_offset) {
-                    return Err(EditError::InvalidOffset);
+                    return Err(EditError::InvalidOffset {
+                        block_id,
+                        offset: byte_offset,
+                        len: visible.len(),
+                    });
                 }
-                if validate_grapheme_boundaries && !is_grapheme_boundary(&visible, byte_offset) {
-                    return Err(EditError::InvalidGraphemeBoundary);
+                if !visible.is_char_boundary(byte_offset)
+                    || (validate_grapheme_boundaries && !rules.is_boundary(&visible, byte_offset))
+                {
+                    return Err(EditError::InvalidGraphemeBoundary {
+                        block_id,
+                        offset: byte_offset,
+                    });
                 }
                 // Prefer grapheme_offset on the run; fall back to byte→grapheme map.
                 let g_off = run.grapheme_offset;
-                insert_graphemes(body, g_off, &run.text, run.op_id)
-                    .ok_or(EditError::InvalidOffset)?;
-
-                self.blocks.update_value(elem_id, updated);
-                self.mark_source_block_dirty(run.block_id);
+                insert_graphemes_with(body, g_off, &run.text, run.op_id, rules).ok_or(
+                    EditError::InvalidOffset {
+                        block_id,
+                        offset: g_off,
+                        len: rules.count(&visible),
+                    },
+                )?;
                 Ok(())
-            }
+            }),
             EditOp::SetMark {
                 block_id,
                 interval_id,
@@ -2008,43 +2259,30 @@ impl Document {
                 end,
                 attrs,
                 op_id,
-            } => {
-                let elem_id = self
-                    .block_elem_id(block_id)
-                    .ok_or(EditError::BlockNotFound)?;
-                let Some(existing) = self.blocks.get_element(&elem_id) else {
-                    return Err(EditError::BlockNotFound);
-                };
-                let Some(block) = existing.value.as_ref() else {
-                    return Err(EditError::BlockNotFound);
-                };
-                let mut updated = block.clone();
-                updated
+            } => self.edit_block(block_id, |block| {
+                block
                     .marks
                     .set_mark(interval_id, kind, start, end, attrs, op_id);
-                self.blocks.update_value(elem_id, updated);
-                self.mark_source_block_dirty(block_id);
                 Ok(())
-            }
+            }),
             EditOp::RemoveMark {
                 block_id,
                 interval_id,
                 observed,
                 op_id,
-            } => {
-                let elem_id = self
-                    .block_elem_id(block_id)
-                    .ok_or(EditError::BlockNotFound)?;
-                let Some(existing) = self.blocks.get_element(&elem_id) else {
-                    return Err(EditError::BlockNotFound);
-                };
-                let Some(block) = existing.value.as_ref() else {
-                    return Err(EditError::BlockNotFound);
-                };
-                let mut updated = block.clone();
-                updated.marks.remove_mark(interval_id, observed, op_id);
-                self.blocks.update_value(elem_id, updated);
-                self.mark_source_block_dirty(block_id);
+            } => self.edit_block(block_id, |block| {
+                block.marks.remove_mark(interval_id, observed, op_id);
+                Ok(())
+            }),
+            EditOp::InsertCodeLine { .. } | EditOp::DeleteCodeLine { .. } => {
+                let block = self
+                    .find_block_by_id(block_id)
+                    .ok_or(EditError::BlockNotFound { block_id })?;
+                if !matches!(block.kind, BlockKind::CodeFence { .. }) {
+                    return Err(EditError::NotCodeFence { block_id });
+                }
+                // An edit against a superseded base is dropped, not rejected.
+                self.apply_code_line_op(&op);
                 Ok(())
             }
         }
@@ -2078,84 +2316,245 @@ impl Document {
     /// Remove (or range-split) a mark. Range split uses [`mark_ops::lower_remove_mark_range`].
     pub fn remove_mark(
         &mut self,
-        block_id: BlockId,
+        block: impl Into<BlockRef>,
         interval_id: MarkIntervalId,
         remove_id: OpId,
         observed: StateVector,
         remove_start: Anchor,
         remove_end: Anchor,
     ) -> Result<Vec<EditOp>, EditError> {
-        let elem_id = self
-            .block_elem_id(block_id)
-            .ok_or(EditError::BlockNotFound)?;
+        let block_id = self.resolve_block_ref(block.into())?;
+        self.check_block_edit(block_id, remove_id.peer)?;
+        self.edit_block(block_id, |updated| {
+            if updated.marks.interval(&interval_id).is_none() {
+                return Err(EditError::MarkNotFound {
+                    block_id,
+                    interval_id,
+                });
+            }
 
-        let Some(existing) = self.blocks.get_element(&elem_id) else {
-            return Err(EditError::BlockNotFound);
-        };
-        let Some(block) = existing.value.as_ref() else {
-            return Err(EditError::BlockNotFound);
-        };
+            // Anchors are ordered by visible position, so pass the text body's element order.
+            let element_order = block_text_seq(&updated.kind)
+                .map(paragraph_visible_ids)
+                .unwrap_or_default();
+            let (new_intervals, _removed) = mark_ops::lower_remove_mark_range(
+                &updated.marks,
+                interval_id,
+                remove_start,
+                remove_end,
+                OpId {
+                    counter: remove_id.counter.saturating_add(1),
+                    peer: remove_id.peer,
+                },
+                &element_order,
+            );
+
+            let mut ops = Vec::new();
+            updated
+                .marks
+                .remove_mark(interval_id, observed.clone(), remove_id);
+            ops.push(EditOp::RemoveMark {
+                block_id,
+                interval_id,
+                observed,
+                op_id: remove_id,
+            });
+
+            for interval in new_intervals {
+                let attrs: BTreeMap<String, MarkValue> = interval
+                    .attrs
+                    .iter()
+                    .map(|(k, reg)| (k.clone(), reg.get()))
+                    .collect();
+                updated.marks.set_mark(
+                    interval.id,
+                    interval.kind.clone(),
+                    interval.start,
+                    interval.end,
+                    attrs.clone(),
+                    interval.op_id,
+                );
+                ops.push(EditOp::SetMark {
+                    block_id,
+                    interval_id: interval.id,
+                    kind: interval.kind,
+                    start: interval.start,
+                    end: interval.end,
+                    attrs,
+                    op_id: interval.op_id,
+                });
+            }
+            Ok(ops)
+        })
+    }
 
-        if block.marks.interval(&interval_id).is_none() {
-            return Err(EditError::InvalidOffset);
+    /// Set a mark over a selection from `start` to `end` (block, grapheme offset)
+    /// that may span top-level blocks, as one interval per covered block. The
+    /// intervals take consecutive counters from `op_id`. Blocks without a text body
+    /// between the endpoints are skipped, and every block is checked before any
+    /// changes.
+    pub fn set_mark_range(
+        &mut self,
+        start: (BlockId, usize),
+        end: (BlockId, usize),
+        kind: MarkKind,
+        attrs: BTreeMap<String, MarkValue>,
+        op_id: OpId,
+    ) -> Result<Vec<EditOp>, EditError> {
+        let segments = self.mark_range_segments(start, end)?;
+        if segments.is_empty() {
+            let len = self
+                .find_block_by_id(start.0)
+                .and_then(|block| block_text_seq(&block.kind))
+                .map_or(0, Sequence::len_visible);
+            return Err(EditError::InvalidRange {
+                block_id: start.0,
+                start: start.1,
+                end: end.1,
+                len,
+            });
         }
+        let mut ops = Vec::with_capacity(segments.len());
+        for (counter, (block_id, range)) in (op_id.counter..).zip(segments) {
+            self.check_block_edit(block_id, op_id.peer)?;
+            let (start, end) = self.grapheme_range_to_anchors(block_id, range)?;
+            let id = OpId {
+                counter,
+                peer: op_id.peer,
+            };
+            ops.push(EditOp::SetMark {
+                block_id,
+                interval_id: id,
+                kind: kind.clone(),
+                start,
+                end,
+                attrs: attrs.clone(),
+                op_id: id,
+            });
+        }
+        for op in &ops {
+            self.raw_apply_op(op.clone(), false)?;
+        }
+        Ok(ops)
+    }
 
-        // Anchors are ordered by visible position, so pass the text body's element order.
-        let element_order = block_text_seq(&block.kind)
-            .map(paragraph_visible_ids)
-            .unwrap_or_default();
-        let (new_intervals, _removed) = mark_ops::lower_remove_mark_range(
-            &block.marks,
-            interval_id,
-            remove_start,
-            remove_end,
-            OpId {
-                counter: remove_id.counter.saturating_add(1),
-                peer: remove_id.peer,
-            },
-            &element_order,
-        );
+    /// Remove `kind` from a selection that may span top-level blocks, splitting
+    /// intervals that extend past it as [`Self::remove_mark`] does. Removals and
+    /// splits take increasing counters from `remove_id`; a selection without the
+    /// mark yields no ops.
+    pub fn remove_mark_range(
+        &mut self,
+        start: (BlockId, usize),
+        end: (BlockId, usize),
+        kind: &MarkKind,
+        remove_id: OpId,
+        observed: StateVector,
+    ) -> Result<Vec<EditOp>, EditError> {
+        let mut targets = Vec::new();
+        for (block_id, range) in self.mark_range_segments(start, end)? {
+            self.check_block_edit(block_id, remove_id.peer)?;
+            let block = self
+                .find_block_by_id(block_id)
+                .ok_or(EditError::BlockNotFound { block_id })?;
+            let order = block_text_seq(&block.kind)
+                .map(paragraph_visible_ids)
+                .unwrap_or_default();
+            let mut overlapping: Vec<MarkIntervalId> = block
+                .marks
+                .render_spans(&order, order.len())
+                .into_iter()
+                .filter(|span| span.start < range.end && range.start < span.end)
+                .flat_map(|span| span.marks)
+                .filter(|id| {
+                    block
+                        .marks
+                        .interval(id)
+                        .is_some_and(|interval| interval.kind == *kind)
+                })
+                .collect();
+            overlapping.sort();
+            overlapping.dedup();
+            let (from, to) = self.grapheme_range_to_anchors(block_id, range)?;
+            targets.extend(
+                overlapping
+                    .into_iter()
+                    .map(|interval_id| (block_id, interval_id, from, to)),
+            );
+        }
 
-        let mut updated = block.clone();
         let mut ops = Vec::new();
-        updated
-            .marks
-            .remove_mark(interval_id, observed.clone(), remove_id);
-        ops.push(EditOp::RemoveMark {
-            block_id,
-            interval_id,
-            observed,
-            op_id: remove_id,
-        });
+        let mut next = remove_id;
+        for (block_id, interval_id, from, to) in targets {
+            let lowered =
+                self.remove_mark(block_id, interval_id, next, observed.clone(), from, to)?;
+            let last = lowered
+                .iter()
+                .filter_map(|op| match op {
+                    EditOp::SetMark { op_id, .. } | EditOp::RemoveMark { op_id, .. } => {
+                        Some(op_id.counter)
+                    }
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(next.counter);
+            next.counter = last + 1;
+            ops.extend(lowered);
+        }
+        Ok(ops)
+    }
 
-        for interval in new_intervals {
-            let attrs: BTreeMap<String, MarkValue> = interval
-                .attrs
+    /// The non-empty per-block grapheme ranges a selection covers, in document order.
+    fn mark_range_segments(
+        &self,
+        start: (BlockId, usize),
+        end: (BlockId, usize),
+    ) -> Result<Vec<(BlockId, std::ops::Range<usize>)>, EditError> {
+        let blocks = self.blocks_in_order();
+        let position = |block_id: BlockId| {
+            blocks
                 .iter()
-                .map(|(k, reg)| (k.clone(), reg.get()))
-                .collect();
-            updated.marks.set_mark(
-                interval.id,
-                interval.kind.clone(),
-                interval.start,
-                interval.end,
-                attrs.clone(),
-                interval.op_id,
-            );
-            ops.push(EditOp::SetMark {
-                block_id,
-                interval_id: interval.id,
-                kind: interval.kind,
-                start: interval.start,
-                end: interval.end,
-                attrs,
-                op_id: interval.op_id,
+                .position(|block| block.id == block_id)
+                .ok_or(EditError::BlockNotFound { block_id })
+        };
+        let (first, last) = (position(start.0)?, position(end.0)?);
+        if last < first {
+            return Err(EditError::InvertedRange {
+                block_id: start.0,
+                end_block: end.0,
             });
         }
 
-        self.blocks.update_value(elem_id, updated);
-        self.mark_source_block_dirty(block_id);
-        Ok(ops)
+        let mut segments = Vec::new();
+        for (index, block) in blocks.iter().enumerate().take(last + 1).skip(first) {
+            let Some(text) = block_text_seq(&block.kind) else {
+                if index == first || index == last {
+                    return Err(EditError::NotTextBlock { block_id: block.id });
+                }
+                continue;
+            };
+            let len = text.len_visible();
+            let from = if index == first { start.1 } else { 0 };
+            let to = if index == last { end.1 } else { len };
+            if let Some(offset) = [from, to].into_iter().find(|&offset| offset > len) {
+                return Err(EditError::InvalidOffset {
+                    block_id: block.id,
+                    offset,
+                    len,
+                });
+            }
+            if from > to {
+                return Err(EditError::InvalidRange {
+                    block_id: block.id,
+                    start: from,
+                    end: to,
+                    len,
+                });
+            }
+            if from < to {
+                segments.push((block.id, from..to));
+            }
+        }
+        Ok(segments)
     }
 
     /// Render mark spans over a paragraph block using visible text-unit order.
@@ -2165,12 +2564,12 @@ impl Document {
     ) -> Result<Vec<crate::core::mark::Span>, EditError> {
         let block = self
             .find_block_by_id(block_id)
-            .ok_or(EditError::BlockNotFound)?;
+            .ok_or(EditError::BlockNotFound { block_id })?;
         let Some(text) = block_text_seq(&block.kind) else {
-            return Err(EditError::InvalidOffset);
+            return Err(EditError::NotTextBlock { block_id });
         };
-        let order = paragraph_visible_ids(text);
-        Ok(block.marks.render_spans(&order, order.len()))
+        let offsets = text.offset_map();
+        Ok(block.marks.render_spans_in(&offsets, offsets.len()))
     }
 
     /// Convert a non-empty half-open grapheme range to stable unit anchors.
@@ -2181,11 +2580,16 @@ impl Document {
     ) -> Result<(Anchor, Anchor), EditError> {
         let block = self
             .find_block_by_id(block_id)
-            .ok_or(EditError::BlockNotFound)?;
-        let text = block_text_seq(&block.kind).ok_or(EditError::InvalidOffset)?;
+            .ok_or(EditError::BlockNotFound { block_id })?;
+        let text = block_text_seq(&block.kind).ok_or(EditError::NotTextBlock { block_id })?;
         let ids = paragraph_visible_ids(text);
         if range.start >= range.end || range.end > ids.len() {
-            return Err(EditError::InvalidOffset);
+            return Err(EditError::InvalidRange {
+                block_id,
+                start: range.start,
+                end: range.end,
+                len: ids.len(),
+            });
         }
         Ok((
             Anchor {
@@ -2207,19 +2611,24 @@ impl Document {
     ) -> Result<(Anchor, Anchor), EditError> {
         let block = self
             .find_block_by_id(block_id)
-            .ok_or(EditError::BlockNotFound)?;
-        let text = block_text_seq(&block.kind).ok_or(EditError::InvalidOffset)?;
+            .ok_or(EditError::BlockNotFound { block_id })?;
+        let text = block_text_seq(&block.kind).ok_or(EditError::NotTextBlock { block_id })?;
         let visible = paragraph_visible_string(text);
         if range.start >= range.end || range.end > visible.len() {
-            return Err(EditError::InvalidOffset);
+            return Err(EditError::InvalidRange {
+                block_id,
+                start: range.start,
+                end: range.end,
+                len: visible.len(),
+            });
         }
-        if !is_grapheme_boundary(&visible, range.start)
-            || !is_grapheme_boundary(&visible, range.end)
-        {
-            return Err(EditError::InvalidGraphemeBoundary);
+        for offset in [range.start, range.end] {
+            if !self.segmentation.is_boundary(&visible, offset) {
+                return Err(EditError::InvalidGraphemeBoundary { block_id, offset });
+            }
         }
-        let start = visible[..range.start].graphemes(true).count();
-        let end = visible[..range.end].graphemes(true).count();
+        let start = self.segmentation.count(&visible[..range.start]);
+        let end = self.segmentation.count(&visible[..range.end]);
         self.grapheme_range_to_anchors(block_id, start..end)
     }
 
@@ -2227,6 +2636,10 @@ impl Document {
         self.frontmatter.as_ref()?.get(key)
     }
 
+    pub fn frontmatter_value(&self, key: &str) -> Option<FrontmatterValue> {
+        self.frontmatter.as_ref()?.value(key)
+    }
+
     pub fn set_frontmatter_field(
         &mut self,
         key: String,
@@ -2238,15 +2651,142 @@ impl Document {
             .set(key, value, op_id)
     }
 
+    /// Merge a remote frontmatter write; see [`Frontmatter::merge`].
+    pub fn merge_frontmatter_field(
+        &mut self,
+        key: String,
+        value: Option<String>,
+        op_id: OpId,
+        observed: &StateVector,
+    ) -> Result<(), FrontmatterError> {
+        self.frontmatter
+            .get_or_insert_with(Frontmatter::empty)
+            .merge(key, value, op_id, observed)
+    }
+
+    /// Check (`done`) or uncheck the task list item `item_id`, making it a task if it is
+    /// a plain item, as a write that had seen `observed`. Each item's checkbox is its own
+    /// last-writer-wins register, so toggles of different items never conflict.
+    ///
+    /// A write that had seen the item's last one replaces it; a concurrent one wins only
+    /// if its id supersedes. Returns false when the write does not take effect.
+    pub fn set_task_state(
+        &mut self,
+        item_id: BlockId,
+        done: bool,
+        op_id: OpId,
+        observed: &StateVector,
+    ) -> Result<bool, EditError> {
+        if self.find_list_item_by_id(item_id).is_none() {
+            return Err(EditError::BlockNotFound { block_id: item_id });
+        }
+        let task = if done {
+            TaskState::Checked
+        } else {
+            TaskState::Unchecked
+        };
+        Ok(self.set_list_item_task(item_id, Some(task), op_id, observed.clone()))
+    }
+
     pub fn serialize(&self, mode: EquivalenceMode) -> String {
         let config = SerializeConfig {
             equivalence: mode,
             prefer_raw_source: true,
+            whitespace: None,
         };
         self.serialize_with_config(&config)
     }
 
+    /// Check every active mark, nested blocks included, in document order.
+    ///
+    /// Remote operations are applied whatever their attributes, so run this
+    /// after merging to catch marks a peer added without the expected keys.
+    pub fn validate_marks(&self, schema: &MarkSchema) -> Result<(), InvalidMark> {
+        let mut blocks = Vec::new();
+        collect_blocks(&self.blocks, &mut blocks);
+        for block in blocks {
+            for interval in block.marks.iter_active_intervals() {
+                schema
+                    .validate_interval(interval)
+                    .map_err(|error| InvalidMark {
+                        block_id: block.id,
+                        interval_id: interval.id,
+                        error,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Active [`MarkKind::Link`] marks with their blocks, in document order.
+    pub fn links(&self) -> Vec<(BlockId, &MarkInterval)> {
+        let mut blocks = Vec::new();
+        collect_blocks(&self.blocks, &mut blocks);
+        blocks
+            .into_iter()
+            .flat_map(|block| {
+                block
+                    .marks
+                    .iter_active_intervals()
+                    .filter(|interval| interval.kind == MarkKind::Link)
+                    .map(move |interval| (block.id, interval))
+            })
+            .collect()
+    }
+
+    /// Run [`MarkSet::gc`] on every live block; returns how many removed intervals
+    /// were dropped.
+    pub fn gc_marks(&mut self, min_state_vector: &StateVector) -> usize {
+        gc_block_marks(&mut self.blocks, min_state_vector)
+    }
+
+    /// Drop text tombstones inserted at or below `floor` that no surviving unit
+    /// or mark is anchored on; see [`Sequence::drop_tombstones`]. Block, list
+    /// item and table tombstones are kept. Returns how many units were dropped.
+    pub fn drop_text_tombstones(&mut self, floor: &StateVector) -> usize {
+        drop_block_tombstones(&mut self.blocks, floor)
+    }
+
+    /// [`Self::serialize`], refusing a document with a mark `schema` rejects.
+    pub fn serialize_validated(
+        &self,
+        mode: EquivalenceMode,
+        schema: &MarkSchema,
+    ) -> Result<String, InvalidMark> {
+        self.validate_marks(schema)?;
+        Ok(self.serialize(mode))
+    }
+
     pub fn serialize_with_config(&self, config: &SerializeConfig) -> String {
+        self.serialize_with(config, &registry::DEFAULT_REGISTRY)
+    }
+
+    /// [`Self::serialize_with_config`], rendering raw blocks and marks through
+    /// `registry`. Blocks kept verbatim from their source are not re-rendered.
+    pub fn serialize_with(
+        &self,
+        config: &SerializeConfig,
+        registry: &SerializerRegistry,
+    ) -> String {
+        let output = self.render_with(config, registry);
+        let trailing = config
+            .whitespace
+            .map_or(TrailingSpaces::Strip, |policy| policy.trailing_spaces);
+        let output = match config.equivalence {
+            EquivalenceMode::Exact => output,
+            EquivalenceMode::Structural => normalize_structural(&output, trailing),
+        };
+        match &config.whitespace {
+            Some(policy) => {
+                policy.apply(&output, self.source.as_ref().map(DocumentSource::original))
+            }
+            None => output,
+        }
+    }
+
+    /// The document as Markdown before structural normalization and any
+    /// whitespace policy.
+    fn render_with(&self, config: &SerializeConfig, registry: &SerializerRegistry) -> String {
         if let EquivalenceMode::Exact = config.equivalence
             && config.prefer_raw_source
             && let Some(source) = &self.source
@@ -2256,7 +2796,7 @@ impl Document {
                 .as_ref()
                 .filter(|frontmatter| frontmatter.is_dirty())
                 .map(Frontmatter::render);
-            return source.render_with_frontmatter(&self.blocks, replacement.as_deref());
+            return source.render_with_frontmatter(&self.blocks, replacement.as_deref(), registry);
         }
 
         let mut output = String::new();
@@ -2266,18 +2806,38 @@ impl Document {
             output.push_str("\n---\n\n");
         }
 
-        let blocks = self.blocks_in_order();
-        for (index, block) in blocks.iter().enumerate() {
-            if index > 0 {
-                output.push_str("\n\n");
+        let visible = self.blocks.iter_all().filter_map(|element| {
+            let block = element.value.as_ref()?;
+            Some((element.id, block))
+        });
+        if !registry.is_empty() {
+            // The render cache holds default renderings only.
+            let rendered: Vec<_> = visible
+                .map(|(_, block)| serialize::serialize_block_with(block, registry))
+                .collect();
+            output.push_str(&rendered.join("\n\n"));
+        } else {
+            // Only blocks mutated since the last call are rendered again; entries of
+            // blocks no longer visible are dropped.
+            let mut cache = self
+                .render_cache
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let mut previous = std::mem::take(&mut *cache);
+            for (index, (elem_id, block)) in visible.enumerate() {
+                if index > 0 {
+                    output.push_str("\n\n");
+                }
+                let version = self.blocks.value_version(elem_id);
+                let rendered = match previous.remove(&elem_id) {
+                    Some((cached, rendered)) if cached == version => rendered,
+                    _ => serialize_block(block),
+                };
+                output.push_str(&rendered);
+                cache.insert(elem_id, (version, rendered));
             }
-            output.push_str(&serialize_block(block));
-        }
-
-        match config.equivalence {
-            EquivalenceMode::Exact => output,
-            EquivalenceMode::Structural => normalize_structural(&output),
         }
+        output
     }
 }
 
@@ -2288,6 +2848,9 @@ impl Default for Document {
 }
 
 impl Block {
+    /// A block whose id is [`block_id_from_op`]`(insert_id)`: no randomness, so
+    /// the same ops always build the same ids. Parsers choose the op ids, see
+    /// [`Parser::parse_with_ids`] and [`Parser::parse_seeded`].
     pub fn new(kind: BlockKind, insert_id: OpId) -> Self {
         Self {
             id: block_id_from_op(insert_id),
@@ -2298,6 +2861,39 @@ impl Block {
             marks: MarkSet::new(),
         }
     }
+
+    /// Insert `text` into this block's own text body, without ACL checks.
+    pub(crate) fn insert_text(
+        &mut self,
+        grapheme_offset: usize,
+        text: &str,
+        op_id: OpId,
+        rules: SegmentationRules,
+    ) -> Result<InsertTextRun, EditError> {
+        let block_id = self.id;
+        let Some(body) = block_text_seq_mut(&mut self.kind) else {
+            return Err(EditError::NotTextBlock { block_id });
+        };
+
+        let visible = paragraph_visible_string(body);
+        let out_of_range = || EditError::InvalidOffset {
+            block_id,
+            offset: grapheme_offset,
+            len: rules.count(&visible),
+        };
+        let byte_offset = rules
+            .byte_offset(&visible, grapheme_offset)
+            .ok_or_else(out_of_range)?;
+        insert_graphemes_with(body, grapheme_offset, text, op_id, rules)
+            .ok_or_else(out_of_range)?;
+        Ok(InsertTextRun {
+            block_id,
+            grapheme_offset,
+            byte_offset,
+            text: text.to_string(),
+            op_id,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -2454,6 +3050,29 @@ mod tests {
         } else {
             panic!("Expected paragraph block");
         }
+
+        let past_end = OpId {
+            counter: 200,
+            peer: 0,
+        };
+        let error = doc.insert_text(block_id, 9, "Y", past_end).unwrap_err();
+        assert_eq!(
+            error,
+            EditError::InvalidOffset {
+                block_id,
+                offset: 9,
+                len: 4,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("offset 9 is past the end of block {block_id} (length 4)")
+        );
+        assert!(!error.is_retryable());
+        let missing = block_id_from_op(past_end);
+        let error = doc.insert_text(missing, 0, "Y", past_end).unwrap_err();
+        assert_eq!(error.block_id(), missing);
+        assert!(error.is_retryable());
     }
 
     #[test]
@@ -2621,7 +3240,10 @@ mod tests {
 
         assert_eq!(
             doc.raw_apply_op(op.clone(), true),
-            Err(EditError::InvalidGraphemeBoundary)
+            Err(EditError::InvalidGraphemeBoundary {
+                block_id,
+                offset: bad_offset,
+            })
         );
         assert!(doc.raw_apply_op(op, false).is_ok());
     }
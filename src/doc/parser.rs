@@ -1,10 +1,228 @@
 use super::*;
+use crate::core::{ClockError, OpIdRange, PeerId};
+use std::collections::HashSet;
 
 pub struct Parser;
 
+/// Dialect extensions recognized on top of CommonMark.
+///
+/// The default matches [`Parser::parse`]: GFM tables and task lists on, the
+/// other extensions off. Syntax of a disabled extension stays literal text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Pipe tables with a delimiter row, modeled as [`BlockKind::Table`].
+    pub gfm_tables: bool,
+    /// `- [ ]` / `- [x]` list items, modeled as [`ListItem::task`].
+    pub task_lists: bool,
+    /// `~~text~~`, modeled as [`MarkKind::Strikethrough`].
+    pub strikethrough: bool,
+    /// `[^label]: note` definitions, kept verbatim as [`BlockKind::RawBlock`]
+    /// with their indented continuation lines; `[^label]` references stay literal.
+    pub footnotes: bool,
+    /// `$$` display math, modeled as a [`BlockKind::CodeFence`] with
+    /// [`FenceMarker::Dollar`].
+    pub math_blocks: bool,
+}
+
+impl ParserOptions {
+    /// Plain CommonMark: every extension off.
+    pub fn commonmark() -> Self {
+        Self {
+            gfm_tables: false,
+            task_lists: false,
+            strikethrough: false,
+            footnotes: false,
+            math_blocks: false,
+        }
+    }
+
+    /// GitHub Flavored Markdown: tables, task lists, and strikethrough.
+    pub fn gfm() -> Self {
+        Self {
+            strikethrough: true,
+            ..Self::default()
+        }
+    }
+
+    /// Pandoc Markdown: every supported extension on.
+    pub fn pandoc() -> Self {
+        Self {
+            gfm_tables: true,
+            task_lists: true,
+            strikethrough: true,
+            footnotes: true,
+            math_blocks: true,
+        }
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            gfm_tables: true,
+            task_lists: true,
+            ..Self::commonmark()
+        }
+    }
+}
+
+/// Bounds on what one parse may build, for input that can't be trusted.
+///
+/// [`Parser::parse`] and friends run unlimited; [`Parser::parse_with_limits`]
+/// stops at the first bound crossed with [`ParseError::LimitExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    /// Deepest container nesting: each block quote or list level adds one.
+    pub max_nesting: usize,
+    /// Most blocks in the document, nested blocks and list items included.
+    pub max_block_count: usize,
+    /// Longest line, in bytes.
+    pub max_line_len: usize,
+    pub max_input_bytes: usize,
+}
+
+impl ParserLimits {
+    /// No bound at all.
+    pub fn unlimited() -> Self {
+        Self {
+            max_nesting: usize::MAX,
+            max_block_count: usize::MAX,
+            max_line_len: usize::MAX,
+            max_input_bytes: usize::MAX,
+        }
+    }
+
+    fn check(&self, limit: ParseLimit, value: usize) -> Result<(), ParseError> {
+        let max = match limit {
+            ParseLimit::Nesting => self.max_nesting,
+            ParseLimit::BlockCount => self.max_block_count,
+            ParseLimit::LineLength => self.max_line_len,
+            ParseLimit::InputBytes => self.max_input_bytes,
+        };
+        if value > max {
+            return Err(ParseError::LimitExceeded { limit, max });
+        }
+        Ok(())
+    }
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_nesting: 64,
+            max_block_count: 1_000_000,
+            max_line_len: 1024 * 1024,
+            max_input_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The [`ParserLimits`] bound a parse crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseLimit {
+    Nesting,
+    BlockCount,
+    LineLength,
+    InputBytes,
+}
+
+impl std::fmt::Display for ParseLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ParseLimit::Nesting => "nesting depth",
+            ParseLimit::BlockCount => "block count",
+            ParseLimit::LineLength => "line length",
+            ParseLimit::InputBytes => "input size",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("{limit} exceeds the parser limit of {max}")]
+    LimitExceeded { limit: ParseLimit, max: usize },
+}
+
 impl Parser {
     pub fn parse(text: &str) -> Document {
+        Self::parse_with_options(text, &ParserOptions::default())
+    }
+
+    pub fn parse_with_options(text: &str, options: &ParserOptions) -> Document {
+        Self::parse_unlimited(text, options, &mut IdAllocator::sequential(0, 1))
+    }
+
+    /// Parse untrusted input, failing once it crosses one of `limits`.
+    pub fn parse_with_limits(
+        text: &str,
+        options: &ParserOptions,
+        limits: &ParserLimits,
+    ) -> Result<Document, ParseError> {
+        Self::parse_with_allocator(text, options, limits, &mut IdAllocator::sequential(0, 1))
+    }
+
+    /// Parse with OpIds taken from `peer`, counting up from `*counter`; on return
+    /// `*counter` is the next unused counter. Parsing into a peer's own id space
+    /// keeps imported blocks from colliding with another peer's import.
+    pub fn parse_with_ids(text: &str, peer: PeerId, counter: &mut u64) -> Document {
+        let mut ids = IdAllocator::sequential(peer, *counter);
+        let document = Self::parse_unlimited(text, &ParserOptions::default(), &mut ids);
+        *counter = ids.counter;
+        document
+    }
+
+    /// Parse with OpIds taken from a reservation (see [`PeerClock::reserve`]), which
+    /// keeps an import from reusing counters handed to live edits meanwhile. On
+    /// success the used ids are consumed from `range`; if it is too small, it is left
+    /// untouched.
+    ///
+    /// [`PeerClock::reserve`]: crate::core::PeerClock::reserve
+    pub fn parse_in_range(text: &str, range: &mut OpIdRange) -> Result<Document, ClockError> {
+        let mut counter = range.start();
+        let document = Self::parse_with_ids(text, range.peer(), &mut counter);
+        range.split_off(counter - range.start())?;
+        Ok(document)
+    }
+
+    /// Parse with OpIds derived from `seed` and each top-level block's source, so
+    /// peers importing the same file with the same seed produce identical ids and
+    /// converge, and a block keeps its ids when blocks around it change.
+    ///
+    /// Every id has `seed` as its peer, so use a seed no live peer writes under.
+    pub fn parse_seeded(text: &str, seed: u64) -> Document {
+        Self::parse_unlimited(
+            text,
+            &ParserOptions::default(),
+            &mut IdAllocator::seeded(seed),
+        )
+    }
+
+    fn parse_unlimited(text: &str, options: &ParserOptions, ids: &mut IdAllocator) -> Document {
+        Self::parse_with_allocator(text, options, &ParserLimits::unlimited(), ids)
+            .expect("an unlimited parse never exceeds a limit")
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            bytes = text.len(),
+            blocks = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        )
+    )]
+    fn parse_with_allocator(
+        text: &str,
+        options: &ParserOptions,
+        limits: &ParserLimits,
+        ids: &mut IdAllocator,
+    ) -> Result<Document, ParseError> {
+        let started = std::time::Instant::now();
+        limits.check(ParseLimit::InputBytes, text.len())?;
         let source_lines = source_lines(text);
+        let longest = source_lines.iter().map(|line| line.text.len()).max();
+        limits.check(ParseLimit::LineLength, longest.unwrap_or(0))?;
         let lines: Vec<&str> = source_lines.iter().map(|line| line.text).collect();
         let mut frontmatter = None;
         let mut start_index = 0;
@@ -27,15 +245,14 @@ impl Parser {
             }
         }
 
-        let mut counter = 1u64;
-        let mut blocks = Vec::new();
         let mut line_spans = Vec::new();
-        parse_blocks_with_spans(
+        let blocks = parse_blocks(
             &lines[start_index..],
-            &mut counter,
-            &mut blocks,
-            Some(&mut line_spans),
-        );
+            ids,
+            options,
+            &mut Budget::new(limits),
+            &mut line_spans,
+        )?;
         let byte_spans = line_spans
             .into_iter()
             .map(|(id, span)| {
@@ -49,6 +266,8 @@ impl Parser {
             })
             .collect();
         let source = DocumentSource::new(text.to_string(), byte_spans, &blocks);
+        let span = tracing::Span::current();
+        span.record("blocks", blocks.len());
 
         let sequence = Sequence::from_ordered(
             blocks
@@ -57,19 +276,23 @@ impl Parser {
                 .collect(),
         );
 
-        Document {
+        span.record("elapsed_us", started.elapsed().as_micros() as u64);
+        Ok(Document {
             frontmatter,
             blocks: IndexedBlocks::new(sequence),
+            acls: BTreeMap::new(),
+            code_lines: BTreeMap::new(),
             source: Some(source),
+            pending_edits: PendingEdits::new(),
+            segmentation: SegmentationRules::default(),
             block_index: RwLock::new(None),
-        }
+            render_cache: Mutex::default(),
+            span_cache: Mutex::default(),
+            hash_cache: Mutex::default(),
+        })
     }
 }
 
-fn parse_blocks(lines: &[&str], counter: &mut u64, out: &mut Vec<Block>) {
-    parse_blocks_with_spans(lines, counter, out, None);
-}
-
 #[derive(Debug, Clone, Copy)]
 struct LineSpan {
     start: usize,
@@ -108,230 +331,355 @@ fn source_lines(text: &str) -> Vec<SourceLine<'_>> {
         .collect()
 }
 
-fn parse_blocks_with_spans(
-    lines: &[&str],
-    counter: &mut u64,
-    out: &mut Vec<Block>,
-    mut spans: Option<&mut Vec<(BlockId, LineSpan)>>,
-) {
-    let mut index = 0;
-    while index < lines.len() {
-        let line = lines[index];
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            index += 1;
-            continue;
-        }
+/// Limit bookkeeping for one parse.
+struct Budget<'l> {
+    limits: &'l ParserLimits,
+    blocks: usize,
+}
 
-        if let Some((style, code_info)) = parse_fence_open(trimmed) {
-            let info = code_info.trim();
-            let mut contents: Vec<&str> = Vec::new();
-            let mut end_index = index + 1;
-            while end_index < lines.len() {
-                if is_fence_close(lines[end_index].trim(), style) {
-                    break;
-                }
-                contents.push(lines[end_index]);
-                end_index += 1;
-            }
-            let text = contents.join("\n");
-            let block = Block::new(
-                BlockKind::CodeFence {
-                    style,
-                    info: if info.is_empty() {
-                        None
-                    } else {
-                        Some(info.to_string())
-                    },
-                    text,
-                },
-                next_op_id(counter),
-            );
-            let next = (end_index + 1).min(lines.len());
-            record_span(&mut spans, block.id, index, next);
-            out.push(block);
-            index = next;
-            continue;
-        }
+impl<'l> Budget<'l> {
+    fn new(limits: &'l ParserLimits) -> Self {
+        Self { limits, blocks: 0 }
+    }
 
-        if trimmed.starts_with('>') {
-            let mut quote_lines: Vec<&str> = Vec::new();
-            let mut end_index = index;
-            while end_index < lines.len() {
-                let current = lines[end_index];
-                let current_trimmed = current.trim();
-                if !current_trimmed.starts_with('>') {
-                    break;
-                }
-                let stripped = current_trimmed.trim_start_matches('>').trim_start();
-                quote_lines.push(stripped);
-                end_index += 1;
-            }
-            let mut child_blocks = Vec::new();
-            parse_blocks(&quote_lines, counter, &mut child_blocks);
+    fn count_block(&mut self) -> Result<(), ParseError> {
+        self.blocks += 1;
+        self.limits.check(ParseLimit::BlockCount, self.blocks)
+    }
+
+    fn enter(&self, depth: usize) -> Result<(), ParseError> {
+        self.limits.check(ParseLimit::Nesting, depth)
+    }
+}
+
+/// A run of lines being parsed into blocks: the document body, or the stripped
+/// lines of a block quote whose children are still being parsed.
+struct BlockFrame<'a> {
+    lines: Vec<&'a str>,
+    index: usize,
+    blocks: Vec<Block>,
+    /// The quote's lines in the enclosing frame.
+    span: LineSpan,
+}
+
+/// Top-level blocks with their line spans. Block quotes nest on an explicit stack
+/// rather than the call stack. A seeded allocator restarts at each top-level
+/// block's own counter slot, keyed by the lines a dry run finds the block spans.
+fn parse_blocks(
+    lines: &[&str],
+    ids: &mut IdAllocator,
+    options: &ParserOptions,
+    budget: &mut Budget,
+    spans: &mut Vec<(BlockId, LineSpan)>,
+) -> Result<Vec<Block>, ParseError> {
+    let mut stack = vec![BlockFrame {
+        lines: lines.to_vec(),
+        index: 0,
+        blocks: Vec::new(),
+        span: LineSpan {
+            start: 0,
+            end: lines.len(),
+        },
+    }];
+    loop {
+        let depth = stack.len() - 1;
+        let frame = stack.last_mut().expect("the body frame is popped last");
+        let index = frame.index;
+        if index >= frame.lines.len() {
+            let done = stack.pop().expect("frame was just inspected");
+            let Some(parent) = stack.last_mut() else {
+                return Ok(done.blocks);
+            };
             let children = Sequence::from_ordered(
-                child_blocks
+                done.blocks
                     .into_iter()
                     .map(|child| (child.elem_id, child))
                     .collect(),
             );
-            let block = Block::new(BlockKind::BlockQuote { children }, next_op_id(counter));
-            record_span(&mut spans, block.id, index, end_index);
-            out.push(block);
-            index = end_index;
-            continue;
-        }
-
-        if trimmed.starts_with(":::") {
-            let mut raw_lines: Vec<&str> = Vec::new();
-            let mut end_index = index;
-            while end_index < lines.len() {
-                if lines[end_index].trim().is_empty() && end_index > index {
-                    break;
-                }
-                raw_lines.push(lines[end_index]);
-                end_index += 1;
+            let block = Block::new(BlockKind::BlockQuote { children }, ids.next_id());
+            if depth == 1 {
+                spans.push((block.id, done.span));
             }
-            let block = Block::new(
-                BlockKind::RawBlock {
-                    raw: raw_lines.join("\n"),
-                },
-                next_op_id(counter),
-            );
-            record_span(&mut spans, block.id, index, end_index);
-            out.push(block);
-            index = end_index;
+            parent.blocks.push(block);
             continue;
         }
 
-        if index + 1 < lines.len()
-            && let Some(columns) = parse_table_delimiter(lines[index + 1])
-            && let Some(header) = parse_table_cells(line)
-            && header.len() == columns.len()
-        {
-            let elem_id = next_op_id(counter);
-            let mut table = Table::new(block_id_from_op(elem_id), elem_id, elem_id);
-            let mut after_column = None;
-            for (column, header) in columns.into_iter().zip(header) {
-                let column_id = next_op_id(counter);
-                table.insert_column(after_column, column.alignment, header, column_id);
-                after_column = Some(column_id);
+        if depth == 0 && ids.is_seeded() && !frame.lines[index].trim().is_empty() {
+            let step = parse_block_at(
+                &frame.lines,
+                index,
+                &mut IdAllocator::sequential(0, 1),
+                options,
+                &mut Budget::new(budget.limits),
+                depth,
+                &mut Vec::new(),
+            )?;
+            let next = match step {
+                BlockStep::Next(next) => next,
+                BlockStep::Quote { end, .. } => end,
+            };
+            ids.begin_block(&frame.lines[index..next]);
+        }
+        let before = frame.blocks.len();
+        match parse_block_at(
+            &frame.lines,
+            index,
+            ids,
+            options,
+            budget,
+            depth,
+            &mut frame.blocks,
+        )? {
+            BlockStep::Next(next) => {
+                if let Some(block) = frame.blocks.get(before) {
+                    budget.count_block()?;
+                    if depth == 0 {
+                        spans.push((
+                            block.id,
+                            LineSpan {
+                                start: index,
+                                end: next,
+                            },
+                        ));
+                    }
+                }
+                frame.index = next;
             }
-            let column_ids: Vec<_> = table
-                .columns_in_order()
-                .into_iter()
-                .map(|column| column.id)
-                .collect();
-            let mut after = None;
-            let mut end_index = index + 2;
-            while end_index < lines.len() {
-                let Some(cells) = parse_table_cells(lines[end_index]) else {
-                    break;
-                };
-                let row_id = next_op_id(counter);
-                table.insert_row(
-                    after,
-                    column_ids.iter().copied().zip(cells).collect(),
-                    row_id,
-                );
-                after = Some(row_id);
-                end_index += 1;
+            BlockStep::Quote { lines, end } => {
+                budget.count_block()?;
+                budget.enter(depth + 1)?;
+                frame.index = end;
+                stack.push(BlockFrame {
+                    lines,
+                    index: 0,
+                    blocks: Vec::new(),
+                    span: LineSpan { start: index, end },
+                });
             }
-            let block = Block::new(
-                BlockKind::Table {
-                    table: Box::new(table),
-                },
-                elem_id,
-            );
-            record_span(&mut spans, block.id, index, end_index);
-            out.push(block);
-            index = end_index;
-            continue;
         }
+    }
+}
 
-        // ATX heading: # .. ######
-        if let Some((level, title)) = parse_atx_heading(trimmed) {
-            let elem_id = next_op_id(counter);
-            let block = inline::parse_text_block(
-                |text| BlockKind::Heading { level, text },
-                title,
-                elem_id,
-                counter,
-            );
-            record_span(&mut spans, block.id, index, index + 1);
-            out.push(block);
-            index += 1;
-            continue;
-        }
+/// What [`parse_block_at`] found.
+enum BlockStep<'a> {
+    /// The block, if the line was not blank, is pushed; the next one starts here.
+    Next(usize),
+    /// A block quote through line `end`, whose stripped `lines` hold its children.
+    Quote { lines: Vec<&'a str>, end: usize },
+}
 
-        // Unordered / ordered list
-        if is_list_start(trimmed) {
-            let (list_block, next) = parse_list(lines, index, counter, indent_of(line));
-            record_span(&mut spans, list_block.id, index, next);
-            out.push(list_block);
-            index = next;
-            continue;
-        }
+/// Parse the block starting at `index` (skipping one blank line) at nesting `depth`.
+/// A block quote is only delimited; its children are left to the caller.
+fn parse_block_at<'a>(
+    lines: &[&'a str],
+    index: usize,
+    ids: &mut IdAllocator,
+    options: &ParserOptions,
+    budget: &mut Budget,
+    depth: usize,
+    out: &mut Vec<Block>,
+) -> Result<BlockStep<'a>, ParseError> {
+    let line = lines[index];
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Ok(BlockStep::Next(index + 1));
+    }
 
-        // Setext heading: title line + === or ---
-        if index + 1 < lines.len()
-            && let Some(level) = parse_setext_underline(lines[index + 1].trim())
-        {
-            let title = trimmed;
-            let elem_id = next_op_id(counter);
-            let block = inline::parse_text_block(
-                |text| BlockKind::Heading { level, text },
-                title,
-                elem_id,
-                counter,
-            );
-            record_span(&mut spans, block.id, index, index + 2);
-            out.push(block);
-            index += 2;
-            continue;
+    if let Some((style, code_info, fence_indent)) = fence_open_line(line, options) {
+        let info = code_info.trim();
+        let mut contents: Vec<&str> = Vec::new();
+        let mut end_index = index + 1;
+        while end_index < lines.len() {
+            if is_fence_close_line(lines[end_index], style) {
+                break;
+            }
+            contents.push(strip_fence_indent(lines[end_index], fence_indent));
+            end_index += 1;
         }
+        let text = contents.join("\n");
+        let block = Block::new(
+            BlockKind::CodeFence {
+                style,
+                info: if info.is_empty() {
+                    None
+                } else {
+                    Some(info.to_string())
+                },
+                text,
+            },
+            ids.next_id(),
+        );
+        let next = (end_index + 1).min(lines.len());
+        out.push(block);
+        return Ok(BlockStep::Next(next));
+    }
 
-        let mut paragraph_lines: Vec<&str> = Vec::new();
+    if trimmed.starts_with('>') {
+        let mut quote_lines: Vec<&str> = Vec::new();
         let mut end_index = index;
         while end_index < lines.len() {
             let current = lines[end_index];
             let current_trimmed = current.trim();
-            if current_trimmed.is_empty()
-                || parse_fence_open(current_trimmed).is_some()
-                || current_trimmed.starts_with('>')
-                || current_trimmed.starts_with(":::")
-                || parse_atx_heading(current_trimmed).is_some()
-                || is_list_start(current_trimmed)
-            {
+            if !current_trimmed.starts_with('>') {
                 break;
             }
-            // Stop before a setext underline that would apply to a single prior line only
-            // (already handled above when starting a new block).
-            paragraph_lines.push(current);
+            let stripped = current_trimmed.trim_start_matches('>').trim_start();
+            quote_lines.push(stripped);
             end_index += 1;
         }
-        let elem_id = next_op_id(counter);
+        return Ok(BlockStep::Quote {
+            lines: quote_lines,
+            end: end_index,
+        });
+    }
+
+    if trimmed.starts_with(":::") {
+        let mut raw_lines: Vec<&str> = Vec::new();
+        let mut end_index = index;
+        while end_index < lines.len() {
+            if lines[end_index].trim().is_empty() && end_index > index {
+                break;
+            }
+            raw_lines.push(lines[end_index]);
+            end_index += 1;
+        }
+        let block = Block::new(
+            BlockKind::RawBlock {
+                raw: raw_lines.join("\n"),
+            },
+            ids.next_id(),
+        );
+        out.push(block);
+        return Ok(BlockStep::Next(end_index));
+    }
+
+    if options.footnotes && is_footnote_definition(line) {
+        let mut end_index = index + 1;
+        while let Some(next) = footnote_continuation_end(lines, end_index) {
+            end_index = next;
+        }
+        let block = Block::new(
+            BlockKind::RawBlock {
+                raw: lines[index..end_index].join("\n"),
+            },
+            ids.next_id(),
+        );
+        out.push(block);
+        return Ok(BlockStep::Next(end_index));
+    }
+
+    if options.gfm_tables
+        && index + 1 < lines.len()
+        && let Some(columns) = parse_table_delimiter(lines[index + 1])
+        && let Some(header) = parse_table_cells(line)
+        && header.len() == columns.len()
+    {
+        let elem_id = ids.next_id();
+        let mut table = Table::new(block_id_from_op(elem_id), elem_id, elem_id);
+        let mut after_column = None;
+        for (column, header) in columns.into_iter().zip(header) {
+            let column_id = ids.next_id();
+            table.insert_column(after_column, column.alignment, header, column_id);
+            after_column = Some(column_id);
+        }
+        let column_ids: Vec<_> = table
+            .columns_in_order()
+            .into_iter()
+            .map(|column| column.id)
+            .collect();
+        let mut after = None;
+        let mut end_index = index + 2;
+        while end_index < lines.len() {
+            let Some(cells) = parse_table_cells(lines[end_index]) else {
+                break;
+            };
+            let row_id = ids.next_id();
+            table.insert_row(
+                after,
+                column_ids.iter().copied().zip(cells).collect(),
+                row_id,
+            );
+            after = Some(row_id);
+            end_index += 1;
+        }
+        let block = Block::new(
+            BlockKind::Table {
+                table: Box::new(table),
+            },
+            elem_id,
+        );
+        out.push(block);
+        return Ok(BlockStep::Next(end_index));
+    }
+
+    // ATX heading: # .. ######
+    if let Some((level, title)) = parse_atx_heading(trimmed) {
+        let elem_id = ids.next_id();
         let block = inline::parse_text_block(
-            |text| BlockKind::Paragraph { text },
-            &paragraph_lines.join("\n"),
+            |text| BlockKind::Heading { level, text },
+            title,
             elem_id,
-            counter,
+            ids,
+            options,
         );
-        record_span(&mut spans, block.id, index, end_index);
         out.push(block);
-        index = end_index;
+        return Ok(BlockStep::Next(index + 1));
     }
-}
 
-fn record_span(
-    spans: &mut Option<&mut Vec<(BlockId, LineSpan)>>,
-    id: BlockId,
-    start: usize,
-    end: usize,
-) {
-    if let Some(spans) = spans.as_deref_mut() {
-        spans.push((id, LineSpan { start, end }));
+    // Unordered / ordered list
+    if is_list_start(trimmed) {
+        let (list_block, next) = parse_list(lines, index, ids, options, budget, depth)?;
+        out.push(list_block);
+        return Ok(BlockStep::Next(next));
+    }
+
+    // Setext heading: title line + === or ---
+    if index + 1 < lines.len()
+        && let Some(level) = parse_setext_underline(lines[index + 1].trim())
+    {
+        let title = trimmed;
+        let elem_id = ids.next_id();
+        let block = inline::parse_text_block(
+            |text| BlockKind::Heading { level, text },
+            title,
+            elem_id,
+            ids,
+            options,
+        );
+        out.push(block);
+        return Ok(BlockStep::Next(index + 2));
     }
+
+    let mut paragraph_lines: Vec<&str> = Vec::new();
+    let mut end_index = index;
+    while end_index < lines.len() {
+        let current = lines[end_index];
+        let current_trimmed = current.trim();
+        if current_trimmed.is_empty()
+            || fence_open_line(current, options).is_some()
+            || current_trimmed.starts_with('>')
+            || current_trimmed.starts_with(":::")
+            || parse_atx_heading(current_trimmed).is_some()
+            || is_list_start(current_trimmed)
+            || (options.footnotes && is_footnote_definition(current))
+        {
+            break;
+        }
+        // Stop before a setext underline that would apply to a single prior line only
+        // (already handled above when starting a new block).
+        paragraph_lines.push(current);
+        end_index += 1;
+    }
+    let elem_id = ids.next_id();
+    let block = inline::parse_text_block(
+        |text| BlockKind::Paragraph { text },
+        &paragraph_lines.join("\n"),
+        elem_id,
+        ids,
+        options,
+    );
+    out.push(block);
+    Ok(BlockStep::Next(end_index))
 }
 
 fn parse_table_cells(line: &str) -> Option<Vec<CellContent>> {
@@ -510,20 +858,26 @@ fn ordered_marker(trimmed: &str) -> Option<(u32, ListDelimiter, &str)> {
     None
 }
 
-fn parse_fence_open(trimmed: &str) -> Option<(CodeFenceStyle, &str)> {
+fn parse_fence_open<'a>(
+    trimmed: &'a str,
+    options: &ParserOptions,
+) -> Option<(CodeFenceStyle, &'a str)> {
     let marker = match trimmed.chars().next()? {
         '`' => FenceMarker::Backtick,
         '~' => FenceMarker::Tilde,
+        '$' if options.math_blocks => FenceMarker::Dollar,
         _ => return None,
     };
-    let symbol = match marker {
-        FenceMarker::Backtick => '`',
-        FenceMarker::Tilde => '~',
-    };
+    let symbol = marker.symbol();
     let length = trimmed
         .chars()
         .take_while(|character| *character == symbol)
         .count();
+    if marker == FenceMarker::Dollar {
+        // Display math opens with exactly `$$` on its own line.
+        return (length == 2 && trimmed[length..].trim().is_empty())
+            .then_some((CodeFenceStyle { marker, length: 2 }, ""));
+    }
     if length < 3 || length > u8::MAX as usize {
         return None;
     }
@@ -540,11 +894,40 @@ fn parse_fence_open(trimmed: &str) -> Option<(CodeFenceStyle, &str)> {
     ))
 }
 
+/// Deepest indentation (in columns) that still opens or closes a fence; four or more
+/// columns make the line indented content instead.
+const MAX_FENCE_INDENT: usize = 3;
+
+/// Recognize a fence opener on a raw line, returning its style, info remainder, and the
+/// opener's indentation (stripped from each content line, as CommonMark specifies).
+fn fence_open_line<'a>(
+    line: &'a str,
+    options: &ParserOptions,
+) -> Option<(CodeFenceStyle, &'a str, usize)> {
+    let indent = indent_of(line);
+    if indent > MAX_FENCE_INDENT {
+        return None;
+    }
+    let (style, info) = parse_fence_open(line.trim(), options)?;
+    Some((style, info, indent))
+}
+
+fn is_fence_close_line(line: &str, style: CodeFenceStyle) -> bool {
+    indent_of(line) <= MAX_FENCE_INDENT && is_fence_close(line.trim(), style)
+}
+
+/// Remove up to `indent` leading spaces from a fenced content line.
+fn strip_fence_indent(line: &str, indent: usize) -> &str {
+    let spaces = line
+        .bytes()
+        .take(indent)
+        .take_while(|byte| *byte == b' ')
+        .count();
+    &line[spaces..]
+}
+
 fn is_fence_close(trimmed: &str, style: CodeFenceStyle) -> bool {
-    let symbol = match style.marker {
-        FenceMarker::Backtick => '`',
-        FenceMarker::Tilde => '~',
-    };
+    let symbol = style.marker.symbol();
     let length = trimmed
         .chars()
         .take_while(|character| *character == symbol)
@@ -552,63 +935,230 @@ fn is_fence_close(trimmed: &str, style: CodeFenceStyle) -> bool {
     length >= usize::from(style.length) && trimmed[length..].chars().all(char::is_whitespace)
 }
 
-fn push_list_paragraph(children: &mut Vec<Block>, lines: &mut Vec<&str>, counter: &mut u64) {
+fn push_list_paragraph(
+    children: &mut Vec<Block>,
+    lines: &mut Vec<&str>,
+    ids: &mut IdAllocator,
+    options: &ParserOptions,
+    budget: &mut Budget,
+) -> Result<(), ParseError> {
     if lines.is_empty() {
-        return;
+        return Ok(());
     }
+    budget.count_block()?;
     let joined = lines.join("\n");
     lines.clear();
-    let elem_id = next_op_id(counter);
+    let elem_id = ids.next_id();
     children.push(inline::parse_text_block(
         |text| BlockKind::Paragraph { text },
         &joined,
         elem_id,
-        counter,
+        ids,
+        options,
     ));
+    Ok(())
+}
+
+/// A list whose items are still being parsed, with items at `base_indent`.
+struct ListFrame<'a> {
+    base_indent: usize,
+    style: ListStyle,
+    items: Vec<ListItem>,
+    /// The item whose continuation lines are being read.
+    item: Option<ItemFrame<'a>>,
+}
+
+struct ItemFrame<'a> {
+    elem_id: OpId,
+    task: Option<TaskState>,
+    children: Vec<Block>,
+    para_lines: Vec<&'a str>,
+}
+
+impl ListFrame<'_> {
+    fn new(first_line: &str) -> Self {
+        let first_trim = first_line.trim();
+        let style = if let Some((start, delimiter, _)) = ordered_marker(first_trim) {
+            ListStyle {
+                ordered: true,
+                start,
+                delimiter,
+                ..ListStyle::default()
+            }
+        } else {
+            ListStyle {
+                bullet: unordered_marker(first_trim)
+                    .map(|(marker, _)| marker)
+                    .unwrap_or(BulletMarker::Dash),
+                ..ListStyle::default()
+            }
+        };
+        Self {
+            base_indent: indent_of(first_line),
+            style,
+            items: Vec::new(),
+            item: None,
+        }
+    }
 }
 
-/// Parse a list starting at `index` with items at `base_indent` or greater content indent.
+/// Parse a list starting at `index` at nesting `depth`. Lists nested in its items go
+/// on an explicit stack, each one level deeper.
 fn parse_list(
     lines: &[&str],
     index: usize,
-    counter: &mut u64,
-    base_indent: usize,
-) -> (Block, usize) {
-    let first_trim = lines[index].trim();
-    let style = if let Some((start, delimiter, _)) = ordered_marker(first_trim) {
-        ListStyle {
-            ordered: true,
-            start,
-            delimiter,
-            ..ListStyle::default()
+    ids: &mut IdAllocator,
+    options: &ParserOptions,
+    budget: &mut Budget,
+    depth: usize,
+) -> Result<(Block, usize), ParseError> {
+    budget.enter(depth + 1)?;
+    let mut stack = vec![ListFrame::new(lines[index])];
+    let mut i = index;
+
+    loop {
+        let nesting = depth + stack.len();
+        let ListFrame {
+            base_indent,
+            style,
+            items,
+            item,
+        } = stack.last_mut().expect("the outer list is popped last");
+        let base_indent = *base_indent;
+
+        let Some(open) = item else {
+            if let Some(opened) = open_list_item(lines, &mut i, base_indent, style, ids, options) {
+                *item = Some(opened);
+                continue;
+            }
+            let finished = stack.pop().expect("frame was just inspected");
+            let list_elem = ids.next_id();
+            let items_seq = Sequence::from_ordered(
+                finished
+                    .items
+                    .into_iter()
+                    .map(|it| (it.elem_id, it))
+                    .collect(),
+            );
+            let block = Block::new(
+                BlockKind::List {
+                    style: finished.style,
+                    items: items_seq,
+                    pending_moves: Vec::new(),
+                },
+                list_elem,
+            );
+            let Some(parent) = stack.last_mut() else {
+                return Ok((block, i));
+            };
+            budget.count_block()?;
+            parent
+                .item
+                .as_mut()
+                .expect("a nested list sits in an open item")
+                .children
+                .push(block);
+            continue;
+        };
+
+        // Continuation lines for the open item
+        let mut close = true;
+        if i < lines.len() {
+            let cl = lines[i];
+            let cind = indent_of(cl);
+            let ctrim = cl.trim();
+            if ctrim.is_empty() {
+                // Look ahead for continuation or nested list
+                let mut j = i + 1;
+                while j < lines.len() && lines[j].trim().is_empty() {
+                    j += 1;
+                }
+                if j < lines.len() && indent_of(lines[j]) > base_indent {
+                    style.loose = true;
+                    if !is_list_start(lines[j].trim()) {
+                        push_list_paragraph(
+                            &mut open.children,
+                            &mut open.para_lines,
+                            ids,
+                            options,
+                            budget,
+                        )?;
+                    }
+                    i += 1;
+                    close = false;
+                }
+            } else if cind > base_indent && is_list_start(ctrim) {
+                // Nested list
+                push_list_paragraph(
+                    &mut open.children,
+                    &mut open.para_lines,
+                    ids,
+                    options,
+                    budget,
+                )?;
+                budget.enter(nesting + 1)?;
+                stack.push(ListFrame::new(cl));
+                continue;
+            } else if cind > base_indent {
+                // Continued paragraph in item (indented)
+                open.para_lines.push(ctrim);
+                i += 1;
+                close = false;
+            }
+            // Same indent: new list item or end
         }
-    } else {
-        ListStyle {
-            bullet: unordered_marker(first_trim)
-                .map(|(marker, _)| marker)
-                .unwrap_or(BulletMarker::Dash),
-            ..ListStyle::default()
+        if !close {
+            continue;
         }
-    };
-    let mut style = style;
-    let mut items: Vec<ListItem> = Vec::new();
-    let mut i = index;
 
-    while i < lines.len() {
-        let line = lines[i];
+        let mut open = item.take().expect("item is open");
+        push_list_paragraph(
+            &mut open.children,
+            &mut open.para_lines,
+            ids,
+            options,
+            budget,
+        )?;
+        budget.count_block()?;
+        let child_seq =
+            Sequence::from_ordered(open.children.into_iter().map(|b| (b.elem_id, b)).collect());
+        items.push(ListItem {
+            id: block_id_from_op(open.elem_id),
+            elem_id: open.elem_id,
+            task: open.task,
+            task_op: open.elem_id,
+            task_observed: StateVector::new(),
+            placement_observed: StateVector::new(),
+            children: child_seq,
+        });
+    }
+}
+
+/// Start the next item of a list at `base_indent`, skipping blank lines between
+/// items, or `None` where the list ends.
+fn open_list_item<'a>(
+    lines: &[&'a str],
+    i: &mut usize,
+    base_indent: usize,
+    style: &mut ListStyle,
+    ids: &mut IdAllocator,
+    options: &ParserOptions,
+) -> Option<ItemFrame<'a>> {
+    while *i < lines.len() {
+        let line = lines[*i];
         let ind = indent_of(line);
         if ind < base_indent && !line.trim().is_empty() {
-            break;
+            return None;
         }
         let trimmed = line.trim();
         if trimmed.is_empty() {
             // Blank line inside list: peek if more list content follows
-            let mut j = i + 1;
+            let mut j = *i + 1;
             while j < lines.len() && lines[j].trim().is_empty() {
                 j += 1;
             }
             if j >= lines.len() {
-                break;
+                return None;
             }
             let next_ind = indent_of(lines[j]);
             let next_trim = lines[j].trim();
@@ -620,13 +1170,13 @@ fn parse_list(
             };
             if next_ind > base_indent || (next_ind == base_indent && same_list) {
                 style.loose = true;
-                i += 1;
+                *i += 1;
                 continue;
             }
-            break;
+            return None;
         }
 
-        let marker_body = if style.ordered {
+        let body = if style.ordered {
             ordered_marker(trimmed)
                 .filter(|(_, delimiter, _)| *delimiter == style.delimiter)
                 .map(|(_, _, body)| body)
@@ -634,94 +1184,49 @@ fn parse_list(
             unordered_marker(trimmed)
                 .filter(|(marker, _)| *marker == style.bullet)
                 .map(|(_, body)| body)
-        };
-        let Some(body) = marker_body else {
-            break;
-        };
-        if ind > base_indent {
-            // Nested list belongs to previous item
-            break;
-        }
-        if ind < base_indent {
-            break;
+        }?;
+        if ind != base_indent {
+            // A deeper marker belongs to the previous item's nested list
+            return None;
         }
 
-        let item_elem = next_op_id(counter);
-        let (task, body) = parse_task_marker(body);
-        let mut children = Vec::new();
-        // First paragraph of the item
-        let mut para_lines = if body.is_empty() {
-            Vec::new()
+        let elem_id = ids.next_id();
+        let (task, body) = if options.task_lists {
+            parse_task_marker(body)
         } else {
-            vec![body]
+            (None, body)
         };
-        i += 1;
-
-        // Continuation lines for this item
-        while i < lines.len() {
-            let cl = lines[i];
-            let cind = indent_of(cl);
-            let ctrim = cl.trim();
-            if ctrim.is_empty() {
-                // Look ahead for continuation or nested list
-                let mut j = i + 1;
-                while j < lines.len() && lines[j].trim().is_empty() {
-                    j += 1;
-                }
-                if j < lines.len() && indent_of(lines[j]) > base_indent {
-                    style.loose = true;
-                    if !is_list_start(lines[j].trim()) {
-                        push_list_paragraph(&mut children, &mut para_lines, counter);
-                    }
-                    i += 1;
-                    continue;
-                }
-                break;
-            }
-            if cind > base_indent && is_list_start(ctrim) {
-                // Nested list
-                push_list_paragraph(&mut children, &mut para_lines, counter);
-                let (nested, next) = parse_list(lines, i, counter, cind);
-                children.push(nested);
-                i = next;
-                continue;
-            }
-            if cind > base_indent {
-                // Continued paragraph in item (indented)
-                para_lines.push(ctrim);
-                i += 1;
-                continue;
-            }
-            // Same indent: new list item or end
-            break;
-        }
-
-        push_list_paragraph(&mut children, &mut para_lines, counter);
-
-        let child_seq =
-            Sequence::from_ordered(children.into_iter().map(|b| (b.elem_id, b)).collect());
-        items.push(ListItem {
-            id: block_id_from_op(item_elem),
-            elem_id: item_elem,
+        *i += 1;
+        return Some(ItemFrame {
+            elem_id,
             task,
-            task_op: item_elem,
-            task_observed: StateVector::new(),
-            placement_observed: StateVector::new(),
-            children: child_seq,
+            children: Vec::new(),
+            // First paragraph of the item
+            para_lines: if body.is_empty() {
+                Vec::new()
+            } else {
+                vec![body]
+            },
         });
     }
+    None
+}
 
-    let list_elem = next_op_id(counter);
-    let items_seq = Sequence::from_ordered(items.into_iter().map(|it| (it.elem_id, it)).collect());
-    let block = Block::new(
-        BlockKind::List {
-            style,
-            items: items_seq,
-            pending_moves: Vec::new(),
-        },
-        list_elem,
-    );
-    (block, i)
+/// `[^label]:` at the start of a line, with a non-empty label and no spaces in it.
+fn is_footnote_definition(line: &str) -> bool {
+    indent_of(line) <= 3
+        && line
+            .trim_start()
+            .strip_prefix("[^")
+            .and_then(|rest| rest.split_once("]:"))
+            .is_some_and(|(label, _)| !label.is_empty() && !label.contains(char::is_whitespace))
+}
+
+/// End of the next continuation line of a footnote starting at `index`: a line
+/// indented four columns, possibly after blank lines.
+fn footnote_continuation_end(lines: &[&str], index: usize) -> Option<usize> {
+    let next = (index..lines.len()).find(|&line| !lines[line].trim().is_empty())?;
+    (indent_of(lines[next]) >= 4).then_some(next + 1)
 }
 
 fn parse_task_marker(body: &str) -> (Option<TaskState>, &str) {
@@ -739,11 +1244,78 @@ fn parse_task_marker(body: &str) -> (Option<TaskState>, &str) {
     (None, body)
 }
 
-pub(super) fn next_op_id(counter: &mut u64) -> OpId {
-    let id = OpId {
-        counter: *counter,
-        peer: 0,
-    };
-    *counter += 1;
-    id
+/// OpId source for one parse.
+pub(super) struct IdAllocator {
+    peer: PeerId,
+    counter: u64,
+    /// Seed and the counter slots already taken, in seeded mode.
+    seeded: Option<(u64, HashSet<u64>)>,
+}
+
+impl IdAllocator {
+    fn sequential(peer: PeerId, counter: u64) -> Self {
+        Self {
+            peer,
+            counter,
+            seeded: None,
+        }
+    }
+
+    fn seeded(seed: u64) -> Self {
+        Self {
+            peer: seed,
+            counter: 1,
+            seeded: Some((seed, HashSet::new())),
+        }
+    }
+
+    fn is_seeded(&self) -> bool {
+        self.seeded.is_some()
+    }
+
+    /// Move to the counter slot of a top-level block: the high 31 bits come from a
+    /// hash of the seed and `lines`, probing past slots taken by identical blocks,
+    /// and the block's own ids count up within the low 32 bits.
+    fn begin_block(&mut self, lines: &[&str]) {
+        let Some((seed, taken)) = &mut self.seeded else {
+            return;
+        };
+        let mut hash = lines
+            .iter()
+            .fold(fnv1a(FNV_OFFSET, &seed.to_le_bytes()), |hash, line| {
+                fnv1a(fnv1a(hash, line.as_bytes()), b"\n")
+            });
+        let slot = loop {
+            let slot = hash >> 33;
+            if taken.insert(slot) {
+                break slot;
+            }
+            hash = fnv1a(hash, &[0xff]);
+        };
+        self.counter = (slot + 1) << 32;
+    }
+
+    pub(super) fn next_id(&mut self) -> OpId {
+        let id = OpId {
+            counter: self.counter,
+            peer: self.peer,
+        };
+        self.counter += 1;
+        id
+    }
+
+    /// Text units for `text`, one id per grapheme.
+    pub(super) fn units(&mut self, text: &str) -> Sequence<TextUnit> {
+        units_from_str(text, &mut self.counter, self.peer)
+    }
+}
+
+pub(super) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+pub(super) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
 }
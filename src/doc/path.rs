@@ -0,0 +1,142 @@
+//! Addressing blocks nested inside blockquotes and list items.
+//!
+//! A [`BlockPath`] names a block by the chain of element ids leading to it from
+//! the top level: each enclosing blockquote's `elem_id`, or a list's `elem_id`
+//! followed by the item's, then the block's own. [`Document::insert_text`] and
+//! [`Document::remove_mark`] take either a path or a [`BlockId`] through
+//! [`BlockRef`], and the structural operations that take a `parent` container
+//! accept [`BlockPath::parent`]. Edits are still shipped with the block's
+//! stable id, so a replica routes them to the nested block wherever it sits.
+
+use super::{Block, BlockContainerPath, BlockId, BlockKind, Document, EditError};
+use crate::core::OpId;
+use serde::{Deserialize, Serialize};
+
+/// Element ids from the top level down to a block, the block's own last.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockPath(Vec<OpId>);
+
+impl BlockPath {
+    /// A path to the top-level block `elem_id`.
+    pub fn top_level(elem_id: OpId) -> Self {
+        Self(vec![elem_id])
+    }
+
+    /// The path to `elem_id` inside this block: a child of a blockquote, or,
+    /// after a list, one of its items and then a child of that item.
+    pub fn child(&self, elem_id: OpId) -> Self {
+        let mut path = self.0.clone();
+        path.push(elem_id);
+        Self(path)
+    }
+
+    pub fn elem_ids(&self) -> &[OpId] {
+        &self.0
+    }
+
+    /// The addressed block's own `elem_id`.
+    pub fn elem_id(&self) -> Option<OpId> {
+        self.0.last().copied()
+    }
+
+    /// The container holding the block (a blockquote or list item), as the
+    /// `parent` argument of [`Document::insert_block_at`] and the session's
+    /// `*_in` operations take it; `None` at the top level.
+    pub fn parent(&self) -> Option<OpId> {
+        self.0.len().checked_sub(2).map(|index| self.0[index])
+    }
+}
+
+impl From<Vec<OpId>> for BlockPath {
+    fn from(elem_ids: Vec<OpId>) -> Self {
+        Self(elem_ids)
+    }
+}
+
+/// A block named by stable id or by [`BlockPath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockRef {
+    Id(BlockId),
+    Path(BlockPath),
+}
+
+impl From<BlockId> for BlockRef {
+    fn from(block_id: BlockId) -> Self {
+        Self::Id(block_id)
+    }
+}
+
+impl From<BlockPath> for BlockRef {
+    fn from(path: BlockPath) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&BlockPath> for BlockRef {
+    fn from(path: &BlockPath) -> Self {
+        Self::Path(path.clone())
+    }
+}
+
+impl Document {
+    /// The path to the block `block_id`, wherever it is nested.
+    pub fn block_path(&self, block_id: BlockId) -> Option<BlockPath> {
+        self.find_block_by_id(block_id)?;
+        let path = self
+            .block_index_read()
+            .as_ref()?
+            .index
+            .by_block_id
+            .get(&block_id)
+            .cloned()?;
+        let mut elem_ids = Vec::with_capacity(path.containers.len() + 1);
+        for container in path.containers {
+            match container {
+                BlockContainerPath::BlockQuote(elem_id) => elem_ids.push(elem_id),
+                BlockContainerPath::ListItem { list, item } => elem_ids.extend([list, item]),
+            }
+        }
+        elem_ids.push(path.elem_id);
+        Some(BlockPath(elem_ids))
+    }
+
+    /// The block at `path`; `None` if any step is missing, deleted, or not a
+    /// container of the next.
+    pub fn block_at(&self, path: &BlockPath) -> Option<&Block> {
+        let (&elem_id, containers) = path.0.split_last()?;
+        let mut current = &*self.blocks;
+        let mut steps = containers.iter();
+        while let Some(&id) = steps.next() {
+            let block = current.get_element(&id)?.value.as_ref()?;
+            match &block.kind {
+                BlockKind::BlockQuote { children } => current = children,
+                BlockKind::List { items, .. } => {
+                    let item = *steps.next()?;
+                    current = &items.get_element(&item)?.value.as_ref()?.children;
+                }
+                _ => return None,
+            }
+        }
+        current.get_element(&elem_id)?.value.as_ref()
+    }
+
+    /// The stable id of the block `block` names, or [`EditError::BlockNotFound`].
+    /// A path that leads nowhere reports the id its last element would create.
+    pub(super) fn resolve_block_ref(&self, block: BlockRef) -> Result<BlockId, EditError> {
+        match block {
+            BlockRef::Id(block_id) => self
+                .find_block_by_id(block_id)
+                .map(|block| block.id)
+                .ok_or(EditError::BlockNotFound { block_id }),
+            BlockRef::Path(path) => {
+                self.block_at(&path)
+                    .map(|block| block.id)
+                    .ok_or(EditError::BlockNotFound {
+                        block_id: path
+                            .elem_id()
+                            .map_or(BlockId::nil(), super::block_id_from_op),
+                    })
+            }
+        }
+    }
+}
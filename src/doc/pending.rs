@@ -0,0 +1,89 @@
+//! Edits that arrive before the block they address.
+//!
+//! A remote [`EditOp`] can overtake the operation that creates its block. Rather
+//! than rejecting it, [`Document::apply_remote_op`] parks it under the block's
+//! id, and the edits parked for a block are replayed in arrival order as soon as
+//! [`Document::insert_block_at`] materializes it. This is the document-level
+//! counterpart of the buffering a [`crate::core::Sequence`] does for elements
+//! whose origin has not arrived. Parked edits live only in memory, apart from
+//! [`Document::to_debug_json`].
+
+use super::{BlockId, Document, EditError, EditOp};
+use std::collections::BTreeMap;
+
+/// Parked edits by target block, each with its grapheme-validation flag.
+pub(crate) type PendingEdits = BTreeMap<BlockId, Vec<(EditOp, bool)>>;
+
+impl Document {
+    /// Apply a remote edit, or park it when its block is not in the tree yet.
+    /// Returns `false` if the edit was parked. Edits to blocks that exist are
+    /// applied as [`Self::raw_apply_op`] does, errors included.
+    pub fn apply_remote_op(
+        &mut self,
+        op: EditOp,
+        validate_grapheme_boundaries: bool,
+    ) -> Result<bool, EditError> {
+        let (block_id, _) = op.target();
+        if self.find_block_by_id(block_id).is_none() {
+            self.pending_edits
+                .entry(block_id)
+                .or_default()
+                .push((op, validate_grapheme_boundaries));
+            return Ok(false);
+        }
+        self.raw_apply_op(op, validate_grapheme_boundaries)?;
+        Ok(true)
+    }
+
+    /// Apply the parked edits whose block is now in the tree and return how many
+    /// took effect; edits the block rejects are dropped. Called by
+    /// [`Self::insert_block_at`]; needed only after changing [`Self::blocks`]
+    /// directly.
+    pub fn replay_pending_edits(&mut self) -> usize {
+        if self.pending_edits.is_empty() {
+            return 0;
+        }
+        let ready: Vec<BlockId> = self
+            .pending_edits
+            .keys()
+            .copied()
+            .filter(|block_id| self.find_block_by_id(*block_id).is_some())
+            .collect();
+        let mut applied = 0;
+        for block_id in ready {
+            let edits = self.pending_edits.remove(&block_id).unwrap_or_default();
+            for (op, validate_grapheme_boundaries) in edits {
+                if self.raw_apply_op(op, validate_grapheme_boundaries).is_ok() {
+                    applied += 1;
+                }
+            }
+        }
+        applied
+    }
+
+    /// Number of parked edits across all blocks.
+    pub fn pending_edit_count(&self) -> usize {
+        self.pending_edits.values().map(Vec::len).sum()
+    }
+
+    /// Blocks with parked edits, in id order.
+    pub fn pending_edit_blocks(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.pending_edits.keys().copied()
+    }
+
+    pub(crate) fn pending_edit_entries(&self) -> &PendingEdits {
+        &self.pending_edits
+    }
+
+    pub(crate) fn set_pending_edit_entries(&mut self, pending_edits: PendingEdits) {
+        self.pending_edits = pending_edits;
+    }
+
+    /// Drop the edits parked for `block_id`, e.g. once its creation is known to
+    /// be lost; returns how many were dropped.
+    pub fn discard_pending_edits(&mut self, block_id: BlockId) -> usize {
+        self.pending_edits
+            .remove(&block_id)
+            .map_or(0, |edits| edits.len())
+    }
+}
@@ -0,0 +1,100 @@
+//! Application-supplied Markdown for raw blocks and marks.
+//!
+//! The serializer writes a [`BlockKind::RawBlock`](super::BlockKind::RawBlock)
+//! verbatim and a custom mark as bare text (or wrapped in its `delimiter`
+//! attribute). A [`SerializerRegistry`] lets an extension take over either: a
+//! raw block renderer claims the raw blocks of its subtype (a callout, an
+//! embed) and rewrites them, and a mark renderer chooses the text written
+//! around every interval of one [`MarkKind`] (a comment, a highlight). Pass it
+//! to [`Document::serialize_with`](super::Document::serialize_with).
+
+use crate::core::mark::{MarkInterval, MarkKind};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+type RawBlockRenderer = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+type MarkRenderer = Arc<dyn Fn(&MarkInterval) -> (String, String) + Send + Sync>;
+
+/// Render functions for raw block subtypes and mark kinds.
+#[derive(Clone, Default)]
+pub struct SerializerRegistry {
+    raw_blocks: Vec<(String, RawBlockRenderer)>,
+    marks: BTreeMap<MarkKind, MarkRenderer>,
+}
+
+impl SerializerRegistry {
+    /// A registry that renders everything the default way.
+    pub const fn new() -> Self {
+        Self {
+            raw_blocks: Vec::new(),
+            marks: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw_blocks.is_empty() && self.marks.is_empty()
+    }
+
+    /// Render raw blocks of the subtype `name`.
+    ///
+    /// `render` gets the block's raw source and returns `None` for raw blocks of
+    /// other subtypes. Subtypes are tried in registration order; registering a
+    /// `name` again replaces its renderer in place.
+    pub fn raw_block(
+        mut self,
+        name: &str,
+        render: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        let render: RawBlockRenderer = Arc::new(render);
+        match self
+            .raw_blocks
+            .iter_mut()
+            .find(|(existing, _)| existing == name)
+        {
+            Some((_, existing)) => *existing = render,
+            None => self.raw_blocks.push((name.to_string(), render)),
+        }
+        self
+    }
+
+    /// Render marks of `kind`: `render` returns the text written before and
+    /// after the marked text.
+    pub fn mark(
+        mut self,
+        kind: MarkKind,
+        render: impl Fn(&MarkInterval) -> (String, String) + Send + Sync + 'static,
+    ) -> Self {
+        self.marks.insert(kind, Arc::new(render));
+        self
+    }
+
+    pub(crate) fn render_raw_block(&self, raw: &str) -> Option<String> {
+        self.raw_blocks.iter().find_map(|(_, render)| render(raw))
+    }
+
+    pub(crate) fn mark_delimiters(&self, interval: &MarkInterval) -> Option<(String, String)> {
+        self.marks
+            .get(&interval.kind)
+            .map(|render| render(interval))
+    }
+}
+
+impl fmt::Debug for SerializerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerializerRegistry")
+            .field(
+                "raw_blocks",
+                &self
+                    .raw_blocks
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("marks", &self.marks.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// The registry [`Document::serialize`](super::Document::serialize) uses.
+pub(crate) static DEFAULT_REGISTRY: SerializerRegistry = SerializerRegistry::new();
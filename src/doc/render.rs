@@ -0,0 +1,94 @@
+//! Styled text runs of a block for editor frontends.
+
+use super::{Document, EditError, block_text_seq};
+use crate::core::mark::{MarkKind, MarkValue};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// A maximal stretch of a block's visible text under one set of marks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledRun {
+    pub text: String,
+    /// Half-open grapheme range of `text` within the block.
+    pub range: Range<usize>,
+    /// Active mark kinds, sorted and deduplicated.
+    pub marks: Vec<MarkKind>,
+    /// Attribute values of the marks that carry any. When two intervals of one
+    /// kind overlap, the later interval's values win.
+    pub attrs: BTreeMap<MarkKind, BTreeMap<String, MarkValue>>,
+}
+
+impl Document {
+    /// The block's visible text split into [`StyledRun`]s, in order. Adjacent runs
+    /// always differ in marks or attributes, even where the underlying intervals
+    /// change; an empty block has no runs.
+    pub fn rendered_block(&self, block_id: super::BlockId) -> Result<Vec<StyledRun>, EditError> {
+        let block = self
+            .find_block_by_id(block_id)
+            .ok_or(EditError::BlockNotFound { block_id })?;
+        let text = block_text_seq(&block.kind).ok_or(EditError::NotTextBlock { block_id })?;
+        let graphemes: Vec<&str> = text.iter().map(|unit| unit.grapheme.as_str()).collect();
+
+        let mut runs: Vec<StyledRun> = Vec::new();
+        for span in self.block_spans(block_id)? {
+            let mut marks = Vec::new();
+            let mut attrs = BTreeMap::new();
+            // Span intervals come in id order, so later intervals overwrite.
+            for interval in span.marks.iter().filter_map(|id| block.marks.interval(id)) {
+                marks.push(interval.kind.clone());
+                let values = interval.attr_values();
+                if !values.is_empty() {
+                    attrs.insert(interval.kind.clone(), values);
+                }
+            }
+            marks.sort();
+            marks.dedup();
+            let text: String = graphemes[span.start..span.end].concat();
+
+            match runs.last_mut() {
+                Some(last) if last.marks == marks && last.attrs == attrs => {
+                    last.text.push_str(&text);
+                    last.range.end = span.end;
+                }
+                _ => runs.push(StyledRun {
+                    text,
+                    range: span.start..span.end,
+                    marks,
+                    attrs,
+                }),
+            }
+        }
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc::Parser;
+
+    #[test]
+    fn adjacent_intervals_with_equal_styles_merge() {
+        let mut document = Parser::parse("abcd");
+        let block_id = document.blocks_in_order()[0].id;
+        for (counter, range) in [(100, 0..2), (101, 2..4)] {
+            let (start, end) = document.grapheme_range_to_anchors(block_id, range).unwrap();
+            let id = crate::core::OpId { counter, peer: 1 };
+            document
+                .set_mark(
+                    block_id,
+                    id,
+                    MarkKind::Bold,
+                    start,
+                    end,
+                    BTreeMap::new(),
+                    id,
+                )
+                .unwrap();
+        }
+        let runs = document.rendered_block(block_id).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].text, "abcd");
+        assert_eq!(runs[0].marks, vec![MarkKind::Bold]);
+    }
+}
@@ -0,0 +1,128 @@
+//! Clearing a container, or the whole document, in one operation.
+//!
+//! Deleting blocks one by one costs an operation, a pending-buffer pass and an
+//! index rebuild per block. [`Document::delete_all_blocks_at`] tombstones a
+//! whole container with a single range delete instead, and
+//! [`Document::replace_all`] follows it with the blocks of new Markdown.
+
+use super::{Document, Parser};
+use crate::core::merge::observe;
+use crate::core::{ClockError, OpId, OpIdRange, SequenceOp, StateVector};
+
+/// A range delete over the children of one container, as
+/// [`Document::delete_all_blocks_at`] applied it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRangeDelete {
+    /// Container block elem_id; `None` = top-level document.
+    pub parent: Option<OpId>,
+    /// First and last element of the range, tombstones included.
+    pub from: OpId,
+    pub to: OpId,
+    pub id: OpId,
+    /// Blocks the delete covers; ones inserted concurrently stay visible.
+    pub observed: StateVector,
+}
+
+/// What [`Document::replace_all`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceAll {
+    /// `None` when the document held no visible blocks.
+    pub cleared: Option<BlockRangeDelete>,
+    /// Element ids of the new top-level blocks, in order.
+    pub inserted: Vec<OpId>,
+}
+
+impl Document {
+    /// Delete every block among `parent`'s children (top-level when `parent` is
+    /// `None`) with one range delete. Returns it, or `None` when the container
+    /// has no visible block or is not in the tree.
+    pub fn delete_all_blocks_at(
+        &mut self,
+        parent: Option<OpId>,
+        id: OpId,
+    ) -> Option<BlockRangeDelete> {
+        let delete = self.block_range_delete(parent, id)?;
+        self.delete_block_range_at(&delete);
+        Some(delete)
+    }
+
+    /// The range delete [`Self::delete_all_blocks_at`] would apply, covering
+    /// every child of `parent` this replica holds; `None` when none is visible.
+    pub fn block_range_delete(&self, parent: Option<OpId>, id: OpId) -> Option<BlockRangeDelete> {
+        let children = self.container_children(parent)?;
+        if children.len_visible() == 0 {
+            return None;
+        }
+        let mut observed = StateVector::new();
+        for element in children.iter_all() {
+            observe(&mut observed, element.id);
+        }
+        Some(BlockRangeDelete {
+            parent,
+            from: children.iter_all().next()?.id,
+            to: children.iter_all().last()?.id,
+            id,
+            observed,
+        })
+    }
+
+    /// Apply a range delete made by [`Self::delete_all_blocks_at`], here or on
+    /// another replica. Returns `false` if `parent` is not a container in the tree.
+    pub fn delete_block_range_at(&mut self, delete: &BlockRangeDelete) -> bool {
+        let op = SequenceOp::DeleteRange {
+            from: delete.from,
+            to: delete.to,
+            id: delete.id,
+            observed: delete.observed.clone(),
+        };
+        match delete.parent {
+            None => {
+                self.source = None;
+                self.blocks.apply(op);
+                true
+            }
+            Some(parent) => {
+                self.mark_source_elem_dirty(parent);
+                self.with_container_children_mut(parent, |children| children.apply(op))
+                    .is_some()
+            }
+        }
+    }
+
+    /// Replace every top-level block with the blocks of `new_markdown`: one range
+    /// delete, then the parsed blocks after the last deleted one. Ids come from
+    /// `ids`, the delete's first; on error nothing changes and `ids` is untouched.
+    ///
+    /// Only the body is replaced. The frontmatter stays as it is, and one at the
+    /// start of `new_markdown` is ignored.
+    pub fn replace_all(
+        &mut self,
+        new_markdown: &str,
+        ids: &mut OpIdRange,
+    ) -> Result<ReplaceAll, ClockError> {
+        let mut reserved = ids.clone();
+        let delete_id = reserved.next_id()?;
+        let parsed = Parser::parse_in_range(new_markdown, &mut reserved)?;
+        *ids = reserved;
+
+        let cleared = self.delete_all_blocks_at(None, delete_id);
+        let mut after = cleared.as_ref().map(|delete| delete.to);
+        let mut inserted = Vec::new();
+        for element in parsed.blocks.iter_all() {
+            let Some(block) = element.value.clone() else {
+                continue;
+            };
+            self.blocks.apply(SequenceOp::Insert {
+                after,
+                id: element.id,
+                value: block,
+                right_origin: None,
+            });
+            after = Some(element.id);
+            inserted.push(element.id);
+        }
+        self.source = None;
+        self.replay_pending_edits();
+        Ok(ReplaceAll { cleared, inserted })
+    }
+}
@@ -0,0 +1,202 @@
+//! Resolution policies for concurrently written registers.
+//!
+//! Frontmatter fields and table header cells merge as last-writer-wins
+//! registers: of two writes that did not observe each other, the higher
+//! [`OpId`](crate::OpId) wins and the other value is dropped without a trace. Some fields
+//! (a document's `status`, a column name other tooling keys on) deserve review
+//! instead. A [`ResolutionPolicy`] attached to a [`ResolutionTarget`] makes a
+//! collaborative session track the target's registers as
+//! [`MultiValueRegister`]s, keeping every concurrent write until a later write
+//! observes them all, and decide which value to show in the meantime.
+//!
+//! The concurrent writes converge on every replica. What is *shown* converges
+//! under [`ResolutionPolicy::Lww`] and [`ResolutionPolicy::Manual`];
+//! [`ResolutionPolicy::PreferLocal`] and [`ResolutionPolicy::PreferRemote`]
+//! depend on which peer is asking, so replicas can show different values until
+//! the register is written again.
+
+use super::{BlockId, ColumnId};
+use crate::core::{MultiValueRegister, PeerId, RegisterWrite};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How a register settles writes that did not observe each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResolutionPolicy {
+    /// Highest op id wins silently; the default.
+    #[default]
+    Lww,
+    /// Show this replica's newest write, falling back to the highest op id when
+    /// none of the concurrent writes is local.
+    PreferLocal,
+    /// Show the newest write from another peer, falling back to the highest op id.
+    PreferRemote,
+    /// Show the highest op id, but keep the register in
+    /// [`CollaborativeDocument::register_conflicts`](crate::CollaborativeDocument::register_conflicts)
+    /// until a write resolves it.
+    Manual,
+}
+
+/// Registers a policy applies to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ResolutionTarget {
+    /// One top-level frontmatter key.
+    FrontmatterField(String),
+    /// Every header cell of one table.
+    TableHeader(BlockId),
+}
+
+/// One register that can have concurrent writes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RegisterKey {
+    FrontmatterField(String),
+    TableHeaderCell {
+        table_id: BlockId,
+        column_id: ColumnId,
+    },
+}
+
+impl RegisterKey {
+    /// The policy target covering this register.
+    pub fn target(&self) -> ResolutionTarget {
+        match self {
+            Self::FrontmatterField(key) => ResolutionTarget::FrontmatterField(key.clone()),
+            Self::TableHeaderCell { table_id, .. } => ResolutionTarget::TableHeader(*table_id),
+        }
+    }
+}
+
+/// A register holding writes that did not observe each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterConflict {
+    pub key: RegisterKey,
+    /// The concurrent writes in op id order; at least two. A `None` value
+    /// deletes a frontmatter field.
+    pub writes: Vec<RegisterWrite<Option<String>>>,
+}
+
+/// Concurrent writes of each register under a policy other than LWW.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RegisterHeads {
+    registers: BTreeMap<RegisterKey, MultiValueRegister<Option<String>>>,
+}
+
+impl RegisterHeads {
+    /// Track `key`, starting from its current winning write.
+    pub(crate) fn seed(&mut self, key: RegisterKey, write: RegisterWrite<Option<String>>) {
+        self.registers.entry(key).or_default().apply(write);
+    }
+
+    /// Merge `write`, returning the register's concurrent writes afterwards.
+    pub(crate) fn record(
+        &mut self,
+        key: RegisterKey,
+        write: RegisterWrite<Option<String>>,
+    ) -> &[RegisterWrite<Option<String>>] {
+        let register = self.registers.entry(key).or_default();
+        register.apply(write);
+        register.writes()
+    }
+
+    /// Stop tracking every register of `target`.
+    pub(crate) fn forget(&mut self, target: &ResolutionTarget) {
+        self.registers.retain(|key, _| key.target() != *target);
+    }
+
+    /// Conflicted registers, in key order.
+    pub(crate) fn conflicts(&self) -> impl Iterator<Item = RegisterConflict> + '_ {
+        self.registers
+            .iter()
+            .filter(|(_, register)| register.is_conflicted())
+            .map(|(key, register)| RegisterConflict {
+                key: key.clone(),
+                writes: register.writes().to_vec(),
+            })
+    }
+
+    pub(crate) fn to_entries(&self) -> Vec<(RegisterKey, MultiValueRegister<Option<String>>)> {
+        self.registers
+            .iter()
+            .map(|(key, register)| (key.clone(), register.clone()))
+            .collect()
+    }
+
+    pub(crate) fn from_entries(
+        entries: Vec<(RegisterKey, MultiValueRegister<Option<String>>)>,
+    ) -> Self {
+        Self {
+            registers: entries.into_iter().collect(),
+        }
+    }
+}
+
+impl ResolutionPolicy {
+    /// The head to show for a register whose heads are `heads` (op id order,
+    /// non-empty), as seen by `local`.
+    pub(crate) fn choose(
+        self,
+        heads: &[RegisterWrite<Option<String>>],
+        local: PeerId,
+    ) -> Option<&RegisterWrite<Option<String>>> {
+        let newest = |local_writes: bool| {
+            heads
+                .iter()
+                .rev()
+                .find(|write| (write.id.peer == local) == local_writes)
+        };
+        match self {
+            Self::Lww | Self::Manual => None,
+            Self::PreferLocal => newest(true),
+            Self::PreferRemote => newest(false),
+        }
+        .or(heads.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{OpId, StateVector};
+
+    fn write(counter: u64, peer: PeerId) -> RegisterWrite<Option<String>> {
+        RegisterWrite {
+            id: OpId { counter, peer },
+            value: Some(format!("{peer}@{counter}")),
+            observed: StateVector::new(),
+        }
+    }
+
+    #[test]
+    fn policies_choose_the_shown_write() {
+        let heads = [write(2, 1), write(2, 2), write(2, 3)];
+        let shown = |policy: ResolutionPolicy, local| policy.choose(&heads, local).unwrap().id.peer;
+        assert_eq!(shown(ResolutionPolicy::Lww, 1), 3);
+        assert_eq!(shown(ResolutionPolicy::Manual, 1), 3);
+        assert_eq!(shown(ResolutionPolicy::PreferLocal, 1), 1);
+        assert_eq!(shown(ResolutionPolicy::PreferLocal, 9), 3);
+        assert_eq!(shown(ResolutionPolicy::PreferRemote, 3), 2);
+    }
+
+    #[test]
+    fn forgetting_a_table_header_keeps_other_registers() {
+        let table_id = BlockId::from_u128(7);
+        let mut heads = RegisterHeads::default();
+        heads.seed(
+            RegisterKey::TableHeaderCell {
+                table_id,
+                column_id: BlockId::from_u128(8),
+            },
+            write(1, 1),
+        );
+        heads.seed(RegisterKey::FrontmatterField("status".into()), write(1, 1));
+        heads.forget(&ResolutionTarget::TableHeader(table_id));
+        assert_eq!(
+            heads
+                .to_entries()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            vec![RegisterKey::FrontmatterField("status".into())]
+        );
+    }
+}
@@ -1,21 +1,32 @@
+use super::registry::DEFAULT_REGISTRY;
 use super::*;
 
 const MAX_ORDERED_LIST_START: u32 = 999_999_999;
 
-pub(super) fn serialize_block(block: &Block) -> String {
+pub(crate) fn serialize_block(block: &Block) -> String {
+    serialize_block_with(block, &DEFAULT_REGISTRY)
+}
+
+pub(crate) fn serialize_block_with(block: &Block, registry: &SerializerRegistry) -> String {
     match &block.kind {
-        BlockKind::Paragraph { text } => super::inline::serialize_text(block, text),
+        BlockKind::Paragraph { text } => super::inline::serialize_text(block, text, registry),
         BlockKind::Heading { level, text } => {
             let hashes = "#".repeat((*level).clamp(1, 6) as usize);
-            format!("{} {}", hashes, super::inline::serialize_text(block, text))
+            format!(
+                "{} {}",
+                hashes,
+                super::inline::serialize_text(block, text, registry)
+            )
         }
-        BlockKind::List { style, items, .. } => serialize_list(*style, items, 0),
+        BlockKind::List { style, items, .. } => serialize_list(*style, items, 0, registry),
         BlockKind::CodeFence { style, info, text } => {
-            let marker = match style.marker {
-                FenceMarker::Backtick => '`',
-                FenceMarker::Tilde => '~',
+            let marker = style.marker.symbol();
+            let length = if style.marker == FenceMarker::Dollar {
+                2
+            } else {
+                usize::from(style.length.max(3)).max(longest_closing_run(text, marker) + 1)
             };
-            let fence = marker.to_string().repeat(usize::from(style.length.max(3)));
+            let fence = marker.to_string().repeat(length);
             let mut output = fence.clone();
             if let Some(info) = info {
                 output.push_str(info);
@@ -29,7 +40,7 @@ pub(super) fn serialize_block(block: &Block) -> String {
         BlockKind::BlockQuote { children } => {
             let mut rendered = Vec::new();
             for child in children.iter_asc() {
-                let child_output = serialize_block(child);
+                let child_output = serialize_block_with(child, registry);
                 // Skip empty children (e.g., empty nested blockquotes)
                 if !child_output.trim().is_empty() {
                     rendered.push(child_output);
@@ -52,12 +63,39 @@ pub(super) fn serialize_block(block: &Block) -> String {
                 .collect::<Vec<_>>()
                 .join("\n")
         }
-        BlockKind::RawBlock { raw } => raw.clone(),
+        BlockKind::RawBlock { raw } => registry
+            .render_raw_block(raw)
+            .unwrap_or_else(|| raw.clone()),
         BlockKind::Table { table } => serialize_table(table),
     }
 }
 
-fn serialize_list(style: ListStyle, items: &Sequence<ListItem>, indent: usize) -> String {
+/// Longest marker run in `text` that would close a fence of that length if emitted as-is.
+///
+/// The serializer lengthens the fence past it so a body containing an inner fence example
+/// (e.g. a triple-backtick snippet inside a four-backtick fence) survives a reparse.
+fn longest_closing_run(text: &str, marker: char) -> usize {
+    text.lines()
+        .filter(|line| line.len() - line.trim_start_matches(' ').len() <= 3)
+        .map(|line| {
+            let trimmed = line.trim();
+            let run = trimmed.chars().take_while(|c| *c == marker).count();
+            if trimmed[run..].trim().is_empty() {
+                run
+            } else {
+                0
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn serialize_list(
+    style: ListStyle,
+    items: &Sequence<ListItem>,
+    indent: usize,
+    registry: &SerializerRegistry,
+) -> String {
     let pad = " ".repeat(indent);
     let mut lines = Vec::new();
     for (n, item) in items.iter_asc().enumerate() {
@@ -95,7 +133,7 @@ fn serialize_list(style: ListStyle, items: &Sequence<ListItem>, indent: usize) -
         for (ci, child) in children.iter().enumerate() {
             match &child.kind {
                 BlockKind::Paragraph { text } => {
-                    let body = super::inline::serialize_text(child, text);
+                    let body = super::inline::serialize_text(child, text, registry);
                     if ci == 0 {
                         let mut body_lines = body.lines();
                         lines.push(format!(
@@ -121,11 +159,11 @@ fn serialize_list(style: ListStyle, items: &Sequence<ListItem>, indent: usize) -
                     if ci == 0 {
                         lines.push(format!("{pad}{marker}{task}"));
                     }
-                    let nested_s = serialize_list(*style, nested, indent + 2);
+                    let nested_s = serialize_list(*style, nested, indent + 2, registry);
                     lines.push(nested_s);
                 }
                 other => {
-                    let s = serialize_block(child);
+                    let s = serialize_block_with(child, registry);
                     if ci == 0 {
                         // first child non-paragraph: put after marker
                         let first = s.lines().next().unwrap_or("");
@@ -221,39 +259,13 @@ fn escape_table_cell(cell: &str) -> String {
     escaped
 }
 
-pub(super) fn grapheme_offset_to_byte(text: &str, grapheme_offset: usize) -> Option<usize> {
-    if grapheme_offset == 0 {
-        return Some(0);
-    }
-
-    let mut count = 0;
-    for (byte_index, _) in text.grapheme_indices(true) {
-        if count == grapheme_offset {
-            return Some(byte_index);
-        }
-        count += 1;
-    }
-    if count == grapheme_offset {
-        Some(text.len())
-    } else {
-        None
-    }
-}
-
-pub(super) fn is_grapheme_boundary(text: &str, byte_offset: usize) -> bool {
-    if byte_offset == 0 || byte_offset == text.len() {
-        return true;
-    }
-    text.grapheme_indices(true)
-        .any(|(index, _)| index == byte_offset)
-}
-
-pub(super) fn normalize_structural(text: &str) -> String {
+pub(super) fn normalize_structural(text: &str, trailing: TrailingSpaces) -> String {
     let mut lines = Vec::new();
     let mut previous_blank = false;
+    let mut fences = FenceTracker::default();
     for line in text.lines() {
-        let trimmed = line.trim_end();
-        if trimmed.is_empty() {
+        let trimmed = trim_trailing(line, trailing, &mut fences);
+        if trimmed.trim_end().is_empty() {
             if !previous_blank {
                 lines.push(String::new());
                 previous_blank = true;
@@ -277,6 +289,7 @@ pub(super) fn normalize_structural(text: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::doc::registry::DEFAULT_REGISTRY;
 
     fn id(counter: u64) -> OpId {
         OpId { counter, peer: 1 }
@@ -294,7 +307,10 @@ mod tests {
             children: Sequence::new(),
         };
         let empty_items = Sequence::from_ordered(vec![(id(1), empty_item)]);
-        assert_eq!(serialize_list(ListStyle::default(), &empty_items, 0), "- ");
+        assert_eq!(
+            serialize_list(ListStyle::default(), &empty_items, 0, &DEFAULT_REGISTRY),
+            "- "
+        );
 
         let code = Block::new(
             BlockKind::CodeFence {
@@ -320,7 +336,7 @@ mod tests {
             children: Sequence::from_ordered(vec![(id(3), code), (id(4), raw)]),
         };
         let items = Sequence::from_ordered(vec![(id(2), item)]);
-        let rendered = serialize_list(ListStyle::default(), &items, 0);
+        let rendered = serialize_list(ListStyle::default(), &items, 0, &DEFAULT_REGISTRY);
         assert!(rendered.starts_with("- ```rs\n  let x = 1;\n  ```"));
         assert!(rendered.ends_with("\n\n  :::note"));
     }
@@ -373,11 +389,12 @@ mod tests {
         assert_eq!(serialize_table(&table), "");
 
         let text = "a👩‍💻b";
-        assert_eq!(grapheme_offset_to_byte(text, 0), Some(0));
-        assert_eq!(grapheme_offset_to_byte(text, 3), Some(text.len()));
-        assert_eq!(grapheme_offset_to_byte(text, 4), None);
-        assert!(is_grapheme_boundary(text, 1));
-        assert!(!is_grapheme_boundary(text, 2));
-        assert!(is_grapheme_boundary(text, text.len()));
+        let rules = SegmentationRules::CURRENT;
+        assert_eq!(rules.byte_offset(text, 0), Some(0));
+        assert_eq!(rules.byte_offset(text, 3), Some(text.len()));
+        assert_eq!(rules.byte_offset(text, 4), None);
+        assert!(rules.is_boundary(text, 1));
+        assert!(!rules.is_boundary(text, 2));
+        assert!(rules.is_boundary(text, text.len()));
     }
 }
@@ -1,4 +1,5 @@
-use super::{Block, BlockId, BlockKind};
+use super::registry::DEFAULT_REGISTRY;
+use super::{Block, BlockId, BlockKind, SerializerRegistry};
 use crate::core::{OpId, Sequence};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -35,12 +36,27 @@ impl DocumentSource {
     pub(crate) fn render_root_region(&self, root: BlockId, block: &Block) -> Option<String> {
         let region = self.regions.get(&root)?;
         if self.dirty.contains(&root) {
-            Some(super::serialize::serialize_block(block))
+            Some(self.render_dirty(region, block, &DEFAULT_REGISTRY))
         } else {
             Some(self.original[region.body_start..region.body_end].to_string())
         }
     }
 
+    /// The Markdown this state was adopted from.
+    pub(crate) fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// Top-level block ids in the order of [`Self::original`].
+    pub(crate) fn order(&self) -> &[BlockId] {
+        &self.order
+    }
+
+    /// Whether the top-level block `root` was edited since the source was adopted.
+    pub(crate) fn is_dirty(&self, root: BlockId) -> bool {
+        self.dirty.contains(&root)
+    }
+
     pub(crate) fn root_for_block(&self, block_id: BlockId) -> Option<BlockId> {
         self.root_by_block.get(&block_id).copied()
     }
@@ -142,6 +158,7 @@ impl DocumentSource {
         &self,
         blocks: &Sequence<Block>,
         frontmatter: Option<&str>,
+        registry: &SerializerRegistry,
     ) -> String {
         let mut output = frontmatter.map_or_else(
             || self.original[..self.preamble_end].to_string(),
@@ -162,7 +179,7 @@ impl DocumentSource {
                     ensure_block_separator(&mut output);
                 }
                 if self.dirty.contains(&block.id) {
-                    output.push_str(&super::serialize::serialize_block(block));
+                    output.push_str(&self.render_dirty(region, block, registry));
                 } else {
                     output.push_str(&self.original[region.body_start..region.body_end]);
                 }
@@ -171,7 +188,7 @@ impl DocumentSource {
                 if emitted_block || !output.is_empty() {
                     ensure_block_separator(&mut output);
                 }
-                output.push_str(&super::serialize::serialize_block(block));
+                output.push_str(&super::serialize::serialize_block_with(block, registry));
                 previous_source_position = None;
             }
             emitted_block = true;
@@ -182,6 +199,28 @@ impl DocumentSource {
         output
     }
 
+    /// An edited block rendered afresh, then given back the indentation, heading
+    /// markers and line endings of its original source, which the model does not
+    /// record, so the edit touches only what changed.
+    fn render_dirty(
+        &self,
+        region: &SourceRegion,
+        block: &Block,
+        registry: &SerializerRegistry,
+    ) -> String {
+        let original = &self.original[region.body_start..region.body_end];
+        let rendered = super::serialize::serialize_block_with(block, registry);
+        let rendered = match &block.kind {
+            BlockKind::Heading { level, .. } => restyle_heading(original, *level, rendered),
+            _ => rendered,
+        };
+        if original.contains("\r\n") {
+            with_crlf(&rendered)
+        } else {
+            rendered
+        }
+    }
+
     fn render_replaced_frontmatter(&self, frontmatter: &str) -> String {
         let preamble = &self.original[..self.preamble_end];
         let remainder = if preamble.starts_with("---") {
@@ -217,6 +256,75 @@ impl DocumentSource {
     }
 }
 
+/// `rendered`, an ATX heading of `level`, in the style of its `original` source:
+/// the same indentation, gap after the opening hashes and closing sequence for
+/// ATX, or the same underline for a setext heading that is still level 1 or 2.
+fn restyle_heading(original: &str, level: u8, rendered: String) -> String {
+    let hashes = usize::from(level.clamp(1, 6));
+    let Some(text) = rendered.get(hashes + 1..) else {
+        return rendered;
+    };
+    let mut lines = original.lines().map(|line| line.trim_end_matches('\r'));
+    let first = lines.next().unwrap_or_default();
+    let content = first.trim_start_matches(' ');
+    let indent = &first[..first.len() - content.len()];
+    if indent.len() > 3 {
+        return rendered;
+    }
+    let opening = content.len() - content.trim_start_matches('#').len();
+    if opening > 0 {
+        let after = &content[opening..];
+        let heading = after.trim_start_matches([' ', '\t']);
+        let gap = &after[..after.len() - heading.len()];
+        if opening > 6 || (gap.is_empty() && !heading.is_empty()) {
+            return rendered;
+        }
+        let trimmed = heading.trim_end_matches([' ', '\t']);
+        let unclosed = trimmed.trim_end_matches('#');
+        // A closing run needs whitespace before it; `# C#` has none.
+        let body = if unclosed.is_empty() || unclosed.ends_with([' ', '\t']) {
+            unclosed.trim_end_matches([' ', '\t'])
+        } else {
+            trimmed
+        };
+        let closing = &heading[body.len()..];
+        let gap = if gap.is_empty() { " " } else { gap };
+        return format!("{indent}{}{gap}{text}{closing}", "#".repeat(hashes));
+    }
+    let underline = lines.next_back().unwrap_or_default().trim();
+    let marker = match level {
+        1 => '=',
+        2 => '-',
+        _ => return rendered,
+    };
+    let Some(underline_marker) = underline.chars().next() else {
+        return rendered;
+    };
+    if !matches!(underline_marker, '=' | '-') || !underline.chars().all(|c| c == underline_marker) {
+        return rendered;
+    }
+    let underline = if underline_marker == marker {
+        underline.to_string()
+    } else {
+        marker.to_string().repeat(underline.len())
+    };
+    format!("{indent}{text}\n{underline}")
+}
+
+/// `text` with every bare `\n` written as `\r\n`.
+fn with_crlf(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() + text.len() / 16);
+    let mut previous = None;
+    for c in text.chars() {
+        if c == '\n' && previous != Some('\r') {
+            output.push('\r');
+        }
+        output.push(c);
+        previous = Some(c);
+    }
+    output
+}
+
 fn ensure_block_separator(output: &mut String) {
     if !output.ends_with("\n\n") {
         if !output.ends_with('\n') {
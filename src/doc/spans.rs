@@ -0,0 +1,250 @@
+//! Mark spans cached per block and refreshed around the edited region.
+//!
+//! [`MarkSet::render_spans`](crate::core::mark::MarkSet::render_spans) rebuilds a
+//! block's spans from every interval at every position. The cache keeps the
+//! last spans together with the unit order and resolved intervals they came
+//! from. On the next request the old and new unit orders are compared by common
+//! prefix and suffix, which bounds a text edit, and intervals that were added,
+//! removed or moved extend that bound by their old and new ranges. Spans
+//! outside the bound are reused, shifted past the edit; only the positions
+//! inside it are recomputed.
+
+use super::{BlockId, Document, EditError, block_text_seq};
+use crate::core::OpId;
+use crate::core::mark::{MarkIntervalId, Span};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+/// Spans of one block as last returned by [`Document::block_spans`].
+#[derive(Debug)]
+pub(crate) struct CachedSpans {
+    /// Value version of the block's top-level element; nested blocks have none
+    /// and always compare their content.
+    version: Option<u64>,
+    order: Vec<OpId>,
+    intervals: BTreeMap<MarkIntervalId, (usize, usize)>,
+    spans: Vec<Span>,
+}
+
+pub(crate) type SpanCache = HashMap<BlockId, CachedSpans>;
+
+/// A block's current unit order and resolved active intervals.
+struct Snapshot {
+    version: Option<u64>,
+    order: Vec<OpId>,
+    intervals: BTreeMap<MarkIntervalId, (usize, usize)>,
+}
+
+/// Where a block changed relative to its cached spans, in current positions.
+struct Change {
+    dirty: Range<usize>,
+    /// Current length minus cached length; cached positions at or after the
+    /// change move by this much.
+    shift: isize,
+}
+
+impl Document {
+    /// Mark spans of a text block, equal to
+    /// [`Self::render_paragraph_spans`] but recomputed only where the block
+    /// changed since the previous call.
+    pub fn block_spans(&self, block_id: BlockId) -> Result<Vec<Span>, EditError> {
+        let snapshot = self.span_snapshot(block_id)?;
+        let mut cache = self
+            .span_cache
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let spans = match cache.get(&block_id) {
+            Some(cached) if snapshot.version.is_some() && cached.version == snapshot.version => {
+                return Ok(cached.spans.clone());
+            }
+            Some(cached) => match change_since(cached, &snapshot) {
+                None => cached.spans.clone(),
+                Some(change) => refresh(cached, &snapshot, &change),
+            },
+            None => spans_over(&snapshot.intervals, 0..snapshot.order.len()),
+        };
+        cache.insert(
+            block_id,
+            CachedSpans {
+                version: snapshot.version,
+                order: snapshot.order,
+                intervals: snapshot.intervals,
+                spans: spans.clone(),
+            },
+        );
+        Ok(spans)
+    }
+
+    /// The grapheme range of a text block whose spans may differ from those the
+    /// last [`Self::block_spans`] call returned, or `None` when nothing changed.
+    /// Positions after the range are unchanged apart from shifting; an empty range
+    /// marks where text was deleted. A block never passed to
+    /// [`Self::block_spans`] is dirty over its whole length.
+    pub fn marks_dirty_range(&self, block_id: BlockId) -> Result<Option<Range<usize>>, EditError> {
+        let snapshot = self.span_snapshot(block_id)?;
+        let cache = self
+            .span_cache
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        Ok(match cache.get(&block_id) {
+            Some(cached) if snapshot.version.is_some() && cached.version == snapshot.version => {
+                None
+            }
+            Some(cached) => change_since(cached, &snapshot).map(|change| change.dirty),
+            None => Some(0..snapshot.order.len()),
+        })
+    }
+
+    fn span_snapshot(&self, block_id: BlockId) -> Result<Snapshot, EditError> {
+        let block = self
+            .find_block_by_id(block_id)
+            .ok_or(EditError::BlockNotFound { block_id })?;
+        let text = block_text_seq(&block.kind).ok_or(EditError::NotTextBlock { block_id })?;
+        let offsets = text.offset_map();
+        let intervals = block
+            .marks
+            .resolved_intervals_in(&offsets)
+            .into_iter()
+            .map(|(interval, start, end)| (interval.id, (start, end)))
+            .collect();
+        let top_level = self
+            .blocks
+            .get_element(&block.elem_id)
+            .and_then(|element| element.value.as_ref())
+            .is_some_and(|top| top.id == block_id);
+        Ok(Snapshot {
+            version: top_level.then(|| self.blocks.value_version(block.elem_id)),
+            order: offsets.ids().to_vec(),
+            intervals,
+        })
+    }
+}
+
+fn change_since(cached: &CachedSpans, snapshot: &Snapshot) -> Option<Change> {
+    let (old, new) = (&cached.order, &snapshot.order);
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(old.len().min(new.len()) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let shift = new.len() as isize - old.len() as isize;
+    let (old_tail, new_tail) = (old.len() - suffix, new.len() - suffix);
+
+    let mut dirty: Option<Range<usize>> = (old != new).then_some(prefix..new_tail);
+    let mut widen = |range: Range<usize>| {
+        // An empty interval at an insertion point maps to a reversed range.
+        let range = range.start.min(range.end)..range.start.max(range.end);
+        dirty = Some(match dirty.take() {
+            Some(current) => current.start.min(range.start)..current.end.max(range.end),
+            None => range,
+        });
+    };
+    // A start resolves before its unit and an end after one, so a position on
+    // the edit boundary belongs to the suffix as a start and the prefix as an end.
+    let start_of = |position: usize| {
+        if position >= old_tail {
+            position.saturating_add_signed(shift)
+        } else {
+            position.min(prefix)
+        }
+    };
+    let end_of = |position: usize| {
+        if position <= prefix {
+            position
+        } else if position >= old_tail {
+            position.saturating_add_signed(shift)
+        } else {
+            new_tail
+        }
+    };
+
+    let mut previous: BTreeMap<MarkIntervalId, (usize, usize)> = cached
+        .intervals
+        .iter()
+        .map(|(id, &(start, end))| (*id, (start_of(start), end_of(end))))
+        .collect();
+    for (id, &(start, end)) in &snapshot.intervals {
+        match previous.remove(id) {
+            Some(moved) if moved == (start, end) => {}
+            Some((old_start, old_end)) => {
+                widen(start..end);
+                widen(old_start..old_end);
+            }
+            None => widen(start..end),
+        }
+    }
+    for (start, end) in previous.into_values() {
+        widen(start..end);
+    }
+    dirty.map(|dirty| Change { dirty, shift })
+}
+
+/// Cached spans with the changed positions recomputed.
+fn refresh(cached: &CachedSpans, snapshot: &Snapshot, change: &Change) -> Vec<Span> {
+    let Range {
+        start: from,
+        end: to,
+    } = change.dirty.clone();
+    let old_to = to.saturating_add_signed(-change.shift);
+    let mut spans: Vec<Span> = Vec::with_capacity(cached.spans.len() + 2);
+    for span in cached.spans.iter().filter(|span| span.start < from) {
+        spans.push(Span {
+            start: span.start,
+            end: span.end.min(from),
+            marks: span.marks.clone(),
+        });
+    }
+    spans.extend(spans_over(&snapshot.intervals, from..to));
+    for span in cached.spans.iter().filter(|span| span.end > old_to) {
+        spans.push(Span {
+            start: span.start.max(old_to).saturating_add_signed(change.shift),
+            end: span.end.saturating_add_signed(change.shift),
+            marks: span.marks.clone(),
+        });
+    }
+    coalesce(spans)
+}
+
+/// Spans covering `range` alone, as `render_spans` would split it.
+fn spans_over(
+    intervals: &BTreeMap<MarkIntervalId, (usize, usize)>,
+    range: Range<usize>,
+) -> Vec<Span> {
+    let mut marks_at = vec![Vec::new(); range.len()];
+    for (id, &(start, end)) in intervals {
+        for marks in marks_at
+            .iter_mut()
+            .take(end.min(range.end).saturating_sub(range.start))
+            .skip(start.saturating_sub(range.start))
+        {
+            marks.push(*id);
+        }
+    }
+    let spans = marks_at
+        .into_iter()
+        .enumerate()
+        .map(|(offset, marks)| Span {
+            start: range.start + offset,
+            end: range.start + offset + 1,
+            marks,
+        })
+        .collect();
+    coalesce(spans)
+}
+
+/// Merge adjacent spans with the same marks.
+fn coalesce(spans: Vec<Span>) -> Vec<Span> {
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.marks == span.marks && last.end == span.start => {
+                last.end = span.end;
+            }
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
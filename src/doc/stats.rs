@@ -0,0 +1,149 @@
+//! Size and CRDT-overhead counters for a [`Document`].
+
+use super::{Block, BlockKind, Document, TextUnit};
+use crate::core::mark::MarkInterval;
+use crate::core::{Sequence, SequenceStats};
+use std::mem::size_of;
+
+/// Live blocks by kind, nested ones included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCounts {
+    pub paragraphs: usize,
+    pub headings: usize,
+    pub lists: usize,
+    pub list_items: usize,
+    pub code_fences: usize,
+    pub block_quotes: usize,
+    pub raw_blocks: usize,
+    pub tables: usize,
+}
+
+impl BlockCounts {
+    /// All blocks; list items are not blocks and do not count.
+    pub fn total(&self) -> usize {
+        self.paragraphs
+            + self.headings
+            + self.lists
+            + self.code_fences
+            + self.block_quotes
+            + self.raw_blocks
+            + self.tables
+    }
+}
+
+/// See [`Document::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DocumentStats {
+    pub blocks: BlockCounts,
+    /// Every sequence of the document combined: blocks, list items, text units,
+    /// and table rows and columns.
+    pub elements: SequenceStats,
+    /// List item and table moves or alignments waiting for their target.
+    pub pending_moves: usize,
+    pub active_marks: usize,
+    /// Mark intervals kept only as removed history.
+    pub removed_marks: usize,
+    /// Block nesting depth: 1 for a flat document, 0 when empty.
+    pub max_depth: usize,
+    /// Rough heap footprint: sequence storage plus text, code, raw, and cell bytes.
+    pub estimated_bytes: usize,
+}
+
+impl DocumentStats {
+    pub fn tombstone_ratio(&self) -> f64 {
+        self.elements.tombstone_ratio()
+    }
+
+    /// Buffered sequence operations and pending moves together.
+    pub fn pending_backlog(&self) -> usize {
+        self.elements.pending_ops + self.pending_moves
+    }
+}
+
+impl Document {
+    /// Block, tombstone, backlog, and mark counts, for watching CRDT overhead
+    /// grow and tuning compaction. Walks the whole tree.
+    pub fn stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats {
+            estimated_bytes: self
+                .frontmatter
+                .as_ref()
+                .map_or(0, |frontmatter| frontmatter.render().len()),
+            ..DocumentStats::default()
+        };
+        visit_blocks(&self.blocks, 1, &mut stats);
+        stats.estimated_bytes += stats.elements.estimated_bytes;
+        stats
+    }
+}
+
+fn visit_blocks(blocks: &Sequence<Block>, depth: usize, stats: &mut DocumentStats) {
+    stats.elements += blocks.stats();
+    for block in blocks.iter() {
+        stats.max_depth = stats.max_depth.max(depth);
+        let intervals = block.marks.iter_all_intervals().count();
+        let active = block.marks.iter_active_intervals().count();
+        stats.active_marks += active;
+        stats.removed_marks += intervals - active;
+        stats.estimated_bytes += intervals * size_of::<MarkInterval>();
+
+        let counts = &mut stats.blocks;
+        match &block.kind {
+            BlockKind::Paragraph { text } => {
+                counts.paragraphs += 1;
+                visit_text(text, stats);
+            }
+            BlockKind::Heading { text, .. } => {
+                counts.headings += 1;
+                visit_text(text, stats);
+            }
+            BlockKind::List {
+                items,
+                pending_moves,
+                ..
+            } => {
+                counts.lists += 1;
+                counts.list_items += items.len_visible();
+                stats.pending_moves += pending_moves.len();
+                stats.elements += items.stats();
+                for item in items.iter() {
+                    visit_blocks(&item.children, depth + 1, stats);
+                }
+            }
+            BlockKind::CodeFence { info, text, .. } => {
+                counts.code_fences += 1;
+                stats.estimated_bytes += text.len() + info.as_ref().map_or(0, String::len);
+            }
+            BlockKind::BlockQuote { children } => {
+                counts.block_quotes += 1;
+                visit_blocks(children, depth + 1, stats);
+            }
+            BlockKind::RawBlock { raw } => {
+                counts.raw_blocks += 1;
+                stats.estimated_bytes += raw.len();
+            }
+            BlockKind::Table { table } => {
+                counts.tables += 1;
+                stats.elements += table.rows.stats();
+                stats.elements += table.columns.stats();
+                stats.pending_moves += table.pending_row_moves.len()
+                    + table.pending_column_moves.len()
+                    + table.pending_column_alignments.len();
+                stats.estimated_bytes += table
+                    .cells
+                    .values()
+                    .map(|cell| size_of_val(cell) + cell.value.len())
+                    .sum::<usize>();
+            }
+        }
+    }
+}
+
+fn visit_text(text: &Sequence<TextUnit>, stats: &mut DocumentStats) {
+    stats.elements += text.stats();
+    stats.estimated_bytes += text
+        .iter_all()
+        .filter_map(|element| element.value.as_ref())
+        .map(|unit| unit.grapheme.len())
+        .sum::<usize>();
+}
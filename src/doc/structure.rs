@@ -0,0 +1,215 @@
+//! Structural comparison of documents without serializing them.
+//!
+//! [`EquivalenceMode::Structural`](super::EquivalenceMode::Structural) compares
+//! normalized Markdown, so a raw block and a paragraph with the same text look the
+//! same. Here the block trees are compared instead: kinds and nesting, contents
+//! with trailing whitespace trimmed from each line, and what marks mean. Replica
+//! identity (OpIds, block ids, CRDT metadata) and purely presentational choices
+//! (bullet, fence, and emphasis delimiters) do not count.
+
+use super::{Block, BlockKind, ColumnAlignment, Document, ListItem, TaskState};
+use super::{block_text_seq, paragraph_visible_ids};
+use crate::core::Sequence;
+use crate::core::mark::{MarkKind, MarkValue};
+use crate::workspace::stable_hash_128;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Tree {
+    frontmatter: Option<String>,
+    blocks: Vec<Node>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+enum Node {
+    Paragraph(Text),
+    Heading {
+        level: u8,
+        text: Text,
+    },
+    List {
+        /// `None` for bullet lists, whose start number means nothing.
+        start: Option<u32>,
+        loose: bool,
+        items: Vec<Item>,
+    },
+    CodeFence {
+        info: Option<String>,
+        text: String,
+    },
+    BlockQuote(Vec<Node>),
+    Raw(String),
+    Table {
+        alignments: Vec<ColumnAlignment>,
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Item {
+    task: Option<TaskState>,
+    children: Vec<Node>,
+}
+
+type Mark = (MarkKind, Vec<(String, MarkValue)>);
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Text {
+    text: String,
+    /// Maximal runs `(start, end, marks)` in graphemes of the trimmed text.
+    marks: Vec<(usize, usize, BTreeSet<Mark>)>,
+}
+
+impl Document {
+    /// Whether both documents have the same block tree and normalized contents,
+    /// regardless of which replica created them or how they would be serialized.
+    pub fn structurally_equal(&self, other: &Document) -> bool {
+        structure(self) == structure(other)
+    }
+
+    /// Stable digest of what [`Self::structurally_equal`] compares; equal
+    /// documents have equal digests on every platform and replica.
+    pub fn structural_digest(&self) -> u128 {
+        let encoded = serde_json::to_vec(&structure(self)).expect("structure encodes as JSON");
+        stable_hash_128(&encoded)
+    }
+}
+
+fn structure(document: &Document) -> Tree {
+    Tree {
+        frontmatter: document
+            .frontmatter
+            .as_ref()
+            .map(|frontmatter| trim_line_ends(&frontmatter.render())),
+        blocks: nodes(&document.blocks),
+    }
+}
+
+fn nodes(blocks: &Sequence<Block>) -> Vec<Node> {
+    blocks.iter().map(node).collect()
+}
+
+fn node(block: &Block) -> Node {
+    match &block.kind {
+        BlockKind::Paragraph { .. } => Node::Paragraph(text(block)),
+        BlockKind::Heading { level, .. } => Node::Heading {
+            level: *level,
+            text: text(block),
+        },
+        BlockKind::List { style, items, .. } => Node::List {
+            start: style.ordered.then_some(style.start),
+            loose: style.loose,
+            items: items.iter().map(item).collect(),
+        },
+        BlockKind::CodeFence { info, text, .. } => Node::CodeFence {
+            info: info
+                .as_deref()
+                .map(str::trim)
+                .filter(|info| !info.is_empty())
+                .map(str::to_string),
+            text: trim_line_ends(text),
+        },
+        BlockKind::BlockQuote { children } => Node::BlockQuote(nodes(children)),
+        BlockKind::RawBlock { raw } => Node::Raw(trim_line_ends(raw)),
+        BlockKind::Table { table } => Node::Table {
+            alignments: table
+                .columns
+                .iter()
+                .map(|column| column.alignment.get_ref().clone())
+                .collect(),
+            header: trim_cells(table.row_cells(table.header_row_id())),
+            rows: table
+                .rows
+                .iter()
+                .filter(|row| !*row.deleted.get_ref())
+                .map(|row| trim_cells(table.row_cells(row.id)))
+                .collect(),
+        },
+    }
+}
+
+fn item(item: &ListItem) -> Item {
+    Item {
+        task: item.task,
+        children: nodes(&item.children),
+    }
+}
+
+fn text(block: &Block) -> Text {
+    let Some(units) = block_text_seq(&block.kind) else {
+        return Text {
+            text: String::new(),
+            marks: Vec::new(),
+        };
+    };
+    let graphemes: Vec<&str> = units.iter().map(|unit| unit.grapheme.as_str()).collect();
+
+    // Drop whitespace that only runs up to a line break or the end of the text,
+    // and map every original grapheme offset to its offset in what is kept.
+    let mut kept = vec![true; graphemes.len()];
+    let mut trailing = true;
+    for (index, grapheme) in graphemes.iter().enumerate().rev() {
+        if *grapheme == "\n" {
+            trailing = true;
+        } else if trailing && grapheme.trim().is_empty() {
+            kept[index] = false;
+        } else {
+            trailing = false;
+        }
+    }
+    let mut offsets = Vec::with_capacity(graphemes.len() + 1);
+    let mut text = String::new();
+    let mut position = 0;
+    for (grapheme, keep) in graphemes.iter().zip(&kept) {
+        offsets.push(position);
+        if *keep {
+            text.push_str(grapheme);
+            position += 1;
+        }
+    }
+    offsets.push(position);
+
+    let mut marks: Vec<(usize, usize, BTreeSet<Mark>)> = Vec::new();
+    let ids = paragraph_visible_ids(units);
+    for span in block.marks.render_spans(&ids, ids.len()) {
+        let (start, end) = (offsets[span.start], offsets[span.end]);
+        let set: BTreeSet<Mark> = span
+            .marks
+            .iter()
+            .filter_map(|interval_id| block.marks.interval(interval_id))
+            .map(|interval| {
+                let attrs = interval
+                    .attr_values()
+                    .into_iter()
+                    .filter(|(key, _)| key != "delimiter")
+                    .collect();
+                (interval.kind.clone(), attrs)
+            })
+            .collect();
+        if start == end || set.is_empty() {
+            continue;
+        }
+        match marks.last_mut() {
+            Some((_, prior_end, prior)) if *prior_end == start && *prior == set => {
+                *prior_end = end;
+            }
+            _ => marks.push((start, end, set)),
+        }
+    }
+    Text { text, marks }
+}
+
+fn trim_line_ends(text: &str) -> String {
+    text.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+fn trim_cells(cells: Vec<String>) -> Vec<String> {
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
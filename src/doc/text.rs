@@ -1,7 +1,6 @@
 //! Grapheme-level paragraph text as a CRDT sequence of units.
 
-use crate::core::{OpId, PeerId, Sequence};
-use unicode_segmentation::UnicodeSegmentation;
+use crate::core::{OpId, PeerId, SegmentationRules, Sequence, VisibleText};
 
 /// One grapheme cluster in a paragraph sequence.
 ///
@@ -11,20 +10,36 @@ pub struct TextUnit {
     pub grapheme: String,
 }
 
+impl VisibleText for TextUnit {
+    fn push_visible(&self, out: &mut String) {
+        out.push_str(&self.grapheme);
+    }
+}
+
 /// Number of grapheme clusters in `s` — the unit granularity `units_from_str` allocates.
 ///
-/// Uses the same `graphemes(true)` segmentation so callers can predict exactly how many
-/// unit OpIds an expansion of `s` will consume.
+/// Uses the same [`SegmentationRules::CURRENT`] segmentation so callers can predict exactly
+/// how many unit OpIds an expansion of `s` will consume.
 pub fn grapheme_count(s: &str) -> usize {
-    s.graphemes(true).count()
+    SegmentationRules::CURRENT.count(s)
 }
 
 /// Build a paragraph unit sequence from a string, allocating sequential OpIds.
 ///
 /// Starts at `*counter` for peer `peer` and advances `counter` past the last unit.
 pub fn units_from_str(s: &str, counter: &mut u64, peer: PeerId) -> Sequence<TextUnit> {
+    units_from_str_with(s, counter, peer, SegmentationRules::CURRENT)
+}
+
+/// [`units_from_str`] segmenting with `rules`.
+pub(crate) fn units_from_str_with(
+    s: &str,
+    counter: &mut u64,
+    peer: PeerId,
+    rules: SegmentationRules,
+) -> Sequence<TextUnit> {
     let mut items = Vec::new();
-    for g in s.graphemes(true) {
+    for g in rules.graphemes(s) {
         let id = OpId {
             counter: *counter,
             peer,
@@ -48,11 +63,7 @@ pub fn units_from_str_at(s: &str, start: OpId) -> Sequence<TextUnit> {
 
 /// Visible paragraph text (skips tombstoned units).
 pub fn paragraph_visible_string(seq: &Sequence<TextUnit>) -> String {
-    let mut out = String::new();
-    for unit in seq.iter() {
-        out.push_str(&unit.grapheme);
-    }
-    out
+    seq.visible_string()
 }
 
 /// Visible unit element ids in order.
@@ -65,11 +76,7 @@ pub fn paragraph_visible_ids(seq: &Sequence<TextUnit>) -> Vec<OpId> {
 
 /// Grapheme offset → left anchor for insert (`None` = start of paragraph).
 pub fn after_for_grapheme_offset(seq: &Sequence<TextUnit>, grapheme_offset: usize) -> Option<OpId> {
-    if grapheme_offset == 0 {
-        return None;
-    }
-    let ids = paragraph_visible_ids(seq);
-    ids.get(grapheme_offset - 1).copied()
+    seq.offset_map().after_for_offset(grapheme_offset)
 }
 
 /// Insert graphemes into a paragraph sequence starting at `op_id.counter`.
@@ -80,6 +87,23 @@ pub fn insert_graphemes(
     grapheme_offset: usize,
     text: &str,
     op_id: OpId,
+) -> Option<usize> {
+    insert_graphemes_with(
+        seq,
+        grapheme_offset,
+        text,
+        op_id,
+        SegmentationRules::CURRENT,
+    )
+}
+
+/// [`insert_graphemes`] segmenting `text` with `rules`.
+pub(crate) fn insert_graphemes_with(
+    seq: &mut Sequence<TextUnit>,
+    grapheme_offset: usize,
+    text: &str,
+    op_id: OpId,
+    rules: SegmentationRules,
 ) -> Option<usize> {
     let visible_len = seq.len_visible();
     if grapheme_offset > visible_len {
@@ -88,7 +112,7 @@ pub fn insert_graphemes(
     let mut after = after_for_grapheme_offset(seq, grapheme_offset);
     let mut counter = op_id.counter;
     let mut n = 0usize;
-    for g in text.graphemes(true) {
+    for g in rules.graphemes(text) {
         let id = OpId {
             counter,
             peer: op_id.peer,
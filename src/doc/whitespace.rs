@@ -0,0 +1,159 @@
+//! Trailing whitespace and line endings of serialized Markdown.
+//!
+//! [`EquivalenceMode::Structural`](super::EquivalenceMode::Structural) strips
+//! trailing spaces and the final newline and writes LF; exact serialization
+//! keeps whatever the source had. A [`WhitespacePolicy`] picks each of those
+//! separately, so a vault can keep its files' final newline and CRLF endings
+//! under structural exports, or keep the spaces that matter inside code fences
+//! while dropping the rest.
+
+use serde::{Deserialize, Serialize};
+
+/// Which trailing whitespace survives serialization.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TrailingSpaces {
+    /// Strip them from every line.
+    Strip,
+    /// Keep them on lines inside fenced code blocks only.
+    InCodeFences,
+    /// Keep them everywhere.
+    #[default]
+    Keep,
+}
+
+/// Whether the output ends with a line ending.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FinalNewline {
+    /// No line ending after the last line, and no trailing blank lines.
+    Strip,
+    /// End non-empty output with a line ending.
+    Ensure,
+    /// Do as the document's source did; [`Self::Ensure`] without one.
+    #[default]
+    MatchSource,
+}
+
+/// The line ending written between lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    /// The ending of the first line of the document's source; LF without one.
+    #[default]
+    MatchSource,
+}
+
+/// Trailing whitespace, final newline and line endings of serialized output.
+///
+/// The default keeps what the document's source did: trailing spaces stay,
+/// and the final newline and line endings follow the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WhitespacePolicy {
+    pub trailing_spaces: TrailingSpaces,
+    pub final_newline: FinalNewline,
+    pub line_endings: LineEndings,
+}
+
+impl WhitespacePolicy {
+    /// What structural serialization does without a policy.
+    pub const STRUCTURAL: Self = Self {
+        trailing_spaces: TrailingSpaces::Strip,
+        final_newline: FinalNewline::Strip,
+        line_endings: LineEndings::Lf,
+    };
+
+    /// `text` reshaped under this policy. `source` is the Markdown the document
+    /// was parsed from, which the `MatchSource` settings follow.
+    pub fn apply(&self, text: &str, source: Option<&str>) -> String {
+        let ending = match self.line_endings {
+            LineEndings::Lf => "\n",
+            LineEndings::Crlf => "\r\n",
+            LineEndings::MatchSource => match source.and_then(|source| source.split_once('\n')) {
+                Some((first, _)) if first.ends_with('\r') => "\r\n",
+                _ => "\n",
+            },
+        };
+        let final_newline = match self.final_newline {
+            FinalNewline::MatchSource => match source {
+                Some(source) if !source.is_empty() && !source.ends_with('\n') => {
+                    FinalNewline::Strip
+                }
+                _ => FinalNewline::Ensure,
+            },
+            setting => setting,
+        };
+
+        let body = text
+            .strip_suffix('\n')
+            .map_or(text, |body| body.strip_suffix('\r').unwrap_or(body));
+        let mut fences = FenceTracker::default();
+        let mut output = String::with_capacity(text.len() + text.len() / 16);
+        for (index, line) in body.split('\n').enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if index > 0 {
+                output.push_str(ending);
+            }
+            output.push_str(trim_trailing(line, self.trailing_spaces, &mut fences));
+        }
+        match final_newline {
+            FinalNewline::Strip => {
+                while let Some(rest) = output.strip_suffix(ending) {
+                    output.truncate(rest.len());
+                }
+            }
+            _ if output.is_empty() => {}
+            _ => output.push_str(ending),
+        }
+        output
+    }
+}
+
+/// `line` without the trailing whitespace `policy` drops there.
+pub(super) fn trim_trailing<'a>(
+    line: &'a str,
+    policy: TrailingSpaces,
+    fences: &mut FenceTracker,
+) -> &'a str {
+    let fenced = fences.inside(line);
+    match policy {
+        TrailingSpaces::Keep => line,
+        TrailingSpaces::InCodeFences if fenced => line,
+        _ => line.trim_end(),
+    }
+}
+
+/// Follows code fences line by line. Fences in block quotes and list items are
+/// found after the quote markers and indentation in front of them.
+#[derive(Debug, Default)]
+pub(super) struct FenceTracker {
+    open: Option<(char, usize)>,
+}
+
+impl FenceTracker {
+    /// Whether `line` is content of a fenced code block; fence lines are not.
+    pub(super) fn inside(&mut self, line: &str) -> bool {
+        let content = line.trim_start_matches([' ', '\t', '>']);
+        let marker = content.chars().next().filter(|c| matches!(c, '`' | '~'));
+        let run = marker.map_or(0, |marker| {
+            content.len() - content.trim_start_matches(marker).len()
+        });
+        match (self.open, marker) {
+            (Some((open, length)), Some(marker))
+                if marker == open
+                    && run >= length
+                    && content[run..].trim_end_matches([' ', '\t']).is_empty() =>
+            {
+                self.open = None;
+                false
+            }
+            (Some(_), _) => true,
+            (None, Some(marker))
+                if run >= 3 && !(marker == '`' && content[run..].contains('`')) =>
+            {
+                self.open = Some((marker, run));
+                false
+            }
+            (None, _) => false,
+        }
+    }
+}
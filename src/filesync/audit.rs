@@ -0,0 +1,152 @@
+//! Cross-check of `.mdcrdt/state` against the vault's Markdown files.
+
+use super::state_key::stored_entries;
+use super::{Vault, VaultError};
+use crate::storage::{Storage, StorageError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What is wrong with the recorded state of one file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditIssue {
+    /// State for a file that was deleted or is now ignored.
+    OrphanedState,
+    /// A vault file with no recorded state.
+    MissingState,
+    /// The newest snapshot's bytes do not match their recorded checksum or length.
+    ChecksumMismatch { reason: String },
+    /// The snapshot or an operation segment cannot be read or decoded.
+    CorruptSnapshot { reason: String },
+}
+
+/// One problem found by [`Vault::audit`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditFinding {
+    /// Vault-relative Markdown path; for orphaned state, the path the state
+    /// was recorded for (in key form, see [`super::state_key`]).
+    pub path: PathBuf,
+    /// Vault-relative path of the state entry.
+    pub state_path: PathBuf,
+    pub issue: AuditIssue,
+}
+
+/// Result of [`Vault::audit`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VaultAudit {
+    /// Vault files and state entries looked at.
+    pub checked: usize,
+    /// Problems in path order.
+    pub findings: Vec<AuditFinding>,
+}
+
+impl VaultAudit {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Vault {
+    /// Check every `.mdcrdt/state` entry against the live files: state left
+    /// behind by deleted files, files without state, and snapshots that fail
+    /// [`Storage::verify`] or do not decode. Reads only.
+    pub fn audit(&self) -> Result<VaultAudit, VaultError> {
+        let state_root = self.state_root();
+        let mut live = BTreeMap::new();
+        for file in self.files() {
+            let relative = file.strip_prefix(&self.path).unwrap_or(&file).to_path_buf();
+            let state = self.state_path_for(&file);
+            let key = state
+                .strip_prefix(&state_root)
+                .unwrap_or(&state)
+                .to_path_buf();
+            live.insert(key, relative);
+        }
+        let stored: BTreeSet<PathBuf> = stored_entries(&state_root, ".mdcrdt")?
+            .into_iter()
+            .collect();
+        let mut audit = VaultAudit {
+            checked: live.len(),
+            findings: Vec::new(),
+        };
+
+        for key in &stored {
+            if live.contains_key(key) {
+                continue;
+            }
+            audit.checked += 1;
+            audit.findings.push(AuditFinding {
+                path: key.with_extension("md"),
+                state_path: self.vault_relative(&state_root.join(key)),
+                issue: AuditIssue::OrphanedState,
+            });
+        }
+        for (key, relative) in &live {
+            let state = state_root.join(key);
+            let issue = if !stored.contains(key) {
+                Some(AuditIssue::MissingState)
+            } else {
+                self.check_state(&state, relative)?
+            };
+            if let Some(issue) = issue {
+                audit.findings.push(AuditFinding {
+                    path: relative.clone(),
+                    state_path: self.vault_relative(&state),
+                    issue,
+                });
+            }
+        }
+        audit.findings.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(audit)
+    }
+
+    /// Resolve the findings of an [`Vault::audit`]: remove orphaned state, and
+    /// record missing or damaged state afresh from the file on disk, as
+    /// [`Vault::flush`] would. Returns the number of findings fixed.
+    pub fn fix_audit(&self, audit: &VaultAudit) -> Result<usize, VaultError> {
+        for finding in &audit.findings {
+            let state = self.path.join(&finding.state_path);
+            if state.is_dir() {
+                fs::remove_dir_all(&state)?;
+            }
+            if finding.issue != AuditIssue::OrphanedState {
+                let file = self.path.join(&finding.path);
+                self.record_flushed(&file)
+                    .map_err(|error| error.in_file(&file))?;
+            }
+        }
+        Ok(audit.findings.len())
+    }
+
+    fn check_state(&self, state: &Path, file: &Path) -> Result<Option<AuditIssue>, VaultError> {
+        match Storage::open(state)?.verify() {
+            Ok(()) => {}
+            Err(StorageError::Io(error)) => return Err(error.into()),
+            Err(StorageError::Missing) => return Ok(Some(AuditIssue::MissingState)),
+            Err(StorageError::Corrupt(
+                reason @ ("checksum mismatch" | "length mismatch" | "superblock checksum"),
+            )) => {
+                return Ok(Some(AuditIssue::ChecksumMismatch {
+                    reason: reason.to_string(),
+                }));
+            }
+            Err(error) => {
+                return Ok(Some(AuditIssue::CorruptSnapshot {
+                    reason: error.to_string(),
+                }));
+            }
+        }
+        match self.read_last_flushed(file) {
+            Ok(_) => Ok(None),
+            Err(VaultError::Serialization) => Ok(Some(AuditIssue::CorruptSnapshot {
+                reason: "undecodable fingerprint state".to_string(),
+            })),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn vault_relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.path).unwrap_or(path).to_path_buf()
+    }
+}
@@ -0,0 +1,364 @@
+//! Single-file vault bundles for moving a vault between machines.
+//!
+//! A bundle holds the vault's Markdown files, everything under `.mdcrdt`, and
+//! the staging files of unfinished exports, so an interrupted transaction is
+//! still recovered after the move. Layout:
+//!
+//! ```text
+//! magic "MDCRDTBN" | version u16 | manifest length u64 | manifest JSON | manifest crc32
+//! file contents, concatenated in manifest order
+//! ```
+//!
+//! Integers are little-endian. Manifest paths are vault-relative with `/`
+//! separators, so a bundle does not depend on where the source vault lived and
+//! imports under any root. The peer id travels with the state: the imported
+//! vault continues as the same replica, and the source should not keep editing.
+//!
+//! Import accepts only what an export writes: notes, their export sidecars, and
+//! the known files and directories of `.mdcrdt`. It stages every file in a
+//! hidden directory of the vault root and then renames them into place, with
+//! `.mdcrdt` last, so a failed import leaves no state behind.
+
+use super::{Vault, VaultConfig, VaultError};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+const BUNDLE_MAGIC: &[u8; 8] = b"MDCRDTBN";
+const BUNDLE_VERSION: u16 = 1;
+const HEADER_LEN: usize = 8 + 2 + 8;
+
+/// Files directly under `.mdcrdt` that a bundle may carry.
+const STATE_FILES: &[&str] = &[
+    "config.toml",
+    "manifest.json",
+    "membership.json",
+    "peer_id",
+    "state_format",
+    "vault_id",
+];
+
+/// Directories under `.mdcrdt` whose files a bundle may carry.
+const STATE_DIRS: &[&str] = &[
+    "document_ids",
+    "frontmatter",
+    "search",
+    "sessions",
+    "state",
+    "transactions",
+];
+
+/// Contents of a bundle, from [`Vault::export_bundle`] or [`Vault::import_bundle`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BundleManifest {
+    /// Files in path order.
+    pub files: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BundleEntry {
+    /// Vault-relative path with `/` separators.
+    pub path: String,
+    pub len: u64,
+    pub crc32: u32,
+}
+
+impl BundleManifest {
+    /// Total size of the bundled files.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|entry| entry.len).sum()
+    }
+}
+
+impl Vault {
+    /// Write the vault's Markdown files and `.mdcrdt` state to the bundle file
+    /// at `path`, replacing it.
+    pub fn export_bundle(&self, path: impl AsRef<Path>) -> Result<BundleManifest, VaultError> {
+        let path = path.as_ref();
+        // An existing bundle inside `.mdcrdt` is being replaced, not bundled.
+        let replaced = fs::canonicalize(path).ok();
+
+        let mut files: Vec<PathBuf> = self
+            .files()
+            .filter_map(|file| file.strip_prefix(&self.path).ok().map(Path::to_path_buf))
+            .collect();
+        let state = self.path.join(".mdcrdt");
+        files.extend(
+            WalkDir::new(&state)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .filter(|entry| {
+                    replaced.is_none() || fs::canonicalize(entry.path()).ok() != replaced
+                })
+                .filter_map(|entry| {
+                    entry
+                        .path()
+                        .strip_prefix(&self.path)
+                        .ok()
+                        .map(Path::to_path_buf)
+                }),
+        );
+        files.extend(
+            super::session::transaction_sidecars(self)?
+                .into_iter()
+                .filter(|sidecar| self.path.join(sidecar).is_file()),
+        );
+        files.sort();
+        files.dedup();
+
+        let mut manifest = BundleManifest { files: Vec::new() };
+        let mut contents = Vec::new();
+        for rel in &files {
+            let bytes =
+                fs::read(self.path.join(rel)).map_err(|err| VaultError::from(err).in_file(rel))?;
+            manifest.files.push(BundleEntry {
+                path: portable_path(rel)?,
+                len: bytes.len() as u64,
+                crc32: crc32fast::hash(&bytes),
+            });
+            contents.push(bytes);
+        }
+
+        let encoded = serde_json::to_vec(&manifest).map_err(|_| VaultError::Serialization)?;
+        let mut out =
+            Vec::with_capacity(HEADER_LEN + encoded.len() + 4 + manifest.total_bytes() as usize);
+        out.extend_from_slice(BUNDLE_MAGIC);
+        out.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+        out.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+        out.extend_from_slice(&encoded);
+        out.extend_from_slice(&crc32fast::hash(&encoded).to_le_bytes());
+        for bytes in contents {
+            out.extend_from_slice(&bytes);
+        }
+        fs::write(path, out)?;
+        Ok(manifest)
+    }
+
+    /// Restore the bundle at `path` into this vault, which must not have
+    /// `.mdcrdt` state yet. Every checksum and path is verified before anything
+    /// is written, and no existing file is overwritten. A failure part-way
+    /// removes what the import wrote. Reloads [`Vault::config`] afterwards.
+    pub fn import_bundle(&mut self, path: impl AsRef<Path>) -> Result<BundleManifest, VaultError> {
+        let bytes = fs::read(path.as_ref())?;
+        let (manifest, contents) = decode_bundle(&bytes)?;
+
+        let state = self.path.join(".mdcrdt");
+        if state.exists() {
+            return Err(VaultError::PathAlreadyExists(state));
+        }
+        let mut seen = BTreeSet::new();
+        let mut rels = Vec::with_capacity(manifest.files.len());
+        for entry in &manifest.files {
+            let rel = vault_relative(&entry.path)?;
+            if !self.is_bundle_entry(&rel) {
+                return Err(VaultError::InvalidBundle(format!(
+                    "unexpected path {:?}",
+                    entry.path
+                )));
+            }
+            if !seen.insert(rel.clone()) {
+                return Err(VaultError::InvalidBundle(format!(
+                    "duplicate path {:?}",
+                    entry.path
+                )));
+            }
+            let target = self.path.join(&rel);
+            if target.exists() {
+                return Err(VaultError::PathAlreadyExists(target));
+            }
+            rels.push(rel);
+        }
+
+        let staging = self.path.join(format!(".mdcrdt-import.{}", Uuid::new_v4()));
+        let result =
+            stage(&staging, &rels, &contents).and_then(|()| self.move_into_place(&staging, &rels));
+        let _ = fs::remove_dir_all(&staging);
+        result?;
+        self.config = VaultConfig::load(&self.path)?;
+        Ok(manifest)
+    }
+
+    /// Whether an export could have written `rel`.
+    fn is_bundle_entry(&self, rel: &Path) -> bool {
+        let mut components = rel.components();
+        if components.next() != Some(Component::Normal(".mdcrdt".as_ref())) {
+            let note = super::session::sidecar_target(rel);
+            return self.is_note(note.as_deref().unwrap_or(rel));
+        }
+        let Some(Component::Normal(first)) = components.next() else {
+            return false;
+        };
+        let nested = components.next().is_some();
+        let first = first.to_str().unwrap_or_default();
+        if nested {
+            STATE_DIRS.contains(&first)
+        } else {
+            STATE_FILES.contains(&first)
+        }
+    }
+
+    /// Rename the staged files into the vault: notes and sidecars first, then
+    /// `.mdcrdt` in one step. On failure the files and directories already
+    /// created are removed again.
+    fn move_into_place(&self, staging: &Path, rels: &[PathBuf]) -> Result<(), VaultError> {
+        let mut created = Vec::new();
+        let result = rels
+            .iter()
+            .filter(|rel| !rel.starts_with(".mdcrdt"))
+            .try_for_each(|rel| {
+                let target = self.path.join(rel);
+                if let Some(parent) = target.parent() {
+                    let missing: Vec<_> = parent
+                        .ancestors()
+                        .take_while(|dir| !dir.exists())
+                        .map(Path::to_path_buf)
+                        .collect();
+                    fs::create_dir_all(parent)?;
+                    created.extend(missing.into_iter().rev());
+                }
+                fs::rename(staging.join(rel), &target)?;
+                created.push(target);
+                Ok(())
+            })
+            .and_then(|()| {
+                let state = staging.join(".mdcrdt");
+                if state.exists() {
+                    fs::rename(state, self.path.join(".mdcrdt"))?;
+                }
+                Ok(())
+            });
+        if result.is_err() {
+            for path in created.iter().rev() {
+                let _ = if path.is_dir() {
+                    fs::remove_dir(path)
+                } else {
+                    fs::remove_file(path)
+                };
+            }
+        }
+        result
+    }
+}
+
+/// Write every bundled file under `staging`.
+fn stage(staging: &Path, rels: &[PathBuf], contents: &[&[u8]]) -> Result<(), VaultError> {
+    for (rel, bytes) in rels.iter().zip(contents) {
+        let target = staging.join(rel);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, bytes).map_err(|err| VaultError::from(err).in_file(rel))?;
+    }
+    Ok(())
+}
+
+fn portable_path(rel: &Path) -> Result<String, VaultError> {
+    let parts: Option<Vec<&str>> = rel
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    parts
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| VaultError::InvalidRelativePath(rel.to_path_buf()))
+}
+
+/// Manifest path to a relative path that cannot leave the vault root.
+fn vault_relative(path: &str) -> Result<PathBuf, VaultError> {
+    let invalid = || VaultError::InvalidBundle(format!("unsafe path {path:?}"));
+    let mut rel = PathBuf::new();
+    for part in path.split('/') {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(normal)), None) if normal == part => rel.push(normal),
+            _ => return Err(invalid()),
+        }
+    }
+    if rel.as_os_str().is_empty() {
+        return Err(invalid());
+    }
+    Ok(rel)
+}
+
+fn decode_bundle(bytes: &[u8]) -> Result<(BundleManifest, Vec<&[u8]>), VaultError> {
+    let invalid = |message: &str| VaultError::InvalidBundle(message.to_string());
+    if bytes.len() < HEADER_LEN || &bytes[..8] != BUNDLE_MAGIC {
+        return Err(invalid("not a vault bundle"));
+    }
+    let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+    if version != BUNDLE_VERSION {
+        return Err(VaultError::InvalidBundle(format!(
+            "unsupported bundle version {version}"
+        )));
+    }
+    let manifest_len = u64::from_le_bytes(bytes[10..HEADER_LEN].try_into().expect("8 bytes"));
+    let manifest_end = usize::try_from(manifest_len)
+        .ok()
+        .and_then(|len| HEADER_LEN.checked_add(len))
+        .filter(|end| end.checked_add(4).is_some_and(|end| end <= bytes.len()))
+        .ok_or_else(|| invalid("truncated manifest"))?;
+    let encoded = &bytes[HEADER_LEN..manifest_end];
+    let stored = u32::from_le_bytes(
+        bytes[manifest_end..manifest_end + 4]
+            .try_into()
+            .expect("4 bytes"),
+    );
+    if crc32fast::hash(encoded) != stored {
+        return Err(invalid("manifest checksum mismatch"));
+    }
+    let manifest: BundleManifest =
+        serde_json::from_slice(encoded).map_err(|_| invalid("malformed manifest"))?;
+
+    let mut rest = &bytes[manifest_end + 4..];
+    let mut contents = Vec::with_capacity(manifest.files.len());
+    for entry in &manifest.files {
+        let len = usize::try_from(entry.len)
+            .ok()
+            .filter(|&len| len <= rest.len())
+            .ok_or_else(|| VaultError::InvalidBundle(format!("truncated file {}", entry.path)))?;
+        let (content, tail) = rest.split_at(len);
+        if crc32fast::hash(content) != entry.crc32 {
+            return Err(VaultError::InvalidBundle(format!(
+                "checksum mismatch for {}",
+                entry.path
+            )));
+        }
+        contents.push(content);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(invalid("trailing data after the last file"));
+    }
+    Ok((manifest, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_paths_cannot_leave_the_vault() {
+        assert_eq!(
+            vault_relative("notes/a.md").unwrap(),
+            Path::new("notes").join("a.md")
+        );
+        for path in [
+            "",
+            "/etc/passwd",
+            "../a.md",
+            "notes/../../a.md",
+            "a//b.md",
+            "./a.md",
+        ] {
+            assert!(
+                matches!(vault_relative(path), Err(VaultError::InvalidBundle(_))),
+                "{path:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Per-vault settings read from `.mdcrdt/config.toml`.
+//!
+//! Every key is optional; a missing file or key keeps the built-in default.
+//!
+//! ```toml
+//! # Overrides the id stored in .mdcrdt/peer_id.
+//! peer_id = 7
+//! # Vault files to leave alone. A pattern without `/` matches file names in any
+//! # directory; otherwise it matches the vault-relative path. `*` and `?` stay
+//! # within one path segment, `**` spans any number of them.
+//! ignore = ["drafts/**", "*.tmp.md"]
+//!
+//! [serialize]
+//! style = "exact"        # or "structural"
+//!
+//! [whitespace]
+//! # Shapes exported files after `serialize.style`; any key opts in, and keys
+//! # left out follow the file as it was.
+//! trailing_spaces = "keep"   # or "strip", or "code-fences" to keep them there only
+//! final_newline = "source"   # or "ensure" or "strip"
+//! line_endings = "source"    # or "lf" or "crlf"
+//!
+//! [sync]
+//! remote = "http://sync.example.com:8080/vault"
+//!
+//! [compaction]
+//! tombstones = "keep-all" # or the number of tombstones to keep
+//!
+//! [state]
+//! # Lowercase the keys of per-file state, for vaults shared with
+//! # case-insensitive file systems.
+//! case_fold = false
+//!
+//! [normalize]
+//! # What ingest normalizes before hashing and diffing file text; the file's own
+//! # bytes are still what exact exports reproduce.
+//! line_endings = true    # CRLF and CR to LF
+//! unicode = true         # normalize to Unicode NFC
+//!
+//! [limits]
+//! max_ops_per_message = 10000
+//! max_payload_bytes = 10485760
+//! max_pending_buffer = 100000
+//! ```
+//!
+//! The file is read with a TOML subset: tables of keys whose values are
+//! strings, integers, booleans or arrays of those. Unknown tables and keys are
+//! errors, so a typo does not silently fall back to a default.
+
+use super::TextNormalization;
+use crate::core::PeerId;
+use crate::doc::{
+    EquivalenceMode, FinalNewline, LineEndings, SerializeConfig, TrailingSpaces, WhitespacePolicy,
+};
+use crate::storage::TombstoneRetention;
+use crate::sync::ValidationLimits;
+use std::path::Path;
+
+/// Settings for one vault, from [`VaultConfig::load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultConfig {
+    /// Peer id for every session in the vault, instead of `.mdcrdt/peer_id`.
+    pub peer_id: Option<PeerId>,
+    /// Glob patterns of Markdown files that [`super::Vault::files`] skips.
+    pub ignore: Vec<String>,
+    /// How exports render documents.
+    pub serialize: EquivalenceMode,
+    /// Trailing whitespace, final newline and line endings of exported files;
+    /// `None` leaves them to [`Self::serialize`].
+    pub whitespace: Option<WhitespacePolicy>,
+    /// Default endpoint for `md-crdt watch --remote`.
+    pub remote: Option<String>,
+    /// Tombstones kept when vault storage is compacted.
+    pub compaction: TombstoneRetention,
+    /// Limits for remote changes applied to vault sessions.
+    pub limits: ValidationLimits,
+    /// Whether [`super::state_key`] lowercases keys, so `Notes.md` and
+    /// `notes.md` share state.
+    pub state_case_fold: bool,
+    /// How ingest normalizes file text before hashing and diffing it.
+    pub normalize: TextNormalization,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        Self {
+            peer_id: None,
+            ignore: Vec::new(),
+            serialize: EquivalenceMode::Exact,
+            whitespace: None,
+            remote: None,
+            compaction: TombstoneRetention::KeepAll,
+            limits: ValidationLimits::default(),
+            state_case_fold: false,
+            normalize: TextNormalization::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl VaultConfig {
+    /// Path of the config file for the vault at `root`.
+    pub fn path(root: &Path) -> std::path::PathBuf {
+        root.join(".mdcrdt").join("config.toml")
+    }
+
+    /// Read the config of the vault at `root`; defaults when there is no file.
+    pub fn load(root: &Path) -> Result<Self, super::VaultError> {
+        match std::fs::read_to_string(Self::path(root)) {
+            Ok(text) => Ok(Self::parse(&text)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// How exports serialize documents under these settings.
+    pub fn serialize_config(&self) -> SerializeConfig {
+        let config = match self.serialize {
+            EquivalenceMode::Exact => SerializeConfig::exact(),
+            EquivalenceMode::Structural => SerializeConfig::structural(),
+        };
+        match self.whitespace {
+            Some(policy) => config.with_whitespace(policy),
+            None => config,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        for entry in parse_entries(text)? {
+            config.set(entry)?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, entry: Entry) -> Result<(), ConfigError> {
+        let Entry {
+            line,
+            table,
+            key,
+            value,
+        } = entry;
+        let unknown = || ConfigError::new(line, format!("unknown key `{}`", dotted(&table, &key)));
+        match (table.as_str(), key.as_str()) {
+            ("", "peer_id") => match value.integer(line)? {
+                0 => return Err(ConfigError::new(line, "peer_id must not be 0")),
+                peer => self.peer_id = Some(peer),
+            },
+            ("", "ignore") => {
+                self.ignore = value
+                    .array(line)?
+                    .into_iter()
+                    .map(|pattern| pattern.string(line))
+                    .collect::<Result<_, _>>()?;
+            }
+            ("serialize", "style") => {
+                self.serialize = match value.string(line)?.as_str() {
+                    "exact" => EquivalenceMode::Exact,
+                    "structural" => EquivalenceMode::Structural,
+                    other => {
+                        return Err(ConfigError::new(
+                            line,
+                            format!("style must be \"exact\" or \"structural\", not {other:?}"),
+                        ));
+                    }
+                };
+            }
+            ("whitespace", "trailing_spaces") => {
+                self.whitespace.get_or_insert_default().trailing_spaces = choice(
+                    value,
+                    line,
+                    &key,
+                    &[
+                        ("strip", TrailingSpaces::Strip),
+                        ("code-fences", TrailingSpaces::InCodeFences),
+                        ("keep", TrailingSpaces::Keep),
+                    ],
+                )?;
+            }
+            ("whitespace", "final_newline") => {
+                self.whitespace.get_or_insert_default().final_newline = choice(
+                    value,
+                    line,
+                    &key,
+                    &[
+                        ("strip", FinalNewline::Strip),
+                        ("ensure", FinalNewline::Ensure),
+                        ("source", FinalNewline::MatchSource),
+                    ],
+                )?;
+            }
+            ("whitespace", "line_endings") => {
+                self.whitespace.get_or_insert_default().line_endings = choice(
+                    value,
+                    line,
+                    &key,
+                    &[
+                        ("lf", LineEndings::Lf),
+                        ("crlf", LineEndings::Crlf),
+                        ("source", LineEndings::MatchSource),
+                    ],
+                )?;
+            }
+            ("sync", "remote") => self.remote = Some(value.string(line)?),
+            ("compaction", "tombstones") => {
+                self.compaction = match value {
+                    Value::String(keep) if keep == "keep-all" => TombstoneRetention::KeepAll,
+                    Value::Integer(max) => TombstoneRetention::MaxCount(to_usize(max, line)?),
+                    _ => {
+                        return Err(ConfigError::new(
+                            line,
+                            "tombstones must be \"keep-all\" or a count",
+                        ));
+                    }
+                };
+            }
+            ("limits", "max_ops_per_message") => {
+                self.limits.max_ops_per_message = to_usize(value.integer(line)?, line)?;
+            }
+            ("limits", "max_payload_bytes") => {
+                self.limits.max_payload_bytes = to_usize(value.integer(line)?, line)?;
+            }
+            ("limits", "max_pending_buffer") => {
+                self.limits.max_pending_buffer = to_usize(value.integer(line)?, line)?;
+            }
+            ("state", "case_fold") => self.state_case_fold = value.boolean(line)?,
+            ("normalize", "line_endings") => {
+                self.normalize.line_endings = value.boolean(line)?;
+            }
+            ("normalize", "unicode") => self.normalize.unicode = value.boolean(line)?,
+            _ => return Err(unknown()),
+        }
+        Ok(())
+    }
+
+    /// Whether the vault-relative `path` matches an [`Self::ignore`] pattern.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let segments: Vec<String> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        self.ignore.iter().any(|pattern| {
+            if pattern.contains('/') {
+                let pattern: Vec<&str> = pattern.split('/').collect();
+                let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+                match_segments(&pattern, &segments)
+            } else {
+                segments
+                    .last()
+                    .is_some_and(|name| match_segment(pattern.as_bytes(), name.as_bytes()))
+            }
+        })
+    }
+}
+
+fn dotted(table: &str, key: &str) -> String {
+    if table.is_empty() {
+        key.to_string()
+    } else {
+        format!("{table}.{key}")
+    }
+}
+
+/// The setting among `choices` that `value` names.
+fn choice<T: Copy>(
+    value: Value,
+    line: usize,
+    key: &str,
+    choices: &[(&str, T)],
+) -> Result<T, ConfigError> {
+    let name = value.string(line)?;
+    if let Some((_, setting)) = choices.iter().find(|(choice, _)| *choice == name) {
+        return Ok(*setting);
+    }
+    let names: Vec<String> = choices
+        .iter()
+        .map(|(choice, _)| format!("{choice:?}"))
+        .collect();
+    Err(ConfigError::new(
+        line,
+        format!("{key} must be one of {}, not {name:?}", names.join(", ")),
+    ))
+}
+
+fn to_usize(value: u64, line: usize) -> Result<usize, ConfigError> {
+    usize::try_from(value).map_err(|_| ConfigError::new(line, "value is too large"))
+}
+
+/// Match path segments against pattern segments, where `**` matches any run.
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((first, rest)) => path.split_first().is_some_and(|(segment, path)| {
+            match_segment(first.as_bytes(), segment.as_bytes()) && match_segments(rest, path)
+        }),
+    }
+}
+
+/// Match one segment against `*` / `?` wildcards.
+fn match_segment(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_segment(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some((byte, rest)) => name.first() == Some(byte) && match_segment(rest, &name[1..]),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn string(self, line: usize) -> Result<String, ConfigError> {
+        match self {
+            Self::String(value) => Ok(value),
+            _ => Err(ConfigError::new(line, "expected a string")),
+        }
+    }
+
+    fn integer(self, line: usize) -> Result<u64, ConfigError> {
+        match self {
+            Self::Integer(value) => Ok(value),
+            _ => Err(ConfigError::new(line, "expected a non-negative integer")),
+        }
+    }
+
+    fn boolean(self, line: usize) -> Result<bool, ConfigError> {
+        match self {
+            Self::Boolean(value) => Ok(value),
+            _ => Err(ConfigError::new(line, "expected true or false")),
+        }
+    }
+
+    fn array(self, line: usize) -> Result<Vec<Value>, ConfigError> {
+        match self {
+            Self::Array(values) => Ok(values),
+            _ => Err(ConfigError::new(line, "expected an array")),
+        }
+    }
+}
+
+struct Entry {
+    line: usize,
+    table: String,
+    key: String,
+    value: Value,
+}
+
+fn parse_entries(text: &str) -> Result<Vec<Entry>, ConfigError> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    while let Some((line, raw)) = lines.next() {
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(header) = content.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| ConfigError::new(line, "unterminated table header"))?
+                .trim();
+            if !is_bare_key(name) {
+                return Err(ConfigError::new(
+                    line,
+                    format!("invalid table name {name:?}"),
+                ));
+            }
+            table = name.to_string();
+            continue;
+        }
+        let (key, value) = content
+            .split_once('=')
+            .ok_or_else(|| ConfigError::new(line, "expected `key = value`"))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(ConfigError::new(line, format!("invalid key {key:?}")));
+        }
+        // Arrays may continue over the following lines.
+        let mut value = value.trim().to_string();
+        while value.starts_with('[') && !brackets_closed(&value) {
+            let (_, next) = lines
+                .next()
+                .ok_or_else(|| ConfigError::new(line, "unterminated array"))?;
+            value.push(' ');
+            value.push_str(strip_comment(next).trim());
+        }
+        let mut parser = ValueParser {
+            rest: value.as_str(),
+            line,
+        };
+        let parsed = parser.value()?;
+        if !parser.rest.trim().is_empty() {
+            return Err(ConfigError::new(line, "unexpected text after value"));
+        }
+        if entries
+            .iter()
+            .any(|entry: &Entry| entry.table == table && entry.key == key)
+        {
+            return Err(ConfigError::new(
+                line,
+                format!("duplicate key `{}`", dotted(&table, key)),
+            ));
+        }
+        entries.push(Entry {
+            line,
+            table: table.clone(),
+            key: key.to_string(),
+            value: parsed,
+        });
+    }
+    Ok(entries)
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+}
+
+/// `line` up to a `#` that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if ch == '\\' => escaped = true,
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch == '#' => return &line[..index],
+            None => {}
+        }
+    }
+    line
+}
+
+fn brackets_closed(value: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    for ch in value.chars() {
+        match quote {
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch == '[' => depth += 1,
+            None if ch == ']' => depth -= 1,
+            None => {}
+        }
+    }
+    depth <= 0
+}
+
+struct ValueParser<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl ValueParser<'_> {
+    fn error(&self, message: &str) -> ConfigError {
+        ConfigError::new(self.line, message)
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        self.rest = self.rest.trim_start();
+        let mut chars = self.rest.chars();
+        match chars.next() {
+            Some('"') => self.basic_string(),
+            Some('\'') => {
+                let body = &self.rest[1..];
+                let end = body
+                    .find('\'')
+                    .ok_or_else(|| self.error("unterminated string"))?;
+                let value = body[..end].to_string();
+                self.rest = &body[end + 1..];
+                Ok(Value::String(value))
+            }
+            Some('[') => {
+                self.rest = &self.rest[1..];
+                let mut values = Vec::new();
+                loop {
+                    self.rest = self.rest.trim_start();
+                    if let Some(rest) = self.rest.strip_prefix(']') {
+                        self.rest = rest;
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.rest = self.rest.trim_start();
+                    if let Some(rest) = self.rest.strip_prefix(',') {
+                        self.rest = rest;
+                    } else if !self.rest.starts_with(']') {
+                        return Err(self.error("expected `,` or `]` in array"));
+                    }
+                }
+            }
+            Some(_) => {
+                let end = self
+                    .rest
+                    .find(|ch: char| ch == ',' || ch == ']' || ch.is_whitespace())
+                    .unwrap_or(self.rest.len());
+                let token = &self.rest[..end];
+                self.rest = &self.rest[end..];
+                match token {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => token
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| self.error(&format!("invalid value {token:?}"))),
+                }
+            }
+            None => Err(self.error("missing value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<Value, ConfigError> {
+        let mut value = String::new();
+        let mut chars = self.rest[1..].char_indices();
+        while let Some((index, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.rest = &self.rest[index + 2..];
+                    return Ok(Value::String(value));
+                }
+                '\\' => match chars.next().map(|(_, escaped)| escaped) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    _ => return Err(self.error("unsupported escape in string")),
+                },
+                _ => value.push(ch),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_setting() {
+        let config = VaultConfig::parse(
+            r#"
+            peer_id = 7 # this machine
+            ignore = [
+                "drafts/**",
+                '*.tmp.md', # scratch files
+            ]
+
+            [serialize]
+            style = "structural"
+
+            [whitespace]
+            trailing_spaces = "code-fences"
+            line_endings = "crlf"
+
+            [sync]
+            remote = "http://host:8080/sync#main"
+
+            [compaction]
+            tombstones = 1_000
+
+            [limits]
+            max_ops_per_message = 50
+            max_payload_bytes = 4096
+            max_pending_buffer = 10
+
+            [state]
+            case_fold = true
+
+            [normalize]
+            unicode = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.peer_id, Some(7));
+        assert_eq!(config.ignore, vec!["drafts/**", "*.tmp.md"]);
+        assert_eq!(config.serialize, EquivalenceMode::Structural);
+        assert_eq!(
+            config.whitespace,
+            Some(WhitespacePolicy {
+                trailing_spaces: TrailingSpaces::InCodeFences,
+                final_newline: FinalNewline::MatchSource,
+                line_endings: LineEndings::Crlf,
+            })
+        );
+        assert_eq!(
+            config.serialize_config(),
+            SerializeConfig::structural().with_whitespace(config.whitespace.unwrap())
+        );
+        assert_eq!(config.remote.as_deref(), Some("http://host:8080/sync#main"));
+        assert_eq!(config.compaction, TombstoneRetention::MaxCount(1000));
+        assert_eq!(
+            config.limits,
+            ValidationLimits {
+                max_ops_per_message: 50,
+                max_payload_bytes: 4096,
+                max_pending_buffer: 10,
+            }
+        );
+        assert!(config.state_case_fold);
+        assert_eq!(
+            config.normalize,
+            TextNormalization {
+                line_endings: true,
+                unicode: false,
+            }
+        );
+        assert_eq!(VaultConfig::parse("").unwrap(), VaultConfig::default());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_mistyped_values() {
+        let error = |text| VaultConfig::parse(text).unwrap_err();
+        assert_eq!(error("\n[sync]\nremotes = []").line, 3);
+        assert!(
+            error("[sync]\nremotes = []")
+                .message
+                .contains("sync.remotes")
+        );
+        assert!(error("peer_id = \"7\"").message.contains("integer"));
+        assert!(error("peer_id = 0").message.contains("0"));
+        assert!(
+            error("[serialize]\nstyle = \"pretty\"")
+                .message
+                .contains("pretty")
+        );
+        assert!(
+            error("[whitespace]\nline_endings = \"cr\"")
+                .message
+                .contains("\"cr\"")
+        );
+        assert!(
+            error("[state]\ncase_fold = 1")
+                .message
+                .contains("true or false")
+        );
+        assert!(error("ignore = [\"a\"").message.contains("unterminated"));
+        assert!(
+            error("peer_id = 1\npeer_id = 2")
+                .message
+                .contains("duplicate")
+        );
+    }
+
+    #[test]
+    fn ignore_patterns_match_names_or_relative_paths() {
+        let config = VaultConfig {
+            ignore: vec!["drafts/**".into(), "*.tmp.md".into(), "a/?/c.md".into()],
+            ..VaultConfig::default()
+        };
+        let ignored = |path: &str| config.is_ignored(Path::new(path));
+        assert!(ignored("drafts/idea.md"));
+        assert!(ignored("drafts/deep/idea.md"));
+        assert!(ignored("notes/x.tmp.md"));
+        assert!(ignored("a/b/c.md"));
+        assert!(!ignored("notes/drafts.md"));
+        assert!(!ignored("a/bb/c.md"));
+        assert!(!ignored("notes/x.md"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Conflict files for ingests that would silently drop one side.
+//!
+//! Ingest makes the collaborative state match the file on disk. When the state
+//! also moved since the file was last ingested or exported (remote operations
+//! applied but not yet exported), that overwrites the other side. The ingest
+//! still goes ahead, but first writes both versions, with conflict markers
+//! around each differing run of lines, to `<name>.conflict.md` next to the
+//! file, and reports what collided as [`IngestConflict`]s.
+
+use super::block_content;
+use super::diff::{GraphemeStep, myers_steps};
+use crate::doc::{BlockId, Document, Parser, RegisterKey, ResolutionPolicy, ResolutionTarget};
+use crate::session::CollaborativeDocument;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Suffix replacing `.md` in the name of a conflict file.
+pub const CONFLICT_SUFFIX: &str = ".conflict.md";
+
+/// A change in the collaborative state that ingesting the file would undo.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IngestConflict {
+    /// A register under [`ResolutionPolicy::Manual`] that both sides changed to
+    /// different values, or whose unresolved concurrent writes the file would settle.
+    Register { key: RegisterKey },
+    /// A top-level block edited, inserted or deleted in the collaborative state
+    /// whose current version has no exact match on disk.
+    Block { block_id: BlockId },
+}
+
+/// Path of the conflict file for the Markdown file `path`: `notes/a.md` becomes
+/// `notes/a.conflict.md`.
+pub fn conflict_path_for(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{stem}{CONFLICT_SUFFIX}"))
+}
+
+/// Whether `path` names a conflict file, which vault walks skip.
+pub(crate) fn is_conflict_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(CONFLICT_SUFFIX))
+}
+
+/// What ingesting `disk` into `session` would undo, judged against the Markdown
+/// the session last adopted as its source. Empty when the session has no
+/// source state to compare with.
+pub(crate) fn detect_conflicts(
+    session: &CollaborativeDocument,
+    disk: &Document,
+) -> Vec<IngestConflict> {
+    let document = session.document();
+    let Some(source) = document.source() else {
+        return Vec::new();
+    };
+    let base = Parser::parse(source.original());
+    let mut registers = BTreeSet::new();
+
+    let base_fields = frontmatter_fields(&base);
+    let crdt_fields = frontmatter_fields(document);
+    let disk_fields = frontmatter_fields(disk);
+    let keys: BTreeSet<&String> = base_fields
+        .keys()
+        .chain(crdt_fields.keys())
+        .chain(disk_fields.keys())
+        .collect();
+    for key in keys {
+        let target = ResolutionTarget::FrontmatterField(key.clone());
+        if session.resolution_policy(&target) != ResolutionPolicy::Manual {
+            continue;
+        }
+        let field = |fields: &BTreeMap<String, Option<String>>| fields.get(key).cloned().flatten();
+        let (base, crdt, disk) = (
+            field(&base_fields),
+            field(&crdt_fields),
+            field(&disk_fields),
+        );
+        if crdt != base && disk != crdt {
+            registers.insert(RegisterKey::FrontmatterField(key.clone()));
+        }
+    }
+
+    let disk_blocks: HashSet<String> = disk
+        .blocks_in_order()
+        .iter()
+        .map(|block| block_content(&block.kind))
+        .collect();
+    for conflict in session.register_conflicts() {
+        let settled = match &conflict.key {
+            RegisterKey::FrontmatterField(key) => {
+                crdt_fields.get(key).cloned().flatten() != disk_fields.get(key).cloned().flatten()
+            }
+            RegisterKey::TableHeaderCell { table_id, .. } => document
+                .find_block_by_id(*table_id)
+                .is_some_and(|table| !disk_blocks.contains(&block_content(&table.kind))),
+        };
+        if settled {
+            registers.insert(conflict.key);
+        }
+    }
+    let mut conflicts: Vec<IngestConflict> = registers
+        .into_iter()
+        .map(|key| IngestConflict::Register { key })
+        .collect();
+
+    let base_content: HashMap<BlockId, String> = source
+        .order()
+        .iter()
+        .zip(base.blocks_in_order())
+        .map(|(id, block)| (*id, block_content(&block.kind)))
+        .collect();
+    let current = document.blocks_in_order();
+    for block in &current {
+        let changed = match base_content.get(&block.id) {
+            Some(_) => source.is_dirty(block.id),
+            None => true,
+        };
+        if changed && !disk_blocks.contains(&block_content(&block.kind)) {
+            conflicts.push(IngestConflict::Block { block_id: block.id });
+        }
+    }
+    let live: HashSet<BlockId> = current.iter().map(|block| block.id).collect();
+    for id in source.order() {
+        if !live.contains(id)
+            && base_content
+                .get(id)
+                .is_some_and(|content| disk_blocks.contains(content))
+        {
+            conflicts.push(IngestConflict::Block { block_id: *id });
+        }
+    }
+    conflicts
+}
+
+fn frontmatter_fields(document: &Document) -> BTreeMap<String, Option<String>> {
+    document
+        .frontmatter
+        .as_ref()
+        .filter(|frontmatter| frontmatter.is_structured())
+        .map(|frontmatter| {
+            frontmatter
+                .entries()
+                .map(|(key, value)| (key.to_string(), value.map(str::to_string)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Both versions line by line, each differing run between `<<<<<<< disk`,
+/// `=======` and `>>>>>>> crdt` markers.
+pub(crate) fn conflict_markdown(disk: &str, crdt: &str) -> String {
+    let disk_lines: Vec<&str> = disk.split_inclusive('\n').collect();
+    let crdt_lines: Vec<&str> = crdt.split_inclusive('\n').collect();
+    let mut output = String::with_capacity(disk.len() + crdt.len());
+    let mut ours = Vec::new();
+    let mut theirs = Vec::new();
+    for step in myers_steps(&disk_lines, &crdt_lines) {
+        match step {
+            GraphemeStep::Equal { old, .. } => {
+                write_hunk(&mut output, &mut ours, &mut theirs);
+                output.push_str(disk_lines[old]);
+            }
+            GraphemeStep::Delete { old } => ours.push(disk_lines[old]),
+            GraphemeStep::Insert { new } => theirs.push(crdt_lines[new]),
+        }
+    }
+    write_hunk(&mut output, &mut ours, &mut theirs);
+    output
+}
+
+fn write_hunk(output: &mut String, ours: &mut Vec<&str>, theirs: &mut Vec<&str>) {
+    if ours.is_empty() && theirs.is_empty() {
+        return;
+    }
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str("<<<<<<< disk\n");
+    for line in ours.drain(..).chain(["=======\n"]).chain(theirs.drain(..)) {
+        output.push_str(line);
+        if !line.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+    output.push_str(">>>>>>> crdt\n");
+}
@@ -1,7 +1,9 @@
 //! Grapheme-level LCS for vault text ingest (D2).
 //!
 //! Given an existing paragraph's visible units and a desired grapheme sequence,
-//! produce delete/insert steps that preserve OpIds for the LCS.
+//! produce delete/insert steps that preserve OpIds for the LCS. [`myers_steps`]
+//! finds the same kind of alignment in linear space; [`diff_block_text`] folds it
+//! into run-level [`TextEdit`]s for the text ops of a fuzzily matched block.
 
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -59,29 +61,181 @@ pub fn graphemes_of(s: &str) -> Vec<&str> {
     s.graphemes(true).collect()
 }
 
-/// Indices of old units that must be tombstoned (not in LCS), high→low for safe offset deletes.
-pub fn delete_indices_high_to_low(steps: &[GraphemeStep]) -> Vec<usize> {
-    let mut v: Vec<usize> = steps
-        .iter()
-        .filter_map(|s| match s {
-            GraphemeStep::Delete { old } => Some(*old),
-            _ => None,
-        })
-        .collect();
-    v.sort_unstable();
-    v.reverse();
-    v
+/// Shortest edit script between grapheme slices (Myers, linear-space variant).
+///
+/// Same step shape as [`lcs_steps`], but `O((N + M) · D)` time and `O(N + M)`
+/// space, so long blocks with small edits stay cheap.
+pub fn myers_steps(old: &[&str], new: &[&str]) -> Vec<GraphemeStep> {
+    let mut steps = Vec::with_capacity(old.len().max(new.len()));
+    myers_range(old, new, 0, 0, &mut steps);
+    steps
 }
 
-/// New grapheme indices that need insert (not in LCS), in left-to-right order.
-pub fn insert_new_indices(steps: &[GraphemeStep]) -> Vec<usize> {
-    steps
+fn myers_range(
+    old: &[&str],
+    new: &[&str],
+    old_base: usize,
+    new_base: usize,
+    steps: &mut Vec<GraphemeStep>,
+) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
         .iter()
-        .filter_map(|s| match s {
-            GraphemeStep::Insert { new } => Some(*new),
-            _ => None,
-        })
-        .collect()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    steps.extend((0..prefix).map(|i| GraphemeStep::Equal {
+        old: old_base + i,
+        new: new_base + i,
+    }));
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let (old_base_mid, new_base_mid) = (old_base + prefix, new_base + prefix);
+
+    if old_mid.is_empty() {
+        steps.extend((0..new_mid.len()).map(|j| GraphemeStep::Insert {
+            new: new_base_mid + j,
+        }));
+    } else if new_mid.is_empty() {
+        steps.extend((0..old_mid.len()).map(|i| GraphemeStep::Delete {
+            old: old_base_mid + i,
+        }));
+    } else {
+        let (x, y, u, v) = middle_snake(old_mid, new_mid);
+        myers_range(
+            &old_mid[..x],
+            &new_mid[..y],
+            old_base_mid,
+            new_base_mid,
+            steps,
+        );
+        steps.extend((0..u - x).map(|i| GraphemeStep::Equal {
+            old: old_base_mid + x + i,
+            new: new_base_mid + y + i,
+        }));
+        myers_range(
+            &old_mid[u..],
+            &new_mid[v..],
+            old_base_mid + u,
+            new_base_mid + v,
+            steps,
+        );
+    }
+
+    let (old_tail, new_tail) = (old_base + old.len() - suffix, new_base + new.len() - suffix);
+    steps.extend((0..suffix).map(|i| GraphemeStep::Equal {
+        old: old_tail + i,
+        new: new_tail + i,
+    }));
+}
+
+/// Middle snake `(x, y) → (u, v)` of the shortest edit path between two non-empty
+/// slices, found by running the forward and reverse searches until they overlap.
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize, usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let at = |k: isize| (k + offset) as usize;
+    // Furthest x reached on each diagonal k = x - y; the reverse search measures x
+    // from the end of both slices.
+    let mut forward = vec![0isize; (2 * offset + 1) as usize];
+    let mut reverse = vec![0isize; (2 * offset + 1) as usize];
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let mirrored = delta - k;
+            if odd && (-(d - 1)..=d - 1).contains(&mirrored) && x + reverse[at(mirrored)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && reverse[at(k - 1)] < reverse[at(k + 1)]) {
+                reverse[at(k + 1)]
+            } else {
+                reverse[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[(n - 1 - x) as usize] == new[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            reverse[at(k)] = x;
+            let mirrored = delta - k;
+            if !odd && (-d..=d).contains(&mirrored) && x + forward[at(mirrored)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("forward and reverse searches meet within (n + m + 1) / 2 rounds")
+}
+
+/// One run-level edit to a block's visible text. Offsets are graphemes into the
+/// text as left by the edits before it, so applying a script in order turns the
+/// old text into the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEdit {
+    Delete { at: usize, len: usize },
+    Insert { at: usize, text: String },
+}
+
+/// Grapheme-level edit script from `old` to `new`, with adjacent steps merged into
+/// runs. Empty when the texts are equal.
+pub fn diff_block_text(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_g = graphemes_of(old);
+    let new_g = graphemes_of(new);
+    edits_from_steps(&myers_steps(&old_g, &new_g), &new_g)
+}
+
+/// Fold an alignment against `new` into run-level edits.
+pub(crate) fn edits_from_steps(steps: &[GraphemeStep], new: &[&str]) -> Vec<TextEdit> {
+    let mut edits: Vec<TextEdit> = Vec::new();
+    let mut at = 0usize;
+    for step in steps {
+        match *step {
+            GraphemeStep::Equal { .. } => at += 1,
+            GraphemeStep::Delete { .. } => match edits.last_mut() {
+                Some(TextEdit::Delete { at: start, len }) if *start == at => *len += 1,
+                _ => edits.push(TextEdit::Delete { at, len: 1 }),
+            },
+            GraphemeStep::Insert { new: index } => {
+                match edits.last_mut() {
+                    Some(TextEdit::Insert { at: start, text })
+                        if *start + graphemes_of(text).len() == at =>
+                    {
+                        text.push_str(new[index]);
+                    }
+                    _ => edits.push(TextEdit::Insert {
+                        at,
+                        text: new[index].to_string(),
+                    }),
+                }
+                at += 1;
+            }
+        }
+    }
+    edits
 }
 
 #[cfg(test)]
@@ -129,4 +283,77 @@ mod tests {
         let g = graphemes_of("a🇺🇸b");
         assert_eq!(g, vec!["a", "🇺🇸", "b"]);
     }
+
+    fn apply(old: &str, edits: &[TextEdit]) -> String {
+        let mut units: Vec<String> = graphemes_of(old).into_iter().map(String::from).collect();
+        for edit in edits {
+            match edit {
+                TextEdit::Delete { at, len } => {
+                    units.drain(*at..*at + *len);
+                }
+                TextEdit::Insert { at, text } => {
+                    let inserted: Vec<String> =
+                        graphemes_of(text).into_iter().map(String::from).collect();
+                    units.splice(*at..*at, inserted);
+                }
+            }
+        }
+        units.concat()
+    }
+
+    #[test]
+    fn myers_matches_lcs_length() {
+        let cases = [
+            ("abcabba", "cbabac"),
+            ("kitten sitting", "sitting kitten"),
+            ("", "abc"),
+            ("abc", ""),
+            ("same", "same"),
+            ("a🇺🇸b", "🇺🇸ab"),
+        ];
+        for (old, new) in cases {
+            let (old_g, new_g) = (graphemes_of(old), graphemes_of(new));
+            let equal = |steps: &[GraphemeStep]| {
+                steps
+                    .iter()
+                    .filter(|s| matches!(s, GraphemeStep::Equal { .. }))
+                    .count()
+            };
+            let myers = myers_steps(&old_g, &new_g);
+            assert_eq!(
+                equal(&myers),
+                equal(&lcs_steps(&old_g, &new_g)),
+                "{old} → {new}"
+            );
+            for step in &myers {
+                if let GraphemeStep::Equal { old, new } = *step {
+                    assert_eq!(old_g[old], new_g[new]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn diff_block_text_emits_runs_that_rebuild_the_new_text() {
+        let old = "The quick brown fox jumps over the lazy dog";
+        let new = "The quick red fox leaps over the lazy dog!";
+        let edits = diff_block_text(old, new);
+        assert_eq!(apply(old, &edits), new);
+        assert!(edits.iter().all(|edit| match edit {
+            TextEdit::Delete { len, .. } => *len > 0,
+            TextEdit::Insert { text, .. } => !text.is_empty(),
+        }));
+        assert!(diff_block_text(old, old).is_empty());
+        assert_eq!(
+            diff_block_text("ab", "axyb"),
+            vec![TextEdit::Insert {
+                at: 1,
+                text: "xy".into()
+            }]
+        );
+        assert_eq!(
+            diff_block_text("a🇺🇸🇺🇸b", "ab"),
+            vec![TextEdit::Delete { at: 1, len: 2 }]
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! Vault-wide index of structured frontmatter fields.
+//!
+//! Each persisted session snapshot refreshes a per-document field file under
+//! `.mdcrdt/frontmatter/`, rewritten only when the document's fields changed.
+//! [`Vault::frontmatter_index`] reads those files instead of the markdown, so
+//! metadata queries see the CRDT state.
+
+use super::{Vault, VaultError, derived_files, derived_path, write_derived};
+use crate::doc::Document;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const FIELDS_EXTENSION: &str = "fm";
+
+/// One frontmatter value as written, plus its items when it is a flow list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldValue {
+    /// The YAML scalar text after `key:`; empty for a bare `key:`.
+    pub raw: String,
+    /// Unquoted items of a `[a, b]` list, or the unquoted scalar as one item.
+    pub items: Vec<String>,
+}
+
+impl FieldValue {
+    fn parse(raw: &str) -> Self {
+        let trimmed = raw.trim();
+        let items = match trimmed
+            .strip_prefix('[')
+            .and_then(|inner| inner.strip_suffix(']'))
+        {
+            Some(inner) => inner
+                .split(',')
+                .map(unquote)
+                .filter(|item| !item.is_empty())
+                .collect(),
+            None if trimmed.is_empty() => Vec::new(),
+            None => vec![unquote(trimmed)],
+        };
+        Self {
+            raw: raw.to_string(),
+            items,
+        }
+    }
+}
+
+/// Predicate on one field, built with [`exists`], [`equals`], or [`contains`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldFilter {
+    Exists,
+    Equals(String),
+    Contains(String),
+}
+
+/// Field is present, whatever its value.
+pub fn exists() -> FieldFilter {
+    FieldFilter::Exists
+}
+
+/// Unquoted scalar value equals `value`.
+pub fn equals(value: impl Into<String>) -> FieldFilter {
+    FieldFilter::Equals(value.into())
+}
+
+/// A list item (or the scalar itself) equals `value`.
+pub fn contains(value: impl Into<String>) -> FieldFilter {
+    FieldFilter::Contains(value.into())
+}
+
+impl FieldFilter {
+    pub fn matches(&self, value: &FieldValue) -> bool {
+        match self {
+            FieldFilter::Exists => true,
+            FieldFilter::Equals(expected) => unquote(&value.raw) == *expected,
+            FieldFilter::Contains(item) => value.items.iter().any(|candidate| candidate == item),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
+struct IndexedFields {
+    path: String,
+    fields: Vec<(String, String)>,
+}
+
+/// Structured frontmatter of every indexed document, keyed by vault-relative path.
+///
+/// Documents with opaque frontmatter (block scalars, nested YAML) are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontmatterIndex {
+    files: BTreeMap<PathBuf, BTreeMap<String, FieldValue>>,
+}
+
+impl FrontmatterIndex {
+    /// Fields of one document, if it has structured frontmatter.
+    pub fn fields(&self, path: impl AsRef<Path>) -> Option<&BTreeMap<String, FieldValue>> {
+        self.files.get(path.as_ref())
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>, key: &str) -> Option<&FieldValue> {
+        self.fields(path)?.get(key)
+    }
+
+    /// Paths whose `key` field satisfies `filter`, in path order.
+    pub fn files_where(&self, key: &str, filter: FieldFilter) -> Vec<&Path> {
+        self.files
+            .iter()
+            .filter(|(_, fields)| fields.get(key).is_some_and(|value| filter.matches(value)))
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+
+    /// Every key used by any document.
+    pub fn keys(&self) -> BTreeSet<&str> {
+        self.files
+            .values()
+            .flat_map(|fields| fields.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// Each item of `key` across the vault with the number of documents using it.
+    pub fn item_counts(&self, key: &str) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for fields in self.files.values() {
+            if let Some(value) = fields.get(key) {
+                let items: BTreeSet<&str> = value.items.iter().map(String::as_str).collect();
+                for item in items {
+                    *counts.entry(item).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl Vault {
+    /// Frontmatter of every document with a persisted session. Documents whose
+    /// markdown file is gone are skipped.
+    pub fn frontmatter_index(&self) -> Result<FrontmatterIndex, VaultError> {
+        let mut files = BTreeMap::new();
+        for path in derived_files(&fields_root(self), FIELDS_EXTENSION)? {
+            let indexed = decode_fields(&fs::read(path)?)?;
+            if !self.path.join(&indexed.path).exists() {
+                continue;
+            }
+            let fields = indexed
+                .fields
+                .iter()
+                .map(|(key, raw)| (key.clone(), FieldValue::parse(raw)))
+                .collect();
+            files.insert(PathBuf::from(indexed.path), fields);
+        }
+        Ok(FrontmatterIndex { files })
+    }
+}
+
+/// Bring the field file of `rel` up to date with `document`'s frontmatter.
+pub(crate) fn update_frontmatter_index(
+    vault: &Vault,
+    rel: &Path,
+    document: &Document,
+) -> Result<(), VaultError> {
+    let path = derived_path(&fields_root(vault), rel, FIELDS_EXTENSION);
+    let fields: Option<Vec<(String, String)>> = document
+        .frontmatter
+        .as_ref()
+        .filter(|frontmatter| frontmatter.is_structured())
+        .map(|frontmatter| {
+            frontmatter
+                .entries()
+                .filter_map(|(key, value)| Some((key.to_string(), value?.to_string())))
+                .collect()
+        });
+    let previous = match fs::read(&path) {
+        Ok(bytes) => decode_fields(&bytes).ok(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+    let Some(fields) = fields else {
+        if previous.is_some() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    };
+    let next = IndexedFields {
+        path: rel.to_string_lossy().into_owned(),
+        fields,
+    };
+    if previous.as_ref() == Some(&next) {
+        return Ok(());
+    }
+    let bytes =
+        rkyv::to_bytes::<rkyv::rancor::Error>(&next).map_err(|_| VaultError::Serialization)?;
+    write_derived(&path, &bytes)
+}
+
+fn fields_root(vault: &Vault) -> PathBuf {
+    vault.path.join(".mdcrdt").join("frontmatter")
+}
+
+fn decode_fields(bytes: &[u8]) -> Result<IndexedFields, VaultError> {
+    let mut aligned = rkyv::util::AlignedVec::<16>::new();
+    aligned.extend_from_slice(bytes);
+    rkyv::from_bytes::<IndexedFields, rkyv::rancor::Error>(&aligned)
+        .map_err(|_| VaultError::Serialization)
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+    if quoted {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,114 @@
+//! Stat cache that lets ingest skip files that have not changed.
+//!
+//! `.mdcrdt/manifest.json` records each ingested file's size, modification
+//! time and content hash. [`super::VaultSession::ingest_all`] trusts a file
+//! whose size and modification time still match and neither reads nor hashes
+//! it; any mismatch falls back to the hash gate. As in git's index, an entry
+//! modified no earlier than the manifest itself was written is "racy": a
+//! second write in the same timestamp tick would leave its stat unchanged, so
+//! such entries are always re-hashed. The manifest is a cache: a missing or
+//! unreadable one, or one recorded under other normalization settings, is
+//! ignored.
+
+use super::{TextNormalization, Vault, VaultError, write_derived};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size, modification time and normalized content hash of one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FileStamp {
+    pub(crate) len: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub(crate) modified: u64,
+    pub(crate) content_hash: u64,
+}
+
+impl FileStamp {
+    /// `None` when the platform reports no modification time.
+    pub(crate) fn of(metadata: &Metadata, content_hash: u64) -> Option<Self> {
+        Some(Self {
+            len: metadata.len(),
+            modified: nanos(metadata.modified().ok()?)?,
+            content_hash,
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct VaultManifest {
+    line_endings: bool,
+    unicode: bool,
+    files: BTreeMap<PathBuf, FileStamp>,
+    /// Modification time of the manifest file when it was loaded.
+    #[serde(skip)]
+    written: Option<u64>,
+}
+
+impl VaultManifest {
+    pub(crate) fn load(vault: &Vault) -> Self {
+        let path = manifest_path(vault);
+        let normalize = vault.config.normalize;
+        let loaded = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Self>(&bytes).ok())
+            .filter(|manifest| {
+                manifest.line_endings == normalize.line_endings
+                    && manifest.unicode == normalize.unicode
+            });
+        match loaded {
+            Some(mut manifest) => {
+                manifest.written = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(nanos);
+                manifest
+            }
+            None => Self::new(normalize),
+        }
+    }
+
+    fn new(normalize: TextNormalization) -> Self {
+        Self {
+            line_endings: normalize.line_endings,
+            unicode: normalize.unicode,
+            ..Self::default()
+        }
+    }
+
+    /// Whether `rel` is known unchanged from its stat alone.
+    pub(crate) fn is_unchanged(&self, rel: &Path, metadata: &Metadata) -> bool {
+        let (Some(stamp), Some(written)) = (self.files.get(rel), self.written) else {
+            return false;
+        };
+        let modified = metadata.modified().ok().and_then(nanos);
+        stamp.len == metadata.len() && modified == Some(stamp.modified) && stamp.modified < written
+    }
+
+    pub(crate) fn record(&mut self, rel: PathBuf, stamp: Option<FileStamp>) {
+        match stamp {
+            Some(stamp) => self.files.insert(rel, stamp),
+            None => self.files.remove(&rel),
+        };
+    }
+
+    /// Drop entries for files no longer in the vault.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.files.retain(|rel, _| keep(rel));
+    }
+
+    pub(crate) fn save(&self, vault: &Vault) -> Result<(), VaultError> {
+        let encoded = serde_json::to_vec(self).map_err(|_| VaultError::Serialization)?;
+        write_derived(&manifest_path(vault), &encoded)
+    }
+}
+
+fn manifest_path(vault: &Vault) -> PathBuf {
+    vault.path.join(".mdcrdt").join("manifest.json")
+}
+
+fn nanos(time: SystemTime) -> Option<u64> {
+    u64::try_from(time.duration_since(UNIX_EPOCH).ok()?.as_nanos()).ok()
+}
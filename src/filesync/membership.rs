@@ -0,0 +1,175 @@
+//! Replicated vault membership: which files the vault holds and which peers
+//! write to it.
+//!
+//! Files are an [`OrSet`] and peers a [`PeerRegistry`], itself backed by one, so
+//! replicas that exchange [`MembershipOp`]s agree on
+//! them in any delivery order, and a file created on one replica while another
+//! deleted an earlier copy of the same path stays a member. The vault's own
+//! peer tags its writes with counters past the highest it has used here; they
+//! are independent of document operation counters. The state is kept in
+//! `.mdcrdt/membership.json`.
+
+use super::{Vault, VaultError};
+use crate::core::{Mergeable, OpId, OrSet, OrSetOp, PeerId, StateVector};
+use crate::sync::{MembershipError, PeerRegistry};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Replicated update to a [`VaultMembership`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MembershipOp {
+    /// A vault-relative Markdown path added or removed.
+    File(OrSetOp<PathBuf>),
+    /// A peer registered or retired.
+    Peer(OrSetOp<PeerId>),
+}
+
+impl MembershipOp {
+    pub fn id(&self) -> OpId {
+        match self {
+            Self::File(OrSetOp::Add { op_id, .. } | OrSetOp::Remove { op_id, .. })
+            | Self::Peer(OrSetOp::Add { op_id, .. } | OrSetOp::Remove { op_id, .. }) => *op_id,
+        }
+    }
+}
+
+/// Files and peers of a vault, as add-wins sets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultMembership {
+    files: OrSet<PathBuf>,
+    peers: PeerRegistry,
+}
+
+impl VaultMembership {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Member files in path order.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(PathBuf::as_path)
+    }
+
+    pub fn contains_file(&self, rel: &Path) -> bool {
+        self.files.contains(&rel.to_path_buf())
+    }
+
+    /// The peer registry.
+    pub fn peers(&self) -> &PeerRegistry {
+        &self.peers
+    }
+
+    /// Add `rel` as `peer`, returning the op to ship (`None` if already a member).
+    pub fn add_file(&mut self, rel: &Path, peer: PeerId) -> Option<MembershipOp> {
+        if self.contains_file(rel) {
+            return None;
+        }
+        let op_id = self.next_id(peer);
+        Some(MembershipOp::File(self.files.add(rel.to_path_buf(), op_id)))
+    }
+
+    /// Remove `rel` as `peer`, returning the op to ship (`None` if not a member).
+    pub fn remove_file(&mut self, rel: &Path, peer: PeerId) -> Option<MembershipOp> {
+        let op_id = self.next_id(peer);
+        self.files
+            .remove(&rel.to_path_buf(), op_id)
+            .map(MembershipOp::File)
+    }
+
+    /// Register `peer` as written by `by`, returning the op to ship (`None` if
+    /// already registered). A retired id is refused.
+    pub fn register_peer(
+        &mut self,
+        peer: PeerId,
+        by: PeerId,
+    ) -> Result<Option<MembershipOp>, MembershipError> {
+        let op_id = self.next_id(by);
+        Ok(self.peers.register(peer, op_id)?.map(MembershipOp::Peer))
+    }
+
+    /// Retire `peer` as written by `by`, returning the op to ship (`None` if
+    /// already retired). A registration `by` had not seen keeps the peer.
+    pub fn retire_peer(
+        &mut self,
+        peer: PeerId,
+        by: PeerId,
+    ) -> Result<Option<MembershipOp>, MembershipError> {
+        let op_id = self.next_id(by);
+        Ok(self.peers.retire(peer, op_id)?.map(MembershipOp::Peer))
+    }
+
+    /// Apply a local or remote update. Commutative and idempotent.
+    pub fn apply(&mut self, op: MembershipOp) {
+        match op {
+            MembershipOp::File(op) => self.files.apply(op),
+            MembershipOp::Peer(op) => self.peers.apply(op),
+        }
+    }
+
+    /// State-based join with another replica's membership.
+    pub fn merge(&mut self, other: &Self) {
+        self.files.merge(&other.files);
+        self.peers.merge(&other.peers);
+    }
+
+    /// The frontier of every membership write held.
+    pub fn state_vector(&self) -> StateVector {
+        let mut frontier = self.files.frontier();
+        for (peer, counter) in self.peers.state_vector().iter() {
+            if frontier.get(peer).unwrap_or(0) < counter {
+                frontier.set(peer, counter);
+            }
+        }
+        frontier
+    }
+
+    /// Updates a replica at `since` has not seen: file changes, then peer changes.
+    pub fn ops_since(&self, since: &StateVector) -> Vec<MembershipOp> {
+        let files = self
+            .files
+            .ops_since(since)
+            .into_iter()
+            .map(MembershipOp::File);
+        let peers = self
+            .peers
+            .ops_since(since)
+            .into_iter()
+            .map(MembershipOp::Peer);
+        files.chain(peers).collect()
+    }
+
+    fn next_id(&self, peer: PeerId) -> OpId {
+        OpId {
+            counter: self.state_vector().get(peer).unwrap_or(0) + 1,
+            peer,
+        }
+    }
+
+    /// The saved membership; empty when the vault has none yet.
+    pub(crate) fn load(vault: &Vault) -> Result<Self, VaultError> {
+        match fs::read(membership_path(vault)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|_| VaultError::Serialization),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Replace the saved membership durably: unlike the manifest, it cannot be
+    /// rebuilt from the files on disk.
+    pub(crate) fn save(&self, vault: &Vault) -> Result<(), VaultError> {
+        let encoded = serde_json::to_vec(self).map_err(|_| VaultError::Serialization)?;
+        let path = membership_path(vault);
+        let temp = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
+
+fn membership_path(vault: &Vault) -> PathBuf {
+    vault.path.join(".mdcrdt").join("membership.json")
+}
@@ -0,0 +1,190 @@
+//! Format versions of the per-file fingerprint state, and the steps that
+//! upgrade older state in place.
+//!
+//! Every state blob under `.mdcrdt/state` starts with the magic `MDCRDTFS` and a
+//! little-endian `u32` format version, followed by the payload. Blobs written
+//! before versioning have no header and count as version 1. Reads upgrade
+//! older payloads in memory; [`Vault::migrate_state_formats`] rewrites them on
+//! disk, one registered [`StateMigration`] per version step.
+//!
+//! The vault records the version it was last fully migrated to in
+//! `.mdcrdt/state_format`, so opening an up-to-date vault does not read every
+//! state blob.
+
+use super::state_key::stored_entries;
+use super::{Vault, VaultError};
+use crate::storage::{Storage, StorageError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format version written by this build.
+pub const STATE_FORMAT_VERSION: u32 = 2;
+
+const STATE_MAGIC: &[u8; 8] = b"MDCRDTFS";
+const STATE_HEADER_LEN: usize = STATE_MAGIC.len() + 4;
+const STATE_FORMAT_FILE: &str = "state_format";
+
+/// One upgrade step, turning a payload of version `from` into one of `from + 1`.
+#[derive(Debug, Clone, Copy)]
+pub struct StateMigration {
+    pub from: u32,
+    pub description: &'static str,
+    upgrade: fn(Vec<u8>) -> Result<Vec<u8>, VaultError>,
+}
+
+/// Registered steps, ordered by `from`. A format change adds a step here and
+/// bumps [`STATE_FORMAT_VERSION`].
+const STATE_MIGRATIONS: &[StateMigration] = &[StateMigration {
+    from: 1,
+    description: "record the format version in a header; the rkyv payload is unchanged",
+    upgrade: Ok,
+}];
+
+/// The registered upgrade steps, oldest first.
+pub fn state_migrations() -> &'static [StateMigration] {
+    STATE_MIGRATIONS
+}
+
+/// One state blob that was (or, in a dry run, would be) upgraded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigratedState {
+    /// Path under `.mdcrdt`, e.g. `state/notes.mdcrdt`.
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+}
+
+/// What [`Vault::migrate_state_formats`] did, or would do in a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateFormatMigration {
+    pub migrated: Vec<MigratedState>,
+    /// Blobs already at [`STATE_FORMAT_VERSION`].
+    pub current: usize,
+    /// Blobs whose storage could not be read; `audit --fix` re-records them.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl StateFormatMigration {
+    pub fn is_empty(&self) -> bool {
+        self.migrated.is_empty()
+    }
+}
+
+/// `payload` behind a header naming [`STATE_FORMAT_VERSION`].
+pub(super) fn frame_state(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(STATE_HEADER_LEN + payload.len());
+    framed.extend_from_slice(STATE_MAGIC);
+    framed.extend_from_slice(&STATE_FORMAT_VERSION.to_le_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// Format version of a stored blob, and its payload.
+fn split_state(bytes: &[u8]) -> (u32, &[u8]) {
+    match bytes.strip_prefix(STATE_MAGIC.as_slice()) {
+        Some(rest) if rest.len() >= 4 => {
+            let version = u32::from_le_bytes(rest[..4].try_into().expect("fixed slice"));
+            (version, &rest[4..])
+        }
+        _ => (1, bytes),
+    }
+}
+
+/// Payload of a stored blob at [`STATE_FORMAT_VERSION`], running the
+/// registered steps over older ones.
+pub(super) fn upgrade_state(bytes: &[u8]) -> Result<Vec<u8>, VaultError> {
+    let (version, payload) = split_state(bytes);
+    run_migrations(version, payload.to_vec())
+}
+
+fn run_migrations(mut version: u32, mut payload: Vec<u8>) -> Result<Vec<u8>, VaultError> {
+    while version < STATE_FORMAT_VERSION {
+        let step = STATE_MIGRATIONS
+            .iter()
+            .find(|step| step.from == version)
+            .ok_or(VaultError::UnsupportedStateFormat {
+                found: version,
+                supported: STATE_FORMAT_VERSION,
+            })?;
+        payload = (step.upgrade)(payload)?;
+        version += 1;
+    }
+    if version > STATE_FORMAT_VERSION {
+        return Err(VaultError::UnsupportedStateFormat {
+            found: version,
+            supported: STATE_FORMAT_VERSION,
+        });
+    }
+    Ok(payload)
+}
+
+impl Vault {
+    /// Rewrite state blobs older than [`STATE_FORMAT_VERSION`]. With `dry_run`
+    /// nothing is written and the report lists what would change. Fails, before
+    /// writing anything, when a blob is newer than this build understands.
+    pub fn migrate_state_formats(&self, dry_run: bool) -> Result<StateFormatMigration, VaultError> {
+        let root = self.state_root();
+        let mut migration = StateFormatMigration::default();
+        let mut upgrades = Vec::new();
+        for relative in stored_entries(&root, ".mdcrdt")? {
+            let path = Path::new("state").join(&relative);
+            let storage = Storage::open(root.join(&relative))?;
+            let (bytes, pending, seq_ref_index_flag) = match storage.read_snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(StorageError::Missing) => continue,
+                Err(StorageError::Io(err)) => return Err(err.into()),
+                Err(_) => {
+                    migration.skipped.push(path);
+                    continue;
+                }
+            };
+            let (version, payload) = split_state(&bytes);
+            if version == STATE_FORMAT_VERSION {
+                migration.current += 1;
+                continue;
+            }
+            let upgraded = run_migrations(version, payload.to_vec())
+                .map_err(|err| err.in_file(root.join(&relative)))?;
+            migration.migrated.push(MigratedState {
+                path,
+                from: version,
+                to: STATE_FORMAT_VERSION,
+            });
+            upgrades.push((storage, upgraded, pending, seq_ref_index_flag));
+        }
+        if dry_run {
+            return Ok(migration);
+        }
+        for (storage, payload, pending, seq_ref_index_flag) in upgrades {
+            storage.write_snapshot(&frame_state(&payload), &pending, seq_ref_index_flag)?;
+        }
+        if migration.skipped.is_empty() {
+            fs::create_dir_all(self.path.join(".mdcrdt"))?;
+            fs::write(self.state_format_path(), STATE_FORMAT_VERSION.to_string())?;
+        }
+        Ok(migration)
+    }
+
+    /// Run [`Self::migrate_state_formats`] unless the vault already records a
+    /// full migration to [`STATE_FORMAT_VERSION`].
+    /// [`super::VaultSession::open`] calls this on every open.
+    pub fn migrate_state_formats_if_needed(
+        &self,
+    ) -> Result<Option<StateFormatMigration>, VaultError> {
+        if self.recorded_state_format() == Some(STATE_FORMAT_VERSION) {
+            return Ok(None);
+        }
+        self.migrate_state_formats(false).map(Some)
+    }
+
+    /// Version recorded by the last complete migration, if any.
+    pub fn recorded_state_format(&self) -> Option<u32> {
+        fs::read_to_string(self.state_format_path())
+            .ok()
+            .and_then(|text| text.trim().parse().ok())
+    }
+
+    fn state_format_path(&self) -> PathBuf {
+        self.path.join(".mdcrdt").join(STATE_FORMAT_FILE)
+    }
+}
@@ -3,12 +3,49 @@
 //! This module provides vault-based file synchronization, enabling sync between
 //! local markdown files and CRDT state using fingerprinting and block matching.
 
+mod audit;
+mod bundle;
+mod config;
+mod conflict;
 mod diff;
+mod frontmatter_index;
+mod manifest;
+mod membership;
+mod migrate;
+mod normalize;
+#[cfg(feature = "search")]
+mod search;
+#[cfg(feature = "service")]
+mod service;
 mod session;
-
-pub use session::{IngestOutcome, VaultSession};
-
-pub use diff::{GraphemeStep, graphemes_of, lcs_steps};
+mod state_key;
+mod status;
+mod watch;
+
+pub use audit::{AuditFinding, AuditIssue, VaultAudit};
+pub use bundle::{BundleEntry, BundleManifest};
+pub use config::{ConfigError, VaultConfig};
+pub use conflict::{CONFLICT_SUFFIX, IngestConflict, conflict_path_for};
+pub use frontmatter_index::{FieldFilter, FieldValue, FrontmatterIndex, contains, equals, exists};
+pub use membership::{MembershipOp, VaultMembership};
+pub use migrate::{
+    MigratedState, STATE_FORMAT_VERSION, StateFormatMigration, StateMigration, state_migrations,
+};
+pub use normalize::TextNormalization;
+pub use session::{IngestOutcome, LinkRewriteReport, RestoredVersion, VaultSession};
+pub use state_key::{StateKeyMigration, state_key};
+pub use status::FileStatus;
+pub use watch::{VaultWatcher, WatchEvent};
+
+#[cfg(feature = "search")]
+pub use search::{SearchHit, SearchIndex};
+#[cfg(feature = "service")]
+pub use service::{
+    ChannelTransport, ServiceConfig, ServiceEvent, ServiceObserver, ShutdownHandle, SyncTransport,
+    TransportError, VaultSyncService,
+};
+
+pub use diff::{GraphemeStep, TextEdit, diff_block_text, graphemes_of, lcs_steps, myers_steps};
 // IngestReport is defined in this module.
 
 use crate::doc::{Block, BlockId, BlockKind, Document, Parser, paragraph_visible_string};
@@ -18,15 +55,26 @@ use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use walkdir::WalkDir;
 
 #[derive(Debug)]
 pub struct Vault {
     pub path: PathBuf,
+    /// Settings from `.mdcrdt/config.toml`, read by [`Vault::open`].
+    pub config: VaultConfig,
 }
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum VaultError {
+    /// `source` happened while working on the vault file at `path`.
+    #[error("{}: {source}", path.display())]
+    InFile {
+        path: PathBuf,
+        #[source]
+        source: Box<VaultError>,
+    },
     #[error("Path does not exist: {0}")]
     PathDoesNotExist(PathBuf),
     #[error("path already exists: {0}")]
@@ -37,12 +85,20 @@ pub enum VaultError {
     Storage(#[from] crate::storage::StorageError),
     #[error("Serialization error")]
     Serialization,
+    #[error("state format version {found} is unsupported; this build reads up to {supported}")]
+    UnsupportedStateFormat { found: u32, supported: u32 },
+    #[error("invalid vault bundle: {0}")]
+    InvalidBundle(String),
+    #[error("invalid .mdcrdt/config.toml: {0}")]
+    Config(#[from] ConfigError),
     #[error("invalid peer id in .mdcrdt/peer_id: {0}")]
     InvalidPeerId(String),
     #[error("invalid persistent workspace identity in {path}: {value}")]
     InvalidIdentity { path: PathBuf, value: String },
     #[error("path must be relative to the vault root: {0}")]
     InvalidRelativePath(PathBuf),
+    #[error("not a Markdown note of the vault: {0}")]
+    NotANote(PathBuf),
     #[error("session not open for path: {0}")]
     SessionNotOpen(PathBuf),
     #[error("session snapshot: {0}")]
@@ -66,6 +122,12 @@ pub enum VaultError {
     RecoverableTransaction { journal: PathBuf, cause: String },
     #[error(transparent)]
     RebaseRequired(#[from] crate::RebaseRequired),
+    #[error("no version tagged {0:?}")]
+    UnknownTag(String),
+    #[error("state for {} is stored under several spellings: {entries:?}", key.display())]
+    StateKeyConflict { key: PathBuf, entries: Vec<PathBuf> },
+    #[error(transparent)]
+    Membership(#[from] crate::sync::MembershipError),
     #[error("stale document revision: expected {expected}, actual {actual}")]
     StaleRevision {
         expected: crate::RevisionToken,
@@ -88,6 +150,68 @@ pub enum VaultError {
     },
 }
 
+impl VaultError {
+    /// Attach the vault file this error concerns; already attributed errors keep their
+    /// innermost path.
+    pub fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        match self {
+            error @ VaultError::InFile { .. } => error,
+            error => VaultError::InFile {
+                path: path.into(),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The file this error concerns, when known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            VaultError::InFile { path, .. }
+            | VaultError::PathDoesNotExist(path)
+            | VaultError::PathAlreadyExists(path)
+            | VaultError::InvalidRelativePath(path)
+            | VaultError::NotANote(path)
+            | VaultError::SessionNotOpen(path)
+            | VaultError::DuplicateDocumentBatch(path) => Some(path),
+            VaultError::InvalidIdentity { path, .. } => Some(path),
+            VaultError::RecoverableTransaction { journal, .. } => Some(journal),
+            VaultError::Storage(crate::storage::StorageError::CorruptOperationSegment {
+                path,
+                ..
+            }) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The error without its [`VaultError::InFile`] context.
+    pub fn root_cause(&self) -> &VaultError {
+        match self {
+            VaultError::InFile { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+
+    /// Whether repeating the operation may succeed: transient I/O, or a precondition
+    /// that went stale because the file or session moved on (re-read, then retry).
+    /// Rebase, corruption and invalid input are never retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self.root_cause() {
+            VaultError::Io(error)
+            | VaultError::Storage(crate::storage::StorageError::Io(error)) => matches!(
+                error.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::ResourceBusy
+            ),
+            VaultError::StaleRevision { .. }
+            | VaultError::StaleDisk { .. }
+            | VaultError::PreviewMismatch => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
 pub struct Fingerprint {
     pub tokens: Vec<u64>,
@@ -147,6 +271,12 @@ pub struct Score(pub u32);
 pub enum MatchType {
     ExactFingerprint,
     FuzzyContent,
+    /// Content matched across containers (e.g. cut from one blockquote and pasted
+    /// into another); carries both container paths.
+    Moved {
+        old_container: Vec<usize>,
+        new_container: Vec<usize>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -170,12 +300,26 @@ pub struct AddedBlock {
 /// Aggregate result of structure ingest across vault files.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct IngestReport {
+    /// Files counted as unchanged, whether skipped by their stat or by the hash gate.
     pub files_noop: usize,
     pub files_changed: usize,
     /// Files skipped because they contain not-yet-ingestable blocks (e.g. tables).
     /// Left untouched on disk; no ops emitted.
     pub files_skipped: usize,
     pub ops_emitted: usize,
+    /// Conflict files written, vault-relative, in walk order.
+    pub conflict_files: Vec<PathBuf>,
+    pub stats: IngestStats,
+}
+
+/// How much work an ingest did. Files whose size and modification time match
+/// the vault manifest are scanned but not read or hashed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IngestStats {
+    pub files_scanned: usize,
+    pub files_hashed: usize,
+    pub files_changed: usize,
+    pub elapsed: Duration,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -190,6 +334,12 @@ pub struct MatchConfig {
     pub min_match_score: Score,
     pub exact_threshold: Score,
     pub copy_threshold: Score,
+    /// Content similarity at which a block left unmatched by the positional pass is
+    /// paired with an unmatched block in any container.
+    pub move_threshold: Score,
+    /// How block content is reduced to a [`Fingerprint`] before scoring. Old and new
+    /// fingerprints must come from the same scheme to be comparable.
+    pub fingerprint: FingerprintScheme,
 }
 
 impl Default for MatchConfig {
@@ -198,6 +348,36 @@ impl Default for MatchConfig {
             min_match_score: Score(2000),
             exact_threshold: Score(10000),
             copy_threshold: Score(7000),
+            move_threshold: Score(9000),
+            fingerprint: FingerprintScheme::Words,
+        }
+    }
+}
+
+/// Token selection for [`Fingerprint::from_content_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintScheme {
+    /// Hash of every distinct whitespace-separated word. Blind to word order and
+    /// grows with the vocabulary of the block.
+    Words,
+    /// Winnowing: rolling hashes of every `k`-character shingle, keeping the minimum
+    /// of each `window` consecutive hashes. Order-sensitive within a shingle, and at
+    /// most `max_tokens` are kept (the smallest hashes, so the kept sets of two
+    /// blocks stay a fair sample of their overlap).
+    Winnowing {
+        k: usize,
+        window: usize,
+        max_tokens: usize,
+    },
+}
+
+impl FingerprintScheme {
+    /// Winnowing with 5-character shingles, windows of 4 and at most 256 tokens.
+    pub const fn winnowing() -> Self {
+        Self::Winnowing {
+            k: 5,
+            window: 4,
+            max_tokens: 256,
         }
     }
 }
@@ -227,6 +407,14 @@ impl LastFlushedState {
         }
     }
 
+    /// Decode a stored blob of any supported format version.
+    fn decode(bytes: &[u8]) -> Result<Self, VaultError> {
+        let payload = migrate::upgrade_state(bytes)?;
+        let archived = rkyv::access::<ArchivedSerializableState, rkyv::rancor::Error>(&payload)
+            .map_err(|_| VaultError::Serialization)?;
+        Ok(Self::from_archived(archived))
+    }
+
     fn from_archived(archived: &ArchivedSerializableState) -> Self {
         Self {
             content_hash: archived.content_hash.into(),
@@ -263,20 +451,47 @@ impl Vault {
         if !path.exists() {
             return Err(VaultError::PathDoesNotExist(path));
         }
-        Ok(Vault { path })
+        let config = VaultConfig::load(&path)?;
+        Ok(Vault { path, config })
     }
 
-    /// Returns an iterator over markdown files in the vault.
+    /// Returns an iterator over markdown files in the vault, skipping hidden
+    /// entries such as `.mdcrdt` and `.git`, those matched by
+    /// [`VaultConfig::ignore`] and conflict files (see [`conflict_path_for`]).
     /// Use `.collect()` if you need a Vec.
     pub fn files(&self) -> impl Iterator<Item = PathBuf> + '_ {
         WalkDir::new(&self.path)
             .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_hidden(e.file_name()))
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file())
-            .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+            .filter(|e| {
+                let rel = e.path().strip_prefix(&self.path).unwrap_or(e.path());
+                self.is_note(rel)
+            })
             .map(|e| e.path().to_path_buf())
     }
 
+    /// Whether the vault-relative `rel` is a note [`Self::files`] would list:
+    /// a Markdown file outside hidden directories, not ignored and not a
+    /// conflict file. Paths from other replicas are checked with this before
+    /// anything is written for them.
+    pub fn is_note(&self, rel: &Path) -> bool {
+        let mut components = rel.components().peekable();
+        if components.peek().is_none() {
+            return false;
+        }
+        let inside = components.all(|component| match component {
+            std::path::Component::Normal(name) => !is_hidden(name),
+            std::path::Component::CurDir => true,
+            _ => false,
+        });
+        inside
+            && rel.extension().is_some_and(|ext| ext == "md")
+            && !conflict::is_conflict_file(rel)
+            && !self.config.is_ignored(rel)
+    }
+
     pub fn init(&self) -> Result<(), VaultError> {
         fs::create_dir_all(self.state_root())?;
         Ok(())
@@ -285,34 +500,40 @@ impl Vault {
     pub fn flush(&self) -> Result<(), VaultError> {
         self.init()?;
         for file in self.files() {
-            let content = fs::read_to_string(&file)?;
-            let doc = Parser::parse(&content);
-            let state = LastFlushedState {
-                content_hash: hash_string(&content),
-                blocks: fingerprint_document(&doc),
-            };
-            let serializable = state.to_serializable();
-            let encoded = rkyv::to_bytes::<rkyv::rancor::Error>(&serializable)
-                .map_err(|_| VaultError::Serialization)?;
-            let storage = Storage::open(self.state_path_for(&file))?;
-            storage.write_snapshot(&encoded, &[], false)?;
+            self.record_flushed(&file)?;
         }
         Ok(())
     }
 
+    /// Record the current content and fingerprints of one vault file.
+    fn record_flushed(&self, file: &Path) -> Result<(), VaultError> {
+        let content = fs::read_to_string(file)?;
+        let content = self.config.normalize.apply(&content);
+        let doc = Parser::parse(&content);
+        let state = LastFlushedState {
+            content_hash: hash_string(&content),
+            blocks: fingerprint_document(&doc),
+        };
+        self.write_last_flushed(file, &state)
+    }
+
+    /// Hash of `content` as the hash gate compares it: after
+    /// [`VaultConfig::normalize`], so line-ending or composition differences
+    /// alone do not count as a change.
+    pub(crate) fn content_hash(&self, content: &str) -> u64 {
+        hash_string(&self.config.normalize.apply(content))
+    }
+
     pub fn ingest(&self) -> Result<IngestResult, VaultError> {
         self.init()?;
         let mut changed = false;
         for file in self.files() {
             let content = fs::read_to_string(&file)?;
-            let content_hash = hash_string(&content);
+            let content_hash = self.content_hash(&content);
             let storage = Storage::open(self.state_path_for(&file))?;
             match storage.read_snapshot() {
                 Ok((bytes, _, _)) => {
-                    let archived =
-                        rkyv::access::<ArchivedSerializableState, rkyv::rancor::Error>(&bytes)
-                            .map_err(|_| VaultError::Serialization)?;
-                    let previous = LastFlushedState::from_archived(archived);
+                    let previous = LastFlushedState::decode(&bytes)?;
                     if previous.content_hash != content_hash {
                         changed = true;
                     }
@@ -346,7 +567,7 @@ impl Vault {
     /// Absolute path of the fingerprint / content-hash state blob for a vault file.
     pub(crate) fn state_path_for(&self, file: &Path) -> PathBuf {
         let relative = file.strip_prefix(&self.path).unwrap_or(file);
-        let mut path = self.state_root().join(relative);
+        let mut path = self.state_root().join(self.state_key(relative));
         path.set_extension("mdcrdt");
         path
     }
@@ -363,12 +584,7 @@ impl Vault {
         };
         let storage = Storage::open(self.state_path_for(&abs))?;
         match storage.read_snapshot() {
-            Ok((bytes, _, _)) => {
-                let archived =
-                    rkyv::access::<ArchivedSerializableState, rkyv::rancor::Error>(&bytes)
-                        .map_err(|_| VaultError::Serialization)?;
-                Ok(Some(LastFlushedState::from_archived(archived)))
-            }
+            Ok((bytes, _, _)) => Ok(Some(LastFlushedState::decode(&bytes)?)),
             Err(crate::storage::StorageError::Missing) => Ok(None),
             Err(err) => Err(VaultError::Storage(err)),
         }
@@ -388,16 +604,24 @@ impl Vault {
         let encoded = rkyv::to_bytes::<rkyv::rancor::Error>(&serializable)
             .map_err(|_| VaultError::Serialization)?;
         let storage = Storage::open(self.state_path_for(&abs))?;
-        storage.write_snapshot(&encoded, &[], false)?;
+        storage.write_snapshot(&migrate::frame_state(&encoded), &[], false)?;
         Ok(())
     }
 }
 
 pub fn fingerprint_document(doc: &Document) -> Vec<BlockFingerprint> {
+    fingerprint_document_with(doc, &FingerprintScheme::Words)
+}
+
+pub fn fingerprint_document_with(
+    doc: &Document,
+    scheme: &FingerprintScheme,
+) -> Vec<BlockFingerprint> {
     let mut fingerprints = Vec::new();
     let mut container_path = Vec::new();
     collect_block_fingerprints(
         &doc.blocks_in_order(),
+        scheme,
         &mut container_path,
         &mut fingerprints,
     );
@@ -405,14 +629,24 @@ pub fn fingerprint_document(doc: &Document) -> Vec<BlockFingerprint> {
 }
 
 pub fn parsed_blocks_from_doc(doc: &Document) -> Vec<ParsedBlock> {
+    parsed_blocks_from_doc_with(doc, &FingerprintScheme::Words)
+}
+
+pub fn parsed_blocks_from_doc_with(doc: &Document, scheme: &FingerprintScheme) -> Vec<ParsedBlock> {
     let mut parsed = Vec::new();
     let mut container_path = Vec::new();
-    collect_parsed_blocks(&doc.blocks_in_order(), &mut container_path, &mut parsed);
+    collect_parsed_blocks(
+        &doc.blocks_in_order(),
+        scheme,
+        &mut container_path,
+        &mut parsed,
+    );
     parsed
 }
 
 fn collect_block_fingerprints(
     blocks: &[&Block],
+    scheme: &FingerprintScheme,
     container_path: &mut Vec<usize>,
     out: &mut Vec<BlockFingerprint>,
 ) {
@@ -421,14 +655,14 @@ fn collect_block_fingerprints(
             BlockKind::BlockQuote { children } => {
                 container_path.push(index);
                 let children_blocks: Vec<_> = children.iter_asc().collect();
-                collect_block_fingerprints(&children_blocks, container_path, out);
+                collect_block_fingerprints(&children_blocks, scheme, container_path, out);
                 container_path.pop();
             }
             other => {
                 let content = block_content(other);
                 out.push(BlockFingerprint {
                     block_id: block.id,
-                    fingerprint: Fingerprint::from_content(&content),
+                    fingerprint: Fingerprint::from_content_with(&content, scheme),
                     container_path: container_path.clone(),
                     position: index,
                 });
@@ -439,6 +673,7 @@ fn collect_block_fingerprints(
 
 fn collect_parsed_blocks(
     blocks: &[&Block],
+    scheme: &FingerprintScheme,
     container_path: &mut Vec<usize>,
     out: &mut Vec<ParsedBlock>,
 ) {
@@ -447,13 +682,13 @@ fn collect_parsed_blocks(
             BlockKind::BlockQuote { children } => {
                 container_path.push(index);
                 let children_blocks: Vec<_> = children.iter_asc().collect();
-                collect_parsed_blocks(&children_blocks, container_path, out);
+                collect_parsed_blocks(&children_blocks, scheme, container_path, out);
                 container_path.pop();
             }
             other => {
                 let content = block_content(other);
                 out.push(ParsedBlock {
-                    fingerprint: Fingerprint::from_content(&content),
+                    fingerprint: Fingerprint::from_content_with(&content, scheme),
                     container_path: container_path.clone(),
                     position: index,
                 });
@@ -524,28 +759,40 @@ pub fn match_blocks(
         }
     }
 
-    edges.sort_by(|a, b| {
-        b.0.cmp(&a.0)
-            .then_with(|| a.1.cmp(&b.1))
-            .then_with(|| a.2.cmp(&b.2))
-    });
-
     let mut matched_old = HashSet::new();
     let mut matched_new = HashSet::new();
     let mut matches = Vec::new();
-    for (score, old_idx, new_idx) in edges {
-        if matched_old.contains(&old_idx) || matched_new.contains(&new_idx) {
+    claim_edges(edges, &mut matched_old, &mut matched_new, &mut matches);
+
+    // Move pass: container and position penalties keep a block pasted into another
+    // container from pairing above, so retry the leftovers on content alone.
+    let mut move_edges = Vec::new();
+    for (old_idx, old) in old_state.blocks.iter().enumerate() {
+        if matched_old.contains(&old_idx) {
             continue;
         }
-        matched_old.insert(old_idx);
-        matched_new.insert(new_idx);
-        matches.push((old_idx, new_idx, score));
+        for (new_idx, new) in new_parsed.iter().enumerate() {
+            if matched_new.contains(&new_idx) {
+                continue;
+            }
+            let similarity = fingerprint_similarity_int(&old.fingerprint, &new.fingerprint);
+            if similarity >= config.move_threshold.0 {
+                move_edges.push((Score(similarity), old_idx, new_idx));
+            }
+        }
     }
+    claim_edges(move_edges, &mut matched_old, &mut matched_new, &mut matches);
 
     let mut result = BlockMapping::default();
     for (old_idx, new_idx, score) in &matches {
         let old_block = &old_state.blocks[*old_idx];
-        let match_type = if *score >= config.exact_threshold {
+        let new_container = &new_parsed[*new_idx].container_path;
+        let match_type = if old_block.container_path != *new_container {
+            MatchType::Moved {
+                old_container: old_block.container_path.clone(),
+                new_container: new_container.clone(),
+            }
+        } else if *score >= config.exact_threshold {
             MatchType::ExactFingerprint
         } else {
             MatchType::FuzzyContent
@@ -592,6 +839,29 @@ pub fn match_blocks(
     result
 }
 
+/// Greedily pair `(score, old, new)` edges, best score first, skipping indices
+/// already claimed.
+fn claim_edges(
+    mut edges: Vec<(Score, usize, usize)>,
+    matched_old: &mut HashSet<usize>,
+    matched_new: &mut HashSet<usize>,
+    matches: &mut Vec<(usize, usize, Score)>,
+) {
+    edges.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then_with(|| a.1.cmp(&b.1))
+            .then_with(|| a.2.cmp(&b.2))
+    });
+    for (score, old_idx, new_idx) in edges {
+        if matched_old.contains(&old_idx) || matched_new.contains(&new_idx) {
+            continue;
+        }
+        matched_old.insert(old_idx);
+        matched_new.insert(new_idx);
+        matches.push((old_idx, new_idx, score));
+    }
+}
+
 /// Deterministic id for a newly observed parsed block (no create OpId yet).
 ///
 /// A domain constant seeds an FNV-style 128-bit mix over the block's fingerprint,
@@ -702,13 +972,27 @@ fn fingerprint_similarity_int(a: &Fingerprint, b: &Fingerprint) -> u32 {
 
 impl Fingerprint {
     pub fn from_content(content: &str) -> Self {
-        let mut tokens: Vec<u64> = content
-            .split_whitespace()
-            .filter(|token| !token.is_empty())
-            .map(stable_hash_string)
-            .collect();
-        tokens.sort_unstable();
-        tokens.dedup();
+        Self::from_content_with(content, &FingerprintScheme::Words)
+    }
+
+    pub fn from_content_with(content: &str, scheme: &FingerprintScheme) -> Self {
+        let tokens = match *scheme {
+            FingerprintScheme::Words => {
+                let mut tokens: Vec<u64> = content
+                    .split_whitespace()
+                    .filter(|token| !token.is_empty())
+                    .map(stable_hash_string)
+                    .collect();
+                tokens.sort_unstable();
+                tokens.dedup();
+                tokens
+            }
+            FingerprintScheme::Winnowing {
+                k,
+                window,
+                max_tokens,
+            } => winnow(content, k.max(1), window.max(1), max_tokens.max(1)),
+        };
         Self {
             tokens,
             len: content.len(),
@@ -716,6 +1000,142 @@ impl Fingerprint {
     }
 }
 
+/// Base of the polynomial rolling hash over shingle characters.
+const ROLLING_BASE: u64 = 0x0000_0100_0000_01b3;
+
+/// Sorted winnowed shingle hashes of `content`, at most `max_tokens` of them.
+///
+/// Streams the characters through a `k`-character ring and a monotone queue of
+/// window minima, so memory stays `O(k + window + max_tokens)` however large the
+/// block is.
+fn winnow(content: &str, k: usize, window: usize, max_tokens: usize) -> Vec<u64> {
+    use std::collections::VecDeque;
+
+    // Whitespace runs count as one space so reflowed text fingerprints alike.
+    let chars = content
+        .split_whitespace()
+        .enumerate()
+        .flat_map(|(index, word)| (index > 0).then_some(' ').into_iter().chain(word.chars()));
+    let drop_factor = (1..k).fold(1u64, |factor, _| factor.wrapping_mul(ROLLING_BASE));
+
+    let mut shingle: VecDeque<char> = VecDeque::with_capacity(k);
+    let mut rolling = 0u64;
+    // (position, hash) with strictly increasing hashes; the front is the window minimum.
+    let mut minima: VecDeque<(usize, u64)> = VecDeque::with_capacity(window);
+    let mut last_selected = None;
+    let mut selected = Vec::new();
+    // Hashes before the first full window, kept whole if no window ever fills.
+    let mut head = Vec::with_capacity(window);
+    let mut position = 0usize;
+
+    for ch in chars {
+        if shingle.len() == k {
+            let dropped = shingle.pop_front().expect("full shingle");
+            rolling = rolling.wrapping_sub(u64::from(dropped).wrapping_mul(drop_factor));
+        }
+        shingle.push_back(ch);
+        rolling = rolling
+            .wrapping_mul(ROLLING_BASE)
+            .wrapping_add(u64::from(ch));
+        if shingle.len() < k {
+            continue;
+        }
+        let hash = mix64(rolling);
+        if position < window {
+            head.push(hash);
+        }
+        // Rightmost minimum wins ties, as in the winnowing paper.
+        while minima.back().is_some_and(|&(_, back)| back >= hash) {
+            minima.pop_back();
+        }
+        minima.push_back((position, hash));
+        if minima
+            .front()
+            .is_some_and(|&(front, _)| front + window <= position)
+        {
+            minima.pop_front();
+        }
+        if position + 1 >= window {
+            let (min_position, min_hash) = minima[0];
+            if last_selected != Some(min_position) {
+                last_selected = Some(min_position);
+                selected.push(min_hash);
+                if selected.len() >= max_tokens.saturating_mul(2) {
+                    keep_smallest(&mut selected, max_tokens);
+                }
+            }
+        }
+        position += 1;
+    }
+
+    if position == 0 {
+        // Shorter than one shingle: the whole (normalized) text is the only token.
+        let short: String = shingle.iter().collect();
+        if !short.is_empty() {
+            selected.push(stable_hash_string(&short));
+        }
+    } else if position < window {
+        selected = head;
+    }
+    keep_smallest(&mut selected, max_tokens);
+    selected
+}
+
+/// SplitMix64 finalizer; spreads rolling hashes so window minima are unbiased.
+fn mix64(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+fn keep_smallest(tokens: &mut Vec<u64>, max_tokens: usize) {
+    tokens.sort_unstable();
+    tokens.dedup();
+    tokens.truncate(max_tokens);
+}
+
+/// Path of the per-document file `<root>/<rel>.<extension>` for data derived from a session.
+pub(crate) fn derived_path(root: &Path, rel: &Path, extension: &str) -> PathBuf {
+    let mut path = root.join(rel).into_os_string();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+/// Replace a derived file through a sibling temp file so readers never see a torn write.
+///
+/// Derived files are rebuilt from session snapshots, so they are not fsynced.
+pub(crate) fn write_derived(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Every derived file with `extension` under `root`, sorted by path.
+pub(crate) fn derived_files(root: &Path, extension: &str) -> Result<Vec<PathBuf>, VaultError> {
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.map_err(|error| VaultError::Io(error.into()))?;
+        let path = entry.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
 pub(crate) fn hash_string(value: &str) -> u64 {
     stable_hash_string(value)
 }
@@ -812,9 +1232,7 @@ mod tests {
         )
         .unwrap();
         let (bytes, _, _) = storage.read_snapshot().unwrap();
-        let archived =
-            rkyv::access::<ArchivedSerializableState, rkyv::rancor::Error>(&bytes).unwrap();
-        let state = LastFlushedState::from_archived(archived);
+        let state = LastFlushedState::decode(&bytes).unwrap();
         assert_eq!(state.content_hash, hash_string("hello"));
         assert!(!state.blocks.is_empty());
     }
@@ -830,6 +1248,53 @@ mod tests {
         assert_eq!(result, IngestResult::NoOp);
     }
 
+    #[test]
+    fn winnowing_fingerprint_is_bounded_and_order_sensitive() {
+        let scheme = FingerprintScheme::winnowing();
+        let FingerprintScheme::Winnowing { max_tokens, .. } = scheme else {
+            unreachable!()
+        };
+        let fence: String = (0..20_000).map(|i| format!("let v{i} = {i};\n")).collect();
+        let huge = Fingerprint::from_content_with(&fence, &scheme);
+        assert_eq!(huge.tokens.len(), max_tokens);
+        assert!(huge.tokens.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let text = "the quick brown fox jumps over the lazy dog near the river bank";
+        let original = Fingerprint::from_content_with(text, &scheme);
+        let reflowed = Fingerprint::from_content_with(&text.replace(' ', "\n  "), &scheme);
+        assert_eq!(fingerprint_similarity_int(&original, &reflowed), 10000);
+
+        let edited = Fingerprint::from_content_with(
+            "the quick brown fox jumps over the lazy cat near the river bank",
+            &scheme,
+        );
+        assert!(fingerprint_similarity_int(&original, &edited) >= 5000);
+
+        // Word sets cannot tell a shuffle from the original; shingles can.
+        let shuffled = "bank river the near dog lazy the over jumps fox brown quick the";
+        assert_eq!(
+            fingerprint_similarity_int(
+                &Fingerprint::from_content(text),
+                &Fingerprint::from_content(shuffled)
+            ),
+            10000
+        );
+        assert!(
+            fingerprint_similarity_int(
+                &original,
+                &Fingerprint::from_content_with(shuffled, &scheme)
+            ) < 5000
+        );
+
+        let short = Fingerprint::from_content_with("ab", &scheme);
+        assert_eq!(short.tokens.len(), 1);
+        assert!(
+            Fingerprint::from_content_with("  ", &scheme)
+                .tokens
+                .is_empty()
+        );
+    }
+
     #[test]
     fn test_block_matching_with_container_scoring() {
         let block_a = BlockId::new_v4();
@@ -870,6 +1335,61 @@ mod tests {
         assert!(mapping.matched.iter().any(|m| m.old_id == block_b));
     }
 
+    #[test]
+    fn block_moved_between_quotes_keeps_its_identity() {
+        let fingerprint = |path: Vec<usize>, position, text| BlockFingerprint {
+            block_id: BlockId::new_v4(),
+            fingerprint: Fingerprint::from_content(text),
+            container_path: path,
+            position,
+        };
+        let moved_text = "p:cut from the first quote and pasted into the second";
+        let old_state = LastFlushedState {
+            content_hash: 0,
+            blocks: vec![
+                fingerprint(vec![0], 0, "p:first quote stays"),
+                fingerprint(vec![0], 1, moved_text),
+                fingerprint(vec![2], 0, "p:second quote stays"),
+            ],
+        };
+        let parsed = |path: Vec<usize>, position, text| ParsedBlock {
+            fingerprint: Fingerprint::from_content(text),
+            container_path: path,
+            position,
+        };
+        let new_blocks = vec![
+            parsed(vec![0], 0, "p:first quote stays"),
+            parsed(vec![2], 0, "p:second quote stays"),
+            parsed(vec![2], 1, moved_text),
+        ];
+
+        let mapping = match_blocks(&old_state, &new_blocks, &MatchConfig::default());
+        assert!(mapping.removed.is_empty());
+        assert!(mapping.added.is_empty());
+        let moved = mapping
+            .matched
+            .iter()
+            .find(|m| m.old_id == old_state.blocks[1].block_id)
+            .unwrap();
+        assert_eq!(moved.new_index, 2);
+        assert_eq!(
+            moved.match_type,
+            MatchType::Moved {
+                old_container: vec![0],
+                new_container: vec![2],
+            }
+        );
+
+        // Below the move threshold the pasted block is a fresh add.
+        let strict = MatchConfig {
+            move_threshold: Score(10001),
+            ..MatchConfig::default()
+        };
+        let mapping = match_blocks(&old_state, &new_blocks, &strict);
+        assert_eq!(mapping.removed, [old_state.blocks[1].block_id]);
+        assert_eq!(mapping.added.len(), 1);
+    }
+
     #[test]
     fn unmatched_parsed_block_id_is_deterministic_and_distinct() {
         let a = ParsedBlock {
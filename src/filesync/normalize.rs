@@ -0,0 +1,56 @@
+//! Text normalization applied when vault files are ingested.
+//!
+//! The same note saved on different systems can differ in bytes only: Windows
+//! editors write CRLF line endings, and macOS tools may write accented letters
+//! decomposed (NFD, `e` + U+0301) where others write them precomposed (NFC,
+//! `é`). Hashed and diffed raw, every such round trip looks like an edit.
+//! Ingest therefore hashes, fingerprints and diffs the text normalized under the
+//! vault's [`TextNormalization`], while the file's own bytes stay the source
+//! that exact serialization reproduces.
+
+use std::borrow::Cow;
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
+
+/// What ingest normalizes before hashing and diffing a file's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextNormalization {
+    /// Turn CRLF and lone CR line endings into LF.
+    pub line_endings: bool,
+    /// Bring text to Unicode NFC, composing decomposed accented letters.
+    pub unicode: bool,
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        Self {
+            line_endings: true,
+            unicode: true,
+        }
+    }
+}
+
+impl TextNormalization {
+    /// Leave text as it is.
+    pub const NONE: Self = Self {
+        line_endings: false,
+        unicode: false,
+    };
+
+    /// `text` normalized under this policy; borrowed when nothing changes.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let text = if self.line_endings && text.contains('\r') {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+        } else {
+            Cow::Borrowed(text)
+        };
+        if !self.unicode || is_nfc_quick(text.chars()) == IsNormalized::Yes {
+            return text;
+        }
+        let composed: String = text.nfc().collect();
+        if composed == *text {
+            text
+        } else {
+            Cow::Owned(composed)
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Vault-wide full-text search over block contents.
+//!
+//! Every persisted session snapshot refreshes a per-document index file under
+//! `.mdcrdt/search/`. Leaf blocks are keyed by a content fingerprint, so a
+//! refresh re-tokenizes only the blocks whose content changed and skips the
+//! write entirely when nothing did. Queries read the index files and never
+//! re-parse markdown.
+
+use super::{
+    Vault, VaultError, block_content, derived_files, derived_path, hash_string, write_derived,
+};
+use crate::doc::{Block, BlockId, BlockKind, Document, paragraph_visible_string};
+use rkyv::{Archive, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INDEX_EXTENSION: &str = "idx";
+
+/// Words kept on each side of the first matching word in a snippet.
+const SNIPPET_CONTEXT_WORDS: usize = 5;
+
+/// One block matching every term of a query.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SearchHit {
+    /// Vault-relative markdown path.
+    pub path: PathBuf,
+    pub block_id: BlockId,
+    /// Words around the first match, with `…` where the block text was cut.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
+struct IndexedBlock {
+    block_id: [u8; 16],
+    content_hash: u64,
+    text: String,
+    terms: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
+struct IndexedDocument {
+    path: String,
+    blocks: Vec<IndexedBlock>,
+}
+
+/// In-memory inverted index over every indexed document of a vault.
+///
+/// Load it once with [`SearchIndex::load`] to run many queries; [`Vault::search`]
+/// loads it per call.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    documents: Vec<IndexedDocument>,
+    /// Term → `(document, block)` positions, both in index order.
+    postings: HashMap<String, BTreeSet<(usize, usize)>>,
+}
+
+impl SearchIndex {
+    /// Read every per-document index of `vault`. Documents whose markdown file is
+    /// gone are skipped.
+    pub fn load(vault: &Vault) -> Result<Self, VaultError> {
+        let mut documents = Vec::new();
+        for path in derived_files(&search_root(vault), INDEX_EXTENSION)? {
+            let document = decode_index(&fs::read(path)?)?;
+            if vault.path.join(&document.path).exists() {
+                documents.push(document);
+            }
+        }
+        let mut postings: HashMap<String, BTreeSet<(usize, usize)>> = HashMap::new();
+        for (doc_index, document) in documents.iter().enumerate() {
+            for (block_index, block) in document.blocks.iter().enumerate() {
+                for term in &block.terms {
+                    postings
+                        .entry(term.clone())
+                        .or_default()
+                        .insert((doc_index, block_index));
+                }
+            }
+        }
+        Ok(Self {
+            documents,
+            postings,
+        })
+    }
+
+    /// Blocks containing every word of `query` (case-insensitive), in path and then
+    /// document order. An empty query matches nothing.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms: BTreeSet<String> = tokenize(query).collect();
+        let mut matches: Option<BTreeSet<(usize, usize)>> = None;
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                return Vec::new();
+            };
+            matches = Some(match matches {
+                None => postings.clone(),
+                Some(found) => found.intersection(postings).copied().collect(),
+            });
+        }
+        matches
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(doc_index, block_index)| {
+                let document = &self.documents[doc_index];
+                let block = &document.blocks[block_index];
+                SearchHit {
+                    path: PathBuf::from(&document.path),
+                    block_id: BlockId::from_bytes(block.block_id),
+                    snippet: snippet(&block.text, &terms),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Vault {
+    /// Search the blocks of every document this vault has indexed (each ingested or
+    /// otherwise persisted session).
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>, VaultError> {
+        Ok(SearchIndex::load(self)?.search(query))
+    }
+}
+
+/// Bring the index file of `rel` up to date with `document`.
+pub(crate) fn update_document_index(
+    vault: &Vault,
+    rel: &Path,
+    document: &Document,
+) -> Result<(), VaultError> {
+    let path = index_path(vault, rel);
+    let previous = match fs::read(&path) {
+        Ok(bytes) => decode_index(&bytes).ok(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+    let mut reusable: BTreeMap<([u8; 16], u64), IndexedBlock> = previous
+        .iter()
+        .flat_map(|previous| previous.blocks.iter())
+        .map(|block| ((block.block_id, block.content_hash), block.clone()))
+        .collect();
+
+    let mut blocks = Vec::new();
+    collect_leaves(&document.blocks_in_order(), &mut |block| {
+        let content_hash = hash_string(&block_content(&block.kind));
+        let key = (*block.id.as_bytes(), content_hash);
+        let indexed = reusable.remove(&key).unwrap_or_else(|| {
+            let text = searchable_text(&block.kind);
+            let terms = tokenize(&text)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            IndexedBlock {
+                block_id: key.0,
+                content_hash,
+                text,
+                terms,
+            }
+        });
+        blocks.push(indexed);
+    });
+    let next = IndexedDocument {
+        path: rel.to_string_lossy().into_owned(),
+        blocks,
+    };
+    if previous.as_ref() == Some(&next) {
+        return Ok(());
+    }
+
+    let bytes =
+        rkyv::to_bytes::<rkyv::rancor::Error>(&next).map_err(|_| VaultError::Serialization)?;
+    write_derived(&path, &bytes)
+}
+
+fn search_root(vault: &Vault) -> PathBuf {
+    vault.path.join(".mdcrdt").join("search")
+}
+
+fn index_path(vault: &Vault, rel: &Path) -> PathBuf {
+    derived_path(&search_root(vault), rel, INDEX_EXTENSION)
+}
+
+fn decode_index(bytes: &[u8]) -> Result<IndexedDocument, VaultError> {
+    let mut aligned = rkyv::util::AlignedVec::<16>::new();
+    aligned.extend_from_slice(bytes);
+    rkyv::from_bytes::<IndexedDocument, rkyv::rancor::Error>(&aligned)
+        .map_err(|_| VaultError::Serialization)
+}
+
+/// Visit leaf blocks in document order; blockquotes are transparent.
+fn collect_leaves(blocks: &[&Block], visit: &mut impl FnMut(&Block)) {
+    for block in blocks {
+        match &block.kind {
+            BlockKind::BlockQuote { children } => {
+                let children: Vec<_> = children.iter_asc().collect();
+                collect_leaves(&children, visit);
+            }
+            _ => visit(block),
+        }
+    }
+}
+
+fn searchable_text(kind: &BlockKind) -> String {
+    match kind {
+        BlockKind::Paragraph { text } | BlockKind::Heading { text, .. } => {
+            paragraph_visible_string(text)
+        }
+        BlockKind::List { items, .. } => items
+            .iter_asc()
+            .flat_map(|item| item.children.iter_asc())
+            .map(|child| searchable_text(&child.kind))
+            .collect::<Vec<_>>()
+            .join(" "),
+        BlockKind::CodeFence { text, .. } => text.clone(),
+        BlockKind::RawBlock { raw } => raw.clone(),
+        BlockKind::BlockQuote { children } => children
+            .iter_asc()
+            .map(|child| searchable_text(&child.kind))
+            .collect::<Vec<_>>()
+            .join(" "),
+        BlockKind::Table { table } => std::iter::once(table.header_row_id())
+            .chain(table.rows.iter().map(|row| row.id))
+            .flat_map(|row| table.row_cells(row))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn snippet(text: &str, terms: &BTreeSet<String>) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let hit = words
+        .iter()
+        .position(|word| tokenize(word).any(|token| terms.contains(&token)))
+        .unwrap_or(0);
+    let start = hit.saturating_sub(SNIPPET_CONTEXT_WORDS);
+    let end = (hit + SNIPPET_CONTEXT_WORDS + 1).min(words.len());
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "… ");
+    }
+    if end < words.len() {
+        snippet.push_str(" …");
+    }
+    snippet
+}
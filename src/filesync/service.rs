@@ -0,0 +1,455 @@
+//! Async vault sync on tokio.
+//!
+//! [`VaultSyncService`] runs the loop the `md-crdt watch` command runs by hand:
+//! it polls the vault for edited files and ingests them, pushes each document's
+//! new operations through a [`SyncTransport`], applies what the transport
+//! delivers and writes the merged Markdown back to disk, and periodically
+//! checkpoints document history. Failed pushes are retried per file under a
+//! [`RetryPolicy`]; every step is reported to a [`ServiceObserver`] for
+//! logging or metrics.
+
+use super::{VaultError, VaultSession, VaultWatcher, WatchEvent};
+use crate::core::StateVector;
+use crate::sync::{ChangeMessage, CheckpointRequest, RetryPolicy, ValidationLimits};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// Error from a [`SyncTransport`], shown to observers and retried.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("transport: {0}")]
+pub struct TransportError(pub String);
+
+/// Carries one vault's change messages to its peers and back.
+pub trait SyncTransport: Send {
+    /// Deliver `message` for the vault-relative `path`.
+    fn send(
+        &mut self,
+        path: &Path,
+        message: ChangeMessage,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Next inbound message, or `None` once the transport is closed.
+    ///
+    /// Must be cancel-safe: the service drops a pending `recv` whenever a timer
+    /// fires first, and a message must not be lost when that happens.
+    fn recv(&mut self) -> impl Future<Output = Option<(PathBuf, ChangeMessage)>> + Send;
+}
+
+/// In-process [`SyncTransport`] over tokio channels.
+#[derive(Debug)]
+pub struct ChannelTransport {
+    outbound: mpsc::UnboundedSender<(PathBuf, ChangeMessage)>,
+    inbound: mpsc::UnboundedReceiver<(PathBuf, ChangeMessage)>,
+}
+
+impl ChannelTransport {
+    /// Two transports, each delivering what the other sends.
+    pub fn pair() -> (Self, Self) {
+        let (left_tx, left_rx) = mpsc::unbounded_channel();
+        let (right_tx, right_rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                outbound: left_tx,
+                inbound: right_rx,
+            },
+            Self {
+                outbound: right_tx,
+                inbound: left_rx,
+            },
+        )
+    }
+}
+
+impl SyncTransport for ChannelTransport {
+    async fn send(&mut self, path: &Path, message: ChangeMessage) -> Result<(), TransportError> {
+        self.outbound
+            .send((path.to_path_buf(), message))
+            .map_err(|_| TransportError("peer closed".into()))
+    }
+
+    async fn recv(&mut self) -> Option<(PathBuf, ChangeMessage)> {
+        self.inbound.recv().await
+    }
+}
+
+/// Timing and limits for a [`VaultSyncService`].
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// How often the vault is polled for edited files.
+    pub poll_interval: Duration,
+    /// Quiet time after the last file event before changed files are ingested.
+    pub debounce: Duration,
+    /// Limits applied to inbound messages.
+    pub limits: ValidationLimits,
+    /// Delay before retrying a failed push, by attempt.
+    pub retry: RetryPolicy,
+    /// How often, and how, open documents' history is checkpointed; `None` never does.
+    pub compaction: Option<(Duration, CheckpointRequest)>,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            debounce: Duration::from_millis(300),
+            limits: ValidationLimits::default(),
+            retry: RetryPolicy::default(),
+            compaction: None,
+        }
+    }
+}
+
+/// One step taken by a [`VaultSyncService`], with a vault-relative path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceEvent {
+    Ingested {
+        path: PathBuf,
+        ops: usize,
+    },
+    Pushed {
+        path: PathBuf,
+        ops: usize,
+    },
+    /// The push is retried after `retry_in`, unless the policy has given up.
+    PushFailed {
+        path: PathBuf,
+        error: String,
+        attempts: u32,
+        retry_in: Option<Duration>,
+    },
+    Applied {
+        path: PathBuf,
+        applied: usize,
+        buffered: usize,
+    },
+    Materialized {
+        path: PathBuf,
+        bytes: usize,
+    },
+    Compacted {
+        path: PathBuf,
+        pruned_ops: usize,
+    },
+    Error {
+        path: PathBuf,
+        error: String,
+        retryable: bool,
+    },
+}
+
+/// Hook called for every [`ServiceEvent`].
+pub trait ServiceObserver: Send {
+    fn on_event(&mut self, event: &ServiceEvent);
+}
+
+impl<F: FnMut(&ServiceEvent) + Send> ServiceObserver for F {
+    fn on_event(&mut self, event: &ServiceEvent) {
+        self(event)
+    }
+}
+
+/// Stops a running [`VaultSyncService`] after its current step.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(watch::Sender<bool>);
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Per-file push state: the frontier the transport has, and the retry schedule.
+#[derive(Debug, Clone)]
+struct Outgoing {
+    pushed: StateVector,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// A vault, its documents' sync state and a transport, run as one unit.
+pub struct VaultSyncService<T: SyncTransport> {
+    session: VaultSession,
+    transport: T,
+    config: ServiceConfig,
+    observer: Option<Box<dyn ServiceObserver>>,
+    watcher: VaultWatcher,
+    outgoing: BTreeMap<PathBuf, Outgoing>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl<T: SyncTransport> VaultSyncService<T> {
+    pub fn new(session: VaultSession, transport: T) -> Self {
+        Self {
+            watcher: VaultWatcher::new(&session.vault),
+            session,
+            transport,
+            config: ServiceConfig::default(),
+            observer: None,
+            outgoing: BTreeMap::new(),
+            shutdown: watch::Sender::new(false),
+        }
+    }
+
+    pub fn with_config(mut self, config: ServiceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_observer(mut self, observer: impl ServiceObserver + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown.clone())
+    }
+
+    pub fn session(&self) -> &VaultSession {
+        &self.session
+    }
+
+    /// Give the vault session back, e.g. after [`Self::run`] returned.
+    pub fn into_session(self) -> VaultSession {
+        self.session
+    }
+
+    /// Sync until shut down or until the transport closes.
+    ///
+    /// Every file in the vault is ingested and offered to the transport first.
+    /// Errors on single files are reported to the observer and do not stop the
+    /// service; only saving session state on exit can fail the run.
+    pub async fn run(&mut self) -> Result<(), VaultError> {
+        let mut stop = self.shutdown.subscribe();
+        let all: BTreeSet<PathBuf> = self
+            .session
+            .vault
+            .files()
+            .filter_map(|file| {
+                file.strip_prefix(&self.session.vault.path)
+                    .ok()
+                    .map(Path::to_path_buf)
+            })
+            .collect();
+        self.ingest(all);
+        self.push_due().await;
+
+        let mut poll = tokio::time::interval(self.config.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // A disabled compaction timer is never polled; it only needs a valid period.
+        let every = self
+            .config
+            .compaction
+            .as_ref()
+            .map_or(Duration::from_secs(3600), |(every, _)| *every);
+        let mut compaction = tokio::time::interval_at(Instant::now() + every, every);
+        let mut changed = BTreeSet::new();
+        let mut last_change = Instant::now();
+        while !*stop.borrow_and_update() {
+            tokio::select! {
+                _ = stop.changed() => {}
+                _ = poll.tick() => {
+                    let events = self.watcher.poll();
+                    if !events.is_empty() {
+                        last_change = Instant::now();
+                    }
+                    for event in events {
+                        match event {
+                            WatchEvent::Changed(file) => {
+                                changed.insert(file);
+                            }
+                            WatchEvent::Removed(file) => {
+                                changed.remove(&file);
+                            }
+                        }
+                    }
+                    if !changed.is_empty() && last_change.elapsed() >= self.config.debounce {
+                        self.ingest(std::mem::take(&mut changed));
+                    }
+                    self.push_due().await;
+                }
+                _ = compaction.tick(), if self.config.compaction.is_some() => self.compact(),
+                inbound = self.transport.recv() => match inbound {
+                    Some((path, message)) => self.apply(path, message),
+                    None => break,
+                },
+            }
+        }
+        self.session.save_all_state()
+    }
+
+    fn emit(&mut self, event: ServiceEvent) {
+        tracing::debug!(?event, "vault sync");
+        if let Some(observer) = &mut self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    fn report(&mut self, path: &Path, error: &VaultError) {
+        self.emit(ServiceEvent::Error {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+            retryable: error.is_retryable(),
+        });
+    }
+
+    /// Ingest `paths` and queue the ones that gained operations for pushing.
+    fn ingest(&mut self, paths: BTreeSet<PathBuf>) {
+        for rel in paths {
+            let result = self
+                .session
+                .state_vector(&rel)
+                .and_then(|before| Ok((before, self.session.ingest_markdown(&rel, None, None)?)));
+            match result {
+                Ok((before, outcome)) if outcome.changed => {
+                    self.emit(ServiceEvent::Ingested {
+                        path: rel.clone(),
+                        ops: outcome.changes.operation_count,
+                    });
+                    self.queue(rel, before);
+                }
+                Ok(_) => {}
+                Err(error) => self.report(&rel, &error),
+            }
+        }
+    }
+
+    fn queue(&mut self, rel: PathBuf, pushed: StateVector) {
+        self.outgoing.entry(rel).or_insert(Outgoing {
+            pushed,
+            attempts: 0,
+            next_attempt: Instant::now(),
+        });
+    }
+
+    /// Push every queued file whose retry time has come.
+    async fn push_due(&mut self) {
+        let now = Instant::now();
+        let due: Vec<PathBuf> = self
+            .outgoing
+            .iter()
+            .filter(|(_, outgoing)| outgoing.next_attempt <= now)
+            .map(|(rel, _)| rel.clone())
+            .collect();
+        for rel in due {
+            let Some(outgoing) = self.outgoing.get(&rel).cloned() else {
+                continue;
+            };
+            let message = match self.session.encode_changes_since(&rel, &outgoing.pushed) {
+                Ok(message) => message,
+                Err(error) => {
+                    self.outgoing.remove(&rel);
+                    self.report(&rel, &error);
+                    continue;
+                }
+            };
+            let ops = message.ops.len();
+            match self.transport.send(&rel, message).await {
+                Ok(()) => {
+                    self.outgoing.remove(&rel);
+                    self.emit(ServiceEvent::Pushed { path: rel, ops });
+                }
+                Err(error) => {
+                    let attempts = outgoing.attempts + 1;
+                    let gave_up = self
+                        .config
+                        .retry
+                        .max_attempts
+                        .is_some_and(|max| attempts >= max);
+                    let retry_in = (!gave_up).then(|| self.config.retry.timeout(attempts));
+                    match retry_in {
+                        Some(delay) => {
+                            self.outgoing.insert(
+                                rel.clone(),
+                                Outgoing {
+                                    attempts,
+                                    next_attempt: Instant::now() + delay,
+                                    ..outgoing
+                                },
+                            );
+                        }
+                        None => {
+                            self.outgoing.remove(&rel);
+                        }
+                    }
+                    self.emit(ServiceEvent::PushFailed {
+                        path: rel,
+                        error: error.to_string(),
+                        attempts,
+                        retry_in,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Apply an inbound message and write the merged document to disk.
+    ///
+    /// Local edits not yet ingested are ingested first so the export cannot
+    /// overwrite them. A path that is not one of the vault's notes (see
+    /// [`super::Vault::is_note`]) is refused before anything is read or written.
+    fn apply(&mut self, rel: PathBuf, message: ChangeMessage) {
+        if !self.session.vault.is_note(&rel) {
+            self.report(&rel, &VaultError::NotANote(rel.clone()));
+            return;
+        }
+        if self.session.vault.path.join(&rel).is_file() {
+            self.ingest(BTreeSet::from([rel.clone()]));
+        }
+        let limits = self.config.limits.clone();
+        let outcome = self
+            .session
+            .apply_remote(&rel, message, &limits)
+            .and_then(|outcome| {
+                let revision = self.session.revision(&rel)?;
+                let export = self.session.export_markdown(&rel, &revision, None)?;
+                Ok((outcome, export))
+            });
+        match outcome {
+            Ok((outcome, export)) => {
+                self.emit(ServiceEvent::Applied {
+                    path: rel.clone(),
+                    applied: outcome.applied.len(),
+                    buffered: outcome.buffered.len(),
+                });
+                if export.changed {
+                    // The watcher sees this write too; re-ingesting it is a no-op.
+                    self.emit(ServiceEvent::Materialized {
+                        path: rel,
+                        bytes: export.bytes_written,
+                    });
+                }
+            }
+            Err(error) => self.report(&rel, &error),
+        }
+    }
+
+    /// Checkpoint the history of every open document.
+    fn compact(&mut self) {
+        let Some((_, request)) = self.config.compaction.clone() else {
+            return;
+        };
+        let open: Vec<PathBuf> = self.session.open_paths().map(Path::to_path_buf).collect();
+        for rel in open {
+            let result = self.session.session_mut(&rel).and_then(|session| {
+                session
+                    .checkpoint_history(&request)
+                    .map_err(|error| VaultError::Snapshot(error.to_string()))
+            });
+            match result.and_then(|report| {
+                self.session.save_state(&rel)?;
+                Ok(report)
+            }) {
+                Ok(report) if report.pruned_ops > 0 => self.emit(ServiceEvent::Compacted {
+                    path: rel,
+                    pruned_ops: report.pruned_ops,
+                }),
+                Ok(_) => {}
+                Err(error) => self.report(&rel, &error),
+            }
+        }
+    }
+}
@@ -1,20 +1,26 @@
 //! Multi-document vault session: shared peer identity + lazy CollaborativeDocuments.
 
-use super::diff::{delete_indices_high_to_low, graphemes_of, insert_new_indices, lcs_steps};
+use super::conflict::{IngestConflict, conflict_markdown, conflict_path_for, detect_conflicts};
+use super::diff::{TextEdit, edits_from_steps, myers_steps};
+use super::manifest::{FileStamp, VaultManifest};
+use super::membership::{MembershipOp, VaultMembership};
 use super::{
-    BlockFingerprint, Fingerprint, IngestReport, LastFlushedState, MatchConfig, ParsedBlock, Score,
-    Vault, VaultError, block_content, fingerprint_document, hash_string, match_blocks,
+    BlockFingerprint, Fingerprint, FingerprintScheme, IngestReport, LastFlushedState, MatchConfig,
+    ParsedBlock, Score, Vault, VaultError, block_content, fingerprint_document, hash_string,
+    match_blocks,
 };
 use crate::codec::{DocOp, JsonOpCodec, OpBody, OpCodec};
 use crate::core::mark::{MarkKind, MarkValue};
 use crate::core::{OpId, PeerId, Sequence, StateVector};
 use crate::doc::{
-    Block, BlockId, BlockKind, ColumnId, Document, ListItem, Parser, RowId, Table,
-    block_id_from_op, paragraph_visible_string,
+    Block, BlockId, BlockKind, ColumnId, Document, LinkTarget, ListItem, Parser, RowId, Table,
+    block_id_from_op, heading_anchor, paragraph_visible_string,
 };
-use crate::session::{CollaborativeDocument, SessionError, SnapshotError, SyncResponse};
-use crate::storage::{Storage, StorageError};
-use crate::sync::{ChangeMessage, ValidationLimits};
+use crate::session::{
+    ChangeEntry, CollaborativeDocument, SessionError, SnapshotError, SyncResponse,
+};
+use crate::storage::{Storage, StorageError, VersionTag};
+use crate::sync::{ChangeMessage, SyncHandshake, ValidationLimits};
 use crate::workspace::{
     capture_outline, replace_moved_ids, stable_hash_128, summarize_outline_change,
 };
@@ -25,10 +31,12 @@ use crate::{
     ProjectionRequest, RecoveryReport, RemoteApplyOutcome, RevisionToken, StructuredEditLimits,
     VaultId, WorkspaceEdit,
 };
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use uuid::Uuid;
 
 /// Shared vault-level identity and open collaborative documents.
@@ -46,6 +54,7 @@ pub struct VaultSession {
     docs: BTreeMap<PathBuf, CollaborativeDocument>,
     document_ids: BTreeMap<PathBuf, DocumentId>,
     revision_cache: BTreeMap<PathBuf, RevisionToken>,
+    membership: VaultMembership,
 }
 
 impl VaultSession {
@@ -53,9 +62,15 @@ impl VaultSession {
     pub fn open(path: impl AsRef<Path>) -> Result<Self, VaultError> {
         let vault = Vault::open(path)?;
         vault.init()?;
+        vault.migrate_state_keys()?;
+        vault.migrate_state_formats_if_needed()?;
         recover_pending_transactions(&vault)?;
         let vault_id = load_or_create_identity::<VaultId>(&vault_id_path(&vault))?;
         let peer = load_or_create_peer_id(&vault)?;
+        let mut membership = VaultMembership::load(&vault)?;
+        if membership.register_peer(peer, peer)?.is_some() {
+            membership.save(&vault)?;
+        }
         Ok(Self {
             vault,
             vault_id,
@@ -64,6 +79,7 @@ impl VaultSession {
             docs: BTreeMap::new(),
             document_ids: BTreeMap::new(),
             revision_cache: BTreeMap::new(),
+            membership,
         })
     }
 
@@ -80,6 +96,57 @@ impl VaultSession {
         self.vault_id
     }
 
+    /// Files this vault holds and peers that write to it, as replicated sets.
+    ///
+    /// Opening the vault registers its peer; ingesting or creating a file adds
+    /// it, and [`Self::rename_markdown`] and [`Self::delete_markdown`] update
+    /// it. A file removed from disk by other means stays a member.
+    pub fn membership(&self) -> &VaultMembership {
+        &self.membership
+    }
+
+    /// Membership updates a replica at `since` has not seen.
+    pub fn membership_changes_since(&self, since: &StateVector) -> Vec<MembershipOp> {
+        self.membership.ops_since(since)
+    }
+
+    /// Apply membership updates from another replica and save the result.
+    pub fn apply_membership(
+        &mut self,
+        ops: impl IntoIterator<Item = MembershipOp>,
+    ) -> Result<(), VaultError> {
+        for op in ops {
+            self.membership.apply(op);
+        }
+        self.membership.save(&self.vault)
+    }
+
+    /// Retire `peer` in the peer registry, returning the op to ship (`None` if
+    /// it was already retired).
+    pub fn retire_peer(&mut self, peer: PeerId) -> Result<Option<MembershipOp>, VaultError> {
+        let op = self.membership.retire_peer(peer, self.peer)?;
+        self.save_membership(op)
+    }
+
+    fn track_file(&mut self, rel: &Path, present: bool) -> Result<(), VaultError> {
+        let op = if present {
+            self.membership.add_file(rel, self.peer)
+        } else {
+            self.membership.remove_file(rel, self.peer)
+        };
+        self.save_membership(op).map(|_| ())
+    }
+
+    fn save_membership(
+        &self,
+        op: Option<MembershipOp>,
+    ) -> Result<Option<MembershipOp>, VaultError> {
+        if op.is_some() {
+            self.membership.save(&self.vault)?;
+        }
+        Ok(op)
+    }
+
     /// Persistent identity for a vault-relative path, independent of file content.
     pub fn document_id(&mut self, rel_path: impl AsRef<Path>) -> Result<DocumentId, VaultError> {
         let rel = normalize_rel(rel_path.as_ref())?;
@@ -485,7 +552,7 @@ impl VaultSession {
             .get(&rel)
             .expect("revision check opened the session")
             .document()
-            .serialize(crate::doc::EquivalenceMode::Exact);
+            .serialize_with_config(&self.vault.config.serialize_config());
         let prior = fs::read(&path).ok();
         let changed = prior.as_deref() != Some(markdown.as_bytes());
         if changed {
@@ -497,7 +564,7 @@ impl VaultSession {
         let session = self.docs.get_mut(&rel).expect("session remains open");
         session.document_mut().adopt_source_from(&parsed);
         let state = LastFlushedState {
-            content_hash: hash_string(&markdown),
+            content_hash: self.vault.content_hash(&markdown),
             blocks: fingerprint_document(session.document()),
         };
         self.vault.write_last_flushed(&path, &state)?;
@@ -577,6 +644,8 @@ impl VaultSession {
         }
         self.document_ids.remove(&from);
         self.document_ids.insert(to.clone(), document_id);
+        self.track_file(&from, false)?;
+        self.track_file(&to, true)?;
         self.document_handle(&to)
     }
 
@@ -607,12 +676,120 @@ impl VaultSession {
         self.docs.remove(&rel);
         self.document_ids.remove(&rel);
         self.revision_cache.remove(&rel);
+        self.track_file(&rel, false)?;
         Ok(DeletedDocument {
             document_id,
             path: rel,
         })
     }
 
+    /// Rewrite note links after `from` was renamed to `to` (see [`Self::rename_markdown`]).
+    ///
+    /// Links elsewhere in the vault that resolved to `from` now point at `to`, and
+    /// relative links inside the moved note are adjusted to its new directory. Each
+    /// rewritten link is a remove + set mark pair in its document's history.
+    pub fn retarget_file_links(
+        &mut self,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+    ) -> Result<LinkRewriteReport, VaultError> {
+        let from = normalize_rel(from.as_ref())?;
+        let to = normalize_rel(to.as_ref())?;
+        self.rewrite_links(|doc, target| {
+            let LinkTarget::Note { path, heading } = target else {
+                return None;
+            };
+            let written_from = if doc == to { &from } else { doc };
+            let resolved = resolve_note_link(written_from, path)?;
+            let destination = if links_to(&resolved, &from) {
+                to.clone()
+            } else if doc == to {
+                resolved
+            } else {
+                return None;
+            };
+            let rewritten = note_link_path(doc, &destination, path);
+            (rewritten != *path).then(|| LinkTarget::Note {
+                path: rewritten,
+                heading: heading.clone(),
+            })
+        })
+    }
+
+    /// Rewrite links to the heading `old` of `rel_path` after it was retitled `new`.
+    pub fn retarget_heading_links(
+        &mut self,
+        rel_path: impl AsRef<Path>,
+        old: &str,
+        new: &str,
+    ) -> Result<LinkRewriteReport, VaultError> {
+        let rel = normalize_rel(rel_path.as_ref())?;
+        let (old, new) = (heading_anchor(old), heading_anchor(new));
+        if old == new {
+            return Ok(LinkRewriteReport::default());
+        }
+        self.rewrite_links(|doc, target| match target {
+            LinkTarget::Heading(heading) if doc == rel && heading_anchor(heading) == old => {
+                Some(LinkTarget::Heading(new.clone()))
+            }
+            LinkTarget::Note {
+                path,
+                heading: Some(heading),
+            } if heading_anchor(heading) == old
+                && resolve_note_link(doc, path)
+                    .is_some_and(|resolved| links_to(&resolved, &rel)) =>
+            {
+                Some(LinkTarget::Note {
+                    path: path.clone(),
+                    heading: Some(new.clone()),
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// Retarget every link mark `rewrite` maps to a new target and persist the
+    /// documents that changed. Links whose `href` does not parse are left alone.
+    fn rewrite_links(
+        &mut self,
+        rewrite: impl Fn(&Path, &LinkTarget) -> Option<LinkTarget>,
+    ) -> Result<LinkRewriteReport, VaultError> {
+        let files: Vec<PathBuf> = self
+            .vault
+            .files()
+            .filter_map(|abs| {
+                abs.strip_prefix(&self.vault.path)
+                    .ok()
+                    .map(Path::to_path_buf)
+            })
+            .collect();
+        let mut report = LinkRewriteReport::default();
+        for rel in files {
+            let session = self.session_mut(&rel)?;
+            let retargets: Vec<(BlockId, OpId, LinkTarget)> = session
+                .document()
+                .links()
+                .into_iter()
+                .filter_map(|(block_id, interval)| {
+                    let target = LinkTarget::parse(interval.href()?).ok()?;
+                    Some((block_id, interval.id, rewrite(&rel, &target)?))
+                })
+                .collect();
+            if retargets.is_empty() {
+                continue;
+            }
+            for (block_id, interval_id, target) in &retargets {
+                session
+                    .retarget_link(*block_id, *interval_id, target)
+                    .map_err(session_err)?;
+            }
+            self.save_state(&rel)?;
+            report.links += retargets.len();
+            report.documents.push(rel);
+        }
+        Ok(report)
+    }
+
     /// Publish several current document views under one recoverable commit intent.
     pub fn export_markdown_transaction(
         &mut self,
@@ -642,7 +819,7 @@ impl VaultSession {
                 .expect("revision verification opens the session");
             let markdown = session
                 .document()
-                .serialize(crate::doc::EquivalenceMode::Exact);
+                .serialize_with_config(&self.vault.config.serialize_config());
             let path = self.vault.path.join(&rel);
             let changed = fs::read(&path).ok().as_deref() != Some(markdown.as_bytes());
             prepared.push(PreparedExport {
@@ -736,7 +913,7 @@ impl VaultSession {
                 .expect("prepared export session remains open");
             session.document_mut().adopt_source_from(&parsed);
             let state = LastFlushedState {
-                content_hash: hash_string(&export.markdown),
+                content_hash: self.vault.content_hash(&export.markdown),
                 blocks: fingerprint_document(session.document()),
             };
             let path = self.vault.path.join(&export.rel);
@@ -797,6 +974,11 @@ impl VaultSession {
         Ok(self.session(rel_path)?.state_vector())
     }
 
+    /// One document's side of a sync handshake, with its frontier floor.
+    pub fn handshake(&mut self, rel_path: impl AsRef<Path>) -> Result<SyncHandshake, VaultError> {
+        Ok(self.session(rel_path)?.handshake())
+    }
+
     /// Encode operations for one document that are not covered by `since`.
     pub fn encode_changes_since(
         &mut self,
@@ -806,6 +988,18 @@ impl VaultSession {
         Ok(self.session(rel_path)?.encode_changes_since(since)?)
     }
 
+    /// Per-peer summary of one document's operations after `since` and up to `until`.
+    pub fn changelog(
+        &mut self,
+        rel_path: impl AsRef<Path>,
+        since: &StateVector,
+        until: &StateVector,
+    ) -> Result<Vec<ChangeEntry>, VaultError> {
+        self.session(rel_path)?
+            .changelog(since, until)
+            .map_err(history_err)
+    }
+
     /// Return an incremental delta or a full checkpoint when the peer is behind history retention.
     pub fn sync_since(
         &mut self,
@@ -843,6 +1037,73 @@ impl VaultSession {
         })
     }
 
+    /// Name `state_vector` of one document as version `name`, replacing an older
+    /// tag of that name. Tags live in the document's session storage.
+    pub fn tag_version(
+        &self,
+        rel_path: impl AsRef<Path>,
+        name: &str,
+        state_vector: &StateVector,
+    ) -> Result<VersionTag, VaultError> {
+        let rel = normalize_rel(rel_path.as_ref())?;
+        Ok(self.tag_storage(&rel)?.tag_version(name, state_vector)?)
+    }
+
+    /// Version tags of one document, oldest first.
+    pub fn list_tags(&self, rel_path: impl AsRef<Path>) -> Result<Vec<VersionTag>, VaultError> {
+        let rel = normalize_rel(rel_path.as_ref())?;
+        Ok(self.tag_storage(&rel)?.list_tags()?)
+    }
+
+    /// Revert one document to tagged version `name`.
+    ///
+    /// The document at the tag's frontier is replayed from the op log, then the live
+    /// session is edited to match it with the same structure diff as a re-ingest, so
+    /// the revert is ordinary history that peers merge like any other edit. The
+    /// returned [`RestoredVersion::revert`] carries those new operations for
+    /// broadcast; the markdown file is rewritten by the next flush.
+    pub fn restore(
+        &mut self,
+        rel_path: impl AsRef<Path>,
+        name: &str,
+    ) -> Result<RestoredVersion, VaultError> {
+        let rel = normalize_rel(rel_path.as_ref())?;
+        let tag = self
+            .tag_storage(&rel)?
+            .read_tag(name)?
+            .ok_or_else(|| VaultError::UnknownTag(name.to_string()))?;
+        let (document, revert, changes) = {
+            let session = self.session_mut(&rel)?;
+            let document = session
+                .materialize_at(&tag.state_vector)
+                .map_err(history_err)?;
+            let before = capture_outline(session.document());
+            let before_vector = session.state_vector();
+            sync_frontmatter(session, &document)?;
+            if session.document().blocks_in_order().is_empty() {
+                insert_tree(session, None, &document.blocks_in_order())?;
+            } else {
+                apply_structure_ingest(session, &document)?;
+            }
+            let revert = session.encode_changes_since(&before_vector)?;
+            let changes = summarize_session_transition(session, &before, &before_vector)?;
+            (document, revert, changes)
+        };
+        self.revision_cache
+            .insert(rel.clone(), changes.revision.clone());
+        self.save_state(&rel)?;
+        Ok(RestoredVersion {
+            tag,
+            document,
+            revert,
+            changes,
+        })
+    }
+
+    fn tag_storage(&self, rel: &Path) -> Result<Storage, VaultError> {
+        Ok(Storage::open(session_storage_path(&self.vault, rel))?)
+    }
+
     /// Persist one open document's session snapshot to storage.
     pub fn save_state(&self, rel_path: impl AsRef<Path>) -> Result<(), VaultError> {
         let rel = normalize_rel(rel_path.as_ref())?;
@@ -874,39 +1135,72 @@ impl VaultSession {
     ///
     /// Text LCS for matched-but-edited paragraphs is deferred. New paragraphs use N6-d
     /// (`insert_paragraph` = empty InsertBlock + InsertText).
+    ///
+    /// Files whose size and modification time match the vault manifest are
+    /// skipped without being read; see [`IngestReport::stats`].
     pub fn ingest_all(&mut self) -> Result<IngestReport, VaultError> {
+        let started = Instant::now();
         let files: Vec<PathBuf> = self.vault.files().collect();
+        let mut manifest = VaultManifest::load(&self.vault);
         let mut report = IngestReport::default();
+        let mut scanned = HashSet::new();
         for abs in files {
             let rel = abs
                 .strip_prefix(&self.vault.path)
                 .unwrap_or(abs.as_path())
                 .to_path_buf();
-            let outcome = self.ingest_file_unchecked(&rel)?;
+            report.stats.files_scanned += 1;
+            scanned.insert(rel.clone());
+            let metadata =
+                fs::metadata(&abs).map_err(|error| VaultError::from(error).in_file(&rel))?;
+            if manifest.is_unchanged(&rel, &metadata) {
+                report.files_noop += 1;
+                continue;
+            }
+            report.stats.files_hashed += 1;
+            let (outcome, content_hash) = self
+                .ingest_file_hashed(&rel)
+                .map_err(|error| error.in_file(&rel))?;
+            manifest.record(rel, FileStamp::of(&metadata, content_hash));
             if outcome.changed {
                 report.files_changed += 1;
                 report.ops_emitted += outcome.changes.operation_count;
             } else {
                 report.files_noop += 1;
             }
+            report.conflict_files.extend(outcome.conflict_file);
         }
+        manifest.retain(|rel| scanned.contains(rel));
+        manifest.save(&self.vault)?;
+        report.stats.files_changed = report.files_changed;
+        report.stats.elapsed = started.elapsed();
         Ok(report)
     }
 
     /// Structure ingest for a single vault-relative markdown path.
-    ///
-    /// Returns `(changed, ops_emitted)`.
     fn ingest_file_unchecked(
         &mut self,
         rel_path: impl AsRef<Path>,
     ) -> Result<IngestOutcome, VaultError> {
+        self.ingest_file_hashed(rel_path)
+            .map(|(outcome, _)| outcome)
+    }
+
+    /// [`Self::ingest_file_unchecked`], also returning the normalized content
+    /// hash the hash gate compared.
+    fn ingest_file_hashed(
+        &mut self,
+        rel_path: impl AsRef<Path>,
+    ) -> Result<(IngestOutcome, u64), VaultError> {
         let rel = normalize_rel(rel_path.as_ref())?;
         let abs = self.vault.path.join(&rel);
         if !abs.exists() {
             return Err(VaultError::PathDoesNotExist(abs));
         }
         let content = fs::read_to_string(&abs)?;
-        let content_hash = hash_string(&content);
+        let normalized = self.vault.config.normalize.apply(&content);
+        let content_hash = hash_string(&normalized);
+        self.track_file(&rel, true)?;
         self.session_mut(&rel)?;
         let before = capture_outline(
             self.docs
@@ -923,7 +1217,13 @@ impl VaultSession {
         if let Some(prev) = self.vault.read_last_flushed(&abs)?
             && prev.content_hash == content_hash
         {
-            let needs_source = !self.session_mut(&rel)?.document().has_source_state();
+            // Adopt the file's own bytes, so exact serialization reproduces them
+            // even when only line endings or composition changed.
+            let needs_source = self
+                .session_mut(&rel)?
+                .document()
+                .source()
+                .is_none_or(|source| source.original() != content);
             if needs_source {
                 let parsed = Parser::parse(&content);
                 self.session_mut(&rel)?
@@ -938,13 +1238,35 @@ impl VaultSession {
             )?;
             self.revision_cache
                 .insert(rel.clone(), changes.revision.clone());
-            return Ok(IngestOutcome {
+            let outcome = IngestOutcome {
                 changed: false,
                 changes,
-            });
+                conflicts: Vec::new(),
+                conflict_file: None,
+            };
+            return Ok((outcome, content_hash));
         }
 
-        let parsed = Parser::parse(&content);
+        let parsed = Parser::parse(&normalized);
+        let conflicts =
+            detect_conflicts(self.docs.get(&rel).expect("session opened above"), &parsed);
+        let conflict_file = if conflicts.is_empty() {
+            None
+        } else {
+            let crdt = self
+                .docs
+                .get(&rel)
+                .expect("session opened above")
+                .document()
+                .serialize_with_config(&self.vault.config.serialize_config());
+            let conflict_rel = conflict_path_for(&rel);
+            atomic_write_markdown(
+                &self.vault.path.join(&conflict_rel),
+                conflict_markdown(&content, &crdt).as_bytes(),
+                PublishControl::default(),
+            )?;
+            Some(conflict_rel)
+        };
         let _ops = {
             let session = self.docs.get_mut(&rel).expect("session opened above");
             let mut ops = sync_frontmatter(session, &parsed)?;
@@ -960,11 +1282,17 @@ impl VaultSession {
             ops
         };
 
+        // Normalized text drove the edits; the file's own bytes become the source
+        // that exact serialization reproduces.
+        let original = match &normalized {
+            Cow::Borrowed(_) => parsed,
+            Cow::Owned(_) => Parser::parse(&content),
+        };
         self.docs
             .get_mut(&rel)
             .expect("session still open")
             .document_mut()
-            .adopt_source_from(&parsed);
+            .adopt_source_from(&original);
 
         // Persist session snapshot + fingerprint/hash gate state.
         self.save_state(&rel)?;
@@ -981,10 +1309,13 @@ impl VaultSession {
         )?;
         self.revision_cache
             .insert(rel.clone(), changes.revision.clone());
-        Ok(IngestOutcome {
+        let outcome = IngestOutcome {
             changed: true,
             changes,
-        })
+            conflicts,
+            conflict_file,
+        };
+        Ok((outcome, content_hash))
     }
 
     fn load_or_create_session(&self, rel: &Path) -> Result<CollaborativeDocument, VaultError> {
@@ -1220,15 +1551,19 @@ fn complete_rename(
     if source.exists() && destination.exists() {
         return Err(VaultError::PathAlreadyExists(destination));
     }
+    // A rename that only changes case keeps its state when keys are case-folded.
+    let same_key = vault.state_key(from) == vault.state_key(to);
     persist_identity(&document_id_path(vault, to), document_id)?;
-    move_artifact(
-        &session_storage_path(vault, from),
-        &session_storage_path(vault, to),
-    )?;
-    move_artifact(
-        &vault.state_path_for(&source),
-        &vault.state_path_for(&destination),
-    )?;
+    if !same_key {
+        move_artifact(
+            &session_storage_path(vault, from),
+            &session_storage_path(vault, to),
+        )?;
+        move_artifact(
+            &vault.state_path_for(&source),
+            &vault.state_path_for(&destination),
+        )?;
+    }
     if source.exists() {
         if let Some(parent) = destination.parent() {
             fs::create_dir_all(parent)?;
@@ -1237,7 +1572,9 @@ fn complete_rename(
     } else if !destination.exists() {
         return Err(VaultError::PathDoesNotExist(source));
     }
-    remove_artifact(&document_id_path(vault, from))?;
+    if !same_key {
+        remove_artifact(&document_id_path(vault, from))?;
+    }
     if let Some(parent) = source.parent() {
         sync_directory(parent)?;
     }
@@ -1259,6 +1596,29 @@ fn complete_delete(vault: &Vault, path: &Path) -> Result<(), VaultError> {
     Ok(())
 }
 
+/// Vault-relative staging files that unfinished export journals still reference.
+pub(super) fn transaction_sidecars(vault: &Vault) -> Result<Vec<PathBuf>, VaultError> {
+    let root = transaction_root(vault);
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    let mut sidecars = Vec::new();
+    for entry in fs::read_dir(&root)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let journal: DurableJournal =
+            serde_json::from_slice(&fs::read(&path)?).map_err(|_| VaultError::Serialization)?;
+        if let DurableJournal::Export { entries } = journal {
+            for entry in entries {
+                sidecars.extend([entry.pending, entry.backup]);
+            }
+        }
+    }
+    Ok(sidecars)
+}
+
 fn recover_pending_transactions(vault: &Vault) -> Result<RecoveryReport, VaultError> {
     let root = transaction_root(vault);
     if !root.exists() {
@@ -1327,6 +1687,20 @@ fn recover_pending_transactions(vault: &Vault) -> Result<RecoveryReport, VaultEr
     Ok(report)
 }
 
+/// Vault-relative note a `.<name>.<uuid>.pending` / `.backup` export sidecar at `rel`
+/// stages content for, or `None` if `rel` is not one.
+pub(super) fn sidecar_target(rel: &Path) -> Option<PathBuf> {
+    let name = rel.file_name()?.to_str()?;
+    if !is_orphan_transaction_temp(name) {
+        return None;
+    }
+    let stem = name
+        .strip_suffix(".pending")
+        .or_else(|| name.strip_suffix(".backup"))?;
+    let (target, _) = stem.strip_prefix('.')?.rsplit_once('.')?;
+    Some(rel.with_file_name(target))
+}
+
 /// True for an interrupted-transaction temp file: `.<name>.<uuid>.pending` / `.backup`
 /// (export content pending) or `.<uuid>.pending` (journal write temp). Matched strictly by
 /// the trailing UUID segment so genuine dotfiles are never removed.
@@ -1858,7 +2232,11 @@ fn vault_id_path(vault: &Vault) -> PathBuf {
 }
 
 fn document_id_path(vault: &Vault, rel: &Path) -> PathBuf {
-    let mut path = vault.path.join(".mdcrdt").join("document_ids").join(rel);
+    let mut path = vault
+        .path
+        .join(".mdcrdt")
+        .join("document_ids")
+        .join(vault.state_key(rel));
     let extension = path
         .extension()
         .and_then(|value| value.to_str())
@@ -1972,6 +2350,9 @@ fn sync_directory(_path: &Path) -> std::io::Result<()> {
 }
 
 fn load_or_create_peer_id(vault: &Vault) -> Result<PeerId, VaultError> {
+    if let Some(peer) = vault.config.peer_id {
+        return Ok(peer);
+    }
     let path = VaultSession::peer_id_path(vault);
     if path.exists() {
         let raw = fs::read_to_string(&path)?;
@@ -2019,8 +2400,8 @@ fn sessions_root(vault: &Vault) -> PathBuf {
     vault.path.join(".mdcrdt").join("sessions")
 }
 
-fn session_storage_path(vault: &Vault, rel: &Path) -> PathBuf {
-    let mut path = sessions_root(vault).join(rel);
+pub(super) fn session_storage_path(vault: &Vault, rel: &Path) -> PathBuf {
+    let mut path = sessions_root(vault).join(vault.state_key(rel));
     path.set_extension("mdcrdt");
     path
 }
@@ -2037,6 +2418,9 @@ fn write_session_snapshot(
     let storage = Storage::open(&storage_path)?;
     doc.write_to_storage(&storage)
         .map_err(|e| VaultError::Snapshot(e.to_string()))?;
+    super::frontmatter_index::update_frontmatter_index(vault, rel, doc.document())?;
+    #[cfg(feature = "search")]
+    super::search::update_document_index(vault, rel, doc.document())?;
     Ok(())
 }
 
@@ -2044,11 +2428,47 @@ fn session_err(err: SessionError) -> VaultError {
     VaultError::Session(err.to_string())
 }
 
+/// Like [`session_err`], but keeps pruned history as a typed [`VaultError::RebaseRequired`].
+fn history_err(err: SessionError) -> VaultError {
+    match err {
+        SessionError::Rebase(error) => VaultError::RebaseRequired(error),
+        other => session_err(other),
+    }
+}
+
+/// Result of [`VaultSession::restore`].
+#[derive(Debug, Clone)]
+pub struct RestoredVersion {
+    pub tag: VersionTag,
+    /// The document as it stood at the tag's frontier.
+    pub document: Document,
+    /// Operations the revert issued on the live session.
+    pub revert: ChangeMessage,
+    pub changes: crate::ChangeSummary,
+}
+
 /// Outcome of ingesting a single file.
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct IngestOutcome {
     pub changed: bool,
     pub changes: crate::ChangeSummary,
+    /// Changes in the collaborative state the file overwrote; see
+    /// [`IngestConflict`].
+    #[serde(default)]
+    pub conflicts: Vec<IngestConflict>,
+    /// Vault-relative conflict file holding both versions, written when
+    /// `conflicts` is non-empty.
+    #[serde(default)]
+    pub conflict_file: Option<PathBuf>,
+}
+
+/// Link marks rewritten by [`VaultSession::retarget_file_links`] or
+/// [`VaultSession::retarget_heading_links`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkRewriteReport {
+    /// Documents with at least one rewritten link, in vault walk order.
+    pub documents: Vec<PathBuf>,
+    pub links: usize,
 }
 
 /// Insert a parsed block tree into `parent`'s children (top-level when `None`),
@@ -2147,7 +2567,7 @@ fn level_match_content(kind: &BlockKind) -> String {
     }
 }
 
-fn level_old_state(blocks: &[Block]) -> LastFlushedState {
+fn level_old_state(blocks: &[Block], scheme: &FingerprintScheme) -> LastFlushedState {
     LastFlushedState {
         content_hash: 0,
         blocks: blocks
@@ -2155,7 +2575,7 @@ fn level_old_state(blocks: &[Block]) -> LastFlushedState {
             .enumerate()
             .map(|(i, b)| BlockFingerprint {
                 block_id: b.id,
-                fingerprint: Fingerprint::from_content(&level_match_content(&b.kind)),
+                fingerprint: Fingerprint::from_content_with(&level_match_content(&b.kind), scheme),
                 container_path: Vec::new(),
                 position: i,
             })
@@ -2163,12 +2583,12 @@ fn level_old_state(blocks: &[Block]) -> LastFlushedState {
     }
 }
 
-fn level_new_parsed(blocks: &[&Block]) -> Vec<ParsedBlock> {
+fn level_new_parsed(blocks: &[&Block], scheme: &FingerprintScheme) -> Vec<ParsedBlock> {
     blocks
         .iter()
         .enumerate()
         .map(|(i, b)| ParsedBlock {
-            fingerprint: Fingerprint::from_content(&level_match_content(&b.kind)),
+            fingerprint: Fingerprint::from_content_with(&level_match_content(&b.kind), scheme),
             container_path: Vec::new(),
             position: i,
         })
@@ -2186,7 +2606,11 @@ fn sync_tree(
         min_match_score: Score(5000),
         ..MatchConfig::default()
     };
-    let mapping = match_blocks(&level_old_state(old), &level_new_parsed(new), &config);
+    let mapping = match_blocks(
+        &level_old_state(old, &config.fingerprint),
+        &level_new_parsed(new, &config.fingerprint),
+        &config,
+    );
 
     let old_by_id: HashMap<BlockId, &Block> = old.iter().map(|b| (b.id, b)).collect();
     let mut matched_by_new: HashMap<usize, BlockId> = mapping
@@ -2337,7 +2761,8 @@ fn sync_tree(
     Ok(ops)
 }
 
-/// Apply grapheme LCS between current paragraph body and `new_text` (InsertText/DeleteText).
+/// Apply the grapheme diff between current paragraph body and `new_text` as run-level
+/// InsertText/DeleteText ops.
 ///
 /// Preserves OpIds for LCS-equal units. Marks on deleted units may be dropped (documented).
 fn apply_paragraph_text_diff(
@@ -2347,8 +2772,9 @@ fn apply_paragraph_text_diff(
     new_text: &str,
     desired_block: &Block,
 ) -> Result<usize, VaultError> {
-    let old_g = graphemes_of(old_text);
-    let new_g = graphemes_of(new_text);
+    let rules = session.document().segmentation();
+    let old_g: Vec<&str> = rules.graphemes(old_text).collect();
+    let new_g: Vec<&str> = rules.graphemes(new_text).collect();
     let desired_marks = mark_specs(desired_block);
     let current_marks = session
         .document()
@@ -2358,7 +2784,7 @@ fn apply_paragraph_text_diff(
     if old_g == new_g && mark_semantics(&current_marks) == mark_semantics(&desired_marks) {
         return Ok(0);
     }
-    let steps = lcs_steps(&old_g, &new_g);
+    let steps = myers_steps(&old_g, &new_g);
     let old_to_new: HashMap<usize, usize> = steps
         .iter()
         .filter_map(|step| match step {
@@ -2389,39 +2815,13 @@ fn apply_paragraph_text_diff(
     };
     let mut ops = 0usize;
 
-    // Deletes first (high index → low) so offsets stay valid.
-    for i in delete_indices_high_to_low(&steps) {
-        session.delete_text(block_id, i, 1).map_err(session_err)?;
-        ops += 1;
-    }
-
-    // After deletes, live body is the LCS sequence. Insert missing new graphemes left→right.
-    let mut live_pos = 0usize;
-    let insert_set: HashSet<usize> = insert_new_indices(&steps).into_iter().collect();
-    let mut run = String::new();
-    let mut run_at: Option<usize> = None;
-    for (j, g) in new_g.iter().enumerate() {
-        if insert_set.contains(&j) {
-            if run_at.is_none() {
-                run_at = Some(live_pos);
-            }
-            run.push_str(g);
-        } else {
-            if let Some(at) = run_at.take() {
-                session
-                    .insert_text(block_id, at, &run)
-                    .map_err(session_err)?;
-                live_pos = at + graphemes_of(&run).len();
-                run.clear();
-                ops += 1;
-            }
-            live_pos += 1;
+    // Each edit's offset is into the text as left by the edits before it.
+    for edit in edits_from_steps(&steps, &new_g) {
+        match edit {
+            TextEdit::Delete { at, len } => session.delete_text(block_id, at, len),
+            TextEdit::Insert { at, text } => session.insert_text(block_id, at, &text),
         }
-    }
-    if let Some(at) = run_at {
-        session
-            .insert_text(block_id, at, &run)
-            .map_err(session_err)?;
+        .map_err(session_err)?;
         ops += 1;
     }
 
@@ -2858,6 +3258,67 @@ fn normalize_rel(path: &Path) -> Result<PathBuf, VaultError> {
     Ok(path.to_path_buf())
 }
 
+/// Vault-relative path a note link written in `from` resolves to; `None` when it
+/// climbs out of the vault. A leading `/` is the vault root.
+fn resolve_note_link(from: &Path, path: &str) -> Option<PathBuf> {
+    let (mut resolved, path) = match path.strip_prefix('/') {
+        Some(path) => (Vec::new(), path),
+        None => (
+            from.parent()
+                .into_iter()
+                .flat_map(Path::components)
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect(),
+            path,
+        ),
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                resolved.pop()?;
+            }
+            part => resolved.push(part.to_string()),
+        }
+    }
+    Some(resolved.iter().collect())
+}
+
+/// Whether a resolved link names `file`, with or without its `.md` extension.
+fn links_to(resolved: &Path, file: &Path) -> bool {
+    resolved == file || (resolved.extension().is_none() && resolved.with_extension("md") == file)
+}
+
+/// `href` path from `from` to `target`, keeping `original`'s style: vault-root
+/// absolute or relative, with or without the `.md` extension.
+fn note_link_path(from: &Path, target: &Path, original: &str) -> String {
+    let mut target = target.to_path_buf();
+    if Path::new(original).extension().is_none() {
+        target.set_extension("");
+    }
+    let parts: Vec<String> = target
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if original.starts_with('/') {
+        return format!("/{}", parts.join("/"));
+    }
+    let base: Vec<String> = from
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let common = base
+        .iter()
+        .zip(&parts)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let mut segments = vec![".."; base.len() - common];
+    segments.extend(parts[common..].iter().map(String::as_str));
+    segments.join("/")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,142 @@
+//! Platform-independent keys for per-file state under `.mdcrdt`.
+//!
+//! Fingerprint state, session snapshots and document ids are stored at paths
+//! derived from the vault-relative path of their Markdown file. Taken raw, the
+//! same file gets different keys on different machines: Windows separates
+//! components with `\`, macOS hands out decomposed (NFD) file names, and
+//! case-insensitive file systems let `Notes.md` and `notes.md` name one file.
+//! [`state_key`] maps all of those spellings to one key, and
+//! [`Vault::migrate_state_keys`](super::Vault::migrate_state_keys) re-keys
+//! state written before keys were normalized.
+
+use super::{Vault, VaultError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+
+/// Key for the vault-relative path `relative`: components joined with `/`
+/// whichever separator the path used, in Unicode NFC, and lowercased when
+/// `case_fold` is set.
+pub fn state_key(relative: &Path, case_fold: bool) -> PathBuf {
+    let raw = relative.to_string_lossy().replace('\\', "/");
+    let mut key: String = raw.nfc().collect();
+    if case_fold {
+        key = key.to_lowercase();
+    }
+    key.split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect()
+}
+
+/// What [`Vault::migrate_state_keys`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateKeyMigration {
+    /// Entries moved to their normalized key, as (old, new) paths under `.mdcrdt`.
+    pub rekeyed: Vec<(PathBuf, PathBuf)>,
+}
+
+impl StateKeyMigration {
+    pub fn is_empty(&self) -> bool {
+        self.rekeyed.is_empty()
+    }
+}
+
+impl Vault {
+    /// Key of the per-file state for the vault-relative `relative`, following
+    /// [`super::VaultConfig::state_case_fold`].
+    pub fn state_key(&self, relative: &Path) -> PathBuf {
+        state_key(relative, self.config.state_case_fold)
+    }
+
+    /// Move fingerprint state, session snapshots and document ids stored under
+    /// raw paths to their normalized keys. Safe to run repeatedly;
+    /// [`super::VaultSession::open`] runs it on every open.
+    ///
+    /// When two spellings of one file both have state, nothing is moved and
+    /// [`VaultError::StateKeyConflict`] names them: which history to keep, or
+    /// how to combine them, is for the user to decide.
+    pub fn migrate_state_keys(&self) -> Result<StateKeyMigration, VaultError> {
+        let mdcrdt = self.path.join(".mdcrdt");
+        let mut moves = Vec::new();
+        for (dir, suffix) in [
+            ("state", ".mdcrdt"),
+            ("sessions", ".mdcrdt"),
+            ("document_ids", ".id"),
+        ] {
+            let root = mdcrdt.join(dir);
+            let mut spellings: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+            for relative in stored_entries(&root, suffix)? {
+                spellings
+                    .entry(self.state_key(&relative))
+                    .or_default()
+                    .push(relative);
+            }
+            for (key, entries) in spellings {
+                match entries.as_slice() {
+                    [relative] if *relative != key => {
+                        moves.push((root.clone(), dir, relative.clone(), key));
+                    }
+                    [_] => {}
+                    _ => {
+                        return Err(VaultError::StateKeyConflict {
+                            key: Path::new(dir).join(&key),
+                            entries: entries
+                                .iter()
+                                .map(|relative| Path::new(dir).join(relative))
+                                .collect(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut migration = StateKeyMigration::default();
+        for (root, dir, relative, key) in moves {
+            let from = root.join(&relative);
+            let to = root.join(&key);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // A case-only rename on a case-insensitive file system needs a
+            // detour, since `from` and `to` name the same entry there.
+            let detour = from.with_extension("rekey");
+            fs::rename(&from, &detour)?;
+            fs::rename(&detour, &to)?;
+            migration
+                .rekeyed
+                .push((Path::new(dir).join(&relative), Path::new(dir).join(&key)));
+        }
+        Ok(migration)
+    }
+}
+
+/// Paths, relative to `root`, of the entries under it whose names end in
+/// `suffix`, without descending into them.
+pub(super) fn stored_entries(root: &Path, suffix: &str) -> Result<Vec<PathBuf>, VaultError> {
+    let mut entries = Vec::new();
+    if !root.is_dir() {
+        return Ok(entries);
+    }
+    let mut walk = WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry.map_err(|error| VaultError::Io(error.into()))?;
+        let is_entry = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.ends_with(suffix));
+        if !is_entry {
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            walk.skip_current_dir();
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        entries.push(relative.to_path_buf());
+    }
+    Ok(entries)
+}
@@ -0,0 +1,101 @@
+//! Per-file divergence of a vault from its last flush.
+
+use super::{MatchConfig, MatchType, Vault, VaultError, match_blocks};
+use crate::doc::{EquivalenceMode, Parser};
+use crate::session::CollaborativeDocument;
+use crate::storage::Storage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How one Markdown file compares with the state recorded by its last flush or
+/// ingest, from [`Vault::status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatus {
+    /// Vault-relative path.
+    pub path: PathBuf,
+    /// Whether a flush or ingest has recorded this file.
+    pub tracked: bool,
+    /// The file's content differs from what was recorded.
+    pub modified: bool,
+    /// Blocks added, removed, edited or moved since the recording, as paired by
+    /// [`match_blocks`]; 0 unless `modified`.
+    pub changed_blocks: usize,
+    /// When the recording was written.
+    pub last_flush: Option<SystemTime>,
+    /// The file was modified while its saved collaborative session also moved
+    /// away from the recording, so the next ingest merges edits from both sides.
+    pub conflicted: bool,
+}
+
+impl FileStatus {
+    /// Untracked or modified.
+    pub fn is_dirty(&self) -> bool {
+        !self.tracked || self.modified
+    }
+}
+
+impl Vault {
+    /// Status of every Markdown file, in path order. Reads state only; nothing
+    /// is created for untracked files.
+    pub fn status(&self) -> Result<Vec<FileStatus>, VaultError> {
+        let mut files: Vec<PathBuf> = self.files().collect();
+        files.sort();
+        files
+            .iter()
+            .map(|file| self.file_status(file).map_err(|err| err.in_file(file)))
+            .collect()
+    }
+
+    /// Status of one Markdown file (absolute or vault-relative).
+    pub fn file_status(&self, file: &Path) -> Result<FileStatus, VaultError> {
+        let abs = if file.is_absolute() {
+            file.to_path_buf()
+        } else {
+            self.path.join(file)
+        };
+        let mut status = FileStatus {
+            path: abs.strip_prefix(&self.path).unwrap_or(&abs).to_path_buf(),
+            tracked: false,
+            modified: false,
+            changed_blocks: 0,
+            last_flush: None,
+            conflicted: false,
+        };
+        let state_path = self.state_path_for(&abs);
+        if !state_path.exists() {
+            return Ok(status);
+        }
+        let Some(flushed) = self.read_last_flushed(&abs)? else {
+            return Ok(status);
+        };
+        status.tracked = true;
+        status.last_flush = Storage::open(&state_path)?.last_written()?;
+
+        let content = fs::read_to_string(&abs)?;
+        if self.content_hash(&content) == flushed.content_hash {
+            return Ok(status);
+        }
+        status.modified = true;
+        let config = MatchConfig::default();
+        let normalized = self.config.normalize.apply(&content);
+        let parsed =
+            super::parsed_blocks_from_doc_with(&Parser::parse(&normalized), &config.fingerprint);
+        let mapping = match_blocks(&flushed, &parsed, &config);
+        let edited = mapping
+            .matched
+            .iter()
+            .filter(|matched| matched.match_type != MatchType::ExactFingerprint)
+            .count();
+        status.changed_blocks = edited + mapping.removed.len() + mapping.added.len();
+
+        let session_path = super::session::session_storage_path(self, &status.path);
+        if session_path.exists() {
+            let session = CollaborativeDocument::read_from_storage(&Storage::open(&session_path)?)
+                .map_err(|err| VaultError::Snapshot(err.to_string()))?;
+            let rendered = session.document().serialize(EquivalenceMode::Exact);
+            status.conflicted = self.content_hash(&rendered) != flushed.content_hash;
+        }
+        Ok(status)
+    }
+}
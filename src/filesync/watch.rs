@@ -0,0 +1,90 @@
+//! Polling change detection for vault Markdown files.
+//!
+//! A [`VaultWatcher`] remembers the size and modification time of every file it
+//! saw and reports what differs on each [`VaultWatcher::poll`]. Polling needs no
+//! platform notification API and tolerates editors that save by renaming a
+//! temporary file over the original.
+
+use super::Vault;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A difference found by [`VaultWatcher::poll`], with a vault-relative path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WatchEvent {
+    /// Created, or written since the previous poll.
+    Changed(PathBuf),
+    Removed(PathBuf),
+}
+
+impl WatchEvent {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Changed(path) | Self::Removed(path) => path,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+pub struct VaultWatcher {
+    vault: Vault,
+    seen: BTreeMap<PathBuf, FileStamp>,
+}
+
+impl VaultWatcher {
+    /// Watch `vault`, treating the files present now as already seen.
+    pub fn new(vault: &Vault) -> Self {
+        let mut watcher = Self {
+            vault: Vault {
+                path: vault.path.clone(),
+                config: vault.config.clone(),
+            },
+            seen: BTreeMap::new(),
+        };
+        watcher.seen = watcher.scan();
+        watcher
+    }
+
+    /// Files created, written or removed since the previous poll, in path order.
+    pub fn poll(&mut self) -> Vec<WatchEvent> {
+        let current = self.scan();
+        let mut events: Vec<WatchEvent> = current
+            .iter()
+            .filter(|(path, stamp)| self.seen.get(*path) != Some(stamp))
+            .map(|(path, _)| WatchEvent::Changed(path.clone()))
+            .collect();
+        events.extend(
+            self.seen
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .map(|path| WatchEvent::Removed(path.clone())),
+        );
+        events.sort();
+        self.seen = current;
+        events
+    }
+
+    fn scan(&self) -> BTreeMap<PathBuf, FileStamp> {
+        self.vault
+            .files()
+            .filter_map(|file| {
+                // A file removed mid-scan is reported as removed on the next poll.
+                let metadata = fs::metadata(&file).ok()?;
+                let relative = file.strip_prefix(&self.vault.path).ok()?.to_path_buf();
+                let stamp = FileStamp {
+                    len: metadata.len(),
+                    modified: metadata.modified().ok(),
+                };
+                Some((relative, stamp))
+            })
+            .collect()
+    }
+}
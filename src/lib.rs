@@ -25,6 +25,10 @@
 //!
 //! - `storage` - Enables checksummed, generation-based persistence with rkyv serialization
 //! - `filesync` - Enables vault-based file system synchronization (requires `storage`)
+//! - `cli` - Builds the `md-crdt` command-line tool (requires `filesync`)
+//! - `search` - Enables the vault-wide full-text block index (`Vault::search`, requires `filesync`)
+//! - `yrs-interop` - Enables import/export of Yjs `Y.Text` updates
+//! - `metrics` - Enables process-wide sync counters (`metrics::snapshot`)
 //! - `dhat-heap` - Enables heap profiling with dhat
 
 /// Compiles the README's Rust examples as doctests so they cannot silently rot.
@@ -58,26 +62,45 @@ pub mod workspace;
 #[cfg(feature = "storage")]
 pub mod storage;
 
+// Test harnesses: corpus round-trips, and storage-backed offline peers
+pub mod testing;
+
 // Optional: File system synchronization
 #[cfg(feature = "filesync")]
 pub mod filesync;
 
+// Optional: Yjs update import/export
+#[cfg(feature = "yrs-interop")]
+pub mod yjs;
+
+// Optional: Sync counters for relay operators
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
 // Re-export core types
-pub use core::{Element, LwwRegister, Map, OpId, PeerId, Sequence, SequenceOp, StateVector};
+pub use core::{
+    CausalOrd, ClockError, Counter, CounterDelta, CounterRanges, Element, ElementInfo, LwwRegister,
+    Map, MapOp, Mergeable, MultiValueRegister, OpId, OpIdRange, OrSet, OrSetOp, PageStore,
+    PagedSequence, PeerClock, PeerId, RegisterWrite, SegmentationRules, Sequence, SequenceDelta,
+    SequenceOp, SequenceStats, StateVector, Text, TextOp,
+};
 
 // Re-export unified mark types (rich causal MarkSet is the single public API)
 pub use core::mark::{
-    Anchor, AnchorBias, MarkInterval, MarkIntervalId, MarkKind, MarkSet, MarkValue, RemoveMark,
-    Span,
+    Anchor, AnchorBias, InvalidMarkKind, MarkAttrType, MarkInterval, MarkIntervalId, MarkKind,
+    MarkOp, MarkSchema, MarkSchemaError, MarkSet, MarkValue, RemoveMark, SchemaMode, Span,
 };
 
 // Re-export doc types
 pub use doc::{
-    Block, BlockId, BlockKind, BulletMarker, CellAddress, CellContent, CodeFenceStyle,
-    ColumnAlignment, ColumnDef, ColumnId, Document, EditError, EditOp, EquivalenceMode,
-    FenceMarker, InsertTextRun, ListDelimiter, ListItem, ListStyle, Parser, RowId, SerializeConfig,
-    Table, TableCell, TableColumn, TableRow, TaskState, block_id_from_op, block_text_seq,
-    block_text_seq_mut,
+    Block, BlockAcl, BlockCounts, BlockHash, BlockId, BlockInsert, BlockKind, BlockPath,
+    BlockRangeDelete, BlockRef, BulletMarker, CellAddress, CellContent, CodeFenceStyle,
+    ColumnAlignment, ColumnDef, ColumnId, Document, DocumentBuilder, DocumentStats, EditError,
+    EditOp, EquivalenceMode, FenceMarker, InsertTextRun, InvalidMark, LinkError, LinkTarget,
+    ListDelimiter, ListItem, ListStyle, ParseError, ParseLimit, Parser, ParserLimits,
+    ParserOptions, RegisterConflict, RegisterKey, ReplaceAll, ResolutionPolicy, ResolutionTarget,
+    RowId, SerializeConfig, SerializerRegistry, StyledRun, Table, TableCell, TableColumn, TableRow,
+    TaskState, block_id_from_op, block_text_seq, block_text_seq_mut, diverged_blocks,
 };
 
 // Re-export doc mark operations
@@ -85,22 +108,28 @@ pub use doc::mark_ops;
 
 // Re-export sync types
 pub use sync::{
-    ApplyResult, ChangeMessage, CheckpointError, CheckpointReport, CheckpointRequest,
-    DocumentTombstonePolicy, MalformedKind, Operation, PeerLease, RebaseRequired, SemanticConflict,
-    SyncState, ValidationError, ValidationLimits, validate_changes,
+    AckTracker, ApplyResult, Capabilities, CausalMode, ChangeMessage, CheckpointError,
+    CheckpointReport, CheckpointRequest, Decision, DocumentTombstonePolicy, FORMAT_VERSION,
+    FormatMismatch, Hello, HelloError, HistoryBudget, KeepAlive, MIN_FORMAT_VERSION,
+    MIN_PROTOCOL_VERSION, MalformedKind, MembershipError, Negotiated, OpClass, OpFilter, OpHookId,
+    Operation, Origin, OutboxBatch, OutboxToken, PROTOCOL_VERSION, PeerLease, PeerRegistry,
+    PeerStatus, ReadOnlyReplica, RebaseRequired, ReplicaMode, RetryPolicy, SemanticConflict,
+    SendRecord, SyncHandshake, SyncState, SyncUpdate, UpdateListenerId, ValidationError,
+    ValidationLimits, validate_changes,
 };
 
 // Re-export codec types
 pub use codec::{
     BlockKindSkeleton, BlockSkeleton, BlockSkeletonInsert, CodecError, DocOp, Envelope,
-    JsonOpCodec, MAX_WIRE_NEST_DEPTH, OpBody, OpCodec, TableCellWire, TextUnitWire, WIRE_VERSION,
-    insert_block_paragraph_is_empty,
+    JsonOpCodec, MAX_WIRE_NEST_DEPTH, MIN_WIRE_VERSION, OpBody, OpCodec, ScalarOp, TableCellWire,
+    TextUnitWire, WIRE_VERSION, insert_block_paragraph_is_empty,
 };
 
 // Re-export session types
 pub use session::{
-    CollaborativeDocument, DocumentDto, SNAPSHOT_FORMAT_VERSION, SessionApplyResult, SessionError,
-    SessionSnapshot, SnapshotError, SyncResponse,
+    ChangeEntry, CollaborativeDocument, DocumentDto, MetadataStamp, MetadataVerifier,
+    SNAPSHOT_FORMAT_VERSION, SessionApplyResult, SessionError, SessionSnapshot, SnapshotDiffError,
+    SnapshotError, SyncResponse, diff_snapshots,
 };
 
 pub use workspace::{
@@ -121,13 +150,21 @@ pub use sync::IntegrateResult;
 
 // Re-export storage types (feature-gated)
 #[cfg(feature = "storage")]
-pub use storage::{CompactionReport, Storage, StorageError, TombstoneRetention};
+pub use storage::{
+    ArchivedDocument, ChainHash, ChainLink, CompactionReport, DocumentArchive, LazyDocument,
+    Storage, StorageError, TombstoneRetention, VersionTag, access_document, encode_document,
+};
 
 // Re-export filesync types (feature-gated)
 #[cfg(feature = "filesync")]
 pub use filesync::{
-    AddedBlock, ArchivedBlockFingerprint, BlockFingerprint, BlockMapping, BlockMatch, Fingerprint,
-    IngestOutcome, IngestReport, IngestResult, LastFlushedState, MatchConfig, MatchType,
-    ParsedBlock, Score, Vault, VaultError, VaultSession, fingerprint_document, match_blocks,
-    parsed_blocks_from_doc,
+    AddedBlock, ArchivedBlockFingerprint, AuditFinding, AuditIssue, BlockFingerprint, BlockMapping,
+    BlockMatch, FieldFilter, FieldValue, Fingerprint, FingerprintScheme, FrontmatterIndex,
+    IngestConflict, IngestOutcome, IngestReport, IngestResult, IngestStats, LastFlushedState,
+    LinkRewriteReport, MatchConfig, MatchType, MigratedState, ParsedBlock, RestoredVersion, Score,
+    StateFormatMigration, StateKeyMigration, Vault, VaultAudit, VaultError, VaultSession,
+    fingerprint_document, fingerprint_document_with, match_blocks, parsed_blocks_from_doc,
+    parsed_blocks_from_doc_with,
 };
+#[cfg(feature = "search")]
+pub use filesync::{SearchHit, SearchIndex};
@@ -0,0 +1,342 @@
+//! Process-wide sync counters for operators of a sync relay.
+//!
+//! Every [`SyncState`](crate::sync::SyncState) in the process feeds the same
+//! counters, so a relay hosting many documents reads one aggregate. Counters
+//! only grow; sample [`snapshot`] periodically and export the deltas to the
+//! monitoring system of choice, e.g. through [`SyncMetrics::export`].
+//!
+//! [`SyncMetrics::pending_depth`] is the total across every live pending
+//! buffer. Per document,
+//! [`SyncState::pending_metrics`](crate::sync::SyncState::pending_metrics)
+//! reports how deep each is and how long its oldest operation has waited,
+//! which is what grows when a peer goes silent or a message is lost.
+
+use crate::core::PeerId;
+use crate::sync::Origin;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the [`LatencyHistogram`] buckets, in milliseconds; a last
+/// bucket holds everything slower.
+pub const LATENCY_BUCKETS_MS: [u64; 7] = [1, 10, 100, 1_000, 10_000, 60_000, 600_000];
+
+const BUCKETS: usize = LATENCY_BUCKETS_MS.len() + 1;
+
+static OPS_APPLIED: AtomicU64 = AtomicU64::new(0);
+static OPS_BUFFERED: AtomicU64 = AtomicU64::new(0);
+static MESSAGES_REJECTED: AtomicU64 = AtomicU64::new(0);
+static PENDING_DEPTH: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static LOCAL_OPS: AtomicU64 = AtomicU64::new(0);
+static REMOTE_OPS: AtomicU64 = AtomicU64::new(0);
+static UNDO_OPS: AtomicU64 = AtomicU64::new(0);
+static IMPORT_OPS: AtomicU64 = AtomicU64::new(0);
+static PROMOTION_LATENCY: [AtomicU64; BUCKETS] = [const { AtomicU64::new(0) }; BUCKETS];
+static PROMOTION_LATENCY_SUM_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Point-in-time copy of the sync counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncMetrics {
+    /// Remote operations integrated into an applied log, including promotions.
+    pub ops_applied: u64,
+    /// Remote operations that arrived before their causal predecessors.
+    pub ops_buffered: u64,
+    /// Change messages refused by validation.
+    pub messages_rejected: u64,
+    /// Operations buffered across every live [`SyncState`](crate::sync::SyncState)
+    /// (a gauge).
+    pub pending_depth: u64,
+    /// Payload bytes of remote operations received.
+    pub bytes_received: u64,
+    /// Payload bytes of operations encoded for peers.
+    pub bytes_sent: u64,
+    /// Operations logged, local ones included, by [`Origin`].
+    pub by_origin: OriginCounts,
+    /// How long buffered operations waited before promotion.
+    pub promotion_latency: LatencyHistogram,
+}
+
+impl SyncMetrics {
+    /// Remote operations applied per second between `earlier` and this
+    /// snapshot, taken `elapsed` apart.
+    pub fn ops_applied_per_sec(&self, earlier: &SyncMetrics, elapsed: Duration) -> f64 {
+        per_sec(
+            self.ops_applied.saturating_sub(earlier.ops_applied),
+            elapsed,
+        )
+    }
+
+    /// Payload bytes received per second since `earlier`.
+    pub fn bytes_received_per_sec(&self, earlier: &SyncMetrics, elapsed: Duration) -> f64 {
+        per_sec(
+            self.bytes_received.saturating_sub(earlier.bytes_received),
+            elapsed,
+        )
+    }
+
+    /// Payload bytes sent per second since `earlier`.
+    pub fn bytes_sent_per_sec(&self, earlier: &SyncMetrics, elapsed: Duration) -> f64 {
+        per_sec(self.bytes_sent.saturating_sub(earlier.bytes_sent), elapsed)
+    }
+
+    /// Pass every value to `sample`, named in the Prometheus style, so an
+    /// exporter or the `metrics` crate can collect them without knowing this
+    /// struct.
+    pub fn export(&self, mut sample: impl FnMut(Sample)) {
+        let counters = [
+            ("md_crdt_ops_applied_total", self.ops_applied),
+            ("md_crdt_ops_buffered_total", self.ops_buffered),
+            ("md_crdt_messages_rejected_total", self.messages_rejected),
+            ("md_crdt_bytes_received_total", self.bytes_received),
+            ("md_crdt_bytes_sent_total", self.bytes_sent),
+        ];
+        for (name, value) in counters {
+            sample(Sample::counter(name, value));
+        }
+        sample(Sample::gauge("md_crdt_pending_depth", self.pending_depth));
+        let origins = [
+            ("local", self.by_origin.local),
+            ("remote", self.by_origin.remote),
+            ("undo", self.by_origin.undo),
+            ("import", self.by_origin.import),
+        ];
+        for (origin, value) in origins {
+            sample(Sample::counter("md_crdt_ops_by_origin_total", value).label("origin", origin));
+        }
+        self.promotion_latency
+            .export("md_crdt_promotion_latency_ms", &mut sample);
+    }
+}
+
+fn per_sec(delta: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    delta as f64 / elapsed.as_secs_f64()
+}
+
+/// Operation counts per [`Origin`]; remote ones are not split by peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OriginCounts {
+    pub local: u64,
+    pub remote: u64,
+    pub undo: u64,
+    pub import: u64,
+}
+
+/// Counts of waits falling into each of [`LATENCY_BUCKETS_MS`], plus one
+/// bucket for longer waits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    pub counts: [u64; BUCKETS],
+    pub sum_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_millis(self.sum_ms / count))
+    }
+
+    /// Each bucket's upper bound, `None` for the last, with its count.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|ms| Some(Duration::from_millis(*ms)))
+            .chain([None])
+            .zip(self.counts)
+    }
+
+    /// Cumulative `_bucket` samples labelled `le`, then `_sum` and `_count`.
+    fn export(&self, name: &str, sample: &mut impl FnMut(Sample)) {
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets() {
+            cumulative += count;
+            let le = bound.map_or_else(|| "+Inf".to_owned(), |bound| bound.as_millis().to_string());
+            sample(
+                Sample::new(&format!("{name}_bucket"), SampleKind::Bucket, cumulative)
+                    .label("le", le),
+            );
+        }
+        sample(Sample::counter(&format!("{name}_sum"), self.sum_ms));
+        sample(Sample::counter(&format!("{name}_count"), cumulative));
+    }
+}
+
+/// Pending buffer of one [`SyncState`](crate::sync::SyncState) at a point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingMetrics {
+    pub depth: usize,
+    /// Buffered operations per authoring peer; peers with none are omitted.
+    pub depth_by_peer: BTreeMap<PeerId, usize>,
+    /// How long the longest-waiting operation has been buffered.
+    pub oldest_age: Option<Duration>,
+}
+
+impl PendingMetrics {
+    /// Whether an operation has waited longer than `max_age`, the usual sign of
+    /// a silent peer or a dropped message.
+    pub fn is_stalled(&self, max_age: Duration) -> bool {
+        self.oldest_age.is_some_and(|age| age > max_age)
+    }
+
+    /// Pass every value to `sample`, as [`SyncMetrics::export`] does.
+    pub fn export(&self, mut sample: impl FnMut(Sample)) {
+        sample(Sample::gauge(
+            "md_crdt_document_pending_depth",
+            self.depth as u64,
+        ));
+        for (peer, depth) in &self.depth_by_peer {
+            sample(
+                Sample::gauge("md_crdt_document_pending_depth_by_peer", *depth as u64)
+                    .label("peer", peer.to_string()),
+            );
+        }
+        let age = self.oldest_age.map_or(0, |age| age.as_millis() as u64);
+        sample(Sample::gauge("md_crdt_document_oldest_pending_ms", age));
+    }
+}
+
+/// Whether a [`Sample`] only grows or may go either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleKind {
+    Counter,
+    Gauge,
+    /// A cumulative histogram bucket.
+    Bucket,
+}
+
+/// One exported value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    pub kind: SampleKind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: u64,
+}
+
+impl Sample {
+    fn new(name: &str, kind: SampleKind, value: u64) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            labels: Vec::new(),
+            value,
+        }
+    }
+
+    fn counter(name: &str, value: u64) -> Self {
+        Self::new(name, SampleKind::Counter, value)
+    }
+
+    fn gauge(name: &str, value: u64) -> Self {
+        Self::new(name, SampleKind::Gauge, value)
+    }
+
+    fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.labels.push((key, value.into()));
+        self
+    }
+}
+
+/// Read all counters.
+pub fn snapshot() -> SyncMetrics {
+    SyncMetrics {
+        ops_applied: OPS_APPLIED.load(Ordering::Relaxed),
+        ops_buffered: OPS_BUFFERED.load(Ordering::Relaxed),
+        messages_rejected: MESSAGES_REJECTED.load(Ordering::Relaxed),
+        pending_depth: PENDING_DEPTH.load(Ordering::Relaxed),
+        bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        by_origin: OriginCounts {
+            local: LOCAL_OPS.load(Ordering::Relaxed),
+            remote: REMOTE_OPS.load(Ordering::Relaxed),
+            undo: UNDO_OPS.load(Ordering::Relaxed),
+            import: IMPORT_OPS.load(Ordering::Relaxed),
+        },
+        promotion_latency: LatencyHistogram {
+            counts: std::array::from_fn(|bucket| PROMOTION_LATENCY[bucket].load(Ordering::Relaxed)),
+            sum_ms: PROMOTION_LATENCY_SUM_MS.load(Ordering::Relaxed),
+        },
+    }
+}
+
+pub(crate) fn record_applied(ops: u64, bytes: u64) {
+    OPS_APPLIED.fetch_add(ops, Ordering::Relaxed);
+    BYTES_RECEIVED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn record_buffered(bytes: u64) {
+    OPS_BUFFERED.fetch_add(1, Ordering::Relaxed);
+    BYTES_RECEIVED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn record_promoted(ops: u64) {
+    OPS_APPLIED.fetch_add(ops, Ordering::Relaxed);
+}
+
+pub(crate) fn record_promotion_latency(waited: Duration) {
+    let ms = waited.as_millis() as u64;
+    let bucket = LATENCY_BUCKETS_MS
+        .iter()
+        .position(|bound| ms <= *bound)
+        .unwrap_or(LATENCY_BUCKETS_MS.len());
+    PROMOTION_LATENCY[bucket].fetch_add(1, Ordering::Relaxed);
+    PROMOTION_LATENCY_SUM_MS.fetch_add(ms, Ordering::Relaxed);
+}
+
+pub(crate) fn record_rejected() {
+    MESSAGES_REJECTED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// One pending buffer's share of [`SyncMetrics::pending_depth`], taken back
+/// out of the total when the buffer's owner is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct PendingGauge {
+    depth: u64,
+}
+
+impl PendingGauge {
+    pub(crate) fn set(&mut self, depth: usize) {
+        let depth = depth as u64;
+        if depth > self.depth {
+            PENDING_DEPTH.fetch_add(depth - self.depth, Ordering::Relaxed);
+        } else {
+            PENDING_DEPTH.fetch_sub(self.depth - depth, Ordering::Relaxed);
+        }
+        self.depth = depth;
+    }
+}
+
+impl Clone for PendingGauge {
+    fn clone(&self) -> Self {
+        PENDING_DEPTH.fetch_add(self.depth, Ordering::Relaxed);
+        Self { depth: self.depth }
+    }
+}
+
+impl Drop for PendingGauge {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
+
+pub(crate) fn record_sent(bytes: u64) {
+    BYTES_SENT.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn record_origin(origin: Origin, ops: u64) {
+    let counter = match origin {
+        Origin::Local => &LOCAL_OPS,
+        Origin::Remote(_) => &REMOTE_OPS,
+        Origin::Undo => &UNDO_OPS,
+        Origin::Import => &IMPORT_OPS,
+    };
+    counter.fetch_add(ops, Ordering::Relaxed);
+}
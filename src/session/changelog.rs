@@ -0,0 +1,280 @@
+//! Human-readable summaries of applied history.
+//!
+//! Each operation's id names the peer that issued it, so decoding the op log
+//! between two frontiers attributes every block insert, edit, and delete to a
+//! peer. Edits, rows, and items added to blocks the same range inserted are
+//! folded into the insert. Operations stamped with metadata also contribute
+//! their authors and time span.
+
+use super::{CollaborativeDocument, SessionError, codec_err};
+use crate::codec::{DocOp, OpBody, OpCodec, OpMetadata};
+use crate::core::merge::covers;
+use crate::core::{PeerId, StateVector};
+use crate::doc::{Block, BlockId, BlockKind, Document, paragraph_visible_string};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Longest block label quoted in a summary, in characters.
+const LABEL_CHARS: usize = 40;
+
+/// Edited block labels quoted before the rest are counted.
+const QUOTED_EDITS: usize = 3;
+
+/// What one peer changed inside a history range.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    pub peer: PeerId,
+    pub operations: usize,
+    pub blocks_added: usize,
+    pub blocks_deleted: usize,
+    pub blocks_moved: usize,
+    /// Labels of pre-existing blocks whose content changed, in first-edit order.
+    /// Blocks no longer in the document are counted but not labelled.
+    pub edited: Vec<String>,
+    pub blocks_edited: usize,
+    pub table_rows_added: usize,
+    pub table_rows_deleted: usize,
+    pub table_columns_added: usize,
+    pub table_columns_deleted: usize,
+    pub list_items_added: usize,
+    pub list_items_deleted: usize,
+    pub tasks_changed: usize,
+    pub frontmatter_changes: usize,
+    /// Author labels from the operations' metadata, in first-seen order.
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Earliest and latest metadata timestamps in the range, in milliseconds
+    /// since the Unix epoch; `None` when no operation carries metadata.
+    #[serde(default)]
+    pub first_timestamp_ms: Option<u64>,
+    #[serde(default)]
+    pub last_timestamp_ms: Option<u64>,
+    /// One-line description, e.g. `peer 3 added 2 blocks, edited 'Intro'`.
+    pub summary: String,
+}
+
+impl<C: OpCodec> CollaborativeDocument<C> {
+    /// Per-peer summary of the applied operations after `since` and up to `until`,
+    /// ordered by peer id. Peers with nothing in the range are omitted.
+    ///
+    /// Fails with [`SessionError::Rebase`] when `since` is below the retained history.
+    pub fn changelog(
+        &self,
+        since: &StateVector,
+        until: &StateVector,
+    ) -> Result<Vec<ChangeEntry>, SessionError> {
+        let message = self.sync.encode_changes_since(since)?;
+        let mut peers: BTreeMap<PeerId, Tally> = BTreeMap::new();
+        for op in message.ops {
+            if op.id.counter > until.get(op.id.peer).unwrap_or(0) {
+                continue;
+            }
+            let envelope = self.codec.decode(&op.payload).map_err(codec_err)?;
+            let tally = peers.entry(op.id.peer).or_default();
+            if let Some(meta) = &envelope.meta {
+                tally.stamp(meta);
+            }
+            let OpBody::Doc(doc_op) = envelope.body;
+            tally.record(&doc_op, &self.document);
+        }
+        Ok(peers
+            .into_iter()
+            .map(|(peer, tally)| tally.finish(peer, &self.document))
+            .collect())
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    entry: ChangeEntry,
+    added: HashSet<BlockId>,
+    edited: Vec<BlockId>,
+}
+
+impl Tally {
+    fn record(&mut self, op: &DocOp, document: &Document) {
+        self.entry.operations += 1;
+        match op {
+            DocOp::InsertBlock { block, .. } => {
+                self.entry.blocks_added += 1;
+                self.added.insert(block.block_id);
+            }
+            DocOp::DeleteBlock { .. } | DocOp::DeleteBlockById { .. } => {
+                self.entry.blocks_deleted += 1;
+            }
+            DocOp::DeleteBlockRange {
+                parent, observed, ..
+            } => {
+                self.entry.blocks_deleted +=
+                    document.container_children(*parent).map_or(0, |children| {
+                        children
+                            .iter_all()
+                            .filter(|element| covers(observed, element.id))
+                            .count()
+                    });
+            }
+            DocOp::MoveBlocks { blocks, .. } => self.entry.blocks_moved += blocks.len(),
+            DocOp::SplitBlock { new_block_id, .. } => {
+                self.entry.blocks_added += 1;
+                self.added.insert(*new_block_id);
+            }
+            DocOp::MergeBlocks { .. } => self.entry.blocks_deleted += 1,
+            DocOp::InsertText { block_id, .. }
+            | DocOp::DeleteText { block_id, .. }
+            | DocOp::SetMark { block_id, .. }
+            | DocOp::RemoveMark { block_id, .. }
+            | DocOp::SetListStyle { block_id, .. }
+            | DocOp::SetCodeFence { block_id, .. }
+            | DocOp::ConvertTextBlock { block_id, .. }
+            | DocOp::ReplaceRawBlock { block_id, .. }
+            | DocOp::SetBlockAcl { block_id, .. }
+            | DocOp::InsertCodeLine { block_id, .. }
+            | DocOp::DeleteCodeLine { block_id, .. } => self.edit(*block_id),
+            DocOp::SetTableCell { table_id, .. }
+            | DocOp::SetTableColumnAlignment { table_id, .. }
+            | DocOp::MoveTableRow { table_id, .. }
+            | DocOp::MoveTableColumn { table_id, .. } => self.edit(*table_id),
+            DocOp::InsertTableRow { table_id, .. } => {
+                if !self.added.contains(table_id) {
+                    self.entry.table_rows_added += 1;
+                }
+            }
+            DocOp::DeleteTableRow { .. } | DocOp::DeleteTableRowById { .. } => {
+                self.entry.table_rows_deleted += 1;
+            }
+            DocOp::InsertTableColumn { table_id, .. } => {
+                if !self.added.contains(table_id) {
+                    self.entry.table_columns_added += 1;
+                }
+            }
+            DocOp::DeleteTableColumnById { .. } => self.entry.table_columns_deleted += 1,
+            DocOp::InsertListItem { list_id, .. } => {
+                if !self.added.contains(list_id) {
+                    self.entry.list_items_added += 1;
+                }
+            }
+            DocOp::DeleteListItemById { .. } => self.entry.list_items_deleted += 1,
+            DocOp::MoveListItem { list_id, .. } => self.edit(*list_id),
+            DocOp::SetListItemTask { .. } => self.entry.tasks_changed += 1,
+            DocOp::SetFrontmatterField { .. } | DocOp::InitializeFrontmatter { .. } => {
+                self.entry.frontmatter_changes += 1;
+            }
+        }
+    }
+
+    fn stamp(&mut self, meta: &OpMetadata) {
+        if let Some(author) = &meta.author
+            && !self.entry.authors.contains(author)
+        {
+            self.entry.authors.push(author.clone());
+        }
+        let time = meta.timestamp_ms;
+        self.entry.first_timestamp_ms =
+            Some(self.entry.first_timestamp_ms.map_or(time, |t| t.min(time)));
+        self.entry.last_timestamp_ms =
+            Some(self.entry.last_timestamp_ms.map_or(time, |t| t.max(time)));
+    }
+
+    fn edit(&mut self, block_id: BlockId) {
+        if !self.added.contains(&block_id) && !self.edited.contains(&block_id) {
+            self.edited.push(block_id);
+        }
+    }
+
+    fn finish(mut self, peer: PeerId, document: &Document) -> ChangeEntry {
+        self.entry.peer = peer;
+        // A block inserted after an earlier edit in the range still counts as added only.
+        self.edited
+            .retain(|block_id| !self.added.contains(block_id));
+        self.entry.blocks_edited = self.edited.len();
+        self.entry.edited = self
+            .edited
+            .iter()
+            .filter_map(|block_id| document.find_block_by_id(*block_id))
+            .map(block_label)
+            .collect();
+        self.entry.summary = summarize(&self.entry);
+        self.entry
+    }
+}
+
+fn block_label(block: &Block) -> String {
+    let text = match &block.kind {
+        BlockKind::Paragraph { text } | BlockKind::Heading { text, .. } => {
+            paragraph_visible_string(text)
+        }
+        BlockKind::CodeFence { .. } => return "code block".to_string(),
+        BlockKind::List { .. } => return "list".to_string(),
+        BlockKind::BlockQuote { .. } => return "blockquote".to_string(),
+        BlockKind::RawBlock { .. } => return "raw block".to_string(),
+        BlockKind::Table { .. } => return "table".to_string(),
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > LABEL_CHARS {
+        let head: String = text.chars().take(LABEL_CHARS).collect();
+        format!("{}…", head.trim_end())
+    } else {
+        text
+    }
+}
+
+fn summarize(entry: &ChangeEntry) -> String {
+    let mut parts = Vec::new();
+    push_count(&mut parts, "added", entry.blocks_added, "block");
+    parts.extend(edit_phrase(entry));
+    push_count(&mut parts, "deleted", entry.blocks_deleted, "block");
+    push_count(&mut parts, "moved", entry.blocks_moved, "block");
+    push_count(&mut parts, "added", entry.table_rows_added, "table row");
+    push_count(&mut parts, "deleted", entry.table_rows_deleted, "table row");
+    push_count(
+        &mut parts,
+        "added",
+        entry.table_columns_added,
+        "table column",
+    );
+    push_count(
+        &mut parts,
+        "deleted",
+        entry.table_columns_deleted,
+        "table column",
+    );
+    push_count(&mut parts, "added", entry.list_items_added, "list item");
+    push_count(&mut parts, "deleted", entry.list_items_deleted, "list item");
+    push_count(&mut parts, "toggled", entry.tasks_changed, "task");
+    push_count(
+        &mut parts,
+        "changed",
+        entry.frontmatter_changes,
+        "frontmatter field",
+    );
+    if parts.is_empty() {
+        return format!("peer {} made {} changes", entry.peer, entry.operations);
+    }
+    format!("peer {} {}", entry.peer, parts.join(", "))
+}
+
+pub(super) fn push_count(parts: &mut Vec<String>, verb: &str, n: usize, noun: &str) {
+    match n {
+        0 => {}
+        1 => parts.push(format!("{verb} a {noun}")),
+        _ => parts.push(format!("{verb} {n} {noun}s")),
+    }
+}
+
+fn edit_phrase(entry: &ChangeEntry) -> Option<String> {
+    let quoted: Vec<String> = entry
+        .edited
+        .iter()
+        .take(QUOTED_EDITS)
+        .map(|label| format!("'{label}'"))
+        .collect();
+    let rest = entry.blocks_edited.checked_sub(quoted.len())?;
+    Some(match (quoted.is_empty(), rest) {
+        (true, 0) => return None,
+        (true, 1) => "edited a block".to_string(),
+        (true, n) => format!("edited {n} blocks"),
+        (false, 0) => format!("edited {}", quoted.join(", ")),
+        (false, 1) => format!("edited {} and 1 more block", quoted.join(", ")),
+        (false, n) => format!("edited {} and {n} more blocks", quoted.join(", ")),
+    })
+}
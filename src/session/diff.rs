@@ -0,0 +1,61 @@
+//! Operations between two encoded session snapshots.
+//!
+//! A snapshot carries its applied op log, so two snapshots of one document,
+//! such as a backup and the live copy, differ by the logged operations one
+//! holds and the other lacks. Ids are what compare: the same operation has the
+//! same id in every replica that applied it.
+
+use super::{SessionSnapshot, SnapshotError};
+use crate::core::OpId;
+use crate::sync::{Operation, RebaseRequired};
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SnapshotDiffError {
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+    /// `new` checkpointed away operations `old` never applied; load `new`
+    /// with `CollaborativeDocument::rebase_from_snapshot` instead.
+    #[error(transparent)]
+    Rebase(#[from] RebaseRequired),
+}
+
+/// Operations in `new_bytes`' applied log that `old_bytes` has not applied, in
+/// id order, both being [`SessionSnapshot::to_bytes`] encodings.
+///
+/// Applying them to a replica restored from `old_bytes`, as a
+/// [`ChangeMessage`](crate::sync::ChangeMessage) whose `since` is the old
+/// snapshot's `state_vector`, brings it up to `new_bytes`. Operations only
+/// `old_bytes` holds are left alone, as any merge would; the diff the other way
+/// round lists them.
+pub fn diff_snapshots(
+    old_bytes: &[u8],
+    new_bytes: &[u8],
+) -> Result<Vec<Operation>, SnapshotDiffError> {
+    let old = SessionSnapshot::from_bytes(old_bytes)?;
+    let new = SessionSnapshot::from_bytes(new_bytes)?;
+    let lacking = new
+        .delta_floor
+        .iter()
+        .any(|(peer, floor)| old.state_vector.get(peer).unwrap_or(0) < floor);
+    if lacking {
+        return Err(RebaseRequired {
+            checkpoint_epoch: new.checkpoint_epoch,
+            delta_floor: new.delta_floor,
+        }
+        .into());
+    }
+    // Operations `old` checkpointed away lie at or below its floor.
+    let held: BTreeSet<OpId> = old.ops.iter().map(|(id, _)| *id).collect();
+    let mut ops: Vec<Operation> = new
+        .ops
+        .into_iter()
+        .filter(|(id, _)| {
+            !held.contains(id) && old.delta_floor.get(id.peer).unwrap_or(0) < id.counter
+        })
+        .map(|(id, payload)| Operation { id, payload })
+        .collect();
+    ops.sort_by_key(|op| op.id);
+    Ok(ops)
+}
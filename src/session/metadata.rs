@@ -0,0 +1,154 @@
+//! Timestamps and authorship stamped on local operations.
+//!
+//! A replica with a [`MetadataStamp`] writes an [`OpMetadata`] into the envelope
+//! of every operation it issues: the wall-clock time, an author label, a client
+//! version, and optionally a signature from an application-supplied signer over
+//! [`Envelope::signing_bytes`]. The metadata travels with the operation through
+//! sync and storage and is read back by [`CollaborativeDocument::op_metadata`]
+//! and [`CollaborativeDocument::changelog`]. Convergence never depends on it.
+
+use super::{CollaborativeDocument, SessionError, codec_err};
+use crate::codec::{Envelope, OpCodec, OpMetadata};
+use crate::core::OpId;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type Signer = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+type Verifier = Arc<dyn Fn(&[u8], &[u8]) -> bool + Send + Sync>;
+
+/// What a replica stamps on the operations it issues.
+#[derive(Clone, Default)]
+pub struct MetadataStamp {
+    author: Option<String>,
+    client_version: Option<String>,
+    signer: Option<Signer>,
+}
+
+impl MetadataStamp {
+    /// A stamp with the timestamp only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn client_version(mut self, client_version: impl Into<String>) -> Self {
+        self.client_version = Some(client_version.into());
+        self
+    }
+
+    /// Sign the metadata: `sign` gets [`Envelope::signing_bytes`] and returns
+    /// the signature to store in [`OpMetadata::signature`].
+    pub fn signer(mut self, sign: impl Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        self.signer = Some(Arc::new(sign));
+        self
+    }
+
+    fn stamp(&self, envelope: &mut Envelope) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        envelope.meta = Some(OpMetadata {
+            timestamp_ms,
+            author: self.author.clone(),
+            client_version: self.client_version.clone(),
+            signature: None,
+        });
+        if let Some(sign) = &self.signer {
+            let signature = sign(&envelope.signing_bytes());
+            if let Some(meta) = envelope.meta.as_mut() {
+                meta.signature = Some(signature);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MetadataStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataStamp")
+            .field("author", &self.author)
+            .field("client_version", &self.client_version)
+            .field("signed", &self.signer.is_some())
+            .finish()
+    }
+}
+
+/// Check on the signatures of remote operation metadata.
+#[derive(Clone)]
+pub struct MetadataVerifier(Verifier);
+
+impl MetadataVerifier {
+    /// `verify` gets [`Envelope::signing_bytes`] and the signature, and returns
+    /// whether the signature is valid.
+    pub fn new(verify: impl Fn(&[u8], &[u8]) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(verify))
+    }
+
+    fn accepts(&self, envelope: &Envelope) -> bool {
+        envelope
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.signature.as_deref())
+            .is_some_and(|signature| (self.0)(&envelope.signing_bytes(), signature))
+    }
+}
+
+impl fmt::Debug for MetadataVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetadataVerifier")
+    }
+}
+
+impl<C: OpCodec> CollaborativeDocument<C> {
+    /// Stamp every operation issued from now on; `None` stops stamping. Like
+    /// the history budget, this is a replica setting that snapshots do not carry.
+    pub fn set_metadata_stamp(&mut self, stamp: Option<MetadataStamp>) {
+        self.metadata_stamp = stamp;
+    }
+
+    pub fn metadata_stamp(&self) -> Option<&MetadataStamp> {
+        self.metadata_stamp.as_ref()
+    }
+
+    /// Reject remote operations whose metadata is unsigned or fails `verifier`
+    /// with [`SessionError::MetadataSignature`]. Operations without metadata
+    /// are not checked.
+    pub fn set_metadata_verifier(&mut self, verifier: Option<MetadataVerifier>) {
+        self.metadata_verifier = verifier;
+    }
+
+    /// Metadata of an operation in the applied log; `None` when the operation
+    /// carries none or was folded away by a checkpoint.
+    pub fn op_metadata(&self, id: OpId) -> Result<Option<OpMetadata>, SessionError> {
+        let Some(payload) = self.sync.get(id) else {
+            return Ok(None);
+        };
+        Ok(self.codec.decode(payload).map_err(codec_err)?.meta)
+    }
+
+    /// Encode a local envelope, stamped when a [`MetadataStamp`] is set.
+    pub(super) fn encode_local(&self, envelope: &Envelope) -> Result<Vec<u8>, SessionError> {
+        match &self.metadata_stamp {
+            Some(stamp) => {
+                let mut stamped = envelope.clone();
+                stamp.stamp(&mut stamped);
+                self.codec.encode(&stamped)
+            }
+            None => self.codec.encode(envelope),
+        }
+        .map_err(codec_err)
+    }
+
+    pub(super) fn check_metadata(&self, id: OpId, envelope: &Envelope) -> Result<(), SessionError> {
+        match &self.metadata_verifier {
+            Some(verifier) if envelope.meta.is_some() && !verifier.accepts(envelope) => {
+                Err(SessionError::MetadataSignature(id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
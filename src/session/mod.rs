@@ -3,11 +3,20 @@
 //! Owns encode-before-apply local commits and pre-decode remote apply.
 //! Payload-opaque [`crate::sync::SyncState`] never sees codec types.
 
+mod changelog;
+mod diff;
+mod metadata;
+mod preview;
 pub mod snapshot;
 mod wire;
 
+pub use changelog::ChangeEntry;
+pub use diff::{SnapshotDiffError, diff_snapshots};
+pub use metadata::{MetadataStamp, MetadataVerifier};
+pub use preview::{MarkChange, MergePreview, TextChange};
 pub use snapshot::{
-    DocumentDto, SNAPSHOT_FORMAT_VERSION, SessionSnapshot, SnapshotError, max_counter_for_peer,
+    DocumentDto, SNAPSHOT_FORMAT_VERSION, SessionSnapshot, SnapshotError, StorageStats,
+    max_counter_for_peer,
 };
 
 use crate::codec::{
@@ -15,22 +24,32 @@ use crate::codec::{
     JsonOpCodec, ListItemSkeleton, MovedBlockWire, MovedTextUnitWire, OpBody, OpCodec,
     TableCellWire, TextBlockKindWire, TextUnitWire, WIRE_VERSION, insert_block_paragraph_is_empty,
 };
-use crate::core::mark::{MarkKind, MarkSet, MarkValue};
-use crate::core::{OpId, PeerId, Sequence, SequenceOp, StateVector};
+use crate::core::mark::{MarkKind, MarkSchema, MarkSchemaError, MarkSet, MarkValue};
+use crate::core::merge::covers;
+use crate::core::{
+    CausalOrd, ClockError, ExpiredOp, OpId, OpIdRange, PeerClock, PeerId, PendingSummary,
+    RegisterWrite, SegmentationRules, Sequence, SequenceOp, StateVector,
+};
 use crate::doc::{
-    Block, BlockId, BlockKind, ColumnAlignment, ColumnDef, ColumnId, Document, ListItem, RowId,
-    Table, TextUnit, after_for_grapheme_offset, block_id_from_op, grapheme_count,
-    paragraph_visible_ids, paragraph_visible_string, units_from_str,
+    Block, BlockAcl, BlockId, BlockKind, BlockRangeDelete, CellAddress, ColumnAlignment, ColumnDef,
+    ColumnId, Document, EditOp, LinkTarget, ListItem, Parser, RegisterConflict, RegisterHeads,
+    RegisterKey, ReplaceAll, ResolutionPolicy, ResolutionTarget, RowId, Table, TextUnit,
+    after_for_grapheme_offset, block_id_from_op, paragraph_visible_ids, paragraph_visible_string,
+    units_from_str_with,
 };
 use crate::sync::{
-    ChangeMessage, CheckpointError, CheckpointReport, CheckpointRequest, IntegrateResult,
-    Operation, RebaseRequired, SyncState, ValidationError, ValidationLimits, validate_changes,
+    ChangeMessage, CheckpointError, CheckpointReport, CheckpointRequest, Decision,
+    DocumentTombstonePolicy, Hello, HistoryBudget, IntegrateResult, OpHookId, Operation, Origin,
+    ReadOnlyReplica, RebaseRequired, ReplicaMode, SemanticConflict, SyncHandshake, SyncState,
+    SyncUpdate, UpdateListenerId, ValidationError, ValidationLimits, validate_changes,
 };
 use crate::workspace::{
     BlockDraft, ListItemDraft, StructuredEditError, StructuredEditLimits, TextBlockKind,
     validate_code_fence, validate_list_style,
 };
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use wire::*;
 
@@ -40,6 +59,8 @@ pub enum SessionError {
     #[error("codec: {0}")]
     Codec(String),
     #[error(transparent)]
+    Clock(#[from] ClockError),
+    #[error(transparent)]
     Validation(#[from] ValidationError),
     #[error("unknown wire version {0}")]
     UnknownWireVersion(u16),
@@ -47,6 +68,8 @@ pub enum SessionError {
     OperationIdMismatch,
     #[error("peer id mismatch in payload")]
     PeerMismatch,
+    #[error("operation {0:?} carries metadata without a valid signature")]
+    MetadataSignature(OpId),
     #[error("paragraph InsertBlock must have empty text; use InsertText for body")]
     NonEmptyParagraphOnInsertBlock,
     #[error("after anchor not found for local insert")]
@@ -83,16 +106,40 @@ pub enum SessionError {
     StructuredEdit(#[from] StructuredEditError),
     #[error("target is not a list")]
     NotList,
+    #[error("target is not an active link mark")]
+    NotLink,
     #[error("list item not found")]
     ListItemNotFound,
     #[error("target is not a code fence")]
     NotCodeFence,
+    #[error("code fence lines cannot contain line breaks")]
+    InvalidCodeLine,
     #[error("target is not an opaque raw block")]
     NotRawBlock,
     #[error("raw block digest precondition does not match")]
     RawDigestMismatch,
     #[error(transparent)]
     Frontmatter(#[from] crate::doc::FrontmatterError),
+    #[error("peer {0} already issued operations in this history")]
+    PeerInUse(PeerId),
+    #[error(transparent)]
+    Rebase(#[from] RebaseRequired),
+    #[error(transparent)]
+    InvalidMark(#[from] MarkSchemaError),
+    #[error("block {block_id} is locked against edits by peer {peer}")]
+    BlockLocked { block_id: BlockId, peer: PeerId },
+    #[error(transparent)]
+    ReadOnly(#[from] ReadOnlyReplica),
+}
+
+fn code_line_err(error: crate::doc::EditError) -> SessionError {
+    use crate::doc::EditError;
+    match error {
+        EditError::BlockNotFound { .. } => SessionError::BlockNotFound,
+        EditError::NotCodeFence { .. } => SessionError::NotCodeFence,
+        EditError::InvalidCodeLine { .. } => SessionError::InvalidCodeLine,
+        _ => SessionError::InvalidOffset,
+    }
 }
 
 fn codec_err<E: std::fmt::Display>(e: E) -> SessionError {
@@ -156,6 +203,7 @@ fn envelope_observed_frontier(envelope: &Envelope) -> Option<&StateVector> {
     match &envelope.body {
         OpBody::Doc(
             DocOp::RemoveMark { observed, .. }
+            | DocOp::SetFrontmatterField { observed, .. }
             | DocOp::SetTableCell { observed, .. }
             | DocOp::SetTableColumnAlignment { observed, .. }
             | DocOp::MoveTableRow { observed, .. }
@@ -165,7 +213,11 @@ fn envelope_observed_frontier(envelope: &Envelope) -> Option<&StateVector> {
             | DocOp::SetListItemTask { observed, .. }
             | DocOp::SetCodeFence { observed, .. }
             | DocOp::ConvertTextBlock { observed, .. }
-            | DocOp::ReplaceRawBlock { observed, .. },
+            | DocOp::ReplaceRawBlock { observed, .. }
+            | DocOp::SetBlockAcl { observed, .. }
+            | DocOp::InsertCodeLine { observed, .. }
+            | DocOp::DeleteCodeLine { observed, .. }
+            | DocOp::DeleteBlockRange { observed, .. },
         ) => Some(observed),
         _ => None,
     }
@@ -176,6 +228,10 @@ fn envelope_observed_frontier(envelope: &Envelope) -> Option<&StateVector> {
 pub struct SessionApplyResult {
     pub applied: Vec<OpId>,
     pub buffered: Vec<OpId>,
+    /// Concurrent writes resolved while applying, such as competing block ACLs.
+    pub conflicts: Vec<SemanticConflict>,
+    /// Operations an incoming hook rejected; see [`CollaborativeDocument::on_incoming`].
+    pub rejected: Vec<OpId>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -196,6 +252,19 @@ pub struct CollaborativeDocument<C: OpCodec = JsonOpCodec> {
     unit_mode: bool,
     /// Decoded envelopes for causally buffered ops (avoid re-decode).
     pending_envelopes: BTreeMap<OpId, Envelope>,
+    /// Checked against local [`Self::set_mark`] calls only.
+    mark_schema: Option<MarkSchema>,
+    /// Whether local and remote operations must satisfy block ACLs.
+    enforce_acls: bool,
+    /// Replica settings; targets absent here resolve by LWW.
+    resolution_policies: BTreeMap<ResolutionTarget, ResolutionPolicy>,
+    /// Concurrent writes of the registers under `resolution_policies`.
+    registers: RegisterHeads,
+    /// Replica setting enforced after every logged operation.
+    history_budget: Option<HistoryBudget>,
+    /// Replica settings for operation metadata.
+    metadata_stamp: Option<MetadataStamp>,
+    metadata_verifier: Option<MetadataVerifier>,
 }
 
 impl CollaborativeDocument<JsonOpCodec> {
@@ -215,6 +284,13 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             codec,
             unit_mode,
             pending_envelopes: BTreeMap::new(),
+            mark_schema: None,
+            enforce_acls: false,
+            resolution_policies: BTreeMap::new(),
+            registers: RegisterHeads::default(),
+            history_budget: None,
+            metadata_stamp: None,
+            metadata_verifier: None,
         }
     }
 
@@ -242,6 +318,17 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.unit_mode = unit_mode;
     }
 
+    /// Grapheme cluster rules local and remote text edits are split with.
+    pub fn segmentation(&self) -> SegmentationRules {
+        self.document.segmentation()
+    }
+
+    /// Pin the cluster rules, e.g. to an older Unicode version peers still run.
+    /// Snapshots carry them; every replica must agree on them before editing.
+    pub fn set_segmentation(&mut self, rules: SegmentationRules) {
+        self.document.set_segmentation(rules);
+    }
+
     /// Peek next OpId without advancing the clock.
     pub fn peek_next_id(&self) -> OpId {
         OpId {
@@ -250,7 +337,31 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         }
     }
 
-    /// Encode ops not yet seen by `since` (for exchange with peers).
+    /// Reserve `n` counters for bulk work (e.g. [`Parser::parse_in_range`]); later
+    /// local ops allocate after the range.
+    ///
+    /// [`Parser::parse_in_range`]: crate::doc::Parser::parse_in_range
+    pub fn reserve_ids(&mut self, n: u64) -> Result<OpIdRange, SessionError> {
+        let clock = PeerClock::starting_at(self.peer, self.next_counter);
+        let range = clock.reserve(n)?;
+        self.next_counter = clock.peek().counter;
+        Ok(range)
+    }
+
+    /// Opening message of a sync connection, naming this session's peer. Adjust
+    /// its capabilities and keep-alive before sending it.
+    pub fn hello(&self) -> Hello {
+        Hello::new(self.peer)
+    }
+
+    /// State vector and frontier floor to open a sync exchange with. A peer below
+    /// the floor gets [`SyncResponse::Rebase`] from [`Self::sync_since`].
+    pub fn handshake(&self) -> SyncHandshake {
+        self.sync.handshake()
+    }
+
+    /// Encode ops not yet seen by `since` (for exchange with peers). Fails with
+    /// [`RebaseRequired`] when `since` is below the frontier floor.
     pub fn encode_changes_since(
         &self,
         since: &StateVector,
@@ -258,6 +369,16 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.sync.encode_changes_since(since)
     }
 
+    /// [`Self::encode_changes_since`] as sent to `peer`, through the
+    /// [`Self::on_outgoing`] hooks.
+    pub fn encode_changes_for(
+        &self,
+        peer: PeerId,
+        since: &StateVector,
+    ) -> Result<ChangeMessage, RebaseRequired> {
+        self.sync.encode_changes_for(peer, since)
+    }
+
     pub fn sync_since(&self, since: &StateVector) -> Result<SyncResponse, SnapshotError> {
         match self.encode_changes_since(since) {
             Ok(message) => Ok(SyncResponse::Delta(message)),
@@ -267,11 +388,76 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         }
     }
 
+    /// Compact the op log, and under [`DocumentTombstonePolicy::CollectRemovedMarks`]
+    /// drop removed mark intervals that this replica and every leased peer have seen.
     pub fn checkpoint_history(
         &mut self,
         request: &CheckpointRequest,
     ) -> Result<CheckpointReport, CheckpointError> {
-        self.sync.checkpoint(request)
+        let mut report = self.sync.checkpoint(request)?;
+        if request.tombstones == DocumentTombstonePolicy::CollectRemovedMarks {
+            let mut observed = StateVector::new();
+            for (peer, counter) in self.sync.state_vector().iter() {
+                let seen = request
+                    .active_peer_leases
+                    .iter()
+                    .map(|lease| lease.acknowledged.get(peer).unwrap_or(0))
+                    .fold(counter, u64::min);
+                observed.set(peer, seen);
+            }
+            report.collected_marks = self.document.gc_marks(&observed);
+        }
+        Ok(report)
+    }
+
+    /// Keep at most `budget` of history from now on, folding older operations
+    /// into the document state (see [`HistoryBudget`]); `None` retains everything.
+    /// Like resolution policies, the budget is a replica setting that snapshots
+    /// do not carry.
+    pub fn set_history_budget(&mut self, budget: Option<HistoryBudget>) {
+        self.history_budget = budget;
+        self.enforce_history_budget();
+    }
+
+    pub fn history_budget(&self) -> Option<HistoryBudget> {
+        self.history_budget
+    }
+
+    /// Fold the operations beyond the history budget: checkpoint them away with no
+    /// peer leases, collect removed marks and unanchored text tombstones, and raise
+    /// the frontier floor. Returns `None` when the log already fits, no budget is
+    /// set, or logged operations are still waiting to reach the document.
+    pub fn enforce_history_budget(&mut self) -> Option<CheckpointReport> {
+        let budget = self.history_budget?;
+        let excess = self.sync.excess_ops(&budget);
+        if excess == 0
+            || self
+                .pending_envelopes
+                .keys()
+                .any(|id| self.sync.contains(*id))
+        {
+            return None;
+        }
+        let request = CheckpointRequest {
+            max_retained_ops: self.sync.applied_count() - excess,
+            active_peer_leases: Vec::new(),
+            tombstones: DocumentTombstonePolicy::CollectRemovedMarks,
+        };
+        // Without leases a checkpoint can only fail on epoch overflow.
+        let mut report = self.checkpoint_history(&request).ok()?;
+        report.dropped_tombstones = self.document.drop_text_tombstones(&report.delta_floor);
+        tracing::debug!(
+            pruned = report.pruned_ops,
+            tombstones = report.dropped_tombstones,
+            "folded history beyond the budget"
+        );
+        Some(report)
+    }
+
+    fn log_local_op(&mut self, op: Operation) -> Result<(), ReadOnlyReplica> {
+        self.sync.add_local_op(op)?;
+        self.enforce_history_budget();
+        Ok(())
     }
 
     /// Insert a top-level block after `after` (None = start). Returns the block `elem_id`.
@@ -310,6 +496,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         check_kind_peers(self.peer, &skeleton)?;
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertBlock {
                 parent,
                 after,
@@ -328,14 +515,16 @@ impl<C: OpCodec> CollaborativeDocument<C> {
 
         // Operation.id is the max embedded id (N1); a paragraph body expands into text
         // units at b+1..b+G, so the op covers a counter range and its id is b+G.
-        let (op_id, _span) = operation_extent(&envelope);
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        let (op_id, _span) = operation_extent(&envelope, self.document.segmentation());
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         // Apply to document before advancing clock / logging (N3).
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        self.log_local_op(Operation {
             id: op_id,
             payload: payload.into(),
-        });
+        })?;
         // Advance past the whole reserved range so later ids never collide with the units.
         self.next_counter = op_id.counter + 1;
         Ok(block_elem)
@@ -367,6 +556,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         };
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::DeleteBlockById {
                 parent,
                 target,
@@ -374,16 +564,154 @@ impl<C: OpCodec> CollaborativeDocument<C> {
                 id: delete_id,
             }),
         };
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        self.log_local_op(Operation {
             id: delete_id,
             payload: payload.into(),
-        });
+        })?;
         self.next_counter = b + 1;
         Ok(delete_id)
     }
 
+    /// Delete every block among `parent`'s children (top-level when `parent` is
+    /// `None`) as one [`DocOp::DeleteBlockRange`]. Returns `None`, without using
+    /// an id, when there is nothing to delete.
+    pub fn delete_all_blocks_in(
+        &mut self,
+        parent: Option<OpId>,
+    ) -> Result<Option<BlockRangeDelete>, SessionError> {
+        if self.document.container_children(parent).is_none() {
+            return Err(SessionError::BlockNotFound);
+        }
+        let id = self.peek_next_id();
+        let Some(delete) = self.document.block_range_delete(parent, id) else {
+            return Ok(None);
+        };
+        let envelope = Envelope {
+            version: WIRE_VERSION,
+            meta: None,
+            body: OpBody::Doc(DocOp::DeleteBlockRange {
+                parent,
+                from: delete.from,
+                to: delete.to,
+                id,
+                observed: delete.observed.clone(),
+            }),
+        };
+        self.commit_single_id(envelope, id)?;
+        Ok(Some(delete))
+    }
+
+    /// Replace the document body with `new_markdown`, as a paste over everything
+    /// does: one [`Self::delete_all_blocks_in`] for the old blocks, then inserts
+    /// for the parsed ones after them. The frontmatter stays as it is.
+    pub fn replace_all(&mut self, new_markdown: &str) -> Result<ReplaceAll, SessionError> {
+        let parsed = Parser::parse(new_markdown);
+        let cleared = self.delete_all_blocks_in(None)?;
+        let mut after = cleared.as_ref().map(|delete| delete.to);
+        let mut inserted = Vec::new();
+        for block in parsed.blocks_in_order() {
+            let elem = self.insert_parsed_block(None, after, block)?;
+            after = Some(elem);
+            inserted.push(elem);
+        }
+        Ok(ReplaceAll { cleared, inserted })
+    }
+
+    /// Re-create a parsed block under this session's ids.
+    fn insert_parsed_block(
+        &mut self,
+        parent: Option<OpId>,
+        after: Option<OpId>,
+        block: &Block,
+    ) -> Result<OpId, SessionError> {
+        match &block.kind {
+            BlockKind::Paragraph { text } => {
+                self.insert_paragraph_in(parent, after, &paragraph_visible_string(text))
+            }
+            BlockKind::Heading { level, text } => self.insert_validated_draft(
+                parent,
+                after,
+                &BlockDraft::Heading {
+                    level: *level,
+                    text: paragraph_visible_string(text),
+                },
+            ),
+            BlockKind::List { style, items, .. } => {
+                let list_elem = self.insert_block_in(
+                    parent,
+                    after,
+                    BlockKind::List {
+                        style: *style,
+                        items: Sequence::new(),
+                        pending_moves: Vec::new(),
+                    },
+                )?;
+                let list_id = block_id_from_op(list_elem);
+                let mut after_item = None;
+                for item in items.iter() {
+                    let item_elem = self.insert_list_item(list_id, after_item, item.task)?;
+                    let mut after_child = None;
+                    for child in item.children.iter() {
+                        after_child =
+                            Some(self.insert_parsed_block(Some(item_elem), after_child, child)?);
+                    }
+                    after_item = Some(item_elem);
+                }
+                Ok(list_elem)
+            }
+            BlockKind::BlockQuote { children } => {
+                let quote_elem = self.insert_block_in(
+                    parent,
+                    after,
+                    BlockKind::BlockQuote {
+                        children: Sequence::new(),
+                    },
+                )?;
+                let mut after_child = None;
+                for child in children.iter() {
+                    after_child =
+                        Some(self.insert_parsed_block(Some(quote_elem), after_child, child)?);
+                }
+                Ok(quote_elem)
+            }
+            BlockKind::Table { table } => {
+                let columns = table.columns_in_order();
+                let header = table.row_cells(table.header_row_id());
+                let table_elem = self.insert_table_in(
+                    parent,
+                    after,
+                    columns
+                        .iter()
+                        .map(|column| ColumnDef {
+                            alignment: column.alignment.get().clone(),
+                        })
+                        .collect(),
+                    header,
+                )?;
+                let table_id = block_id_from_op(table_elem);
+                let mut after_row = None;
+                for row in table.rows_in_order() {
+                    if row.id == table.header_row_id() {
+                        continue;
+                    }
+                    after_row = Some(self.insert_table_row(
+                        table_id,
+                        after_row,
+                        table.row_cells(row.id),
+                    )?);
+                }
+                Ok(table_elem)
+            }
+            BlockKind::CodeFence { .. } | BlockKind::RawBlock { .. } => {
+                self.insert_block_in(parent, after, block.kind.clone())
+            }
+        }
+    }
+
     /// Insert an empty paragraph skeleton, then `InsertText` for `text` when non-empty.
     ///
     /// Two N3 commits (N6-d). Returns the block `elem_id`. Empty `text` is block-only.
@@ -530,6 +858,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let right_origin = items.compute_right_origin(after);
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertListItem {
                 list_elem,
                 list_id,
@@ -581,6 +910,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::DeleteListItemById {
                     list_elem,
                     list_id,
@@ -626,6 +956,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::MoveListItem {
                     from_list_elem,
                     to_list_elem,
@@ -661,6 +992,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::SetListStyle {
                     block_elem,
                     block_id: list_id,
@@ -686,6 +1018,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::SetListItemTask {
                     item_id,
                     id,
@@ -718,6 +1051,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::SetCodeFence {
                     block_elem,
                     block_id,
@@ -732,6 +1066,85 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         )
     }
 
+    /// Insert `line` as line `index` of code fence `block_id`. Unlike
+    /// [`Self::set_code_fence`], concurrent edits to different lines all survive.
+    pub fn insert_code_line(
+        &mut self,
+        block_id: BlockId,
+        index: usize,
+        line: &str,
+    ) -> Result<OpId, SessionError> {
+        let block = self
+            .document
+            .find_block_by_id(block_id)
+            .ok_or(SessionError::BlockNotFound)?;
+        let BlockKind::CodeFence { style, info, .. } = &block.kind else {
+            return Err(SessionError::NotCodeFence);
+        };
+        validate_code_fence(*style, info.as_deref(), line)?;
+        let id = self.peek_next_id();
+        let EditOp::InsertCodeLine {
+            base,
+            after,
+            right_origin,
+            ..
+        } = self
+            .document
+            .plan_code_line_insert(block_id, index, line, id)
+            .map_err(code_line_err)?
+        else {
+            unreachable!("plan_code_line_insert plans an insert");
+        };
+        let observed = self.state_vector();
+        self.commit_single_id(
+            Envelope {
+                version: WIRE_VERSION,
+                meta: None,
+                body: OpBody::Doc(DocOp::InsertCodeLine {
+                    block_id,
+                    base,
+                    after,
+                    right_origin,
+                    id,
+                    line: line.to_string(),
+                    observed,
+                }),
+            },
+            id,
+        )
+    }
+
+    /// Delete line `index` of code fence `block_id`.
+    pub fn delete_code_line(
+        &mut self,
+        block_id: BlockId,
+        index: usize,
+    ) -> Result<OpId, SessionError> {
+        let id = self.peek_next_id();
+        let EditOp::DeleteCodeLine { base, target, .. } = self
+            .document
+            .plan_code_line_delete(block_id, index, id)
+            .map_err(code_line_err)?
+        else {
+            unreachable!("plan_code_line_delete plans a delete");
+        };
+        let observed = self.state_vector();
+        self.commit_single_id(
+            Envelope {
+                version: WIRE_VERSION,
+                meta: None,
+                body: OpBody::Doc(DocOp::DeleteCodeLine {
+                    block_id,
+                    base,
+                    target,
+                    id,
+                    observed,
+                }),
+            },
+            id,
+        )
+    }
+
     pub fn convert_text_block(
         &mut self,
         block_id: BlockId,
@@ -760,6 +1173,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::ConvertTextBlock {
                     block_elem,
                     block_id,
@@ -790,6 +1204,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(
             Envelope {
                 version: WIRE_VERSION,
+                meta: None,
                 body: OpBody::Doc(DocOp::ReplaceRawBlock {
                     block_elem,
                     block_id,
@@ -866,6 +1281,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertTableColumn {
                 table_elem,
                 table_id,
@@ -915,6 +1331,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertTableRow {
                 table_elem,
                 table_id,
@@ -993,6 +1410,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let observed = self.state_vector();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::SetTableCell {
                 table_elem,
                 table_id,
@@ -1030,6 +1448,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::DeleteTableRowById {
                 table_elem,
                 table_id,
@@ -1108,6 +1527,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let observed = self.state_vector();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::SetTableColumnAlignment {
                 table_elem,
                 table_id,
@@ -1141,6 +1561,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::DeleteTableColumnById {
                 table_elem,
                 table_id,
@@ -1184,6 +1605,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let observed = self.state_vector();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::MoveTableColumn {
                 table_elem,
                 table_id,
@@ -1230,6 +1652,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let observed = self.state_vector();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::MoveTableRow {
                 table_elem,
                 table_id,
@@ -1263,12 +1686,17 @@ impl<C: OpCodec> CollaborativeDocument<C> {
     }
 
     fn commit_single_id(&mut self, envelope: Envelope, id: OpId) -> Result<OpId, SessionError> {
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        if let Some((key, write, _)) = self.register_write(&envelope) {
+            self.registers.record(key, write);
+        }
+        self.log_local_op(Operation {
             id,
             payload: payload.into(),
-        });
+        })?;
         self.next_counter = id.counter + 1;
         Ok(id)
     }
@@ -1286,7 +1714,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             return Ok(None);
         }
 
-        let (block_elem, units) = {
+        let (block_elem, units, run) = {
             let block = self
                 .document
                 .find_block_by_id(block_id)
@@ -1300,17 +1728,22 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             }
 
             let mut after = after_for_grapheme_offset(body, grapheme_offset);
+            // A typed character extends the run it follows; pastes stay separate.
+            let rules = self.document.segmentation();
+            let typed = rules.graphemes(text).nth(1).is_none();
+            let run = typed.then(|| self.open_text_run(block_id, after)).flatten();
             let mut counter = self.next_counter;
             let mut units = Vec::new();
-            for g in unicode_segmentation::UnicodeSegmentation::graphemes(text, true) {
+            for g in rules.graphemes(text) {
                 let id = OpId {
                     counter,
                     peer: self.peer,
                 };
                 counter = counter.saturating_add(1);
                 // First unit: right_origin from current paragraph; subsequent chain units
-                // insert after a brand-new id so right_origin is None.
-                let right_origin = if units.is_empty() {
+                // insert after a brand-new id so right_origin is None. Continuing an unsent
+                // run makes the first unit a chain unit too.
+                let right_origin = if units.is_empty() && run.is_none() {
                     body.compute_right_origin(after)
                 } else {
                     None
@@ -1323,32 +1756,88 @@ impl<C: OpCodec> CollaborativeDocument<C> {
                 });
                 after = Some(id);
             }
-            (block_elem, units)
+            (block_elem, units, run)
         };
 
         if units.is_empty() {
             return Ok(None);
         }
 
+        let run = run.map(|(replaced, mut run_units)| {
+            run_units.extend(units.iter().cloned());
+            (replaced, run_units)
+        });
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertText {
                 block_elem,
                 block_id,
                 units,
             }),
         };
-        let (op_id, _span) = operation_extent(&envelope);
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        let (op_id, _span) = operation_extent(&envelope, self.document.segmentation());
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let coalesced = match run {
+            Some((replaced, run_units)) => {
+                let merged = Envelope {
+                    version: WIRE_VERSION,
+                    meta: None,
+                    body: OpBody::Doc(DocOp::InsertText {
+                        block_elem,
+                        block_id,
+                        units: run_units,
+                    }),
+                };
+                let payload = self.encode_local(&merged)?;
+                self.sync.coalesce_local_op(
+                    replaced,
+                    Operation {
+                        id: op_id,
+                        payload: payload.into(),
+                    },
+                )
+            }
+            None => false,
+        };
+        if !coalesced {
+            let payload = self.encode_local(&envelope)?;
+            self.log_local_op(Operation {
+                id: op_id,
+                payload: payload.into(),
+            })?;
+        }
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
-            id: op_id,
-            payload: payload.into(),
-        });
         self.next_counter = op_id.counter + 1;
         Ok(Some(op_id))
     }
 
+    /// The unsent `InsertText` a character typed at `after` in `block_id` continues:
+    /// this peer's latest operation, into the same block, ending at `after`.
+    /// Appending to it keeps a typing run as one operation until it is sent.
+    fn open_text_run(
+        &self,
+        block_id: BlockId,
+        after: Option<OpId>,
+    ) -> Option<(OpId, Vec<TextUnitWire>)> {
+        let latest = OpId {
+            counter: self.next_counter.checked_sub(1)?,
+            peer: self.peer,
+        };
+        let envelope = self.codec.decode(self.sync.unsent(latest)?).ok()?;
+        match envelope.body {
+            OpBody::Doc(DocOp::InsertText {
+                block_id: run_block,
+                units,
+                ..
+            }) if run_block == block_id && after.is_some() && units.last()?.id == after? => {
+                Some((latest, units))
+            }
+            _ => None,
+        }
+    }
+
     /// Delete a visible grapheme range from a paragraph. Returns the delete-op id.
     ///
     /// `grapheme_count == 0` is a no-op that does not advance the clock.
@@ -1388,6 +1877,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         };
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::DeleteText {
                 block_elem,
                 block_id,
@@ -1395,17 +1885,361 @@ impl<C: OpCodec> CollaborativeDocument<C> {
                 targets,
             }),
         };
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        self.log_local_op(Operation {
             id: delete_id,
             payload: payload.into(),
-        });
+        })?;
         self.next_counter = delete_id.counter + 1;
         Ok(Some(delete_id))
     }
 
+    /// Validate attributes of marks added through [`Self::set_mark`]; `None`
+    /// accepts any. Remote marks are not checked, see [`Document::validate_marks`].
+    pub fn set_mark_schema(&mut self, schema: Option<MarkSchema>) {
+        self.mark_schema = schema;
+    }
+
+    pub fn mark_schema(&self) -> Option<&MarkSchema> {
+        self.mark_schema.as_ref()
+    }
+
+    /// Settle concurrent writes of `target`'s registers by `policy` from now on;
+    /// [`ResolutionPolicy::Lww`] detaches the policy. Like the mark schema, policies
+    /// are replica settings that snapshots do not carry.
+    pub fn set_resolution_policy(&mut self, target: ResolutionTarget, policy: ResolutionPolicy) {
+        if policy == ResolutionPolicy::Lww {
+            self.resolution_policies.remove(&target);
+            self.registers.forget(&target);
+            return;
+        }
+        for (key, write) in self.current_register_writes(&target) {
+            self.registers.seed(key, write);
+        }
+        self.resolution_policies.insert(target, policy);
+    }
+
+    pub fn resolution_policy(&self, target: &ResolutionTarget) -> ResolutionPolicy {
+        self.resolution_policies
+            .get(target)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Registers under [`ResolutionPolicy::Manual`] holding concurrent writes. Each
+    /// stays listed until [`Self::resolve_register`], or any later write that
+    /// observed all of them, replaces the values.
+    pub fn register_conflicts(&self) -> Vec<RegisterConflict> {
+        self.registers
+            .conflicts()
+            .filter(|conflict| {
+                self.resolution_policy(&conflict.key.target()) == ResolutionPolicy::Manual
+            })
+            .collect()
+    }
+
+    /// Write `value` to `key`, superseding every concurrent write this replica has
+    /// seen. `None` deletes a frontmatter field and empties a header cell.
+    pub fn resolve_register(
+        &mut self,
+        key: &RegisterKey,
+        value: Option<String>,
+    ) -> Result<OpId, SessionError> {
+        match key {
+            RegisterKey::FrontmatterField(field) => {
+                self.set_frontmatter_field(field.clone(), value)
+            }
+            RegisterKey::TableHeaderCell {
+                table_id,
+                column_id,
+            } => self.set_table_cell(*table_id, *table_id, *column_id, value.unwrap_or_default()),
+        }
+    }
+
+    /// Winning writes of `target`'s registers as the document holds them now.
+    fn current_register_writes(
+        &self,
+        target: &ResolutionTarget,
+    ) -> Vec<(RegisterKey, RegisterWrite<Option<String>>)> {
+        match target {
+            ResolutionTarget::FrontmatterField(field) => {
+                let Some(frontmatter) = &self.document.frontmatter else {
+                    return Vec::new();
+                };
+                let Some(id) = frontmatter.field_op_id(field) else {
+                    return Vec::new();
+                };
+                let write = RegisterWrite {
+                    id,
+                    value: frontmatter.get(field).map(str::to_string),
+                    observed: StateVector::new(),
+                };
+                vec![(RegisterKey::FrontmatterField(field.clone()), write)]
+            }
+            ResolutionTarget::TableHeader(table_id) => {
+                let Some(BlockKind::Table { table }) = self
+                    .document
+                    .find_block_by_id(*table_id)
+                    .map(|block| &block.kind)
+                else {
+                    return Vec::new();
+                };
+                table
+                    .columns_in_order()
+                    .into_iter()
+                    .filter_map(|column| {
+                        let cell = table.cells.get(&CellAddress {
+                            row_id: table.header_row_id(),
+                            column_id: column.id,
+                        })?;
+                        let key = RegisterKey::TableHeaderCell {
+                            table_id: *table_id,
+                            column_id: column.id,
+                        };
+                        let write = RegisterWrite {
+                            id: cell.op_id,
+                            value: Some(cell.value.clone()),
+                            observed: cell.observed.clone(),
+                        };
+                        Some((key, write))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The register write `envelope` makes, when its register has a policy.
+    fn register_write(
+        &self,
+        envelope: &Envelope,
+    ) -> Option<(RegisterKey, RegisterWrite<Option<String>>, ResolutionPolicy)> {
+        let (key, write) = match &envelope.body {
+            OpBody::Doc(DocOp::SetFrontmatterField {
+                id,
+                key,
+                value,
+                observed,
+            }) => (
+                RegisterKey::FrontmatterField(key.clone()),
+                RegisterWrite {
+                    id: *id,
+                    value: value.clone(),
+                    observed: observed.clone(),
+                },
+            ),
+            OpBody::Doc(DocOp::SetTableCell {
+                table_id,
+                row_id,
+                column_id,
+                id,
+                value,
+                observed,
+                ..
+            }) if row_id == table_id => (
+                RegisterKey::TableHeaderCell {
+                    table_id: *table_id,
+                    column_id: *column_id,
+                },
+                RegisterWrite {
+                    id: *id,
+                    value: Some(value.clone()),
+                    observed: observed.clone(),
+                },
+            ),
+            _ => return None,
+        };
+        let policy = self.resolution_policy(&key.target());
+        (policy != ResolutionPolicy::Lww).then_some((key, write, policy))
+    }
+
+    /// Track a remote register write under its policy and show the value the policy
+    /// picks. The document has already merged the write by LWW, so it holds the
+    /// newest concurrent write.
+    fn settle_register(&mut self, envelope: &Envelope) -> Option<SemanticConflict> {
+        let (key, write, policy) = self.register_write(envelope)?;
+        let id = write.id;
+        let heads = self.registers.record(key.clone(), write);
+        if heads.len() < 2 || heads.iter().all(|head| head.id != id) {
+            return None;
+        }
+        let newest = heads.last()?.clone();
+        let shown = policy.choose(heads, self.peer)?.clone();
+        let writes = heads.iter().map(|head| head.id).collect();
+        if shown.id != newest.id {
+            // Keep the newest id so a later write that observed it still wins.
+            match &key {
+                RegisterKey::FrontmatterField(field) => {
+                    let _ = self.document.set_frontmatter_field(
+                        field.clone(),
+                        shown.value.clone(),
+                        newest.id,
+                    );
+                }
+                RegisterKey::TableHeaderCell {
+                    table_id,
+                    column_id,
+                } => {
+                    let table_elem = self.document.find_block_by_id(*table_id)?.elem_id;
+                    self.document.with_block_mut(table_elem, |block| {
+                        if let BlockKind::Table { table } = &mut block.kind {
+                            table.set_cell_observed(
+                                *table_id,
+                                *column_id,
+                                shown.value.clone().unwrap_or_default(),
+                                newest.id,
+                                newest.observed.clone(),
+                            );
+                        }
+                    });
+                }
+            }
+        }
+        Some(SemanticConflict::RegisterConflict {
+            key,
+            policy,
+            writes,
+            shown: shown.id,
+        })
+    }
+
+    pub fn replica_mode(&self) -> ReplicaMode {
+        self.sync.mode()
+    }
+
+    /// Call `listener` after every local edit and every [`Self::apply_remote`]
+    /// that applies an operation. [`SyncUpdate::origin`] lets a UI skip
+    /// re-rendering its own edits.
+    pub fn on_update(
+        &mut self,
+        listener: impl Fn(&SyncUpdate) + Send + Sync + 'static,
+    ) -> UpdateListenerId {
+        self.sync.on_update(listener)
+    }
+
+    /// Stop calling a listener from [`Self::on_update`]. Returns whether it was registered.
+    pub fn remove_update_listener(&mut self, id: UpdateListenerId) -> bool {
+        self.sync.remove_update_listener(id)
+    }
+
+    /// Pass operations leaving for peers through `hook`; see
+    /// [`SyncState::on_outgoing`]. Hooks see encoded envelopes, so a redacting
+    /// hook decodes the payload with the session's codec.
+    pub fn on_outgoing(
+        &mut self,
+        hook: impl Fn(Operation, Option<PeerId>) -> Option<Operation> + Send + Sync + 'static,
+    ) -> OpHookId {
+        self.sync.on_outgoing(hook)
+    }
+
+    /// Screen remote operations with `hook` before [`Self::apply_remote`] decodes
+    /// them; see [`SyncState::on_incoming`]. A replacement is validated like
+    /// any remote operation.
+    pub fn on_incoming(
+        &mut self,
+        hook: impl Fn(&Operation, Origin) -> Decision + Send + Sync + 'static,
+    ) -> OpHookId {
+        self.sync.on_incoming(hook)
+    }
+
+    pub fn remove_op_hook(&mut self, id: OpHookId) -> bool {
+        self.sync.remove_op_hook(id)
+    }
+
+    /// Make this session a follower or a writer. A [`ReplicaMode::ReadOnly`]
+    /// session still applies remote changes, but local edits fail with
+    /// [`SessionError::ReadOnly`] before touching the document.
+    pub fn set_replica_mode(&mut self, mode: ReplicaMode) {
+        self.sync.set_mode(mode);
+    }
+
+    fn ensure_writable(&self, envelope: &Envelope) -> Result<(), SessionError> {
+        if self.sync.mode() == ReplicaMode::ReadOnly {
+            let (op_id, _) = operation_extent(envelope, self.document.segmentation());
+            return Err(ReadOnlyReplica { op_id }.into());
+        }
+        Ok(())
+    }
+
+    /// Enforce block ACLs on this replica. Local edits, and remote messages in
+    /// [`Self::apply_remote`], that a [`BlockAcl`] forbids fail with
+    /// [`SessionError::BlockLocked`]; a rejected message is not applied at all.
+    ///
+    /// Off by default: ACLs are stored and merged either way. Turn it on for every
+    /// replica that should refuse such edits, such as a relay.
+    pub fn set_acl_enforcement(&mut self, enforce: bool) {
+        self.enforce_acls = enforce;
+    }
+
+    pub fn acl_enforcement(&self) -> bool {
+        self.enforce_acls
+    }
+
+    /// Replace the ACL of `block_id`; `None` clears it. With enforcement on, only
+    /// the current ACL's owners may change it.
+    pub fn set_block_acl(
+        &mut self,
+        block_id: BlockId,
+        acl: Option<BlockAcl>,
+    ) -> Result<OpId, SessionError> {
+        let block_elem = self
+            .document
+            .block_elem_id(block_id)
+            .ok_or(SessionError::BlockNotFound)?;
+        let id = self.peek_next_id();
+        let envelope = Envelope {
+            version: WIRE_VERSION,
+            meta: None,
+            body: OpBody::Doc(DocOp::SetBlockAcl {
+                block_elem,
+                block_id,
+                id,
+                acl,
+                observed: self.sync.state_vector(),
+            }),
+        };
+        self.commit_single_id(envelope, id)
+    }
+
+    /// Check `envelope` against block ACLs when enforcement is on; `pending` holds
+    /// ACL writes accepted earlier in the same batch.
+    fn check_acls(
+        &self,
+        envelope: &Envelope,
+        pending: &BTreeMap<BlockId, Option<BlockAcl>>,
+    ) -> Result<(), SessionError> {
+        if !self.enforce_acls {
+            return Ok(());
+        }
+        let peer = operation_extent(envelope, self.document.segmentation())
+            .0
+            .peer;
+        if let OpBody::Doc(DocOp::SetBlockAcl { block_id, .. }) = &envelope.body {
+            let current = match pending.get(block_id) {
+                Some(acl) => acl.as_ref(),
+                None => self.document.block_acl(*block_id),
+            };
+            if current.is_some_and(|acl| !acl.permits_change(peer)) {
+                return Err(SessionError::BlockLocked {
+                    block_id: *block_id,
+                    peer,
+                });
+            }
+            return Ok(());
+        }
+        for block_id in acl_targets(&self.document, envelope) {
+            self.document
+                .check_block_edit_with(block_id, peer, pending)
+                .map_err(|_| SessionError::BlockLocked { block_id, peer })?;
+        }
+        Ok(())
+    }
+
     /// Set a mark over a non-empty half-open grapheme range.
+    ///
+    /// Custom kinds must be namespaced, as in `app:comment`.
     pub fn set_mark(
         &mut self,
         block_id: BlockId,
@@ -1413,6 +2247,10 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         kind: MarkKind,
         attrs: BTreeMap<String, MarkValue>,
     ) -> Result<OpId, SessionError> {
+        kind.validate().map_err(MarkSchemaError::from)?;
+        if let Some(schema) = &self.mark_schema {
+            schema.validate(&kind, &attrs)?;
+        }
         let block = self
             .document
             .find_block_by_id(block_id)
@@ -1425,6 +2263,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::SetMark {
                 block_elem,
                 block_id,
@@ -1455,6 +2294,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::RemoveMark {
                 block_elem,
                 block_id,
@@ -1466,6 +2306,51 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         self.commit_single_id(envelope, id)
     }
 
+    /// Point an active link mark at `target`: removes the interval and sets an
+    /// identical one with the new `href`. Returns the new interval id.
+    pub fn retarget_link(
+        &mut self,
+        block_id: BlockId,
+        interval_id: OpId,
+        target: &LinkTarget,
+    ) -> Result<OpId, SessionError> {
+        let block = self
+            .document
+            .find_block_by_id(block_id)
+            .ok_or(SessionError::BlockNotFound)?;
+        let interval = block
+            .marks
+            .interval(&interval_id)
+            .filter(|interval| {
+                interval.kind == MarkKind::Link && block.marks.is_active(&interval.id)
+            })
+            .ok_or(SessionError::NotLink)?;
+        let block_elem = block.elem_id;
+        let (start, end) = (interval.start, interval.end);
+        let mut attrs = interval.attr_values();
+        attrs.insert("href".into(), MarkValue::String(target.to_string()));
+        if let Some(schema) = &self.mark_schema {
+            schema.validate(&MarkKind::Link, &attrs)?;
+        }
+
+        self.remove_mark(block_id, interval_id)?;
+        let id = self.peek_next_id();
+        let envelope = Envelope {
+            version: WIRE_VERSION,
+            meta: None,
+            body: OpBody::Doc(DocOp::SetMark {
+                block_elem,
+                block_id,
+                id,
+                kind: MarkKind::Link,
+                start,
+                end,
+                attrs,
+            }),
+        };
+        self.commit_single_id(envelope, id)
+    }
+
     /// Set or delete a supported top-level frontmatter field.
     pub fn set_frontmatter_field(
         &mut self,
@@ -1483,11 +2368,27 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         probe.set(key.clone(), value.clone(), id)?;
         let envelope = Envelope {
             version: WIRE_VERSION,
-            body: OpBody::Doc(DocOp::SetFrontmatterField { id, key, value }),
+            meta: None,
+            body: OpBody::Doc(DocOp::SetFrontmatterField {
+                id,
+                key,
+                value,
+                observed: self.state_vector(),
+            }),
         };
         self.commit_single_id(envelope, id)
     }
 
+    /// [`Self::set_frontmatter_field`] with a typed value, written as valid YAML.
+    /// A list value merges with concurrent writes as an add-wins set.
+    pub fn set_frontmatter_value(
+        &mut self,
+        key: impl Into<String>,
+        value: Option<crate::doc::FrontmatterValue>,
+    ) -> Result<OpId, SessionError> {
+        self.set_frontmatter_field(key, value.map(|value| value.to_yaml()))
+    }
+
     /// Establish a lossless frontmatter base. Existing frontmatter is never overwritten.
     pub fn initialize_frontmatter(
         &mut self,
@@ -1499,6 +2400,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InitializeFrontmatter { id, frontmatter }),
         };
         self.commit_single_id(envelope, id).map(Some)
@@ -1675,18 +2577,21 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = moves.last().expect("non-empty move").id;
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::MoveBlocks {
                 to_parent,
                 id,
                 blocks: moves,
             }),
         };
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        self.log_local_op(Operation {
             id,
             payload: payload.into(),
-        });
+        })?;
         self.next_counter = id.counter + 1;
         Ok(id)
     }
@@ -1747,6 +2652,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let id = self.peek_next_id();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::SplitBlock {
                 parent,
                 target,
@@ -1852,6 +2758,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             .collect();
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::MergeBlocks {
                 parent,
                 left,
@@ -1862,22 +2769,38 @@ impl<C: OpCodec> CollaborativeDocument<C> {
                 units,
             }),
         };
-        let (op_id, _) = operation_extent(&envelope);
-        let payload = self.codec.encode(&envelope).map_err(codec_err)?;
+        let (op_id, _) = operation_extent(&envelope, self.document.segmentation());
+        self.ensure_writable(&envelope)?;
+        self.check_acls(&envelope, &BTreeMap::new())?;
+        let payload = self.encode_local(&envelope)?;
         apply_envelope_to_document(&mut self.document, &envelope);
-        self.sync.add_local_op(Operation {
+        self.log_local_op(Operation {
             id: op_id,
             payload: payload.into(),
-        });
+        })?;
         self.next_counter = op_id.counter.saturating_add(1);
         Ok(op_id)
     }
 
     /// Apply remote changes: pre-decode all, then integrate with document apply.
+    /// Update listeners see them as [`Origin::Remote`] from the author of the
+    /// message's first operation.
     pub fn apply_remote(
         &mut self,
         message: ChangeMessage,
         limits: &ValidationLimits,
+    ) -> Result<SessionApplyResult, SessionError> {
+        let sender = message.ops.first().map_or(0, |op| op.id.peer);
+        self.apply_remote_from(message, limits, Origin::Remote(sender))
+    }
+
+    /// [`Self::apply_remote`], reporting the applied operations to update
+    /// listeners as coming from `origin`.
+    pub fn apply_remote_from(
+        &mut self,
+        message: ChangeMessage,
+        limits: &ValidationLimits,
+        origin: Origin,
     ) -> Result<SessionApplyResult, SessionError> {
         let deferred_count = self
             .pending_envelopes
@@ -1889,9 +2812,26 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             limits,
             self.sync.pending_count().saturating_add(deferred_count),
         )?;
+        self.sync.check_gaps(&message)?;
 
+        let gaps = message.gap_map();
         let mut prepared: Vec<(Operation, Envelope)> = Vec::with_capacity(message.ops.len());
+        let mut pending_acls = BTreeMap::new();
+        let mut mismatched = Vec::new();
+        let mut rejected = Vec::new();
         for op in message.ops {
+            let op = match self.sync.screen_incoming(op, origin) {
+                Ok(op) => op,
+                Err(id) => {
+                    rejected.push(id);
+                    continue;
+                }
+            };
+            if self.sync.is_duplicate_mismatch(&op) {
+                tracing::warn!(op = ?op.id, "duplicate operation id with a different payload");
+                mismatched.push(SemanticConflict::DuplicateOpMismatch { id: op.id });
+                continue;
+            }
             if self.sync.contains(op.id) {
                 continue;
             }
@@ -1915,16 +2855,35 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             if self.unit_mode && !insert_block_paragraph_is_empty(&env) {
                 return Err(SessionError::NonEmptyParagraphOnInsertBlock);
             }
-            check_operation_id_is_max(&op, &env)?;
+            check_operation_id_is_max(&op, &env, self.document.segmentation())?;
             check_peer_consistency(&op, &env)?;
+            self.check_metadata(op.id, &env)?;
+            self.check_acls(&env, &pending_acls)?;
+            if let OpBody::Doc(DocOp::SetBlockAcl {
+                block_id, acl, id, ..
+            }) = &env.body
+                && self
+                    .document
+                    .block_acl_op(*block_id)
+                    .is_none_or(|winner| CausalOrd::wins(*id, winner))
+            {
+                pending_acls.insert(*block_id, acl.clone());
+            }
             prepared.push((op, env));
         }
 
-        let mut result = SessionApplyResult::default();
+        let mut result = SessionApplyResult {
+            conflicts: mismatched,
+            rejected,
+            ..SessionApplyResult::default()
+        };
         for (op, env) in prepared {
             let id = op.id;
-            let (_, span) = operation_extent(&env);
-            match self.sync.apply_one(op, span) {
+            let (_, span) = operation_extent(&env, self.document.segmentation());
+            let after = gaps
+                .get(&id)
+                .map_or(id.counter.saturating_sub(span), |after| *after);
+            match self.sync.apply_one_after(op, span, after) {
                 IntegrateResult::AlreadyPresent => {}
                 IntegrateResult::Buffered => {
                     self.pending_envelopes.insert(id, env);
@@ -1938,6 +2897,10 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             }
         }
         self.drain_deferred(&mut result);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_origin(origin, result.applied.len() as u64);
+        self.sync.notify_applied(&result.applied, origin);
+        self.enforce_history_budget();
         Ok(result)
     }
 
@@ -1971,7 +2934,12 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             }
             return;
         }
-        apply_envelope_to_document(&mut self.document, &envelope);
+        if let Some(conflict) = apply_envelope_to_document(&mut self.document, &envelope) {
+            result.conflicts.push(conflict);
+        }
+        if let Some(conflict) = self.settle_register(&envelope) {
+            result.conflicts.push(conflict);
+        }
         result.buffered.retain(|pending| *pending != id);
         if !result.applied.contains(&id) {
             result.applied.push(id);
@@ -1999,17 +2967,41 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         }
     }
 
+    /// Operations buffered for the earlier counters of their peer; see
+    /// [`SyncState::pending_summary`].
+    pub fn pending_summary(&self, now: Instant) -> PendingSummary {
+        self.sync.pending_summary(now)
+    }
+
+    /// [`SyncState::pending_metrics`] of the session's log.
+    #[cfg(feature = "metrics")]
+    pub fn pending_metrics(&self, now: Instant) -> crate::metrics::PendingMetrics {
+        self.sync.pending_metrics(now)
+    }
+
+    /// Drop buffered remote operations that have waited at least `ttl` as of
+    /// `now`, so their peer can be asked to retransmit from each
+    /// [`ExpiredOp::awaited`]. Operations already logged but waiting to reach the
+    /// document are kept.
+    pub fn expire_pending(&mut self, now: Instant, ttl: Duration) -> Vec<ExpiredOp> {
+        let expired = self.sync.expire_pending(now, ttl);
+        for op in &expired {
+            self.pending_envelopes.remove(&op.id);
+        }
+        expired
+    }
+
     /// Serialize current session state (document + op log + clock).
     ///
     /// Includes the full applied op log for retransmission; growth without
     /// compaction is accepted for 0.1.
     pub fn save_snapshot(&self) -> Result<SessionSnapshot, SnapshotError> {
         let ops = self.sync.applied_ops();
-        let pending: Vec<(OpId, Vec<u8>)> = self
+        let pending: Vec<(OpId, Arc<[u8]>)> = self
             .sync
             .pending()
             .into_iter()
-            .map(|op| (op.id, op.payload.to_vec()))
+            .map(|op| (op.id, op.payload))
             .collect();
         let deferred = self
             .pending_envelopes
@@ -2018,7 +3010,7 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             .map(|(id, envelope)| {
                 self.codec
                     .encode(envelope)
-                    .map(|payload| (*id, payload))
+                    .map(|payload| (*id, payload.into()))
                     .map_err(|error| SnapshotError::Serde(error.to_string()))
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -2034,9 +3026,29 @@ impl<C: OpCodec> CollaborativeDocument<C> {
             ops,
             pending,
             deferred,
+            registers: self.registers.to_entries(),
         })
     }
 
+    /// Size of this session's snapshot and what its compact ids save over full ones.
+    pub fn storage_stats(&self) -> Result<StorageStats, SnapshotError> {
+        self.save_snapshot()?.storage_stats()
+    }
+
+    /// Import every applied operation of `other`, a replica of the same history that may
+    /// have been edited by peers this replica has never seen. The state vector advances
+    /// to cover them; operations still waiting on their causal predecessors stay buffered.
+    pub fn merge_from(&mut self, other: &Self) -> Result<SessionApplyResult, SessionError> {
+        let message = other.encode_changes_since(&self.state_vector())?;
+        // Both sides are local replicas, so the transport limits do not apply.
+        let limits = ValidationLimits {
+            max_ops_per_message: usize::MAX,
+            max_payload_bytes: usize::MAX,
+            max_pending_buffer: usize::MAX,
+        };
+        self.apply_remote_from(message, &limits, Origin::Import)
+    }
+
     /// Switch peer identity after restore (late join without reloading bytes).
     pub fn rebind_peer(&mut self, local_peer: PeerId) {
         self.peer = local_peer;
@@ -2058,10 +3070,68 @@ impl<C: OpCodec> CollaborativeDocument<C> {
         let snap = self.save_snapshot()?;
         let bytes = snap.to_bytes()?;
         storage.write_snapshot(&bytes, &[], false)?;
+        storage.write_block_index(self.document())?;
+        if storage.has_chain() {
+            storage.record_snapshot_link(&snap.state_vector)?;
+        }
         Ok(())
     }
 }
 
+impl<C: OpCodec + Clone> CollaborativeDocument<C> {
+    /// Independent replica of this history that edits as `peer`, e.g. to draft a rewrite
+    /// and [`merge_from`](Self::merge_from) it back later. `peer` must be new to the
+    /// history so the fork cannot reissue an existing operation id.
+    pub fn fork(&self, peer: PeerId) -> Result<Self, SessionError> {
+        let known = peer == self.peer
+            || self.sync.state_vector().get(peer).is_some()
+            || self.sync.delta_floor().get(peer).is_some()
+            || self.sync.pending().iter().any(|op| op.id.peer == peer);
+        if known {
+            return Err(SessionError::PeerInUse(peer));
+        }
+        Ok(self.replica(peer, 1))
+    }
+
+    fn replica(&self, peer: PeerId, next_counter: u64) -> Self {
+        Self {
+            peer,
+            next_counter,
+            document: self.document.clone(),
+            sync: self.sync.clone(),
+            codec: self.codec.clone(),
+            unit_mode: self.unit_mode,
+            pending_envelopes: self.pending_envelopes.clone(),
+            mark_schema: self.mark_schema.clone(),
+            enforce_acls: self.enforce_acls,
+            resolution_policies: self.resolution_policies.clone(),
+            registers: self.registers.clone(),
+            history_budget: self.history_budget,
+            metadata_stamp: self.metadata_stamp.clone(),
+            metadata_verifier: self.metadata_verifier.clone(),
+        }
+    }
+
+    /// The document as it stood at `frontier`, replayed from the applied op log.
+    ///
+    /// Fails with [`SessionError::Rebase`] once a checkpoint has pruned the history
+    /// the replay would start from.
+    pub fn materialize_at(&self, frontier: &StateVector) -> Result<Document, SessionError> {
+        let mut message = self.sync.encode_changes_since(&StateVector::new())?;
+        message
+            .ops
+            .retain(|op| op.id.counter <= frontier.get(op.id.peer).unwrap_or(0));
+        let mut replica = Self::with_codec(self.peer, self.codec.clone(), self.unit_mode);
+        let limits = ValidationLimits {
+            max_ops_per_message: usize::MAX,
+            max_payload_bytes: usize::MAX,
+            max_pending_buffer: usize::MAX,
+        };
+        replica.apply_remote(message, &limits)?;
+        Ok(replica.document)
+    }
+}
+
 impl CollaborativeDocument<JsonOpCodec> {
     /// Crash recovery for the **same** peer: restore peer id and `next_counter`.
     pub fn restore_from_snapshot(snap: SessionSnapshot) -> Result<Self, SnapshotError> {
@@ -2094,14 +3164,8 @@ impl CollaborativeDocument<JsonOpCodec> {
             .pending
             .into_iter()
             .map(|(id, payload)| {
-                let span = span_of_payload(&payload);
-                (
-                    Operation {
-                        id,
-                        payload: payload.into(),
-                    },
-                    span,
-                )
+                let span = span_of_payload(&payload, doc.segmentation());
+                (Operation { id, payload }, span)
             })
             .collect();
         sync.restore_pending(pending_ops);
@@ -2124,6 +3188,13 @@ impl CollaborativeDocument<JsonOpCodec> {
             codec,
             unit_mode: snap.unit_mode,
             pending_envelopes,
+            mark_schema: None,
+            enforce_acls: false,
+            resolution_policies: BTreeMap::new(),
+            registers: RegisterHeads::from_entries(snap.registers),
+            history_budget: None,
+            metadata_stamp: None,
+            metadata_verifier: None,
         })
     }
 
@@ -2140,9 +3211,9 @@ impl CollaborativeDocument<JsonOpCodec> {
     /// Late join: load compact state as `local_peer` (does not adopt snapshot peer).
     pub fn import_state(
         document: DocumentDto,
-        ops: Vec<(OpId, Vec<u8>)>,
-        pending: Vec<(OpId, Vec<u8>)>,
-        deferred: Vec<(OpId, Vec<u8>)>,
+        ops: Vec<(OpId, Arc<[u8]>)>,
+        pending: Vec<(OpId, Arc<[u8]>)>,
+        deferred: Vec<(OpId, Arc<[u8]>)>,
         local_peer: PeerId,
         unit_mode: bool,
     ) -> Result<Self, SnapshotError> {
@@ -2160,14 +3231,8 @@ impl CollaborativeDocument<JsonOpCodec> {
         let pending_ops: Vec<(Operation, u64)> = pending
             .into_iter()
             .map(|(id, payload)| {
-                let span = span_of_payload(&payload);
-                (
-                    Operation {
-                        id,
-                        payload: payload.into(),
-                    },
-                    span,
-                )
+                let span = span_of_payload(&payload, doc.segmentation());
+                (Operation { id, payload }, span)
             })
             .collect();
         sync.restore_pending(pending_ops);
@@ -2189,6 +3254,13 @@ impl CollaborativeDocument<JsonOpCodec> {
             codec,
             unit_mode,
             pending_envelopes,
+            mark_schema: None,
+            enforce_acls: false,
+            resolution_policies: BTreeMap::new(),
+            registers: RegisterHeads::default(),
+            history_budget: None,
+            metadata_stamp: None,
+            metadata_verifier: None,
         })
     }
 
@@ -0,0 +1,199 @@
+//! Dry-run reports of what an incoming change message would do.
+//!
+//! The message is applied to a private copy of the session and the copy's
+//! document is compared with the live one, so the report reflects exactly what
+//! [`CollaborativeDocument::apply_remote`] would produce, buffering and
+//! conflict resolution included, while the session itself stays untouched.
+
+use super::changelog::push_count;
+use super::{CollaborativeDocument, SessionError};
+use crate::codec::OpCodec;
+use crate::core::mark::MarkIntervalId;
+use crate::core::{OpId, Sequence};
+use crate::doc::{Block, BlockId, BlockKind, Document, block_text_seq, paragraph_visible_ids};
+use crate::sync::{ChangeMessage, SemanticConflict, ValidationLimits};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// What [`CollaborativeDocument::apply_remote`] would change, from
+/// [`CollaborativeDocument::preview_changes`]. Block lists are in id order and
+/// include nested blocks and list items' children.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergePreview {
+    /// Operations that would apply, and those that would wait for dependencies.
+    pub applied: Vec<OpId>,
+    pub buffered: Vec<OpId>,
+    pub blocks_added: Vec<BlockId>,
+    pub blocks_removed: Vec<BlockId>,
+    /// Surviving blocks whose content, kind, marks or attributes would change.
+    pub blocks_modified: Vec<BlockId>,
+    pub text_changes: Vec<TextChange>,
+    pub mark_changes: Vec<MarkChange>,
+    pub frontmatter_changed: bool,
+    pub conflicts: Vec<SemanticConflict>,
+}
+
+/// The one grapheme range of a surviving text block that an apply rewrites.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    pub block_id: BlockId,
+    /// Graphemes of the current text that would be replaced; empty for a pure
+    /// insertion.
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+/// Mark intervals of a surviving block that would become active or inactive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkChange {
+    pub block_id: BlockId,
+    pub added: Vec<MarkIntervalId>,
+    pub removed: Vec<MarkIntervalId>,
+}
+
+impl MergePreview {
+    /// Whether applying the message would leave the document as it is.
+    pub fn is_empty(&self) -> bool {
+        self.blocks_added.is_empty()
+            && self.blocks_removed.is_empty()
+            && self.blocks_modified.is_empty()
+            && !self.frontmatter_changed
+    }
+
+    /// One-line description for logs, e.g. `3 ops: added a block, edited 2 blocks`.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        push_count(&mut parts, "added", self.blocks_added.len(), "block");
+        push_count(&mut parts, "removed", self.blocks_removed.len(), "block");
+        push_count(&mut parts, "edited", self.blocks_modified.len(), "block");
+        if self.frontmatter_changed {
+            parts.push("changed frontmatter".to_string());
+        }
+        push_count(&mut parts, "buffered", self.buffered.len(), "op");
+        push_count(&mut parts, "resolved", self.conflicts.len(), "conflict");
+        let ops = match self.applied.len() {
+            1 => "1 op".to_string(),
+            n => format!("{n} ops"),
+        };
+        if parts.is_empty() {
+            return format!("{ops}: no visible change");
+        }
+        format!("{ops}: {}", parts.join(", "))
+    }
+}
+
+impl<C: OpCodec + Clone> CollaborativeDocument<C> {
+    /// Report what [`Self::apply_remote`] would do with `message` under `limits`
+    /// without changing this session. Fails as that call would.
+    pub fn preview_changes(
+        &self,
+        message: &ChangeMessage,
+        limits: &ValidationLimits,
+    ) -> Result<MergePreview, SessionError> {
+        let mut replica = self.replica(self.peer, self.next_counter);
+        let result = replica.apply_remote(message.clone(), limits)?;
+        let mut preview = compare(&self.document, &replica.document);
+        preview.applied = result.applied;
+        preview.buffered = result.buffered;
+        preview.conflicts = result.conflicts;
+        Ok(preview)
+    }
+}
+
+fn compare(before: &Document, after: &Document) -> MergePreview {
+    let (old, new) = (all_blocks(before), all_blocks(after));
+    let mut preview = MergePreview {
+        blocks_added: new
+            .keys()
+            .filter(|id| !old.contains_key(*id))
+            .copied()
+            .collect(),
+        blocks_removed: old
+            .keys()
+            .filter(|id| !new.contains_key(*id))
+            .copied()
+            .collect(),
+        frontmatter_changed: before.frontmatter != after.frontmatter,
+        ..MergePreview::default()
+    };
+    for (block_id, old_block) in &old {
+        let Some(new_block) = new.get(block_id) else {
+            continue;
+        };
+        if old_block == new_block {
+            continue;
+        }
+        preview.blocks_modified.push(*block_id);
+        if let Some(change) = text_change(*block_id, old_block, new_block) {
+            preview.text_changes.push(change);
+        }
+        let active = |block: &Block| -> BTreeSet<MarkIntervalId> {
+            block
+                .marks
+                .iter_active_intervals()
+                .map(|interval| interval.id)
+                .collect()
+        };
+        let (was, now) = (active(old_block), active(new_block));
+        if was != now {
+            preview.mark_changes.push(MarkChange {
+                block_id: *block_id,
+                added: now.difference(&was).copied().collect(),
+                removed: was.difference(&now).copied().collect(),
+            });
+        }
+    }
+    preview
+}
+
+fn all_blocks(document: &Document) -> BTreeMap<BlockId, &Block> {
+    fn walk<'a>(blocks: &'a Sequence<Block>, out: &mut BTreeMap<BlockId, &'a Block>) {
+        for block in blocks.iter() {
+            out.insert(block.id, block);
+            match &block.kind {
+                BlockKind::BlockQuote { children } => walk(children, out),
+                BlockKind::List { items, .. } => {
+                    for item in items.iter() {
+                        walk(&item.children, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    walk(document.blocks(), &mut out);
+    out
+}
+
+/// The rewritten range between the common prefix and suffix of visible units.
+fn text_change(block_id: BlockId, before: &Block, after: &Block) -> Option<TextChange> {
+    let (old, new) = (block_text_seq(&before.kind)?, block_text_seq(&after.kind)?);
+    let (old_ids, new_ids) = (paragraph_visible_ids(old), paragraph_visible_ids(new));
+    if old_ids == new_ids {
+        return None;
+    }
+    let prefix = old_ids
+        .iter()
+        .zip(&new_ids)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_ids
+        .iter()
+        .rev()
+        .zip(new_ids.iter().rev())
+        .take(old_ids.len().min(new_ids.len()) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let replacement = new
+        .iter()
+        .skip(prefix)
+        .take(new_ids.len() - suffix - prefix)
+        .map(|unit| unit.grapheme.as_str())
+        .collect();
+    Some(TextChange {
+        block_id,
+        range: prefix..old_ids.len() - suffix,
+        replacement,
+    })
+}
@@ -1,22 +1,31 @@
 //! Session snapshot schema and Document ↔ DTO conversion.
 //!
 //! Snapshots persist document materialization plus the opaque op log for
-//! crash recovery, checkpoint rebase, and late join.
+//! crash recovery, checkpoint rebase, and late join. The same DTOs back the
+//! serde impls of [`Document`], [`Block`], and [`Table`], and the hand-readable
+//! [`Document::to_debug_json`].
 
 use crate::core::mark::MarkSet;
-use crate::core::{Element, LwwRegister, OpId, PeerId, Sequence, SequenceOp};
+use crate::core::{
+    Element, LwwRegister, OpId, PeerId, SegmentationRules, Sequence, SequenceOp, StateVector,
+};
 use crate::doc::{
-    Block, BlockId, BlockKind, CellAddress, CellContent, CodeFenceStyle, ColumnAlignment, ColumnId,
-    Document, DocumentSource, Frontmatter, ListStyle, PendingColumnAlignment, PendingListItemMove,
-    PendingTableMove, RowId, Table, TableCell, TableColumn, TableRow, TaskState, TextUnit,
+    AclEntry, Block, BlockId, BlockKind, CellAddress, CellContent, CodeFenceStyle, CodeLines,
+    ColumnAlignment, ColumnId, Document, DocumentSource, EditOp, Frontmatter, ListStyle,
+    PendingColumnAlignment, PendingEdits, PendingListItemMove, PendingTableMove, RowId, Table,
+    TableCell, TableColumn, TableRow, TaskState, TextUnit,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// Snapshot schema version (not wire `Envelope` version).
 ///
 /// v6: unresolved sequence inserts/deletes survive snapshot and checkpoint restore.
-pub const SNAPSHOT_FORMAT_VERSION: u16 = 6;
+/// v7: sequence elements and op logs write ids as `[peer_index, counter]` against a peer table,
+/// and mark kinds as their stable names.
+pub const SNAPSHOT_FORMAT_VERSION: u16 = 7;
 
 /// Errors loading or decoding session snapshots.
 #[derive(Debug, Error)]
@@ -46,12 +55,22 @@ pub struct SessionSnapshot {
     pub checkpoint_epoch: u64,
     pub delta_floor: crate::core::StateVector,
     pub document: DocumentDto,
-    /// Applied ops `(OpId, payload bytes)` for retransmission / audit.
-    pub ops: Vec<(OpId, Vec<u8>)>,
+    /// Applied ops `(OpId, payload bytes)` for retransmission / audit; the
+    /// payloads are shared with the session's op log.
+    #[serde(with = "compact_log")]
+    pub ops: Vec<(OpId, Arc<[u8]>)>,
     /// Causally buffered ops not yet in the applied log.
-    pub pending: Vec<(OpId, Vec<u8>)>,
+    #[serde(with = "compact_log")]
+    pub pending: Vec<(OpId, Arc<[u8]>)>,
     /// Applied operations waiting for an observed cross-peer frontier.
-    pub deferred: Vec<(OpId, Vec<u8>)>,
+    #[serde(with = "compact_log")]
+    pub deferred: Vec<(OpId, Arc<[u8]>)>,
+    /// Concurrent writes of registers under a resolution policy other than LWW.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registers: Vec<(
+        crate::doc::RegisterKey,
+        crate::core::MultiValueRegister<Option<String>>,
+    )>,
 }
 
 /// Serializable document: ordered sequence elements (incl. tombstones).
@@ -59,7 +78,19 @@ pub struct SessionSnapshot {
 pub struct DocumentDto {
     pub frontmatter: Option<Frontmatter>,
     pub blocks: SequenceDto<BlockDto>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) acls: BTreeMap<BlockId, AclEntry>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) code_lines: BTreeMap<BlockId, CodeLines>,
     pub(crate) source: Option<DocumentSource>,
+    /// Absent from documents written before rules were pinned, which
+    /// segmented as the default rules do.
+    #[serde(default, skip_serializing_if = "is_default_segmentation")]
+    pub(crate) segmentation: SegmentationRules,
+}
+
+fn is_default_segmentation(rules: &SegmentationRules) -> bool {
+    *rules == SegmentationRules::default()
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -72,8 +103,12 @@ pub struct ElementDto<T> {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SequenceDto<T> {
+    #[serde(with = "compact_elements")]
     pub elements: Vec<ElementDto<T>>,
     pub pending: Vec<SequenceOpDto<T>>,
+    /// See [`Sequence::compacted`].
+    #[serde(default, skip_serializing_if = "StateVector::is_empty")]
+    pub compacted: StateVector,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -88,6 +123,12 @@ pub enum SequenceOpDto<T> {
         target: OpId,
         id: OpId,
     },
+    DeleteRange {
+        from: OpId,
+        to: OpId,
+        id: OpId,
+        observed: crate::core::StateVector,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -196,7 +237,10 @@ impl DocumentDto {
         Self {
             frontmatter: doc.frontmatter.clone(),
             blocks: sequence_to_dto(doc.blocks(), block_to_dto),
+            acls: doc.acl_entries().clone(),
+            code_lines: doc.code_line_entries().clone(),
             source: doc.source_state(),
+            segmentation: doc.segmentation(),
         }
     }
 
@@ -204,11 +248,163 @@ impl DocumentDto {
         let mut doc = Document::new();
         doc.frontmatter = self.frontmatter;
         *doc.blocks_mut() = sequence_from_dto(self.blocks, block_from_dto);
+        doc.set_acl_entries(self.acls);
+        doc.set_code_line_entries(self.code_lines);
         doc.set_source_state(self.source);
+        doc.set_segmentation(self.segmentation);
         doc
     }
 }
 
+/// Standalone JSON form of a [`Document`], tagged with the snapshot schema
+/// version its body follows.
+#[derive(Serialize, Deserialize)]
+struct VersionedDocument {
+    format_version: u16,
+    document: DocumentDto,
+}
+
+impl Serialize for Document {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        VersionedDocument {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            document: DocumentDto::from_document(self),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = VersionedDocument::deserialize(deserializer)?;
+        if value.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(serde::de::Error::custom(
+                SnapshotError::ReinitializeRequired {
+                    found: value.format_version,
+                    expected: SNAPSHOT_FORMAT_VERSION,
+                },
+            ));
+        }
+        Ok(value.document.into_document())
+    }
+}
+
+/// [`Document::to_debug_json`] form: the snapshot schema with every id spelled
+/// out, plus the state the snapshot leaves out.
+#[derive(Serialize, Deserialize)]
+struct DebugDocument {
+    format_version: u16,
+    /// Highest counter per peer among the document's ids. Informational; not read back.
+    state_vector: StateVector,
+    document: DocumentDto,
+    /// Edits parked for blocks that have not arrived, in arrival order per block.
+    pending_edits: Vec<ParkedEdit>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParkedEdit {
+    block_id: BlockId,
+    edit: EditOp,
+    validate_grapheme_boundaries: bool,
+}
+
+impl Document {
+    /// Pretty JSON of the whole CRDT state: every sequence element and tombstone
+    /// with its origins, buffered sequence operations and parked edits, marks and
+    /// their removals, registers, and the frontier of ids the document holds.
+    ///
+    /// Meant for bug reports and tests. Ids are written as `{counter, peer}`
+    /// objects rather than the compact snapshot encoding, so the file can be
+    /// read and edited by hand; [`Self::from_debug_json`] loads it back.
+    pub fn to_debug_json(&self) -> Result<String, SnapshotError> {
+        let debug = DebugDocument {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            state_vector: document_frontier(self),
+            document: DocumentDto::from_document(self),
+            pending_edits: self
+                .pending_edit_entries()
+                .iter()
+                .flat_map(|(block_id, edits)| {
+                    edits
+                        .iter()
+                        .map(|(edit, validate_grapheme_boundaries)| ParkedEdit {
+                            block_id: *block_id,
+                            edit: edit.clone(),
+                            validate_grapheme_boundaries: *validate_grapheme_boundaries,
+                        })
+                })
+                .collect(),
+        };
+        compact_elements::plain(|| serde_json::to_string_pretty(&debug))
+            .map_err(|e| SnapshotError::Serde(e.to_string()))
+    }
+
+    /// Load a document written by [`Self::to_debug_json`] under the same
+    /// [`SNAPSHOT_FORMAT_VERSION`].
+    pub fn from_debug_json(json: &str) -> Result<Document, SnapshotError> {
+        let debug: DebugDocument = compact_elements::plain(|| serde_json::from_str(json))
+            .map_err(|e| SnapshotError::Serde(e.to_string()))?;
+        if debug.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(SnapshotError::ReinitializeRequired {
+                found: debug.format_version,
+                expected: SNAPSHOT_FORMAT_VERSION,
+            });
+        }
+        let mut document = debug.document.into_document();
+        let mut pending_edits = PendingEdits::new();
+        for parked in debug.pending_edits {
+            pending_edits
+                .entry(parked.block_id)
+                .or_default()
+                .push((parked.edit, parked.validate_grapheme_boundaries));
+        }
+        document.set_pending_edit_entries(pending_edits);
+        Ok(document)
+    }
+}
+
+impl Serialize for Block {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        block_to_dto(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        BlockDto::deserialize(deserializer).map(block_from_dto)
+    }
+}
+
+impl Serialize for Table {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        table_to_dto(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Table {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        TableDto::deserialize(deserializer).map(table_from_dto)
+    }
+}
+
 impl SessionSnapshot {
     pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
         serde_json::to_vec(self).map_err(|e| SnapshotError::Serde(e.to_string()))
@@ -227,128 +423,234 @@ impl SessionSnapshot {
     }
 }
 
-/// Max counter for `peer` across applied ops and document element ids.
-pub fn max_counter_for_peer(peer: PeerId, ops: &[(OpId, Vec<u8>)], doc: &Document) -> u64 {
+/// Encoded size of a session snapshot and what its compact ids save.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Size of [`SessionSnapshot::to_bytes`].
+    pub snapshot_bytes: usize,
+    /// Ids written as `[peer_index, counter]` in sequences and op logs.
+    pub compact_ids: usize,
+    /// Peer table entries written alongside them.
+    pub peer_table_entries: usize,
+    /// Size the snapshot would have with those sequences and logs written with full ids.
+    pub full_id_bytes: usize,
+}
+
+impl StorageStats {
+    /// Bytes saved by the peer tables (`full_id_bytes - snapshot_bytes`).
+    pub fn bytes_saved(&self) -> usize {
+        self.full_id_bytes.saturating_sub(self.snapshot_bytes)
+    }
+}
+
+impl SessionSnapshot {
+    /// Measure the encoded snapshot against the same state written with full ids.
+    pub fn storage_stats(&self) -> Result<StorageStats, SnapshotError> {
+        let snapshot_bytes = self.to_bytes()?.len();
+        let mut measure = IdMeasure::default();
+        for log in [&self.ops, &self.pending, &self.deferred] {
+            measure.log(log)?;
+        }
+        measure.sequence(&self.document.blocks)?;
+        Ok(StorageStats {
+            snapshot_bytes,
+            compact_ids: measure.ids,
+            peer_table_entries: measure.peers,
+            full_id_bytes: snapshot_bytes.saturating_add_signed(measure.saved),
+        })
+    }
+}
+
+/// Running totals for [`SessionSnapshot::storage_stats`]; `saved` is the
+/// full-id encoding's size minus the compact one, per sequence or log.
+#[derive(Default)]
+struct IdMeasure {
+    ids: usize,
+    peers: usize,
+    saved: isize,
+}
+
+impl IdMeasure {
+    fn log(&mut self, log: &[(OpId, Arc<[u8]>)]) -> Result<(), SnapshotError> {
+        let compact = compact_log::compact(log);
+        self.ids += log.len();
+        self.peers += compact.0.len();
+        self.saved += json_len(&log)? as isize - json_len(&compact)? as isize;
+        Ok(())
+    }
+
+    fn sequence<T: Serialize + NestedSequences>(
+        &mut self,
+        sequence: &SequenceDto<T>,
+    ) -> Result<(), SnapshotError> {
+        let compact = compact_elements::compact(&sequence.elements);
+        self.ids += sequence
+            .elements
+            .iter()
+            .map(|element| {
+                1 + usize::from(element.after.is_some())
+                    + usize::from(element.right_origin.is_some())
+            })
+            .sum::<usize>();
+        self.peers += compact.0.len();
+        // Nested sequences are compact in both encodings and cancel out here.
+        self.saved += json_len(&sequence.elements)? as isize - json_len(&compact)? as isize;
+        for value in sequence
+            .elements
+            .iter()
+            .filter_map(|element| element.value.as_ref())
+        {
+            value.measure(self)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_len<T: Serialize + ?Sized>(value: &T) -> Result<usize, SnapshotError> {
+    serde_json::to_vec(value)
+        .map(|bytes| bytes.len())
+        .map_err(|e| SnapshotError::Serde(e.to_string()))
+}
+
+/// DTO values that hold further [`SequenceDto`]s.
+trait NestedSequences {
+    fn measure(&self, _measure: &mut IdMeasure) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+impl NestedSequences for TextUnitDto {}
+impl NestedSequences for TableColumnDto {}
+impl NestedSequences for TableRowDto {}
+
+impl NestedSequences for ListItemDto {
+    fn measure(&self, measure: &mut IdMeasure) -> Result<(), SnapshotError> {
+        measure.sequence(&self.children)
+    }
+}
+
+impl NestedSequences for BlockDto {
+    fn measure(&self, measure: &mut IdMeasure) -> Result<(), SnapshotError> {
+        match &self.kind {
+            BlockKindDto::Paragraph { units } | BlockKindDto::Heading { units, .. } => {
+                measure.sequence(units)
+            }
+            BlockKindDto::List { items, .. } => measure.sequence(items),
+            BlockKindDto::BlockQuote { children } => measure.sequence(children),
+            BlockKindDto::Table { table } => {
+                measure.sequence(&table.columns)?;
+                measure.sequence(&table.rows)
+            }
+            BlockKindDto::CodeFence { .. } | BlockKindDto::RawBlock { .. } => Ok(()),
+        }
+    }
+}
+
+/// Max counter for `peer` across applied ops and document element and text unit ids.
+pub fn max_counter_for_peer(peer: PeerId, ops: &[(OpId, Arc<[u8]>)], doc: &Document) -> u64 {
     let mut max = 0u64;
-    for (id, _) in ops {
+    let mut see = |id: OpId| {
         if id.peer == peer {
             max = max.max(id.counter);
         }
+    };
+    for (id, _) in ops {
+        see(*id);
     }
-    walk_block_seq_max_peer(peer, doc.blocks(), &mut max);
+    walk_block_seq_ids(doc.blocks(), &mut see);
     max
 }
 
-fn walk_block_seq_max_peer(peer: PeerId, seq: &Sequence<Block>, max: &mut u64) {
-    for elem in seq.iter_all() {
-        if elem.id.peer == peer {
-            *max = (*max).max(elem.id.counter);
+/// Highest counter per peer among the document's element, text unit, register and
+/// mark ids.
+fn document_frontier(doc: &Document) -> StateVector {
+    let mut frontier = StateVector::new();
+    walk_block_seq_ids(doc.blocks(), &mut |id: OpId| {
+        if id.counter > frontier.get(id.peer).unwrap_or(0) {
+            frontier.set(id.peer, id.counter);
         }
+    });
+    frontier
+}
+
+fn walk_block_seq_ids(seq: &Sequence<Block>, see: &mut impl FnMut(OpId)) {
+    for elem in seq.iter_all() {
+        see(elem.id);
         if let Some(block) = elem.value.as_ref() {
-            if block.kind_op.peer == peer {
-                *max = (*max).max(block.kind_op.counter);
-            }
-            walk_marks_max_peer(peer, &block.marks, max);
-            walk_kind_max_peer(peer, &block.kind, max);
+            see(block.kind_op);
+            walk_marks_ids(&block.marks, see);
+            walk_kind_ids(&block.kind, see);
         }
     }
 }
 
-fn walk_kind_max_peer(peer: PeerId, kind: &BlockKind, max: &mut u64) {
+fn walk_kind_ids(kind: &BlockKind, see: &mut impl FnMut(OpId)) {
     match kind {
-        BlockKind::BlockQuote { children } => walk_block_seq_max_peer(peer, children, max),
+        BlockKind::Paragraph { text } | BlockKind::Heading { text, .. } => {
+            for elem in text.iter_all() {
+                see(elem.id);
+            }
+        }
+        BlockKind::BlockQuote { children } => walk_block_seq_ids(children, see),
         BlockKind::List {
             items,
             pending_moves,
             ..
         } => {
             for element in items.iter_all() {
-                if element.id.peer == peer {
-                    *max = (*max).max(element.id.counter);
-                }
+                see(element.id);
                 if let Some(item) = element.value.as_ref() {
-                    if item.task_op.peer == peer {
-                        *max = (*max).max(item.task_op.counter);
-                    }
-                    walk_block_seq_max_peer(peer, &item.children, max);
+                    see(item.task_op);
+                    walk_block_seq_ids(&item.children, see);
                 }
             }
             for movement in pending_moves {
-                if movement.id.peer == peer {
-                    *max = (*max).max(movement.id.counter);
-                }
+                see(movement.id);
             }
         }
         BlockKind::Table { table } => {
-            if table.elem_id.peer == peer {
-                *max = (*max).max(table.elem_id.counter);
-            }
+            see(table.elem_id);
             for elem in table.columns.iter_all() {
-                if elem.id.peer == peer {
-                    *max = (*max).max(elem.id.counter);
-                }
+                see(elem.id);
                 if let Some(column) = elem.value.as_ref() {
-                    for id in [column.deleted.op_id(), column.alignment.op_id()] {
-                        if id.peer == peer {
-                            *max = (*max).max(id.counter);
-                        }
-                    }
+                    see(column.deleted.op_id());
+                    see(column.alignment.op_id());
                 }
             }
             for elem in table.rows.iter_all() {
-                if elem.id.peer == peer {
-                    *max = (*max).max(elem.id.counter);
-                }
+                see(elem.id);
                 if let Some(row) = elem.value.as_ref() {
-                    let id = row.deleted.op_id();
-                    if id.peer == peer {
-                        *max = (*max).max(id.counter);
-                    }
+                    see(row.deleted.op_id());
                 }
             }
             for cell in table.cells.values() {
-                let id = cell.op_id;
-                if id.peer == peer {
-                    *max = (*max).max(id.counter);
-                }
+                see(cell.op_id);
             }
             for movement in table
                 .pending_row_moves
                 .iter()
                 .chain(&table.pending_column_moves)
             {
-                if movement.id.peer == peer {
-                    *max = (*max).max(movement.id.counter);
-                }
+                see(movement.id);
             }
             for alignment in &table.pending_column_alignments {
-                if alignment.id.peer == peer {
-                    *max = (*max).max(alignment.id.counter);
-                }
+                see(alignment.id);
             }
         }
         _ => {}
     }
 }
 
-fn walk_marks_max_peer(peer: PeerId, marks: &MarkSet, max: &mut u64) {
+fn walk_marks_ids(marks: &MarkSet, see: &mut impl FnMut(OpId)) {
     for interval in marks.iter_all_intervals() {
-        for id in [interval.id, interval.op_id] {
-            if id.peer == peer {
-                *max = (*max).max(id.counter);
-            }
-        }
+        see(interval.id);
+        see(interval.op_id);
         for register in interval.attrs.values() {
-            let id = register.op_id();
-            if id.peer == peer {
-                *max = (*max).max(id.counter);
-            }
+            see(register.op_id());
         }
     }
     for (_, remove) in marks.iter_removes() {
-        if remove.op_id.peer == peer {
-            *max = (*max).max(remove.op_id.counter);
-        }
+        see(remove.op_id);
     }
 }
 
@@ -383,8 +685,20 @@ where
                     right_origin,
                 },
                 SequenceOp::Delete { target, id } => SequenceOpDto::Delete { target, id },
+                SequenceOp::DeleteRange {
+                    from,
+                    to,
+                    id,
+                    observed,
+                } => SequenceOpDto::DeleteRange {
+                    from,
+                    to,
+                    id,
+                    observed,
+                },
             })
             .collect(),
+        compacted: seq.compacted().clone(),
     }
 }
 
@@ -419,12 +733,23 @@ where
                 right_origin,
             },
             SequenceOpDto::Delete { target, id } => SequenceOp::Delete { target, id },
+            SequenceOpDto::DeleteRange {
+                from,
+                to,
+                id,
+                observed,
+            } => SequenceOp::DeleteRange {
+                from,
+                to,
+                id,
+                observed,
+            },
         })
         .collect();
-    Sequence::from_elements_and_pending(elements, pending)
+    Sequence::from_elements_and_pending(elements, pending).with_compacted(dto.compacted)
 }
 
-fn block_to_dto(block: &Block) -> BlockDto {
+pub(crate) fn block_to_dto(block: &Block) -> BlockDto {
     BlockDto {
         id: block.id,
         elem_id: block.elem_id,
@@ -643,3 +968,124 @@ fn row_from_dto(dto: TableRowDto) -> TableRow {
         placement_observed: dto.placement_observed,
     }
 }
+
+/// Compact form of [`SequenceDto::elements`]: `[peers, items]`, each item
+/// `[id, value, after, right_origin]` with `[peer_index, counter]` ids.
+mod compact_elements {
+    use super::ElementDto;
+    use crate::core::{CompactOpId, PeerTable};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+    use std::cell::Cell;
+
+    thread_local! {
+        /// Set while debug JSON is written or read; it spells every id out in full.
+        static PLAIN: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Run `f` with elements written and read as plain [`ElementDto`] lists.
+    pub(super) fn plain<R>(f: impl FnOnce() -> R) -> R {
+        struct Reset(bool);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                PLAIN.set(self.0);
+            }
+        }
+        let _reset = Reset(PLAIN.replace(true));
+        f()
+    }
+
+    type Item<T> = (
+        CompactOpId,
+        Option<T>,
+        Option<CompactOpId>,
+        Option<CompactOpId>,
+    );
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct CompactElements<T>(pub(super) Vec<u64>, Vec<Item<T>>);
+
+    pub(super) fn compact<T>(elements: &[ElementDto<T>]) -> CompactElements<&T> {
+        let mut table = PeerTable::new();
+        let items = elements
+            .iter()
+            .map(|element| {
+                (
+                    table.compact(element.id),
+                    element.value.as_ref(),
+                    element.after.map(|id| table.compact(id)),
+                    element.right_origin.map(|id| table.compact(id)),
+                )
+            })
+            .collect();
+        CompactElements(table.peers().to_vec(), items)
+    }
+
+    pub(super) fn serialize<S: Serializer, T: Serialize>(
+        elements: &[ElementDto<T>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if PLAIN.get() {
+            return elements.serialize(serializer);
+        }
+        compact(elements).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<ElementDto<T>>, D::Error> {
+        if PLAIN.get() {
+            return Vec::deserialize(deserializer);
+        }
+        let CompactElements(peers, items) = CompactElements::<T>::deserialize(deserializer)?;
+        let table = PeerTable::from_peers(peers).map_err(D::Error::custom)?;
+        let expand = |id| table.expand(id).map_err(D::Error::custom);
+        items
+            .into_iter()
+            .map(|(id, value, after, right_origin)| {
+                Ok(ElementDto {
+                    id: expand(id)?,
+                    value,
+                    after: after.map(expand).transpose()?,
+                    right_origin: right_origin.map(expand).transpose()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Compact form of an op log: `[peers, entries]`, each entry `[[peer_index, counter], payload]`.
+mod compact_log {
+    use crate::core::{CompactOpId, OpId, PeerTable};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct CompactLog<P>(pub(super) Vec<u64>, Vec<(CompactOpId, P)>);
+
+    type Log = Vec<(OpId, Arc<[u8]>)>;
+
+    pub(super) fn compact(log: &[(OpId, Arc<[u8]>)]) -> CompactLog<&[u8]> {
+        let mut table = PeerTable::new();
+        let entries = log
+            .iter()
+            .map(|(id, payload)| (table.compact(*id), &payload[..]))
+            .collect();
+        CompactLog(table.peers().to_vec(), entries)
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        log: &[(OpId, Arc<[u8]>)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        compact(log).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Log, D::Error> {
+        let CompactLog(peers, entries) = CompactLog::<Arc<[u8]>>::deserialize(deserializer)?;
+        let table = PeerTable::from_peers(peers).map_err(D::Error::custom)?;
+        entries
+            .into_iter()
+            .map(|(id, payload)| Ok((table.expand(id).map_err(D::Error::custom)?, payload)))
+            .collect()
+    }
+}
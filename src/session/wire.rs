@@ -2,18 +2,19 @@ use super::*;
 
 /// Counter span an op payload covers, for restoring pending ops. Falls back to 1 if the
 /// payload cannot be decoded (trusted local disk, N5).
-pub(super) fn span_of_payload(payload: &[u8]) -> u64 {
+pub(super) fn span_of_payload(payload: &[u8], rules: SegmentationRules) -> u64 {
     JsonOpCodec
         .decode(payload)
-        .map(|env| operation_extent(&env).1)
+        .map(|env| operation_extent(&env, rules).1)
         .unwrap_or(1)
 }
 
 pub(super) fn check_operation_id_is_max(
     op: &Operation,
     env: &Envelope,
+    rules: SegmentationRules,
 ) -> Result<(), SessionError> {
-    let (max, _span) = operation_extent(env);
+    let (max, _span) = operation_extent(env, rules);
     if op.id != max {
         return Err(SessionError::OperationIdMismatch);
     }
@@ -25,10 +26,10 @@ pub(super) fn check_operation_id_is_max(
 /// allocates, so it covers `[max.counter - span + 1, max.counter]`. For a well-formed
 /// op all embedded ids share the op's peer; foreign-peer ids are rejected separately by
 /// [`check_peer_consistency`].
-pub(super) fn operation_extent(env: &Envelope) -> (OpId, u64) {
+pub(super) fn operation_extent(env: &Envelope, rules: SegmentationRules) -> (OpId, u64) {
     match &env.body {
         OpBody::Doc(DocOp::InsertBlock { id, block, .. }) => {
-            let hi = max_counter_in_kind(&block.kind, *id);
+            let hi = max_counter_in_kind(&block.kind, *id, rules);
             let span = hi.saturating_sub(id.counter).saturating_add(1);
             (
                 OpId {
@@ -105,24 +106,34 @@ pub(super) fn operation_extent(env: &Envelope) -> (OpId, u64) {
             | DocOp::SetListItemTask { id, .. }
             | DocOp::SetCodeFence { id, .. }
             | DocOp::ConvertTextBlock { id, .. }
-            | DocOp::ReplaceRawBlock { id, .. },
+            | DocOp::ReplaceRawBlock { id, .. }
+            | DocOp::SetBlockAcl { id, .. }
+            | DocOp::InsertCodeLine { id, .. }
+            | DocOp::DeleteCodeLine { id, .. }
+            | DocOp::DeleteBlockRange { id, .. },
         ) => (*id, 1),
     }
 }
 
 /// Highest counter that `kind_from_skeleton` assigns when expanding `kind` under
 /// `parent`. A paragraph seeds units at `parent.counter + 1 ..= parent.counter + G`.
-pub(super) fn max_counter_in_kind(kind: &BlockKindSkeleton, parent: OpId) -> u64 {
+pub(super) fn max_counter_in_kind(
+    kind: &BlockKindSkeleton,
+    parent: OpId,
+    rules: SegmentationRules,
+) -> u64 {
     match kind {
         BlockKindSkeleton::Paragraph { text } | BlockKindSkeleton::Heading { text, .. } => {
-            parent.counter.saturating_add(grapheme_count(text) as u64)
+            parent.counter.saturating_add(rules.count(text) as u64)
         }
         BlockKindSkeleton::BlockQuote { children } => {
             let mut hi = parent.counter;
             for child in children {
-                hi = hi
-                    .max(child.id.counter)
-                    .max(max_counter_in_kind(&child.block.kind, child.id));
+                hi = hi.max(child.id.counter).max(max_counter_in_kind(
+                    &child.block.kind,
+                    child.id,
+                    rules,
+                ));
             }
             hi
         }
@@ -131,9 +142,11 @@ pub(super) fn max_counter_in_kind(kind: &BlockKindSkeleton, parent: OpId) -> u64
             for item in items {
                 hi = hi.max(item.id.counter).max(item.task_op.counter);
                 for child in &item.children {
-                    hi = hi
-                        .max(child.id.counter)
-                        .max(max_counter_in_kind(&child.block.kind, child.id));
+                    hi = hi.max(child.id.counter).max(max_counter_in_kind(
+                        &child.block.kind,
+                        child.id,
+                        rules,
+                    ));
                 }
             }
             hi
@@ -216,7 +229,11 @@ pub(super) fn check_peer_consistency(op: &Operation, env: &Envelope) -> Result<(
             | DocOp::SetListItemTask { id, .. }
             | DocOp::SetCodeFence { id, .. }
             | DocOp::ConvertTextBlock { id, .. }
-            | DocOp::ReplaceRawBlock { id, .. },
+            | DocOp::ReplaceRawBlock { id, .. }
+            | DocOp::SetBlockAcl { id, .. }
+            | DocOp::InsertCodeLine { id, .. }
+            | DocOp::DeleteCodeLine { id, .. }
+            | DocOp::DeleteBlockRange { id, .. },
         ) => {
             if id.peer != peer {
                 return Err(SessionError::PeerMismatch);
@@ -346,7 +363,11 @@ fn current_block_elem(document: &Document, block_id: BlockId, fallback: OpId) ->
     document.block_elem_id(block_id).unwrap_or(fallback)
 }
 
-pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Envelope) {
+/// Apply one decoded operation; returns the semantic conflict it resolved, if any.
+pub(super) fn apply_envelope_to_document(
+    document: &mut Document,
+    envelope: &Envelope,
+) -> Option<SemanticConflict> {
     match &envelope.body {
         OpBody::Doc(DocOp::InsertBlock {
             parent,
@@ -355,7 +376,7 @@ pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Env
             right_origin,
             block,
         }) => {
-            let value = block_from_skeleton(block, *id);
+            let value = block_from_skeleton(block, *id, document.segmentation());
             document.insert_block_at(*parent, *after, *id, value, *right_origin);
         }
         OpBody::Doc(DocOp::DeleteBlock { parent, target, id }) => {
@@ -375,6 +396,21 @@ pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Env
                 document.delete_block_at(*parent, *target, *id);
             }
         }
+        OpBody::Doc(DocOp::DeleteBlockRange {
+            parent,
+            from,
+            to,
+            id,
+            observed,
+        }) => {
+            document.delete_block_range_at(&BlockRangeDelete {
+                parent: *parent,
+                from: *from,
+                to: *to,
+                id: *id,
+                observed: observed.clone(),
+            });
+        }
         OpBody::Doc(DocOp::InsertText {
             block_elem,
             block_id,
@@ -448,8 +484,13 @@ pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Env
                 block.marks.remove_mark(*interval_id, observed.clone(), *id);
             });
         }
-        OpBody::Doc(DocOp::SetFrontmatterField { id, key, value }) => {
-            let _ = document.set_frontmatter_field(key.clone(), value.clone(), *id);
+        OpBody::Doc(DocOp::SetFrontmatterField {
+            id,
+            key,
+            value,
+            observed,
+        }) => {
+            let _ = document.merge_frontmatter_field(key.clone(), value.clone(), *id, observed);
         }
         OpBody::Doc(DocOp::InitializeFrontmatter { frontmatter, .. }) => {
             if document.frontmatter.is_none() {
@@ -514,6 +555,8 @@ pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Env
                     marks,
                 };
                 document.insert_block_at(*parent, Some(*target), *id, block, *right_origin);
+                let claims: Vec<OpId> = units.iter().map(|unit| unit.id).collect();
+                settle_split_claims(document, *parent, *id, &claims);
             }
         }
         OpBody::Doc(DocOp::MergeBlocks {
@@ -865,34 +908,150 @@ pub(super) fn apply_envelope_to_document(document: &mut Document, envelope: &Env
             let block_elem = current_block_elem(document, *block_id, *block_elem);
             document.replace_raw_block(block_elem, raw.clone(), *id, observed.clone());
         }
+        OpBody::Doc(DocOp::SetBlockAcl {
+            block_id,
+            acl,
+            id,
+            observed,
+            ..
+        }) => {
+            return document.apply_block_acl(*block_id, acl.clone(), *id, observed.clone());
+        }
+        OpBody::Doc(DocOp::InsertCodeLine {
+            block_id,
+            base,
+            after,
+            right_origin,
+            id,
+            line,
+            ..
+        }) => {
+            document.apply_code_line_op(&EditOp::InsertCodeLine {
+                block_id: *block_id,
+                base: *base,
+                after: *after,
+                right_origin: *right_origin,
+                line: line.clone(),
+                op_id: *id,
+            });
+        }
+        OpBody::Doc(DocOp::DeleteCodeLine {
+            block_id,
+            base,
+            target,
+            id,
+            ..
+        }) => {
+            document.apply_code_line_op(&EditOp::DeleteCodeLine {
+                block_id: *block_id,
+                base: *base,
+                target: *target,
+                op_id: *id,
+            });
+        }
+    }
+    None
+}
+
+/// Leave each unit the split at `split_elem` moved visible in one block only.
+///
+/// Concurrent splits of one block each move the suffix they saw, so a unit
+/// past both split points is claimed twice. It stays in whichever claimant
+/// comes last among `parent`'s children and is tombstoned in the others: the
+/// suffix after the later point ends up in the later block and every block
+/// keeps its stretch of text in order. Two splits at the same point leave the
+/// earlier block empty. Sibling order never changes once both blocks exist, so
+/// replicas settle alike whichever split they apply first.
+fn settle_split_claims(
+    document: &mut Document,
+    parent: Option<OpId>,
+    split_elem: OpId,
+    claims: &[OpId],
+) {
+    let claimed: std::collections::BTreeSet<OpId> = claims.iter().copied().collect();
+    let Some(children) = document.container_children(parent) else {
+        return;
+    };
+    let mut split_first = false;
+    let mut earlier: Vec<(OpId, Vec<OpId>)> = Vec::new();
+    let mut later: Vec<OpId> = Vec::new();
+    for element in children.iter_all() {
+        if element.id == split_elem {
+            split_first = true;
+            continue;
+        }
+        let Some(body) = element
+            .value
+            .as_ref()
+            .and_then(|b| crate::doc::block_text_seq(&b.kind))
+        else {
+            continue;
+        };
+        let shown: Vec<OpId> = body
+            .iter_all()
+            .filter(|unit| unit.value.is_some() && claimed.contains(&unit.id))
+            .map(|unit| unit.id)
+            .collect();
+        if shown.is_empty() {
+            continue;
+        }
+        if split_first {
+            later.extend(shown);
+        } else {
+            earlier.push((element.id, shown));
+        }
+    }
+    let tombstone = |block: &mut Block, units: &[OpId]| {
+        if let Some(body) = crate::doc::block_text_seq_mut(&mut block.kind) {
+            for &target in units {
+                body.apply(SequenceOp::Delete {
+                    target,
+                    id: split_elem,
+                });
+            }
+        }
+    };
+    for (elem, shown) in earlier {
+        document.with_block_mut(elem, |block| tombstone(block, &shown));
+    }
+    if !later.is_empty() {
+        document.with_block_mut(split_elem, |block| tombstone(block, &later));
     }
 }
 
-pub(super) fn block_from_skeleton(skel: &BlockSkeleton, elem_id: OpId) -> Block {
+pub(super) fn block_from_skeleton(
+    skel: &BlockSkeleton,
+    elem_id: OpId,
+    rules: SegmentationRules,
+) -> Block {
     Block {
         id: skel.block_id,
         elem_id,
         kind_op: elem_id,
         kind_observed: StateVector::new(),
-        kind: kind_from_skeleton(&skel.kind, elem_id),
+        kind: kind_from_skeleton(&skel.kind, elem_id, rules),
         marks: MarkSet::new(),
     }
 }
 
-pub(super) fn kind_from_skeleton(kind: &BlockKindSkeleton, parent_elem: OpId) -> BlockKind {
+pub(super) fn kind_from_skeleton(
+    kind: &BlockKindSkeleton,
+    parent_elem: OpId,
+    rules: SegmentationRules,
+) -> BlockKind {
     match kind {
         BlockKindSkeleton::Paragraph { text } => {
             // Deterministic unit ids after the block elem (same on every peer).
             let mut counter = parent_elem.counter.saturating_add(1);
             BlockKind::Paragraph {
-                text: units_from_str(text, &mut counter, parent_elem.peer),
+                text: units_from_str_with(text, &mut counter, parent_elem.peer, rules),
             }
         }
         BlockKindSkeleton::Heading { level, text } => {
             let mut counter = parent_elem.counter.saturating_add(1);
             BlockKind::Heading {
                 level: *level,
-                text: units_from_str(text, &mut counter, parent_elem.peer),
+                text: units_from_str_with(text, &mut counter, parent_elem.peer, rules),
             }
         }
         BlockKindSkeleton::List { style, items } => {
@@ -905,7 +1064,7 @@ pub(super) fn kind_from_skeleton(kind: &BlockKindSkeleton, parent_elem: OpId) ->
                         elem_id: child.id,
                         kind_op: child.id,
                         kind_observed: StateVector::new(),
-                        kind: kind_from_skeleton(&child.block.kind, child.id),
+                        kind: kind_from_skeleton(&child.block.kind, child.id, rules),
                         marks: MarkSet::new(),
                     };
                     child_seq.apply(SequenceOp::Insert {
@@ -951,7 +1110,7 @@ pub(super) fn kind_from_skeleton(kind: &BlockKindSkeleton, parent_elem: OpId) ->
                     elem_id: child.id,
                     kind_op: child.id,
                     kind_observed: StateVector::new(),
-                    kind: kind_from_skeleton(&child.block.kind, child.id),
+                    kind: kind_from_skeleton(&child.block.kind, child.id, rules),
                     marks: MarkSet::new(),
                 };
                 seq.apply(SequenceOp::Insert {
@@ -989,9 +1148,91 @@ pub(super) fn alignment_from_wire(alignment: ColumnAlignmentWire) -> ColumnAlign
     }
 }
 
+/// Blocks whose [`BlockAcl`](crate::doc::BlockAcl)s govern `envelope`, resolved
+/// against the current tree. `SetBlockAcl` is governed by the target's own ACL
+/// and is not listed.
+pub(super) fn acl_targets(document: &Document, envelope: &Envelope) -> Vec<BlockId> {
+    let block_of = |elem: OpId| document.find_block(elem).map(|block| block.id);
+    let container_of = |elem: &Option<OpId>| {
+        let elem = (*elem)?;
+        block_of(elem).or_else(|| {
+            let item = document.find_list_item(elem)?;
+            document.list_containing_item(item.id).map(|(list, _)| list)
+        })
+    };
+    let OpBody::Doc(op) = &envelope.body;
+    match op {
+        DocOp::InsertBlock { parent, .. } => container_of(parent).into_iter().collect(),
+        DocOp::DeleteBlock { target, .. } => block_of(*target).into_iter().collect(),
+        DocOp::DeleteBlockRange {
+            parent, observed, ..
+        } => document
+            .container_children(*parent)
+            .into_iter()
+            .flat_map(|children| children.iter_all())
+            .filter(|element| covers(observed, element.id))
+            .filter_map(|element| element.value.as_ref().map(|block| block.id))
+            .chain(container_of(parent))
+            .collect(),
+        DocOp::DeleteBlockById { block_id, .. }
+        | DocOp::InsertText { block_id, .. }
+        | DocOp::DeleteText { block_id, .. }
+        | DocOp::SetMark { block_id, .. }
+        | DocOp::RemoveMark { block_id, .. }
+        | DocOp::SetListStyle { block_id, .. }
+        | DocOp::SetCodeFence { block_id, .. }
+        | DocOp::ConvertTextBlock { block_id, .. }
+        | DocOp::ReplaceRawBlock { block_id, .. }
+        | DocOp::InsertCodeLine { block_id, .. }
+        | DocOp::DeleteCodeLine { block_id, .. } => vec![*block_id],
+        DocOp::MoveBlocks {
+            to_parent, blocks, ..
+        } => blocks
+            .iter()
+            .map(|moved| moved.block_id)
+            .chain(container_of(to_parent))
+            .collect(),
+        DocOp::SplitBlock { target, .. } => block_of(*target).into_iter().collect(),
+        DocOp::MergeBlocks { left, right, .. } => block_of(*left)
+            .into_iter()
+            .chain(block_of(*right))
+            .collect(),
+        DocOp::InsertTableRow { table_id, .. }
+        | DocOp::InsertTableColumn { table_id, .. }
+        | DocOp::SetTableCell { table_id, .. }
+        | DocOp::DeleteTableRow { table_id, .. }
+        | DocOp::DeleteTableRowById { table_id, .. }
+        | DocOp::DeleteTableColumnById { table_id, .. }
+        | DocOp::SetTableColumnAlignment { table_id, .. }
+        | DocOp::MoveTableRow { table_id, .. }
+        | DocOp::MoveTableColumn { table_id, .. } => vec![*table_id],
+        DocOp::InsertListItem { list_id, .. } | DocOp::DeleteListItemById { list_id, .. } => {
+            vec![*list_id]
+        }
+        DocOp::MoveListItem {
+            from_list_elem,
+            to_list_elem,
+            list_id,
+            ..
+        } => std::iter::once(*list_id)
+            .chain(block_of(*from_list_elem))
+            .chain(block_of(*to_list_elem))
+            .collect(),
+        DocOp::SetListItemTask { item_id, .. } => document
+            .list_containing_item(*item_id)
+            .map(|(list, _)| list)
+            .into_iter()
+            .collect(),
+        DocOp::SetFrontmatterField { .. }
+        | DocOp::InitializeFrontmatter { .. }
+        | DocOp::SetBlockAcl { .. } => Vec::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::doc::units_from_str;
 
     fn id(counter: u64) -> OpId {
         OpId { counter, peer: 1 }
@@ -999,10 +1240,11 @@ mod tests {
 
     #[test]
     fn span_helpers_handle_invalid_and_empty_payloads() {
-        assert_eq!(span_of_payload(b"not-json"), 1);
+        assert_eq!(span_of_payload(b"not-json", SegmentationRules::CURRENT), 1);
 
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(DocOp::InsertText {
                 block_elem: id(1),
                 block_id: block_id_from_op(id(1)),
@@ -1010,7 +1252,7 @@ mod tests {
             }),
         };
         assert_eq!(
-            operation_extent(&envelope),
+            operation_extent(&envelope, SegmentationRules::CURRENT),
             (
                 OpId {
                     counter: 0,
@@ -1020,7 +1262,7 @@ mod tests {
             )
         );
         let payload = JsonOpCodec.encode(&envelope).unwrap();
-        assert_eq!(span_of_payload(&payload), 1);
+        assert_eq!(span_of_payload(&payload, SegmentationRules::CURRENT), 1);
     }
 
     #[test]
@@ -1074,7 +1316,7 @@ mod tests {
             BlockKindSkeleton::BlockQuote { .. }
         ));
 
-        let restored = kind_from_skeleton(&skeleton, id(1));
+        let restored = kind_from_skeleton(&skeleton, id(1), SegmentationRules::CURRENT);
         let BlockKind::List { items, .. } = restored else {
             panic!("expected restored list");
         };
@@ -1091,7 +1333,7 @@ mod tests {
         let table_skeleton = block_kind_to_skeleton(&table, false).unwrap();
         assert!(matches!(&table_skeleton, BlockKindSkeleton::Table));
         assert!(matches!(
-            kind_from_skeleton(&table_skeleton, id(20)),
+            kind_from_skeleton(&table_skeleton, id(20), SegmentationRules::CURRENT),
             BlockKind::Table { .. }
         ));
 
@@ -1101,7 +1343,11 @@ mod tests {
             text: "fn main() {}".into(),
         };
         assert!(matches!(
-            kind_from_skeleton(&block_kind_to_skeleton(&code, false).unwrap(), id(30)),
+            kind_from_skeleton(
+                &block_kind_to_skeleton(&code, false).unwrap(),
+                id(30),
+                SegmentationRules::CURRENT
+            ),
             BlockKind::CodeFence { .. }
         ));
 
@@ -0,0 +1,304 @@
+//! Tamper-evident snapshot history.
+//!
+//! A chained storage keeps one [`ChainLink`] per published snapshot: the
+//! SHA-256 of the snapshot, the state vector it covers, and the hash of the
+//! link before it. [`Storage::verify_chain`] walks the links and compares the
+//! newest one with the snapshot on disk, so replacing a snapshot, editing or
+//! reordering a link, or recording a frontier that drops history breaks the
+//! chain.
+//!
+//! Chaining is opt-in: the first [`Storage::record_snapshot_link`] starts it,
+//! and every later `write_snapshot` or `compact` must be followed by another
+//! link. [`CollaborativeDocument::write_to_storage`](crate::CollaborativeDocument::write_to_storage)
+//! does this itself for chained storage. Whoever can rewrite the whole chain
+//! file can also forge a consistent one, so audits should keep the head hash
+//! returned by `verify_chain` somewhere the storage owner cannot change.
+
+use super::{Storage, StorageError, atomic_write_durable, checksum_bytes};
+use crate::core::{PeerId, StateVector};
+use rkyv::{Archive, Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CHAIN_FILE: &str = "chain.bin";
+
+/// SHA-256 digest of a snapshot or a link.
+pub type ChainHash = [u8; 32];
+
+/// One snapshot recorded in the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainLink {
+    /// Position in the chain, from 0.
+    pub index: u64,
+    /// [`ChainLink::hash`] of the previous link; zero for the first.
+    pub previous: ChainHash,
+    /// Digest of the snapshot payload, pending operations and flags.
+    pub snapshot: ChainHash,
+    /// Frontier the snapshot covers.
+    pub state_vector: StateVector,
+    /// Milliseconds since the Unix epoch when the link was recorded.
+    pub recorded_at_ms: u64,
+}
+
+impl ChainLink {
+    /// Digest of every field, which the next link records as `previous`.
+    pub fn hash(&self) -> ChainHash {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.previous);
+        bytes.extend_from_slice(&self.snapshot);
+        for (peer, counter) in self.state_vector.iter() {
+            bytes.extend_from_slice(&peer.to_le_bytes());
+            bytes.extend_from_slice(&counter.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.recorded_at_ms.to_le_bytes());
+        sha256(&bytes)
+    }
+}
+
+#[derive(Debug, Archive, Serialize, Deserialize)]
+struct LinkRecord {
+    index: u64,
+    previous: ChainHash,
+    snapshot: ChainHash,
+    frontier: Vec<(PeerId, u64)>,
+    recorded_at_ms: u64,
+}
+
+impl Storage {
+    /// Whether a snapshot chain has been started.
+    pub fn has_chain(&self) -> bool {
+        self.root.join(CHAIN_FILE).exists()
+    }
+
+    /// Append a link for the current snapshot, which covers `state_vector`.
+    pub fn record_snapshot_link(
+        &self,
+        state_vector: &StateVector,
+    ) -> Result<ChainLink, StorageError> {
+        let mut links = self.chain()?;
+        let link = ChainLink {
+            index: links.len() as u64,
+            previous: links.last().map_or([0; 32], ChainLink::hash),
+            snapshot: self.snapshot_hash()?,
+            state_vector: state_vector.clone(),
+            recorded_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        };
+        links.push(link.clone());
+        self.write_chain(&links)?;
+        Ok(link)
+    }
+
+    /// Every link, oldest first; empty when the storage is not chained.
+    pub fn chain(&self) -> Result<Vec<ChainLink>, StorageError> {
+        match fs::read(self.root.join(CHAIN_FILE)) {
+            Ok(bytes) => decode_chain(&bytes),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(error) => Err(StorageError::Io(error)),
+        }
+    }
+
+    /// Check that every link follows from the one before it and that the newest
+    /// describes the current snapshot. Returns the head link's hash, or `None`
+    /// when the storage is not chained.
+    pub fn verify_chain(&self) -> Result<Option<ChainHash>, StorageError> {
+        let links = self.chain()?;
+        let mut previous: Option<&ChainLink> = None;
+        for (position, link) in links.iter().enumerate() {
+            let broken = |reason| StorageError::ChainBroken {
+                index: position as u64,
+                reason,
+            };
+            if link.index != position as u64 {
+                return Err(broken("index out of sequence"));
+            }
+            if link.previous != previous.map_or([0; 32], ChainLink::hash) {
+                return Err(broken("previous hash mismatch"));
+            }
+            if let Some(previous) = previous {
+                let regressed = previous
+                    .state_vector
+                    .iter()
+                    .any(|(peer, counter)| link.state_vector.get(peer).unwrap_or(0) < counter);
+                if regressed {
+                    return Err(broken("state vector regressed"));
+                }
+            }
+            previous = Some(link);
+        }
+        let Some(head) = previous else {
+            return Ok(None);
+        };
+        if head.snapshot != self.snapshot_hash()? {
+            return Err(StorageError::ChainBroken {
+                index: head.index,
+                reason: "snapshot does not match the chain head",
+            });
+        }
+        Ok(Some(head.hash()))
+    }
+
+    fn snapshot_hash(&self) -> Result<ChainHash, StorageError> {
+        let (payload, pending_ops, seq_ref_index_flag) = self.read_snapshot()?;
+        let mut bytes = Vec::with_capacity(payload.len() + pending_ops.len() + 17);
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&(pending_ops.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&pending_ops);
+        bytes.push(u8::from(seq_ref_index_flag));
+        Ok(sha256(&bytes))
+    }
+
+    fn write_chain(&self, links: &[ChainLink]) -> Result<(), StorageError> {
+        let records: Vec<LinkRecord> = links
+            .iter()
+            .map(|link| LinkRecord {
+                index: link.index,
+                previous: link.previous,
+                snapshot: link.snapshot,
+                frontier: link.state_vector.iter().collect(),
+                recorded_at_ms: link.recorded_at_ms,
+            })
+            .collect();
+        let body = rkyv::to_bytes::<rkyv::rancor::Error>(&records)
+            .map_err(|_| StorageError::Corrupt("encode"))?;
+        let mut bytes = body.to_vec();
+        bytes.extend_from_slice(&checksum_bytes(&body).to_le_bytes());
+        atomic_write_durable(&self.root, CHAIN_FILE, &bytes)
+    }
+}
+
+fn decode_chain(bytes: &[u8]) -> Result<Vec<ChainLink>, StorageError> {
+    if bytes.len() < 4 {
+        return Err(StorageError::Corrupt("chain checksum"));
+    }
+    let (body, trailer) = bytes.split_at(bytes.len() - 4);
+    let stored_checksum = u32::from_le_bytes(
+        trailer
+            .try_into()
+            .map_err(|_| StorageError::Corrupt("chain checksum"))?,
+    );
+    if checksum_bytes(body) != stored_checksum {
+        return Err(StorageError::Corrupt("chain checksum"));
+    }
+    let mut aligned = rkyv::util::AlignedVec::<16>::new();
+    aligned.extend_from_slice(body);
+    let records = rkyv::from_bytes::<Vec<LinkRecord>, rkyv::rancor::Error>(&aligned)
+        .map_err(|_| StorageError::Corrupt("decode"))?;
+    Ok(records
+        .into_iter()
+        .map(|record| {
+            let mut state_vector = StateVector::new();
+            for (peer, counter) in record.frontier {
+                state_vector.set(peer, counter);
+            }
+            ChainLink {
+                index: record.index,
+                previous: record.previous,
+                snapshot: record.snapshot,
+                state_vector,
+                recorded_at_ms: record.recorded_at_ms,
+            }
+        })
+        .collect())
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// FIPS 180-4 SHA-256.
+fn sha256(data: &[u8]) -> ChainHash {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().expect("4 bytes"));
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sha256;
+
+    fn hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn sha256_matches_published_vectors() {
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Zero-copy archived form of a [`Document`].
+//!
+//! [`encode_document`] writes an rkyv image holding the top-level blocks in
+//! order, each with its rendered Markdown, plus both document renderings. A
+//! reader validates the bytes with [`access_document`] and queries the
+//! resulting [`ArchivedDocument`] in place, so opening a large vault from a
+//! memory-mapped file costs one validation pass instead of rebuilding every
+//! sequence. [`ArchivedDocument::to_document`] restores the full CRDT state
+//! when the document is about to be edited.
+//!
+//! Buffers must be aligned for rkyv; a memory-mapped file (page aligned) or the
+//! [`AlignedVec`] returned by [`encode_document`] satisfies this.
+
+use super::StorageError;
+use crate::core::OpId;
+use crate::doc::{BlockId, BlockKind, Document, EquivalenceMode, serialize_block};
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+
+/// Archive layout version, checked by [`access_document`].
+pub const DOCUMENT_ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Archive, Serialize, Deserialize, Clone)]
+#[rkyv(archived = ArchivedDocument)]
+pub struct DocumentArchive {
+    version: u32,
+    blocks: Vec<BlockRecord>,
+    exact: String,
+    structural: String,
+    /// Serde JSON of the whole document, decoded only by `to_document`.
+    document: Vec<u8>,
+}
+
+/// Read-only view of one top-level block.
+#[derive(Debug, Archive, Serialize, Deserialize, Clone)]
+pub struct BlockRecord {
+    id: [u8; 16],
+    elem_counter: u64,
+    elem_peer: u64,
+    kind: BlockKindTag,
+    markdown: String,
+}
+
+/// Block kind without its contents.
+#[derive(Debug, Archive, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKindTag {
+    Paragraph,
+    Heading { level: u8 },
+    List,
+    CodeFence,
+    BlockQuote,
+    RawBlock,
+    Table,
+}
+
+impl From<&BlockKind> for BlockKindTag {
+    fn from(kind: &BlockKind) -> Self {
+        match kind {
+            BlockKind::Paragraph { .. } => Self::Paragraph,
+            BlockKind::Heading { level, .. } => Self::Heading { level: *level },
+            BlockKind::List { .. } => Self::List,
+            BlockKind::CodeFence { .. } => Self::CodeFence,
+            BlockKind::BlockQuote { .. } => Self::BlockQuote,
+            BlockKind::RawBlock { .. } => Self::RawBlock,
+            BlockKind::Table { .. } => Self::Table,
+        }
+    }
+}
+
+impl DocumentArchive {
+    pub fn from_document(doc: &Document) -> Result<Self, StorageError> {
+        let blocks = doc
+            .blocks_in_order()
+            .into_iter()
+            .map(|block| BlockRecord {
+                id: block.id.into_bytes(),
+                elem_counter: block.elem_id.counter,
+                elem_peer: block.elem_id.peer,
+                kind: BlockKindTag::from(&block.kind),
+                markdown: serialize_block(block),
+            })
+            .collect();
+        Ok(Self {
+            version: DOCUMENT_ARCHIVE_VERSION,
+            blocks,
+            exact: doc.serialize(EquivalenceMode::Exact),
+            structural: doc.serialize(EquivalenceMode::Structural),
+            document: serde_json::to_vec(doc).map_err(|_| StorageError::Corrupt("encode"))?,
+        })
+    }
+}
+
+/// Encode `doc` as an rkyv image readable through [`access_document`].
+pub fn encode_document(doc: &Document) -> Result<AlignedVec, StorageError> {
+    let archive = DocumentArchive::from_document(doc)?;
+    rkyv::to_bytes::<rkyv::rancor::Error>(&archive).map_err(|_| StorageError::Corrupt("encode"))
+}
+
+/// Validate `bytes` and view them as an [`ArchivedDocument`] without copying.
+pub fn access_document(bytes: &[u8]) -> Result<&ArchivedDocument, StorageError> {
+    let archived = rkyv::access::<ArchivedDocument, rkyv::rancor::Error>(bytes)
+        .map_err(|_| StorageError::Corrupt("decode"))?;
+    let version: u32 = archived.version.into();
+    if version != DOCUMENT_ARCHIVE_VERSION {
+        return Err(StorageError::ReinitializeRequired {
+            found: Some(version),
+            expected: DOCUMENT_ARCHIVE_VERSION,
+        });
+    }
+    Ok(archived)
+}
+
+impl ArchivedDocument {
+    /// Visible top-level blocks in document order.
+    pub fn blocks_in_order(&self) -> &[ArchivedBlockRecord] {
+        &self.blocks
+    }
+
+    /// Same output as [`Document::serialize`] at the time of encoding.
+    pub fn serialize(&self, mode: EquivalenceMode) -> &str {
+        match mode {
+            EquivalenceMode::Exact => &self.exact,
+            EquivalenceMode::Structural => &self.structural,
+        }
+    }
+
+    /// Decode the full document, including tombstones and pending operations.
+    pub fn to_document(&self) -> Result<Document, StorageError> {
+        serde_json::from_slice(&self.document).map_err(|_| StorageError::Corrupt("decode"))
+    }
+}
+
+impl ArchivedBlockRecord {
+    pub fn id(&self) -> BlockId {
+        BlockId::from_bytes(self.id)
+    }
+
+    pub fn elem_id(&self) -> OpId {
+        OpId {
+            counter: self.elem_counter.into(),
+            peer: self.elem_peer.into(),
+        }
+    }
+
+    pub fn kind(&self) -> BlockKindTag {
+        match self.kind {
+            ArchivedBlockKindTag::Paragraph => BlockKindTag::Paragraph,
+            ArchivedBlockKindTag::Heading { level } => BlockKindTag::Heading { level },
+            ArchivedBlockKindTag::List => BlockKindTag::List,
+            ArchivedBlockKindTag::CodeFence => BlockKindTag::CodeFence,
+            ArchivedBlockKindTag::BlockQuote => BlockKindTag::BlockQuote,
+            ArchivedBlockKindTag::RawBlock => BlockKindTag::RawBlock,
+            ArchivedBlockKindTag::Table => BlockKindTag::Table,
+        }
+    }
+
+    /// The block rendered from its CRDT state, without source trivia.
+    pub fn markdown(&self) -> &str {
+        &self.markdown
+    }
+}
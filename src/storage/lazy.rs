@@ -0,0 +1,365 @@
+//! Lazily loaded documents.
+//!
+//! [`Storage::write_block_index`] stores a document as two files under
+//! `blocks/`: a body file holding each visible top-level block's JSON back to
+//! back, and a checksummed index recording where each body starts plus the
+//! block outline (id, kind and heading text) and the rest of the document
+//! with every block body left out. [`Document::open_lazy`] reads only the
+//! index, so a note with thousands of blocks opens in one small read;
+//! [`LazyDocument`] reads a block's body the first time it is asked for or
+//! edited, and [`LazyDocument::into_document`] reads the rest.
+
+use super::{
+    BlockKindTag, Storage, StorageError, atomic_write_durable, checksum_bytes, decode_op_segment,
+    encode_op_segment,
+};
+use crate::core::OpId;
+use crate::doc::{
+    Block, BlockId, BlockKind, Document, EditError, EditOp, paragraph_visible_string,
+};
+use crate::session::snapshot::block_to_dto;
+use crate::session::{DocumentDto, SNAPSHOT_FORMAT_VERSION};
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const BLOCKS_DIR: &str = "blocks";
+const INDEX_FILE: &str = "index";
+const BODIES_FILE: &str = "bodies";
+
+/// Block index layout version, checked by [`Document::open_lazy`].
+pub const BLOCK_INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Archive, Serialize, Deserialize)]
+struct BlockIndex {
+    version: u32,
+    /// [`SNAPSHOT_FORMAT_VERSION`] of `shell`.
+    snapshot_format: u16,
+    /// JSON [`DocumentDto`] whose top-level block values are all left out.
+    shell: Vec<u8>,
+    entries: Vec<BlockIndexEntry>,
+}
+
+#[derive(Debug, Archive, Serialize, Deserialize)]
+struct BlockIndexEntry {
+    id: [u8; 16],
+    elem_counter: u64,
+    elem_peer: u64,
+    kind: BlockKindTag,
+    heading: Option<String>,
+    offset: u64,
+    len: u64,
+    checksum: u32,
+}
+
+/// A top-level block as known before its body is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockOutline {
+    pub id: BlockId,
+    pub elem_id: OpId,
+    pub kind: BlockKindTag,
+    /// Visible text of a heading; `None` for other kinds.
+    pub heading: Option<String>,
+}
+
+impl BlockOutline {
+    fn of(block: &Block) -> Self {
+        Self {
+            id: block.id,
+            elem_id: block.elem_id,
+            kind: BlockKindTag::from(&block.kind),
+            heading: match &block.kind {
+                BlockKind::Heading { text, .. } => Some(paragraph_visible_string(text)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Where a block's body lies in the body file.
+#[derive(Debug, Clone, Copy)]
+struct BodySpan {
+    offset: u64,
+    len: u64,
+    checksum: u32,
+}
+
+/// Errors editing a [`LazyDocument`].
+#[derive(Debug, thiserror::Error)]
+pub enum LazyEditError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Edit(#[from] EditError),
+}
+
+impl Storage {
+    /// Write `doc` for [`Document::open_lazy`], replacing any earlier index.
+    pub fn write_block_index(&self, doc: &Document) -> Result<(), StorageError> {
+        let encode_error = |_| StorageError::Corrupt("encode");
+        let mut bodies = Vec::new();
+        let mut entries = Vec::new();
+        for block in doc.blocks_in_order() {
+            let body = serde_json::to_vec(block).map_err(encode_error)?;
+            let outline = BlockOutline::of(block);
+            entries.push(BlockIndexEntry {
+                id: outline.id.into_bytes(),
+                elem_counter: outline.elem_id.counter,
+                elem_peer: outline.elem_id.peer,
+                kind: outline.kind,
+                heading: outline.heading,
+                offset: bodies.len() as u64,
+                len: body.len() as u64,
+                checksum: checksum_bytes(&body),
+            });
+            bodies.extend_from_slice(&body);
+        }
+        let mut shell = DocumentDto::from_document(doc);
+        for element in &mut shell.blocks.elements {
+            element.value = None;
+        }
+        let index = BlockIndex {
+            version: BLOCK_INDEX_VERSION,
+            snapshot_format: SNAPSHOT_FORMAT_VERSION,
+            shell: serde_json::to_vec(&shell).map_err(encode_error)?,
+            entries,
+        };
+        let index = rkyv::to_bytes::<rkyv::rancor::Error>(&index)
+            .map_err(|_| StorageError::Corrupt("encode"))?;
+
+        let dir = self.root.join(BLOCKS_DIR);
+        fs::create_dir_all(&dir)?;
+        // Bodies first: an index never points into a body file older than itself.
+        atomic_write_durable(&dir, BODIES_FILE, &bodies)?;
+        atomic_write_durable(&dir, INDEX_FILE, &encode_op_segment(&index))
+    }
+}
+
+impl Document {
+    /// Open the document last written by [`Storage::write_block_index`],
+    /// reading its block outline now and block bodies on demand.
+    pub fn open_lazy(storage: &Storage) -> Result<LazyDocument, StorageError> {
+        let dir = storage.root.join(BLOCKS_DIR);
+        let path = dir.join(INDEX_FILE);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StorageError::Missing);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let payload = decode_op_segment(&path, &bytes)?;
+        let mut aligned = AlignedVec::<16>::with_capacity(payload.len());
+        aligned.extend_from_slice(&payload);
+        let index = rkyv::from_bytes::<BlockIndex, rkyv::rancor::Error>(&aligned)
+            .map_err(|_| StorageError::Corrupt("decode"))?;
+        if index.version != BLOCK_INDEX_VERSION {
+            return Err(StorageError::ReinitializeRequired {
+                found: Some(index.version),
+                expected: BLOCK_INDEX_VERSION,
+            });
+        }
+        if index.snapshot_format != SNAPSHOT_FORMAT_VERSION {
+            return Err(StorageError::ReinitializeRequired {
+                found: Some(u32::from(index.snapshot_format)),
+                expected: u32::from(SNAPSHOT_FORMAT_VERSION),
+            });
+        }
+        let shell: DocumentDto =
+            serde_json::from_slice(&index.shell).map_err(|_| StorageError::Corrupt("decode"))?;
+
+        let mut blocks = Vec::with_capacity(index.entries.len());
+        let mut spans = BTreeMap::new();
+        let mut positions = BTreeMap::new();
+        for entry in index.entries {
+            let outline = BlockOutline {
+                id: BlockId::from_bytes(entry.id),
+                elem_id: OpId {
+                    counter: entry.elem_counter,
+                    peer: entry.elem_peer,
+                },
+                kind: entry.kind,
+                heading: entry.heading,
+            };
+            spans.insert(
+                outline.elem_id,
+                BodySpan {
+                    offset: entry.offset,
+                    len: entry.len,
+                    checksum: entry.checksum,
+                },
+            );
+            positions.insert(outline.id, blocks.len());
+            blocks.push(outline);
+        }
+        Ok(LazyDocument {
+            bodies: dir.join(BODIES_FILE),
+            shell,
+            blocks,
+            positions,
+            spans,
+            hydrated: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+        })
+    }
+}
+
+/// A stored document whose top-level block bodies load on first use.
+///
+/// Reading or editing a block loads that block alone; blocks never touched stay
+/// on disk until [`Self::into_document`].
+#[derive(Debug)]
+pub struct LazyDocument {
+    bodies: PathBuf,
+    shell: DocumentDto,
+    blocks: Vec<BlockOutline>,
+    positions: BTreeMap<BlockId, usize>,
+    spans: BTreeMap<OpId, BodySpan>,
+    hydrated: BTreeMap<OpId, Block>,
+    /// Blocks edited since opening, re-rendered instead of copied from source.
+    dirty: BTreeSet<OpId>,
+}
+
+impl LazyDocument {
+    /// Visible top-level blocks in document order.
+    pub fn blocks(&self) -> &[BlockOutline] {
+        &self.blocks
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Whether the body of `block_id` has been loaded.
+    pub fn is_hydrated(&self, block_id: BlockId) -> bool {
+        self.outline(block_id)
+            .is_some_and(|outline| self.hydrated.contains_key(&outline.elem_id))
+    }
+
+    /// Number of blocks whose bodies have been loaded.
+    pub fn hydrated_len(&self) -> usize {
+        self.hydrated.len()
+    }
+
+    /// The top-level block `block_id`, loading its body if needed.
+    pub fn block(&mut self, block_id: BlockId) -> Result<Option<&Block>, StorageError> {
+        let Some(elem_id) = self.outline(block_id).map(|outline| outline.elem_id) else {
+            return Ok(None);
+        };
+        self.hydrate(elem_id).map(|block| Some(&*block))
+    }
+
+    /// Mutate the top-level block `block_id`, loading only that block.
+    ///
+    /// Returns `None` if there is no such top-level block.
+    pub fn with_block_mut<R>(
+        &mut self,
+        block_id: BlockId,
+        f: impl FnOnce(&mut Block) -> R,
+    ) -> Result<Option<R>, StorageError> {
+        let Some(&position) = self.positions.get(&block_id) else {
+            return Ok(None);
+        };
+        let elem_id = self.blocks[position].elem_id;
+        let block = self.hydrate(elem_id)?;
+        let result = f(block);
+        let outline = BlockOutline::of(block);
+        self.blocks[position] = outline;
+        self.dirty.insert(elem_id);
+        Ok(Some(result))
+    }
+
+    /// [`Document::insert_text`] on a top-level block, loading only that block.
+    pub fn insert_text(
+        &mut self,
+        block_id: BlockId,
+        grapheme_offset: usize,
+        text: &str,
+        op_id: OpId,
+    ) -> Result<Vec<EditOp>, LazyEditError> {
+        let locked = self
+            .shell
+            .acls
+            .get(&block_id)
+            .and_then(|entry| entry.acl.as_ref())
+            .is_some_and(|acl| !acl.permits_edit(op_id.peer));
+        if locked {
+            return Err(EditError::BlockLocked {
+                block_id,
+                peer: op_id.peer,
+            }
+            .into());
+        }
+        let rules = self.shell.segmentation;
+        let run = self
+            .with_block_mut(block_id, |block| {
+                block.insert_text(grapheme_offset, text, op_id, rules)
+            })?
+            .ok_or(EditError::BlockNotFound { block_id })??;
+        Ok(vec![EditOp::InsertText(run)])
+    }
+
+    /// Load every remaining block and assemble the full document, edits included.
+    pub fn into_document(self) -> Result<Document, StorageError> {
+        let Self {
+            bodies,
+            mut shell,
+            spans,
+            mut hydrated,
+            dirty,
+            ..
+        } = self;
+        for element in &mut shell.blocks.elements {
+            let Some(span) = spans.get(&element.id) else {
+                continue;
+            };
+            let block = match hydrated.remove(&element.id) {
+                Some(block) => block,
+                None => read_body(&bodies, *span)?,
+            };
+            element.value = Some(block_to_dto(&block));
+        }
+        let mut doc = shell.into_document();
+        for elem_id in dirty {
+            doc.mark_source_elem_dirty(elem_id);
+        }
+        Ok(doc)
+    }
+
+    fn outline(&self, block_id: BlockId) -> Option<&BlockOutline> {
+        self.positions
+            .get(&block_id)
+            .map(|position| &self.blocks[*position])
+    }
+
+    fn hydrate(&mut self, elem_id: OpId) -> Result<&mut Block, StorageError> {
+        if !self.hydrated.contains_key(&elem_id) {
+            let span = self
+                .spans
+                .get(&elem_id)
+                .ok_or(StorageError::Corrupt("block not indexed"))?;
+            let block = read_body(&self.bodies, *span)?;
+            self.hydrated.insert(elem_id, block);
+        }
+        Ok(self.hydrated.get_mut(&elem_id).expect("hydrated above"))
+    }
+}
+
+fn read_body(path: &Path, span: BodySpan) -> Result<Block, StorageError> {
+    let len = usize::try_from(span.len).map_err(|_| StorageError::Corrupt("length overflow"))?;
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(span.offset))?;
+    let mut body = vec![0; len];
+    file.read_exact(&mut body)?;
+    if checksum_bytes(&body) != span.checksum {
+        return Err(StorageError::Corrupt("block body checksum"));
+    }
+    serde_json::from_slice(&body).map_err(|_| StorageError::Corrupt("decode"))
+}
@@ -3,12 +3,28 @@
 //! This module provides generation-based recovery using paired metadata/payload
 //! slots, checksumming, and atomic file replacement. Files are synced before
 //! publication; containing directories are additionally synced on Unix.
+//! [`encode_document`] and [`access_document`] provide a zero-copy document
+//! image for fast read-only opens.
 
 use crc32fast::Hasher;
 use rkyv::{Archive, Deserialize, Serialize};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+mod chain;
+mod document;
+mod lazy;
+mod tags;
+
+pub use chain::{ChainHash, ChainLink};
+pub use document::{
+    ArchivedBlockRecord, ArchivedDocument, BlockKindTag, BlockRecord, DOCUMENT_ARCHIVE_VERSION,
+    DocumentArchive, access_document, encode_document,
+};
+pub use lazy::{BLOCK_INDEX_VERSION, BlockOutline, LazyDocument, LazyEditError};
+pub use tags::VersionTag;
 
 const SUPERBLOCK_A: &str = "superblock_a";
 const SUPERBLOCK_B: &str = "superblock_b";
@@ -17,6 +33,7 @@ const SEGMENT_A: &str = "segment_a";
 const SEGMENT_B: &str = "segment_b";
 const OPS_DIR: &str = "ops";
 const ARCHIVE_DIR: &str = "archive";
+const PAGES_DIR: &str = "pages";
 const TOMBSTONES_FILE: &str = "tombstones.bin";
 const OP_SEGMENT_MAGIC: &[u8; 8] = b"MDCRDTOP";
 const OP_SEGMENT_VERSION: u16 = 1;
@@ -74,6 +91,8 @@ pub enum StorageError {
     CorruptOperationSegment { path: PathBuf, reason: &'static str },
     #[error("missing storage")]
     Missing,
+    #[error("snapshot chain broken at link {index}: {reason}")]
+    ChainBroken { index: u64, reason: &'static str },
     #[error(
         "storage format version {found:?} is unsupported; expected {expected}; reinitialize and re-ingest from Markdown"
     )]
@@ -101,12 +120,23 @@ impl Storage {
         Ok(Self { root })
     }
 
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            bytes = payload.len(),
+            pending_bytes = pending_ops.len(),
+            generation = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        )
+    )]
     pub fn write_snapshot(
         &self,
         payload: &[u8],
         pending_ops: &[u8],
         seq_ref_index_flag: bool,
     ) -> Result<(), StorageError> {
+        let started = Instant::now();
         if self.root.join(LEGACY_SEGMENT_FILE).exists() {
             return Err(StorageError::ReinitializeRequired {
                 found: None,
@@ -145,10 +175,77 @@ impl Storage {
         encoded.extend_from_slice(&body);
         encoded.extend_from_slice(&checksum_bytes(&body).to_le_bytes());
         atomic_write_durable(&self.root, target.superblock, &encoded)?;
+        let span = tracing::Span::current();
+        span.record("generation", generation);
+        span.record("elapsed_us", started.elapsed().as_micros() as u64);
         Ok(())
     }
 
+    /// Check the storage more strictly than [`Self::read_snapshot`], which
+    /// silently falls back to the older slot: every superblock must decode, the
+    /// newest one's segment must match its checksum, and every operation segment
+    /// must be intact. The older slot's segment may lag its superblock after an
+    /// interrupted write, which is not an error.
+    pub fn verify(&self) -> Result<(), StorageError> {
+        self.read_snapshot()?;
+        let mut newest: Option<(StorageSlot, SuperblockMetadata)> = None;
+        for slot in STORAGE_SLOTS {
+            let bytes = match fs::read(self.root.join(slot.superblock)) {
+                Ok(bytes) => bytes,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(StorageError::Io(error)),
+            };
+            let metadata = decode_superblock(&bytes)?;
+            if newest
+                .as_ref()
+                .is_none_or(|(_, newest)| metadata.generation > newest.generation)
+            {
+                newest = Some((slot, metadata));
+            }
+        }
+        if let Some((slot, metadata)) = newest {
+            let segment = match fs::read(self.root.join(slot.segment)) {
+                Ok(segment) => segment,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    return Err(StorageError::Corrupt("missing segment"));
+                }
+                Err(error) => return Err(StorageError::Io(error)),
+            };
+            if segment.len() as u64 != metadata.segment_len {
+                return Err(StorageError::Corrupt("length mismatch"));
+            }
+            if checksum_bytes(&segment) != metadata.segment_checksum {
+                return Err(StorageError::Corrupt("checksum mismatch"));
+            }
+        }
+        self.read_op_segments()?;
+        Ok(())
+    }
+
+    /// When the newest snapshot was published, or `None` before the first.
+    pub fn last_written(&self) -> Result<Option<SystemTime>, StorageError> {
+        let mut newest = None;
+        for slot in STORAGE_SLOTS {
+            match fs::metadata(self.root.join(slot.superblock)) {
+                Ok(metadata) => newest = newest.max(Some(metadata.modified()?)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(StorageError::Io(error)),
+            }
+        }
+        Ok(newest)
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            bytes = tracing::field::Empty,
+            generation = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        )
+    )]
     pub fn read_snapshot(&self) -> Result<(Vec<u8>, Vec<u8>, bool), StorageError> {
+        let started = Instant::now();
         let mut candidates = Vec::new();
         let mut saw_superblock = false;
         let mut last_corruption = "decode";
@@ -185,6 +282,10 @@ impl Storage {
                 last_corruption = "checksum mismatch";
                 continue;
             }
+            let span = tracing::Span::current();
+            span.record("bytes", segment.len());
+            span.record("generation", metadata.generation);
+            span.record("elapsed_us", started.elapsed().as_micros() as u64);
             return Ok((segment, metadata.pending_ops, metadata.seq_ref_index_flag));
         }
 
@@ -194,6 +295,7 @@ impl Storage {
                 expected: V2_VERSION,
             })
         } else if saw_superblock {
+            tracing::warn!(reason = last_corruption, "no intact snapshot slot");
             Err(StorageError::Corrupt(last_corruption))
         } else {
             Err(StorageError::Missing)
@@ -250,6 +352,17 @@ impl Storage {
             .collect()
     }
 
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            bytes = payload.len(),
+            archived_segments = tracing::field::Empty,
+            archived_ops = tracing::field::Empty,
+            pruned_tombstones = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        )
+    )]
     pub fn compact(
         &self,
         payload: &[u8],
@@ -258,6 +371,7 @@ impl Storage {
         retention: TombstoneRetention,
         tombstones: &[u64],
     ) -> Result<CompactionReport, StorageError> {
+        let started = Instant::now();
         let archive_dir = self.root.join(ARCHIVE_DIR);
         fs::create_dir_all(&archive_dir)?;
 
@@ -298,6 +412,11 @@ impl Storage {
 
         self.write_snapshot(payload, pending_ops, seq_ref_index_flag)?;
 
+        let span = tracing::Span::current();
+        span.record("archived_segments", archived_segments);
+        span.record("archived_ops", archived_ops);
+        span.record("pruned_tombstones", pruned_tombstones);
+        span.record("elapsed_us", started.elapsed().as_micros() as u64);
         Ok(CompactionReport {
             archived_segments,
             archived_ops,
@@ -318,6 +437,36 @@ impl Storage {
     }
 }
 
+/// Archived [`PagedSequence`](crate::core::PagedSequence) pages, one checksummed
+/// file each under `pages/`. Use one storage root per paged sequence.
+impl crate::core::PageStore for Storage {
+    type Error = StorageError;
+
+    fn write_page(&mut self, bytes: &[u8]) -> Result<crate::core::PageId, StorageError> {
+        let pages_dir = self.root.join(PAGES_DIR);
+        fs::create_dir_all(&pages_dir)?;
+        let index = next_index(&pages_dir, "page_")?;
+        atomic_write_durable(
+            &pages_dir,
+            &format!("page_{index}"),
+            &encode_op_segment(bytes),
+        )?;
+        Ok(index as crate::core::PageId)
+    }
+
+    fn read_page(&mut self, page: crate::core::PageId) -> Result<Vec<u8>, StorageError> {
+        let path = self.root.join(PAGES_DIR).join(format!("page_{page}"));
+        decode_op_segment(&path, &fs::read(&path)?)
+    }
+
+    fn remove_page(&mut self, page: crate::core::PageId) -> Result<(), StorageError> {
+        match fs::remove_file(self.root.join(PAGES_DIR).join(format!("page_{page}"))) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
 fn read_slot_generation(root: &Path, slot: StorageSlot) -> Result<Option<u64>, StorageError> {
     match fs::read(root.join(slot.superblock)) {
         Ok(bytes) => match decode_superblock(&bytes) {
@@ -0,0 +1,143 @@
+//! Named versions: history frontiers saved under a user-chosen name.
+//!
+//! The registry lives in one checksummed file beside the snapshot slots and is
+//! rewritten atomically on every change. A tag only records *where* in history
+//! a version sits; materializing it replays the session op log up to that
+//! frontier.
+
+use super::{Storage, StorageError, atomic_write_durable, checksum_bytes};
+use crate::core::{PeerId, StateVector};
+use rkyv::{Archive, Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TAGS_FILE: &str = "tags.bin";
+
+/// A named point in a document's history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionTag {
+    pub name: String,
+    pub state_vector: StateVector,
+    /// Milliseconds since the Unix epoch when the tag was written.
+    pub created_at_ms: u64,
+}
+
+#[derive(Debug, Archive, Serialize, Deserialize)]
+struct TagRecord {
+    name: String,
+    frontier: Vec<(PeerId, u64)>,
+    created_at_ms: u64,
+}
+
+impl Storage {
+    /// Save `state_vector` as version `name`, replacing any older tag of that name.
+    pub fn tag_version(
+        &self,
+        name: &str,
+        state_vector: &StateVector,
+    ) -> Result<VersionTag, StorageError> {
+        let tag = VersionTag {
+            name: name.to_string(),
+            state_vector: state_vector.clone(),
+            created_at_ms: unix_millis(SystemTime::now()),
+        };
+        let mut tags = self.list_tags()?;
+        tags.retain(|existing| existing.name != name);
+        tags.push(tag.clone());
+        self.write_tags(&tags)?;
+        Ok(tag)
+    }
+
+    /// Every tag, oldest first.
+    pub fn list_tags(&self) -> Result<Vec<VersionTag>, StorageError> {
+        let bytes = match fs::read(self.root.join(TAGS_FILE)) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(StorageError::Io(error)),
+        };
+        let mut tags = decode_tags(&bytes)?;
+        tags.sort_by_key(|tag| tag.created_at_ms);
+        Ok(tags)
+    }
+
+    pub fn read_tag(&self, name: &str) -> Result<Option<VersionTag>, StorageError> {
+        Ok(self.list_tags()?.into_iter().find(|tag| tag.name == name))
+    }
+
+    /// Newest tag written at or before `at`, e.g. "yesterday's version".
+    pub fn tag_as_of(&self, at: SystemTime) -> Result<Option<VersionTag>, StorageError> {
+        let at = unix_millis(at);
+        Ok(self
+            .list_tags()?
+            .into_iter()
+            .rev()
+            .find(|tag| tag.created_at_ms <= at))
+    }
+
+    /// Remove tag `name`. Returns whether it existed.
+    pub fn delete_tag(&self, name: &str) -> Result<bool, StorageError> {
+        let mut tags = self.list_tags()?;
+        let before = tags.len();
+        tags.retain(|tag| tag.name != name);
+        if tags.len() == before {
+            return Ok(false);
+        }
+        self.write_tags(&tags)?;
+        Ok(true)
+    }
+
+    fn write_tags(&self, tags: &[VersionTag]) -> Result<(), StorageError> {
+        let records: Vec<TagRecord> = tags
+            .iter()
+            .map(|tag| TagRecord {
+                name: tag.name.clone(),
+                frontier: tag.state_vector.iter().collect(),
+                created_at_ms: tag.created_at_ms,
+            })
+            .collect();
+        let body = rkyv::to_bytes::<rkyv::rancor::Error>(&records)
+            .map_err(|_| StorageError::Corrupt("encode"))?;
+        let mut bytes = body.to_vec();
+        bytes.extend_from_slice(&checksum_bytes(&body).to_le_bytes());
+        atomic_write_durable(&self.root, TAGS_FILE, &bytes)
+    }
+}
+
+fn decode_tags(bytes: &[u8]) -> Result<Vec<VersionTag>, StorageError> {
+    if bytes.len() < 4 {
+        return Err(StorageError::Corrupt("tags checksum"));
+    }
+    let (body, trailer) = bytes.split_at(bytes.len() - 4);
+    let stored_checksum = u32::from_le_bytes(
+        trailer
+            .try_into()
+            .map_err(|_| StorageError::Corrupt("tags checksum"))?,
+    );
+    if checksum_bytes(body) != stored_checksum {
+        return Err(StorageError::Corrupt("tags checksum"));
+    }
+    let mut aligned = rkyv::util::AlignedVec::<16>::new();
+    aligned.extend_from_slice(body);
+    let records = rkyv::from_bytes::<Vec<TagRecord>, rkyv::rancor::Error>(&aligned)
+        .map_err(|_| StorageError::Corrupt("decode"))?;
+    Ok(records
+        .into_iter()
+        .map(|record| {
+            let mut state_vector = StateVector::new();
+            for (peer, counter) in record.frontier {
+                state_vector.set(peer, counter);
+            }
+            VersionTag {
+                name: record.name,
+                state_vector,
+                created_at_ms: record.created_at_ms,
+            }
+        })
+        .collect())
+}
+
+fn unix_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
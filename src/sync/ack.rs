@@ -0,0 +1,145 @@
+//! Acknowledgement tracking for sent operations.
+//!
+//! Each sent operation waits for an ack until its deadline; an op whose
+//! deadline passes is due for resend, and every resend lengthens the next
+//! timeout by the [`RetryPolicy`] backoff factor.
+
+use crate::core::OpId;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long to wait for an acknowledgement and how often to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Wait after the first send.
+    pub initial_timeout: Duration,
+    /// Multiplier applied to the timeout after each resend.
+    pub backoff_factor: u32,
+    /// Upper bound on any single timeout.
+    pub max_timeout: Duration,
+    /// Sends after which an op is no longer offered for resend; `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_timeout: Duration::from_secs(2),
+            backoff_factor: 2,
+            max_timeout: Duration::from_secs(60),
+            max_attempts: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Timeout after the `attempts`-th send (1 for the first).
+    pub fn timeout(&self, attempts: u32) -> Duration {
+        let factor = self
+            .backoff_factor
+            .max(1)
+            .saturating_pow(attempts.saturating_sub(1));
+        self.initial_timeout
+            .saturating_mul(factor)
+            .min(self.max_timeout)
+    }
+}
+
+/// Backoff state of one unacknowledged operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendRecord {
+    /// Sends so far, including the first.
+    pub attempts: u32,
+    pub last_sent: Instant,
+    /// When the op becomes due for resend.
+    pub deadline: Instant,
+}
+
+/// Sent-but-unacknowledged operations with their resend deadlines.
+#[derive(Debug, Clone, Default)]
+pub struct AckTracker {
+    policy: RetryPolicy,
+    in_flight: BTreeMap<OpId, SendRecord>,
+}
+
+impl AckTracker {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            in_flight: BTreeMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Replace the policy; deadlines already scheduled are kept.
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// Record a send (or resend) of `op_id` at `now` and schedule its deadline.
+    pub fn record_sent(&mut self, op_id: OpId, now: Instant) {
+        let attempts = self
+            .in_flight
+            .get(&op_id)
+            .map_or(1, |record| record.attempts.saturating_add(1));
+        let deadline = now
+            .checked_add(self.policy.timeout(attempts))
+            .unwrap_or(now);
+        self.in_flight.insert(
+            op_id,
+            SendRecord {
+                attempts,
+                last_sent: now,
+                deadline,
+            },
+        );
+    }
+
+    /// Stop tracking `op_id`. Returns whether it was in flight.
+    pub fn acknowledge(&mut self, op_id: OpId) -> bool {
+        self.in_flight.remove(&op_id).is_some()
+    }
+
+    pub fn contains(&self, op_id: OpId) -> bool {
+        self.in_flight.contains_key(&op_id)
+    }
+
+    pub fn record(&self, op_id: OpId) -> Option<&SendRecord> {
+        self.in_flight.get(&op_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Ops whose deadline has passed at `now` and that have retries left.
+    pub fn due(&self, now: Instant) -> Vec<OpId> {
+        self.in_flight
+            .iter()
+            .filter(|(_, record)| record.deadline <= now && !self.is_exhausted(record))
+            .map(|(op_id, _)| *op_id)
+            .collect()
+    }
+
+    /// Ops that used every attempt of the policy without an acknowledgement.
+    pub fn exhausted(&self) -> Vec<OpId> {
+        self.in_flight
+            .iter()
+            .filter(|(_, record)| self.is_exhausted(record))
+            .map(|(op_id, _)| *op_id)
+            .collect()
+    }
+
+    fn is_exhausted(&self, record: &SendRecord) -> bool {
+        self.policy
+            .max_attempts
+            .is_some_and(|max| record.attempts >= max)
+    }
+}
@@ -0,0 +1,67 @@
+//! Classification of local operations into shared and local-only.
+//!
+//! Local-only operations (view state such as column widths) live beside the op
+//! log rather than in it: they never advance the state vector, enter the outbox,
+//! or appear in [`SyncState::encode_changes_since`](super::SyncState::encode_changes_since)
+//! and [`SyncState::applied_ops`](super::SyncState::applied_ops). Peers therefore
+//! never wait on their counters, which is also why they must be minted outside
+//! the shared counter sequence, for example under a peer id reserved for view
+//! state.
+
+use super::Operation;
+use crate::core::PeerId;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// Whether a local operation is sent to peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpClass {
+    #[default]
+    Shared,
+    LocalOnly,
+}
+
+type Classifier = Arc<dyn Fn(&Operation) -> OpClass + Send + Sync>;
+
+/// Decides the [`OpClass`] of each operation passed to
+/// [`SyncState::add_local_op`](super::SyncState::add_local_op). The default
+/// filter shares everything.
+#[derive(Clone, Default)]
+pub struct OpFilter {
+    classify: Option<Classifier>,
+}
+
+impl OpFilter {
+    pub fn new(classify: impl Fn(&Operation) -> OpClass + Send + Sync + 'static) -> Self {
+        Self {
+            classify: Some(Arc::new(classify)),
+        }
+    }
+
+    /// Operations minted under any of `peers` are local-only.
+    pub fn local_peers(peers: impl IntoIterator<Item = PeerId>) -> Self {
+        let peers: BTreeSet<PeerId> = peers.into_iter().collect();
+        Self::new(move |op| {
+            if peers.contains(&op.id.peer) {
+                OpClass::LocalOnly
+            } else {
+                OpClass::Shared
+            }
+        })
+    }
+
+    pub fn classify(&self, op: &Operation) -> OpClass {
+        self.classify
+            .as_ref()
+            .map_or(OpClass::Shared, |classify| classify(op))
+    }
+}
+
+impl fmt::Debug for OpFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpFilter")
+            .field("custom", &self.classify.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,127 @@
+//! Compact binary framing for [`ChangeMessage`].
+//!
+//! The message's peers are written once in a [`PeerTable`]; every operation id
+//! and frontier entry then costs a varint peer index and a varint counter
+//! instead of two full 64-bit words. Payloads are copied through unchanged.
+//! Version 2 appends the message's declared gaps; a message without any is
+//! still written as version 1.
+
+use super::{ChangeMessage, Operation};
+use crate::core::StateVector;
+use crate::core::compact::{CompactError, CompactOpId, PeerTable, read_varint, write_varint};
+
+const MAGIC: &[u8; 4] = b"MDCM";
+const FRAMING_VERSION: u64 = 1;
+/// [`FRAMING_VERSION`] followed by `(id, after)` gap records.
+const FRAMING_VERSION_GAPS: u64 = 2;
+
+impl ChangeMessage {
+    /// Encode as `MDCM`, version, peer table, frontier, `(id, payload)` records, then
+    /// `(id, after)` gap records when there are any.
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        let mut peers = PeerTable::new();
+        for (peer, _) in self.since.iter() {
+            peers.intern(peer);
+        }
+        for op in &self.ops {
+            peers.intern(op.id.peer);
+        }
+        for (id, _) in &self.gaps {
+            peers.intern(id.peer);
+        }
+
+        let mut out = MAGIC.to_vec();
+        let version = if self.gaps.is_empty() {
+            FRAMING_VERSION
+        } else {
+            FRAMING_VERSION_GAPS
+        };
+        write_varint(&mut out, version);
+        write_varint(&mut out, peers.len() as u64);
+        for peer in peers.peers() {
+            write_varint(&mut out, *peer);
+        }
+        write_varint(&mut out, self.since.iter().count() as u64);
+        for (peer, counter) in self.since.iter() {
+            write_varint(&mut out, u64::from(peers.intern(peer)));
+            write_varint(&mut out, counter);
+        }
+        write_varint(&mut out, self.ops.len() as u64);
+        for op in &self.ops {
+            let CompactOpId(index, counter) = peers.compact(op.id);
+            write_varint(&mut out, u64::from(index));
+            write_varint(&mut out, counter);
+            write_varint(&mut out, op.payload.len() as u64);
+            out.extend_from_slice(&op.payload);
+        }
+        if version == FRAMING_VERSION_GAPS {
+            write_varint(&mut out, self.gaps.len() as u64);
+            for (id, after) in &self.gaps {
+                let CompactOpId(index, counter) = peers.compact(*id);
+                write_varint(&mut out, u64::from(index));
+                write_varint(&mut out, counter);
+                write_varint(&mut out, *after);
+            }
+        }
+        out
+    }
+
+    /// Decode bytes written by [`Self::to_compact_bytes`].
+    pub fn from_compact_bytes(bytes: &[u8]) -> Result<Self, CompactError> {
+        let mut input = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or(CompactError::BadMagic)?;
+        let version = read_varint(&mut input)?;
+        if version != FRAMING_VERSION && version != FRAMING_VERSION_GAPS {
+            return Err(CompactError::UnsupportedVersion(version));
+        }
+        let peer_count = read_len(&mut input)?;
+        let mut peers = Vec::with_capacity(peer_count.min(input.len()));
+        for _ in 0..peer_count {
+            peers.push(read_varint(&mut input)?);
+        }
+        let peers = PeerTable::from_peers(peers)?;
+
+        let mut since = StateVector::new();
+        for _ in 0..read_len(&mut input)? {
+            let id = read_id(&mut input, &peers)?;
+            since.set(id.peer, id.counter);
+        }
+        let op_count = read_len(&mut input)?;
+        let mut ops = Vec::with_capacity(op_count.min(input.len()));
+        for _ in 0..op_count {
+            let id = read_id(&mut input, &peers)?;
+            let len = read_len(&mut input)?;
+            if input.len() < len {
+                return Err(CompactError::Truncated);
+            }
+            let (payload, rest) = input.split_at(len);
+            input = rest;
+            ops.push(Operation {
+                id,
+                payload: payload.into(),
+            });
+        }
+        let mut gaps = Vec::new();
+        if version == FRAMING_VERSION_GAPS {
+            for _ in 0..read_len(&mut input)? {
+                let id = read_id(&mut input, &peers)?;
+                gaps.push((id, read_varint(&mut input)?));
+            }
+        }
+        if !input.is_empty() {
+            return Err(CompactError::TrailingBytes(input.len()));
+        }
+        Ok(Self { since, ops, gaps })
+    }
+}
+
+fn read_len(input: &mut &[u8]) -> Result<usize, CompactError> {
+    usize::try_from(read_varint(input)?).map_err(|_| CompactError::Truncated)
+}
+
+fn read_id(input: &mut &[u8], peers: &PeerTable) -> Result<crate::core::OpId, CompactError> {
+    let index = read_varint(input)?;
+    let index = u32::try_from(index).map_err(|_| CompactError::UnknownPeerIndex(index))?;
+    peers.expand(CompactOpId(index, read_varint(input)?))
+}
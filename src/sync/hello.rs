@@ -0,0 +1,262 @@
+//! Opening a sync connection: protocol version, capabilities and keep-alive.
+//!
+//! Each side sends a [`Hello`] first. [`Hello::negotiate`] settles the highest
+//! protocol version both speak and the optional features both support, and
+//! fails with a [`HelloError`] when the two cannot talk, so a wire change
+//! rolls out behind a version or capability instead of breaking older peers.
+//!
+//! [`SyncHandshake`](super::SyncHandshake) follows on a negotiated connection;
+//! it is about document state, the hello about the connection.
+
+use crate::core::PeerId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{BitOr, RangeInclusive};
+use std::time::{Duration, Instant};
+
+/// Version of the connection protocol: the messages peers exchange and their order.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Oldest [`PROTOCOL_VERSION`] this build still speaks.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Set of optional protocol features. Bits this build does not implement,
+/// reserved or unknown, are kept as sent and never negotiated, so newer peers
+/// can advertise more.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Reserved for compressed change payloads; not implemented.
+    pub const COMPRESSION: Self = Self(1);
+    /// Reserved for authenticated operations, e.g. signed
+    /// [`OpMetadata`](crate::codec::OpMetadata); not implemented.
+    pub const AUTH: Self = Self(1 << 1);
+    /// Reserved for presence and cursor messages alongside changes; not
+    /// implemented.
+    pub const AWARENESS: Self = Self(1 << 2);
+    /// Reserved for backfill of a large history in bounded chunks; not
+    /// implemented.
+    pub const CHUNKED_BACKFILL: Self = Self(1 << 3);
+    /// Periodic keep-alives, see [`KeepAlive`].
+    pub const KEEP_ALIVE: Self = Self(1 << 4);
+
+    const NAMED: [(Self, &'static str); 5] = [
+        (Self::COMPRESSION, "compression"),
+        (Self::AUTH, "auth"),
+        (Self::AWARENESS, "awareness"),
+        (Self::CHUNKED_BACKFILL, "chunked_backfill"),
+        (Self::KEEP_ALIVE, "keep_alive"),
+    ];
+
+    /// Every feature this build implements: keep-alive. The reserved bits are
+    /// not part of it.
+    pub const fn all() -> Self {
+        Self::KEEP_ALIVE
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Features in `self` but not in `other`.
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for (flag, name) in Self::NAMED {
+            if self.contains(flag) {
+                set.entry(&format_args!("{name}"));
+            }
+        }
+        let named = Self::NAMED
+            .iter()
+            .fold(Self::NONE, |named, (flag, _)| named | *flag);
+        let unknown = self.difference(named);
+        if !unknown.is_empty() {
+            set.entry(&format_args!("{:#x}", unknown.0));
+        }
+        set.finish()
+    }
+}
+
+/// First message on a sync connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub peer: PeerId,
+    pub protocol_version: u16,
+    pub min_protocol_version: u16,
+    /// Features this side can use.
+    pub capabilities: Capabilities,
+    /// Features this side cannot do without; each is also in `capabilities`.
+    pub required: Capabilities,
+    /// Longest silence this side tolerates before dropping the connection, in
+    /// milliseconds; 0 disables keep-alive on this side.
+    pub keep_alive_ms: u64,
+}
+
+impl Hello {
+    /// A hello from `peer` speaking this build's protocol versions and every
+    /// feature it implements, requiring none, without keep-alive.
+    pub fn new(peer: PeerId) -> Self {
+        Self {
+            peer,
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            capabilities: Capabilities::all(),
+            required: Capabilities::NONE,
+            keep_alive_ms: 0,
+        }
+    }
+
+    /// What a connection between this side and `remote` uses, or why there can
+    /// be none. Both sides reach the same result from the same two hellos.
+    pub fn negotiate(&self, remote: &Hello) -> Result<Negotiated, HelloError> {
+        if self.peer == remote.peer {
+            return Err(HelloError::SamePeer(self.peer));
+        }
+        let protocol_version = self.protocol_version.min(remote.protocol_version);
+        if protocol_version < self.min_protocol_version
+            || protocol_version < remote.min_protocol_version
+        {
+            return Err(HelloError::ProtocolMismatch {
+                local: self.min_protocol_version..=self.protocol_version,
+                remote: remote.min_protocol_version..=remote.protocol_version,
+            });
+        }
+        let missing = self.required.difference(remote.capabilities);
+        if !missing.is_empty() {
+            return Err(HelloError::RemoteLacks(missing));
+        }
+        let missing = remote.required.difference(self.capabilities);
+        if !missing.is_empty() {
+            return Err(HelloError::LocalLacks(missing));
+        }
+        let capabilities = self
+            .capabilities
+            .intersection(remote.capabilities)
+            .intersection(Capabilities::all());
+        let keep_alive = capabilities
+            .contains(Capabilities::KEEP_ALIVE)
+            .then(|| {
+                [self.keep_alive_ms, remote.keep_alive_ms]
+                    .into_iter()
+                    .filter(|ms| *ms > 0)
+                    .min()
+            })
+            .flatten()
+            .map(Duration::from_millis);
+        Ok(Negotiated {
+            remote_peer: remote.peer,
+            protocol_version,
+            capabilities,
+            keep_alive,
+        })
+    }
+}
+
+/// Settings of a connection, from [`Hello::negotiate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub remote_peer: PeerId,
+    pub protocol_version: u16,
+    /// Features both sides support and this build implements.
+    pub capabilities: Capabilities,
+    /// Shorter of the two sides' tolerated silences, when both use keep-alive.
+    pub keep_alive: Option<Duration>,
+}
+
+impl Negotiated {
+    pub fn supports(&self, capability: Capabilities) -> bool {
+        self.capabilities.contains(capability)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HelloError {
+    #[error("no common protocol version: local speaks {local:?}, remote speaks {remote:?}")]
+    ProtocolMismatch {
+        local: RangeInclusive<u16>,
+        remote: RangeInclusive<u16>,
+    },
+    #[error("remote peer lacks required capabilities {0:?}")]
+    RemoteLacks(Capabilities),
+    #[error("remote peer requires capabilities {0:?} this side lacks")]
+    LocalLacks(Capabilities),
+    #[error("both sides claim peer id {0}")]
+    SamePeer(PeerId),
+}
+
+/// Keep-alive timing of one connection: when to send something so the remote
+/// does not give up, and when to give up on a silent remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    interval: Duration,
+    last_sent: Instant,
+    last_heard: Instant,
+}
+
+impl KeepAlive {
+    /// Tracking from `now`, for a negotiated [`Negotiated::keep_alive`] interval.
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            last_sent: now,
+            last_heard: now,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Record any message sent to the remote.
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = self.last_sent.max(now);
+    }
+
+    /// Record any message received from the remote.
+    pub fn heard(&mut self, now: Instant) {
+        self.last_heard = self.last_heard.max(now);
+    }
+
+    /// Whether to send a keep-alive: nothing went out for half the interval.
+    pub fn ping_due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_sent) >= self.interval / 2
+    }
+
+    /// Whether the remote has been silent for longer than the interval.
+    pub fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_heard) > self.interval
+    }
+}
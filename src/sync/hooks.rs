@@ -0,0 +1,116 @@
+//! Hooks that screen operations on their way to and from peers.
+//!
+//! An outgoing hook sees each operation as it leaves for a peer and may pass,
+//! rewrite or withhold it, e.g. to strip a confidential block for some peers.
+//! An incoming hook sees each remote operation before it is applied and
+//! returns a [`Decision`]. Hooks run in registration order; the first to
+//! withhold or reject an operation ends the chain.
+//!
+//! A hook may change an operation's payload but never its id: ids are what
+//! state vectors, causal readiness and duplicate detection are built on. An
+//! operation a hook returns under another id is withheld or rejected, and the
+//! violation is logged.
+//!
+//! Withholding and rejecting keep causality intact on the receiving side. A
+//! rejected operation is neither logged nor counted in the state vector, so
+//! later operations of its peer that follow it wait in the pending buffer. A
+//! withheld one is simply never sent, which is what redaction wants; a peer
+//! that receives edits of a withheld block parks them like any edit that
+//! arrives before its block.
+
+use super::{Operation, Origin};
+use crate::core::{OpId, PeerId};
+use std::fmt;
+use std::sync::Arc;
+
+/// What an incoming hook does with a remote operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Accept,
+    /// Apply this operation instead; it must keep the original id.
+    Replace(Operation),
+    /// Drop the operation; it is reported in `ApplyResult::rejected`.
+    Reject,
+}
+
+/// Handle for removing a hook registered with
+/// [`SyncState::on_outgoing`](super::SyncState::on_outgoing) or
+/// [`SyncState::on_incoming`](super::SyncState::on_incoming).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpHookId(u64);
+
+type OutgoingHook = Arc<dyn Fn(Operation, Option<PeerId>) -> Option<Operation> + Send + Sync>;
+type IncomingHook = Arc<dyn Fn(&Operation, Origin) -> Decision + Send + Sync>;
+
+/// Registered hooks. Unlike update listeners they are policy, so clones keep them.
+#[derive(Clone, Default)]
+pub(super) struct OpHooks {
+    next_id: u64,
+    outgoing: Vec<(OpHookId, OutgoingHook)>,
+    incoming: Vec<(OpHookId, IncomingHook)>,
+}
+
+impl OpHooks {
+    fn next_id(&mut self) -> OpHookId {
+        let id = OpHookId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub(super) fn add_outgoing(&mut self, hook: OutgoingHook) -> OpHookId {
+        let id = self.next_id();
+        self.outgoing.push((id, hook));
+        id
+    }
+
+    pub(super) fn add_incoming(&mut self, hook: IncomingHook) -> OpHookId {
+        let id = self.next_id();
+        self.incoming.push((id, hook));
+        id
+    }
+
+    pub(super) fn remove(&mut self, id: OpHookId) -> bool {
+        let before = self.outgoing.len() + self.incoming.len();
+        self.outgoing.retain(|(existing, _)| *existing != id);
+        self.incoming.retain(|(existing, _)| *existing != id);
+        self.outgoing.len() + self.incoming.len() != before
+    }
+
+    /// `op` as it leaves for `to`, or `None` when a hook withholds it.
+    pub(super) fn outgoing(&self, mut op: Operation, to: Option<PeerId>) -> Option<Operation> {
+        for (_, hook) in &self.outgoing {
+            let id = op.id;
+            op = hook(op, to)?;
+            if op.id != id {
+                tracing::warn!(op = ?id, rewritten = ?op.id, "outgoing hook changed an operation id; withholding it");
+                return None;
+            }
+        }
+        Some(op)
+    }
+
+    /// `op` as it is applied, or its id when a hook rejects it.
+    pub(super) fn incoming(&self, mut op: Operation, origin: Origin) -> Result<Operation, OpId> {
+        for (_, hook) in &self.incoming {
+            match hook(&op, origin) {
+                Decision::Accept => {}
+                Decision::Reject => return Err(op.id),
+                Decision::Replace(replacement) if replacement.id == op.id => op = replacement,
+                Decision::Replace(replacement) => {
+                    tracing::warn!(op = ?op.id, rewritten = ?replacement.id, "incoming hook changed an operation id; rejecting it");
+                    return Err(op.id);
+                }
+            }
+        }
+        Ok(op)
+    }
+}
+
+impl fmt::Debug for OpHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpHooks")
+            .field("outgoing", &self.outgoing.len())
+            .field("incoming", &self.incoming.len())
+            .finish()
+    }
+}
@@ -3,10 +3,12 @@
 //! This module provides the sync layer for exchanging operations between peers,
 //! including validation, causal ordering, and conflict resolution.
 
-use crate::core::{OpId, StateVector};
+use crate::core::pending::{self, BufferedSince};
+use crate::core::{CounterRanges, ExpiredOp, OpId, PeerId, PendingSummary, StateVector};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Operation {
@@ -18,15 +20,31 @@ pub struct Operation {
 pub struct ChangeMessage {
     pub since: StateVector,
     pub ops: Vec<Operation>,
+    /// `(id, after)` for each operation of `ops` whose sender skipped counters
+    /// right before it: `after` is the counter of its own peer it follows.
+    /// Every other operation follows the counter right before its span.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gaps: Vec<(OpId, u64)>,
+}
+
+impl ChangeMessage {
+    /// [`Self::gaps`] by operation id.
+    pub(crate) fn gap_map(&self) -> BTreeMap<OpId, u64> {
+        self.gaps.iter().copied().collect()
+    }
 }
 
 /// Tombstone policy for a history checkpoint.
 ///
 /// Operation acknowledgement alone does not prove that structural anchors can be
-/// garbage-collected, so the current safe policy retains all document tombstones.
+/// garbage-collected, so sequence tombstones are always retained. Removed mark
+/// intervals anchor nothing and can go once every replica has seen the removal.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DocumentTombstonePolicy {
     KeepAll,
+    /// Drop removed mark intervals whose removal this replica and every active
+    /// peer lease have observed.
+    CollectRemovedMarks,
 }
 
 /// One peer supported for incremental deltas at this checkpoint.
@@ -52,6 +70,44 @@ pub struct CheckpointReport {
     pub pruned_ops: usize,
     pub retained_ops: usize,
     pub delta_floor: StateVector,
+    /// Removed mark intervals dropped under [`DocumentTombstonePolicy::CollectRemovedMarks`].
+    pub collected_marks: usize,
+    /// Text tombstones dropped by a [`HistoryBudget`] fold.
+    #[serde(default)]
+    pub dropped_tombstones: usize,
+}
+
+/// Cap on the history a replica retains, for
+/// [`CollaborativeDocument::set_history_budget`](crate::CollaborativeDocument::set_history_budget).
+///
+/// Past either limit the oldest operations are folded into the document state:
+/// checkpointed away without peer leases, removed marks and unanchored text
+/// tombstones dropped, and the frontier floor raised. Peers whose state is
+/// below the floor must then rebase from a snapshot instead of merging a delta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryBudget {
+    /// Most operations kept in the log; the newest one is always kept.
+    pub max_ops: usize,
+    /// Most payload bytes kept in the log.
+    pub max_bytes: usize,
+}
+
+impl HistoryBudget {
+    /// A budget limiting only the operation count.
+    pub const fn ops(max_ops: usize) -> Self {
+        Self {
+            max_ops,
+            max_bytes: usize::MAX,
+        }
+    }
+
+    /// A budget limiting only the payload bytes.
+    pub const fn bytes(max_bytes: usize) -> Self {
+        Self {
+            max_ops: usize::MAX,
+            max_bytes,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
@@ -69,15 +125,109 @@ pub enum CheckpointError {
     EpochOverflow,
 }
 
+/// Version of the formats two replicas must share to sync: envelope payloads
+/// ([`WIRE_VERSION`](crate::codec::WIRE_VERSION)), snapshots and compact
+/// [`ChangeMessage`] framing. Bumped whenever any of them changes; the fixtures
+/// under `tests/fixtures/conformance` pin the bytes of each version.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Oldest [`FORMAT_VERSION`] this build still reads and writes.
+pub const MIN_FORMAT_VERSION: u16 = 2;
+
+/// Opening message of a sync exchange: what the sender has applied, the
+/// frontier floor below which it no longer has the operations to serve a delta,
+/// and the range of format versions it speaks.
+///
+/// A peer whose state vector is below a sender's floor must bootstrap from that
+/// sender's snapshot instead of asking for changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncHandshake {
+    pub state_vector: StateVector,
+    pub frontier_floor: StateVector,
+    pub format_version: u16,
+    pub min_format_version: u16,
+}
+
+impl Default for SyncHandshake {
+    fn default() -> Self {
+        Self {
+            state_vector: StateVector::new(),
+            frontier_floor: StateVector::new(),
+            format_version: FORMAT_VERSION,
+            min_format_version: MIN_FORMAT_VERSION,
+        }
+    }
+}
+
+impl SyncHandshake {
+    /// Whether the sender can bring `state_vector` up to date with a delta.
+    pub fn can_serve(&self, state_vector: &StateVector) -> bool {
+        !below_floor(state_vector, &self.frontier_floor)
+    }
+
+    /// Highest format version both sides speak.
+    pub fn negotiate_format(&self, remote: &SyncHandshake) -> Result<u16, FormatMismatch> {
+        let version = self.format_version.min(remote.format_version);
+        if version < self.min_format_version || version < remote.min_format_version {
+            return Err(FormatMismatch {
+                local: self.min_format_version..=self.format_version,
+                remote: remote.min_format_version..=remote.format_version,
+            });
+        }
+        Ok(version)
+    }
+}
+
+/// Two handshakes whose format version ranges do not overlap.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("no common format version: local speaks {local:?}, remote speaks {remote:?}")]
+pub struct FormatMismatch {
+    pub local: std::ops::RangeInclusive<u16>,
+    pub remote: std::ops::RangeInclusive<u16>,
+}
+
+/// Some peer's counter in `since` is older than `floor` allows.
+fn below_floor(since: &StateVector, floor: &StateVector) -> bool {
+    floor
+        .iter()
+        .any(|(peer, floor)| since.get(peer).unwrap_or(0) < floor)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
-#[error("peer is behind checkpoint epoch {checkpoint_epoch}")]
+#[error(
+    "requested state is below the frontier floor of checkpoint epoch {checkpoint_epoch}; bootstrap from a snapshot"
+)]
 pub struct RebaseRequired {
     pub checkpoint_epoch: u64,
     pub delta_floor: StateVector,
 }
 
+mod ack;
+mod filter;
+mod framing;
+mod hello;
+mod hooks;
+mod origin;
+mod outbox;
+mod peers;
+mod replica;
 mod validation;
 
+pub use ack::{AckTracker, RetryPolicy, SendRecord};
+pub use filter::{OpClass, OpFilter};
+pub use hello::{
+    Capabilities, Hello, HelloError, KeepAlive, MIN_PROTOCOL_VERSION, Negotiated, PROTOCOL_VERSION,
+};
+use hooks::OpHooks;
+pub use hooks::{Decision, OpHookId};
+pub use origin::Origin;
+pub use outbox::{OutboxBatch, OutboxToken};
+pub use peers::{
+    EPOCH_BITS, MAX_DEVICE, MembershipError, PeerRecord, PeerRegistry, PeerStatus, epoch_peer_id,
+    split_peer_id,
+};
+use replica::UpdateListeners;
+pub use replica::{ReadOnlyReplica, ReplicaMode, SyncUpdate, UpdateListenerId};
 pub use validation::{MalformedKind, ValidationError, ValidationLimits, validate_changes};
 
 /// Semantic conflicts detected during apply
@@ -93,6 +243,25 @@ pub enum SemanticConflict {
         winner: OpId,
         loser: OpId,
     },
+    /// A block's access control list was changed concurrently; the higher OpId won
+    AclConflict {
+        block_id: crate::doc::BlockId,
+        winner: OpId,
+        loser: OpId,
+    },
+    /// An operation arrived again with a payload that differs from the one already
+    /// held under its id; the held payload was kept
+    DuplicateOpMismatch { id: OpId },
+    /// A frontmatter field or table header cell under a
+    /// [`ResolutionPolicy`](crate::doc::ResolutionPolicy) other than LWW received a
+    /// write concurrent with `writes`' other entries; `shown` is the write the
+    /// policy displays
+    RegisterConflict {
+        key: crate::doc::RegisterKey,
+        policy: crate::doc::ResolutionPolicy,
+        writes: Vec<OpId>,
+        shown: OpId,
+    },
 }
 
 /// Result of applying changes
@@ -104,6 +273,22 @@ pub struct ApplyResult {
     pub buffered: Vec<OpId>,
     /// Semantic conflicts that were detected and auto-resolved
     pub conflicts: Vec<SemanticConflict>,
+    /// Index of the first operation left unexamined because the pending buffer reached
+    /// [`ValidationLimits::max_pending_buffer`]. Resend `ops[resume_at..]` once buffered
+    /// operations have been promoted.
+    pub resume_at: Option<usize>,
+    /// Buffered operations dropped under [`SyncState::set_pending_ttl`] before this
+    /// batch was applied.
+    pub expired: Vec<ExpiredOp>,
+    /// Operations an incoming hook rejected; see [`SyncState::on_incoming`].
+    pub rejected: Vec<OpId>,
+}
+
+impl ApplyResult {
+    /// Whether every operation in the message was examined.
+    pub fn is_complete(&self) -> bool {
+        self.resume_at.is_none()
+    }
 }
 
 /// Outcome of integrating a single remote operation into the op log.
@@ -121,6 +306,32 @@ pub enum IntegrateResult {
     Applied,
 }
 
+/// When a remote operation counts as causally ready.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CausalMode {
+    /// Ready only once every earlier counter of its peer has been applied. A
+    /// peer that skipped counters stalls its later operations in the pending
+    /// buffer; useful for debugging a sender's counter bookkeeping.
+    Strict,
+    /// Ready once the counter it follows on its own peer has been applied: the
+    /// one its message declares in [`ChangeMessage::gaps`], or else the counter
+    /// right before its span. Counters its author kept local-only do not hold
+    /// it back, and a declaration that skips a counter the receiver holds is
+    /// refused ([`SyncState::check_gaps`]).
+    #[default]
+    GapTolerant,
+}
+
+/// An operation waiting in the pending buffer.
+#[derive(Debug, Clone)]
+struct Buffered {
+    op: Operation,
+    /// Counters covered: `[id.counter - span + 1, id.counter]`.
+    span: u64,
+    /// Counter of its own peer the operation declares it follows.
+    after: u64,
+}
+
 /// Sync state for managing operations and their synchronization.
 ///
 /// Note: This was previously named `Document` in md-crdt-sync but renamed to
@@ -129,34 +340,189 @@ pub enum IntegrateResult {
 pub struct SyncState {
     ops: BTreeMap<OpId, Arc<[u8]>>,
     state_vector: StateVector,
-    /// Operations waiting for causal dependencies, with the counter span each covers.
-    /// An op with id counter `e` and span `n` covers `[e - n + 1, e]`.
-    pending: BTreeMap<OpId, (Operation, u64)>,
+    /// Counters applied per peer; unlike `state_vector`, these show gaps.
+    applied: BTreeMap<PeerId, CounterRanges>,
+    /// Counter of its own peer each logged operation follows, for those whose peer
+    /// skipped the counters right before them; sent along as [`ChangeMessage::gaps`].
+    gaps: BTreeMap<OpId, u64>,
+    causal_mode: CausalMode,
+    /// Operations waiting for causal dependencies.
+    pending: BTreeMap<OpId, Buffered>,
+    /// `pending` ids by peer, ordered by the first counter each covers, so only
+    /// the head of each peer's queue needs a readiness check.
+    pending_by_peer: BTreeMap<PeerId, BTreeSet<(u64, OpId)>>,
+    pending_since: BufferedSince,
+    pending_ttl: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    pending_gauge: crate::metrics::PendingGauge,
     /// Operations that have been generated locally but not yet sent
     outbox: BTreeSet<OpId>,
-    /// Operations that have been sent but not confirmed
-    sent: BTreeSet<OpId>,
+    /// Operations that have been sent but not confirmed, with resend deadlines
+    acks: AckTracker,
     checkpoint_epoch: u64,
     delta_floor: StateVector,
+    limits: ValidationLimits,
+    mode: ReplicaMode,
+    listeners: UpdateListeners,
+    filter: OpFilter,
+    hooks: OpHooks,
+    /// Local ops the filter kept out of the op log.
+    local_only: BTreeMap<OpId, Arc<[u8]>>,
 }
 
 impl SyncState {
     pub fn new() -> Self {
+        Self::with_limits(ValidationLimits::default())
+    }
+
+    /// Empty state whose [`Self::apply_changes`] enforces `limits`.
+    pub fn with_limits(limits: ValidationLimits) -> Self {
         Self {
             ops: BTreeMap::new(),
             state_vector: StateVector::new(),
+            applied: BTreeMap::new(),
+            gaps: BTreeMap::new(),
+            causal_mode: CausalMode::default(),
             pending: BTreeMap::new(),
+            pending_by_peer: BTreeMap::new(),
+            pending_since: BufferedSince::default(),
+            pending_ttl: None,
+            #[cfg(feature = "metrics")]
+            pending_gauge: Default::default(),
             outbox: BTreeSet::new(),
-            sent: BTreeSet::new(),
+            acks: AckTracker::default(),
             checkpoint_epoch: 0,
             delta_floor: StateVector::new(),
+            limits,
+            mode: ReplicaMode::default(),
+            listeners: UpdateListeners::default(),
+            filter: OpFilter::default(),
+            hooks: OpHooks::default(),
+            local_only: BTreeMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &ValidationLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: ValidationLimits) {
+        self.limits = limits;
+    }
+
+    pub fn mode(&self) -> ReplicaMode {
+        self.mode
+    }
+
+    /// Switching to [`ReplicaMode::ReadOnly`] drops the outbox and every
+    /// unacknowledged send, so the replica has nothing left to offer peers.
+    pub fn set_mode(&mut self, mode: ReplicaMode) {
+        self.mode = mode;
+        if mode == ReplicaMode::ReadOnly {
+            self.outbox.clear();
+            self.acks = AckTracker::new(*self.acks.policy());
+        }
+    }
+
+    pub fn causal_mode(&self) -> CausalMode {
+        self.causal_mode
+    }
+
+    /// Operations already buffered are promoted under the new mode.
+    pub fn set_causal_mode(&mut self, mode: CausalMode) {
+        self.causal_mode = mode;
+    }
+
+    /// Call `listener` after every [`Self::apply_changes`] that applies at least one
+    /// operation, and after every [`Self::add_local_op`]; [`SyncUpdate::origin`]
+    /// tells them apart. Listeners are not carried over to clones.
+    pub fn on_update(
+        &mut self,
+        listener: impl Fn(&SyncUpdate) + Send + Sync + 'static,
+    ) -> UpdateListenerId {
+        self.listeners.add(Arc::new(listener))
+    }
+
+    /// Returns whether `id` was registered.
+    pub fn remove_update_listener(&mut self, id: UpdateListenerId) -> bool {
+        self.listeners.remove(id)
+    }
+
+    pub fn op_filter(&self) -> &OpFilter {
+        &self.filter
+    }
+
+    /// Classify later [`Self::add_local_op`] calls with `filter`. Operations
+    /// already added keep their class.
+    pub fn set_op_filter(&mut self, filter: OpFilter) {
+        self.filter = filter;
+    }
+
+    /// Pass every operation leaving for a peer through `hook`, which returns it,
+    /// a rewrite under the same id, or `None` to withhold it. The second argument
+    /// is the recipient when known, as in [`Self::encode_changes_for`].
+    ///
+    /// Hooks run on [`Self::outbox`], [`Self::drain_outbox`],
+    /// [`Self::ops_needing_resend`] and [`Self::encode_changes_for`];
+    /// [`Self::encode_changes_since`] and [`Self::applied_ops`] return the log
+    /// as held, for storage and local history. A withheld operation still
+    /// leaves the outbox when its batch is marked sent.
+    pub fn on_outgoing(
+        &mut self,
+        hook: impl Fn(Operation, Option<PeerId>) -> Option<Operation> + Send + Sync + 'static,
+    ) -> OpHookId {
+        self.hooks.add_outgoing(Arc::new(hook))
+    }
+
+    /// Screen every remote operation with `hook` before it is applied, including
+    /// redeliveries, so a rewriting hook must be deterministic. A
+    /// [`Decision::Replace`] is what the log keeps and later relays to peers.
+    /// Operations applied through [`Self::apply_one`] and [`Self::apply_op`] are
+    /// not screened.
+    pub fn on_incoming(
+        &mut self,
+        hook: impl Fn(&Operation, Origin) -> Decision + Send + Sync + 'static,
+    ) -> OpHookId {
+        self.hooks.add_incoming(Arc::new(hook))
+    }
+
+    /// Returns whether `id` was registered.
+    pub fn remove_op_hook(&mut self, id: OpHookId) -> bool {
+        self.hooks.remove(id)
+    }
+
+    /// `op` after the incoming hooks, or its id when one rejects it.
+    pub(crate) fn screen_incoming(&self, op: Operation, origin: Origin) -> Result<Operation, OpId> {
+        self.hooks.incoming(op, origin)
+    }
+
+    /// `op` after the outgoing hooks for `to`, or `None` when one withholds it.
+    pub(crate) fn screen_outgoing(&self, op: Operation, to: Option<PeerId>) -> Option<Operation> {
+        self.hooks.outgoing(op, to)
+    }
+
+    /// Local-only operations, in id order.
+    pub fn local_only_ops(&self) -> Vec<Operation> {
+        self.local_only
+            .iter()
+            .map(|(id, payload)| Operation {
+                id: *id,
+                payload: payload.clone(),
+            })
+            .collect()
+    }
+
+    /// Forget local-only operations, such as view state that was superseded.
+    pub fn discard_local_only(&mut self, op_ids: &[OpId]) {
+        for op_id in op_ids {
+            self.local_only.remove(op_id);
         }
     }
 
     /// Apply a single operation (internal use)
     pub fn apply_op(&mut self, op: Operation) {
         if !self.ops.contains_key(&op.id) {
-            self.observe(op.id);
+            self.observe(op.id, 1);
             self.ops.insert(op.id, op.payload);
         }
     }
@@ -171,29 +537,76 @@ impl SyncState {
         self.ops.get(&id).map(AsRef::as_ref)
     }
 
-    /// Snapshot of all applied (id, payload) pairs for persistence.
-    pub fn applied_ops(&self) -> Vec<(OpId, Vec<u8>)> {
+    /// Number of operations in the applied log.
+    pub fn applied_count(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Payload bytes held by the applied log.
+    pub fn retained_bytes(&self) -> usize {
+        self.ops.values().map(|payload| payload.len()).sum()
+    }
+
+    /// How many of the oldest applied operations must go to fit `budget`.
+    pub fn excess_ops(&self, budget: &HistoryBudget) -> usize {
+        let mut count = self.ops.len();
+        let mut bytes = self.retained_bytes();
+        let mut excess = 0;
+        for payload in self.ops.values() {
+            if count <= 1 || (count <= budget.max_ops && bytes <= budget.max_bytes) {
+                break;
+            }
+            count -= 1;
+            bytes -= payload.len();
+            excess += 1;
+        }
+        excess
+    }
+
+    /// Snapshot of all applied (id, payload) pairs for persistence. Payloads
+    /// are shared with the log, not copied.
+    pub fn applied_ops(&self) -> Vec<(OpId, Arc<[u8]>)> {
         self.ops
             .iter()
-            .map(|(id, payload)| (*id, payload.to_vec()))
+            .map(|(id, payload)| (*id, payload.clone()))
             .collect()
     }
 
     /// Restore applied ops from a snapshot (does not touch pending/outbox).
-    pub fn restore_applied(&mut self, ops: Vec<(OpId, Vec<u8>)>) {
+    pub fn restore_applied(&mut self, ops: Vec<(OpId, Arc<[u8]>)>) {
         for (id, payload) in ops {
-            self.observe(id);
-            self.ops.insert(id, payload.into());
+            self.observe(id, 1);
+            self.ops.insert(id, payload);
         }
     }
 
-    fn observe(&mut self, id: OpId) {
+    fn observe(&mut self, id: OpId, span: u64) {
         if id.counter > self.state_vector.get(id.peer).unwrap_or(0) {
             self.state_vector.set(id.peer, id.counter);
         }
+        self.applied
+            .entry(id.peer)
+            .or_default()
+            .insert(Self::span_start(id.counter, span), id.counter);
     }
 
-    fn max_applied_counter(&self, peer: crate::core::PeerId) -> u64 {
+    /// Remember that `id`, applied under [`CausalMode::GapTolerant`], follows
+    /// counter `after` of its peer rather than the one right before its span.
+    fn observe_gap(&mut self, id: OpId, span: u64, after: u64) {
+        if self.causal_mode == CausalMode::GapTolerant
+            && after.saturating_add(1) < Self::span_start(id.counter, span)
+        {
+            self.gaps.insert(id, after);
+        }
+    }
+
+    /// Counters of `peer` in the applied log, including those covered by
+    /// multi-counter operations and by restored history.
+    pub fn applied_ranges(&self, peer: PeerId) -> Option<&CounterRanges> {
+        self.applied.get(&peer)
+    }
+
+    fn max_applied_counter(&self, peer: PeerId) -> u64 {
         self.state_vector.get(peer).unwrap_or(0)
     }
 
@@ -202,10 +615,120 @@ impl SyncState {
         id_counter.saturating_sub(span.saturating_sub(1))
     }
 
+    fn buffer(&mut self, op: Operation, span: u64, after: u64) {
+        self.pending_since.stamp(op.id);
+        self.pending_by_peer
+            .entry(op.id.peer)
+            .or_default()
+            .insert((Self::span_start(op.id.counter, span), op.id));
+        self.pending.insert(op.id, Buffered { op, span, after });
+        #[cfg(feature = "metrics")]
+        self.pending_gauge.set(self.pending.len());
+    }
+
+    fn unbuffer(&mut self, id: &OpId) -> Option<Operation> {
+        let Buffered { op, span, .. } = self.pending.remove(id)?;
+        #[cfg(feature = "metrics")]
+        self.pending_gauge.set(self.pending.len());
+        self.pending_since.clear(id);
+        if let Some(queue) = self.pending_by_peer.get_mut(&id.peer) {
+            queue.remove(&(Self::span_start(id.counter, span), *id));
+            if queue.is_empty() {
+                self.pending_by_peer.remove(&id.peer);
+            }
+        }
+        Some(op)
+    }
+
+    /// Whether an operation of `id`'s peer that covers `span` counters and declares
+    /// it follows counter `after` can be applied under [`Self::causal_mode`].
+    ///
+    /// A gap-tolerant operation still never overtakes a buffered operation of its
+    /// own peer, whose counters it cannot have skipped.
+    fn is_ready(&self, id: OpId, span: u64, after: u64) -> bool {
+        let start = Self::span_start(id.counter, span);
+        match self.causal_mode {
+            CausalMode::Strict => start <= self.max_applied_counter(id.peer) + 1,
+            CausalMode::GapTolerant => {
+                let follows = after == 0
+                    || self
+                        .applied
+                        .get(&id.peer)
+                        .is_some_and(|applied| applied.contains(after));
+                let overtakes = self
+                    .pending_by_peer
+                    .get(&id.peer)
+                    .and_then(BTreeSet::first)
+                    .is_some_and(|(first, head)| *first < start && *head != id);
+                follows && !overtakes
+            }
+        }
+    }
+
+    /// Check the gaps `message` declares against what this replica knows: a
+    /// declared gap may not skip a counter of its peer that is applied here,
+    /// buffered here, or carried by the message itself. Declarations for
+    /// operations already held are not checked, since their own span is known.
+    pub fn check_gaps(&self, message: &ChangeMessage) -> Result<(), ValidationError> {
+        for &(op_id, after) in &message.gaps {
+            if self.ops.contains_key(&op_id) || self.pending.contains_key(&op_id) {
+                continue;
+            }
+            let (start, end) = (after.saturating_add(1), op_id.counter.saturating_sub(1));
+            let contradiction = |counter: u64| (start..=end).contains(&counter);
+            let applied = self
+                .applied
+                .get(&op_id.peer)
+                .into_iter()
+                .flat_map(CounterRanges::iter)
+                .map(|range| (*range.start()).max(start)..=(*range.end()).min(end))
+                .find_map(|overlap| (!overlap.is_empty()).then(|| *overlap.start()));
+            let buffered = self
+                .pending_by_peer
+                .get(&op_id.peer)
+                .into_iter()
+                .flatten()
+                .map(|(_, id)| id.counter)
+                .find(|counter| contradiction(*counter));
+            let carried = message
+                .ops
+                .iter()
+                .filter(|op| op.id.peer == op_id.peer)
+                .map(|op| op.id.counter)
+                .find(|counter| contradiction(*counter));
+            if let Some(counter) = applied.or(buffered).or(carried) {
+                return Err(ValidationError::ContradictedGap { op_id, counter });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an operation already applied or buffered under `op`'s id carries a
+    /// different payload. Ids are unique per peer, so this means a buggy or
+    /// malicious sender, and replicas that took different payloads have diverged.
+    pub fn is_duplicate_mismatch(&self, op: &Operation) -> bool {
+        let known = self
+            .ops
+            .get(&op.id)
+            .or_else(|| self.pending.get(&op.id).map(|known| &known.op.payload));
+        known.is_some_and(|payload| *payload != op.payload)
+    }
+
     /// Integrate one operation covering `span` contiguous counters, without promoting
     /// other pending ops. `span` is 1 for a single-counter op; larger when one operation
     /// allocates a contiguous range of ids (e.g. a block plus its expanded text units).
+    ///
+    /// The operation is taken to follow the counter right before its span; see
+    /// [`Self::apply_one_after`] for one that declares a gap.
     pub fn apply_one(&mut self, op: Operation, span: u64) -> IntegrateResult {
+        let after = Self::span_start(op.id.counter, span).saturating_sub(1);
+        self.apply_one_after(op, span, after)
+    }
+
+    /// [`Self::apply_one`] for an operation that declares it follows counter `after`
+    /// of its own peer, which may lie well before its span. Under
+    /// [`CausalMode::Strict`] the declaration is ignored.
+    pub fn apply_one_after(&mut self, op: Operation, span: u64, after: u64) -> IntegrateResult {
         if self.ops.contains_key(&op.id) {
             return IntegrateResult::AlreadyPresent;
         }
@@ -214,13 +737,17 @@ impl SyncState {
             return IntegrateResult::Buffered;
         }
 
-        let frontier = self.max_applied_counter(op.id.peer);
-        let start = Self::span_start(op.id.counter, span);
-        if start > frontier + 1 {
-            self.pending.insert(op.id, (op, span));
+        if !self.is_ready(op.id, span, after) {
+            tracing::trace!(op = ?op.id, span, after, "buffering operation ahead of its peer frontier");
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_buffered(op.payload.len() as u64);
+            self.buffer(op, span, after);
             IntegrateResult::Buffered
         } else {
-            self.observe(op.id);
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_applied(1, op.payload.len() as u64);
+            self.observe(op.id, span);
+            self.observe_gap(op.id, span, after);
             self.ops.insert(op.id, op.payload);
             IntegrateResult::Applied
         }
@@ -232,23 +759,49 @@ impl SyncState {
     /// for each without re-decoding from a side map when payloads are still available.
     pub fn promote_ready_pending(&mut self) -> Vec<Operation> {
         let mut promoted = Vec::new();
-        let mut made_progress = true;
-        while made_progress {
-            made_progress = false;
-            let pending_ids: Vec<OpId> = self.pending.keys().copied().collect();
-            for op_id in pending_ids {
-                let frontier = self.max_applied_counter(op_id.peer);
-                let span = self.pending.get(&op_id).map(|(_, s)| *s).unwrap_or(1);
-                if Self::span_start(op_id.counter, span) <= frontier + 1
-                    && let Some((op, _)) = self.pending.remove(&op_id)
-                {
-                    self.observe(op.id);
+        loop {
+            // Each peer's queue is ordered by span start, so if its head is not
+            // ready nothing behind it is either.
+            let mut ready: Vec<OpId> = self
+                .pending_by_peer
+                .values()
+                .filter_map(|queue| {
+                    let (_, id) = queue.first()?;
+                    let buffered = self.pending.get(id)?;
+                    self.is_ready(*id, buffered.span, buffered.after)
+                        .then_some(*id)
+                })
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            ready.sort_unstable();
+            for op_id in ready {
+                let (span, after) = self
+                    .pending
+                    .get(&op_id)
+                    .map_or((1, 0), |buffered| (buffered.span, buffered.after));
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_promotion_latency(
+                    self.pending_since.get(&op_id, Instant::now()).elapsed(),
+                );
+                if let Some(op) = self.unbuffer(&op_id) {
+                    self.observe(op.id, span);
+                    self.observe_gap(op.id, span, after);
                     self.ops.insert(op.id, op.payload.clone());
                     promoted.push(op);
-                    made_progress = true;
                 }
             }
         }
+        if !promoted.is_empty() {
+            tracing::debug!(
+                promoted = promoted.len(),
+                pending = self.pending.len(),
+                "promoted buffered operations"
+            );
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_promoted(promoted.len() as u64);
+        }
         promoted
     }
 
@@ -262,17 +815,14 @@ impl SyncState {
         &self,
         since: &StateVector,
     ) -> Result<ChangeMessage, RebaseRequired> {
-        if self
-            .delta_floor
-            .iter()
-            .any(|(peer, floor)| since.get(peer).unwrap_or(0) < floor)
-        {
+        if below_floor(since, &self.delta_floor) {
             return Err(RebaseRequired {
                 checkpoint_epoch: self.checkpoint_epoch,
                 delta_floor: self.delta_floor.clone(),
             });
         }
         let mut ops = Vec::new();
+        let mut gaps = Vec::new();
         for (op_id, payload) in &self.ops {
             let seen = since.get(op_id.peer).unwrap_or(0);
             if op_id.counter > seen {
@@ -280,14 +830,36 @@ impl SyncState {
                     id: *op_id,
                     payload: payload.clone(),
                 });
+                gaps.extend(self.gaps.get(op_id).map(|after| (*op_id, *after)));
             }
         }
+        let bytes: usize = ops.iter().map(|op| op.payload.len()).sum();
+        tracing::debug!(ops = ops.len(), bytes, "encoded changes");
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_sent(bytes as u64);
         Ok(ChangeMessage {
             since: since.clone(),
             ops,
+            gaps,
         })
     }
 
+    /// [`Self::encode_changes_since`] as sent to `peer`: each operation passes
+    /// through the [`Self::on_outgoing`] hooks.
+    pub fn encode_changes_for(
+        &self,
+        peer: PeerId,
+        since: &StateVector,
+    ) -> Result<ChangeMessage, RebaseRequired> {
+        let mut message = self.encode_changes_since(since)?;
+        message.ops = message
+            .ops
+            .into_iter()
+            .filter_map(|op| self.screen_outgoing(op, Some(peer)))
+            .collect();
+        Ok(message)
+    }
+
     /// Compact applied history without silently invalidating an active peer lease.
     pub fn checkpoint(
         &mut self,
@@ -306,6 +878,8 @@ impl SyncState {
                 pruned_ops: 0,
                 retained_ops: self.ops.len(),
                 delta_floor: self.delta_floor.clone(),
+                collected_marks: 0,
+                dropped_tombstones: 0,
             });
         }
         let eligible: Vec<OpId> = self
@@ -332,8 +906,9 @@ impl SyncState {
             .ok_or(CheckpointError::EpochOverflow)?;
         for id in &pruned {
             self.ops.remove(id);
+            self.gaps.remove(id);
             self.outbox.remove(id);
-            self.sent.remove(id);
+            self.acks.acknowledge(*id);
             let floor = self.delta_floor.get(id.peer).unwrap_or(0).max(id.counter);
             self.delta_floor.set(id.peer, floor);
         }
@@ -343,6 +918,8 @@ impl SyncState {
             pruned_ops: pruned.len(),
             retained_ops: self.ops.len(),
             delta_floor: self.delta_floor.clone(),
+            collected_marks: 0,
+            dropped_tombstones: 0,
         })
     }
 
@@ -358,43 +935,149 @@ impl SyncState {
         &self.delta_floor
     }
 
+    /// This replica's side of a sync handshake; [`Self::delta_floor`] is the
+    /// advertised frontier floor.
+    pub fn handshake(&self) -> SyncHandshake {
+        SyncHandshake {
+            state_vector: self.state_vector.clone(),
+            frontier_floor: self.delta_floor.clone(),
+            ..SyncHandshake::default()
+        }
+    }
+
     pub(crate) fn restore_history(
         &mut self,
         state_vector: StateVector,
         checkpoint_epoch: u64,
         delta_floor: StateVector,
     ) {
+        // Everything up to the restored frontier is settled, whether or not its
+        // operations are still in the log.
+        for (peer, counter) in state_vector.iter() {
+            self.applied.entry(peer).or_default().insert(1, counter);
+        }
         self.state_vector = state_vector;
         self.checkpoint_epoch = checkpoint_epoch;
         self.delta_floor = delta_floor;
     }
 
+    /// [`Self::apply_changes_from`] a peer taken to be the author of the
+    /// message's first operation, which is the sender unless it relays others'
+    /// operations.
+    pub fn apply_changes(
+        &mut self,
+        message: ChangeMessage,
+    ) -> Result<ApplyResult, ValidationError> {
+        let sender = message.ops.first().map_or(0, |op| op.id.peer);
+        self.apply_changes_from(message, Origin::Remote(sender))
+    }
+
     /// Apply a batch of changes (log only). Promotes ready pending after each apply.
+    /// Update listeners see the applied operations tagged with `origin`.
+    ///
+    /// Under [`CausalMode::GapTolerant`], an operation listed in
+    /// [`ChangeMessage::gaps`] waits only for the counter declared there, so counters
+    /// its sender never had do not hold it back.
+    ///
+    /// The message is checked against [`Self::limits`] first and rejected whole if it
+    /// is too large or malformed. An operation that would grow the pending buffer past
+    /// `max_pending_buffer` stops the batch instead; see [`ApplyResult::resume_at`].
     ///
     /// For document-aware apply with per-op interleaving, use [`Self::apply_one`] and
     /// [`Self::promote_ready_pending`] from the session layer.
-    pub fn apply_changes(&mut self, message: ChangeMessage) -> ApplyResult {
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            ops = message.ops.len(),
+            applied = tracing::field::Empty,
+            buffered = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        )
+    )]
+    pub fn apply_changes_from(
+        &mut self,
+        message: ChangeMessage,
+        origin: Origin,
+    ) -> Result<ApplyResult, ValidationError> {
+        let started = Instant::now();
+        validation::validate_message(&message, &self.limits)?;
+        self.check_gaps(&message)?;
         let mut result = ApplyResult::default();
+        if let Some(ttl) = self.pending_ttl {
+            result.expired = self.expire_pending(started, ttl);
+        }
+        let mut applied = Vec::new();
+        let gaps = message.gap_map();
 
-        for op in message.ops {
+        for (index, op) in message.ops.into_iter().enumerate() {
             let op_id = op.id;
             // Legacy batch path: each operation covers a single counter.
-            match self.apply_one(op, 1) {
+            let after = gaps
+                .get(&op_id)
+                .map_or(op_id.counter.saturating_sub(1), |after| *after);
+            let op = match self.screen_incoming(op, origin) {
+                Ok(op) => op,
+                Err(rejected) => {
+                    result.rejected.push(rejected);
+                    continue;
+                }
+            };
+            if self.pending.len() >= self.limits.max_pending_buffer
+                && !self.ops.contains_key(&op_id)
+                && !self.pending.contains_key(&op_id)
+                && !self.is_ready(op_id, 1, after)
+            {
+                tracing::debug!(
+                    resume_at = index,
+                    capacity = self.limits.max_pending_buffer,
+                    "pending buffer full; stopping batch"
+                );
+                result.resume_at = Some(index);
+                break;
+            }
+            if self.is_duplicate_mismatch(&op) {
+                tracing::warn!(op = ?op_id, "duplicate operation id with a different payload");
+                result
+                    .conflicts
+                    .push(SemanticConflict::DuplicateOpMismatch { id: op_id });
+                continue;
+            }
+            let payload = (!self.listeners.is_empty()).then(|| op.payload.clone());
+            match self.apply_one_after(op, 1, after) {
                 IntegrateResult::AlreadyPresent => {}
                 IntegrateResult::Buffered => {
                     result.buffered.push(op_id);
                 }
                 IntegrateResult::Applied => {
                     result.applied.push(op_id);
+                    applied.extend(payload.map(|payload| Operation { id: op_id, payload }));
                     for promoted in self.promote_ready_pending() {
-                        result.buffered.retain(|id| *id != promoted.id);
                         result.applied.push(promoted.id);
+                        if !self.listeners.is_empty() {
+                            applied.push(promoted);
+                        }
                     }
                 }
             }
         }
+        // Ops buffered earlier in the batch may have been promoted since.
+        result.buffered.retain(|id| self.pending.contains_key(id));
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_origin(origin, result.applied.len() as u64);
+        if !applied.is_empty() {
+            self.listeners.notify(&SyncUpdate {
+                applied,
+                state_vector: self.state_vector.clone(),
+                origin,
+            });
+        }
 
-        result
+        let span = tracing::Span::current();
+        span.record("applied", result.applied.len());
+        span.record("buffered", result.buffered.len());
+        span.record("elapsed_us", started.elapsed().as_micros() as u64);
+        Ok(result)
     }
 
     /// Get the number of pending (causally unready) operations
@@ -402,43 +1085,239 @@ impl SyncState {
         self.pending.len()
     }
 
+    /// Buffered operations grouped by the counter they wait for, with ages measured
+    /// at `now`.
+    pub fn pending_summary(&self, now: Instant) -> PendingSummary {
+        pending::summarize(
+            self.pending
+                .iter()
+                .map(|(id, buffered)| (self.awaited(buffered), self.pending_since.get(id, now))),
+            now,
+        )
+    }
+
+    /// Depth and age of the pending buffer at `now`, per authoring peer.
+    #[cfg(feature = "metrics")]
+    pub fn pending_metrics(&self, now: Instant) -> crate::metrics::PendingMetrics {
+        crate::metrics::PendingMetrics {
+            depth: self.pending.len(),
+            depth_by_peer: self
+                .pending_by_peer
+                .iter()
+                .map(|(peer, queue)| (*peer, queue.len()))
+                .collect(),
+            oldest_age: self
+                .pending
+                .keys()
+                .map(|id| now.saturating_duration_since(self.pending_since.get(id, now)))
+                .max(),
+        }
+    }
+
+    /// Counter that blocks `buffered`: the first missing counter of its peer, or
+    /// under [`CausalMode::GapTolerant`] the first missing counter up to the one
+    /// the first buffered operation of its peer declares it follows.
+    fn awaited(&self, buffered: &Buffered) -> OpId {
+        let peer = buffered.op.id.peer;
+        let counter = match self.causal_mode {
+            CausalMode::Strict => self.max_applied_counter(peer) + 1,
+            // Everything behind the head of the peer's queue waits on the head.
+            CausalMode::GapTolerant => {
+                let after = self
+                    .pending_by_peer
+                    .get(&peer)
+                    .and_then(BTreeSet::first)
+                    .and_then(|(_, head)| self.pending.get(head))
+                    .map_or(buffered.after, |head| head.after);
+                let held = self
+                    .applied
+                    .get(&peer)
+                    .into_iter()
+                    .flat_map(CounterRanges::iter)
+                    .take_while(|range| *range.start() <= after)
+                    .last()
+                    .map_or(0, |range| (*range.end()).min(after));
+                held + 1
+            }
+        };
+        OpId { counter, peer }
+    }
+
+    pub fn pending_ttl(&self) -> Option<Duration> {
+        self.pending_ttl
+    }
+
+    /// Have [`Self::apply_changes`] first drop buffered operations older than
+    /// `ttl`, reporting them in [`ApplyResult::expired`]. `None`, the default,
+    /// keeps them until their dependencies arrive.
+    pub fn set_pending_ttl(&mut self, ttl: Option<Duration>) {
+        self.pending_ttl = ttl;
+    }
+
+    /// Drop buffered operations that have waited at least `ttl` as of `now`.
+    pub fn expire_pending(&mut self, now: Instant, ttl: Duration) -> Vec<ExpiredOp> {
+        let expired: Vec<ExpiredOp> = self
+            .pending
+            .iter()
+            .filter_map(|(id, buffered)| {
+                let age = now.saturating_duration_since(self.pending_since.get(id, now));
+                (age >= ttl).then(|| ExpiredOp {
+                    id: *id,
+                    awaited: self.awaited(buffered),
+                    age,
+                })
+            })
+            .collect();
+        for op in &expired {
+            self.unbuffer(&op.id);
+        }
+        if !expired.is_empty() {
+            tracing::debug!(
+                expired = expired.len(),
+                pending = self.pending.len(),
+                "expired buffered operations"
+            );
+        }
+        expired
+    }
+
     /// Get pending operations (for persistence). Spans are recomputed on restore.
     pub fn pending(&self) -> Vec<Operation> {
         self.pending
             .iter()
-            .map(|(id, (op, _span))| Operation {
+            .map(|(id, buffered)| Operation {
                 id: *id,
-                payload: op.payload.clone(),
+                payload: buffered.op.payload.clone(),
             })
             .collect()
     }
 
-    /// Add a local operation to the outbox
-    pub fn add_local_op(&mut self, op: Operation) {
+    /// Add a local operation to the outbox; refused on a read-only replica.
+    ///
+    /// Operations the [`OpFilter`] classes as [`OpClass::LocalOnly`] are kept
+    /// aside instead, even on a read-only replica, since peers never see them.
+    pub fn add_local_op(&mut self, op: Operation) -> Result<(), ReadOnlyReplica> {
+        self.add_local_op_from(op, Origin::Local)
+    }
+
+    /// [`Self::add_local_op`], reporting the operation to update listeners as
+    /// made by `origin`, such as [`Origin::Undo`].
+    pub fn add_local_op_from(
+        &mut self,
+        op: Operation,
+        origin: Origin,
+    ) -> Result<(), ReadOnlyReplica> {
+        if self.filter.classify(&op) == OpClass::LocalOnly {
+            self.local_only.insert(op.id, op.payload);
+            return Ok(());
+        }
+        if self.mode == ReplicaMode::ReadOnly {
+            return Err(ReadOnlyReplica { op_id: op.id });
+        }
+        let op_id = op.id;
+        // Counters set aside as local-only since the last shared operation of
+        // this peer never reach other replicas.
+        let logged = self.max_applied_counter(op_id.peer);
+        if self
+            .local_only
+            .keys()
+            .any(|id| id.peer == op_id.peer && id.counter > logged && id.counter < op_id.counter)
+        {
+            self.gaps.insert(op_id, logged);
+        }
+        self.observe(op.id, 1);
+        self.ops.insert(op.id, op.payload);
+        self.outbox.insert(op_id);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_origin(origin, 1);
+        self.notify_applied(&[op_id], origin);
+        Ok(())
+    }
+
+    /// Report the logged operations `ids` to update listeners as applied from `origin`.
+    pub(crate) fn notify_applied(&self, ids: &[OpId], origin: Origin) {
+        if self.listeners.is_empty() || ids.is_empty() {
+            return;
+        }
+        let applied = ids
+            .iter()
+            .filter_map(|id| {
+                Some(Operation {
+                    id: *id,
+                    payload: self.ops.get(id)?.clone(),
+                })
+            })
+            .collect();
+        self.listeners.notify(&SyncUpdate {
+            applied,
+            state_vector: self.state_vector.clone(),
+            origin,
+        });
+    }
+
+    /// Payload of the local operation `id` while it still waits in the outbox.
+    pub fn unsent(&self, id: OpId) -> Option<&[u8]> {
+        if !self.outbox.contains(&id) {
+            return None;
+        }
+        self.ops.get(&id).map(|payload| &payload[..])
+    }
+
+    /// Replace the unsent local operation `replaced` with `op`, which covers its
+    /// counters and continues past them, so a run of edits leaves as one
+    /// operation. Returns false, changing nothing, once `replaced` has been sent
+    /// or when the [`OpFilter`] would keep `op` local.
+    pub fn coalesce_local_op(&mut self, replaced: OpId, op: Operation) -> bool {
+        if op.id.peer != replaced.peer
+            || op.id.counter <= replaced.counter
+            || self.unsent(replaced).is_none()
+            || self.filter.classify(&op) == OpClass::LocalOnly
+        {
+            return false;
+        }
+        self.outbox.remove(&replaced);
+        self.ops.remove(&replaced);
+        if let Some(after) = self.gaps.remove(&replaced) {
+            self.gaps.insert(op.id, after);
+        }
+        self.observe(op.id, op.id.counter - replaced.counter + 1);
         let op_id = op.id;
-        self.observe(op.id);
         self.ops.insert(op.id, op.payload);
         self.outbox.insert(op_id);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_origin(Origin::Local, 1);
+        self.notify_applied(&[op_id], Origin::Local);
+        true
     }
 
-    /// Get operations that need to be sent to peers
+    /// Get operations that need to be sent to peers. Clones the whole outbox;
+    /// see [`Self::drain_outbox`] for bounded batches.
     pub fn outbox(&self) -> Vec<Operation> {
         self.outbox
             .iter()
             .filter_map(|op_id| {
-                self.ops.get(op_id).map(|payload| Operation {
-                    id: *op_id,
-                    payload: payload.clone(),
-                })
+                let payload = self.ops.get(op_id)?.clone();
+                self.screen_outgoing(
+                    Operation {
+                        id: *op_id,
+                        payload,
+                    },
+                    None,
+                )
             })
             .collect()
     }
 
     /// Mark operations as sent (move from outbox to sent)
     pub fn mark_sent(&mut self, op_ids: &[OpId]) {
+        self.mark_sent_at(op_ids, Instant::now());
+    }
+
+    /// Mark operations as sent or resent at `now`, scheduling their ack deadlines.
+    pub fn mark_sent_at(&mut self, op_ids: &[OpId], now: Instant) {
         for op_id in op_ids {
-            if self.outbox.remove(op_id) {
-                self.sent.insert(*op_id);
+            if self.outbox.remove(op_id) || self.acks.contains(*op_id) {
+                self.acks.record_sent(*op_id, now);
             }
         }
     }
@@ -446,15 +1325,41 @@ impl SyncState {
     /// Mark operations as confirmed (remove from sent tracking)
     pub fn mark_confirmed(&mut self, op_ids: &[OpId]) {
         for op_id in op_ids {
-            self.sent.remove(op_id);
+            self.acks.acknowledge(*op_id);
         }
     }
 
+    /// Sent operations whose ack deadline has passed at `now`. Pass them back to
+    /// [`Self::mark_sent_at`] after resending to back off the next deadline.
+    pub fn ops_needing_resend(&self, now: Instant) -> Vec<Operation> {
+        self.acks
+            .due(now)
+            .into_iter()
+            .filter_map(|op_id| {
+                let payload = self.ops.get(&op_id)?.clone();
+                self.screen_outgoing(Operation { id: op_id, payload }, None)
+            })
+            .collect()
+    }
+
+    /// Unacknowledged sends and their backoff state.
+    pub fn ack_tracker(&self) -> &AckTracker {
+        &self.acks
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.acks.set_policy(policy);
+    }
+
     /// Restore pending operations (for crash recovery), each with its counter span.
+    /// Declared gaps are not persisted, so each is taken to follow the counter right
+    /// before its span.
     pub fn restore_pending(&mut self, ops: Vec<(Operation, u64)>) {
         for (op, span) in ops {
             if !self.ops.contains_key(&op.id) {
-                self.pending.insert(op.id, (op, span));
+                let after = Self::span_start(op.id.counter, span).saturating_sub(1);
+                self.unbuffer(&op.id);
+                self.buffer(op, span, after);
             }
         }
     }
@@ -553,6 +1458,7 @@ mod tests {
                 },
                 payload: vec![].into(), // Empty payload is malformed
             }],
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits::default();
 
@@ -574,6 +1480,7 @@ mod tests {
                 }, // Zero counter is invalid
                 payload: vec![1].into(),
             }],
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits::default();
 
@@ -597,6 +1504,7 @@ mod tests {
                     payload: vec![1].into(),
                 })
                 .collect(),
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits {
             max_ops_per_message: 100,
@@ -621,6 +1529,7 @@ mod tests {
                 },
                 payload: vec![0; 1001].into(), // 1001 bytes
             }],
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits {
             max_payload_bytes: 1000,
@@ -645,6 +1554,7 @@ mod tests {
                 },
                 payload: vec![1].into(),
             }],
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits {
             max_pending_buffer: 10,
@@ -670,6 +1580,7 @@ mod tests {
                 },
                 payload: vec![1, 2, 3].into(),
             }],
+            gaps: Vec::new(),
         };
         let limits = ValidationLimits::default();
 
@@ -699,9 +1610,10 @@ mod tests {
                     payload: vec![2].into(),
                 },
             ],
+            gaps: Vec::new(),
         };
 
-        let result = doc.apply_changes(message);
+        let result = doc.apply_changes(message).unwrap();
 
         assert_eq!(result.applied.len(), 2);
         assert!(result.buffered.is_empty());
@@ -730,15 +1642,95 @@ mod tests {
                 },
                 payload: vec![3].into(),
             }],
+            gaps: Vec::new(),
         };
 
-        let result = doc.apply_changes(message);
+        let result = doc.apply_changes(message).unwrap();
 
         assert!(result.applied.is_empty());
         assert_eq!(result.buffered.len(), 1);
         assert_eq!(doc.pending_count(), 1);
     }
 
+    #[test]
+    fn test_apply_changes_rejects_malformed_message_whole() {
+        let mut doc = SyncState::new();
+        let message = ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![
+                Operation {
+                    id: OpId {
+                        counter: 1,
+                        peer: 1,
+                    },
+                    payload: vec![1].into(),
+                },
+                Operation {
+                    id: OpId {
+                        counter: 2,
+                        peer: 1,
+                    },
+                    payload: vec![].into(),
+                },
+            ],
+            gaps: Vec::new(),
+        };
+
+        let result = doc.apply_changes(message);
+
+        assert!(matches!(
+            result,
+            Err(ValidationError::MalformedOperation { .. })
+        ));
+        assert_eq!(doc.state_vector().get(1), None);
+    }
+
+    #[test]
+    fn test_apply_changes_stops_at_pending_limit_and_resumes() {
+        let op = |counter| Operation {
+            id: OpId { counter, peer: 1 },
+            payload: vec![1].into(),
+        };
+        let mut doc = SyncState::with_limits(ValidationLimits {
+            max_pending_buffer: 2,
+            ..Default::default()
+        });
+
+        let ops = vec![op(3), op(4), op(5), op(1)];
+        let result = doc
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops: ops.clone(),
+                gaps: Vec::new(),
+            })
+            .unwrap();
+        assert_eq!(result.buffered.len(), 2);
+        assert_eq!(result.resume_at, Some(2));
+        assert!(!result.is_complete());
+        assert_eq!(doc.pending_count(), 2);
+
+        // Ready operations are never held back by a full buffer.
+        let result = doc
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops: vec![op(1), op(2)],
+                gaps: Vec::new(),
+            })
+            .unwrap();
+        assert!(result.is_complete());
+        assert_eq!(doc.pending_count(), 0);
+
+        let result = doc
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops: ops[2..].to_vec(),
+                gaps: Vec::new(),
+            })
+            .unwrap();
+        assert!(result.is_complete());
+        assert_eq!(doc.state_vector().get(1), Some(5));
+    }
+
     #[test]
     fn test_apply_changes_unbuffers_when_ready() {
         let mut doc = SyncState::new();
@@ -761,8 +1753,9 @@ mod tests {
                 },
                 payload: vec![3].into(),
             }],
+            gaps: Vec::new(),
         };
-        doc.apply_changes(message1);
+        doc.apply_changes(message1).unwrap();
         assert_eq!(doc.pending_count(), 1);
 
         // Now apply counter=2 (should unbuffer counter=3)
@@ -775,14 +1768,50 @@ mod tests {
                 },
                 payload: vec![2].into(),
             }],
+            gaps: Vec::new(),
         };
-        let result = doc.apply_changes(message2);
+        let result = doc.apply_changes(message2).unwrap();
 
         assert_eq!(result.applied.len(), 2); // counter=2 and counter=3
         assert_eq!(doc.pending_count(), 0);
         assert_eq!(doc.state_vector().get(1), Some(3));
     }
 
+    #[test]
+    fn test_apply_changes_backfill_promotes_each_peer_in_order() {
+        let mut doc = SyncState::new();
+        let mut ops: Vec<Operation> = (1..=3u64)
+            .flat_map(|peer| {
+                (1..=4u64).map(move |counter| Operation {
+                    id: OpId { counter, peer },
+                    payload: vec![counter as u8].into(),
+                })
+            })
+            .collect();
+        ops.reverse();
+        let result = doc
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops,
+                gaps: Vec::new(),
+            })
+            .unwrap();
+
+        assert_eq!(result.applied.len(), 12);
+        assert!(result.buffered.is_empty());
+        assert_eq!(doc.pending_count(), 0);
+        for peer in 1..=3 {
+            let counters: Vec<u64> = result
+                .applied
+                .iter()
+                .filter(|id| id.peer == peer)
+                .map(|id| id.counter)
+                .collect();
+            assert_eq!(counters, [1, 2, 3, 4]);
+            assert_eq!(doc.state_vector().get(peer), Some(4));
+        }
+    }
+
     #[test]
     fn test_apply_changes_idempotent() {
         let mut doc = SyncState::new();
@@ -797,16 +1826,18 @@ mod tests {
         let message1 = ChangeMessage {
             since: StateVector::new(),
             ops: vec![op.clone()],
+            gaps: Vec::new(),
         };
-        let result1 = doc.apply_changes(message1);
+        let result1 = doc.apply_changes(message1).unwrap();
         assert_eq!(result1.applied.len(), 1);
 
         // Apply same operation again
         let message2 = ChangeMessage {
             since: StateVector::new(),
             ops: vec![op],
+            gaps: Vec::new(),
         };
-        let result2 = doc.apply_changes(message2);
+        let result2 = doc.apply_changes(message2).unwrap();
         assert!(result2.applied.is_empty()); // Already applied
     }
 
@@ -822,7 +1853,7 @@ mod tests {
             payload: vec![1, 2, 3].into(),
         };
 
-        doc.add_local_op(op.clone());
+        doc.add_local_op(op.clone()).unwrap();
 
         let outbox = doc.outbox();
         assert_eq!(outbox.len(), 1);
@@ -839,7 +1870,7 @@ mod tests {
             },
             payload: vec![1].into(),
         };
-        doc.add_local_op(op.clone());
+        doc.add_local_op(op.clone()).unwrap();
 
         assert_eq!(doc.outbox().len(), 1);
 
@@ -870,8 +1901,9 @@ mod tests {
                 },
                 payload: vec![3].into(),
             }],
+            gaps: Vec::new(),
         };
-        doc.apply_changes(message);
+        doc.apply_changes(message).unwrap();
 
         // Get pending for persistence
         let pending_ops = doc.pending();
@@ -900,8 +1932,9 @@ mod tests {
                 },
                 payload: vec![2].into(),
             }],
+            gaps: Vec::new(),
         };
-        let result = doc2.apply_changes(message2);
+        let result = doc2.apply_changes(message2).unwrap();
 
         // Both counter=2 and counter=3 should be applied
         assert_eq!(result.applied.len(), 2);
@@ -0,0 +1,29 @@
+//! Where operations entering a replica came from.
+//!
+//! Every [`SyncUpdate`](super::SyncUpdate) carries the [`Origin`] of the
+//! operations it reports, so a listener can tell its own edits from a peer's:
+//! an editor skips re-rendering the echo of what it just typed, an undo manager
+//! records only [`Origin::Local`] edits, and metrics attribute traffic by
+//! source ([`crate::metrics`] counts operations per origin).
+
+use crate::core::PeerId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Origin {
+    /// Made on this replica by its user.
+    Local,
+    /// Received from the peer `0`, which relayed them if they were authored elsewhere.
+    Remote(PeerId),
+    /// Made on this replica by reverting earlier edits.
+    Undo,
+    /// Loaded from a file, backup or other out-of-band source.
+    Import,
+}
+
+impl Origin {
+    /// Whether the operations were made on this replica.
+    pub fn is_local(self) -> bool {
+        matches!(self, Origin::Local | Origin::Undo)
+    }
+}
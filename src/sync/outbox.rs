@@ -0,0 +1,95 @@
+//! Bounded draining of the outbox for slow links.
+//!
+//! [`SyncState::drain_outbox`] hands out the oldest unsent operations in a
+//! batch capped by operation count and payload bytes, sharing the stored
+//! payloads rather than copying them. The operations stay in the outbox until
+//! the batch's [`OutboxToken`] is passed to [`SyncState::mark_batch_sent`], so a
+//! failed send simply drains the same batch again.
+
+use super::{Operation, SyncState};
+use crate::core::OpId;
+use std::time::Instant;
+
+/// Operations from one [`SyncState::drain_outbox`] call, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxBatch {
+    /// Payloads are shared with the op log.
+    pub ops: Vec<Operation>,
+    /// Payload bytes in [`Self::ops`].
+    pub bytes: usize,
+    /// Unsent operations left behind this batch.
+    pub remaining: usize,
+    /// Marks exactly these operations as sent, including any an outgoing hook
+    /// withheld from [`Self::ops`].
+    pub token: OutboxToken,
+}
+
+impl OutboxBatch {
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Receipt for an [`OutboxBatch`], redeemed with [`SyncState::mark_batch_sent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use = "the batch stays in the outbox until its token is marked sent"]
+pub struct OutboxToken {
+    ids: Vec<OpId>,
+}
+
+impl OutboxToken {
+    /// Operations the token covers.
+    pub fn op_ids(&self) -> &[OpId] {
+        &self.ids
+    }
+}
+
+impl SyncState {
+    /// The oldest unsent operations, at most `max_ops` of them and at most
+    /// `max_bytes` of payload. The first operation is always included, however
+    /// large, so draining never stalls. Leaves the outbox unchanged.
+    pub fn drain_outbox(&self, max_ops: usize, max_bytes: usize) -> OutboxBatch {
+        let mut ids = Vec::new();
+        let mut bytes = 0;
+        let unsent = self
+            .outbox
+            .iter()
+            .filter_map(|id| self.ops.get(id).map(|payload| (*id, payload)));
+        for (id, payload) in unsent {
+            if ids.len() == max_ops || (!ids.is_empty() && bytes + payload.len() > max_bytes) {
+                break;
+            }
+            bytes += payload.len();
+            ids.push(id);
+        }
+        let remaining = self.outbox.len() - ids.len();
+        // Operations an outgoing hook withholds still leave with the token.
+        let ops: Vec<Operation> = ids
+            .iter()
+            .filter_map(|id| {
+                let payload = self.ops.get(id)?.clone();
+                self.screen_outgoing(Operation { id: *id, payload }, None)
+            })
+            .collect();
+        OutboxBatch {
+            bytes: ops.iter().map(|op| op.payload.len()).sum(),
+            ops,
+            remaining,
+            token: OutboxToken { ids },
+        }
+    }
+
+    /// Mark the batch behind `token` as sent, as [`Self::mark_sent`] does.
+    /// Operations that left the outbox since the batch was drained are skipped.
+    pub fn mark_batch_sent(&mut self, token: OutboxToken) {
+        self.mark_batch_sent_at(token, Instant::now());
+    }
+
+    pub fn mark_batch_sent_at(&mut self, token: OutboxToken, now: Instant) {
+        for id in token.ids {
+            if self.outbox.remove(&id) {
+                self.acks.record_sent(id, now);
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Peer membership: which peer ids may issue operations, and rejoins.
+//!
+//! A [`PeerId`] must never reissue a counter it already used: LWW registers and
+//! RGA ordering both key on [`OpId`], so two different operations with one id
+//! silently diverge replicas. A device that loses its counter state therefore
+//! rejoins under a fresh peer id. The low [`EPOCH_BITS`] of an epoch-scheme peer
+//! id hold the epoch and the high bits the device, so [`PeerRegistry::rejoin`]
+//! can retire the old id and hand out the next epoch of the same device.
+//!
+//! Membership is an [`OrSet`] of peer ids, so registries that exchange the
+//! [`OrSetOp`]s their writes return agree in any delivery order. Like any
+//! observed-remove set it is add-wins: a registration the retiring replica had
+//! not seen keeps the peer. Retirement stays final locally, since
+//! [`PeerRegistry::register`] refuses an id that was removed.
+
+use crate::core::{Mergeable, OpId, OrSet, OrSetOp, PeerId, StateVector};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Low bits of a peer id that hold its epoch.
+pub const EPOCH_BITS: u32 = 16;
+
+/// Largest device number that fits beside the epoch.
+pub const MAX_DEVICE: u64 = u64::MAX >> EPOCH_BITS;
+
+/// Peer id for `epoch` of `device`, or `None` if `device` exceeds [`MAX_DEVICE`].
+pub fn epoch_peer_id(device: u64, epoch: u16) -> Option<PeerId> {
+    (device <= MAX_DEVICE).then_some((device << EPOCH_BITS) | u64::from(epoch))
+}
+
+/// `(device, epoch)` of an epoch-scheme peer id.
+pub fn split_peer_id(peer: PeerId) -> (u64, u16) {
+    (peer >> EPOCH_BITS, peer as u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeerStatus {
+    Active,
+    /// Left for good; its id may not issue new operations.
+    Retired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub status: PeerStatus,
+    /// Highest counter seen from this peer.
+    pub max_counter: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MembershipError {
+    #[error("peer {0} is not registered")]
+    UnknownPeer(PeerId),
+    #[error("peer {0} is retired")]
+    Retired(PeerId),
+    #[error(
+        "peer {peer} resumed at counter {next_counter} but already issued {max_counter}; rejoin with a new epoch"
+    )]
+    CounterReset {
+        peer: PeerId,
+        max_counter: u64,
+        next_counter: u64,
+    },
+    #[error("device {0} has no epochs left")]
+    EpochExhausted(u64),
+}
+
+/// Known peers with their status and counter high-water marks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRegistry {
+    members: OrSet<PeerId>,
+    /// Highest counter seen per peer; local bookkeeping, joined by maximum.
+    max_counters: BTreeMap<PeerId, u64>,
+}
+
+impl PeerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with every peer of `applied` active at its applied counter,
+    /// each registered by the last operation of it that was applied.
+    pub fn from_state_vector(applied: &StateVector) -> Self {
+        let mut registry = Self::new();
+        for (peer, counter) in applied.iter() {
+            registry.members.add(peer, OpId { counter, peer });
+            registry.max_counters.insert(peer, counter);
+        }
+        registry
+    }
+
+    /// Admit `peer` as the write `op_id`, returning the op to ship (`None` if it
+    /// is already active). A retired id is refused.
+    pub fn register(
+        &mut self,
+        peer: PeerId,
+        op_id: OpId,
+    ) -> Result<Option<OrSetOp<PeerId>>, MembershipError> {
+        match self.status(peer) {
+            Some(PeerStatus::Retired) => Err(MembershipError::Retired(peer)),
+            Some(PeerStatus::Active) => Ok(None),
+            None => Ok(Some(self.members.add(peer, op_id))),
+        }
+    }
+
+    /// Retire `peer` as the write `op_id` so later operations under its id are
+    /// refused, returning the op to ship (`None` if it was already retired).
+    pub fn retire(
+        &mut self,
+        peer: PeerId,
+        op_id: OpId,
+    ) -> Result<Option<OrSetOp<PeerId>>, MembershipError> {
+        match self.status(peer) {
+            None => Err(MembershipError::UnknownPeer(peer)),
+            Some(_) => Ok(self.members.remove(&peer, op_id)),
+        }
+    }
+
+    pub fn record(&self, peer: PeerId) -> Option<PeerRecord> {
+        self.status(peer).map(|status| PeerRecord {
+            status,
+            max_counter: self.max_counter(peer),
+        })
+    }
+
+    pub fn contains(&self, peer: PeerId) -> bool {
+        self.members.contains(&peer)
+    }
+
+    /// Active peers in id order.
+    pub fn active_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.members.iter().copied()
+    }
+
+    /// Admit an operation from an active peer and raise its high-water mark.
+    pub fn observe(&mut self, op_id: OpId) -> Result<(), MembershipError> {
+        self.check_active(op_id.peer)?;
+        let max_counter = self.max_counters.entry(op_id.peer).or_default();
+        *max_counter = (*max_counter).max(op_id.counter);
+        Ok(())
+    }
+
+    /// Check that `peer`, reconnecting with its clock at `next_counter`, will not
+    /// reissue counters it already used.
+    pub fn check_resume(&self, peer: PeerId, next_counter: u64) -> Result<(), MembershipError> {
+        self.check_active(peer)?;
+        let max_counter = self.max_counter(peer);
+        if next_counter <= max_counter {
+            return Err(MembershipError::CounterReset {
+                peer,
+                max_counter,
+                next_counter,
+            });
+        }
+        Ok(())
+    }
+
+    /// Retire every epoch of `peer`'s device and register the next one, all as
+    /// the write `op_id`, returning the new id and the ops to ship. The device
+    /// then starts again at counter 1 without colliding with old ops.
+    pub fn rejoin(
+        &mut self,
+        peer: PeerId,
+        op_id: OpId,
+    ) -> Result<(PeerId, Vec<OrSetOp<PeerId>>), MembershipError> {
+        if self.status(peer).is_none() {
+            return Err(MembershipError::UnknownPeer(peer));
+        }
+        let (device, epoch) = split_peer_id(peer);
+        let epochs: Vec<PeerId> = self
+            .members
+            .known()
+            .copied()
+            .filter(|known| split_peer_id(*known).0 == device)
+            .collect();
+        let newest = epochs
+            .iter()
+            .map(|known| split_peer_id(*known).1)
+            .max()
+            .unwrap_or(epoch);
+        let next = newest
+            .checked_add(1)
+            .and_then(|next| epoch_peer_id(device, next))
+            .ok_or(MembershipError::EpochExhausted(device))?;
+        let mut ops: Vec<_> = epochs
+            .iter()
+            .filter_map(|known| self.members.remove(known, op_id))
+            .collect();
+        ops.extend(self.register(next, op_id)?);
+        Ok((next, ops))
+    }
+
+    /// Apply a registration or retirement from another registry. Commutative
+    /// and idempotent.
+    pub fn apply(&mut self, op: OrSetOp<PeerId>) {
+        self.members.apply(op);
+    }
+
+    /// State-based join with another registry, high-water marks included.
+    pub fn merge(&mut self, other: &Self) {
+        self.members.merge(&other.members);
+        for (peer, counter) in &other.max_counters {
+            let max_counter = self.max_counters.entry(*peer).or_default();
+            *max_counter = (*max_counter).max(*counter);
+        }
+    }
+
+    /// The frontier of every registration and retirement held.
+    pub fn state_vector(&self) -> StateVector {
+        self.members.frontier()
+    }
+
+    /// Registrations and retirements a registry at `since` has not seen.
+    pub fn ops_since(&self, since: &StateVector) -> Vec<OrSetOp<PeerId>> {
+        self.members.ops_since(since)
+    }
+
+    fn status(&self, peer: PeerId) -> Option<PeerStatus> {
+        if self.members.contains(&peer) {
+            Some(PeerStatus::Active)
+        } else if self.members.known().any(|known| *known == peer) {
+            Some(PeerStatus::Retired)
+        } else {
+            None
+        }
+    }
+
+    fn check_active(&self, peer: PeerId) -> Result<(), MembershipError> {
+        match self.status(peer) {
+            Some(PeerStatus::Active) => Ok(()),
+            Some(PeerStatus::Retired) => Err(MembershipError::Retired(peer)),
+            None => Err(MembershipError::UnknownPeer(peer)),
+        }
+    }
+
+    fn max_counter(&self, peer: PeerId) -> u64 {
+        self.max_counters.get(&peer).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,94 @@
+//! Replica modes and update listeners for followers.
+//!
+//! A read-only replica (a dashboard, a renderer) applies remote changes but never
+//! originates operations, so it mints no counters under its
+//! [`PeerId`](crate::core::PeerId) and cannot collide with a writer that reuses
+//! the id. Followers observe progress through
+//! [`SyncState::on_update`](super::SyncState::on_update) instead of polling the
+//! state vector.
+
+use super::{Operation, Origin};
+use crate::core::{OpId, StateVector};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Whether a replica may originate operations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicaMode {
+    #[default]
+    ReadWrite,
+    /// Applies remote changes; local operations are refused and nothing is queued to send.
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("read-only replica refused local operation {op_id:?}")]
+pub struct ReadOnlyReplica {
+    pub op_id: OpId,
+}
+
+/// Operations one call moved into the applied log, in application order: a
+/// [`SyncState::apply_changes`](super::SyncState::apply_changes) batch or one
+/// [`SyncState::add_local_op`](super::SyncState::add_local_op).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncUpdate {
+    pub applied: Vec<Operation>,
+    /// State vector after the batch.
+    pub state_vector: StateVector,
+    pub origin: Origin,
+}
+
+/// Handle for removing a listener registered with
+/// [`SyncState::on_update`](super::SyncState::on_update).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UpdateListenerId(u64);
+
+type Listener = Arc<dyn Fn(&SyncUpdate) + Send + Sync>;
+
+/// Registered update callbacks. Cloning yields an empty set: a cloned state is a
+/// different replica, and its updates should not reach the original's followers.
+#[derive(Default)]
+pub(super) struct UpdateListeners {
+    next_id: u64,
+    listeners: Vec<(UpdateListenerId, Listener)>,
+}
+
+impl UpdateListeners {
+    pub(super) fn add(&mut self, listener: Listener) -> UpdateListenerId {
+        let id = UpdateListenerId(self.next_id);
+        self.next_id += 1;
+        self.listeners.push((id, listener));
+        id
+    }
+
+    pub(super) fn remove(&mut self, id: UpdateListenerId) -> bool {
+        let before = self.listeners.len();
+        self.listeners.retain(|(existing, _)| *existing != id);
+        self.listeners.len() != before
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    pub(super) fn notify(&self, update: &SyncUpdate) {
+        for (_, listener) in &self.listeners {
+            listener(update);
+        }
+    }
+}
+
+impl Clone for UpdateListeners {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for UpdateListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpdateListeners")
+            .field("len", &self.listeners.len())
+            .finish()
+    }
+}
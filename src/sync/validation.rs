@@ -13,6 +13,9 @@ pub enum ValidationError {
     /// Message exceeds configured resource limits
     #[error("resource limit exceeded: {actual} > {limit}")]
     ResourceLimitExceeded { limit: usize, actual: usize },
+    /// A declared gap skips a counter the receiver knows exists
+    #[error("operation {op_id:?} declares counter {counter} of its peer skipped, but it exists")]
+    ContradictedGap { op_id: OpId, counter: u64 },
     /// Pending operation buffer is full (backpressure)
     #[error("buffer full (capacity: {capacity})")]
     BufferFull { capacity: usize },
@@ -41,7 +44,7 @@ impl std::fmt::Display for MalformedKind {
 }
 
 /// Configuration for validation limits
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationLimits {
     pub max_ops_per_message: usize,
     pub max_payload_bytes: usize,
@@ -59,10 +62,43 @@ impl Default for ValidationLimits {
 }
 
 /// Validate a change message against configured limits
+#[tracing::instrument(level = "debug", skip_all, fields(ops = message.ops.len(), pending_count))]
 pub fn validate_changes(
     message: &ChangeMessage,
     limits: &ValidationLimits,
     pending_count: usize,
+) -> Result<(), ValidationError> {
+    let result = check_message_size(message, limits)
+        .and_then(|()| check_pending_buffer(message, limits, pending_count))
+        .and_then(|()| check_operations(message));
+    if let Err(error) = &result {
+        reject(error);
+    }
+    result
+}
+
+/// Every check of [`validate_changes`] except the pending buffer, which
+/// [`SyncState::apply_changes`](super::SyncState::apply_changes) enforces per operation.
+pub(super) fn validate_message(
+    message: &ChangeMessage,
+    limits: &ValidationLimits,
+) -> Result<(), ValidationError> {
+    let result = check_message_size(message, limits).and_then(|()| check_operations(message));
+    if let Err(error) = &result {
+        reject(error);
+    }
+    result
+}
+
+fn reject(error: &ValidationError) {
+    tracing::debug!(%error, "rejected change message");
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_rejected();
+}
+
+fn check_message_size(
+    message: &ChangeMessage,
+    limits: &ValidationLimits,
 ) -> Result<(), ValidationError> {
     // Check operation count limit
     if message.ops.len() > limits.max_ops_per_message {
@@ -80,14 +116,24 @@ pub fn validate_changes(
             actual: total_payload,
         });
     }
+    Ok(())
+}
 
+fn check_pending_buffer(
+    message: &ChangeMessage,
+    limits: &ValidationLimits,
+    pending_count: usize,
+) -> Result<(), ValidationError> {
     // Check if pending buffer would overflow (backpressure)
     if pending_count + message.ops.len() > limits.max_pending_buffer {
         return Err(ValidationError::BufferFull {
             capacity: limits.max_pending_buffer,
         });
     }
+    Ok(())
+}
 
+fn check_operations(message: &ChangeMessage) -> Result<(), ValidationError> {
     // Validate each operation
     for op in &message.ops {
         // Check for empty payload (malformed)
@@ -107,6 +153,14 @@ pub fn validate_changes(
         }
     }
 
+    // A declared gap lies before the operation it belongs to.
+    if let Some((id, _)) = message.gaps.iter().find(|(id, after)| *after >= id.counter) {
+        return Err(ValidationError::MalformedOperation {
+            op_id: *id,
+            kind: MalformedKind::InvalidSequence,
+        });
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,17 @@
+//! Test harnesses for code built on this crate.
+//!
+//! - [`roundtrip_corpus`] runs a directory of Markdown files through the parser
+//!   and serializer and reports the files that do not round-trip, so a vault can
+//!   be checked before adopting the crate.
+//! - `OfflinePeer` (feature `storage`) drives replicas that go offline between
+//!   sessions through storage.
+
+#[cfg(feature = "storage")]
+mod offline;
+mod roundtrip;
+
+#[cfg(feature = "storage")]
+pub use offline::{OfflinePeer, assert_converged};
+pub use roundtrip::{
+    CorpusReport, RoundTripFailure, RoundTripInvariant, check_roundtrip, roundtrip_corpus,
+};
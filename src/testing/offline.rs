@@ -0,0 +1,234 @@
+//! Test harness for replicas that go offline between sessions.
+//!
+//! An [`OfflinePeer`] owns a [`CollaborativeDocument`] backed by its own
+//! [`Storage`] directory. [`OfflinePeer::go_offline`] writes the session to
+//! storage and drops it; [`OfflinePeer::resume`] reloads it and checks that the
+//! op log, the pending buffer, the clock and the unacknowledged local
+//! operations all survived the restart, so tests can exercise storage, pending
+//! restore and causal buffering end to end.
+
+use crate::core::{PeerId, StateVector};
+use crate::doc::EquivalenceMode;
+use crate::session::{
+    CollaborativeDocument, SessionApplyResult, SessionError, SessionSnapshot, SnapshotError,
+};
+use crate::storage::Storage;
+use crate::sync::{ChangeMessage, Operation, ValidationLimits};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_DIR: AtomicU64 = AtomicU64::new(0);
+
+/// A replica that can be persisted, dropped and reloaded between exchanges.
+///
+/// The storage directory is created under the system temp dir and removed when
+/// the peer is dropped.
+pub struct OfflinePeer {
+    peer: PeerId,
+    dir: PathBuf,
+    storage: Storage,
+    session: Option<CollaborativeDocument>,
+    /// Frontier the last replica this peer sent to had reached afterwards.
+    acknowledged: StateVector,
+    /// State written by the last [`Self::go_offline`], checked on resume.
+    saved: Option<SessionSnapshot>,
+}
+
+impl OfflinePeer {
+    /// An online peer with an empty document and fresh storage.
+    pub fn new(peer: PeerId) -> Result<Self, SnapshotError> {
+        let dir = std::env::temp_dir().join(format!(
+            "md-crdt-offline-{}-{}-{peer}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let storage = Storage::open(&dir)?;
+        Ok(Self {
+            peer,
+            dir,
+            storage,
+            session: Some(CollaborativeDocument::new(peer)),
+            acknowledged: StateVector::new(),
+            saved: None,
+        })
+    }
+
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    pub fn storage_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// # Panics
+    ///
+    /// Panics while the peer is offline.
+    pub fn session(&self) -> &CollaborativeDocument {
+        self.session
+            .as_ref()
+            .unwrap_or_else(|| panic!("peer {} is offline", self.peer))
+    }
+
+    /// # Panics
+    ///
+    /// Panics while the peer is offline.
+    pub fn session_mut(&mut self) -> &mut CollaborativeDocument {
+        let peer = self.peer;
+        self.session
+            .as_mut()
+            .unwrap_or_else(|| panic!("peer {peer} is offline"))
+    }
+
+    /// Local operations the replica this peer last sent to had not yet seen,
+    /// in id order.
+    pub fn unacknowledged(&self) -> Result<Vec<Operation>, SessionError> {
+        let message = self.session().encode_changes_since(&self.acknowledged)?;
+        Ok(message
+            .ops
+            .into_iter()
+            .filter(|op| op.id.peer == self.peer)
+            .collect())
+    }
+
+    /// Write the session to storage and drop it. A peer already offline stays so.
+    pub fn go_offline(&mut self) -> Result<(), SnapshotError> {
+        let Some(session) = self.session.take() else {
+            return Ok(());
+        };
+        session.write_to_storage(&self.storage)?;
+        self.saved = Some(session.save_snapshot()?);
+        Ok(())
+    }
+
+    /// Reload the session from storage. A peer already online stays as it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the reloaded session differs from the one taken offline in
+    /// its applied operations, pending or deferred operations, frontier, clock,
+    /// document, or unacknowledged local operations.
+    pub fn resume(&mut self) -> Result<(), SnapshotError> {
+        if self.session.is_some() {
+            return Ok(());
+        }
+        let session = CollaborativeDocument::read_from_storage(&self.storage)?;
+        let restored = session.save_snapshot()?;
+        self.session = Some(session);
+        if let Some(saved) = self.saved.take() {
+            let peer = self.peer;
+            assert_eq!(restored.ops, saved.ops, "peer {peer}: op log changed");
+            assert_eq!(
+                restored.pending, saved.pending,
+                "peer {peer}: pending buffer changed"
+            );
+            assert_eq!(
+                restored.deferred, saved.deferred,
+                "peer {peer}: deferred operations changed"
+            );
+            assert_eq!(
+                restored.state_vector, saved.state_vector,
+                "peer {peer}: frontier changed"
+            );
+            assert_eq!(
+                restored.next_counter, saved.next_counter,
+                "peer {peer}: clock changed"
+            );
+            assert_eq!(
+                restored
+                    .document
+                    .into_document()
+                    .serialize(EquivalenceMode::Exact),
+                saved
+                    .document
+                    .into_document()
+                    .serialize(EquivalenceMode::Exact),
+                "peer {peer}: document changed"
+            );
+            let acknowledged = self.acknowledged.get(peer).unwrap_or(0);
+            let unsent: Vec<_> = saved
+                .ops
+                .into_iter()
+                .filter(|(id, _)| id.peer == peer && id.counter > acknowledged)
+                .map(|(id, payload)| Operation { id, payload })
+                .collect();
+            assert_eq!(
+                self.unacknowledged()
+                    .unwrap_or_else(|error| panic!("peer {peer}: {error}")),
+                unsent,
+                "peer {peer}: unacknowledged local operations changed"
+            );
+        }
+        Ok(())
+    }
+
+    /// Apply `message` under default limits.
+    pub fn receive(&mut self, message: ChangeMessage) -> Result<SessionApplyResult, SessionError> {
+        self.session_mut()
+            .apply_remote(message, &ValidationLimits::default())
+    }
+
+    /// Send `other` every operation it has not seen, and remember how far it got.
+    pub fn send_to(&mut self, other: &mut Self) -> Result<SessionApplyResult, SessionError> {
+        let message = self
+            .session()
+            .encode_changes_since(&other.session().state_vector())?;
+        let result = other.receive(message)?;
+        self.acknowledged = other.session().state_vector();
+        Ok(result)
+    }
+
+    /// Exchange changes both ways.
+    pub fn sync_with(&mut self, other: &mut Self) -> Result<(), SessionError> {
+        self.send_to(other)?;
+        other.send_to(self)?;
+        Ok(())
+    }
+}
+
+impl Drop for OfflinePeer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Assert that every peer is online with the same frontier, nothing buffered,
+/// and the same document.
+///
+/// # Panics
+///
+/// Panics when two peers differ, naming the first pair that does.
+pub fn assert_converged(peers: &[&OfflinePeer]) {
+    let Some((first, rest)) = peers.split_first() else {
+        return;
+    };
+    let expected = first.session().document().serialize(EquivalenceMode::Exact);
+    for peer in peers {
+        let summary = peer.session().pending_summary(std::time::Instant::now());
+        assert!(
+            summary.is_empty(),
+            "peer {} still buffers operations: {summary:?}",
+            peer.peer()
+        );
+    }
+    for peer in rest {
+        assert_eq!(
+            peer.session().state_vector(),
+            first.session().state_vector(),
+            "peers {} and {} have different frontiers",
+            first.peer(),
+            peer.peer()
+        );
+        assert_eq!(
+            peer.session().document().serialize(EquivalenceMode::Exact),
+            expected,
+            "peers {} and {} have different documents",
+            first.peer(),
+            peer.peer()
+        );
+    }
+}
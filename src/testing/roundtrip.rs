@@ -0,0 +1,188 @@
+//! Round-trip checks over a directory of real Markdown files.
+//!
+//! [`roundtrip_corpus`] parses every `.md` file under a directory and checks the
+//! invariants the serializer promises: [`EquivalenceMode::Exact`] output is the
+//! input byte for byte, and [`EquivalenceMode::Structural`] output is a fixed
+//! point that parses back to the same block tree. A file that breaks one is
+//! shrunk to a small input that still breaks it, line by line, so the report
+//! names a case short enough to read; [`CorpusReport::write_failures`] dumps
+//! those cases as files to attach to a bug report.
+
+use crate::doc::{EquivalenceMode, Parser};
+use std::fmt;
+use std::io;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Path, PathBuf};
+
+/// A round-trip property a Markdown input failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RoundTripInvariant {
+    /// Parsing or serializing panicked.
+    NoPanic,
+    /// Exact serialization did not reproduce the input.
+    ExactSource,
+    /// Structural output changed when parsed and serialized again.
+    StructuralFixedPoint,
+    /// Structural output parsed to a different block tree than the input.
+    StructuralTree,
+}
+
+impl fmt::Display for RoundTripInvariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoPanic => "parse and serialize without panicking",
+            Self::ExactSource => "exact output equals the input",
+            Self::StructuralFixedPoint => "structural output is a fixed point",
+            Self::StructuralTree => "structural output keeps the block tree",
+        })
+    }
+}
+
+/// One corpus file that failed, with the smallest input found that still fails
+/// the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTripFailure {
+    pub path: PathBuf,
+    pub invariant: RoundTripInvariant,
+    pub minimized: String,
+}
+
+/// Outcome of [`roundtrip_corpus`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorpusReport {
+    pub files_checked: usize,
+    /// Failures in path order, at most one per file.
+    pub failures: Vec<RoundTripFailure>,
+}
+
+impl CorpusReport {
+    pub fn is_clean(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Write each minimized case to `dir` as `<n>-<file stem>.md`, creating
+    /// the directory, and return the paths written.
+    pub fn write_failures(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        let mut written = Vec::with_capacity(self.failures.len());
+        for (index, failure) in self.failures.iter().enumerate() {
+            let stem = failure
+                .path
+                .file_stem()
+                .map_or_else(|| "case".into(), |stem| stem.to_string_lossy());
+            let path = dir.join(format!("{index:03}-{stem}.md"));
+            std::fs::write(&path, &failure.minimized)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+impl fmt::Display for CorpusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} files failed a round trip",
+            self.failures.len(),
+            self.files_checked
+        )?;
+        for failure in &self.failures {
+            write!(
+                f,
+                "\n{}: {}; minimized: {:?}",
+                failure.path.display(),
+                failure.invariant,
+                failure.minimized
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check every `.md` file under `path` (recursively, skipping hidden entries).
+/// Files that are not UTF-8 are skipped and not counted.
+pub fn roundtrip_corpus(path: impl AsRef<Path>) -> io::Result<CorpusReport> {
+    let mut files = Vec::new();
+    collect_markdown(path.as_ref(), &mut files)?;
+    files.sort();
+    let mut report = CorpusReport::default();
+    for file in files {
+        let Ok(markdown) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        report.files_checked += 1;
+        if let Err(invariant) = check_roundtrip(&markdown) {
+            report.failures.push(RoundTripFailure {
+                path: file,
+                invariant,
+                minimized: minimize(&markdown, invariant),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Check one input against every round-trip invariant, returning the first it
+/// fails in [`RoundTripInvariant`] order.
+pub fn check_roundtrip(markdown: &str) -> Result<(), RoundTripInvariant> {
+    let checked = catch_unwind(AssertUnwindSafe(|| {
+        let document = Parser::parse(markdown);
+        if document.serialize(EquivalenceMode::Exact) != markdown {
+            return Err(RoundTripInvariant::ExactSource);
+        }
+        let structural = document.serialize(EquivalenceMode::Structural);
+        let reparsed = Parser::parse(&structural);
+        if reparsed.serialize(EquivalenceMode::Structural) != structural {
+            return Err(RoundTripInvariant::StructuralFixedPoint);
+        }
+        if !document.structurally_equal(&reparsed) {
+            return Err(RoundTripInvariant::StructuralTree);
+        }
+        Ok(())
+    }));
+    checked.unwrap_or(Err(RoundTripInvariant::NoPanic))
+}
+
+/// Drop chunks of lines, halving the chunk size down to single lines, for as
+/// long as the input still fails `invariant`.
+fn minimize(markdown: &str, invariant: RoundTripInvariant) -> String {
+    let fails = |lines: &[&str]| check_roundtrip(&lines.concat()) == Err(invariant);
+    let mut lines: Vec<&str> = markdown.split_inclusive('\n').collect();
+    let mut chunk = lines.len().div_ceil(2).max(1);
+    loop {
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<&str> = lines[..start]
+                .iter()
+                .chain(&lines[end..])
+                .copied()
+                .collect();
+            if fails(&candidate) {
+                lines = candidate;
+            } else {
+                start = end;
+            }
+        }
+        if chunk == 1 {
+            return lines.concat();
+        }
+        chunk = chunk.div_ceil(2);
+    }
+}
+
+fn collect_markdown(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_markdown(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -818,10 +818,7 @@ pub(crate) fn validate_code_fence(
     info: Option<&str>,
     text: &str,
 ) -> Result<(), StructuredEditError> {
-    let marker = match style.marker {
-        crate::doc::FenceMarker::Backtick => '`',
-        crate::doc::FenceMarker::Tilde => '~',
-    };
+    let marker = style.marker.symbol();
     let longest = text
         .lines()
         .map(|line| {
@@ -832,7 +829,16 @@ pub(crate) fn validate_code_fence(
         })
         .max()
         .unwrap_or(0);
-    if style.length < 3 || usize::from(style.length) <= longest {
+    let min_length = if style.marker == crate::doc::FenceMarker::Dollar {
+        2
+    } else {
+        3
+    };
+    if style.length < min_length || usize::from(style.length) <= longest {
+        return Err(StructuredEditError::CodeFence);
+    }
+    // Display math always renders as a bare `$$` pair.
+    if style.marker == crate::doc::FenceMarker::Dollar && (style.length != 2 || info.is_some()) {
         return Err(StructuredEditError::CodeFence);
     }
     if info.is_some_and(|info| {
@@ -1196,17 +1202,12 @@ impl Document {
                         anchor: anchor.elem_id,
                     });
                 }
-                let ids = paragraph_visible_ids(text);
-                let index = ids.iter().position(|id| *id == anchor.elem_id).ok_or(
+                text.offset_map().anchor_offset(&anchor).ok_or(
                     WorkspaceTargetError::DeletedAnchor {
                         block_id: point.block_id,
                         anchor: anchor.elem_id,
                     },
-                )?;
-                Ok(match anchor.bias {
-                    AnchorBias::Before => index,
-                    AnchorBias::After => index + 1,
-                })
+                )
             }
         }
     }
@@ -2439,6 +2440,7 @@ fn hash_mark_kind(digest: &mut StableDigest, kind: &MarkKind) {
         MarkKind::Italic => digest.field(b"italic"),
         MarkKind::Code => digest.field(b"code"),
         MarkKind::Link => digest.field(b"link"),
+        MarkKind::Strikethrough => digest.field(b"strikethrough"),
         MarkKind::Custom(name) => {
             digest.field(b"custom");
             digest.field(name.as_bytes());
@@ -0,0 +1,348 @@
+//! YATA integration of decoded items into one root `Y.Text`.
+//!
+//! Items are expanded to one node per character (or per clock for non-text
+//! content) so origins that point inside an item resolve without splitting.
+//! Integration follows `Item.integrate` from Yjs, so the resulting order does
+//! not depend on the order structs appear in the update.
+
+use super::YjsError;
+use super::update::{Content, Item, Parent, Update, YId};
+use std::collections::{BTreeMap, HashSet};
+
+/// Formatting attributes in effect for a run of text.
+pub(super) type Attributes = BTreeMap<String, serde_json::Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Unit {
+    Char(char),
+    Format {
+        key: String,
+        value: serde_json::Value,
+    },
+    Opaque,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    /// Placed in the target text.
+    Linked,
+    /// Belongs to another root, a nested type, or a map entry.
+    Elsewhere,
+    /// Depends on garbage-collected structs.
+    Dead,
+}
+
+#[derive(Debug)]
+struct Node {
+    id: YId,
+    /// Clock ticks covered (2 for characters outside the BMP).
+    len: u64,
+    origin: Option<YId>,
+    right_origin: Option<YId>,
+    parent: Option<Parent>,
+    has_parent_sub: bool,
+    unit: Unit,
+    deleted: bool,
+    state: State,
+    right: Option<usize>,
+}
+
+/// Resolved reference to another clock tick.
+enum Target {
+    Node(usize),
+    Collected,
+}
+
+/// Visible text of `root` as `(text, attributes)` runs with merged formatting.
+pub(super) fn text_runs(
+    update: &Update,
+    root: &str,
+) -> Result<Vec<(String, Attributes)>, YjsError> {
+    let mut list = TextList::new(update);
+    for node in 0..list.nodes.len() {
+        list.ensure(node, root)?;
+    }
+    for (id, len) in &update.deletes {
+        list.delete(*id, *len);
+    }
+    Ok(list.runs())
+}
+
+struct TextList {
+    nodes: Vec<Node>,
+    /// Node position keyed by its first clock tick.
+    index: BTreeMap<YId, usize>,
+    gc: BTreeMap<YId, u64>,
+    start: Option<usize>,
+}
+
+impl TextList {
+    fn new(update: &Update) -> Self {
+        let mut list = Self {
+            nodes: Vec::new(),
+            index: BTreeMap::new(),
+            gc: update.gc.iter().copied().collect(),
+            start: None,
+        };
+        for item in &update.items {
+            list.expand(item);
+        }
+        list
+    }
+
+    fn expand(&mut self, item: &Item) {
+        let mut clock = item.id.clock;
+        let mut origin = item.origin;
+        let mut push = |list: &mut Self, len: u64, unit: Unit, deleted: bool| {
+            let id = YId {
+                client: item.id.client,
+                clock,
+            };
+            list.index.insert(id, list.nodes.len());
+            list.nodes.push(Node {
+                id,
+                len,
+                origin,
+                right_origin: item.right_origin,
+                parent: item.parent.clone(),
+                has_parent_sub: item.parent_sub.is_some(),
+                unit,
+                deleted,
+                state: State::Pending,
+                right: None,
+            });
+            clock += len;
+            origin = Some(YId {
+                client: item.id.client,
+                clock: clock - 1,
+            });
+        };
+        match &item.content {
+            Content::String(text) => {
+                for ch in text.chars() {
+                    push(self, ch.len_utf16() as u64, Unit::Char(ch), false);
+                }
+            }
+            Content::Format { key, value } => push(
+                self,
+                1,
+                Unit::Format {
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+                false,
+            ),
+            Content::Deleted(len) => {
+                for _ in 0..*len {
+                    push(self, 1, Unit::Opaque, true);
+                }
+            }
+            Content::Opaque(len) => {
+                for _ in 0..*len {
+                    push(self, 1, Unit::Opaque, false);
+                }
+            }
+        }
+    }
+
+    fn lookup(&self, id: YId) -> Result<Target, YjsError> {
+        let covers =
+            |first: &YId, len: u64| first.client == id.client && id.clock - first.clock < len;
+        if let Some((first, position)) = self.index.range(..=id).next_back()
+            && covers(first, self.nodes[*position].len)
+        {
+            return Ok(Target::Node(*position));
+        }
+        if let Some((first, len)) = self.gc.range(..=id).next_back()
+            && covers(first, *len)
+        {
+            return Ok(Target::Collected);
+        }
+        Err(YjsError::MissingDependency {
+            client: id.client,
+            clock: id.clock,
+        })
+    }
+
+    /// Integrate `node` after every node its origins point at.
+    fn ensure(&mut self, node: usize, root: &str) -> Result<(), YjsError> {
+        let mut stack = vec![node];
+        let mut on_stack = HashSet::from([node]);
+        while let Some(&current) = stack.last() {
+            if self.nodes[current].state != State::Pending {
+                stack.pop();
+                on_stack.remove(&current);
+                continue;
+            }
+            let mut blocked = None;
+            for reference in [self.nodes[current].origin, self.nodes[current].right_origin]
+                .into_iter()
+                .flatten()
+            {
+                if let Target::Node(dependency) = self.lookup(reference)?
+                    && self.nodes[dependency].state == State::Pending
+                {
+                    blocked = Some(dependency);
+                    break;
+                }
+            }
+            match blocked {
+                Some(dependency) => {
+                    if !on_stack.insert(dependency) {
+                        return Err(YjsError::Invalid("cyclic origins"));
+                    }
+                    stack.push(dependency);
+                }
+                None => self.place(current, root)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn place(&mut self, node: usize, root: &str) -> Result<(), YjsError> {
+        let origin = self.resolve(self.nodes[node].origin)?;
+        let right = self.resolve(self.nodes[node].right_origin)?;
+        let state = match (&origin, &right) {
+            (Some(Target::Collected), _) | (_, Some(Target::Collected)) => State::Dead,
+            // Items with an origin inherit the parent of the item they attach to.
+            (Some(Target::Node(neighbor)), _) | (None, Some(Target::Node(neighbor))) => {
+                self.nodes[*neighbor].state
+            }
+            (None, None) => match &self.nodes[node].parent {
+                Some(Parent::Root(name)) if name == root && !self.nodes[node].has_parent_sub => {
+                    State::Linked
+                }
+                _ => State::Elsewhere,
+            },
+        };
+        self.nodes[node].state = state;
+        if state == State::Linked {
+            let as_node = |target: Option<Target>| match target {
+                Some(Target::Node(position)) => Some(position),
+                _ => None,
+            };
+            self.integrate(node, as_node(origin), as_node(right));
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, id: Option<YId>) -> Result<Option<Target>, YjsError> {
+        id.map(|id| self.lookup(id)).transpose()
+    }
+
+    /// Yjs `Item.integrate`: skip concurrent inserts between the origins that
+    /// order before this one.
+    fn integrate(&mut self, node: usize, origin: Option<usize>, right: Option<usize>) {
+        let mut left = origin;
+        let mut cursor = match origin {
+            Some(origin) => self.nodes[origin].right,
+            None => self.start,
+        };
+        let mut before_origin = HashSet::new();
+        let mut conflicting = HashSet::new();
+        while let Some(other) = cursor {
+            if Some(other) == right {
+                break;
+            }
+            before_origin.insert(other);
+            conflicting.insert(other);
+            let other_origin = self.origin_node(other);
+            if other_origin == origin {
+                if self.nodes[other].id.client < self.nodes[node].id.client {
+                    left = Some(other);
+                    conflicting.clear();
+                } else if self.right_origin_node(other) == right {
+                    break;
+                }
+            } else if let Some(other_origin) = other_origin
+                && before_origin.contains(&other_origin)
+            {
+                if !conflicting.contains(&other_origin) {
+                    left = Some(other);
+                    conflicting.clear();
+                }
+            } else {
+                break;
+            }
+            cursor = self.nodes[other].right;
+        }
+
+        match left {
+            Some(left) => {
+                self.nodes[node].right = self.nodes[left].right;
+                self.nodes[left].right = Some(node);
+            }
+            None => {
+                self.nodes[node].right = self.start;
+                self.start = Some(node);
+            }
+        }
+    }
+
+    fn origin_node(&self, node: usize) -> Option<usize> {
+        match self.nodes[node].origin.map(|id| self.lookup(id)) {
+            Some(Ok(Target::Node(position))) => Some(position),
+            _ => None,
+        }
+    }
+
+    fn right_origin_node(&self, node: usize) -> Option<usize> {
+        match self.nodes[node].right_origin.map(|id| self.lookup(id)) {
+            Some(Ok(Target::Node(position))) => Some(position),
+            _ => None,
+        }
+    }
+
+    fn delete(&mut self, first: YId, len: u64) {
+        let last = YId {
+            client: first.client,
+            clock: first.clock.saturating_add(len),
+        };
+        let start = match self.index.range(..=first).next_back() {
+            Some((id, _)) if id.client == first.client => *id,
+            _ => first,
+        };
+        let covered: Vec<usize> = self
+            .index
+            .range(start..last)
+            .map(|(_, position)| *position)
+            .collect();
+        for position in covered {
+            let node = &mut self.nodes[position];
+            if node.id.clock + node.len > first.clock {
+                node.deleted = true;
+            }
+        }
+    }
+
+    fn runs(&self) -> Vec<(String, Attributes)> {
+        let mut runs: Vec<(String, Attributes)> = Vec::new();
+        let mut attributes = BTreeMap::new();
+        let mut cursor = self.start;
+        while let Some(node) = cursor {
+            let current = &self.nodes[node];
+            cursor = current.right;
+            if current.deleted {
+                continue;
+            }
+            match &current.unit {
+                Unit::Char(ch) => match runs.last_mut() {
+                    Some((text, run_attributes)) if *run_attributes == attributes => {
+                        text.push(*ch);
+                    }
+                    _ => runs.push((ch.to_string(), attributes.clone())),
+                },
+                Unit::Format { key, value } => {
+                    if value.is_null() {
+                        attributes.remove(key);
+                    } else {
+                        attributes.insert(key.clone(), value.clone());
+                    }
+                }
+                Unit::Opaque => {}
+            }
+        }
+        runs
+    }
+}
@@ -0,0 +1,309 @@
+//! Import and export of Yjs rich text (`Y.Text`) updates.
+//!
+//! The bridge follows the Quill delta convention most Yjs editors use: text
+//! attributes become marks (`bold`, `italic`, `code`, `strike`, `link` →
+//! [`MarkKind`];
+//! any other key → [`MarkKind::Custom`] carrying its value under `"value"`,
+//! in the `yjs` namespace unless the key is already `namespace:name`),
+//! each newline ends a paragraph, and a `header` attribute on the newline makes
+//! it a heading. Blocks without a rich-text form (lists, code, tables, ...)
+//! export as their Markdown, one line per paragraph. Attribute keys that cannot
+//! name a mark kind are dropped.
+//!
+//! Imported documents use the parser's id scheme (peer 0, counters from 1), so
+//! importing the same update twice yields identical documents.
+
+mod list;
+mod update;
+
+use crate::core::mark::{Anchor, AnchorBias, MarkInterval, MarkKind, MarkValue};
+use crate::core::{OpId, Sequence};
+use crate::doc::{
+    Block, BlockKind, Document, block_text_seq, grapheme_count, paragraph_visible_ids,
+    serialize_block,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+use update::Content;
+
+/// Namespace of custom marks imported from attributes without one.
+const YJS_NAMESPACE: &str = "yjs";
+
+/// Errors decoding a Yjs update.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum YjsError {
+    #[error("truncated update")]
+    Truncated,
+    #[error("invalid update: {0}")]
+    Invalid(&'static str),
+    #[error("unsupported content type {0}")]
+    UnsupportedContent(u8),
+    #[error("update references missing struct ({client}, {clock})")]
+    MissingDependency { client: u64, clock: u64 },
+}
+
+/// One run of a text delta: text sharing the same formatting attributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaRun {
+    pub insert: String,
+    pub attributes: BTreeMap<String, Value>,
+}
+
+/// Visible content of root text `root` in a v1 update, like `Y.Text.toDelta()`.
+pub fn text_delta(update: &[u8], root: &str) -> Result<Vec<DeltaRun>, YjsError> {
+    let runs = list::text_runs(&update::decode(update)?, root)?;
+    Ok(runs
+        .into_iter()
+        .map(|(insert, attributes)| DeltaRun { insert, attributes })
+        .collect())
+}
+
+/// Build a [`Document`] from root text `root` in a v1 update.
+pub fn import_update(update: &[u8], root: &str) -> Result<Document, YjsError> {
+    Ok(document_from_delta(&text_delta(update, root)?))
+}
+
+/// Encode `doc` as a v1 update creating root text `root` from `client`.
+pub fn export_update(doc: &Document, root: &str, client: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    let mut current = BTreeMap::new();
+    for run in document_delta(doc) {
+        switch_attributes(&mut contents, &mut current, &run.attributes);
+        contents.push(Content::String(run.insert));
+    }
+    switch_attributes(&mut contents, &mut current, &BTreeMap::new());
+    update::encode_chain(client, root, &contents)
+}
+
+/// Delta `doc` exports as: one newline-terminated line per block.
+pub fn document_delta(doc: &Document) -> Vec<DeltaRun> {
+    let mut delta: Vec<DeltaRun> = Vec::new();
+    let mut push = |insert: String, attributes: BTreeMap<String, Value>| match delta.last_mut() {
+        Some(last) if last.attributes == attributes => last.insert.push_str(&insert),
+        _ => delta.push(DeltaRun { insert, attributes }),
+    };
+    for block in doc.blocks_in_order() {
+        let Some(text) = block_text_seq(&block.kind) else {
+            for line in serialize_block(block).lines() {
+                push(format!("{line}\n"), BTreeMap::new());
+            }
+            continue;
+        };
+        let offsets = text.offset_map();
+        let mut unit_attributes = vec![BTreeMap::new(); offsets.len()];
+        for (interval, start, end) in block.marks.resolved_intervals_in(&offsets) {
+            let (key, value) = mark_attribute(interval);
+            for attributes in &mut unit_attributes[start..end] {
+                attributes.insert(key.clone(), value.clone());
+            }
+        }
+        for (unit, attributes) in text.iter().zip(unit_attributes) {
+            push(unit.grapheme.clone(), attributes);
+        }
+        let mut line_end = BTreeMap::new();
+        if let BlockKind::Heading { level, .. } = &block.kind {
+            line_end.insert("header".to_string(), Value::from(*level));
+        }
+        push("\n".to_string(), line_end);
+    }
+    delta
+}
+
+/// Emit format items moving the open attributes from `current` to `next`.
+fn switch_attributes(
+    contents: &mut Vec<Content>,
+    current: &mut BTreeMap<String, Value>,
+    next: &BTreeMap<String, Value>,
+) {
+    for key in current.keys() {
+        if !next.contains_key(key) {
+            contents.push(Content::Format {
+                key: key.clone(),
+                value: Value::Null,
+            });
+        }
+    }
+    for (key, value) in next {
+        if current.get(key) != Some(value) {
+            contents.push(Content::Format {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    *current = next.clone();
+}
+
+fn mark_attribute(interval: &MarkInterval) -> (String, Value) {
+    let attr = |name: &str| {
+        interval
+            .attrs
+            .get(name)
+            .map(|register| match register.get_ref() {
+                MarkValue::String(value) => Value::from(value.as_str()),
+                MarkValue::Bool(value) => Value::from(*value),
+            })
+            .unwrap_or(Value::Bool(true))
+    };
+    match &interval.kind {
+        MarkKind::Bold => ("bold".to_string(), Value::Bool(true)),
+        MarkKind::Italic => ("italic".to_string(), Value::Bool(true)),
+        MarkKind::Code => ("code".to_string(), Value::Bool(true)),
+        MarkKind::Link => ("link".to_string(), attr("href")),
+        MarkKind::Strikethrough => ("strike".to_string(), Value::Bool(true)),
+        MarkKind::Custom(_) => {
+            let key = match interval.kind.namespace() {
+                Some(YJS_NAMESPACE) => interval.kind.local_name(),
+                _ => interval.kind.as_str(),
+            };
+            (key.to_string(), attr("value"))
+        }
+    }
+}
+
+/// Mark for a text attribute; `None` for attributes that switch formatting off.
+fn attribute_mark(key: &str, value: &Value) -> Option<(MarkKind, BTreeMap<String, MarkValue>)> {
+    if value.is_null() || *value == Value::Bool(false) {
+        return None;
+    }
+    let mut attrs = BTreeMap::new();
+    let kind = match key {
+        // Line attribute, read from the newline by `line_block`.
+        "header" => return None,
+        "bold" => MarkKind::Bold,
+        "italic" => MarkKind::Italic,
+        "code" => MarkKind::Code,
+        "strike" => MarkKind::Strikethrough,
+        "link" => {
+            if let Some(href) = value.as_str() {
+                attrs.insert("href".to_string(), MarkValue::String(href.to_string()));
+            }
+            MarkKind::Link
+        }
+        name => {
+            let kind = name
+                .parse::<MarkKind>()
+                .or_else(|_| MarkKind::custom(YJS_NAMESPACE, name))
+                .ok()?;
+            match value {
+                Value::Bool(true) => {}
+                Value::String(text) => {
+                    attrs.insert("value".to_string(), MarkValue::String(text.clone()));
+                }
+                json => {
+                    attrs.insert("value".to_string(), MarkValue::String(json.to_string()));
+                }
+            }
+            kind
+        }
+    };
+    Some((kind, attrs))
+}
+
+fn document_from_delta(delta: &[DeltaRun]) -> Document {
+    let mut counter = 1u64;
+    let mut blocks = Vec::new();
+    let mut line: Vec<(&str, &BTreeMap<String, Value>)> = Vec::new();
+    for run in delta {
+        let mut pieces = run.insert.split('\n').peekable();
+        while let Some(piece) = pieces.next() {
+            if !piece.is_empty() {
+                line.push((piece, &run.attributes));
+            }
+            if pieces.peek().is_some() {
+                blocks.extend(line_block(&line, &run.attributes, &mut counter));
+                line.clear();
+            }
+        }
+    }
+    blocks.extend(line_block(&line, &BTreeMap::new(), &mut counter));
+
+    let mut doc = Document::new();
+    *doc.blocks_mut() = Sequence::from_ordered(
+        blocks
+            .into_iter()
+            .map(|block| (block.elem_id, block))
+            .collect(),
+    );
+    doc
+}
+
+fn next_op_id(counter: &mut u64) -> OpId {
+    let id = OpId {
+        counter: *counter,
+        peer: 0,
+    };
+    *counter += 1;
+    id
+}
+
+/// Block for one line; empty paragraphs have no Markdown form and are dropped.
+fn line_block(
+    line: &[(&str, &BTreeMap<String, Value>)],
+    line_end: &BTreeMap<String, Value>,
+    counter: &mut u64,
+) -> Option<Block> {
+    let text: String = line.iter().map(|(piece, _)| *piece).collect();
+    let level = line_end
+        .get("header")
+        .and_then(Value::as_u64)
+        .map(|level| level.clamp(1, 6) as u8);
+    if text.is_empty() && level.is_none() {
+        return None;
+    }
+
+    let elem_id = next_op_id(counter);
+    let start = OpId {
+        counter: *counter,
+        peer: 0,
+    };
+    *counter += grapheme_count(&text) as u64;
+    let kind = match level {
+        Some(level) => BlockKind::heading(level, &text, start),
+        None => BlockKind::paragraph(&text, start),
+    };
+    let mut block = Block::new(kind, elem_id);
+    let ids = block_text_seq(&block.kind)
+        .map(paragraph_visible_ids)
+        .unwrap_or_default();
+
+    // Grapheme range each attribute value covers, merged across adjacent runs.
+    let mut ranges: Vec<(&str, &Value, usize, usize)> = Vec::new();
+    let mut offset = 0;
+    for (piece, attributes) in line {
+        let end = offset + piece.graphemes(true).count();
+        for (key, value) in attributes.iter() {
+            match ranges.iter_mut().rev().find(|range| range.0 == key) {
+                Some(range) if range.1 == value && range.3 == offset => range.3 = end,
+                _ => ranges.push((key, value, offset, end)),
+            }
+        }
+        offset = end;
+    }
+    for (key, value, start, end) in ranges {
+        let Some((kind, attrs)) = attribute_mark(key, value) else {
+            continue;
+        };
+        if start >= end || end > ids.len() {
+            continue;
+        }
+        let interval_id = next_op_id(counter);
+        block.marks.set_mark(
+            interval_id,
+            kind,
+            Anchor {
+                elem_id: ids[start],
+                bias: AnchorBias::Before,
+            },
+            Anchor {
+                elem_id: ids[end - 1],
+                bias: AnchorBias::After,
+            },
+            attrs,
+            interval_id,
+        );
+    }
+    Some(block)
+}
@@ -0,0 +1,312 @@
+//! Yjs update v1 wire format (lib0 variable-length encoding).
+//!
+//! Only the struct kinds that can appear in a `Y.Text` are decoded into
+//! content; embeds, JSON, binary, and nested types are kept as opaque slots so
+//! clocks and origins still line up. `ContentAny` and sub-documents are
+//! rejected.
+
+use super::YjsError;
+
+const INFO_ORIGIN: u8 = 0x80;
+const INFO_RIGHT_ORIGIN: u8 = 0x40;
+const INFO_PARENT_SUB: u8 = 0x20;
+const INFO_CONTENT: u8 = 0x1f;
+
+const CONTENT_GC: u8 = 0;
+const CONTENT_DELETED: u8 = 1;
+const CONTENT_JSON: u8 = 2;
+const CONTENT_BINARY: u8 = 3;
+const CONTENT_STRING: u8 = 4;
+const CONTENT_EMBED: u8 = 5;
+const CONTENT_FORMAT: u8 = 6;
+const CONTENT_TYPE: u8 = 7;
+const CONTENT_SKIP: u8 = 10;
+
+/// Yjs type refs whose `ContentType` carries a node name.
+const TYPE_XML_ELEMENT: u64 = 3;
+const TYPE_XML_HOOK: u64 = 5;
+
+/// `(client, clock)` of one Yjs clock tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct YId {
+    pub client: u64,
+    pub clock: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Parent {
+    Root(String),
+    Item(YId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Content {
+    Deleted(u64),
+    String(String),
+    Format {
+        key: String,
+        value: serde_json::Value,
+    },
+    /// Countable content with no text (embeds, nested types, JSON, binary).
+    Opaque(u64),
+}
+
+impl Content {
+    /// Clock ticks the content occupies; strings count UTF-16 code units.
+    pub fn len(&self) -> u64 {
+        match self {
+            Content::Deleted(len) | Content::Opaque(len) => *len,
+            Content::String(text) => text.encode_utf16().count() as u64,
+            Content::Format { .. } => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Item {
+    pub id: YId,
+    pub origin: Option<YId>,
+    pub right_origin: Option<YId>,
+    /// Present only when neither origin is set.
+    pub parent: Option<Parent>,
+    pub parent_sub: Option<String>,
+    pub content: Content,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Update {
+    pub items: Vec<Item>,
+    /// Garbage-collected `(first id, len)` ranges.
+    pub gc: Vec<(YId, u64)>,
+    /// Delete set as `(first id, len)` ranges.
+    pub deletes: Vec<(YId, u64)>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, YjsError> {
+        let byte = *self.bytes.get(self.pos).ok_or(YjsError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn var_uint(&mut self) -> Result<u64, YjsError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(YjsError::Invalid("varuint overflow"))
+    }
+
+    fn len(&mut self) -> Result<usize, YjsError> {
+        usize::try_from(self.var_uint()?).map_err(|_| YjsError::Invalid("length overflow"))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], YjsError> {
+        let len = self.len()?;
+        let end = self.pos.checked_add(len).ok_or(YjsError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(YjsError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn string(&mut self) -> Result<String, YjsError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| YjsError::Invalid("string is not UTF-8"))
+    }
+
+    fn json(&mut self) -> Result<serde_json::Value, YjsError> {
+        let text = self.string()?;
+        // Yjs writes `undefined` for missing values.
+        if text == "undefined" {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&text).map_err(|_| YjsError::Invalid("format value is not JSON"))
+    }
+
+    fn id(&mut self) -> Result<YId, YjsError> {
+        Ok(YId {
+            client: self.var_uint()?,
+            clock: self.var_uint()?,
+        })
+    }
+
+    fn content(&mut self, kind: u8) -> Result<Content, YjsError> {
+        Ok(match kind {
+            CONTENT_DELETED => Content::Deleted(self.var_uint()?),
+            CONTENT_JSON => {
+                let len = self.var_uint()?;
+                for _ in 0..len {
+                    self.string()?;
+                }
+                Content::Opaque(len)
+            }
+            CONTENT_BINARY => {
+                self.bytes()?;
+                Content::Opaque(1)
+            }
+            CONTENT_STRING => Content::String(self.string()?),
+            CONTENT_EMBED => {
+                self.string()?;
+                Content::Opaque(1)
+            }
+            CONTENT_FORMAT => Content::Format {
+                key: self.string()?,
+                value: self.json()?,
+            },
+            CONTENT_TYPE => {
+                let type_ref = self.var_uint()?;
+                if type_ref == TYPE_XML_ELEMENT || type_ref == TYPE_XML_HOOK {
+                    self.string()?;
+                }
+                Content::Opaque(1)
+            }
+            other => return Err(YjsError::UnsupportedContent(other)),
+        })
+    }
+}
+
+/// Decode a v1 update (`Y.encodeStateAsUpdate` / `Y.encodeStateAsUpdateV1`).
+pub(super) fn decode(bytes: &[u8]) -> Result<Update, YjsError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut update = Update::default();
+
+    for _ in 0..reader.var_uint()? {
+        let structs = reader.var_uint()?;
+        let client = reader.var_uint()?;
+        let mut clock = reader.var_uint()?;
+        for _ in 0..structs {
+            let info = reader.u8()?;
+            let id = YId { client, clock };
+            let len = match info & INFO_CONTENT {
+                CONTENT_GC => {
+                    let len = reader.var_uint()?;
+                    update.gc.push((id, len));
+                    len
+                }
+                CONTENT_SKIP => reader.var_uint()?,
+                kind => {
+                    let origin = (info & INFO_ORIGIN != 0).then(|| reader.id()).transpose()?;
+                    let right_origin = (info & INFO_RIGHT_ORIGIN != 0)
+                        .then(|| reader.id())
+                        .transpose()?;
+                    let explicit_parent = origin.is_none() && right_origin.is_none();
+                    let parent = if explicit_parent {
+                        Some(if reader.var_uint()? == 1 {
+                            Parent::Root(reader.string()?)
+                        } else {
+                            Parent::Item(reader.id()?)
+                        })
+                    } else {
+                        None
+                    };
+                    let parent_sub = (explicit_parent && info & INFO_PARENT_SUB != 0)
+                        .then(|| reader.string())
+                        .transpose()?;
+                    let content = reader.content(kind)?;
+                    let len = content.len();
+                    update.items.push(Item {
+                        id,
+                        origin,
+                        right_origin,
+                        parent,
+                        parent_sub,
+                        content,
+                    });
+                    len
+                }
+            };
+            clock = clock
+                .checked_add(len)
+                .ok_or(YjsError::Invalid("clock overflow"))?;
+        }
+    }
+
+    for _ in 0..reader.var_uint()? {
+        let client = reader.var_uint()?;
+        for _ in 0..reader.var_uint()? {
+            let clock = reader.var_uint()?;
+            let len = reader.var_uint()?;
+            update.deletes.push((YId { client, clock }, len));
+        }
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(YjsError::Invalid("trailing bytes"));
+    }
+    Ok(update)
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn var_uint(&mut self, mut value: u64) {
+        while value > 0x7f {
+            self.bytes.push(0x80 | (value & 0x7f) as u8);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn string(&mut self, text: &str) {
+        self.var_uint(text.len() as u64);
+        self.bytes.extend_from_slice(text.as_bytes());
+    }
+}
+
+/// Encode one client's items, chained left to right from clock 0 under root
+/// type `root`, with an empty delete set.
+pub(super) fn encode_chain(client: u64, root: &str, contents: &[Content]) -> Vec<u8> {
+    let mut writer = Writer::default();
+    if contents.is_empty() {
+        writer.var_uint(0);
+        writer.var_uint(0);
+        return writer.bytes;
+    }
+    writer.var_uint(1);
+    writer.var_uint(contents.len() as u64);
+    writer.var_uint(client);
+    writer.var_uint(0);
+
+    let mut clock = 0u64;
+    for content in contents {
+        let kind = match content {
+            Content::String(_) => CONTENT_STRING,
+            Content::Format { .. } => CONTENT_FORMAT,
+            Content::Deleted(_) | Content::Opaque(_) => {
+                unreachable!("export emits only strings and formats")
+            }
+        };
+        if clock == 0 {
+            writer.bytes.push(kind);
+            writer.var_uint(1);
+            writer.string(root);
+        } else {
+            writer.bytes.push(kind | INFO_ORIGIN);
+            writer.var_uint(client);
+            writer.var_uint(clock - 1);
+        }
+        match content {
+            Content::String(text) => writer.string(text),
+            Content::Format { key, value } => {
+                writer.string(key);
+                writer.string(&value.to_string());
+            }
+            Content::Deleted(_) | Content::Opaque(_) => {}
+        }
+        clock += content.len();
+    }
+    writer.var_uint(0);
+    writer.bytes
+}
@@ -4,10 +4,11 @@
 
 use md_crdt::codec::{
     BlockKindSkeleton, BlockSkeleton, BlockSkeletonInsert, CodecError, DocOp, Envelope,
-    JsonOpCodec, ListItemSkeleton, MAX_WIRE_NEST_DEPTH, MovedTextUnitWire, OpBody, OpCodec,
-    TableCellWire, TextBlockKindWire, WIRE_VERSION, insert_block_paragraph_is_empty,
+    JsonOpCodec, ListItemSkeleton, MAX_WIRE_NEST_DEPTH, MIN_WIRE_VERSION, MovedTextUnitWire,
+    OpBody, OpCodec, TableCellWire, TextBlockKindWire, WIRE_VERSION,
+    insert_block_paragraph_is_empty,
 };
-use md_crdt::core::{OpId, StateVector};
+use md_crdt::core::{Anchor, AnchorBias, MarkKind, OpId, StateVector};
 use md_crdt::doc::BlockId;
 use md_crdt::{CodeFenceStyle, ListStyle, TaskState};
 use uuid::Uuid;
@@ -23,6 +24,7 @@ fn block_id(n: u128) -> BlockId {
 fn sample_insert_block(text: &str) -> Envelope {
     Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -52,6 +54,7 @@ fn insert_block_with_after_and_right_origin_round_trip() {
     let codec = JsonOpCodec;
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: Some(op(2, 1)),
@@ -76,6 +79,7 @@ fn delete_block_round_trip() {
     let codec = JsonOpCodec;
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::DeleteBlock {
             parent: None,
             target: op(5, 1),
@@ -96,6 +100,40 @@ fn unknown_wire_version_rejected() {
     assert!(matches!(err, CodecError::UnknownVersion(v) if v == WIRE_VERSION + 99));
 }
 
+#[test]
+fn previous_wire_version_still_decodes() {
+    // A v4 peer spells mark kinds the old way; v5 only changed that spelling.
+    let bytes = br#"{"version":4,"meta":null,"body":{"Doc":{"SetMark":{"block_elem":{"counter":2,"peer":1},"block_id":"00000000-0000-0000-0000-000000000001","id":{"counter":9,"peer":1},"kind":"Bold","start":{"elem_id":{"counter":3,"peer":1},"bias":"Before"},"end":{"elem_id":{"counter":4,"peer":1},"bias":"After"},"attrs":{}}}}}"#;
+    let env = JsonOpCodec.decode(bytes).expect("v4 decodes");
+    assert_eq!(env.version, MIN_WIRE_VERSION);
+    assert_eq!(
+        env.body,
+        OpBody::Doc(DocOp::SetMark {
+            block_elem: op(2, 1),
+            block_id: block_id(1),
+            id: op(9, 1),
+            kind: MarkKind::Bold,
+            start: Anchor {
+                elem_id: op(3, 1),
+                bias: AnchorBias::Before,
+            },
+            end: Anchor {
+                elem_id: op(4, 1),
+                bias: AnchorBias::After,
+            },
+            attrs: Default::default(),
+        })
+    );
+
+    let mut env = sample_insert_block("");
+    env.version = MIN_WIRE_VERSION - 1;
+    let bytes = JsonOpCodec.encode(&env).expect("encode");
+    assert!(matches!(
+        JsonOpCodec.decode(&bytes),
+        Err(CodecError::UnknownVersion(v)) if v == MIN_WIRE_VERSION - 1
+    ));
+}
+
 #[test]
 fn malformed_json_rejected() {
     let codec = JsonOpCodec;
@@ -125,6 +163,7 @@ fn nest_depth_exceeded_on_encode() {
     }
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -162,6 +201,7 @@ fn nest_depth_exceeded_on_decode() {
     }
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -207,6 +247,7 @@ fn insert_block_paragraph_is_empty_helper() {
     // Paragraph InsertBlock can violate the unit-mode empty rule).
     let del = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::DeleteBlock {
             parent: None,
             target: op(1, 1),
@@ -221,6 +262,7 @@ fn raw_and_quote_kinds_round_trip() {
     let codec = JsonOpCodec;
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -253,6 +295,7 @@ fn heading_and_list_kinds_round_trip() {
     let codec = JsonOpCodec;
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -332,6 +375,7 @@ fn table_row_ops_round_trip() {
     for operation in operations {
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(operation),
         };
         let bytes = codec.encode(&envelope).expect("encode");
@@ -344,6 +388,7 @@ fn table_block_skeleton_round_trip() {
     let codec = JsonOpCodec;
     let envelope = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -429,6 +474,7 @@ fn structured_mutation_ops_round_trip() {
     for operation in operations {
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(operation),
         };
         let bytes = codec.encode(&envelope).expect("encode");
@@ -468,6 +514,7 @@ fn split_and_merge_block_ops_round_trip() {
     for operation in operations {
         let envelope = Envelope {
             version: WIRE_VERSION,
+            meta: None,
             body: OpBody::Doc(operation),
         };
         let bytes = codec.encode(&envelope).expect("encode");
@@ -0,0 +1,184 @@
+//! Golden-file conformance vectors for the sync formats.
+//!
+//! `tests/fixtures/conformance` holds the exact bytes this crate writes for a
+//! fixed two-peer history: every operation envelope, the compact change
+//! message carrying them, and the session snapshot, plus a manifest naming the
+//! format versions and the Markdown each vector must decode to. Independent
+//! implementations can check themselves against the same files. Regenerate with
+//! `MD_CRDT_UPDATE_FIXTURES=1` after a deliberate format change, and bump
+//! `FORMAT_VERSION` with it.
+
+use md_crdt::codec::{JsonOpCodec, OpCodec, WIRE_VERSION};
+use md_crdt::core::{MarkKind, MarkValue, StateVector};
+use md_crdt::doc::{EquivalenceMode, block_id_from_op};
+use md_crdt::session::{CollaborativeDocument, SNAPSHOT_FORMAT_VERSION, SessionSnapshot};
+use md_crdt::sync::{
+    ChangeMessage, FORMAT_VERSION, FormatMismatch, MIN_FORMAT_VERSION, SyncHandshake,
+    ValidationLimits,
+};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/conformance")
+}
+
+fn markdown(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Exact)
+}
+
+/// A fixed history: peer 1 writes and formats, with a built-in and a custom mark, and
+/// peer 2 edits the text it has seen.
+fn history() -> CollaborativeDocument {
+    let mut a = CollaborativeDocument::new(1);
+    let mut b = CollaborativeDocument::new(2);
+    a.set_frontmatter_field("title", Some("Vectors".into()))
+        .unwrap();
+    let block = block_id_from_op(a.insert_paragraph(None, "Hello world").unwrap());
+    a.set_mark(block, 0..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    let delimiter = BTreeMap::from([("delimiter".to_string(), MarkValue::String("==".into()))]);
+    a.set_mark(
+        block,
+        1..4,
+        MarkKind::custom("app", "highlight").unwrap(),
+        delimiter,
+    )
+    .unwrap();
+    b.apply_remote(
+        a.encode_changes_since(&b.state_vector()).unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+    b.insert_text(block, 11, ", again").unwrap();
+    b.delete_text(block, 5, 6).unwrap();
+    a.apply_remote(
+        b.encode_changes_since(&a.state_vector()).unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+    a
+}
+
+/// Every vector file by name, and the manifest describing them.
+fn vectors() -> (BTreeMap<String, Vec<u8>>, Value) {
+    let doc = history();
+    let message = doc.encode_changes_since(&StateVector::new()).unwrap();
+    let mut files = BTreeMap::new();
+    let mut envelopes = Vec::new();
+    for (index, op) in message.ops.iter().enumerate() {
+        let name = format!("envelope-{:02}.json", index + 1);
+        envelopes.push(json!({
+            "file": name,
+            "id": { "counter": op.id.counter, "peer": op.id.peer },
+        }));
+        files.insert(name, op.payload.to_vec());
+    }
+    files.insert("change-message.bin".into(), message.to_compact_bytes());
+    files.insert(
+        "snapshot.json".into(),
+        doc.save_snapshot().unwrap().to_bytes().unwrap(),
+    );
+    let manifest = json!({
+        "format_version": FORMAT_VERSION,
+        "wire_version": WIRE_VERSION,
+        "snapshot_format_version": SNAPSHOT_FORMAT_VERSION,
+        "envelopes": envelopes,
+        "change_message": "change-message.bin",
+        "snapshot": "snapshot.json",
+        "markdown": markdown(&doc),
+    });
+    (files, manifest)
+}
+
+fn read(name: &str) -> Vec<u8> {
+    fs::read(fixture_dir().join(name)).unwrap_or_else(|error| panic!("{name}: {error}"))
+}
+
+#[test]
+fn writer_output_matches_golden_files() {
+    let (files, manifest) = vectors();
+    if std::env::var_os("MD_CRDT_UPDATE_FIXTURES").is_some() {
+        let dir = fixture_dir();
+        fs::create_dir_all(&dir).unwrap();
+        for (name, bytes) in &files {
+            fs::write(dir.join(name), bytes).unwrap();
+        }
+        fs::write(
+            dir.join("manifest.json"),
+            serde_json::to_string_pretty(&manifest).unwrap() + "\n",
+        )
+        .unwrap();
+        return;
+    }
+    let frozen: Value = serde_json::from_slice(&read("manifest.json")).unwrap();
+    assert_eq!(
+        frozen, manifest,
+        "format changed; bump FORMAT_VERSION and regenerate"
+    );
+    for (name, bytes) in &files {
+        assert!(read(name) == *bytes, "{name} differs from its golden file");
+    }
+}
+
+#[test]
+fn golden_files_decode_to_the_recorded_document() {
+    if std::env::var_os("MD_CRDT_UPDATE_FIXTURES").is_some() {
+        return;
+    }
+    let manifest: Value = serde_json::from_slice(&read("manifest.json")).unwrap();
+    let expected = manifest["markdown"].as_str().unwrap();
+    assert_eq!(manifest["format_version"], FORMAT_VERSION);
+
+    let message = ChangeMessage::from_compact_bytes(&read("change-message.bin")).unwrap();
+    let envelopes = manifest["envelopes"].as_array().unwrap();
+    assert_eq!(message.ops.len(), envelopes.len());
+    for (op, vector) in message.ops.iter().zip(envelopes) {
+        let bytes = read(vector["file"].as_str().unwrap());
+        assert_eq!(op.payload.as_ref(), bytes.as_slice());
+        assert_eq!(vector["id"]["counter"], op.id.counter);
+        assert_eq!(vector["id"]["peer"], op.id.peer);
+        let envelope = JsonOpCodec.decode(&bytes).unwrap();
+        assert_eq!(JsonOpCodec.encode(&envelope).unwrap(), bytes);
+    }
+    let mut replayed = CollaborativeDocument::new(3);
+    replayed
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(markdown(&replayed), expected);
+
+    let snapshot = SessionSnapshot::from_bytes(&read("snapshot.json")).unwrap();
+    let restored = CollaborativeDocument::restore_from_snapshot(snapshot).unwrap();
+    assert_eq!(markdown(&restored), expected);
+}
+
+#[test]
+fn handshakes_negotiate_a_shared_format_version() {
+    let local = CollaborativeDocument::new(1).handshake();
+    assert_eq!(local.format_version, FORMAT_VERSION);
+    assert_eq!(local.min_format_version, MIN_FORMAT_VERSION);
+    assert_eq!(local.negotiate_format(&local), Ok(FORMAT_VERSION));
+
+    let newer = SyncHandshake {
+        format_version: FORMAT_VERSION + 2,
+        min_format_version: MIN_FORMAT_VERSION,
+        ..local.clone()
+    };
+    assert_eq!(local.negotiate_format(&newer), Ok(FORMAT_VERSION));
+    assert_eq!(newer.negotiate_format(&local), Ok(FORMAT_VERSION));
+
+    let incompatible = SyncHandshake {
+        format_version: FORMAT_VERSION + 2,
+        min_format_version: FORMAT_VERSION + 1,
+        ..local.clone()
+    };
+    assert_eq!(
+        local.negotiate_format(&incompatible),
+        Err(FormatMismatch {
+            local: MIN_FORMAT_VERSION..=FORMAT_VERSION,
+            remote: FORMAT_VERSION + 1..=FORMAT_VERSION + 2,
+        })
+    );
+}
@@ -0,0 +1,157 @@
+//! Every conflict resolution picks its winner by `CausalOrd`, which is a total,
+//! antisymmetric order, so replicas agree whatever order writes arrive in.
+
+use md_crdt::core::mark::{Anchor, AnchorBias, MarkKind, MarkSet, MarkValue};
+use md_crdt::core::{CausalOrd, LwwRegister, OpId, Sequence, SequenceOp, StateVector};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+mod proptest_config;
+
+fn op(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn op_id() -> impl Strategy<Value = OpId> {
+    (1u64..4, 1u64..6).prop_map(|(peer, counter)| op(peer, counter))
+}
+
+/// Distinct ids, each used by one write.
+fn distinct_ids(max: usize) -> impl Strategy<Value = Vec<OpId>> {
+    proptest::collection::btree_set(op_id(), 1..max).prop_map(|ids| ids.into_iter().collect())
+}
+
+fn anchor(counter: u64, bias: AnchorBias) -> Anchor {
+    Anchor {
+        elem_id: op(9, counter),
+        bias,
+    }
+}
+
+#[test]
+fn counters_decide_before_peers() {
+    assert!(CausalOrd::wins(op(1, 2), op(9, 1)));
+    assert!(CausalOrd::wins(op(2, 1), op(1, 1)));
+    assert!(CausalOrd::supersedes(op(1, 1), op(1, 1)));
+    assert!(!CausalOrd::wins(op(1, 1), op(1, 1)));
+    assert_eq!(CausalOrd::winner(op(1, 3), op(2, 3)), op(2, 3));
+    assert_eq!(CausalOrd::loser(op(1, 3), op(2, 3)), op(1, 3));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+
+    #[test]
+    fn causal_order_is_total_antisymmetric_and_transitive(
+        a in op_id(),
+        b in op_id(),
+        c in op_id(),
+    ) {
+        let outcomes = [CausalOrd::wins(a, b), CausalOrd::wins(b, a), a == b];
+        prop_assert_eq!(outcomes.iter().filter(|outcome| **outcome).count(), 1);
+        prop_assert_eq!(CausalOrd::supersedes(a, b), !CausalOrd::wins(b, a));
+        if CausalOrd::wins(a, b) && CausalOrd::wins(b, c) {
+            prop_assert!(CausalOrd::wins(a, c));
+        }
+        prop_assert_eq!(a.cmp(&b), CausalOrd(a).cmp(&CausalOrd(b)));
+        let (winner, loser) = (CausalOrd::winner(a, b), CausalOrd::loser(a, b));
+        prop_assert_eq!(CausalOrd::winner(b, a), winner);
+        prop_assert!(CausalOrd::supersedes(winner, loser));
+        prop_assert!((winner, loser) == (a, b) || (winner, loser) == (b, a));
+    }
+
+    #[test]
+    fn lww_registers_keep_the_causal_maximum_in_any_order(
+        ids in distinct_ids(6),
+        rotation in 0usize..6,
+    ) {
+        let mut forward = LwwRegister::new(None, op(0, 0));
+        for id in &ids {
+            forward.set(Some(*id), *id);
+        }
+        let mut rotated = LwwRegister::new(None, op(0, 0));
+        let mut reordered = ids.clone();
+        reordered.rotate_left(rotation % ids.len());
+        reordered.reverse();
+        for id in &reordered {
+            rotated.set(Some(*id), *id);
+        }
+        let best = ids.iter().copied().reduce(CausalOrd::winner);
+        prop_assert_eq!(forward.get(), best);
+        prop_assert_eq!(rotated.get(), best);
+    }
+
+    #[test]
+    fn mark_sets_agree_on_sets_and_removes_in_any_order(
+        writes in distinct_ids(6),
+        removes in proptest::collection::vec((any::<bool>(), 0u64..6), 6),
+        rotation in 0usize..12,
+    ) {
+        let interval = op(9, 100);
+        let mut ops: Vec<(OpId, Option<u64>)> = writes.iter().map(|id| (*id, None)).collect();
+        for (remove, seen) in removes.iter().take(writes.len()) {
+            if *remove {
+                ops.push((op(8, *seen + 1), Some(*seen)));
+            }
+        }
+        let apply = |order: &[(OpId, Option<u64>)]| {
+            let mut set = MarkSet::new();
+            for (id, removal) in order {
+                match removal {
+                    None => {
+                        let href = format!("{}@{}", id.peer, id.counter);
+                        let attrs =
+                            BTreeMap::from([("href".to_string(), MarkValue::String(href))]);
+                        set.set_mark(
+                            interval,
+                            MarkKind::Link,
+                            anchor(id.counter, AnchorBias::Before),
+                            anchor(id.counter + 1, AnchorBias::After),
+                            attrs,
+                            *id,
+                        );
+                    }
+                    Some(seen) => {
+                        let mut observed = StateVector::new();
+                        observed.set(interval.peer, *seen + 100);
+                        set.remove_mark(interval, observed, *id);
+                    }
+                }
+            }
+            set
+        };
+        let mut reordered = ops.clone();
+        reordered.rotate_left(rotation % ops.len());
+        reordered.reverse();
+        let (a, b) = (apply(&ops), apply(&reordered));
+        prop_assert_eq!(a.is_active(&interval), b.is_active(&interval));
+        prop_assert_eq!(a.interval(&interval), b.interval(&interval));
+        let best = writes.iter().copied().reduce(CausalOrd::winner).unwrap();
+        prop_assert_eq!(a.interval(&interval).unwrap().op_id, best);
+    }
+
+    #[test]
+    fn concurrent_siblings_order_by_causal_order(
+        ids in distinct_ids(6),
+        rotation in 0usize..6,
+    ) {
+        let apply = |order: &[OpId]| {
+            let mut seq = Sequence::new();
+            for id in order {
+                seq.apply(SequenceOp::Insert {
+                    after: None,
+                    id: *id,
+                    value: *id,
+                    right_origin: None,
+                });
+            }
+            seq.to_vec()
+        };
+        let mut reordered = ids.clone();
+        reordered.rotate_left(rotation % ids.len());
+        reordered.reverse();
+        let mut expected = ids.clone();
+        expected.sort_by_key(|id| std::cmp::Reverse(CausalOrd(*id)));
+        prop_assert_eq!(apply(&ids), expected.clone());
+        prop_assert_eq!(apply(&reordered), expected);
+    }
+}
@@ -0,0 +1,95 @@
+//! Peer-table ids, varints and compact change-message framing.
+
+use md_crdt::core::compact::{read_varint, write_varint};
+use md_crdt::core::{CompactError, CompactOpId, OpId, PeerTable, StateVector};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::{ChangeMessage, Operation};
+
+#[test]
+fn peer_table_interns_in_first_seen_order() {
+    let mut table = PeerTable::new();
+    let id = |counter, peer| OpId { counter, peer };
+    assert_eq!(table.compact(id(7, u64::MAX)), CompactOpId(0, 7));
+    assert_eq!(table.compact(id(3, 42)), CompactOpId(1, 3));
+    assert_eq!(table.compact(id(9, u64::MAX)), CompactOpId(0, 9));
+    assert_eq!(table.peers(), &[u64::MAX, 42]);
+    assert_eq!(table.expand(CompactOpId(1, 3)), Ok(id(3, 42)));
+    assert_eq!(
+        table.expand(CompactOpId(2, 1)),
+        Err(CompactError::UnknownPeerIndex(2))
+    );
+    assert_eq!(
+        PeerTable::from_peers(vec![1, 2, 1]),
+        Err(CompactError::DuplicatePeer(1))
+    );
+}
+
+#[test]
+fn varints_round_trip_and_reject_bad_input() {
+    for value in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, value);
+        let mut input = bytes.as_slice();
+        assert_eq!(read_varint(&mut input), Ok(value));
+        assert!(input.is_empty());
+    }
+    let mut small = Vec::new();
+    write_varint(&mut small, 127);
+    assert_eq!(small.len(), 1);
+
+    assert_eq!(read_varint(&mut &[0x80][..]), Err(CompactError::Truncated));
+    let too_long = [0xff; 10];
+    assert_eq!(
+        read_varint(&mut &too_long[..]),
+        Err(CompactError::VarintOverflow)
+    );
+}
+
+#[test]
+fn compact_change_messages_round_trip_and_shrink() {
+    let peer = 0x1234_5678_9abc_def0;
+    let mut a = CollaborativeDocument::new(peer);
+    for line in 0..50 {
+        a.insert_paragraph(None, &format!("line {line}")).unwrap();
+    }
+    let message = a.encode_changes_since(&StateVector::new()).unwrap();
+    let bytes = message.to_compact_bytes();
+    assert_eq!(ChangeMessage::from_compact_bytes(&bytes).unwrap(), message);
+
+    let ids: usize = message.ops.len() * 16;
+    let payloads: usize = message.ops.iter().map(|op| op.payload.len()).sum();
+    assert!(bytes.len() < ids + payloads, "{} bytes", bytes.len());
+
+    let mut since = StateVector::new();
+    since.set(5, 3);
+    let with_frontier = ChangeMessage {
+        since,
+        ops: vec![Operation {
+            id: OpId {
+                counter: 4,
+                peer: 5,
+            },
+            payload: vec![1, 2, 3].into(),
+        }],
+        gaps: Vec::new(),
+    };
+    let bytes = with_frontier.to_compact_bytes();
+    assert_eq!(
+        ChangeMessage::from_compact_bytes(&bytes).unwrap(),
+        with_frontier
+    );
+    assert_eq!(
+        ChangeMessage::from_compact_bytes(&bytes[..bytes.len() - 1]),
+        Err(CompactError::Truncated)
+    );
+    assert_eq!(
+        ChangeMessage::from_compact_bytes(b"JSON"),
+        Err(CompactError::BadMagic)
+    );
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        ChangeMessage::from_compact_bytes(&trailing),
+        Err(CompactError::TrailingBytes(1))
+    );
+}
@@ -0,0 +1,118 @@
+use md_crdt::codec::{JsonOpCodec, ScalarOp};
+use md_crdt::core::{Counter, MultiValueRegister, OpId, StateVector};
+use md_crdt::sync::{ChangeMessage, Operation, SyncState};
+use proptest::prelude::*;
+mod proptest_config;
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn observed(entries: &[(u64, u64)]) -> StateVector {
+    let mut vector = StateVector::new();
+    for (peer, counter) in entries {
+        vector.set(*peer, *counter);
+    }
+    vector
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+    #[test]
+    fn counter_deltas_converge_under_reordering_and_replay(
+        steps in prop::collection::vec((1u64..4, any::<bool>(), 0u64..50), 0..30),
+    ) {
+        let mut source = Counter::new();
+        let mut counters = std::collections::BTreeMap::<u64, u64>::new();
+        let mut expected = 0i64;
+        let mut deltas = Vec::new();
+        for (peer, up, amount) in steps {
+            let counter = counters.entry(peer).or_default();
+            *counter += 1;
+            let id = op_id(peer, *counter);
+            deltas.push(if up {
+                expected += amount as i64;
+                source.increment(amount, id)
+            } else {
+                expected -= amount as i64;
+                source.decrement(amount, id)
+            });
+        }
+
+        let mut reversed = Counter::new();
+        for delta in deltas.iter().rev().chain(deltas.iter()) {
+            reversed.apply(delta);
+        }
+        let mut merged = Counter::new();
+        merged.merge(&reversed);
+        merged.merge(&source);
+
+        prop_assert_eq!(source.value(), expected);
+        prop_assert_eq!(reversed.value(), expected);
+        prop_assert_eq!(merged, source);
+    }
+}
+
+#[test]
+fn multi_value_register_surfaces_concurrent_writes_until_resolved() {
+    let mut left = MultiValueRegister::new();
+    let mut right = MultiValueRegister::new();
+    let base = left.set(3, op_id(1, 1), observed(&[]));
+    right.apply(base);
+
+    let from_left = left.set(4, op_id(1, 2), observed(&[(1, 1)]));
+    let from_right = right.set(5, op_id(2, 1), observed(&[(1, 1)]));
+    left.apply(from_right.clone());
+    right.apply(from_left.clone());
+
+    assert_eq!(left, right);
+    assert!(left.is_conflicted());
+    assert_eq!(left.values().copied().collect::<Vec<_>>(), vec![5, 4]);
+
+    let resolved = left.set(4, op_id(1, 3), observed(&[(1, 2), (2, 1)]));
+    assert_eq!(left.values().copied().collect::<Vec<_>>(), vec![4]);
+
+    // A replica that sees the resolution first ignores the stale writes it covers.
+    let mut late = MultiValueRegister::new();
+    late.apply(resolved);
+    late.apply(from_left);
+    late.apply(from_right);
+    assert_eq!(late, left);
+    assert!(!late.is_conflicted());
+}
+
+#[test]
+fn scalar_ops_travel_as_sync_operations() {
+    let codec = JsonOpCodec;
+    let mut counter = Counter::new();
+    let mut rating = MultiValueRegister::new();
+    let ops = [
+        ScalarOp::Counter(counter.increment(10, op_id(1, 1))),
+        ScalarOp::Register(rating.set("good".to_string(), op_id(1, 2), observed(&[]))),
+        ScalarOp::Counter(counter.decrement(3, op_id(1, 3))),
+    ];
+
+    let mut sender = SyncState::new();
+    for op in &ops {
+        sender.apply_op(Operation {
+            id: op.id(),
+            payload: codec.encode_scalar(op).expect("encode").into(),
+        });
+    }
+    let message: ChangeMessage = sender
+        .encode_changes_since(&StateVector::new())
+        .expect("no checkpoint");
+
+    let mut remote_counter = Counter::new();
+    let mut remote_rating = MultiValueRegister::<String>::new();
+    for operation in message.ops {
+        match codec.decode_scalar(&operation.payload).expect("decode") {
+            ScalarOp::Counter(delta) => remote_counter.apply(&delta),
+            ScalarOp::Register(write) => remote_rating.apply(write),
+        }
+    }
+
+    assert_eq!(remote_counter.value(), 7);
+    assert_eq!(remote_counter, counter);
+    assert_eq!(remote_rating, rating);
+}
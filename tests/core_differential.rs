@@ -131,9 +131,6 @@ proptest! {
 }
 
 #[test]
-fn sequence_reports_the_compiled_ordering_strategy() {
-    assert_eq!(
-        Sequence::<u8>::incremental_ordering_enabled(),
-        cfg!(feature = "sequence_incremental")
-    );
+fn sequence_integrates_incrementally_in_every_build() {
+    assert!(Sequence::<u8>::incremental_ordering_enabled());
 }
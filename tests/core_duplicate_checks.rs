@@ -0,0 +1,52 @@
+#![cfg(feature = "duplicate-checks")]
+
+use md_crdt::core::{DuplicateOpMismatch, OpId, Sequence, SequenceOp};
+
+fn insert(after: Option<OpId>, id: OpId, value: char) -> SequenceOp<char> {
+    SequenceOp::Insert {
+        after,
+        id,
+        value,
+        right_origin: None,
+    }
+}
+
+#[test]
+fn checked_apply_refuses_a_different_value_under_a_known_id() {
+    let a = OpId {
+        counter: 1,
+        peer: 1,
+    };
+    let b = OpId {
+        counter: 2,
+        peer: 1,
+    };
+    let orphan = OpId {
+        counter: 3,
+        peer: 2,
+    };
+    let mut seq = Sequence::new();
+    seq.apply_checked(insert(None, a, 'a')).unwrap();
+    seq.apply_checked(insert(Some(orphan), b, 'b')).unwrap();
+
+    assert_eq!(seq.apply_checked(insert(None, a, 'a')), Ok(()));
+    assert_eq!(
+        seq.apply_checked(insert(None, a, 'x')),
+        Err(DuplicateOpMismatch { id: a })
+    );
+    assert_eq!(
+        seq.apply_checked(insert(Some(orphan), b, 'x')),
+        Err(DuplicateOpMismatch { id: b })
+    );
+    assert_eq!(seq.to_vec(), vec!['a']);
+
+    // A deleted element keeps no value to compare against.
+    seq.delete(
+        a,
+        OpId {
+            counter: 4,
+            peer: 1,
+        },
+    );
+    assert_eq!(seq.apply_checked(insert(None, a, 'x')), Ok(()));
+}
@@ -1,4 +1,4 @@
-use md_crdt::core::{LwwRegister, Map, OpId};
+use md_crdt::core::{LwwRegister, Map, MapOp, OpId};
 use proptest::prelude::*;
 mod proptest_config;
 
@@ -65,3 +65,93 @@ proptest! {
         prop_assert_eq!(*map2.get(&key).unwrap(), expected_val, "Map 2 should have the value of the op with the highest OpId");
     }
 }
+
+fn id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn map_remove_hides_the_observed_write_and_iteration_skips_it() {
+    let mut map = Map::new();
+    map.set("title", "Draft", id(1, 1));
+    map.set("views", "3", id(1, 2));
+
+    let removed = map.remove(&"title", id(1, 3)).expect("live key");
+    assert_eq!(
+        removed,
+        MapOp::Remove {
+            key: "title",
+            observed: id(1, 1),
+            op_id: id(1, 3),
+        }
+    );
+    assert_eq!(map.get(&"title"), None);
+    assert!(map.remove(&"title", id(1, 4)).is_none());
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"views"]);
+    assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&"views", &"3")]);
+
+    map.set("title", "Final", id(1, 5));
+    assert_eq!(map.get(&"title"), Some(&"Final"));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn map_concurrent_write_survives_remove_and_replicas_converge() {
+    let mut left = Map::new();
+    left.set("k", 1, id(1, 1));
+    let mut right = left.clone();
+
+    let remove = left.remove(&"k", id(1, 2)).expect("live key");
+    let write = MapOp::Set {
+        key: "k",
+        value: 2,
+        op_id: id(2, 1),
+    };
+    right.apply(write.clone());
+
+    // Deliver the remove twice and before the write it raced with.
+    let mut late = Map::new();
+    late.apply(remove.clone());
+    late.apply(write);
+    late.apply(remove.clone());
+    late.set("k", 1, id(1, 1));
+    right.apply(remove);
+    left.merge(&right);
+
+    assert_eq!(left.get(&"k"), Some(&2));
+    assert_eq!(left, right);
+    assert_eq!(late, left);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+    #[test]
+    fn map_merge_is_commutative_and_idempotent(
+        ops in prop::collection::vec((0u8..4, any::<bool>(), 1u64..50, any::<u8>()), 0..20),
+    ) {
+        let mut left = Map::new();
+        let mut right = Map::new();
+        for (index, (key, remove, counter, value)) in ops.iter().enumerate() {
+            // Unique ids per op: equal-id writes with different values are not a valid history.
+            let peer = index as u64 % 2 + 1;
+            let op_id = id(peer, counter * 100 + index as u64);
+            let target = if peer == 1 { &mut left } else { &mut right };
+            if *remove {
+                target.remove(key, op_id);
+            } else {
+                target.set(*key, *value, op_id);
+            }
+        }
+
+        let mut left_then_right = left.clone();
+        left_then_right.merge(&right);
+        let mut right_then_left = right.clone();
+        right_then_left.merge(&left);
+        let mut twice = left_then_right.clone();
+        twice.merge(&right);
+
+        prop_assert_eq!(left_then_right.iter().collect::<Vec<_>>(), right_then_left.iter().collect::<Vec<_>>());
+        prop_assert_eq!(&twice, &left_then_right);
+    }
+}
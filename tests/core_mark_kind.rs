@@ -0,0 +1,141 @@
+use md_crdt::StateVector;
+use md_crdt::core::{InvalidMarkKind, LEGACY_MARK_NAMESPACE, MarkKind, MarkSchemaError, MarkValue};
+use md_crdt::doc::{EquivalenceMode, block_id_from_op};
+use md_crdt::session::{CollaborativeDocument, SessionError, SessionSnapshot};
+use md_crdt::sync::ValidationLimits;
+use std::collections::BTreeMap;
+
+fn highlight() -> MarkKind {
+    MarkKind::custom("app", "highlight").unwrap()
+}
+
+#[test]
+fn kinds_have_stable_names() {
+    for (kind, name) in [
+        (MarkKind::Bold, "bold"),
+        (MarkKind::Italic, "italic"),
+        (MarkKind::Code, "code"),
+        (MarkKind::Link, "link"),
+        (MarkKind::Strikethrough, "strikethrough"),
+        (highlight(), "app:highlight"),
+    ] {
+        assert_eq!(kind.as_str(), name);
+        assert_eq!(kind.to_string(), name);
+        assert_eq!(name.parse::<MarkKind>(), Ok(kind.clone()));
+        assert_eq!(serde_json::to_string(&kind).unwrap(), format!("\"{name}\""));
+        assert_eq!(
+            serde_json::from_str::<MarkKind>(&format!("\"{name}\"")).unwrap(),
+            kind
+        );
+    }
+
+    let kind = highlight();
+    assert_eq!(kind.namespace(), Some("app"));
+    assert_eq!(kind.local_name(), "highlight");
+    assert!(!kind.is_builtin());
+    assert_eq!(MarkKind::Bold.namespace(), None);
+    assert_eq!(MarkKind::Bold.local_name(), "bold");
+}
+
+#[test]
+fn custom_kinds_must_be_namespaced() {
+    for name in [
+        "comment",
+        "App:comment",
+        ":comment",
+        "app:",
+        "app:two words",
+        "a:b:c",
+    ] {
+        assert_eq!(
+            name.parse::<MarkKind>(),
+            Err(InvalidMarkKind(name.to_string())),
+            "{name}"
+        );
+    }
+    assert!(MarkKind::custom("my-app.v2", "Review_Note").is_ok());
+
+    let unnamespaced = MarkKind::Custom("comment".into());
+    assert!(unnamespaced.validate().is_err());
+    assert!(serde_json::to_string(&unnamespaced).is_err());
+    assert!(serde_json::from_str::<MarkKind>("\"comment\"").is_err());
+
+    let mut doc = CollaborativeDocument::new(1);
+    let block = block_id_from_op(doc.insert_paragraph(None, "note").unwrap());
+    let before = doc.state_vector();
+    assert!(matches!(
+        doc.set_mark(block, 0..4, unnamespaced, BTreeMap::new()),
+        Err(SessionError::InvalidMark(MarkSchemaError::InvalidKind(_)))
+    ));
+    assert_eq!(doc.state_vector(), before);
+}
+
+#[test]
+fn custom_marks_round_trip_through_sync_snapshots_and_markdown() {
+    let mut a = CollaborativeDocument::new(1);
+    let block = block_id_from_op(a.insert_paragraph(None, "plain marked plain").unwrap());
+    let delimiter = BTreeMap::from([("delimiter".to_string(), MarkValue::String("==".into()))]);
+    a.set_mark(block, 6..12, highlight(), delimiter).unwrap();
+    a.set_mark(
+        block,
+        0..5,
+        MarkKind::custom("app", "comment").unwrap(),
+        BTreeMap::new(),
+    )
+    .unwrap();
+    let markdown = a.document().serialize(EquivalenceMode::Exact);
+    assert_eq!(markdown, "plain ==marked== plain");
+
+    let mut b = CollaborativeDocument::new(2);
+    b.apply_remote(
+        a.encode_changes_since(&StateVector::new()).unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(b.document(), a.document());
+
+    let snapshot =
+        SessionSnapshot::from_bytes(&a.save_snapshot().unwrap().to_bytes().unwrap()).unwrap();
+    let restored = CollaborativeDocument::restore_from_snapshot(snapshot).unwrap();
+    let kinds: Vec<_> = restored.document().blocks_in_order()[0]
+        .marks
+        .iter_active_intervals()
+        .map(|interval| interval.kind.to_string())
+        .collect();
+    assert!(kinds.contains(&"app:highlight".to_string()));
+    assert!(kinds.contains(&"app:comment".to_string()));
+    assert_eq!(
+        restored.document().serialize(EquivalenceMode::Exact),
+        markdown
+    );
+}
+
+#[test]
+fn kinds_written_before_stable_names_still_load() {
+    for (encoded, kind) in [
+        ("\"Bold\"", MarkKind::Bold),
+        ("\"Italic\"", MarkKind::Italic),
+        ("\"Code\"", MarkKind::Code),
+        ("\"Link\"", MarkKind::Link),
+        ("\"Strikethrough\"", MarkKind::Strikethrough),
+        (
+            r#"{"Custom":"comment"}"#,
+            MarkKind::custom(LEGACY_MARK_NAMESPACE, "comment").unwrap(),
+        ),
+        (r#"{"Custom":"app:highlight"}"#, highlight()),
+    ] {
+        assert_eq!(
+            serde_json::from_str::<MarkKind>(encoded).unwrap(),
+            kind,
+            "{encoded}"
+        );
+    }
+    assert!(serde_json::from_str::<MarkKind>(r#"{"Custom":"two words"}"#).is_err());
+
+    // A snapshot saved before kinds had stable names, with a bold interval.
+    let bytes = include_bytes!("fixtures/legacy-mark-kinds-snapshot.json");
+    let snapshot = SessionSnapshot::from_bytes(bytes).unwrap();
+    let restored = CollaborativeDocument::restore_from_snapshot(snapshot).unwrap();
+    let markdown = restored.document().serialize(EquivalenceMode::Exact);
+    assert!(markdown.contains("**Hello**"), "{markdown}");
+}
@@ -0,0 +1,157 @@
+//! Generic replica catch-up through `Mergeable`, across the container types.
+
+use md_crdt::core::{
+    Anchor, AnchorBias, LwwRegister, Map, MarkKind, MarkSet, Mergeable, OpId, OrSet, Sequence,
+    StateVector, Text,
+};
+use std::collections::BTreeMap;
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+/// Bring `to` up to `from` by shipping what `to`'s state vector lacks.
+fn catch_up<M: Mergeable>(from: &M, to: &mut M) {
+    for op in from.ops_since(&to.state_vector()) {
+        to.apply_op(op);
+    }
+}
+
+#[test]
+fn sequences_exchange_tombstones_and_what_hangs_off_them() {
+    let mut left = Sequence::new();
+    left.insert(None, 'a', op(1, 1));
+    left.insert(Some(op(1, 1)), 'b', op(2, 1));
+    left.insert(Some(op(2, 1)), 'c', op(3, 1));
+    left.delete(op(2, 1), op(4, 1));
+    let mut right = Sequence::new();
+    right.insert(None, 'x', op(1, 2));
+
+    let mut from_left = right.clone();
+    catch_up(&left, &mut from_left);
+    let mut from_right = left.clone();
+    catch_up(&right, &mut from_right);
+
+    assert_eq!(from_left.to_vec(), from_right.to_vec());
+    assert_eq!(from_left.element_ids(), from_right.element_ids());
+    assert_eq!(from_left.len_visible(), 3);
+    assert_eq!(from_left.state_vector(), from_right.state_vector());
+}
+
+#[test]
+fn a_delete_after_the_receivers_frontier_is_resent() {
+    let mut left = Sequence::new();
+    left.insert(None, 'a', op(1, 1));
+    let mut right = Sequence::new();
+    right.merge_state(&left);
+
+    left.delete(op(1, 1), op(2, 1));
+    catch_up(&left, &mut right);
+    assert!(right.to_vec().is_empty());
+}
+
+#[test]
+fn texts_converge_with_deleted_runs_and_marks() {
+    let mut left = Text::new(1);
+    left.insert(0, "hello world");
+    let mut right = Text::new(2);
+    right.merge_state(&left);
+
+    left.delete(0..6);
+    left.mark(0..5, MarkKind::Bold, BTreeMap::new());
+    right.insert(11, "!");
+    let unseen = left.ops_since(&right.state_vector());
+
+    catch_up(&left, &mut right);
+    catch_up(&right, &mut left);
+    assert_eq!(left.to_string(), "world!");
+    assert_eq!(left.to_string(), right.to_string());
+    assert_eq!(left.spans(), right.spans());
+    assert_eq!(left.state_vector(), right.state_vector());
+    // The insert the receiver already held is not resent.
+    assert!(!unseen.iter().any(|op| matches!(
+        op,
+        md_crdt::TextOp::Run(md_crdt::core::RunOp::Insert { .. })
+    )));
+}
+
+#[test]
+fn a_fresh_replica_rebuilds_deleted_text_it_never_saw() {
+    let mut text = Text::new(1);
+    text.insert(0, "abc");
+    text.delete(1..2);
+    text.insert(2, "d");
+
+    let mut fresh = Text::new(2);
+    fresh.merge_state(&text);
+    assert_eq!(fresh.to_string(), "acd");
+    assert_eq!(fresh.runs().unit_ids(), text.runs().unit_ids());
+}
+
+#[test]
+fn registers_maps_and_sets_join_through_the_trait() {
+    let mut register = LwwRegister::new("old", op(1, 1));
+    let newer = LwwRegister::new("new", op(2, 2));
+    assert_eq!(newer.ops_since(&register.state_vector()).len(), 1);
+    register.merge_state(&newer);
+    assert_eq!(register.get(), "new");
+    assert!(newer.ops_since(&register.state_vector()).is_empty());
+
+    let mut left: Map<&str, u32> = Map::new();
+    left.set("a", 1, op(1, 1));
+    left.set("b", 2, op(2, 1));
+    let mut right = Map::new();
+    catch_up(&left, &mut right);
+    left.remove(&"a", op(3, 1));
+    catch_up(&left, &mut right);
+    assert_eq!(right, left);
+    assert_eq!(right.get(&"b"), Some(&2));
+    assert!(!right.contains_key(&"a"));
+
+    let mut set = OrSet::new();
+    set.add("x", op(1, 1));
+    set.add("y", op(2, 1));
+    set.remove(&"x", op(3, 1));
+    let mut other = OrSet::new();
+    catch_up(&set, &mut other);
+    assert_eq!(other, set);
+    assert_eq!(other.state_vector(), set.frontier());
+}
+
+#[test]
+fn mark_sets_resend_only_unseen_writes() {
+    let anchor = |counter, bias| Anchor {
+        elem_id: op(counter, 9),
+        bias,
+    };
+    let mut marks = MarkSet::new();
+    marks.set_mark(
+        op(1, 1),
+        MarkKind::Link,
+        anchor(1, AnchorBias::Before),
+        anchor(3, AnchorBias::After),
+        BTreeMap::from([("href".into(), md_crdt::MarkValue::String("a".into()))]),
+        op(1, 1),
+    );
+    let mut replica = MarkSet::new();
+    catch_up(&marks, &mut replica);
+    assert_eq!(replica, marks);
+
+    marks.set_mark(
+        op(1, 1),
+        MarkKind::Link,
+        anchor(1, AnchorBias::Before),
+        anchor(3, AnchorBias::After),
+        BTreeMap::from([("title".into(), md_crdt::MarkValue::String("t".into()))]),
+        op(2, 1),
+    );
+    marks.remove_mark(op(1, 1), marks.state_vector(), op(3, 1));
+    assert_eq!(marks.ops_since(&replica.state_vector()).len(), 2);
+    catch_up(&marks, &mut replica);
+    assert_eq!(replica, marks);
+    assert!(!replica.is_active(&op(1, 1)));
+
+    let mut everything = StateVector::new();
+    everything.set(1, 3);
+    assert!(marks.ops_since(&everything).is_empty());
+}
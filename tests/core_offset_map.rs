@@ -0,0 +1,71 @@
+use md_crdt::core::{Anchor, AnchorBias, MarkKind, OffsetMap, OpId, RunSequence, Sequence, Text};
+use md_crdt::doc::{TextUnit, units_from_str};
+
+fn id(counter: u64) -> OpId {
+    OpId { counter, peer: 1 }
+}
+
+#[test]
+fn offset_map_translates_both_ways_and_skips_tombstones() {
+    let mut seq = Sequence::new();
+    seq.insert(None, 'a', id(1));
+    seq.insert(Some(id(1)), 'b', id(2));
+    seq.insert(Some(id(2)), 'c', id(3));
+    seq.delete(id(2), id(4));
+
+    assert_eq!(seq.visible_string(), "ac");
+    let offsets = seq.offset_map();
+    assert_eq!(offsets.len(), 2);
+    assert_eq!(offsets.ids(), &[id(1), id(3)]);
+    assert_eq!(offsets.id_at(1), Some(id(3)));
+    assert_eq!(offsets.id_at(2), None);
+    assert_eq!(offsets.offset_of(id(3)), Some(1));
+    assert_eq!(offsets.offset_of(id(2)), None);
+    assert_eq!(offsets.after_for_offset(0), None);
+    assert_eq!(offsets.after_for_offset(2), Some(id(3)));
+    assert_eq!(
+        offsets.anchor_offset(&Anchor {
+            elem_id: id(3),
+            bias: AnchorBias::After,
+        }),
+        Some(2)
+    );
+    assert_eq!(
+        offsets.anchor_offset(&Anchor {
+            elem_id: id(2),
+            bias: AnchorBias::Before,
+        }),
+        None
+    );
+}
+
+#[test]
+fn run_and_unit_sequences_agree_on_offsets() {
+    let mut counter = 1;
+    let units: Sequence<TextUnit> = units_from_str("he\u{301}llo", &mut counter, 1);
+    let mut runs = RunSequence::new();
+    runs.insert(None, "he\u{301}llo", id(1));
+
+    assert_eq!(units.visible_string(), runs.visible_string());
+    assert_eq!(units.offset_map(), runs.offset_map());
+    assert_eq!(runs.offset_map().offset_of(id(3)), Some(2));
+}
+
+#[test]
+fn mark_resolution_matches_with_and_without_prebuilt_map() {
+    let mut text = Text::new(1);
+    text.insert(0, "hello world");
+    text.mark(0..5, MarkKind::Bold, Default::default());
+    text.delete(2..4);
+
+    let offsets: OffsetMap = text.runs().offset_map();
+    assert_eq!(
+        text.marks().render_spans_in(&offsets, offsets.len()),
+        text.marks().render_spans(offsets.ids(), offsets.len())
+    );
+    assert_eq!(
+        text.marks().resolved_intervals_in(&offsets),
+        text.marks().resolved_intervals(offsets.ids())
+    );
+    assert_eq!(text.spans(), text.marks().render_spans_in(&offsets, 9));
+}
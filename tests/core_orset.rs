@@ -0,0 +1,128 @@
+//! Observed-remove set: a concurrent add survives a remove.
+
+use md_crdt::core::{OpId, OrSet, OrSetOp, StateVector};
+use proptest::prelude::*;
+mod proptest_config;
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn concurrent_add_and_remove_keep_the_add() {
+    let mut base = OrSet::new();
+    base.add("x", op(1, 1));
+    let mut left = base.clone();
+    let mut right = base.clone();
+
+    let remove = left.remove(&"x", op(2, 1)).unwrap();
+    let add = right.add("x", op(2, 2));
+    assert!(!left.contains(&"x"));
+    left.apply(add);
+    right.apply(remove);
+
+    assert_eq!(left, right);
+    assert!(left.contains(&"x"));
+    assert_eq!(left.additions(&"x").collect::<Vec<_>>(), [op(2, 2)]);
+}
+
+#[test]
+fn a_remove_delivered_before_its_add_still_applies() {
+    let mut source = OrSet::new();
+    let add = source.add(7u32, op(1, 1));
+    let remove = source.remove(&7, op(2, 1)).unwrap();
+
+    let mut target = OrSet::new();
+    target.apply(remove);
+    target.apply(add);
+    assert!(target.is_empty());
+    assert_eq!(target, source);
+    assert!(target.remove(&7, op(3, 2)).is_none());
+}
+
+#[test]
+fn membership_as_of_a_frontier() {
+    let mut set = OrSet::new();
+    set.add("a", op(1, 1));
+    set.add("b", op(2, 1));
+    set.remove(&"a", op(3, 2));
+
+    let mut early = StateVector::new();
+    early.set(1, 1);
+    assert!(set.contains_as_of(&"a", &early));
+    assert!(!set.contains_as_of(&"b", &early));
+    assert!(!set.contains_as_of(&"a", &set.frontier()));
+    assert!(set.contains_as_of(&"b", &set.frontier()));
+
+    // A write that only saw `a` removes that addition and nothing later.
+    assert!(set.remove_as_of(&"b", &early, op(4, 3)).is_none());
+    assert_eq!(set.iter().collect::<Vec<_>>(), [&"b"]);
+}
+
+#[test]
+fn sets_of_structured_elements_round_trip_through_json() {
+    let mut set = OrSet::new();
+    set.add((1u8, "one".to_string()), op(1, 1));
+    set.add((2u8, "two".to_string()), op(2, 1));
+    set.remove(&(1u8, "one".to_string()), op(3, 1));
+    let encoded = serde_json::to_string(&set).unwrap();
+    let decoded: OrSet<(u8, String)> = serde_json::from_str(&encoded).unwrap();
+    assert_eq!(decoded, set);
+    assert_eq!(decoded.len(), 1);
+}
+
+fn ops() -> impl Strategy<Value = Vec<(bool, u8, u64)>> {
+    proptest::collection::vec((any::<bool>(), 0u8..4, 1u64..4), 1..12)
+}
+
+/// Replay `script` as writes by the given peers, each from its own replica,
+/// returning the ops in issue order.
+fn issue(script: &[(bool, u8, u64)]) -> Vec<OrSetOp<u8>> {
+    let mut replicas: Vec<OrSet<u8>> = vec![OrSet::new(); 4];
+    let mut issued = Vec::new();
+    for (counter, (add, element, peer)) in script.iter().enumerate() {
+        let id = op(counter as u64 + 1, *peer);
+        let replica = &mut replicas[*peer as usize];
+        let op = if *add {
+            Some(replica.add(*element, id))
+        } else {
+            replica.remove(element, id)
+        };
+        issued.extend(op);
+    }
+    issued
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+
+    #[test]
+    fn replicas_converge_in_any_delivery_order(
+        script in ops(),
+        rotation in 0usize..12,
+    ) {
+        let issued = issue(&script);
+        let mut forward = OrSet::new();
+        for op in &issued {
+            forward.apply(op.clone());
+        }
+        let mut reordered = issued.clone();
+        if !issued.is_empty() {
+            reordered.rotate_left(rotation % issued.len());
+        }
+        reordered.reverse();
+        let mut backward = OrSet::new();
+        for op in &reordered {
+            backward.apply(op.clone());
+        }
+        prop_assert_eq!(&forward, &backward);
+
+        let (half, rest) = issued.split_at(issued.len() / 2);
+        let mut left = OrSet::new();
+        half.iter().for_each(|op| left.apply(op.clone()));
+        let mut right = OrSet::new();
+        rest.iter().for_each(|op| right.apply(op.clone()));
+        left.merge(&right);
+        prop_assert_eq!(left, forward);
+    }
+}
@@ -0,0 +1,157 @@
+//! Paging cold tombstones out of a sequence and back in on demand.
+
+use md_crdt::core::{MemoryPageStore, OpId, PagedSequence, Sequence, SequenceOp};
+
+fn id(counter: u64) -> OpId {
+    OpId { counter, peer: 1 }
+}
+
+/// "0123456789…" typed in order, each element anchored on the previous one.
+fn typed(len: u64) -> Sequence<char> {
+    let mut sequence = Sequence::new();
+    let mut after = None;
+    for counter in 1..=len {
+        let value = char::from(b'a' + (counter % 26) as u8);
+        sequence.insert(after, value, id(counter));
+        after = Some(id(counter));
+    }
+    sequence
+}
+
+fn delete(sequence: &mut Sequence<char>, counters: std::ops::RangeInclusive<u64>, base: u64) {
+    for counter in counters {
+        sequence.delete(id(counter), id(base + counter));
+    }
+}
+
+#[test]
+fn deleted_tail_is_paged_out_and_back_in_on_a_remote_anchor() {
+    let mut full = typed(100);
+    delete(&mut full, 41..=100, 1000);
+    let mut paged = PagedSequence::new(full.clone(), MemoryPageStore::new());
+
+    // Element 41 stays resident as the root of the archived subtree.
+    assert_eq!(paged.spill_cold(8).unwrap(), 59);
+    assert_eq!(paged.resident_len(), 41);
+    assert_eq!(paged.store().len(), 1);
+    assert!(paged.is_archived(id(70)));
+    assert!(!paged.is_archived(id(41)));
+    assert_eq!(paged.sequence().to_vec(), full.to_vec());
+
+    // A local insert after the root sees the same right origin as the full sequence.
+    assert_eq!(
+        paged.compute_right_origin(Some(id(40))).unwrap(),
+        full.compute_right_origin(Some(id(40)))
+    );
+
+    let remote = SequenceOp::Insert {
+        after: Some(id(70)),
+        id: OpId {
+            counter: 5,
+            peer: 2,
+        },
+        value: '!',
+        right_origin: full.compute_right_origin(Some(id(70))),
+    };
+    full.apply(remote.clone());
+    paged.apply(remote).unwrap();
+    assert!(!paged.is_archived(id(70)));
+    assert!(paged.store().is_empty());
+    assert_eq!(paged.sequence().to_vec(), full.to_vec());
+    assert_eq!(
+        paged.into_inner().unwrap().element_ids(),
+        full.element_ids()
+    );
+}
+
+#[test]
+fn only_fully_tombstoned_subtrees_are_archived() {
+    let mut full = typed(50);
+    // A deleted middle still anchors the visible tail, so nothing can move.
+    delete(&mut full, 10..=30, 1000);
+    let mut paged = PagedSequence::new(full.clone(), MemoryPageStore::new());
+    assert_eq!(paged.spill_cold(1).unwrap(), 0);
+
+    // Once the tail goes too, everything after element 10 is one cold subtree, but
+    // subtrees below the threshold stay resident.
+    delete(&mut full, 31..=50, 1000);
+    let mut paged = PagedSequence::new(full, MemoryPageStore::new());
+    assert_eq!(paged.spill_cold(100).unwrap(), 0);
+    assert_eq!(paged.spill_cold(1).unwrap(), 40);
+}
+
+#[test]
+fn paged_and_unpaged_replicas_converge() {
+    let mut full = typed(30);
+    let mut paged = PagedSequence::new(full.clone(), MemoryPageStore::new());
+    let mut next = 10_000;
+    for round in 0..40u64 {
+        let anchor = id(1 + (round * 7) % 30);
+        let ops = [
+            SequenceOp::Insert {
+                after: Some(anchor),
+                id: OpId {
+                    counter: next,
+                    peer: 3,
+                },
+                value: 'x',
+                right_origin: full.compute_right_origin(Some(anchor)),
+            },
+            SequenceOp::Delete {
+                target: OpId {
+                    counter: next,
+                    peer: 3,
+                },
+                id: OpId {
+                    counter: next + 1,
+                    peer: 3,
+                },
+            },
+            SequenceOp::Delete {
+                target: id(1 + (round * 11) % 30),
+                id: OpId {
+                    counter: next + 2,
+                    peer: 3,
+                },
+            },
+        ];
+        next += 3;
+        for op in ops {
+            full.apply(op.clone());
+            paged.apply(op).unwrap();
+        }
+        paged.spill_cold(1).unwrap();
+        assert_eq!(paged.sequence().to_vec(), full.to_vec());
+    }
+    assert!(paged.archived_len() > 0);
+    assert_eq!(
+        paged.into_inner().unwrap().element_ids(),
+        full.element_ids()
+    );
+}
+
+#[cfg(feature = "storage")]
+#[test]
+fn storage_holds_pages_until_they_are_paged_in() {
+    use md_crdt::storage::Storage;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut full = typed(40);
+    delete(&mut full, 11..=40, 1000);
+    let mut paged = PagedSequence::new(full.clone(), Storage::open(dir.path()).unwrap());
+    assert_eq!(paged.spill_cold(4).unwrap(), 29);
+    let page = dir.path().join("pages").join("page_0");
+    assert!(page.exists());
+
+    paged
+        .apply(SequenceOp::Delete {
+            target: id(30),
+            id: id(5000),
+        })
+        .unwrap();
+    assert!(!page.exists());
+    assert_eq!(
+        paged.into_inner().unwrap().element_ids(),
+        full.element_ids()
+    );
+}
@@ -0,0 +1,108 @@
+//! Counter reservation: disjoint ranges under interleaving, and exhaustion checks.
+
+use md_crdt::core::{ClockError, OpId, PeerClock};
+use md_crdt::doc::{EquivalenceMode, Parser};
+use md_crdt::session::CollaborativeDocument;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[test]
+fn interleaved_reservations_never_collide() {
+    let clock = Arc::new(PeerClock::new(7));
+    let handles: Vec<_> = (0..8)
+        .map(|worker| {
+            let clock = Arc::clone(&clock);
+            std::thread::spawn(move || {
+                let mut ids = Vec::new();
+                for round in 0..200 {
+                    if (worker + round) % 3 == 0 {
+                        ids.push(clock.next_id().unwrap());
+                    } else {
+                        ids.extend(clock.reserve(1 + round % 17).unwrap());
+                    }
+                }
+                ids
+            })
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    for handle in handles {
+        for id in handle.join().unwrap() {
+            assert_eq!(id.peer, 7);
+            assert!(id.counter >= 1);
+            assert!(seen.insert(id), "{id:?} handed out twice");
+        }
+    }
+    assert_eq!(clock.peek().counter, seen.len() as u64 + 1);
+}
+
+#[test]
+fn reservations_check_for_exhaustion() {
+    let clock = PeerClock::starting_at(1, u64::MAX - 3);
+    assert_eq!(
+        clock.reserve(4),
+        Err(ClockError::ClockExhausted {
+            peer: 1,
+            requested: 4,
+            available: 3,
+        })
+    );
+    let mut range = clock.reserve(3).unwrap();
+    assert_eq!(range.remaining(), 3);
+    assert_eq!(
+        range.split_off(4),
+        Err(ClockError::RangeExhausted {
+            requested: 4,
+            remaining: 3,
+        })
+    );
+    assert_eq!(range.by_ref().count(), 3);
+    assert!(range.is_empty());
+    assert!(range.next_id().is_err());
+
+    let fresh = PeerClock::new(2);
+    fresh.observe(10);
+    assert_eq!(fresh.peek().counter, 11);
+    fresh.observe(3);
+    assert_eq!(fresh.reserve(2).unwrap().start(), 11);
+}
+
+#[test]
+fn bulk_import_consumes_a_session_reservation() {
+    let input = "# Imported\n\nA long *paragraph* of text\n\n- one\n- two\n";
+    let mut session = CollaborativeDocument::new(4);
+    session.insert_paragraph(None, "live").unwrap();
+
+    let mut range = session.reserve_ids(1_000).unwrap();
+    let reserved = range.clone();
+    assert!(session.peek_next_id().counter >= reserved.end());
+
+    // Live editing continues while the import draws from its range.
+    let live = session.insert_paragraph(None, "meanwhile").unwrap();
+    assert!(!reserved.contains(live));
+    let imported = Parser::parse_in_range(input, &mut range).unwrap();
+    assert!(range.remaining() < reserved.remaining());
+    for block in imported.blocks_in_order() {
+        assert!(reserved.contains(block.elem_id));
+    }
+    assert_eq!(
+        imported.serialize(EquivalenceMode::Structural),
+        Parser::parse(input).serialize(EquivalenceMode::Structural)
+    );
+
+    let mut tiny = session.reserve_ids(2).unwrap();
+    let before = tiny.clone();
+    assert!(matches!(
+        Parser::parse_in_range(input, &mut tiny),
+        Err(ClockError::RangeExhausted { .. })
+    ));
+    assert_eq!(tiny, before);
+    assert!(
+        session.peek_next_id()
+            > OpId {
+                counter: before.end() - 1,
+                peer: 4
+            }
+    );
+}
@@ -1,4 +1,5 @@
-use md_crdt::core::{OpId, Sequence};
+use md_crdt::core::{OpId, Sequence, SequenceOp};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_out_of_order_buffering() {
@@ -42,3 +43,53 @@ fn test_long_causal_chain_reverse() {
     seq.insert(None, 0, ids[0]);
     assert_eq!(seq.len_visible() as u64, total);
 }
+
+#[test]
+fn pending_ops_are_summarized_by_dependency_and_expire() {
+    let mut seq = Sequence::new();
+    let id = |counter| OpId { counter, peer: 1 };
+    seq.insert(Some(id(1)), 'b', id(2));
+    seq.insert(Some(id(1)), 'c', id(3));
+    seq.delete(id(9), id(4));
+
+    let later = Instant::now() + Duration::from_secs(60);
+    let summary = seq.pending_summary(later);
+    assert_eq!(summary.blocked, 3);
+    let awaited: Vec<_> = summary
+        .awaited
+        .iter()
+        .map(|dependency| (dependency.id, dependency.blocked))
+        .collect();
+    assert_eq!(awaited, vec![(id(1), 2), (id(9), 1)]);
+    assert!(summary.oldest_age().unwrap() >= Duration::from_secs(60));
+
+    // Fresh buffers survive a long TTL, and buffering times do not affect equality.
+    assert!(
+        seq.expire_pending(Instant::now(), Duration::from_secs(60))
+            .is_empty()
+    );
+    let mut replayed = Sequence::new();
+    replayed.insert(Some(id(1)), 'b', id(2));
+    replayed.insert(Some(id(1)), 'c', id(3));
+    replayed.delete(id(9), id(4));
+    assert_eq!(seq, replayed);
+
+    let expired = seq.expire_pending(later, Duration::from_secs(60));
+    let expired: Vec<_> = expired.iter().map(|op| (op.id, op.awaited)).collect();
+    assert_eq!(
+        expired,
+        vec![(id(2), id(1)), (id(3), id(1)), (id(4), id(9))]
+    );
+    assert!(seq.pending_summary(later).is_empty());
+
+    // Expired ops no longer wait; a retransmission applies normally.
+    seq.insert(None, 'a', id(1));
+    assert_eq!(seq.to_vec(), vec!['a']);
+    seq.apply(SequenceOp::Insert {
+        after: Some(id(1)),
+        id: id(2),
+        value: 'b',
+        right_origin: None,
+    });
+    assert_eq!(seq.to_vec(), vec!['a', 'b']);
+}
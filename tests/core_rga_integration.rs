@@ -0,0 +1,301 @@
+use md_crdt::core::{OpId, Sequence, SequenceOp};
+use md_crdt_naive_oracle::Sequence as OracleSequence;
+use proptest::collection::vec;
+use proptest::prelude::*;
+mod proptest_config;
+
+const BASE_PEER: u64 = 9;
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+/// "xy", known to every peer before it edits.
+fn base() -> (Sequence<char>, Vec<SequenceOp<char>>) {
+    let mut sequence = Sequence::new();
+    let mut ops = Vec::new();
+    let mut after = None;
+    for (counter, value) in [(1, 'x'), (2, 'y')] {
+        let id = op_id(BASE_PEER, counter);
+        let op = SequenceOp::Insert {
+            after,
+            id,
+            value,
+            right_origin: sequence.compute_right_origin(after),
+        };
+        sequence.apply(op.clone());
+        ops.push(op);
+        after = Some(id);
+    }
+    (sequence, ops)
+}
+
+/// Insert at physical `index` of `replica`, as a local edit would.
+fn local_insert(replica: &mut Sequence<char>, index: usize, id: OpId) -> SequenceOp<char> {
+    let after = index
+        .checked_sub(1)
+        .map(|index| replica.element_ids()[index]);
+    let op = SequenceOp::Insert {
+        after,
+        id,
+        value: char::from(b'a' + (id.peer as u8 - 1) * 3 + id.counter as u8 % 3),
+        right_origin: replica.compute_right_origin(after),
+    };
+    replica.apply(op.clone());
+    op
+}
+
+/// Every merge of `chains` that keeps each chain in order.
+fn interleavings(chains: &[Vec<SequenceOp<char>>]) -> Vec<Vec<SequenceOp<char>>> {
+    fn walk(
+        chains: &[Vec<SequenceOp<char>>],
+        next: &mut Vec<usize>,
+        current: &mut Vec<SequenceOp<char>>,
+        out: &mut Vec<Vec<SequenceOp<char>>>,
+    ) {
+        let mut done = true;
+        for chain in 0..chains.len() {
+            if next[chain] < chains[chain].len() {
+                done = false;
+                current.push(chains[chain][next[chain]].clone());
+                next[chain] += 1;
+                walk(chains, next, current, out);
+                next[chain] -= 1;
+                current.pop();
+            }
+        }
+        if done {
+            out.push(current.clone());
+        }
+    }
+    let mut out = Vec::new();
+    walk(
+        chains,
+        &mut vec![0; chains.len()],
+        &mut Vec::new(),
+        &mut out,
+    );
+    out
+}
+
+fn inserted(op: &SequenceOp<char>) -> (OpId, Option<OpId>, Option<OpId>) {
+    match op {
+        SequenceOp::Insert {
+            after,
+            id,
+            right_origin,
+            ..
+        } => (*id, *after, *right_origin),
+        _ => unreachable!("scenarios only insert"),
+    }
+}
+
+/// Each insert lands between its anchor and its right origin, and every
+/// replica's own view survives the merge.
+fn assert_intentions(order: &[OpId], ops: &[SequenceOp<char>], views: &[Vec<OpId>]) {
+    let position = |id: OpId| order.iter().position(|known| *known == id).unwrap();
+    for op in ops {
+        let (id, after, right_origin) = inserted(op);
+        if let Some(after) = after {
+            assert!(position(after) < position(id), "{id:?} before its anchor");
+        }
+        if let Some(right_origin) = right_origin {
+            assert!(
+                position(id) < position(right_origin),
+                "{id:?} after its right origin"
+            );
+        }
+    }
+    for view in views {
+        let merged: Vec<OpId> = order
+            .iter()
+            .copied()
+            .filter(|id| view.contains(id))
+            .collect();
+        assert_eq!(&merged, view, "a replica's local order was not preserved");
+    }
+}
+
+/// Up to two inserts by each of three peers on "xy", optionally with peer 3
+/// editing after receiving peer 1's inserts, delivered in every chain-respecting
+/// order (peer 3's inserts may arrive before the ones they depend on).
+#[test]
+fn exhaustive_small_cases_converge_to_the_oracle_order() {
+    let (base_sequence, base_ops) = base();
+    // Insert positions for up to two local inserts over a base of two elements.
+    let mut edits: Vec<Vec<usize>> = vec![Vec::new()];
+    for first in 0..=2 {
+        edits.push(vec![first]);
+        for second in 0..=3 {
+            edits.push(vec![first, second]);
+        }
+    }
+
+    let mut scenarios = 0;
+    for peer3_sees_peer1 in [false, true] {
+        for edits1 in &edits {
+            for edits2 in &edits {
+                for edits3 in &edits {
+                    let mut chains = Vec::new();
+                    let mut views = Vec::new();
+                    let mut peer1_ops: Vec<SequenceOp<char>> = Vec::new();
+                    for (peer, positions) in [(1, edits1), (2, edits2), (3, edits3)] {
+                        let mut replica = base_sequence.clone();
+                        if peer == 3 && peer3_sees_peer1 {
+                            for op in &peer1_ops {
+                                replica.apply(op.clone());
+                            }
+                        }
+                        let mut chain = Vec::new();
+                        for (offset, &index) in positions.iter().enumerate() {
+                            let index = index.min(replica.element_ids().len());
+                            let id = op_id(peer, 10 + offset as u64);
+                            chain.push(local_insert(&mut replica, index, id));
+                        }
+                        if peer == 1 {
+                            peer1_ops = chain.clone();
+                        }
+                        views.push(replica.element_ids());
+                        chains.push(chain);
+                    }
+
+                    let ops: Vec<_> = chains.iter().flatten().cloned().collect();
+                    let mut oracle = OracleSequence::new();
+                    for op in base_ops.iter().chain(ops.iter().rev()) {
+                        oracle.apply(op.clone());
+                    }
+
+                    let mut expected: Option<Vec<OpId>> = None;
+                    for delivery in interleavings(&chains) {
+                        let mut replica = base_sequence.clone();
+                        for op in delivery {
+                            replica.apply(op);
+                        }
+                        let order = replica.element_ids();
+                        match &expected {
+                            None => {
+                                assert_eq!(replica.to_vec(), oracle.elements());
+                                assert_intentions(&order, &ops, &views);
+                                expected = Some(order);
+                            }
+                            Some(expected) => assert_eq!(&order, expected),
+                        }
+                    }
+                    scenarios += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(scenarios, 2 * 16 * 16 * 16);
+}
+
+#[derive(Clone, Debug)]
+enum Step {
+    Insert {
+        peer: usize,
+        index: prop::sample::Index,
+    },
+    Delete {
+        peer: usize,
+        index: prop::sample::Index,
+    },
+    /// Deliver every operation made so far to `peer`.
+    Sync { peer: usize },
+}
+
+fn step_strategy() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        4 => (0usize..3, any::<prop::sample::Index>())
+            .prop_map(|(peer, index)| Step::Insert { peer, index }),
+        1 => (0usize..3, any::<prop::sample::Index>())
+            .prop_map(|(peer, index)| Step::Delete { peer, index }),
+        1 => (0usize..3).prop_map(|peer| Step::Sync { peer }),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+    #[test]
+    fn replicas_converge_to_the_oracle_in_any_delivery_order(
+        steps in vec(step_strategy(), 0..60),
+        keys in vec(any::<u64>(), 1..64),
+    ) {
+        let mut replicas = vec![Sequence::new(); 3];
+        let mut counters = [0u64; 3];
+        let mut ops: Vec<SequenceOp<char>> = Vec::new();
+        for step in steps {
+            let op = match step {
+                Step::Insert { peer, index } => {
+                    counters[peer] += 1;
+                    let replica = &mut replicas[peer];
+                    let index = index.index(replica.element_ids().len() + 1);
+                    local_insert(replica, index, op_id(peer as u64 + 1, counters[peer]))
+                }
+                Step::Delete { peer, index } => {
+                    let ids = replicas[peer].element_ids();
+                    if ids.is_empty() {
+                        continue;
+                    }
+                    counters[peer] += 1;
+                    let op = SequenceOp::Delete {
+                        target: ids[index.index(ids.len())],
+                        id: op_id(peer as u64 + 1, counters[peer]),
+                    };
+                    replicas[peer].apply(op.clone());
+                    op
+                }
+                Step::Sync { peer } => {
+                    for op in &ops {
+                        replicas[peer].apply(op.clone());
+                    }
+                    continue;
+                }
+            };
+            ops.push(op);
+        }
+
+        // A fresh replica receiving everything in a scrambled order.
+        let mut scrambled: Vec<_> = ops.iter().cloned().enumerate().collect();
+        scrambled.sort_by_key(|(index, _)| (keys[index % keys.len()], *index));
+        let mut late = Sequence::new();
+        let mut oracle = OracleSequence::new();
+        for (_, op) in scrambled {
+            late.apply(op.clone());
+            oracle.apply(op);
+        }
+        for replica in &mut replicas {
+            for op in &ops {
+                replica.apply(op.clone());
+            }
+            prop_assert_eq!(replica.element_ids(), late.element_ids());
+            prop_assert_eq!(replica.to_vec(), late.to_vec());
+        }
+        prop_assert_eq!(late.to_vec(), oracle.elements());
+    }
+}
+
+#[test]
+fn an_insert_waits_for_its_right_origin() {
+    let mut sequence = Sequence::new();
+    let x = op_id(1, 1);
+    let y = op_id(1, 2);
+    sequence.insert(None, 'x', x);
+    let insert_y = SequenceOp::Insert {
+        after: Some(x),
+        id: y,
+        value: 'y',
+        right_origin: None,
+    };
+    // Typed between x and y by a replica that had both.
+    let between = SequenceOp::Insert {
+        after: Some(x),
+        id: op_id(2, 1),
+        value: 'm',
+        right_origin: Some(y),
+    };
+
+    sequence.apply(between);
+    assert_eq!(sequence.to_vec(), vec!['x']);
+    sequence.apply(insert_y);
+    assert_eq!(sequence.to_vec(), vec!['x', 'm', 'y']);
+}
@@ -0,0 +1,235 @@
+use md_crdt::core::{OpId, RunOp, RunSequence, Sequence, SequenceOp};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+mod proptest_config;
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+/// Expand a run operation into the per-unit operations a [`Sequence`] would see.
+fn expand(op: &RunOp) -> Vec<SequenceOp<String>> {
+    match op {
+        RunOp::Insert {
+            after,
+            id,
+            text,
+            right_origin,
+        } => {
+            let mut anchor = *after;
+            text.graphemes(true)
+                .enumerate()
+                .map(|(offset, unit)| {
+                    let unit_id = op_id(id.peer, id.counter + offset as u64);
+                    let op = SequenceOp::Insert {
+                        after: anchor,
+                        id: unit_id,
+                        value: unit.to_string(),
+                        right_origin: *right_origin,
+                    };
+                    anchor = Some(unit_id);
+                    op
+                })
+                .collect()
+        }
+        RunOp::Delete { target, id } => vec![SequenceOp::Delete {
+            target: *target,
+            id: *id,
+        }],
+    }
+}
+
+#[derive(Clone, Debug)]
+enum OpSpec {
+    Insert {
+        after_index: Option<usize>,
+        len: usize,
+        peer: u64,
+    },
+    Delete {
+        target_index: usize,
+        peer: u64,
+    },
+    /// Deliver every operation so far to `peer`.
+    Sync {
+        peer: u64,
+    },
+}
+
+fn op_spec_strategy() -> impl Strategy<Value = Vec<OpSpec>> {
+    vec(
+        prop_oneof![
+            3 => (any::<Option<prop::sample::Index>>(), 1usize..4, 1u64..4u64)
+                .prop_map(|(after, len, peer)| OpSpec::Insert {
+                    after_index: after.map(|i| i.index(128)),
+                    len,
+                    peer,
+                }),
+            1 => (0usize..128, 1u64..4u64).prop_map(|(target_index, peer)| OpSpec::Delete {
+                target_index,
+                peer,
+            }),
+            1 => (1u64..4u64).prop_map(|peer| OpSpec::Sync { peer }),
+        ],
+        0..40,
+    )
+}
+
+/// Operations as three peers would produce them, each editing its own replica
+/// (so right origins are the ones a replica computes) and syncing at `Sync`.
+fn realize_ops(specs: &[OpSpec]) -> Vec<RunOp> {
+    let mut ops: Vec<RunOp> = Vec::new();
+    let mut replicas: std::collections::BTreeMap<u64, RunSequence> = Default::default();
+    let mut counters: std::collections::BTreeMap<u64, u64> = Default::default();
+    let pick = |units: &[OpId], index: Option<usize>| {
+        index.and_then(|index| (!units.is_empty()).then(|| units[index % units.len()]))
+    };
+
+    for spec in specs {
+        let op = match *spec {
+            OpSpec::Insert {
+                after_index,
+                len,
+                peer,
+            } => {
+                let replica = replicas.entry(peer).or_default();
+                let counter = counters.entry(peer).or_default();
+                let id = op_id(peer, *counter + 1);
+                *counter += len as u64;
+                let after = pick(&replica.unit_ids(), after_index);
+                RunOp::Insert {
+                    after,
+                    id,
+                    text: "abcd"[..len].to_string(),
+                    right_origin: replica.compute_right_origin(after),
+                }
+            }
+            OpSpec::Delete { target_index, peer } => {
+                let replica = replicas.entry(peer).or_default();
+                let counter = counters.entry(peer).or_default();
+                *counter += 1;
+                let target =
+                    pick(&replica.unit_ids(), Some(target_index)).unwrap_or(op_id(9999, 9999));
+                RunOp::Delete {
+                    target,
+                    id: op_id(peer, *counter),
+                }
+            }
+            OpSpec::Sync { peer } => {
+                let replica = replicas.entry(peer).or_default();
+                for op in &ops {
+                    replica.apply(op.clone());
+                }
+                continue;
+            }
+        };
+        let peer = match &op {
+            RunOp::Insert { id, .. } | RunOp::Delete { id, .. } => id.peer,
+        };
+        replicas.entry(peer).or_default().apply(op.clone());
+        ops.push(op);
+    }
+
+    ops
+}
+
+fn unit_sequence(ops: impl IntoIterator<Item = RunOp>) -> Sequence<String> {
+    let mut sequence = Sequence::new();
+    for op in ops {
+        for unit_op in expand(&op) {
+            sequence.apply(unit_op);
+        }
+    }
+    sequence
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+    #[test]
+    fn runs_match_the_per_unit_sequence(specs in op_spec_strategy()) {
+        let ops = realize_ops(&specs);
+        let mut runs = RunSequence::new();
+        let mut reversed = RunSequence::new();
+        for op in &ops {
+            runs.apply(op.clone());
+        }
+        for op in ops.iter().rev() {
+            reversed.apply(op.clone());
+        }
+        let units = unit_sequence(ops);
+
+        prop_assert_eq!(runs.text(), units.to_vec().concat());
+        prop_assert_eq!(runs.unit_ids(), units.element_ids());
+        prop_assert_eq!(reversed.text(), runs.text());
+        prop_assert_eq!(reversed.unit_ids(), runs.unit_ids());
+    }
+}
+
+#[test]
+fn contiguous_typing_extends_a_single_run() {
+    let mut runs = RunSequence::new();
+    runs.insert(None, "he", op_id(1, 1));
+    runs.insert(Some(op_id(1, 2)), "llo", op_id(1, 3));
+    runs.insert(Some(op_id(1, 5)), " 👨‍👩‍👧", op_id(1, 6));
+
+    assert_eq!(runs.text(), "hello 👨‍👩‍👧");
+    assert_eq!(runs.len_visible(), 7);
+    assert_eq!(runs.runs().len(), 1);
+    assert_eq!(runs.units().last(), Some((op_id(1, 7), "👨‍👩‍👧")));
+}
+
+#[test]
+fn concurrent_insert_inside_a_run_splits_it_lazily() {
+    let mut local = RunSequence::new();
+    local.insert(None, "abcd", op_id(1, 1));
+    let right_origin = local.compute_right_origin(Some(op_id(1, 2)));
+    assert_eq!(right_origin, Some(op_id(1, 3)));
+
+    local.apply(RunOp::Insert {
+        after: Some(op_id(1, 2)),
+        id: op_id(2, 1),
+        text: "XY".to_string(),
+        right_origin,
+    });
+
+    assert_eq!(local.text(), "abXYcd");
+    let starts: Vec<_> = local.runs().iter().map(|run| run.id).collect();
+    assert_eq!(starts, vec![op_id(1, 1), op_id(2, 1), op_id(1, 3)]);
+}
+
+#[test]
+fn deleting_inside_a_run_leaves_a_single_unit_tombstone() {
+    let mut runs = RunSequence::new();
+    runs.insert(None, "abcd", op_id(1, 1));
+    runs.delete(op_id(1, 3), op_id(1, 5));
+    runs.delete(op_id(1, 3), op_id(1, 6));
+
+    assert_eq!(runs.text(), "abd");
+    let shape: Vec<_> = runs
+        .runs()
+        .iter()
+        .map(|run| (run.len(), run.is_deleted()))
+        .collect();
+    assert_eq!(shape, vec![(2, false), (1, true), (1, false)]);
+    assert_eq!(runs.unit_ids().len(), 4);
+}
+
+#[test]
+fn operations_wait_for_a_missing_unit_inside_a_later_run() {
+    let mut runs = RunSequence::new();
+    runs.apply(RunOp::Delete {
+        target: op_id(1, 2),
+        id: op_id(2, 1),
+    });
+    runs.apply(RunOp::Insert {
+        after: Some(op_id(1, 3)),
+        id: op_id(2, 2),
+        text: "!".to_string(),
+        right_origin: None,
+    });
+    assert_eq!(runs.text(), "");
+
+    runs.insert(None, "abc", op_id(1, 1));
+    assert_eq!(runs.text(), "ac!");
+}
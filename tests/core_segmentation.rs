@@ -0,0 +1,115 @@
+//! Grapheme clusters under each pinned Unicode version.
+
+use md_crdt::SegmentationRules::{self, Unicode8, Unicode15, Unicode16};
+
+fn clusters(rules: SegmentationRules, text: &str) -> Vec<&str> {
+    rules.graphemes(text).collect()
+}
+
+const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+const THUMBS_UP_MEDIUM: &str = "\u{1F44D}\u{1F3FD}";
+const US: &str = "\u{1F1FA}\u{1F1F8}";
+const FR: &str = "\u{1F1EB}\u{1F1F7}";
+const KSSA: &str = "\u{915}\u{94D}\u{937}";
+
+#[test]
+fn current_rules_match_the_segmentation_crate() {
+    use unicode_segmentation::UnicodeSegmentation;
+    let text = format!("a{FAMILY}{US}{FR}{THUMBS_UP_MEDIUM}{KSSA}e\u{301}\r\n한");
+    let expected: Vec<&str> = text.graphemes(true).collect();
+    assert_eq!(clusters(SegmentationRules::CURRENT, &text), expected);
+    assert_eq!(SegmentationRules::default(), SegmentationRules::CURRENT);
+}
+
+#[test]
+fn zwj_sequences_split_before_unicode_9() {
+    assert_eq!(clusters(Unicode16, FAMILY), vec![FAMILY]);
+    assert_eq!(clusters(Unicode15, FAMILY), vec![FAMILY]);
+    assert_eq!(
+        clusters(Unicode8, FAMILY),
+        vec!["\u{1F468}\u{200D}", "\u{1F469}\u{200D}", "\u{1F467}"]
+    );
+    // A variation selector after the ZWJ still extends under every version.
+    let rainbow = "\u{1F3F3}\u{FE0F}\u{200D}\u{1F308}";
+    assert_eq!(
+        clusters(Unicode8, rainbow),
+        vec!["\u{1F3F3}\u{FE0F}\u{200D}", "\u{1F308}"]
+    );
+}
+
+#[test]
+fn skin_tone_modifiers_split_before_unicode_9() {
+    assert_eq!(
+        clusters(Unicode16, THUMBS_UP_MEDIUM),
+        vec![THUMBS_UP_MEDIUM]
+    );
+    assert_eq!(
+        clusters(Unicode15, THUMBS_UP_MEDIUM),
+        vec![THUMBS_UP_MEDIUM]
+    );
+    assert_eq!(
+        clusters(Unicode8, THUMBS_UP_MEDIUM),
+        vec!["\u{1F44D}", "\u{1F3FD}"]
+    );
+}
+
+#[test]
+fn flags_pair_up_except_under_unicode_8() {
+    let flags = format!("{US}{FR}");
+    assert_eq!(clusters(Unicode16, &flags), vec![US, FR]);
+    assert_eq!(clusters(Unicode15, &flags), vec![US, FR]);
+    assert_eq!(clusters(Unicode8, &flags), vec![flags.as_str()]);
+
+    let odd = format!("{US}\u{1F1EB}x");
+    assert_eq!(clusters(Unicode16, &odd), vec![US, "\u{1F1EB}", "x"]);
+    assert_eq!(
+        clusters(Unicode8, &odd),
+        vec![format!("{US}\u{1F1EB}").as_str(), "x"]
+    );
+}
+
+#[test]
+fn conjuncts_join_from_unicode_15_1() {
+    assert_eq!(clusters(Unicode16, KSSA), vec![KSSA]);
+    assert_eq!(clusters(Unicode15, KSSA), vec!["\u{915}\u{94D}", "\u{937}"]);
+    assert_eq!(clusters(Unicode8, KSSA), vec!["\u{915}\u{94D}", "\u{937}"]);
+}
+
+#[test]
+fn clusters_every_version_agrees_on() {
+    for text in [
+        "e\u{301}",
+        "\r\n",
+        "\u{1100}\u{1161}\u{11A8}",
+        "\u{915}\u{93F}",
+    ] {
+        for rules in SegmentationRules::ALL {
+            assert_eq!(clusters(rules, text), vec![text], "{rules} on {text:?}");
+        }
+    }
+}
+
+#[test]
+fn offsets_and_boundaries_follow_the_rules() {
+    let text = format!("a{THUMBS_UP_MEDIUM}b");
+    let modifier = 1 + "\u{1F44D}".len();
+    assert!(!Unicode16.is_boundary(&text, modifier));
+    assert!(Unicode8.is_boundary(&text, modifier));
+    assert_eq!(Unicode16.count(&text), 3);
+    assert_eq!(Unicode8.count(&text), 4);
+    assert_eq!(Unicode8.byte_offset(&text, 2), Some(modifier));
+    assert_eq!(Unicode8.byte_offset(&text, 4), Some(text.len()));
+    assert_eq!(Unicode8.byte_offset(&text, 5), None);
+    let indices: Vec<usize> = Unicode8.grapheme_indices(&text).map(|(i, _)| i).collect();
+    assert_eq!(indices, vec![0, 1, modifier, text.len() - 1]);
+}
+
+#[test]
+fn every_version_covers_the_text_exactly() {
+    let text = format!("x{FAMILY}{US}{FR}\u{1F1EB}{KSSA}{THUMBS_UP_MEDIUM}\r\ne\u{301}");
+    for rules in SegmentationRules::ALL {
+        assert_eq!(clusters(rules, &text).concat(), text, "{rules}");
+    }
+    assert_eq!(Unicode16.version(), (16, 0));
+    assert_eq!(Unicode8.to_string(), "Unicode 8.0 grapheme clusters");
+}
@@ -1,4 +1,4 @@
-use md_crdt::core::{OpId, Sequence, SequenceOp};
+use md_crdt::core::{OpId, Sequence, SequenceOp, StateVector};
 use proptest::collection::vec;
 use proptest::prelude::*;
 mod proptest_config;
@@ -71,3 +71,211 @@ fn test_concurrent_insert_ordering() {
         "Concurrent inserts should be ordered by descending OpId"
     );
 }
+
+fn insert(after: Option<OpId>, id: OpId, value: char) -> SequenceOp<char> {
+    SequenceOp::Insert {
+        after,
+        id,
+        value,
+        right_origin: None,
+    }
+}
+
+/// Peer 1 writes `a b c` and peer 2 slips `x` after `a`; peer 3 deletes
+/// `a..=c` having seen all of it while peer 4 concurrently inserts `y` after `a`.
+/// Both `x` and `y` sort between `a` and `b`.
+fn range_delete_scenario() -> Vec<SequenceOp<char>> {
+    let mut observed = StateVector::new();
+    observed.set(1, 3);
+    observed.set(2, 5);
+    vec![
+        insert(None, op_id(1, 1), 'a'),
+        insert(Some(op_id(1, 1)), op_id(1, 2), 'b'),
+        insert(Some(op_id(1, 2)), op_id(1, 3), 'c'),
+        insert(Some(op_id(1, 1)), op_id(2, 5), 'x'),
+        insert(Some(op_id(1, 1)), op_id(4, 9), 'y'),
+        SequenceOp::DeleteRange {
+            from: op_id(1, 1),
+            to: op_id(1, 3),
+            id: op_id(3, 1),
+            observed,
+        },
+    ]
+}
+
+#[test]
+fn delete_range_keeps_concurrent_inserts_inside_the_range() {
+    let mut sequence = Sequence::new();
+    for op in range_delete_scenario() {
+        sequence.apply(op);
+    }
+
+    assert_eq!(sequence.to_vec(), vec!['y']);
+    assert_eq!(
+        sequence.iter_all().count(),
+        5,
+        "range delete leaves tombstones"
+    );
+}
+
+#[test]
+fn delete_range_covers_observed_inserts_that_arrive_late() {
+    let ops = range_delete_scenario();
+    let mut sequence = Sequence::new();
+    // Range first: both endpoints are missing, so it buffers on `from`.
+    sequence.apply(ops[5].clone());
+    sequence.apply(ops[0].clone());
+    sequence.apply(ops[2].clone());
+    assert_eq!(sequence.to_vec(), vec!['a'], "`to` is buffered behind `b`");
+    sequence.apply(ops[3].clone());
+    sequence.apply(ops[4].clone());
+    sequence.apply(ops[1].clone());
+
+    assert_eq!(sequence.to_vec(), vec!['y']);
+}
+
+#[test]
+fn delete_range_all_clears_what_was_observed_in_one_op() {
+    let mut sequence = Sequence::new();
+    assert!(
+        sequence
+            .delete_range_all(op_id(1, 9), StateVector::new())
+            .is_none()
+    );
+    sequence.insert(None, 'a', op_id(1, 1));
+    sequence.insert(Some(op_id(1, 1)), 'b', op_id(1, 2));
+    sequence.insert(Some(op_id(1, 2)), 'c', op_id(1, 3));
+    sequence.delete(op_id(1, 1), op_id(1, 4));
+    let mut peer = sequence.clone();
+    peer.insert(Some(op_id(1, 2)), 'x', op_id(2, 1));
+
+    let mut observed = StateVector::new();
+    observed.set(1, 4);
+    let op = sequence
+        .delete_range_all(op_id(1, 5), observed.clone())
+        .unwrap();
+    assert_eq!(
+        op,
+        SequenceOp::DeleteRange {
+            from: op_id(1, 1),
+            to: op_id(1, 3),
+            id: op_id(1, 5),
+            observed,
+        }
+    );
+    assert!(sequence.to_vec().is_empty());
+
+    peer.apply(op);
+    assert_eq!(peer.to_vec(), vec!['x']);
+}
+
+#[test]
+fn delete_range_converges_under_every_delivery_order() {
+    fn permutations(items: &[usize]) -> Vec<Vec<usize>> {
+        if items.len() <= 1 {
+            return vec![items.to_vec()];
+        }
+        let mut out = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            let mut rest = items.to_vec();
+            rest.remove(index);
+            for mut tail in permutations(&rest) {
+                tail.insert(0, *item);
+                out.push(tail);
+            }
+        }
+        out
+    }
+
+    let ops = range_delete_scenario();
+    let mut reference = Sequence::new();
+    for op in &ops {
+        reference.apply(op.clone());
+    }
+
+    for order in permutations(&(0..ops.len()).collect::<Vec<_>>()) {
+        let mut sequence = Sequence::new();
+        for index in &order {
+            sequence.apply(ops[*index].clone());
+        }
+        assert_eq!(sequence.to_vec(), vec!['y'], "order {order:?}");
+        assert_eq!(
+            sequence.element_ids(),
+            reference.element_ids(),
+            "order {order:?}"
+        );
+    }
+}
+
+#[test]
+fn drop_tombstones_keeps_origins_anchors_and_unfloored_ids() {
+    let mut seq = Sequence::new();
+    seq.insert(None, 'a', op_id(1, 1));
+    seq.insert(Some(op_id(1, 1)), 'b', op_id(1, 2));
+    seq.insert(Some(op_id(1, 2)), 'c', op_id(1, 3));
+    seq.insert(Some(op_id(1, 3)), 'd', op_id(1, 4));
+    seq.insert(Some(op_id(1, 4)), 'e', op_id(1, 5));
+    for target in 2..=5 {
+        seq.delete(op_id(1, target), op_id(1, 5 + target));
+    }
+
+    let mut floor = StateVector::new();
+    floor.set(1, 4);
+    let keep = std::collections::BTreeSet::from([op_id(1, 2)]);
+    // 'e' is above the floor, so 'd' and 'c' stay its origins; 'b' is kept.
+    assert_eq!(seq.drop_tombstones(&floor, &keep), 0);
+
+    floor.set(1, 10);
+    assert_eq!(seq.drop_tombstones(&floor, &keep), 3);
+    assert_eq!(seq.element_ids(), vec![op_id(1, 1), op_id(1, 2)]);
+    seq.insert(Some(op_id(1, 2)), 'f', op_id(1, 11));
+    assert_eq!(seq.visible_string(), "af");
+}
+
+#[test]
+fn inserts_next_to_a_dropped_tombstone_converge() {
+    let mut a = Sequence::new();
+    a.insert(None, 'x', op_id(1, 1));
+    a.insert(Some(op_id(1, 1)), 't', op_id(1, 2));
+    a.delete(op_id(1, 2), op_id(1, 3));
+    let mut b = a.clone();
+
+    let mut floor = StateVector::new();
+    floor.set(1, 3);
+    assert_eq!(a.drop_tombstones(&floor, &Default::default()), 1);
+    assert_eq!(a.compacted(), &floor);
+
+    // B still holds the tombstone: `y` names it as right origin, `z` as anchor.
+    let insert = |sequence: &Sequence<char>, after, value, id| SequenceOp::Insert {
+        after,
+        id,
+        value,
+        right_origin: sequence.compute_right_origin(after),
+    };
+    let y = insert(&b, Some(op_id(1, 1)), 'y', op_id(2, 1));
+    assert!(matches!(y, SequenceOp::Insert { right_origin: Some(t), .. } if t == op_id(1, 2)));
+    b.apply(y.clone());
+    let z = insert(&b, Some(op_id(1, 2)), 'z', op_id(2, 2));
+    b.apply(z.clone());
+    // A late delete of the dropped tombstone is already done.
+    let delete = SequenceOp::Delete {
+        target: op_id(1, 2),
+        id: op_id(2, 3),
+    };
+    b.apply(delete.clone());
+    for op in [y, z, delete] {
+        a.apply(op);
+    }
+
+    assert_eq!(a.stats().pending_ops, 0);
+    assert_eq!(a.to_vec(), vec!['x', 'y', 'z']);
+    assert_eq!(a.to_vec(), b.to_vec());
+
+    // Re-sending the dropped tombstone does not bring it back, and the floor
+    // survives a serde round trip.
+    a.insert(None, 'w', op_id(1, 2));
+    let restored: Sequence<char> =
+        serde_json::from_str(&serde_json::to_string(&a).unwrap()).unwrap();
+    assert_eq!(restored.compacted(), &floor);
+    assert_eq!(restored.to_vec(), vec!['x', 'y', 'z']);
+}
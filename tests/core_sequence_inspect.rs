@@ -0,0 +1,92 @@
+use md_crdt::core::{ElementInfo, OpId, Sequence};
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn inspect_reports_visible_indices_origins_and_tombstones() {
+    let mut seq = Sequence::new();
+    seq.insert(None, 'a', op_id(1, 1));
+    seq.insert(Some(op_id(1, 1)), 'b', op_id(1, 2));
+    seq.insert(Some(op_id(1, 2)), 'c', op_id(1, 3));
+    seq.delete(op_id(1, 2), op_id(1, 4));
+    // Concurrent insert between `a` and `c` from another peer.
+    seq.insert(Some(op_id(1, 1)), 'x', op_id(2, 1));
+
+    let info: Vec<ElementInfo> = seq.inspect().collect();
+    assert_eq!(info.len(), 4);
+    assert_eq!(
+        info.iter().map(|e| e.visible_index).collect::<Vec<_>>(),
+        vec![Some(0), Some(1), None, Some(2)]
+    );
+    assert_eq!(info[2].id, op_id(1, 2));
+    assert!(info[2].is_tombstone);
+    assert_eq!(info[1].after, Some(op_id(1, 1)));
+    assert_eq!(info[1].right_origin, Some(op_id(1, 2)));
+
+    assert_eq!(
+        seq.debug_dump(),
+        "#0 1@1 after=- right=-\n\
+         #1 2@1 after=1@1 right=1@2\n\
+         #- 1@2 after=1@1 right=- deleted\n\
+         #2 1@3 after=1@2 right=-\n"
+    );
+    assert_eq!(Sequence::<char>::new().debug_dump(), "");
+}
+
+#[cfg(feature = "filesync")]
+#[test]
+#[allow(deprecated)]
+fn inspect_command_prints_the_requested_sections() {
+    use assert_cmd::prelude::*;
+    use std::process::Command;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("note.md"), "# Title\n\n> body **bold**\n").unwrap();
+    Command::cargo_bin("md-crdt")
+        .unwrap()
+        .arg("ingest")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+    let inspect = |args: &[&str]| {
+        let output = Command::cargo_bin("md-crdt")
+            .unwrap()
+            .arg("inspect")
+            .arg("note.md")
+            .args(args)
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let all = inspect(&[]);
+    for section in ["blocks:", "elements:", "marks:", "ops:"] {
+        assert!(all.lines().any(|line| line == section), "{section}");
+    }
+    let tombstones = inspect(&["--tombstones"]);
+    assert!(tombstones.starts_with("elements:\n  #0 "));
+    assert!(!tombstones.contains("blocks:"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&inspect(&["--blocks", "--marks", "--ops", "--json"])).unwrap();
+    assert!(json.get("elements").is_none());
+    let blocks = json["blocks"].as_array().unwrap();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[0]["kind"], "heading 1");
+    assert_eq!(blocks[0]["text"], "Title");
+    assert_eq!(blocks[2]["depth"], 1);
+    let marks = json["marks"].as_array().unwrap();
+    assert_eq!(marks.len(), 1);
+    assert_eq!(marks[0]["kind"], "bold");
+    assert_eq!(marks[0]["active"], true);
+    let ops = json["ops"].as_array().unwrap();
+    assert!(
+        ops.iter()
+            .all(|op| op["state"] == "applied" && op["op"] != "undecodable")
+    );
+    assert!(ops.iter().any(|op| op["op"] == "SetMark"));
+}
@@ -0,0 +1,272 @@
+//! `Sequence::merge_state` against op replay.
+
+use md_crdt::core::{Element, Mergeable, OpId, Sequence, SequenceOp, StateVector};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::collections::BTreeSet;
+mod proptest_config;
+
+const PEERS: usize = 3;
+
+#[derive(Clone, Debug)]
+enum Step {
+    Insert {
+        peer: usize,
+        at: Index,
+        value: u8,
+    },
+    Delete {
+        peer: usize,
+        at: Index,
+    },
+    DeleteRange {
+        peer: usize,
+        from: Index,
+        len: usize,
+    },
+    /// Hand `to` the last `count` operations `from` applied, possibly newest
+    /// first, so some wait on what they depend on.
+    Deliver {
+        from: usize,
+        to: usize,
+        count: Index,
+        reversed: bool,
+    },
+}
+
+fn steps() -> impl Strategy<Value = Vec<Step>> {
+    vec(
+        prop_oneof![
+            3 => (0..PEERS, any::<Index>(), any::<u8>())
+                .prop_map(|(peer, at, value)| Step::Insert { peer, at, value }),
+            1 => (0..PEERS, any::<Index>()).prop_map(|(peer, at)| Step::Delete { peer, at }),
+            1 => (0..PEERS, any::<Index>(), 0..4usize)
+                .prop_map(|(peer, from, len)| Step::DeleteRange { peer, from, len }),
+            2 => (0..PEERS, 0..PEERS, any::<Index>(), any::<bool>()).prop_map(
+                |(from, to, count, reversed)| Step::Deliver {
+                    from,
+                    to,
+                    count,
+                    reversed,
+                }
+            ),
+        ],
+        0..50,
+    )
+}
+
+/// One peer's state and every operation it applied, in order.
+#[derive(Clone, Default)]
+struct Replica {
+    sequence: Sequence<u8>,
+    log: Vec<SequenceOp<u8>>,
+    counter: u64,
+}
+
+impl Replica {
+    fn next_id(&mut self, peer: usize) -> OpId {
+        self.counter += 1;
+        OpId {
+            counter: self.counter,
+            peer: peer as u64 + 1,
+        }
+    }
+
+    fn visible(&self) -> Vec<OpId> {
+        self.sequence
+            .iter_all()
+            .filter(|elem| elem.value.is_some())
+            .map(|elem| elem.id)
+            .collect()
+    }
+
+    fn apply(&mut self, op: SequenceOp<u8>) {
+        self.sequence.apply(op.clone());
+        self.log.push(op);
+    }
+}
+
+fn run(steps: &[Step]) -> Vec<Replica> {
+    let mut replicas = vec![Replica::default(); PEERS];
+    for step in steps {
+        match step {
+            Step::Insert { peer, at, value } => {
+                let replica = &mut replicas[*peer];
+                let visible = replica.visible();
+                let after = match at.index(visible.len() + 1) {
+                    0 => None,
+                    position => Some(visible[position - 1]),
+                };
+                let id = replica.next_id(*peer);
+                let right_origin = replica.sequence.compute_right_origin(after);
+                replica.apply(SequenceOp::Insert {
+                    after,
+                    id,
+                    value: *value,
+                    right_origin,
+                });
+            }
+            Step::Delete { peer, at } => {
+                let replica = &mut replicas[*peer];
+                let visible = replica.visible();
+                if visible.is_empty() {
+                    continue;
+                }
+                let target = visible[at.index(visible.len())];
+                let id = replica.next_id(*peer);
+                replica.apply(SequenceOp::Delete { target, id });
+            }
+            Step::DeleteRange { peer, from, len } => {
+                let replica = &mut replicas[*peer];
+                let visible = replica.visible();
+                if visible.is_empty() {
+                    continue;
+                }
+                let start = from.index(visible.len());
+                let end = (start + len).min(visible.len() - 1);
+                let observed = replica.sequence.state_vector();
+                let id = replica.next_id(*peer);
+                replica.apply(SequenceOp::DeleteRange {
+                    from: visible[start],
+                    to: visible[end],
+                    id,
+                    observed,
+                });
+            }
+            Step::Deliver {
+                from,
+                to,
+                count,
+                reversed,
+            } => {
+                if from == to {
+                    continue;
+                }
+                let log = &replicas[*from].log;
+                let mut ops = log[log.len() - count.index(log.len() + 1)..].to_vec();
+                if *reversed {
+                    ops.reverse();
+                }
+                for op in ops {
+                    replicas[*to].apply(op);
+                }
+            }
+        }
+    }
+    replicas
+}
+
+/// Elements in order, and the buffered operations and range deletes as a set.
+fn state(sequence: &Sequence<u8>) -> (Vec<Element<u8>>, BTreeSet<String>) {
+    let elements = sequence.iter_all().cloned().collect();
+    let serialized = serde_json::to_value(sequence).unwrap();
+    let pending = serialized["pending"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    (elements, pending)
+}
+
+fn merged(left: &Sequence<u8>, right: &Sequence<u8>) -> Sequence<u8> {
+    let mut merged = left.clone();
+    merged.merge_state(right);
+    merged
+}
+
+fn replayed(left: &Sequence<u8>, right: &Replica) -> Sequence<u8> {
+    let mut replayed = left.clone();
+    for op in &right.log {
+        replayed.apply(op.clone());
+    }
+    replayed
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+
+    #[test]
+    fn merging_state_matches_replaying_the_other_replicas_operations(steps in steps()) {
+        let replicas = run(&steps);
+        for left in &replicas {
+            for right in &replicas {
+                let expected = state(&replayed(&left.sequence, right));
+                prop_assert_eq!(state(&merged(&left.sequence, &right.sequence)), expected.clone());
+
+                let mut shipped = left.sequence.clone();
+                for delta in right.sequence.ops_since(&StateVector::new()) {
+                    Mergeable::apply_op(&mut shipped, delta);
+                }
+                prop_assert_eq!(state(&shipped), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn merging_state_is_commutative_associative_and_idempotent(steps in steps()) {
+        let replicas = run(&steps);
+        let [a, b, c] = [0, 1, 2].map(|peer| &replicas[peer].sequence);
+
+        prop_assert_eq!(state(&merged(a, b)), state(&merged(b, a)));
+        prop_assert_eq!(
+            state(&merged(&merged(a, b), c)),
+            state(&merged(a, &merged(b, c)))
+        );
+        prop_assert_eq!(state(&merged(a, a)), state(a));
+        let ab = merged(a, b);
+        prop_assert_eq!(state(&merged(&ab, b)), state(&ab));
+        prop_assert_eq!(state(&merged(&ab, a)), state(&ab));
+    }
+
+    #[test]
+    fn merged_replicas_converge_with_a_replica_that_saw_every_operation(steps in steps()) {
+        let replicas = run(&steps);
+        let mut everything = Sequence::new();
+        for replica in &replicas {
+            for op in &replica.log {
+                everything.apply(op.clone());
+            }
+        }
+        let mut joined = Sequence::new();
+        for replica in replicas.iter().rev() {
+            joined.merge_state(&replica.sequence);
+        }
+        prop_assert_eq!(state(&joined), state(&everything));
+        prop_assert_eq!(joined.to_vec(), everything.to_vec());
+    }
+}
+
+#[test]
+fn an_empty_replica_bootstraps_from_a_snapshot_state() {
+    let op = |counter| OpId { counter, peer: 1 };
+    let mut source = Sequence::new();
+    source.insert(None, 'a', op(1));
+    source.insert(Some(op(1)), 'b', op(2));
+    source.insert(Some(op(2)), 'c', op(3));
+    source.delete(op(2), op(4));
+    // Waits on an element this replica never received.
+    source.delete(
+        OpId {
+            counter: 1,
+            peer: 9,
+        },
+        op(5),
+    );
+
+    let mut fresh = Sequence::new();
+    fresh.merge_state(&source);
+    assert_eq!(fresh, source);
+    assert_eq!(fresh.to_vec(), vec!['a', 'c']);
+
+    fresh.insert(
+        None,
+        'x',
+        OpId {
+            counter: 1,
+            peer: 9,
+        },
+    );
+    assert_eq!(fresh.to_vec(), vec!['a', 'c']);
+}
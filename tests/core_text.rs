@@ -0,0 +1,80 @@
+use md_crdt::{MarkKind, Text};
+use std::collections::BTreeMap;
+
+#[test]
+fn grapheme_offsets_drive_insert_delete_and_slice() {
+    let mut title = Text::new(1);
+    title.insert(0, "Hello world");
+    title.insert(5, ",");
+    title.insert(title.len_graphemes(), " 👋🏽");
+
+    assert_eq!(title.to_string(), "Hello, world 👋🏽");
+    assert_eq!(title.len_graphemes(), 14);
+    assert_eq!(title.slice(7..12), "world");
+    assert_eq!(title.slice(13..14), "👋🏽");
+
+    let ops = title.delete(5..6);
+    assert_eq!(ops.len(), 1);
+    assert_eq!(title.to_string(), "Hello world 👋🏽");
+    assert!(title.insert(0, "").is_empty());
+}
+
+#[test]
+fn concurrent_edits_converge_when_ops_are_exchanged() {
+    let mut left = Text::new(1);
+    let base = left.insert(0, "shared");
+    let mut right = Text::new(2);
+    for op in base {
+        right.apply(op);
+    }
+
+    let from_left = [left.insert(0, "my "), left.delete(5..9)].concat();
+    let from_right = right.insert(6, " text");
+    for op in from_right {
+        left.apply(op);
+    }
+    for op in from_left {
+        right.apply(op);
+    }
+
+    assert_eq!(left.to_string(), right.to_string());
+    assert_eq!(left.to_string(), "my sh text");
+    assert_eq!(left.observed(), right.observed());
+}
+
+#[test]
+fn marks_follow_their_units_and_can_be_removed() {
+    let mut text = Text::new(1);
+    let mut log = text.insert(0, "plain bold plain");
+    let mark = text.mark(6..10, MarkKind::Bold, BTreeMap::new());
+    log.extend(mark.clone());
+    log.extend(text.insert(0, ">> "));
+
+    let bold = |text: &Text| -> Vec<String> {
+        text.spans()
+            .into_iter()
+            .filter(|span| !span.marks.is_empty())
+            .map(|span| text.slice(span.start..span.end))
+            .collect()
+    };
+    assert_eq!(bold(&text), vec!["bold"]);
+
+    let mut replica = Text::new(2);
+    for op in log {
+        replica.apply(op);
+    }
+    assert_eq!(bold(&replica), vec!["bold"]);
+
+    let md_crdt::TextOp::SetMark { interval_id, .. } = &mark[0] else {
+        panic!("mark() emits a set-mark op");
+    };
+    text.unmark(*interval_id);
+    assert!(bold(&text).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn insert_past_the_end_panics() {
+    let mut text = Text::new(1);
+    text.insert(1, "x");
+}
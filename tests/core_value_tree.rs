@@ -0,0 +1,153 @@
+use md_crdt::core::{OpId, PathKey, Scalar, ValueInit, ValueOp, ValueTree};
+use serde_json::json;
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn string(value: &str) -> ValueInit {
+    ValueInit::Scalar(Scalar::String(value.to_string()))
+}
+
+/// Frontmatter-like tree: `{ title: Text, tags: [..], settings: { theme } }`.
+fn seeded() -> (ValueTree, Vec<ValueOp>) {
+    let mut tree = ValueTree::new();
+    let mut log = vec![
+        tree.set(vec![], "title", ValueInit::Text, op_id(1, 1)),
+        tree.set(vec![], "tags", ValueInit::List, op_id(1, 2)),
+        tree.set(vec![], "settings", ValueInit::Map, op_id(1, 3)),
+    ];
+    let title = tree.path(&[PathKey::Key("title")]).unwrap();
+    let tags = tree.path(&[PathKey::Key("tags")]).unwrap();
+    let settings = tree.path(&[PathKey::Key("settings")]).unwrap();
+    log.extend(tree.insert_text(title, 0, "Notes", op_id(1, 4)));
+    log.extend(tree.insert(tags.clone(), 0, string("crdt"), op_id(1, 10)));
+    log.extend(tree.insert(tags, 1, string("rust"), op_id(1, 11)));
+    log.push(tree.set(settings, "theme", string("dark"), op_id(1, 12)));
+    (tree, log)
+}
+
+#[test]
+fn nested_values_materialize_as_json() {
+    let (tree, _) = seeded();
+    assert_eq!(
+        tree.to_json(),
+        json!({
+            "settings": { "theme": "dark" },
+            "tags": ["crdt", "rust"],
+            "title": "Notes",
+        })
+    );
+    let theme = tree
+        .path(&[PathKey::Key("settings"), PathKey::Key("theme")])
+        .unwrap();
+    assert_eq!(
+        tree.get(&theme).and_then(|value| value.as_scalar()),
+        Some(&Scalar::String("dark".to_string()))
+    );
+}
+
+#[test]
+fn concurrent_edits_merge_at_every_level() {
+    let (mut left, log) = seeded();
+    let mut right = ValueTree::new();
+    for op in log {
+        right.apply(op);
+    }
+    assert_eq!(left, right);
+
+    let title = left.path(&[PathKey::Key("title")]).unwrap();
+    let tags = left.path(&[PathKey::Key("tags")]).unwrap();
+    let settings = left.path(&[PathKey::Key("settings")]).unwrap();
+    let from_left = vec![
+        left.insert_text(title.clone(), 5, "!", op_id(1, 20))
+            .unwrap(),
+        left.insert(tags.clone(), 2, string("sync"), op_id(1, 21))
+            .unwrap(),
+        left.set(settings.clone(), "font", string("mono"), op_id(1, 22)),
+    ];
+    let from_right = vec![
+        right.insert_text(title, 0, "My ", op_id(2, 1)).unwrap(),
+        right.delete(tags, 0, op_id(2, 4)).unwrap(),
+        right.set(settings, "theme", string("light"), op_id(2, 13)),
+    ];
+    for op in from_right {
+        left.apply(op);
+    }
+    for op in from_left {
+        right.apply(op);
+    }
+
+    assert_eq!(left.to_json(), right.to_json());
+    assert_eq!(
+        left.to_json(),
+        json!({
+            "settings": { "font": "mono", "theme": "light" },
+            "tags": ["rust", "sync"],
+            "title": "My Notes!",
+        })
+    );
+}
+
+#[test]
+fn edits_inside_a_replaced_container_are_dropped() {
+    let mut left = ValueTree::new();
+    let first = left.set(vec![], "meta", ValueInit::Map, op_id(1, 1));
+    let meta = left.path(&[PathKey::Key("meta")]).unwrap();
+    let nested = left.set(
+        meta,
+        "draft",
+        ValueInit::Scalar(Scalar::Bool(true)),
+        op_id(1, 2),
+    );
+
+    // A concurrent higher assignment replaces the whole map.
+    let replace = ValueOp::Set {
+        target: vec![],
+        key: "meta".to_string(),
+        value: ValueInit::List,
+        id: op_id(2, 5),
+    };
+    left.apply(replace.clone());
+
+    let mut right = ValueTree::new();
+    for op in [replace, nested, first] {
+        right.apply(op);
+    }
+
+    assert_eq!(left.to_json(), json!({ "meta": [] }));
+    assert_eq!(left, right);
+    assert_eq!(
+        right.pending_len(),
+        0,
+        "stale ops are dropped, not buffered"
+    );
+}
+
+#[test]
+fn ops_wait_for_their_container_and_removes_are_observed() {
+    let (source, log) = seeded();
+    let mut reversed = ValueTree::new();
+    for op in log.iter().rev() {
+        reversed.apply(op.clone());
+    }
+    assert_eq!(reversed.to_json(), source.to_json());
+    assert_eq!(reversed.pending_len(), 0);
+
+    let mut left = source.clone();
+    let mut right = source;
+    let remove = left.remove(vec![], "settings", op_id(1, 30)).unwrap();
+    let reassign = right.set(vec![], "settings", ValueInit::Map, op_id(2, 30));
+    left.apply(reassign);
+    right.apply(remove);
+
+    assert_eq!(left.to_json(), right.to_json());
+    assert_eq!(
+        left.root()
+            .get("settings")
+            .and_then(|v| v.as_map())
+            .map(|m| m.len()),
+        Some(0)
+    );
+    assert!(left.remove(vec![], "missing", op_id(1, 31)).is_none());
+}
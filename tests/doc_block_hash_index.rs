@@ -0,0 +1,57 @@
+//! Per-block content hashes for comparing replicas.
+
+use md_crdt::core::OpId;
+use md_crdt::doc::{BlockHash, Parser, diverged_blocks};
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn replicas_with_equal_content_have_equal_indexes() {
+    let doc = Parser::parse("# Title\n\nfirst\n\n> quoted\n> text\n\n- a\n- b\n");
+    let replica = doc.clone();
+    let index = doc.block_hash_index();
+    assert_eq!(index.len(), 4);
+    assert_eq!(
+        index
+            .iter()
+            .map(|entry| entry.order_index)
+            .collect::<Vec<_>>(),
+        [0, 1, 2, 3]
+    );
+    assert_eq!(index, replica.block_hash_index());
+    assert!(diverged_blocks(&index, &replica.block_hash_index()).is_empty());
+}
+
+#[test]
+fn edits_change_only_the_edited_blocks_hash() {
+    let mut doc = Parser::parse("one\n\ntwo\n\nthree\n");
+    let replica = doc.clone();
+    let before = doc.block_hash_index();
+    let edited = before[1].block_id;
+
+    doc.insert_text(edited, 3, "!", op(1000, 9)).unwrap();
+    let after = doc.block_hash_index();
+    assert_ne!(after[1].content_hash, before[1].content_hash);
+    assert_eq!(after[0], before[0]);
+    assert_eq!(after[2], before[2]);
+    assert_eq!(
+        diverged_blocks(&after, &replica.block_hash_index()),
+        [edited]
+    );
+    assert_eq!(
+        after,
+        doc.clone().block_hash_index(),
+        "cached hashes match fresh ones"
+    );
+}
+
+#[test]
+fn blocks_on_one_side_only_are_diverged() {
+    let doc = Parser::parse("kept\n\nextra\n");
+    let index = doc.block_hash_index();
+    let trimmed: Vec<BlockHash> = index[..1].to_vec();
+    assert_eq!(diverged_blocks(&index, &trimmed), [index[1].block_id]);
+    assert_eq!(diverged_blocks(&trimmed, &index), [index[1].block_id]);
+}
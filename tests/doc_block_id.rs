@@ -65,3 +65,56 @@ fn table_insert_row_id_matches_op() {
     assert_eq!(row.id, block_id_from_op(row_op));
     assert_eq!(row.elem_id, row_op);
 }
+
+#[test]
+fn parse_with_ids_allocates_from_the_given_peer_and_counter() {
+    let input = "# Title\n\nHello *world*\n\n- a\n- b\n";
+    let mut counter = 100;
+    let doc = Parser::parse_with_ids(input, 5, &mut counter);
+    let blocks = doc.blocks_in_order();
+    assert!(
+        blocks
+            .iter()
+            .all(|b| b.elem_id.peer == 5 && b.elem_id.counter >= 100)
+    );
+    assert!(counter > 100);
+    assert_eq!(
+        doc.serialize(md_crdt::doc::EquivalenceMode::Structural),
+        Parser::parse(input).serialize(md_crdt::doc::EquivalenceMode::Structural)
+    );
+
+    // A second import continues where the first stopped.
+    let first_next = counter;
+    let again = Parser::parse_with_ids(input, 5, &mut counter);
+    assert!(
+        again
+            .blocks_in_order()
+            .iter()
+            .all(|b| b.elem_id.counter >= first_next)
+    );
+}
+
+#[test]
+fn seeded_parse_converges_and_keeps_block_ids_across_edits() {
+    let input = "# Title\n\nSame\n\nSame\n\nTail\n";
+    let ids = |doc: &md_crdt::doc::Document| -> Vec<OpId> {
+        doc.blocks_in_order().iter().map(|b| b.elem_id).collect()
+    };
+    let a = Parser::parse_seeded(input, 9);
+    let b = Parser::parse_seeded(input, 9);
+    assert_eq!(ids(&a), ids(&b));
+    assert!(ids(&a).iter().all(|id| id.peer == 9));
+    // Identical blocks still get distinct ids.
+    let mut distinct = ids(&a);
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 4);
+
+    // A block inserted above leaves the others' ids untouched.
+    let edited = Parser::parse_seeded("# Title\n\nNew\n\nSame\n\nSame\n\nTail\n", 9);
+    let edited_ids = ids(&edited);
+    assert_eq!(edited_ids[0], ids(&a)[0]);
+    assert_eq!(edited_ids[2..], ids(&a)[1..]);
+
+    assert_ne!(ids(&Parser::parse_seeded(input, 10)), ids(&a));
+}
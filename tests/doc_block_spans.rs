@@ -0,0 +1,121 @@
+use md_crdt::core::mark::MarkKind;
+use md_crdt::doc::{BlockId, block_id_from_op};
+use md_crdt::session::CollaborativeDocument;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+mod proptest_config;
+
+fn session_with(text: &str) -> (CollaborativeDocument, BlockId) {
+    let mut session = CollaborativeDocument::new(1);
+    let elem = session.insert_paragraph(None, text).unwrap();
+    (session, block_id_from_op(elem))
+}
+
+#[test]
+fn dirty_range_covers_only_the_edit_neighborhood() {
+    let (mut session, block_id) = session_with("0123456789");
+    let document = session.document();
+    assert_eq!(document.marks_dirty_range(block_id).unwrap(), Some(0..10));
+    document.block_spans(block_id).unwrap();
+    assert_eq!(document.marks_dirty_range(block_id).unwrap(), None);
+
+    session
+        .set_mark(block_id, 2..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    assert_eq!(
+        session.document().marks_dirty_range(block_id).unwrap(),
+        Some(2..5)
+    );
+    session.document().block_spans(block_id).unwrap();
+
+    session.insert_text(block_id, 8, "ab").unwrap();
+    assert_eq!(
+        session.document().marks_dirty_range(block_id).unwrap(),
+        Some(8..10)
+    );
+    session.document().block_spans(block_id).unwrap();
+
+    session.delete_text(block_id, 0, 1).unwrap();
+    assert_eq!(
+        session.document().marks_dirty_range(block_id).unwrap(),
+        Some(0..0)
+    );
+    let spans = session.document().block_spans(block_id).unwrap();
+    assert_eq!(
+        spans,
+        session.document().render_paragraph_spans(block_id).unwrap()
+    );
+    assert_eq!((spans[1].start, spans[1].end), (1, 4));
+}
+
+#[derive(Debug, Clone)]
+enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, len: usize },
+    Mark { at: usize, len: usize, italic: bool },
+    Unmark { index: usize },
+}
+
+fn edit_strategy() -> impl Strategy<Value = Edit> {
+    prop_oneof![
+        (0usize..40, "[a-z]{1,4}").prop_map(|(at, text)| Edit::Insert { at, text }),
+        (0usize..40, 1usize..5).prop_map(|(at, len)| Edit::Delete { at, len }),
+        (0usize..40, 1usize..8, any::<bool>()).prop_map(|(at, len, italic)| Edit::Mark {
+            at,
+            len,
+            italic
+        }),
+        (0usize..8).prop_map(|index| Edit::Unmark { index }),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(proptest_config::cases()))]
+
+    #[test]
+    fn cached_spans_match_a_full_render(
+        edits in prop::collection::vec((edit_strategy(), any::<bool>()), 1..30)
+    ) {
+        let (mut session, block_id) = session_with("the quick brown fox");
+        for (edit, render) in edits {
+            let len = session.document().find_block_by_id(block_id).map_or(0, |block| {
+                md_crdt::doc::block_text_seq(&block.kind).map_or(0, |text| text.len_visible())
+            });
+            match edit {
+                Edit::Insert { at, text } => {
+                    session.insert_text(block_id, at.min(len), &text).unwrap();
+                }
+                Edit::Delete { at, len: count } if at < len => {
+                    session.delete_text(block_id, at, count.min(len - at)).unwrap();
+                }
+                Edit::Mark { at, len: count, italic } if at < len => {
+                    let kind = if italic { MarkKind::Italic } else { MarkKind::Bold };
+                    let range = at..(at + count).min(len);
+                    session.set_mark(block_id, range, kind, BTreeMap::new()).unwrap();
+                }
+                Edit::Unmark { index } => {
+                    let active: Vec<_> = session
+                        .document()
+                        .find_block_by_id(block_id)
+                        .unwrap()
+                        .marks
+                        .iter_active_intervals()
+                        .map(|interval| interval.id)
+                        .collect();
+                    if let Some(id) = active.get(index) {
+                        session.remove_mark(block_id, *id).unwrap();
+                    }
+                }
+                _ => {}
+            }
+            if render {
+                let document = session.document();
+                prop_assert_eq!(
+                    document.block_spans(block_id).unwrap(),
+                    document.render_paragraph_spans(block_id).unwrap()
+                );
+                prop_assert_eq!(document.marks_dirty_range(block_id).unwrap(), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Documents built in code take their ids from a clock and replay from their ops.
+
+use md_crdt::core::{ClockError, OpId, PeerClock};
+use md_crdt::doc::{BlockKind, Document, DocumentBuilder, EquivalenceMode};
+
+#[test]
+fn replaying_the_inserts_builds_an_equal_document() {
+    let clock = PeerClock::new(3);
+    let (document, ops) = DocumentBuilder::new(&clock)
+        .paragraph("intro")
+        .quote(|quote| {
+            quote
+                .paragraph("outer")
+                .quote(|inner| inner.heading(2, "inner"))
+        })
+        .table(&["a", "b"], &[&["1", "2"], &["3"]])
+        .paragraph("")
+        .build()
+        .unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "intro\n\n> outer\n>\n> > ## inner\n\n| a | b |\n| --- | --- |\n| 1 | 2 |\n| 3 |  |"
+    );
+
+    assert_eq!(document.blocks().len_visible(), 4);
+
+    let mut replica = Document::new();
+    for insert in &ops {
+        assert!(insert.apply(&mut replica));
+    }
+    assert_eq!(replica, document);
+
+    // The quote's children name it as parent; everything else is top-level.
+    let quote = document.blocks().iter().nth(1).unwrap();
+    assert!(matches!(quote.kind, BlockKind::BlockQuote { .. }));
+    let parents: Vec<_> = ops.iter().map(|insert| insert.parent).collect();
+    assert_eq!(parents[..3], [None, None, Some(quote.elem_id)]);
+}
+
+#[test]
+fn ids_come_from_the_clock_and_never_repeat() {
+    let clock = PeerClock::starting_at(5, 100);
+    let (_, ops) = DocumentBuilder::new(&clock)
+        .paragraph("abc")
+        .code("", "x")
+        .build()
+        .unwrap();
+    // The paragraph takes its element id and one per grapheme.
+    assert_eq!(
+        ops[0].id,
+        OpId {
+            counter: 100,
+            peer: 5
+        }
+    );
+    assert_eq!(
+        ops[1].id,
+        OpId {
+            counter: 104,
+            peer: 5
+        }
+    );
+    assert_eq!(
+        clock.peek(),
+        OpId {
+            counter: 105,
+            peer: 5
+        }
+    );
+
+    let (later, _) = DocumentBuilder::new(&clock).paragraph("d").build().unwrap();
+    assert_eq!(
+        later.blocks().iter().next().unwrap().elem_id,
+        OpId {
+            counter: 105,
+            peer: 5
+        }
+    );
+}
+
+#[test]
+fn an_exhausted_clock_fails_the_build() {
+    let clock = PeerClock::starting_at(1, u64::MAX - 2);
+    let built = DocumentBuilder::new(&clock)
+        .paragraph("a")
+        .paragraph("too long")
+        .quote(|quote| quote.paragraph("never"))
+        .build();
+    assert!(matches!(
+        built,
+        Err(ClockError::ClockExhausted { peer: 1, .. })
+    ));
+}
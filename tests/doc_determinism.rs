@@ -1,6 +1,6 @@
 use md_crdt::core::mark::MarkSet;
 use md_crdt::core::{OpId, SequenceOp, StateVector};
-use md_crdt::doc::{Block, BlockKind, Document, EquivalenceMode, SerializeConfig};
+use md_crdt::doc::{Block, BlockKind, Document, SerializeConfig};
 use uuid::Uuid;
 
 fn fixed_block(id: Uuid, elem_id: OpId, text: &str) -> Block {
@@ -38,10 +38,7 @@ fn dc1_same_state_same_config_is_identical() {
         },
     );
 
-    let config = SerializeConfig {
-        equivalence: EquivalenceMode::Structural,
-        prefer_raw_source: false,
-    };
+    let config = SerializeConfig::structural();
 
     let first = doc.serialize_with_config(&config);
     let second = doc.serialize_with_config(&config);
@@ -82,10 +79,7 @@ fn dc1_different_op_order_same_state_same_output() {
     doc_b.blocks.apply(op_insert_b);
     doc_b.blocks.apply(op_insert_a);
 
-    let config = SerializeConfig {
-        equivalence: EquivalenceMode::Structural,
-        prefer_raw_source: false,
-    };
+    let config = SerializeConfig::structural();
 
     let output_a = doc_a.serialize_with_config(&config);
     let output_b = doc_b.serialize_with_config(&config);
@@ -96,10 +90,7 @@ fn dc1_different_op_order_same_state_same_output() {
 fn dc1_serialization_stable_across_restarts() {
     let input = "Hello\n\nWorld\n";
     let doc = md_crdt::doc::Parser::parse(input);
-    let config = SerializeConfig {
-        equivalence: EquivalenceMode::Structural,
-        prefer_raw_source: false,
-    };
+    let config = SerializeConfig::structural();
 
     let output = doc.serialize_with_config(&config);
     let doc_reloaded = md_crdt::doc::Parser::parse(&output);
@@ -0,0 +1,171 @@
+//! Typed frontmatter values, their YAML rendering, and add-wins list merging.
+
+use md_crdt::core::{OpId, StateVector};
+use md_crdt::doc::{Frontmatter, FrontmatterValue};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::ValidationLimits;
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn exchange(source: &CollaborativeDocument, target: &mut CollaborativeDocument) {
+    let message = source.encode_changes_since(&target.state_vector()).unwrap();
+    target
+        .apply_remote(message, &ValidationLimits::default())
+        .expect("apply remote changes");
+}
+
+fn list(items: &[&str]) -> FrontmatterValue {
+    FrontmatterValue::List(items.iter().map(|item| item.to_string()).collect())
+}
+
+#[test]
+fn values_parse_by_type() {
+    let frontmatter = Frontmatter::parse(
+        "title: Notes\ncount: 12\nratio: -0.5\ndraft: false\ndue: 2024-03-01\n\
+         tags: [work, \"a, b\"]\nquoted: \"42\""
+            .to_string(),
+    );
+    let value = |key| frontmatter.value(key).unwrap();
+    assert_eq!(value("title"), FrontmatterValue::String("Notes".into()));
+    assert_eq!(value("count"), FrontmatterValue::Number("12".into()));
+    assert_eq!(value("ratio").as_f64(), Some(-0.5));
+    assert_eq!(value("draft"), FrontmatterValue::Bool(false));
+    assert_eq!(value("due"), FrontmatterValue::Date("2024-03-01".into()));
+    assert_eq!(value("tags"), list(&["work", "a, b"]));
+    assert_eq!(value("quoted"), FrontmatterValue::String("42".into()));
+    assert_eq!(
+        FrontmatterValue::parse("2024-13-01"),
+        FrontmatterValue::String("2024-13-01".into())
+    );
+}
+
+#[test]
+fn values_render_as_yaml_that_reads_back_the_same() {
+    let values = [
+        FrontmatterValue::String("plain words".into()),
+        FrontmatterValue::String("true".into()),
+        FrontmatterValue::String("12".into()),
+        FrontmatterValue::String("key: value # not a comment".into()),
+        FrontmatterValue::String("say \"hi\"\nbye".into()),
+        FrontmatterValue::String(String::new()),
+        FrontmatterValue::Number("3.25".into()),
+        FrontmatterValue::Bool(true),
+        FrontmatterValue::Date("2024-02-29".into()),
+        list(&["x", "y, z", "[n]", ""]),
+    ];
+    for value in values {
+        let yaml = value.to_yaml();
+        let expected = match &value {
+            FrontmatterValue::List(items) => FrontmatterValue::List(
+                items
+                    .iter()
+                    .filter(|item| !item.is_empty())
+                    .cloned()
+                    .collect(),
+            ),
+            other => other.clone(),
+        };
+        assert_eq!(FrontmatterValue::parse(&yaml), expected, "{yaml}");
+    }
+    assert_eq!(
+        FrontmatterValue::String("true".into()).to_yaml(),
+        "\"true\""
+    );
+    assert_eq!(list(&["a", "b c"]).to_yaml(), "[a, b c]");
+}
+
+#[test]
+fn new_keys_render_in_key_order_after_the_original_lines() {
+    let mut frontmatter = Frontmatter::parse("title: Notes".to_string());
+    frontmatter
+        .set_value("zeta".into(), Some(FrontmatterValue::Bool(true)), op(1, 1))
+        .unwrap();
+    frontmatter
+        .set_value("alpha".into(), Some(list(&["a", "b"])), op(2, 1))
+        .unwrap();
+    frontmatter
+        .set_value(
+            "title".into(),
+            Some(FrontmatterValue::String("a: b".into())),
+            op(3, 1),
+        )
+        .unwrap();
+    assert_eq!(
+        frontmatter.render(),
+        "title: \"a: b\"\nalpha: [a, b]\nzeta: true"
+    );
+}
+
+#[test]
+fn list_writes_remove_only_the_items_they_observed() {
+    let base = Frontmatter::parse("tags: [a, b]".to_string());
+    let mut observed = StateVector::new();
+    observed.set(1, 1);
+
+    // Peer 1 adds `c`; peer 2, concurrently, removes `a`.
+    let add = (Some("[a, b, c]".to_string()), op(2, 1));
+    let remove = (Some("[b]".to_string()), op(2, 2));
+    let mut first = base.clone();
+    let mut second = base.clone();
+    first
+        .merge("tags".into(), add.0.clone(), add.1, &observed)
+        .unwrap();
+    first
+        .merge("tags".into(), remove.0.clone(), remove.1, &observed)
+        .unwrap();
+    second
+        .merge("tags".into(), remove.0, remove.1, &observed)
+        .unwrap();
+    second
+        .merge("tags".into(), add.0, add.1, &observed)
+        .unwrap();
+    assert_eq!(first.value("tags"), Some(list(&["b", "c"])));
+    assert_eq!(first, second);
+
+    let encoded = serde_json::to_string(&first).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Frontmatter>(&encoded).unwrap(),
+        first
+    );
+}
+
+#[test]
+fn concurrent_tag_additions_both_survive_between_sessions() {
+    let mut first = CollaborativeDocument::new(1);
+    let mut second = CollaborativeDocument::new(2);
+    first
+        .set_frontmatter_value("tags", Some(list(&["base"])))
+        .unwrap();
+    first
+        .set_frontmatter_value("status", Some(FrontmatterValue::String("draft".into())))
+        .unwrap();
+    exchange(&first, &mut second);
+
+    first
+        .set_frontmatter_value("tags", Some(list(&["base", "work"])))
+        .unwrap();
+    second
+        .set_frontmatter_value("tags", Some(list(&["home"])))
+        .unwrap();
+    second
+        .set_frontmatter_value("status", Some(FrontmatterValue::String("done".into())))
+        .unwrap();
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+
+    let tags = first.document().frontmatter_value("tags");
+    assert_eq!(second.document().frontmatter_value("tags"), tags);
+    let Some(FrontmatterValue::List(mut items)) = tags else {
+        panic!("tags should stay a list");
+    };
+    items.sort();
+    assert_eq!(items, ["home", "work"]);
+    for session in [&first, &second] {
+        assert_eq!(
+            session.document().frontmatter_value("status"),
+            Some(FrontmatterValue::String("done".into()))
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use md_crdt::{Block, BlockKind, DocumentDto, EditError, EquivalenceMode, OpId, Parser};
+use md_crdt::{
+    Block, BlockKind, DocumentDto, EditError, EquivalenceMode, OpId, Parser, block_text_seq,
+};
 
 #[test]
 fn exact_serialization_preserves_all_original_bytes() {
@@ -113,7 +115,14 @@ fn opaque_source_block_rejects_structured_text_mutation_without_losing_bytes() {
         )
         .unwrap_err();
 
-    assert_eq!(error, EditError::InvalidOffset);
+    assert_eq!(
+        error,
+        EditError::NotTextBlock {
+            block_id: raw_block
+        }
+    );
+    assert_eq!(error.block_id(), raw_block);
+    assert!(!error.is_retryable());
     assert_eq!(document.serialize(EquivalenceMode::Exact), input);
 }
 
@@ -190,3 +199,45 @@ fn multi_region_edit_leaves_untouched_unicode_region_byte_identical() {
     assert!(output.contains("alpha Xone"), "first edit: {output:?}");
     assert!(output.contains("gamma Ythree"), "third edit: {output:?}");
 }
+
+fn edit_first_block(input: &str, offset: usize, text: &str) -> String {
+    let mut document = Parser::parse(input);
+    let first = document.blocks_in_order()[0].id;
+    document
+        .insert_text(
+            first,
+            offset,
+            text,
+            OpId {
+                peer: 3,
+                counter: 1,
+            },
+        )
+        .unwrap();
+    document.serialize(EquivalenceMode::Exact)
+}
+
+#[test]
+fn edited_block_keeps_its_own_heading_markers_and_line_endings() {
+    let cases = [
+        (
+            "##  Heading  ##\r\n\r\nnext\r\n",
+            "##  Heading!  ##\r\n\r\nnext\r\n",
+        ),
+        ("  # Indented\n", "  # Indented!\n"),
+        ("# C#\n", "# C#!\n"),
+        ("Title\n=====\n\nnext\n", "Title!\n=====\n\nnext\n"),
+        ("Title\r\n---\r\n", "Title!\r\n---\r\n"),
+        (
+            "alpha\r\nbeta\r\n\r\nnext\r\n",
+            "alpha\r\nbeta!\r\n\r\nnext\r\n",
+        ),
+    ];
+    for (input, expected) in cases {
+        let document = Parser::parse(input);
+        let end = block_text_seq(&document.blocks_in_order()[0].kind)
+            .unwrap()
+            .len_visible();
+        assert_eq!(edit_first_block(input, end, "!"), expected, "{input:?}");
+    }
+}
@@ -0,0 +1,125 @@
+use md_crdt::core::mark::MarkKind;
+use md_crdt::core::{OpId, StateVector};
+use md_crdt::doc::{Document, EditError, EditOp, EquivalenceMode, Parser};
+use std::collections::BTreeMap;
+
+fn id(counter: u64) -> OpId {
+    OpId { counter, peer: 9 }
+}
+
+/// Observes everything the parser wrote.
+fn parsed_frontier() -> StateVector {
+    let mut observed = StateVector::new();
+    observed.set(0, u64::MAX);
+    observed
+}
+
+fn parse(source: &str) -> (Document, Vec<md_crdt::doc::BlockId>) {
+    let document = Parser::parse(source);
+    let ids = document
+        .blocks_in_order()
+        .iter()
+        .map(|block| block.id)
+        .collect();
+    (document, ids)
+}
+
+#[test]
+fn a_range_across_blocks_marks_each_covered_block() {
+    let (mut document, blocks) = parse("alpha\n\n```\ncode\n```\n\nbeta\n\ngamma");
+    let ops = document
+        .set_mark_range(
+            (blocks[0], 2),
+            (blocks[3], 3),
+            MarkKind::Bold,
+            BTreeMap::new(),
+            id(100),
+        )
+        .unwrap();
+
+    let marked: Vec<_> = ops
+        .iter()
+        .map(|op| match op {
+            EditOp::SetMark {
+                block_id, op_id, ..
+            } => (*block_id, *op_id),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect();
+    assert_eq!(
+        marked,
+        vec![
+            (blocks[0], id(100)),
+            (blocks[2], id(101)),
+            (blocks[3], id(102))
+        ]
+    );
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "al**pha**\n\n```\ncode\n```\n\n**beta**\n\n**gam**ma"
+    );
+}
+
+#[test]
+fn invalid_endpoints_leave_the_document_untouched() {
+    let (mut document, blocks) = parse("alpha\n\nbeta");
+    let before = document.serialize(EquivalenceMode::Structural);
+    let mark = |document: &mut Document, start, end| {
+        document.set_mark_range(start, end, MarkKind::Italic, BTreeMap::new(), id(1))
+    };
+
+    assert!(matches!(
+        mark(&mut document, (blocks[1], 0), (blocks[0], 2)),
+        Err(EditError::InvertedRange { .. })
+    ));
+    assert!(matches!(
+        mark(&mut document, (blocks[0], 1), (blocks[1], 9)),
+        Err(EditError::InvalidOffset { offset: 9, .. })
+    ));
+    assert!(matches!(
+        mark(&mut document, (blocks[0], 5), (blocks[1], 0)),
+        Err(EditError::InvalidRange { .. })
+    ));
+    assert_eq!(document.serialize(EquivalenceMode::Structural), before);
+}
+
+#[test]
+fn removing_a_range_splits_marks_at_both_ends() {
+    let (mut document, blocks) = parse("**alpha**\n\n**beta**\n\n**gamma**");
+    let ops = document
+        .remove_mark_range(
+            (blocks[0], 3),
+            (blocks[2], 2),
+            &MarkKind::Bold,
+            id(200),
+            parsed_frontier(),
+        )
+        .unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "**alp**ha\n\nbeta\n\nga**mma**"
+    );
+
+    let mut ids: Vec<_> = ops
+        .iter()
+        .map(|op| match op {
+            EditOp::SetMark { op_id, .. } | EditOp::RemoveMark { op_id, .. } => *op_id,
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect();
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), count, "every op gets its own id");
+
+    let again = document
+        .remove_mark_range(
+            (blocks[1], 0),
+            (blocks[1], 4),
+            &MarkKind::Bold,
+            id(300),
+            parsed_frontier(),
+        )
+        .unwrap();
+    assert!(again.is_empty());
+}
@@ -182,6 +182,7 @@ mod commutativity {
             serialize_structural(&doc2),
             "Concurrent inserts after same block should converge"
         );
+        assert!(doc1.structurally_equal(&doc2));
     }
 }
 
@@ -0,0 +1,197 @@
+//! Blocks nested in blockquotes and list items are editable by id or path.
+
+use md_crdt::core::mark::MarkKind;
+use md_crdt::core::{OpId, Sequence, StateVector};
+use md_crdt::doc::{
+    Block, BlockKind, BlockPath, EditError, EquivalenceMode, Parser, block_id_from_op,
+};
+use std::collections::BTreeMap;
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn first_child(block: &Block) -> &Block {
+    match &block.kind {
+        BlockKind::BlockQuote { children } => children.iter().next().unwrap(),
+        _ => panic!("not a container"),
+    }
+}
+
+#[test]
+fn text_inserted_into_a_quoted_paragraph_replicates() {
+    let mut local = Parser::parse("> hello\n");
+    let mut remote = local.clone();
+    let quote = local.blocks().iter().next().unwrap().clone();
+    let paragraph = first_child(&quote).clone();
+
+    let path = local.block_path(paragraph.id).unwrap();
+    assert_eq!(path.elem_ids(), [quote.elem_id, paragraph.elem_id]);
+    assert_eq!(path.parent(), Some(quote.elem_id));
+    assert_eq!(
+        local.block_at(&path).map(|block| block.id),
+        Some(paragraph.id)
+    );
+
+    let ops = local.insert_text(&path, 5, " world", op(100, 9)).unwrap();
+    assert_eq!(
+        local.serialize(EquivalenceMode::Structural),
+        "> hello world"
+    );
+    for edit in ops {
+        assert!(remote.apply_remote_op(edit, true).unwrap());
+    }
+    assert_eq!(remote, local);
+}
+
+#[test]
+fn paths_through_list_items_name_the_item() {
+    let mut document = Parser::parse("- one\n");
+    let list = document.blocks().iter().next().unwrap().clone();
+    let BlockKind::List { items, .. } = &list.kind else {
+        panic!("expected a list");
+    };
+    let item = items.iter().next().unwrap().clone();
+    let after = item.children.iter().next().unwrap().elem_id;
+
+    let quote_id = op(100, 9);
+    let quote = Block::new(
+        BlockKind::BlockQuote {
+            children: Sequence::new(),
+        },
+        quote_id,
+    );
+    assert!(document.insert_block_at(Some(item.elem_id), Some(after), quote_id, quote, None));
+    let quote_path = BlockPath::top_level(list.elem_id)
+        .child(item.elem_id)
+        .child(quote_id);
+    assert_eq!(quote_path.parent(), Some(item.elem_id));
+
+    let paragraph_id = op(101, 9);
+    let paragraph = Block::new(BlockKind::paragraph("two", op(102, 9)), paragraph_id);
+    let paragraph_block = paragraph.id;
+    assert!(document.insert_block_at(quote_path.elem_id(), None, paragraph_id, paragraph, None));
+
+    let path = document.block_path(paragraph_block).unwrap();
+    assert_eq!(path, quote_path.child(paragraph_id));
+    assert_eq!(path.parent(), Some(quote_id));
+
+    document
+        .insert_text(paragraph_block, 3, "!", op(200, 9))
+        .unwrap();
+    assert!(matches!(
+        document.insert_text(&path, 9, "?", op(210, 9)),
+        Err(EditError::InvalidOffset { .. })
+    ));
+    document
+        .insert_text(&path, 0, "still ", op(220, 9))
+        .unwrap();
+    assert!(
+        document
+            .serialize(EquivalenceMode::Structural)
+            .ends_with("> still two!")
+    );
+}
+
+#[test]
+fn nested_marks_are_removed_by_path() {
+    let mut document = Parser::parse("> bold text\n");
+    let quote = document.blocks().iter().next().unwrap().clone();
+    let paragraph = first_child(&quote).clone();
+    let path = document.block_path(paragraph.id).unwrap();
+
+    let (start, end) = document
+        .grapheme_range_to_anchors(paragraph.id, 0..4)
+        .unwrap();
+    let interval = op(100, 9);
+    document
+        .set_mark(
+            paragraph.id,
+            interval,
+            MarkKind::Bold,
+            start,
+            end,
+            BTreeMap::new(),
+            interval,
+        )
+        .unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "> **bold** text"
+    );
+
+    let mut observed = StateVector::new();
+    observed.set(9, 100);
+    document
+        .remove_mark(&path, interval, op(101, 9), observed, start, end)
+        .unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "> bold text"
+    );
+}
+
+#[test]
+fn a_path_that_leads_nowhere_is_not_found() {
+    let mut document = Parser::parse("> quoted\n");
+    let quote = document.blocks().iter().next().unwrap().clone();
+    let missing = BlockPath::top_level(quote.elem_id).child(op(50, 3));
+    assert!(document.block_at(&missing).is_none());
+    assert_eq!(
+        document.insert_text(&missing, 0, "x", op(100, 9)),
+        Err(EditError::BlockNotFound {
+            block_id: block_id_from_op(op(50, 3))
+        })
+    );
+
+    // A quote is a container, not a step past a leaf block.
+    let paragraph = first_child(&quote).clone();
+    let through_leaf = BlockPath::from(vec![quote.elem_id, paragraph.elem_id, op(50, 3)]);
+    assert!(document.block_at(&through_leaf).is_none());
+}
+
+#[test]
+fn edits_to_sibling_children_commute_and_keep_the_tree_indexed() {
+    let mut origin = Parser::parse("> one\n>\n> two\n");
+    let quote = origin.blocks().iter().next().unwrap().clone();
+    let BlockKind::BlockQuote { children } = &quote.kind else {
+        panic!("expected a blockquote");
+    };
+    let ids: Vec<_> = children.iter().map(|child| child.id).collect();
+    let mut left = origin.clone();
+    let mut right = origin.clone();
+
+    let first = origin.insert_text(ids[0], 3, "!", op(100, 9)).unwrap();
+    let second = origin.insert_text(ids[1], 0, "and ", op(200, 8)).unwrap();
+    for edit in first.iter().chain(&second) {
+        left.apply_remote_op(edit.clone(), true).unwrap();
+    }
+    for edit in second.iter().chain(&first) {
+        right.apply_remote_op(edit.clone(), true).unwrap();
+    }
+    assert_eq!(left, origin);
+    assert_eq!(right, origin);
+    assert_eq!(
+        origin.serialize(EquivalenceMode::Structural),
+        "> one!\n>\n> and two"
+    );
+
+    // Structural edits still find the quote after text edits inside it.
+    let inserted = op(300, 9);
+    let block = Block::new(BlockKind::paragraph("three", op(301, 9)), inserted);
+    assert!(origin.insert_block_at(Some(quote.elem_id), None, inserted, block, None));
+    assert_eq!(
+        origin
+            .container_children(Some(quote.elem_id))
+            .unwrap()
+            .len_visible(),
+        3
+    );
+    assert_eq!(
+        origin
+            .block_path(block_id_from_op(inserted))
+            .unwrap()
+            .parent(),
+        Some(quote.elem_id)
+    );
+}
@@ -0,0 +1,85 @@
+use md_crdt::doc::{EquivalenceMode, ParseError, ParseLimit, Parser, ParserLimits, ParserOptions};
+
+fn parse_limited(text: &str, limits: &ParserLimits) -> Result<String, ParseError> {
+    Parser::parse_with_limits(text, &ParserOptions::default(), limits)
+        .map(|doc| doc.serialize(EquivalenceMode::Structural))
+}
+
+#[test]
+fn deeply_nested_input_is_rejected_without_recursing() {
+    let limits = ParserLimits::default();
+    let quotes = format!("{}deep\n", "> ".repeat(100_000));
+    assert_eq!(
+        parse_limited(&quotes, &limits),
+        Err(ParseError::LimitExceeded {
+            limit: ParseLimit::Nesting,
+            max: limits.max_nesting,
+        })
+    );
+
+    let lists: String = (0..2_000)
+        .map(|depth| format!("{}- item\n", " ".repeat(depth * 2)))
+        .collect();
+    assert!(matches!(
+        parse_limited(&lists, &limits),
+        Err(ParseError::LimitExceeded {
+            limit: ParseLimit::Nesting,
+            ..
+        })
+    ));
+
+    let shallow = format!("{}deep\n", "> ".repeat(limits.max_nesting));
+    assert!(parse_limited(&shallow, &limits).is_ok());
+}
+
+#[test]
+fn size_limits_bound_blocks_lines_and_input() {
+    let limits = ParserLimits {
+        max_block_count: 3,
+        max_line_len: 16,
+        max_input_bytes: 64,
+        ..ParserLimits::default()
+    };
+    let exceeded = |limit| match limit {
+        ParseLimit::BlockCount => Err(ParseError::LimitExceeded { limit, max: 3 }),
+        ParseLimit::LineLength => Err(ParseError::LimitExceeded { limit, max: 16 }),
+        ParseLimit::InputBytes => Err(ParseError::LimitExceeded { limit, max: 64 }),
+        ParseLimit::Nesting => unreachable!(),
+    };
+
+    assert!(parse_limited("one\n\ntwo\n\nthree\n", &limits).is_ok());
+    assert_eq!(
+        parse_limited("one\n\ntwo\n\nthree\n\nfour\n", &limits),
+        exceeded(ParseLimit::BlockCount)
+    );
+    // A list, its item, and the item's paragraph each count.
+    assert_eq!(
+        parse_limited("- a\n- b\n", &limits),
+        exceeded(ParseLimit::BlockCount)
+    );
+    assert_eq!(
+        parse_limited("a line well past sixteen bytes\n", &limits),
+        exceeded(ParseLimit::LineLength)
+    );
+    assert_eq!(
+        parse_limited(&"short\n".repeat(11), &limits),
+        exceeded(ParseLimit::InputBytes)
+    );
+}
+
+#[test]
+fn parsing_within_limits_matches_an_unlimited_parse() {
+    let input = "# Title\n\n> quote\n> > nested\n\n- a\n  - b\n    - c\n- d\n\n```\ncode\n```\n";
+    assert_eq!(
+        parse_limited(input, &ParserLimits::default()).unwrap(),
+        Parser::parse(input).serialize(EquivalenceMode::Structural)
+    );
+    assert_eq!(
+        ParseError::LimitExceeded {
+            limit: ParseLimit::Nesting,
+            max: 2,
+        }
+        .to_string(),
+        "nesting depth exceeds the parser limit of 2"
+    );
+}
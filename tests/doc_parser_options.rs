@@ -0,0 +1,108 @@
+use md_crdt::core::mark::MarkKind;
+use md_crdt::{
+    BlockKind, Document, EquivalenceMode, FenceMarker, Parser, ParserOptions, block_text_seq,
+};
+
+fn kinds(document: &Document) -> Vec<BlockKind> {
+    document
+        .blocks_in_order()
+        .into_iter()
+        .map(|block| block.kind.clone())
+        .collect()
+}
+
+fn text(kind: &BlockKind) -> String {
+    block_text_seq(kind)
+        .expect("text block")
+        .iter()
+        .map(|unit| unit.grapheme.as_str())
+        .collect()
+}
+
+#[test]
+fn gfm_strikethrough_is_a_mark_and_round_trips() {
+    let input = "keep ~~old **bold**~~ new\n";
+    let document = Parser::parse_with_options(input, &ParserOptions::gfm());
+    let block = &document.blocks_in_order()[0];
+    assert_eq!(text(&block.kind), "keep old bold new");
+    let marks: Vec<MarkKind> = block
+        .marks
+        .active_intervals()
+        .into_iter()
+        .map(|interval| interval.kind.clone())
+        .collect();
+    assert!(marks.contains(&MarkKind::Strikethrough));
+    assert!(marks.contains(&MarkKind::Bold));
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        input.trim_end()
+    );
+}
+
+#[test]
+fn default_options_keep_tildes_literal() {
+    let document = Parser::parse("a ~~b~~ c\n");
+    let block = &document.blocks_in_order()[0];
+    assert_eq!(text(&block.kind), "a ~~b~~ c");
+    assert!(block.marks.active_intervals().is_empty());
+}
+
+#[test]
+fn commonmark_leaves_task_markers_and_tables_as_text() {
+    let input = "- [ ] write\n\n| a | b |\n| --- | --- |\n| 1 | 2 |\n";
+    let document = Parser::parse_with_options(input, &ParserOptions::commonmark());
+    let kinds = kinds(&document);
+    let BlockKind::List { items, .. } = &kinds[0] else {
+        panic!("expected list, got {:?}", kinds[0]);
+    };
+    let item = items.iter_asc().next().expect("list item");
+    assert_eq!(item.task, None);
+    let paragraph = item.children.iter_asc().next().expect("item paragraph");
+    assert_eq!(text(&paragraph.kind), "[ ] write");
+    assert!(matches!(kinds[1], BlockKind::Paragraph { .. }));
+    assert_eq!(document.serialize(EquivalenceMode::Exact), input);
+}
+
+#[test]
+fn math_blocks_model_display_math_as_dollar_fence() {
+    let input = "before\n\n$$\nE = mc^2\n$$\n\nafter\n";
+    let document = Parser::parse_with_options(input, &ParserOptions::pandoc());
+    let kinds = kinds(&document);
+    let BlockKind::CodeFence { style, info, text } = &kinds[1] else {
+        panic!("expected math block, got {:?}", kinds[1]);
+    };
+    assert_eq!(style.marker, FenceMarker::Dollar);
+    assert_eq!(info, &None);
+    assert_eq!(text, "E = mc^2");
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        input.trim_end()
+    );
+
+    let plain = Parser::parse(input);
+    assert!(
+        plain
+            .blocks_in_order()
+            .into_iter()
+            .all(|block| !matches!(block.kind, BlockKind::CodeFence { .. }))
+    );
+}
+
+#[test]
+fn footnote_definitions_are_preserved_and_references_stay_literal() {
+    let input = "See [^1](not a link).\n\n[^1]: The note\n    continues here.\n\n    Second paragraph.\n\nAfter.\n";
+    let document = Parser::parse_with_options(input, &ParserOptions::pandoc());
+    let kinds = kinds(&document);
+    assert_eq!(text(&kinds[0]), "See [^1](not a link).");
+    assert_eq!(
+        kinds[1],
+        BlockKind::RawBlock {
+            raw: "[^1]: The note\n    continues here.\n\n    Second paragraph.".into()
+        }
+    );
+    assert_eq!(text(&kinds[2]), "After.");
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        input.trim_end()
+    );
+}
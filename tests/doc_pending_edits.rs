@@ -0,0 +1,61 @@
+//! Remote edits that overtake the insert of their block are parked and replayed.
+
+use md_crdt::core::OpId;
+use md_crdt::doc::{Block, BlockKind, Document, EditError, EditOp, EquivalenceMode};
+
+fn op(counter: u64, peer: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn edits_to_an_unknown_block_replay_when_it_arrives() {
+    let insert_id = op(1, 7);
+    let block = Block::new(BlockKind::paragraph("ac", op(2, 7)), insert_id);
+    let block_id = block.id;
+
+    let mut origin = Document::new();
+    assert!(origin.insert_block_at(None, None, insert_id, block.clone(), None));
+    let first = origin.insert_text(block_id, 1, "b", op(10, 7)).unwrap();
+    let second = origin.insert_text(block_id, 3, "d", op(11, 7)).unwrap();
+
+    let mut replica = Document::new();
+    for edit in first.into_iter().chain(second) {
+        assert!(!replica.apply_remote_op(edit, true).unwrap());
+    }
+    assert_eq!(replica.pending_edit_count(), 2);
+    assert_eq!(
+        replica.pending_edit_blocks().collect::<Vec<_>>(),
+        [block_id]
+    );
+
+    assert!(replica.insert_block_at(None, None, insert_id, block, None));
+    assert_eq!(replica.pending_edit_count(), 0);
+    assert_eq!(replica.serialize(EquivalenceMode::Structural), "abcd");
+    assert_eq!(replica, origin);
+}
+
+#[test]
+fn edits_to_present_blocks_apply_at_once_and_local_edits_still_fail() {
+    let insert_id = op(1, 7);
+    let block = Block::new(BlockKind::paragraph("x", op(2, 7)), insert_id);
+    let block_id = block.id;
+    let mut doc = Document::new();
+    doc.insert_block_at(None, None, insert_id, block.clone(), None);
+    let mut source = doc.clone();
+    let edit = source.insert_text(block_id, 1, "y", op(10, 7)).unwrap();
+    assert!(doc.apply_remote_op(edit[0].clone(), true).unwrap());
+    assert_eq!(doc.pending_edit_count(), 0);
+
+    let missing = Block::new(BlockKind::paragraph("", op(21, 7)), op(20, 7)).id;
+    assert_eq!(
+        doc.insert_text(missing, 0, "z", op(30, 7)),
+        Err(EditError::BlockNotFound { block_id: missing })
+    );
+    let mut parked = edit[0].clone();
+    if let EditOp::InsertText(run) = &mut parked {
+        run.block_id = missing;
+    }
+    assert!(!doc.apply_remote_op(parked, true).unwrap());
+    assert_eq!(doc.discard_pending_edits(missing), 1);
+    assert_eq!(doc.pending_edit_count(), 0);
+}
@@ -0,0 +1,43 @@
+use md_crdt::core::mark::{MarkKind, MarkValue};
+use md_crdt::doc::{EditError, Parser};
+
+#[test]
+fn runs_carry_text_ranges_marks_and_link_targets() {
+    let document = Parser::parse("plain **bold [link](https://example.com)** tail");
+    let block_id = document.blocks_in_order()[0].id;
+    let runs = document.rendered_block(block_id).unwrap();
+
+    let summary: Vec<_> = runs
+        .iter()
+        .map(|run| (run.text.as_str(), run.range.clone(), run.marks.clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("plain ", 0..6, vec![]),
+            ("bold ", 6..11, vec![MarkKind::Bold]),
+            ("link", 11..15, vec![MarkKind::Bold, MarkKind::Link]),
+            (" tail", 15..20, vec![]),
+        ]
+    );
+    assert_eq!(
+        runs[2].attrs[&MarkKind::Link].get("href"),
+        Some(&MarkValue::String("https://example.com".into()))
+    );
+    assert!(runs[0].attrs.is_empty());
+}
+
+#[test]
+fn graphemes_stay_whole_and_non_text_blocks_are_rejected() {
+    let document = Parser::parse("*e\u{301}*x\n\n```\ncode\n```");
+    let blocks = document.blocks_in_order();
+    let runs = document.rendered_block(blocks[0].id).unwrap();
+    assert_eq!(runs[0].text, "e\u{301}");
+    assert_eq!(runs[0].range, 0..1);
+    assert_eq!(runs[1].range, 1..2);
+
+    assert!(matches!(
+        document.rendered_block(blocks[1].id),
+        Err(EditError::NotTextBlock { .. })
+    ));
+}
@@ -8,9 +8,9 @@ use md_crdt::doc::{EquivalenceMode, Parser, SerializeConfig};
 
 /// Helper to assert round-trip idempotency
 fn assert_round_trip(input: &str, mode: EquivalenceMode) {
-    let config = SerializeConfig {
-        equivalence: mode,
-        prefer_raw_source: mode == EquivalenceMode::Exact,
+    let config = match mode {
+        EquivalenceMode::Exact => SerializeConfig::exact(),
+        EquivalenceMode::Structural => SerializeConfig::structural(),
     };
 
     let doc1 = Parser::parse(input);
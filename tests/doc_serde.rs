@@ -0,0 +1,143 @@
+use md_crdt::core::{Map, Sequence, SequenceOp};
+use md_crdt::{Block, BlockKind, Document, EquivalenceMode, OpId, Parser, SNAPSHOT_FORMAT_VERSION};
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+#[test]
+fn document_json_round_trip_restores_blocks_tables_and_source() {
+    let input = "---\ntitle: Notes\n---\n\n#  Plan\n\n| a | b |\n| --- | :---: |\n| 1 | 2 |\n\n- [ ] write\n- [x] ship\n";
+    let mut document = Parser::parse(input);
+    let heading = document.blocks_in_order()[0].id;
+    document.insert_text(heading, 2, " B", op_id(3, 1)).unwrap();
+
+    let json = serde_json::to_value(&document).unwrap();
+    assert_eq!(json["format_version"], SNAPSHOT_FORMAT_VERSION);
+    let restored: Document = serde_json::from_value(json).unwrap();
+
+    assert_eq!(restored, document);
+    assert_eq!(
+        restored.serialize(EquivalenceMode::Exact),
+        document.serialize(EquivalenceMode::Exact)
+    );
+
+    let table = document
+        .blocks_in_order()
+        .into_iter()
+        .find(|block| matches!(block.kind, BlockKind::Table { .. }))
+        .expect("table block");
+    let restored_block: Block =
+        serde_json::from_str(&serde_json::to_string(table).unwrap()).unwrap();
+    assert_eq!(&restored_block, table);
+}
+
+#[test]
+fn document_json_from_another_schema_version_is_rejected() {
+    let mut json = serde_json::to_value(Parser::parse("alpha\n")).unwrap();
+    json["format_version"] = (SNAPSHOT_FORMAT_VERSION + 1).into();
+    let error = serde_json::from_value::<Document>(json).unwrap_err();
+    assert!(error.to_string().contains("reinitialize and re-ingest"));
+}
+
+#[test]
+fn sequence_json_keeps_tombstones_and_pending_ops() {
+    let mut sequence = Sequence::new();
+    sequence.apply(SequenceOp::Insert {
+        after: None,
+        id: op_id(1, 1),
+        value: 'a',
+        right_origin: None,
+    });
+    sequence.apply(SequenceOp::Insert {
+        after: Some(op_id(1, 1)),
+        id: op_id(1, 2),
+        value: 'b',
+        right_origin: None,
+    });
+    sequence.apply(SequenceOp::Delete {
+        target: op_id(1, 1),
+        id: op_id(1, 3),
+    });
+    // Anchored on an element that has not arrived yet.
+    let late = SequenceOp::Insert {
+        after: Some(op_id(2, 1)),
+        id: op_id(2, 2),
+        value: 'd',
+        right_origin: None,
+    };
+    sequence.apply(late);
+
+    let json = serde_json::to_string(&sequence).unwrap();
+    let mut restored: Sequence<char> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, sequence);
+
+    for replica in [&mut sequence, &mut restored] {
+        replica.apply(SequenceOp::Insert {
+            after: Some(op_id(1, 2)),
+            id: op_id(2, 1),
+            value: 'c',
+            right_origin: None,
+        });
+    }
+    assert_eq!(restored, sequence);
+    assert_eq!(restored.iter().collect::<String>(), "bcd");
+}
+
+#[test]
+fn map_json_keeps_non_string_keys_and_removes() {
+    let mut map = Map::new();
+    map.set(1u64, "one".to_string(), op_id(1, 1));
+    map.set(2u64, "two".to_string(), op_id(1, 2));
+    map.remove(&1, op_id(1, 3));
+
+    let restored: Map<u64, String> =
+        serde_json::from_str(&serde_json::to_string(&map).unwrap()).unwrap();
+    assert_eq!(restored, map);
+    assert_eq!(restored.get(&1), None);
+    assert_eq!(restored.get(&2), Some(&"two".to_string()));
+}
+
+#[test]
+fn debug_json_spells_out_ids_and_keeps_parked_edits() {
+    let insert_id = op_id(7, 1);
+    let block = Block::new(BlockKind::paragraph("ac", op_id(7, 2)), insert_id);
+    let block_id = block.id;
+    let mut origin = Document::new();
+    origin.insert_block_at(None, None, insert_id, block.clone(), None);
+    let edit = origin.insert_text(block_id, 1, "b", op_id(7, 10)).unwrap();
+
+    let mut document = Parser::parse("# Title\n\nbody\n");
+    let heading = document.blocks_in_order()[0].id;
+    document.insert_text(heading, 2, "!", op_id(3, 1)).unwrap();
+    for parked in edit {
+        assert!(!document.apply_remote_op(parked, true).unwrap());
+    }
+
+    let json = document.to_debug_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["format_version"], SNAPSHOT_FORMAT_VERSION);
+    assert_eq!(value["state_vector"]["peers"]["3"], 1);
+    let first = &value["document"]["blocks"]["elements"][0];
+    assert!(first["id"]["counter"].is_u64() && first["id"]["peer"].is_u64());
+    assert_eq!(value["pending_edits"].as_array().unwrap().len(), 1);
+
+    let mut restored = Document::from_debug_json(&json).unwrap();
+    assert_eq!(restored, document);
+    assert_eq!(restored.pending_edit_count(), 1);
+    assert_eq!(restored.to_debug_json().unwrap(), json);
+
+    // The parked edit still lands once its block arrives.
+    restored.insert_block_at(None, None, insert_id, block, None);
+    assert_eq!(restored.pending_edit_count(), 0);
+    assert!(restored.serialize(EquivalenceMode::Exact).contains("abc"));
+}
+
+#[test]
+fn debug_json_from_another_schema_version_is_rejected() {
+    let json = Parser::parse("alpha\n").to_debug_json().unwrap();
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["format_version"] = (SNAPSHOT_FORMAT_VERSION + 1).into();
+    let error = Document::from_debug_json(&value.to_string()).unwrap_err();
+    assert!(error.to_string().contains("reinitialize and re-ingest"));
+}
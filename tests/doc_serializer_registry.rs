@@ -0,0 +1,80 @@
+use md_crdt::{
+    Document, EquivalenceMode, MarkKind, OpId, Parser, SerializeConfig, SerializerRegistry,
+};
+use std::collections::BTreeMap;
+
+fn comment() -> MarkKind {
+    MarkKind::custom("app", "comment").unwrap()
+}
+
+fn registry() -> SerializerRegistry {
+    SerializerRegistry::new()
+        .raw_block("callout", |raw| {
+            let body = raw.strip_prefix(":::note\n")?.strip_suffix("\n:::")?;
+            Some(format!("> [!NOTE]\n> {body}"))
+        })
+        .mark(comment(), |interval| {
+            let note = interval.string_attr("note").unwrap_or_default();
+            (format!("{{>>{note}<<}}{{=="), "==}".to_string())
+        })
+}
+
+fn commented(markdown: &str, index: usize, range: std::ops::Range<usize>) -> Document {
+    let mut document = Parser::parse(markdown);
+    let block = document.blocks_in_order()[index].id;
+    let (start, end) = document.grapheme_range_to_anchors(block, range).unwrap();
+    let id = OpId {
+        counter: 100,
+        peer: 2,
+    };
+    let attrs = BTreeMap::from([(
+        "note".to_string(),
+        md_crdt::MarkValue::String("check".into()),
+    )]);
+    document
+        .set_mark(block, id, comment(), start, end, attrs, id)
+        .unwrap();
+    document
+}
+
+#[test]
+fn registered_renderers_control_raw_blocks_and_marks() {
+    let document = commented(
+        "see this claim\n\n:::note\nBe careful\n:::\n\n:::tip\nkept\n:::",
+        0,
+        4..8,
+    );
+
+    assert_eq!(
+        document.serialize_with(&SerializeConfig::structural(), &registry()),
+        "see {>>check<<}{==this==} claim\n\n> [!NOTE]\n> Be careful\n\n:::tip\nkept\n:::"
+    );
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "see this claim\n\n:::note\nBe careful\n:::\n\n:::tip\nkept\n:::"
+    );
+}
+
+#[test]
+fn exact_mode_renders_only_edited_blocks_through_the_registry() {
+    let document = commented(":::note\nBe careful\n:::\n\nsee this claim\n", 1, 0..3);
+
+    assert_eq!(
+        document.serialize_with(&SerializeConfig::exact(), &registry()),
+        ":::note\nBe careful\n:::\n\n{>>check<<}{==see==} this claim\n"
+    );
+}
+
+#[test]
+fn later_registrations_replace_earlier_ones() {
+    let registry = SerializerRegistry::new()
+        .raw_block("callout", |_| Some("first".into()))
+        .raw_block("callout", |_| Some("second".into()));
+    assert!(!registry.is_empty());
+    assert!(SerializerRegistry::new().is_empty());
+    let document = Parser::parse(":::note\nx\n:::\n");
+    assert_eq!(
+        document.serialize_with(&SerializeConfig::structural(), &registry),
+        "second"
+    );
+}
@@ -0,0 +1,80 @@
+use md_crdt::core::{OpId, Sequence, SequenceOp};
+use md_crdt::{CollaborativeDocument, MarkKind, Parser, block_id_from_op};
+use std::collections::BTreeMap;
+
+#[test]
+fn stats_count_blocks_marks_and_nesting() {
+    let doc = Parser::parse(
+        "# Title\n\nsome **bold** and *em*\n\n- one\n  - nested\n\n> quoted\n\n```\ncode\n```\n",
+    );
+    let stats = doc.stats();
+    assert_eq!(stats.blocks.headings, 1);
+    assert_eq!(stats.blocks.paragraphs, 4);
+    assert_eq!(stats.blocks.lists, 2);
+    assert_eq!(stats.blocks.list_items, 2);
+    assert_eq!(stats.blocks.block_quotes, 1);
+    assert_eq!(stats.blocks.code_fences, 1);
+    assert_eq!(stats.blocks.total(), 9);
+    assert_eq!(stats.max_depth, 3);
+    assert_eq!(stats.active_marks, 2);
+    assert_eq!(stats.removed_marks, 0);
+    assert_eq!(stats.elements.tombstones, 0);
+    assert_eq!(stats.pending_backlog(), 0);
+    assert!(stats.estimated_bytes > 0);
+}
+
+#[test]
+fn stats_track_tombstones_and_removed_marks() {
+    let mut doc = CollaborativeDocument::new(1);
+    let elem = doc.insert_paragraph(None, "hello world").unwrap();
+    let block_id = block_id_from_op(elem);
+    let before = doc.document().stats();
+    assert_eq!(before.tombstone_ratio(), 0.0);
+
+    let interval_id = doc
+        .set_mark(block_id, 0..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    doc.remove_mark(block_id, interval_id).unwrap();
+    doc.delete_text(block_id, 5, 6).unwrap();
+
+    let after = doc.document().stats();
+    assert_eq!(after.active_marks, 0);
+    assert_eq!(after.removed_marks, 1);
+    assert_eq!(after.elements.tombstones, 6);
+    assert_eq!(after.elements.visible, before.elements.visible - 6);
+    assert!(after.tombstone_ratio() > 0.0);
+}
+
+#[test]
+fn sequence_stats_count_pending_operations() {
+    let mut seq = Sequence::new();
+    seq.apply(SequenceOp::Insert {
+        after: Some(OpId {
+            counter: 1,
+            peer: 2,
+        }),
+        id: OpId {
+            counter: 2,
+            peer: 2,
+        },
+        value: 'b',
+        right_origin: None,
+    });
+    let stats = seq.stats();
+    assert_eq!(stats.pending_ops, 1);
+    assert_eq!(stats.elements(), 0);
+    assert_eq!(stats.tombstone_ratio(), 0.0);
+
+    seq.apply(SequenceOp::Insert {
+        after: None,
+        id: OpId {
+            counter: 1,
+            peer: 2,
+        },
+        value: 'a',
+        right_origin: None,
+    });
+    let stats = seq.stats();
+    assert_eq!(stats.pending_ops, 0);
+    assert_eq!(stats.visible, 2);
+}
@@ -0,0 +1,87 @@
+use md_crdt::core::{OpId, SequenceOp};
+use md_crdt::doc::{Block, BlockKind, Document, EquivalenceMode, Parser};
+use md_crdt::{CollaborativeDocument, MarkKind};
+use std::collections::BTreeMap;
+
+fn single_block(kind: BlockKind) -> Document {
+    let id = OpId {
+        counter: 1,
+        peer: 1,
+    };
+    let mut doc = Document::new();
+    doc.blocks.apply(SequenceOp::Insert {
+        after: None,
+        id,
+        value: Block::new(kind, id),
+        right_origin: None,
+    });
+    doc
+}
+
+#[test]
+fn a_raw_block_is_not_a_paragraph_with_the_same_text() {
+    let id = OpId {
+        counter: 1,
+        peer: 1,
+    };
+    let paragraph = single_block(BlockKind::paragraph("plain words", id));
+    let raw = single_block(BlockKind::RawBlock {
+        raw: "plain words".into(),
+    });
+    assert_eq!(
+        paragraph.serialize(EquivalenceMode::Structural),
+        raw.serialize(EquivalenceMode::Structural)
+    );
+    assert!(!paragraph.structurally_equal(&raw));
+    assert_ne!(paragraph.structural_digest(), raw.structural_digest());
+}
+
+#[test]
+fn presentation_and_replica_identity_do_not_count() {
+    let pairs = [
+        ("- a\n- b\n", "* a\n* b\n"),
+        ("**bold** and *it*  \n", "**bold** and *it*\n"),
+        (
+            "```rust\nlet x = 1;   \n```\n",
+            "~~~~ rust\nlet x = 1;\n~~~~\n",
+        ),
+        ("> quoted  \n", "> quoted\n"),
+    ];
+    for (left, right) in pairs {
+        let (a, b) = (Parser::parse(left), Parser::parse(right));
+        assert!(a.structurally_equal(&b), "{left:?} vs {right:?}");
+        assert_eq!(a.structural_digest(), b.structural_digest());
+    }
+
+    let different = [
+        ("1. a\n", "3. a\n"),
+        ("- a\n", "1. a\n"),
+        ("- [ ] a\n", "- [x] a\n"),
+        ("**a** b\n", "**a b**\n"),
+        ("[a](x)\n", "[a](y)\n"),
+        ("# a\n", "## a\n"),
+        ("> a\n", "a\n"),
+    ];
+    for (left, right) in different {
+        let (a, b) = (Parser::parse(left), Parser::parse(right));
+        assert!(!a.structurally_equal(&b), "{left:?} vs {right:?}");
+        assert_ne!(a.structural_digest(), b.structural_digest());
+    }
+
+    // Same content typed on two replicas: every id differs, the structure does not,
+    // and the parsed copy's `**` delimiter attribute is presentation only.
+    let mut first = CollaborativeDocument::new(1);
+    let mut second = CollaborativeDocument::new(2);
+    for doc in [&mut first, &mut second] {
+        let elem = doc.insert_paragraph(None, "see docs  ").unwrap();
+        let block_id = md_crdt::block_id_from_op(elem);
+        doc.set_mark(block_id, 4..8, MarkKind::Bold, BTreeMap::new())
+            .unwrap();
+    }
+    assert!(first.document().structurally_equal(second.document()));
+    assert!(
+        first
+            .document()
+            .structurally_equal(&Parser::parse("see **docs**\n"))
+    );
+}
@@ -123,3 +123,58 @@ fn nested_list_as_first_item_child_preserves_the_outer_item() {
     assert_eq!(children.len(), 1);
     assert!(matches!(children[0].kind, BlockKind::List { .. }));
 }
+
+fn only_code_fence(input: &str) -> (md_crdt::CodeFenceStyle, Option<String>, String) {
+    let doc = Parser::parse(input);
+    let blocks = doc.blocks_in_order();
+    assert_eq!(
+        blocks.len(),
+        1,
+        "expected a single fenced block for {input:?}"
+    );
+    let BlockKind::CodeFence { style, info, text } = &blocks[0].kind else {
+        panic!("expected code fence for {input:?}")
+    };
+    (*style, info.clone(), text.clone())
+}
+
+#[test]
+fn tilde_and_long_fences_only_close_on_a_matching_run() {
+    let (style, info, text) = only_code_fence("~~~~ md\n~~~\n```\nbody\n~~~~~");
+    assert_eq!(style.marker, md_crdt::FenceMarker::Tilde);
+    assert_eq!(style.length, 4);
+    assert_eq!(info.as_deref(), Some("md"));
+    assert_eq!(text, "~~~\n```\nbody");
+
+    let (style, _, text) = only_code_fence("````\n```rust\nfn main() {}\n```\n````");
+    assert_eq!(style.length, 4);
+    assert_eq!(text, "```rust\nfn main() {}\n```");
+}
+
+#[test]
+fn indented_fences_strip_opener_indentation_and_accept_indented_closers() {
+    let (_, info, text) = only_code_fence("  ```js\n  let a;\n    let b;\n let c;\n   ```");
+    assert_eq!(info.as_deref(), Some("js"));
+    assert_eq!(text, "let a;\n  let b;\nlet c;");
+
+    // A closer indented four columns is content, not a fence.
+    let (_, _, text) = only_code_fence("```\ninside\n    ```\n```");
+    assert_eq!(text, "inside\n    ```");
+    assert_structural_idempotence("```\ninside\n    ```\n```");
+}
+
+#[test]
+fn serializer_lengthens_fences_around_inner_fence_runs() {
+    let mut doc = Parser::parse("```\nplaceholder\n```");
+    let elem_id = doc.blocks_in_order()[0].elem_id;
+    doc.with_block_mut(elem_id, |block| {
+        let BlockKind::CodeFence { text, .. } = &mut block.kind else {
+            panic!("expected code fence")
+        };
+        *text = "```\ninner\n```".into();
+    });
+    let rendered = doc.serialize(EquivalenceMode::Structural);
+    assert_eq!(rendered, "````\n```\ninner\n```\n````");
+    let (_, _, text) = only_code_fence(&rendered);
+    assert_eq!(text, "```\ninner\n```");
+}
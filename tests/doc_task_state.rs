@@ -0,0 +1,129 @@
+use md_crdt::{
+    BlockKind, Document, EditError, EquivalenceMode, OpId, Parser, StateVector, TaskState,
+};
+
+fn op_id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn item_ids(document: &Document) -> Vec<md_crdt::BlockId> {
+    let BlockKind::List { items, .. } = &document.blocks_in_order()[0].kind else {
+        panic!("expected a list");
+    };
+    items.iter_asc().map(|item| item.id).collect()
+}
+
+fn tasks(document: &Document) -> Vec<Option<TaskState>> {
+    item_ids(document)
+        .into_iter()
+        .map(|id| document.find_list_item_by_id(id).unwrap().task)
+        .collect()
+}
+
+#[test]
+fn concurrent_toggles_of_different_tasks_both_apply() {
+    let base = Parser::parse("- [ ] milk\n- [ ] eggs\n");
+    let [milk, eggs] = item_ids(&base)[..] else {
+        panic!("expected two items");
+    };
+
+    let mut left = base.clone();
+    let mut right = base;
+    left.set_task_state(milk, true, op_id(1, 10), &StateVector::new())
+        .unwrap();
+    right
+        .set_task_state(eggs, true, op_id(2, 10), &StateVector::new())
+        .unwrap();
+    left.set_task_state(eggs, true, op_id(2, 10), &StateVector::new())
+        .unwrap();
+    right
+        .set_task_state(milk, true, op_id(1, 10), &StateVector::new())
+        .unwrap();
+
+    assert_eq!(tasks(&left), vec![Some(TaskState::Checked); 2]);
+    assert_eq!(tasks(&left), tasks(&right));
+    assert_eq!(
+        left.serialize(EquivalenceMode::Exact),
+        "- [x] milk\n- [x] eggs\n"
+    );
+}
+
+#[test]
+fn concurrent_toggles_of_one_task_converge_on_the_last_writer() {
+    let base = Parser::parse("- [ ] milk\n");
+    let milk = item_ids(&base)[0];
+
+    let mut left = base.clone();
+    let mut right = base;
+    let unseen = StateVector::new();
+    assert!(
+        left.set_task_state(milk, true, op_id(1, 10), &unseen)
+            .unwrap()
+    );
+    assert!(
+        left.set_task_state(milk, false, op_id(2, 10), &unseen)
+            .unwrap()
+    );
+    assert!(
+        right
+            .set_task_state(milk, false, op_id(2, 10), &unseen)
+            .unwrap()
+    );
+    assert!(
+        !right
+            .set_task_state(milk, true, op_id(1, 10), &unseen)
+            .unwrap()
+    );
+
+    assert_eq!(tasks(&left), vec![Some(TaskState::Unchecked)]);
+    assert_eq!(tasks(&left), tasks(&right));
+    assert_eq!(left.serialize(EquivalenceMode::Structural), "- [ ] milk");
+}
+
+#[test]
+fn set_task_state_turns_plain_items_into_tasks() {
+    let mut document = Parser::parse("- milk\n");
+    let milk = item_ids(&document)[0];
+    document
+        .set_task_state(milk, false, op_id(1, 10), &StateVector::new())
+        .unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "- [ ] milk"
+    );
+
+    let missing = md_crdt::block_id_from_op(op_id(9, 9));
+    assert_eq!(
+        document.set_task_state(missing, true, op_id(1, 11), &StateVector::new()),
+        Err(EditError::BlockNotFound { block_id: missing })
+    );
+}
+
+#[test]
+fn a_toggle_that_saw_the_last_one_wins_whatever_its_id() {
+    let mut document = Parser::parse("- [ ] milk\n");
+    let milk = item_ids(&document)[0];
+    assert!(
+        document
+            .set_task_state(milk, true, op_id(1, 100), &StateVector::new())
+            .unwrap()
+    );
+
+    // A peer with a lower counter unchecks it after seeing the check.
+    let mut seen = StateVector::new();
+    seen.set(1, 100);
+    assert!(
+        document
+            .set_task_state(milk, false, op_id(2, 3), &seen)
+            .unwrap()
+    );
+    assert_eq!(tasks(&document), vec![Some(TaskState::Unchecked)]);
+
+    // A write that had not seen the uncheck loses to it.
+    assert!(
+        !document
+            .set_task_state(milk, true, op_id(1, 2), &StateVector::new())
+            .unwrap()
+    );
+    assert_eq!(tasks(&document), vec![Some(TaskState::Unchecked)]);
+}
@@ -0,0 +1,156 @@
+//! Whitespace policies between exact and structural serialization.
+
+use md_crdt::doc::{
+    EquivalenceMode, FinalNewline, LineEndings, Parser, SerializeConfig, TrailingSpaces,
+    WhitespacePolicy,
+};
+
+const NOTE: &str = "# Title  \n\nSome text  \n\n```sh\necho hi   \n\tindented\t\n```\n";
+
+fn policy(
+    trailing_spaces: TrailingSpaces,
+    final_newline: FinalNewline,
+    line_endings: LineEndings,
+) -> WhitespacePolicy {
+    WhitespacePolicy {
+        trailing_spaces,
+        final_newline,
+        line_endings,
+    }
+}
+
+#[test]
+fn without_a_policy_both_modes_behave_as_before() {
+    let doc = Parser::parse(NOTE);
+    assert_eq!(doc.serialize(EquivalenceMode::Exact), NOTE);
+    let structural = doc.serialize(EquivalenceMode::Structural);
+    assert_eq!(
+        structural,
+        "# Title\n\nSome text\n\n```sh\necho hi\n\tindented\n```"
+    );
+    assert_eq!(
+        doc.serialize_with_config(
+            &SerializeConfig::structural().with_whitespace(WhitespacePolicy::STRUCTURAL)
+        ),
+        doc.serialize_with_config(&SerializeConfig::structural())
+    );
+}
+
+#[test]
+fn structural_output_can_keep_the_final_newline_and_code_spaces() {
+    let doc = Parser::parse(NOTE);
+    let config = SerializeConfig::structural().with_whitespace(policy(
+        TrailingSpaces::InCodeFences,
+        FinalNewline::MatchSource,
+        LineEndings::MatchSource,
+    ));
+    assert_eq!(
+        doc.serialize_with_config(&config),
+        "# Title\n\nSome text\n\n```sh\necho hi   \n\tindented\t\n```\n"
+    );
+
+    // Structural output renders the heading afresh, without the spaces after
+    // it; the paragraph's trailing spaces are text and stay.
+    let keep = SerializeConfig::structural().with_whitespace(WhitespacePolicy::default());
+    assert_eq!(
+        doc.serialize_with_config(&keep),
+        "# Title\n\nSome text  \n\n```sh\necho hi   \n\tindented\t\n```\n"
+    );
+}
+
+#[test]
+fn exact_output_can_drop_trailing_spaces_outside_code() {
+    let doc = Parser::parse(NOTE);
+    let config = SerializeConfig::exact().with_whitespace(policy(
+        TrailingSpaces::InCodeFences,
+        FinalNewline::MatchSource,
+        LineEndings::MatchSource,
+    ));
+    assert_eq!(
+        doc.serialize_with_config(&config),
+        "# Title\n\nSome text\n\n```sh\necho hi   \n\tindented\t\n```\n"
+    );
+    assert_eq!(
+        doc.serialize_with_config(&SerializeConfig::exact().with_whitespace(Default::default())),
+        NOTE
+    );
+}
+
+#[test]
+fn final_newline_follows_the_source_or_the_setting() {
+    let without = Parser::parse("a\n\nb");
+    let with = Parser::parse("a\n\nb\n");
+    let serialize = |doc: &md_crdt::doc::Document, final_newline| {
+        doc.serialize_with_config(&SerializeConfig::structural().with_whitespace(policy(
+            TrailingSpaces::Strip,
+            final_newline,
+            LineEndings::Lf,
+        )))
+    };
+    assert_eq!(serialize(&without, FinalNewline::MatchSource), "a\n\nb");
+    assert_eq!(serialize(&with, FinalNewline::MatchSource), "a\n\nb\n");
+    assert_eq!(serialize(&without, FinalNewline::Ensure), "a\n\nb\n");
+    assert_eq!(serialize(&with, FinalNewline::Strip), "a\n\nb");
+
+    let exact = SerializeConfig::exact().with_whitespace(policy(
+        TrailingSpaces::Keep,
+        FinalNewline::Strip,
+        LineEndings::Lf,
+    ));
+    assert_eq!(Parser::parse("a\n\n\n").serialize_with_config(&exact), "a");
+    // A document built without a source ends with a newline under MatchSource.
+    let mut built = md_crdt::doc::Document::new();
+    built.blocks = Parser::parse("a").blocks;
+    assert_eq!(
+        built.serialize_with_config(
+            &SerializeConfig::structural().with_whitespace(WhitespacePolicy::default())
+        ),
+        "a\n"
+    );
+    assert_eq!(
+        md_crdt::doc::Document::new().serialize_with_config(
+            &SerializeConfig::structural().with_whitespace(WhitespacePolicy::default())
+        ),
+        ""
+    );
+}
+
+#[test]
+fn line_endings_follow_the_source_or_the_setting() {
+    let crlf = Parser::parse("# Title\r\n\r\ntext  \r\n");
+    let structural = |line_endings| {
+        crlf.serialize_with_config(&SerializeConfig::structural().with_whitespace(policy(
+            TrailingSpaces::Strip,
+            FinalNewline::MatchSource,
+            line_endings,
+        )))
+    };
+    assert_eq!(
+        structural(LineEndings::MatchSource),
+        "# Title\r\n\r\ntext\r\n"
+    );
+    assert_eq!(structural(LineEndings::Lf), "# Title\n\ntext\n");
+
+    let lf = Parser::parse("# Title\n\ntext\n");
+    let config = SerializeConfig::exact().with_whitespace(policy(
+        TrailingSpaces::Keep,
+        FinalNewline::MatchSource,
+        LineEndings::Crlf,
+    ));
+    assert_eq!(lf.serialize_with_config(&config), "# Title\r\n\r\ntext\r\n");
+}
+
+#[test]
+fn fences_in_containers_and_tilde_fences_keep_their_spaces() {
+    let source = "> ```  \n> code  \n> ```\n\n~~~~\n~~~ still code  \n~~~~\nafter  \n";
+    let doc = Parser::parse(source);
+    let config = SerializeConfig::exact().with_whitespace(policy(
+        TrailingSpaces::InCodeFences,
+        FinalNewline::MatchSource,
+        LineEndings::MatchSource,
+    ));
+    assert_eq!(
+        doc.serialize_with_config(&config),
+        "> ```\n> code  \n> ```\n\n~~~~\n~~~ still code  \n~~~~\nafter\n"
+    );
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{AuditFinding, AuditIssue, Vault};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn finding(path: &str, state: &str, issue: AuditIssue) -> AuditFinding {
+    AuditFinding {
+        path: PathBuf::from(path),
+        state_path: PathBuf::from(".mdcrdt/state").join(state),
+        issue,
+    }
+}
+
+#[test]
+fn audit_reports_orphaned_and_missing_state_and_fix_resolves_them() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("kept.md"), "kept").unwrap();
+    fs::write(dir.path().join("gone.md"), "gone").unwrap();
+    let vault = Vault::open(dir.path()).unwrap();
+    vault.flush().unwrap();
+    assert!(vault.audit().unwrap().is_clean());
+
+    fs::remove_file(dir.path().join("gone.md")).unwrap();
+    fs::write(dir.path().join("new.md"), "new").unwrap();
+    let audit = vault.audit().unwrap();
+    assert_eq!(audit.checked, 3);
+    assert_eq!(
+        audit.findings,
+        vec![
+            finding("gone.md", "gone.mdcrdt", AuditIssue::OrphanedState),
+            finding("new.md", "new.mdcrdt", AuditIssue::MissingState),
+        ]
+    );
+
+    assert_eq!(vault.fix_audit(&audit).unwrap(), 2);
+    assert!(vault.audit().unwrap().is_clean());
+    assert!(!dir.path().join(".mdcrdt/state/gone.mdcrdt").exists());
+    assert!(
+        vault
+            .file_status(&dir.path().join("new.md"))
+            .unwrap()
+            .tracked
+    );
+}
+
+#[test]
+fn audit_flags_damaged_snapshots() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.md"), "alpha").unwrap();
+    fs::write(dir.path().join("b.md"), "beta").unwrap();
+    let vault = Vault::open(dir.path()).unwrap();
+    vault.flush().unwrap();
+
+    let segment = dir.path().join(".mdcrdt/state/a.mdcrdt/segment_a");
+    let mut bytes = fs::read(&segment).unwrap();
+    bytes[0] ^= 0xff;
+    fs::write(&segment, bytes).unwrap();
+    fs::remove_file(dir.path().join(".mdcrdt/state/b.mdcrdt/segment_a")).unwrap();
+
+    let audit = vault.audit().unwrap();
+    assert_eq!(
+        audit.findings,
+        vec![
+            finding(
+                "a.md",
+                "a.mdcrdt",
+                AuditIssue::ChecksumMismatch {
+                    reason: "checksum mismatch".into(),
+                },
+            ),
+            finding(
+                "b.md",
+                "b.mdcrdt",
+                AuditIssue::CorruptSnapshot {
+                    reason: "corrupt storage: missing segment".into(),
+                },
+            ),
+        ]
+    );
+    vault.fix_audit(&audit).unwrap();
+    assert!(vault.audit().unwrap().is_clean());
+}
@@ -0,0 +1,189 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{BundleEntry, BundleManifest, Vault, VaultError, VaultSession};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn bundle_moves_files_history_and_identity_to_a_new_root() {
+    let source = tempdir().unwrap();
+    fs::create_dir(source.path().join("notes")).unwrap();
+    fs::write(source.path().join("notes").join("a.md"), "# A\n\nfirst").unwrap();
+    let mut session = VaultSession::open(source.path()).unwrap();
+    session.ingest_all().unwrap();
+    let peer = session.peer();
+    let vector = session.state_vector("notes/a.md").unwrap();
+    session.vault.flush().unwrap();
+    let bundle = source.path().join("vault.bundle");
+    let exported = session.vault.export_bundle(&bundle).unwrap();
+    assert!(
+        exported
+            .files
+            .iter()
+            .any(|entry| entry.path == "notes/a.md")
+    );
+    assert!(
+        exported
+            .files
+            .iter()
+            .any(|entry| entry.path == ".mdcrdt/peer_id")
+    );
+
+    let target = tempdir().unwrap();
+    let mut vault = Vault::open(target.path()).unwrap();
+    assert_eq!(vault.import_bundle(&bundle).unwrap(), exported);
+    assert_eq!(
+        fs::read_to_string(target.path().join("notes").join("a.md")).unwrap(),
+        "# A\n\nfirst"
+    );
+    assert!(vault.status().unwrap().iter().all(|file| !file.is_dirty()));
+
+    let mut moved = VaultSession::open(target.path()).unwrap();
+    assert_eq!(moved.peer(), peer);
+    assert_eq!(moved.state_vector("notes/a.md").unwrap(), vector);
+}
+
+#[test]
+fn corrupt_bundle_is_rejected_before_anything_is_written() {
+    let source = tempdir().unwrap();
+    fs::write(source.path().join("a.md"), "content").unwrap();
+    let vault = Vault::open(source.path()).unwrap();
+    vault.flush().unwrap();
+    let bundle = source.path().join("vault.bundle");
+    vault.export_bundle(&bundle).unwrap();
+
+    let mut bytes = fs::read(&bundle).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&bundle, &bytes).unwrap();
+
+    let target = tempdir().unwrap();
+    let error = Vault::open(target.path())
+        .unwrap()
+        .import_bundle(&bundle)
+        .unwrap_err();
+    assert!(matches!(&error, VaultError::InvalidBundle(message) if message.contains("checksum")));
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn import_refuses_a_vault_that_already_has_state() {
+    let source = tempdir().unwrap();
+    fs::write(source.path().join("a.md"), "content").unwrap();
+    let vault = Vault::open(source.path()).unwrap();
+    vault.flush().unwrap();
+    let bundle = source.path().join("vault.bundle");
+    vault.export_bundle(&bundle).unwrap();
+
+    let target = tempdir().unwrap();
+    let mut existing = Vault::open(target.path()).unwrap();
+    existing.init().unwrap();
+    assert!(matches!(
+        existing.import_bundle(&bundle),
+        Err(VaultError::PathAlreadyExists(_))
+    ));
+}
+
+/// Hand-built bundle holding `files` in order.
+fn write_bundle(path: &Path, files: &[(&str, &[u8])]) {
+    let entries: Vec<BundleEntry> = files
+        .iter()
+        .map(|(path, bytes)| BundleEntry {
+            path: path.to_string(),
+            len: bytes.len() as u64,
+            crc32: crc32fast::hash(bytes),
+        })
+        .collect();
+    let manifest = serde_json::to_vec(&BundleManifest { files: entries }).unwrap();
+    let mut out = b"MDCRDTBN".to_vec();
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&(manifest.len() as u64).to_le_bytes());
+    out.extend_from_slice(&manifest);
+    out.extend_from_slice(&crc32fast::hash(&manifest).to_le_bytes());
+    for (_, bytes) in files {
+        out.extend_from_slice(bytes);
+    }
+    fs::write(path, out).unwrap();
+}
+
+#[test]
+fn import_accepts_only_notes_and_vault_state() {
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("vault.bundle");
+    for path in [
+        ".git/config",
+        "notes.txt",
+        ".hidden/a.md",
+        ".mdcrdt/hooks/post-merge",
+        ".mdcrdt/sessions",
+        ".mdcrdt-import/a.md",
+    ] {
+        write_bundle(&bundle, &[("a.md", b"a"), (path, b"payload")]);
+        let target = tempdir().unwrap();
+        let error = Vault::open(target.path())
+            .unwrap()
+            .import_bundle(&bundle)
+            .unwrap_err();
+        assert!(
+            matches!(&error, VaultError::InvalidBundle(message) if message.contains("unexpected")),
+            "{path}: {error}"
+        );
+        assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+    }
+
+    write_bundle(
+        &bundle,
+        &[
+            (".mdcrdt/peer_id", b"7\n"),
+            (".mdcrdt/sessions/a.md.mdcrdt/snapshot", b"state"),
+            (".a.md.6f1c1a5e-4bd5-4a4c-9d2e-0a6b8f4c2d1e.pending", b"a2"),
+            ("a.md", b"a"),
+        ],
+    );
+    let target = tempdir().unwrap();
+    Vault::open(target.path())
+        .unwrap()
+        .import_bundle(&bundle)
+        .unwrap();
+    assert_eq!(fs::read(target.path().join("a.md")).unwrap(), b"a");
+}
+
+#[test]
+fn import_rejects_duplicate_entries() {
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("vault.bundle");
+    write_bundle(&bundle, &[("a.md", b"first"), ("a.md", b"second")]);
+
+    let target = tempdir().unwrap();
+    let error = Vault::open(target.path())
+        .unwrap()
+        .import_bundle(&bundle)
+        .unwrap_err();
+    assert!(matches!(&error, VaultError::InvalidBundle(message) if message.contains("duplicate")));
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn a_failed_import_leaves_nothing_behind() {
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("vault.bundle");
+    // `a.md` cannot be both a file and the directory of `a.md/b.md`.
+    write_bundle(
+        &bundle,
+        &[
+            (".mdcrdt/peer_id", b"7\n"),
+            ("a.md", b"a"),
+            ("a.md/b.md", b"b"),
+        ],
+    );
+    let target = tempdir().unwrap();
+    let mut vault = Vault::open(target.path()).unwrap();
+    vault.import_bundle(&bundle).unwrap_err();
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+
+    // Nothing blocks a retry with a good bundle.
+    write_bundle(&bundle, &[(".mdcrdt/peer_id", b"7\n"), ("a.md", b"a")]);
+    vault.import_bundle(&bundle).unwrap();
+    assert_eq!(VaultSession::open(target.path()).unwrap().peer(), 7);
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::doc::EquivalenceMode;
+use md_crdt::filesync::{Vault, VaultConfig, VaultError, VaultSession};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn write_config(root: &std::path::Path, text: &str) {
+    fs::create_dir_all(root.join(".mdcrdt")).unwrap();
+    fs::write(VaultConfig::path(root), text).unwrap();
+}
+
+#[test]
+fn open_without_config_uses_defaults() {
+    let dir = tempdir().unwrap();
+    let vault = Vault::open(dir.path()).unwrap();
+    assert_eq!(vault.config, VaultConfig::default());
+    assert_eq!(vault.config.serialize, EquivalenceMode::Exact);
+}
+
+#[test]
+fn ignored_files_are_not_listed() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("drafts")).unwrap();
+    fs::write(dir.path().join("drafts").join("idea.md"), "idea").unwrap();
+    fs::write(dir.path().join("scratch.tmp.md"), "scratch").unwrap();
+    fs::write(dir.path().join("note.md"), "note").unwrap();
+    write_config(dir.path(), "ignore = [\"drafts/**\", \"*.tmp.md\"]\n");
+
+    let vault = Vault::open(dir.path()).unwrap();
+    let files: Vec<PathBuf> = vault
+        .files()
+        .map(|file| file.strip_prefix(dir.path()).unwrap().to_path_buf())
+        .collect();
+    assert_eq!(files, vec![PathBuf::from("note.md")]);
+}
+
+#[test]
+fn configured_peer_id_overrides_the_stored_one() {
+    let dir = tempdir().unwrap();
+    let stored = VaultSession::open(dir.path()).unwrap().peer;
+    write_config(dir.path(), "peer_id = 424242\n");
+    let session = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(session.peer, 424242);
+    assert_ne!(stored, 424242);
+}
+
+#[test]
+fn invalid_config_fails_open_with_its_line() {
+    let dir = tempdir().unwrap();
+    write_config(dir.path(), "[limits]\nmax_ops = 10\n");
+    let error = Vault::open(dir.path()).unwrap_err();
+    assert!(matches!(&error, VaultError::Config(config) if config.line == 2));
+    assert!(error.to_string().contains("limits.max_ops"));
+}
+
+#[test]
+fn structural_exports_follow_the_whitespace_table() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("note.md");
+    fs::write(&path, "# Title  \r\n\r\n\r\nalpha  \r\n").unwrap();
+    write_config(
+        dir.path(),
+        "[serialize]\nstyle = \"structural\"\n\n[whitespace]\ntrailing_spaces = \"strip\"\n",
+    );
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    let opened = vault.open_document("note.md").unwrap();
+    let outcome = vault
+        .export_markdown("note.md", &opened.revision, opened.disk_fingerprint)
+        .unwrap();
+    assert!(outcome.changed);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Title\r\n\r\nalpha\r\n"
+    );
+}
@@ -0,0 +1,115 @@
+//! Conflict files for ingests that overwrite unexported collaborative changes.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::doc::{EquivalenceMode, RegisterKey, ResolutionPolicy, ResolutionTarget};
+use md_crdt::filesync::{IngestConflict, VaultSession};
+use std::fs;
+use tempfile::tempdir;
+
+fn first_block(vs: &mut VaultSession, path: &str) -> md_crdt::doc::BlockId {
+    vs.session_mut(path).unwrap().document().blocks_in_order()[0].id
+}
+
+#[test]
+fn ingest_writes_conflict_file_when_both_sides_edited_a_block() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "alpha\n\nbeta\n\ngamma\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    let alpha = first_block(&mut vs, "note.md");
+    vs.session_mut("note.md")
+        .unwrap()
+        .insert_text(alpha, 5, " from peer")
+        .unwrap();
+    fs::write(&note, "alpha from disk\n\nbeta\n\ngamma\n").unwrap();
+
+    let outcome = vs.ingest_markdown("note.md", None, None).unwrap();
+    assert!(outcome.changed);
+    assert_eq!(
+        outcome.conflicts,
+        vec![IngestConflict::Block { block_id: alpha }]
+    );
+    assert_eq!(
+        outcome.conflict_file.as_deref(),
+        Some(std::path::Path::new("note.conflict.md"))
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("note.conflict.md")).unwrap(),
+        "<<<<<<< disk\nalpha from disk\n=======\nalpha from peer\n>>>>>>> crdt\n\nbeta\n\ngamma\n"
+    );
+    assert_eq!(
+        vs.session_mut("note.md")
+            .unwrap()
+            .document()
+            .serialize(EquivalenceMode::Structural),
+        "alpha from disk\n\nbeta\n\ngamma"
+    );
+
+    let report = vs.ingest_all().unwrap();
+    assert_eq!(report.files_changed + report.files_noop, 1);
+    assert!(report.conflict_files.is_empty());
+    assert!(!vs.is_open("note.conflict.md"));
+}
+
+#[test]
+fn disk_edits_alone_write_no_conflict_file() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "alpha\n\nbeta\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    let beta = vs
+        .session_mut("note.md")
+        .unwrap()
+        .document()
+        .blocks_in_order()[1]
+        .id;
+    vs.session_mut("note.md")
+        .unwrap()
+        .insert_text(beta, 4, "!")
+        .unwrap();
+    fs::write(&note, "alpha edited\n\nbeta!\n").unwrap();
+
+    let outcome = vs.ingest_markdown("note.md", None, None).unwrap();
+    assert!(outcome.changed);
+    assert!(outcome.conflicts.is_empty());
+    assert_eq!(outcome.conflict_file, None);
+    assert!(!dir.path().join("note.conflict.md").exists());
+}
+
+#[test]
+fn manual_frontmatter_fields_changed_on_both_sides_conflict() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "---\nstatus: draft\ntitle: Plan\n---\nbody\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    let session = vs.session_mut("note.md").unwrap();
+    session.set_resolution_policy(
+        ResolutionTarget::FrontmatterField("status".into()),
+        ResolutionPolicy::Manual,
+    );
+    session
+        .set_frontmatter_field("status", Some("review".into()))
+        .unwrap();
+    session
+        .set_frontmatter_field("title", Some("Roadmap".into()))
+        .unwrap();
+    fs::write(&note, "---\nstatus: done\ntitle: Plans\n---\nbody\n").unwrap();
+
+    let outcome = vs.ingest_markdown("note.md", None, None).unwrap();
+    assert_eq!(
+        outcome.conflicts,
+        vec![IngestConflict::Register {
+            key: RegisterKey::FrontmatterField("status".into()),
+        }]
+    );
+    let conflict = fs::read_to_string(dir.path().join("note.conflict.md")).unwrap();
+    assert!(conflict.contains("status: done"), "{conflict}");
+    assert!(conflict.contains("status: review"), "{conflict}");
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{VaultSession, contains, equals, exists};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn frontmatter_fields_are_queryable_across_the_vault() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("alpha.md"),
+        "---\ntitle: \"Alpha\"\ntags: [project, home]\n---\n\nbody\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("beta.md"),
+        "---\ntitle: Beta\ntags: project\nstatus: done\n---\n\nbody\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("opaque.md"),
+        "---\ntags:\n  - project\n---\n\nbody\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("plain.md"), "no frontmatter\n").unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.ingest_all().unwrap();
+
+    let index = vault.vault.frontmatter_index().unwrap();
+    assert_eq!(index.len(), 2);
+    assert_eq!(
+        index.files_where("tags", contains("project")),
+        [Path::new("alpha.md"), Path::new("beta.md")]
+    );
+    assert_eq!(
+        index.files_where("tags", contains("home")),
+        [Path::new("alpha.md")]
+    );
+    assert_eq!(
+        index.files_where("title", equals("Alpha")),
+        [Path::new("alpha.md")]
+    );
+    assert_eq!(
+        index.files_where("status", exists()),
+        [Path::new("beta.md")]
+    );
+    assert_eq!(
+        index.get("alpha.md", "tags").unwrap().items,
+        ["project", "home"]
+    );
+    assert_eq!(index.item_counts("tags")["project"], 2);
+    assert!(index.keys().contains("status"));
+}
+
+#[test]
+fn frontmatter_index_follows_session_edits() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("note.md"),
+        "---\nstatus: draft\n---\n\nbody\n",
+    )
+    .unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.ingest_all().unwrap();
+
+    vault
+        .session_mut("note.md")
+        .unwrap()
+        .set_frontmatter_field("status", Some("done".into()))
+        .unwrap();
+    vault.save_state("note.md").unwrap();
+    let index = vault.vault.frontmatter_index().unwrap();
+    assert!(index.files_where("status", equals("draft")).is_empty());
+    assert_eq!(
+        index.files_where("status", equals("done")),
+        [Path::new("note.md")]
+    );
+
+    vault
+        .session_mut("note.md")
+        .unwrap()
+        .set_frontmatter_field("status", None)
+        .unwrap();
+    vault.save_state("note.md").unwrap();
+    let index = vault.vault.frontmatter_index().unwrap();
+    assert!(index.files_where("status", exists()).is_empty());
+
+    fs::remove_file(dir.path().join("note.md")).unwrap();
+    assert!(vault.vault.frontmatter_index().unwrap().is_empty());
+}
@@ -0,0 +1,100 @@
+//! The vault manifest lets ingest skip files whose stat has not changed.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::VaultSession;
+use std::fs::{self, File};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+/// Let the filesystem clock tick past the files' modification times, so the
+/// manifest written next is not racy against them.
+fn settle() {
+    sleep(Duration::from_millis(50));
+}
+
+fn set_modified(path: &Path, time: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(time)
+        .unwrap();
+}
+
+#[test]
+fn unchanged_files_are_scanned_but_not_hashed() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.md"), "alpha\n").unwrap();
+    fs::write(dir.path().join("b.md"), "beta\n").unwrap();
+    settle();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    let first = vs.ingest_all().unwrap();
+    assert_eq!(first.stats.files_scanned, 2);
+    assert_eq!(first.stats.files_hashed, 2);
+    assert_eq!(first.stats.files_changed, 2);
+
+    let again = VaultSession::open(dir.path())
+        .unwrap()
+        .ingest_all()
+        .unwrap();
+    assert_eq!(again.files_noop, 2);
+    assert_eq!(again.stats.files_scanned, 2);
+    assert_eq!(again.stats.files_hashed, 0);
+
+    fs::write(dir.path().join("b.md"), "beta, edited\n").unwrap();
+    settle();
+    let edited = vs.ingest_all().unwrap();
+    assert_eq!(edited.stats.files_hashed, 1);
+    assert_eq!(edited.stats.files_changed, 1);
+    assert_eq!(edited.files_noop, 1);
+}
+
+#[test]
+fn touched_files_are_hashed_and_found_unchanged() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "text\n").unwrap();
+    settle();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    set_modified(&note, SystemTime::now() - Duration::from_secs(60));
+    let touched = vs.ingest_all().unwrap();
+    assert_eq!(touched.stats.files_hashed, 1);
+    assert_eq!(touched.stats.files_changed, 0);
+    assert_eq!(vs.ingest_all().unwrap().stats.files_hashed, 0);
+}
+
+#[test]
+fn racy_entries_are_rehashed_even_when_the_stat_matches() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    let future = SystemTime::now() + Duration::from_secs(3600);
+    fs::write(&note, "one\n").unwrap();
+    set_modified(&note, future);
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    // Same size, same modification time, different bytes.
+    fs::write(&note, "two\n").unwrap();
+    set_modified(&note, future);
+    let report = vs.ingest_all().unwrap();
+    assert_eq!(report.stats.files_hashed, 1);
+    assert_eq!(report.stats.files_changed, 1);
+}
+
+#[test]
+fn a_corrupt_manifest_falls_back_to_hashing() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "text\n").unwrap();
+    settle();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+    fs::write(dir.path().join(".mdcrdt/manifest.json"), "not json").unwrap();
+    let report = vs.ingest_all().unwrap();
+    assert_eq!(report.stats.files_hashed, 1);
+    assert_eq!(report.files_noop, 1);
+}
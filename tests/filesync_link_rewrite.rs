@@ -0,0 +1,139 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::doc::{EquivalenceMode, LinkError, LinkTarget, heading_anchor};
+use md_crdt::filesync::VaultSession;
+use tempfile::tempdir;
+
+fn document_text(vault: &mut VaultSession, path: &str) -> String {
+    vault
+        .session_mut(path)
+        .unwrap()
+        .document()
+        .serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn link_targets_classify_and_render_hrefs() {
+    let note = |path: &str, heading: Option<&str>| LinkTarget::Note {
+        path: path.into(),
+        heading: heading.map(Into::into),
+    };
+    for (href, target) in [
+        (
+            "https://example.com/a(b)",
+            LinkTarget::External("https://example.com/a(b)".into()),
+        ),
+        (
+            "mailto:me@example.com",
+            LinkTarget::External("mailto:me@example.com".into()),
+        ),
+        (
+            "//cdn.example.com/x",
+            LinkTarget::External("//cdn.example.com/x".into()),
+        ),
+        ("#next-steps", LinkTarget::Heading("next-steps".into())),
+        ("../notes/plan.md", note("../notes/plan.md", None)),
+        ("plan#next-steps", note("plan", Some("next-steps"))),
+    ] {
+        assert_eq!(LinkTarget::parse(href), Ok(target.clone()));
+        assert_eq!(target.to_string(), href);
+    }
+
+    assert_eq!(LinkTarget::parse(""), Err(LinkError::Empty));
+    assert_eq!(
+        LinkTarget::parse("my note.md"),
+        Err(LinkError::InvalidCharacter(' '))
+    );
+    assert_eq!(
+        LinkTarget::parse("a)b"),
+        Err(LinkError::UnbalancedParentheses)
+    );
+    assert_eq!(LinkTarget::parse("plan.md#"), Err(LinkError::EmptyAnchor));
+    assert_eq!(
+        heading_anchor(" Next Steps: Q3 & Beyond "),
+        "next-steps-q3--beyond"
+    );
+}
+
+#[test]
+fn renaming_a_file_rewrites_links_across_the_vault() {
+    let dir = tempdir().unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    let moved = vault
+        .create_markdown(
+            "notes/a.md",
+            "# Intro Section\n\nsee [c](c.md) and [top](#intro-section)\n",
+        )
+        .unwrap();
+    vault
+        .create_markdown(
+            "b.md",
+            "[a](notes/a.md#intro-section) [web](https://a.md)\n",
+        )
+        .unwrap();
+    vault
+        .create_markdown("notes/c.md", "[a](a.md) and [bare](a) and [b](/b.md)\n")
+        .unwrap();
+    let before = vault.state_vector("b.md").unwrap();
+
+    vault
+        .rename_markdown(
+            "notes/a.md",
+            "archive/a.md",
+            &moved.revision,
+            moved.disk_fingerprint,
+        )
+        .unwrap();
+    let report = vault
+        .retarget_file_links("notes/a.md", "archive/a.md")
+        .unwrap();
+    assert_eq!(report.links, 4);
+    assert_eq!(report.documents.len(), 3);
+
+    assert_eq!(
+        document_text(&mut vault, "b.md"),
+        "[a](archive/a.md#intro-section) [web](https://a.md)"
+    );
+    assert_eq!(
+        document_text(&mut vault, "notes/c.md"),
+        "[a](../archive/a.md) and [bare](../archive/a) and [b](/b.md)"
+    );
+    assert_eq!(
+        document_text(&mut vault, "archive/a.md"),
+        "# Intro Section\n\nsee [c](../notes/c.md) and [top](#intro-section)"
+    );
+    // The rewrite is ordinary history other replicas receive.
+    assert!(
+        !vault
+            .encode_changes_since("b.md", &before)
+            .unwrap()
+            .ops
+            .is_empty()
+    );
+
+    let report = vault
+        .retarget_heading_links("archive/a.md", "Intro Section", "Overview")
+        .unwrap();
+    assert_eq!(report.links, 2);
+    assert_eq!(
+        document_text(&mut vault, "b.md"),
+        "[a](archive/a.md#overview) [web](https://a.md)"
+    );
+    assert_eq!(
+        document_text(&mut vault, "archive/a.md"),
+        "# Intro Section\n\nsee [c](../notes/c.md) and [top](#overview)"
+    );
+
+    let mut reopened = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(
+        document_text(&mut reopened, "b.md"),
+        "[a](archive/a.md#overview) [web](https://a.md)"
+    );
+    assert_eq!(
+        reopened
+            .retarget_file_links("notes/a.md", "archive/a.md")
+            .unwrap()
+            .links,
+        0
+    );
+}
@@ -0,0 +1,169 @@
+//! Vault membership: the replicated file set and peer registry.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{VaultError, VaultMembership, VaultSession};
+use md_crdt::sync::{MembershipError, PeerStatus};
+use md_crdt::{PeerId, StateVector};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn files(membership: &VaultMembership) -> Vec<PathBuf> {
+    membership.files().map(Path::to_path_buf).collect()
+}
+
+/// Hand `from`'s unseen membership updates to `to`.
+fn exchange(from: &VaultSession, to: &mut VaultSession) {
+    let ops = from.membership_changes_since(&to.membership().state_vector());
+    to.apply_membership(ops).unwrap();
+}
+
+#[test]
+fn opening_registers_the_peer_and_ingest_tracks_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.md"), "a\n").unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/b.md"), "b\n").unwrap();
+
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    let peer = vault.peer();
+    assert_eq!(
+        vault
+            .membership()
+            .peers()
+            .active_peers()
+            .collect::<Vec<_>>(),
+        vec![peer]
+    );
+    vault.ingest_all().unwrap();
+    vault.create_markdown("c.md", "c\n").unwrap();
+    assert_eq!(
+        files(vault.membership()),
+        vec![
+            PathBuf::from("a.md"),
+            PathBuf::from("c.md"),
+            PathBuf::from("sub/b.md")
+        ]
+    );
+
+    // The membership outlives the session.
+    drop(vault);
+    let reopened = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(files(reopened.membership()).len(), 3);
+    assert_eq!(
+        reopened
+            .membership()
+            .peers()
+            .active_peers()
+            .collect::<Vec<_>>(),
+        vec![peer]
+    );
+}
+
+#[test]
+fn rename_and_delete_update_the_file_set() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.md"), "a\n").unwrap();
+    fs::write(dir.path().join("b.md"), "b\n").unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.ingest_all().unwrap();
+
+    let revision = vault.revision("a.md").unwrap();
+    vault
+        .rename_markdown("a.md", "renamed.md", &revision, None)
+        .unwrap();
+    let revision = vault.revision("b.md").unwrap();
+    vault.delete_markdown("b.md", &revision, None).unwrap();
+    assert_eq!(files(vault.membership()), vec![PathBuf::from("renamed.md")]);
+}
+
+#[test]
+fn a_file_added_concurrently_with_its_removal_stays() {
+    let first_dir = tempdir().unwrap();
+    let second_dir = tempdir().unwrap();
+    fs::write(first_dir.path().join("note.md"), "note\n").unwrap();
+    let mut first = VaultSession::open(first_dir.path()).unwrap();
+    let mut second = VaultSession::open(second_dir.path()).unwrap();
+    first.ingest_all().unwrap();
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+    assert!(second.membership().contains_file(Path::new("note.md")));
+
+    // The first replica deletes the note while the second recreates it.
+    let revision = first.revision("note.md").unwrap();
+    first.delete_markdown("note.md", &revision, None).unwrap();
+    let mut second_copy = second.membership().clone();
+    let removal = second_copy
+        .remove_file(Path::new("note.md"), second.peer())
+        .unwrap();
+    let addition = second_copy
+        .add_file(Path::new("note.md"), second.peer())
+        .unwrap();
+    second.apply_membership([removal, addition]).unwrap();
+
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+    assert_eq!(first.membership(), second.membership());
+    assert!(first.membership().contains_file(Path::new("note.md")));
+}
+
+#[test]
+fn peers_learn_of_each_other_and_retire_one() {
+    let first_dir = tempdir().unwrap();
+    let second_dir = tempdir().unwrap();
+    let mut first = VaultSession::open(first_dir.path()).unwrap();
+    let mut second = VaultSession::open(second_dir.path()).unwrap();
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+
+    let mut expected: Vec<PeerId> = vec![first.peer(), second.peer()];
+    expected.sort_unstable();
+    assert_eq!(
+        first
+            .membership()
+            .peers()
+            .active_peers()
+            .collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(first.membership(), second.membership());
+
+    let retired = second.peer();
+    let op = first.retire_peer(retired).unwrap().unwrap();
+    assert_eq!(op.id().peer, first.peer());
+    assert!(first.retire_peer(retired).unwrap().is_none());
+    exchange(&first, &mut second);
+    assert!(!second.membership().peers().contains(retired));
+    assert_eq!(
+        second.membership().peers().record(retired).unwrap().status,
+        PeerStatus::Retired
+    );
+    assert_eq!(
+        second.membership_changes_since(&first.membership().state_vector()),
+        Vec::new()
+    );
+    assert!(
+        !first
+            .membership_changes_since(&StateVector::new())
+            .is_empty()
+    );
+}
+
+#[test]
+fn a_retired_peer_cannot_reopen_its_vault() {
+    let first_dir = tempdir().unwrap();
+    let second_dir = tempdir().unwrap();
+    let mut first = VaultSession::open(first_dir.path()).unwrap();
+    let mut second = VaultSession::open(second_dir.path()).unwrap();
+    exchange(&second, &mut first);
+    let retired = second.peer();
+    first.retire_peer(retired).unwrap();
+    exchange(&first, &mut second);
+    drop(second);
+
+    assert!(matches!(
+        VaultSession::open(second_dir.path()),
+        Err(VaultError::Membership(MembershipError::Retired(peer))) if peer == retired
+    ));
+}
@@ -0,0 +1,108 @@
+//! Format versions of per-file state, and migrating older vaults.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{STATE_FORMAT_VERSION, state_migrations};
+use md_crdt::storage::Storage;
+use md_crdt::{MigratedState, Vault, VaultError, VaultSession};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+const HEADER_LEN: usize = 12;
+
+fn flushed_vault(root: &Path) -> Vault {
+    fs::write(root.join("a.md"), "# A\n\nalpha\n").unwrap();
+    let vault = Vault::open(root).unwrap();
+    vault.flush().unwrap();
+    vault
+}
+
+fn state_storage(root: &Path) -> Storage {
+    Storage::open(root.join(".mdcrdt/state/a.mdcrdt")).unwrap()
+}
+
+/// Rewrite the state of `a.md` as a build before format versioning did.
+fn strip_header(root: &Path) {
+    let storage = state_storage(root);
+    let (bytes, _, _) = storage.read_snapshot().unwrap();
+    assert_eq!(&bytes[..8], b"MDCRDTFS");
+    storage
+        .write_snapshot(&bytes[HEADER_LEN..], &[], false)
+        .unwrap();
+}
+
+#[test]
+fn every_older_version_has_a_registered_step() {
+    let froms: Vec<u32> = state_migrations().iter().map(|step| step.from).collect();
+    assert_eq!(froms, (1..STATE_FORMAT_VERSION).collect::<Vec<_>>());
+}
+
+#[test]
+fn unversioned_state_stays_readable_and_migrates() {
+    let dir = tempdir().unwrap();
+    let vault = flushed_vault(dir.path());
+    strip_header(dir.path());
+    assert!(!vault.status().unwrap()[0].is_dirty());
+
+    let dry = vault.migrate_state_formats(true).unwrap();
+    let expected = vec![MigratedState {
+        path: PathBuf::from("state/a.mdcrdt"),
+        from: 1,
+        to: STATE_FORMAT_VERSION,
+    }];
+    assert_eq!(dry.migrated, expected);
+    let (bytes, _, _) = state_storage(dir.path()).read_snapshot().unwrap();
+    assert_ne!(&bytes[..8], b"MDCRDTFS");
+    assert_eq!(vault.recorded_state_format(), None);
+
+    let migration = vault.migrate_state_formats(false).unwrap();
+    assert_eq!(migration.migrated, expected);
+    assert_eq!(vault.recorded_state_format(), Some(STATE_FORMAT_VERSION));
+    assert!(!vault.status().unwrap()[0].is_dirty());
+
+    let again = vault.migrate_state_formats(false).unwrap();
+    assert!(again.is_empty());
+    assert_eq!(again.current, 1);
+}
+
+#[test]
+fn opening_a_session_migrates_once() {
+    let dir = tempdir().unwrap();
+    flushed_vault(dir.path());
+    strip_header(dir.path());
+
+    let session = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(
+        session.vault.recorded_state_format(),
+        Some(STATE_FORMAT_VERSION)
+    );
+    let (bytes, _, _) = state_storage(dir.path()).read_snapshot().unwrap();
+    assert_eq!(&bytes[..8], b"MDCRDTFS");
+    assert!(
+        session
+            .vault
+            .migrate_state_formats_if_needed()
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn newer_state_is_refused_without_writing() {
+    let dir = tempdir().unwrap();
+    let vault = flushed_vault(dir.path());
+    let storage = state_storage(dir.path());
+    let (mut bytes, _, _) = storage.read_snapshot().unwrap();
+    bytes[8..HEADER_LEN].copy_from_slice(&(STATE_FORMAT_VERSION + 1).to_le_bytes());
+    storage.write_snapshot(&bytes, &[], false).unwrap();
+
+    let err = vault.migrate_state_formats(false).unwrap_err();
+    assert!(matches!(
+        err.root_cause(),
+        VaultError::UnsupportedStateFormat { found, supported }
+            if *found == STATE_FORMAT_VERSION + 1 && *supported == STATE_FORMAT_VERSION
+    ));
+    assert_eq!(vault.recorded_state_format(), None);
+    assert!(vault.status().is_err());
+}
@@ -0,0 +1,76 @@
+//! Line-ending and Unicode normalization on ingest.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::doc::EquivalenceMode;
+use md_crdt::filesync::{TextNormalization, VaultSession};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn normalization_applies_line_endings_then_composition() {
+    let policy = TextNormalization::default();
+    assert_eq!(policy.apply("a\r\nb\rc\n"), "a\nb\nc\n");
+    assert_eq!(policy.apply("cafe\u{301}\r\n"), "caf\u{e9}\n");
+    // Full NFC beyond Latin letters: Hangul jamo, Greek, reordered marks.
+    assert_eq!(policy.apply("\u{1100}\u{1161}"), "\u{ac00}");
+    assert_eq!(policy.apply("\u{3b1}\u{301}"), "\u{3ac}");
+    assert_eq!(policy.apply("a\u{302}\u{323}"), "\u{1ead}");
+    assert!(matches!(
+        policy.apply("caf\u{e9}\n"),
+        std::borrow::Cow::Borrowed(_)
+    ));
+    assert_eq!(
+        TextNormalization::NONE.apply("e\u{301}\r\n"),
+        "e\u{301}\r\n"
+    );
+}
+
+#[test]
+fn byte_only_rewrites_ingest_as_no_ops_and_exports_keep_the_original_bytes() {
+    let dir = tempdir().unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "# Caf\u{e9}\n\nfirst line\nsecond\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(vs.ingest_all().unwrap().files_changed, 1);
+
+    // The same note saved on Windows by a tool that writes NFD.
+    fs::write(&note, "# Cafe\u{301}\r\n\r\nfirst line\r\nsecond\r\n").unwrap();
+    let report = vs.ingest_all().unwrap();
+    assert_eq!(report.files_noop, 1);
+    assert_eq!(report.ops_emitted, 0);
+
+    let revision = vs.revision("note.md").unwrap();
+    vs.export_markdown("note.md", &revision, None).unwrap();
+    assert_eq!(
+        fs::read_to_string(&note).unwrap(),
+        "# Cafe\u{301}\r\n\r\nfirst line\r\nsecond\r\n"
+    );
+
+    let mut reopened = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(reopened.ingest_all().unwrap().files_noop, 1);
+    let text = reopened
+        .session_mut("note.md")
+        .unwrap()
+        .document()
+        .serialize(EquivalenceMode::Structural);
+    assert_eq!(text, "# Caf\u{e9}\n\nfirst line\nsecond");
+}
+
+#[test]
+fn disabled_normalization_treats_line_ending_changes_as_edits() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join(".mdcrdt")).unwrap();
+    fs::write(
+        dir.path().join(".mdcrdt/config.toml"),
+        "[normalize]\nline_endings = false\nunicode = false\n",
+    )
+    .unwrap();
+    let note = dir.path().join("note.md");
+    fs::write(&note, "one\n\ntwo\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    vs.ingest_all().unwrap();
+
+    fs::write(&note, "one\r\n\r\ntwo\r\n").unwrap();
+    assert_eq!(vs.ingest_all().unwrap().files_changed, 1);
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "search")]
+
+use md_crdt::StateVector;
+use md_crdt::filesync::{SearchIndex, Vault, VaultSession};
+use md_crdt::sync::ValidationLimits;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tempfile::tempdir;
+
+fn index_modified(dir: &std::path::Path, rel: &str) -> SystemTime {
+    fs::metadata(dir.join(".mdcrdt/search").join(format!("{rel}.idx")))
+        .unwrap()
+        .modified()
+        .unwrap()
+}
+
+#[test]
+fn ingested_blocks_are_searchable_with_snippets() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("garden.md"),
+        "# Garden\n\nPlant the tomatoes after the last frost, then water them daily until they settle in.\n",
+    )
+    .unwrap();
+    fs::create_dir(dir.path().join("notes")).unwrap();
+    fs::write(
+        dir.path().join("notes").join("recipes.md"),
+        "- Tomatoes\n- Basil\n\n| dish | tomato |\n| --- | --- |\n| soup | yes |\n",
+    )
+    .unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.ingest_all().unwrap();
+
+    let hits = vault.vault.search("TOMATOES").unwrap();
+    let paths: Vec<_> = hits.iter().map(|hit| hit.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("garden.md"),
+            PathBuf::from("notes").join("recipes.md")
+        ]
+    );
+    assert_eq!(
+        hits[0].snippet,
+        "Plant the tomatoes after the last frost, then …"
+    );
+    let body = vault
+        .session_mut("garden.md")
+        .unwrap()
+        .document()
+        .blocks_in_order()[1]
+        .id;
+    assert_eq!(hits[0].block_id, body);
+
+    // Every term must match within one block.
+    assert_eq!(vault.vault.search("soup yes").unwrap().len(), 1);
+    assert!(vault.vault.search("soup basil").unwrap().is_empty());
+    assert!(vault.vault.search("  ").unwrap().is_empty());
+}
+
+#[test]
+fn merges_update_the_index_and_unchanged_documents_are_not_rewritten() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.md"), "alpha\n").unwrap();
+    fs::write(dir.path().join("b.md"), "beta\n").unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.ingest_all().unwrap();
+    let untouched = index_modified(dir.path(), "b.md");
+
+    let mut remote = md_crdt::CollaborativeDocument::new(42);
+    remote
+        .apply_remote(
+            vault
+                .encode_changes_since("a.md", &StateVector::new())
+                .unwrap(),
+            &ValidationLimits::default(),
+        )
+        .unwrap();
+    let block = remote.document().blocks_in_order()[0].id;
+    remote.insert_text(block, 5, " gamma").unwrap();
+    let since = vault.state_vector("a.md").unwrap();
+    vault
+        .apply_remote(
+            "a.md",
+            remote.encode_changes_since(&since).unwrap(),
+            &ValidationLimits::default(),
+        )
+        .unwrap();
+    vault.save_all_state().unwrap();
+
+    let index = SearchIndex::load(&Vault::open(dir.path()).unwrap()).unwrap();
+    assert_eq!(index.search("gamma")[0].snippet, "alpha gamma");
+    assert_eq!(index.search("beta").len(), 1);
+    assert_eq!(index_modified(dir.path(), "b.md"), untouched);
+
+    fs::remove_file(dir.path().join("b.md")).unwrap();
+    assert!(vault.vault.search("beta").unwrap().is_empty());
+}
@@ -0,0 +1,207 @@
+//! VaultSyncService: ingest, push, apply and materialize between two vaults.
+
+#![cfg(feature = "service")]
+
+use md_crdt::filesync::{
+    ChannelTransport, ServiceConfig, ServiceEvent, SyncTransport, TransportError, VaultSession,
+    VaultSyncService,
+};
+use md_crdt::sync::{ChangeMessage, RetryPolicy};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn fast_config() -> ServiceConfig {
+    ServiceConfig {
+        poll_interval: Duration::from_millis(10),
+        debounce: Duration::ZERO,
+        ..ServiceConfig::default()
+    }
+}
+
+fn recorder() -> (
+    Arc<Mutex<Vec<ServiceEvent>>>,
+    impl FnMut(&ServiceEvent) + Send,
+) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    (events, move |event: &ServiceEvent| {
+        sink.lock().unwrap().push(event.clone())
+    })
+}
+
+async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for {what}"));
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+#[tokio::test]
+async fn services_exchange_local_edits_and_materialize_them() {
+    let (first_dir, second_dir) = (tempdir().unwrap(), tempdir().unwrap());
+    let (first_note, second_note) = (
+        first_dir.path().join("note.md"),
+        second_dir.path().join("note.md"),
+    );
+    fs::write(&first_note, "base\n").unwrap();
+
+    let (left, right) = ChannelTransport::pair();
+    let (events, observer) = recorder();
+    let mut first = VaultSyncService::new(VaultSession::open(first_dir.path()).unwrap(), left)
+        .with_config(fast_config())
+        .with_observer(observer);
+    let mut second = VaultSyncService::new(VaultSession::open(second_dir.path()).unwrap(), right)
+        .with_config(fast_config());
+    let (stop_first, stop_second) = (first.shutdown_handle(), second.shutdown_handle());
+
+    let driver = async {
+        wait_for("the initial copy", || read(&second_note).contains("base")).await;
+        fs::write(&second_note, "base\n\nfrom second\n").unwrap();
+        wait_for("the second vault's edit", || {
+            read(&first_note).contains("from second")
+        })
+        .await;
+        stop_first.shutdown();
+        stop_second.shutdown();
+    };
+    let (first_run, second_run, ()) = tokio::join!(first.run(), second.run(), driver);
+    first_run.unwrap();
+    second_run.unwrap();
+
+    assert_eq!(read(&first_note), read(&second_note));
+    let events = events.lock().unwrap();
+    let note = PathBuf::from("note.md");
+    assert!(events.contains(&ServiceEvent::Ingested {
+        path: note.clone(),
+        ops: 2,
+    }));
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ServiceEvent::Pushed { path, .. } if *path == note))
+    );
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ServiceEvent::Materialized { path, .. } if *path == note))
+    );
+}
+
+/// Refuses every send and never delivers anything.
+struct Unreachable;
+
+impl SyncTransport for Unreachable {
+    async fn send(&mut self, _: &Path, _: ChangeMessage) -> Result<(), TransportError> {
+        Err(TransportError("unreachable".into()))
+    }
+
+    async fn recv(&mut self) -> Option<(PathBuf, ChangeMessage)> {
+        std::future::pending().await
+    }
+}
+
+#[tokio::test]
+async fn failed_pushes_back_off_until_the_policy_gives_up() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "text\n").unwrap();
+    let (events, observer) = recorder();
+    let retry = RetryPolicy {
+        initial_timeout: Duration::from_millis(20),
+        backoff_factor: 2,
+        max_timeout: Duration::from_secs(1),
+        max_attempts: Some(3),
+    };
+    let mut service = VaultSyncService::new(VaultSession::open(dir.path()).unwrap(), Unreachable)
+        .with_config(ServiceConfig {
+            retry,
+            ..fast_config()
+        })
+        .with_observer(observer);
+    let stop = service.shutdown_handle();
+    let failures = || {
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                ServiceEvent::PushFailed {
+                    attempts, retry_in, ..
+                } => Some((*attempts, *retry_in)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let driver = async {
+        wait_for("the policy to give up", || failures().len() == 3).await;
+        // Nothing is retried once the policy has given up.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.shutdown();
+    };
+    let (run, ()) = tokio::join!(service.run(), driver);
+    run.unwrap();
+
+    assert_eq!(
+        failures(),
+        vec![
+            (1, Some(Duration::from_millis(20))),
+            (2, Some(Duration::from_millis(40))),
+            (3, None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn messages_for_paths_outside_the_notes_are_refused() {
+    let (source_dir, dir) = (tempdir().unwrap(), tempdir().unwrap());
+    fs::write(source_dir.path().join("note.md"), "remote\n").unwrap();
+    let mut source = VaultSession::open(source_dir.path()).unwrap();
+    source.ingest_all().unwrap();
+    let message = source
+        .encode_changes_since("note.md", &Default::default())
+        .unwrap();
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    fs::write(dir.path().join(".git/config"), "[core]\n").unwrap();
+
+    let (mut remote, local) = ChannelTransport::pair();
+    let (events, observer) = recorder();
+    let mut service = VaultSyncService::new(VaultSession::open(dir.path()).unwrap(), local)
+        .with_config(fast_config())
+        .with_observer(observer);
+    let stop = service.shutdown_handle();
+    let refused = [".mdcrdt/x", ".git/config", "notes.txt", "sub/.hidden/a.md"];
+    for path in refused {
+        remote.send(Path::new(path), message.clone()).await.unwrap();
+    }
+    remote.send(Path::new("note.md"), message).await.unwrap();
+    let driver = async {
+        wait_for("the note", || {
+            read(&dir.path().join("note.md")).contains("remote")
+        })
+        .await;
+        stop.shutdown();
+    };
+    let (run, ()) = tokio::join!(service.run(), driver);
+    run.unwrap();
+
+    let events = events.lock().unwrap();
+    for path in refused {
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServiceEvent::Error { path: refused, .. } if refused == Path::new(path)
+        )));
+    }
+    assert_eq!(read(&dir.path().join(".git/config")), "[core]\n");
+    assert!(!dir.path().join(".mdcrdt/x").exists());
+    assert!(!dir.path().join("notes.txt").exists());
+    assert!(!dir.path().join("sub").exists());
+}
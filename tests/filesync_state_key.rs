@@ -0,0 +1,119 @@
+//! Normalized keys for per-file vault state.
+
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{StateKeyMigration, state_key};
+use md_crdt::{Vault, VaultError, VaultSession};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+#[test]
+fn state_key_unifies_separators_composition_and_case() {
+    let key = |path: &str, case_fold| state_key(Path::new(path), case_fold);
+    assert_eq!(
+        key("notes\\daily\\a.md", false),
+        PathBuf::from("notes/daily/a.md")
+    );
+    assert_eq!(
+        key("caf\u{65}\u{301}.md", false),
+        PathBuf::from("caf\u{e9}.md")
+    );
+    assert_eq!(key("./Notes//A.md", false), PathBuf::from("Notes/A.md"));
+    assert_eq!(
+        key("Notes/A\u{308}.md", true),
+        PathBuf::from("notes/\u{e4}.md")
+    );
+    assert_eq!(key("q\u{301}.md", false), PathBuf::from("q\u{301}.md"));
+    // Full NFC: Hangul jamo, Greek, and combining marks in either order.
+    assert_eq!(
+        key("\u{1100}\u{1161}.md", false),
+        PathBuf::from("\u{ac00}.md")
+    );
+    assert_eq!(key("\u{3b1}\u{301}.md", false), PathBuf::from("\u{3ac}.md"));
+    assert_eq!(
+        key("a\u{302}\u{323}.md", false),
+        key("a\u{323}\u{302}.md", false)
+    );
+    assert_eq!(
+        key("a\u{323}\u{302}.md", false),
+        PathBuf::from("\u{1ead}.md")
+    );
+}
+
+#[test]
+fn migration_rekeys_state_written_under_decomposed_names() {
+    let dir = tempdir().unwrap();
+    let decomposed = "caf\u{65}\u{301}.md";
+    fs::write(dir.path().join(decomposed), "hello\n").unwrap();
+    fs::create_dir_all(dir.path().join(".mdcrdt")).unwrap();
+    let vault = Vault::open(dir.path()).unwrap();
+    vault.init().unwrap();
+    let raw_state = dir.path().join(".mdcrdt/state/caf\u{65}\u{301}.mdcrdt");
+    md_crdt::storage::Storage::open(&raw_state)
+        .unwrap()
+        .write_snapshot(b"state", &[], false)
+        .unwrap();
+
+    let migration = vault.migrate_state_keys().unwrap();
+    assert_eq!(
+        migration.rekeyed,
+        vec![(
+            PathBuf::from("state/caf\u{65}\u{301}.mdcrdt"),
+            PathBuf::from("state/caf\u{e9}.mdcrdt"),
+        )]
+    );
+    assert!(!raw_state.exists());
+    assert!(dir.path().join(".mdcrdt/state/caf\u{e9}.mdcrdt").is_dir());
+    assert_eq!(
+        vault.migrate_state_keys().unwrap(),
+        StateKeyMigration::default()
+    );
+}
+
+#[test]
+fn case_folded_vaults_share_state_and_keep_it_across_case_renames() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join(".mdcrdt")).unwrap();
+    fs::write(
+        dir.path().join(".mdcrdt/config.toml"),
+        "[state]\ncase_fold = true\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("Note.md"), "body\n").unwrap();
+    let mut vs = VaultSession::open(dir.path()).unwrap();
+    let id = vs.document_id("Note.md").unwrap();
+    vs.ingest_all().unwrap();
+    assert!(dir.path().join(".mdcrdt/state/note.mdcrdt").is_dir());
+    assert!(dir.path().join(".mdcrdt/sessions/note.mdcrdt").is_dir());
+
+    let revision = vs.revision("Note.md").unwrap();
+    vs.rename_markdown("Note.md", "NOTE.md", &revision, None)
+        .unwrap();
+    assert!(dir.path().join(".mdcrdt/state/note.mdcrdt").is_dir());
+    assert!(dir.path().join(".mdcrdt/sessions/note.mdcrdt").is_dir());
+    let mut reopened = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(reopened.document_id("NOTE.md").unwrap(), id);
+}
+
+#[test]
+fn two_spellings_with_state_refuse_to_open_and_keep_both() {
+    let dir = tempdir().unwrap();
+    let sessions = dir.path().join(".mdcrdt/sessions");
+    let composed = sessions.join("caf\u{e9}.mdcrdt");
+    let decomposed = sessions.join("caf\u{65}\u{301}.mdcrdt");
+    for path in [&composed, &decomposed] {
+        md_crdt::storage::Storage::open(path)
+            .unwrap()
+            .write_snapshot(b"state", &[], false)
+            .unwrap();
+    }
+
+    let Err(VaultError::StateKeyConflict { key, entries }) = VaultSession::open(dir.path()) else {
+        panic!("expected a state key conflict");
+    };
+    assert_eq!(key, PathBuf::from("sessions/caf\u{e9}.mdcrdt"));
+    assert_eq!(entries.len(), 2);
+    assert!(composed.is_dir());
+    assert!(decomposed.is_dir());
+}
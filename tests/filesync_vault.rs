@@ -49,3 +49,24 @@ fn test_vault_open_errors_for_non_existent_path() {
         other => panic!("Expected PathDoesNotExist error, got {other:?}"),
     }
 }
+
+#[test]
+fn ingest_errors_name_the_file_and_classify_retries() {
+    use md_crdt::filesync::VaultSession;
+    use std::io;
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("good.md"), "fine\n").unwrap();
+    fs::write(dir.path().join("broken.md"), [0xff, 0xfe, b'\n']).unwrap();
+    let mut session = VaultSession::open(dir.path()).unwrap();
+    let error = session.ingest_all().unwrap_err();
+    assert_eq!(error.path(), Some(Path::new("broken.md")));
+    assert!(matches!(error.root_cause(), VaultError::Io(_)));
+    assert!(error.to_string().starts_with("broken.md: "));
+    assert!(!error.is_retryable());
+
+    let transient = VaultError::from(io::Error::from(io::ErrorKind::Interrupted)).in_file("a.md");
+    assert!(transient.is_retryable());
+    // Already attributed errors keep the innermost path.
+    assert_eq!(transient.in_file("b.md").path(), Some(Path::new("a.md")));
+}
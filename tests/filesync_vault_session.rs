@@ -201,4 +201,10 @@ fn path_scoped_sync_reports_retention_and_returns_a_rebase_checkpoint() {
         vault.sync_since("note.md", &StateVector::new()).unwrap(),
         SyncResponse::Rebase { .. }
     ));
+
+    let handshake = vault.handshake("note.md").unwrap();
+    assert!(!handshake.can_serve(&StateVector::new()));
+    vault.save_state("note.md").unwrap();
+    let mut reopened = VaultSession::open(dir.path()).unwrap();
+    assert_eq!(reopened.handshake("note.md").unwrap(), handshake);
 }
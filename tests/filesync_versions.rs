@@ -0,0 +1,112 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::doc::EquivalenceMode;
+use md_crdt::filesync::{VaultError, VaultSession};
+use md_crdt::{CollaborativeDocument, StateVector, Storage, ValidationLimits};
+use std::fs;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+fn markdown(vault: &mut VaultSession) -> String {
+    vault
+        .session_mut("note.md")
+        .unwrap()
+        .document()
+        .serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn restore_reverts_the_live_document_to_a_tagged_version() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "# Title\n\nalpha beta\n").unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.open_document("note.md").unwrap();
+    let tagged = markdown(&mut vault);
+    let frontier = vault.state_vector("note.md").unwrap();
+    vault.tag_version("note.md", "draft", &frontier).unwrap();
+
+    let mut peer = CollaborativeDocument::new(99);
+    peer.apply_remote(
+        vault
+            .encode_changes_since("note.md", &StateVector::new())
+            .unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+
+    let session = vault.session_mut("note.md").unwrap();
+    let body = session.document().blocks_in_order()[1].clone();
+    session.insert_text(body.id, 6, "brave ").unwrap();
+    session
+        .insert_paragraph(Some(body.elem_id), "gamma")
+        .unwrap();
+    assert_ne!(markdown(&mut vault), tagged);
+
+    let restored = vault.restore("note.md", "draft").unwrap();
+    assert_eq!(restored.tag.state_vector, frontier);
+    assert_eq!(
+        restored.document.serialize(EquivalenceMode::Structural),
+        tagged
+    );
+    assert_eq!(markdown(&mut vault), tagged);
+    assert!(!restored.revert.ops.is_empty());
+
+    // The revert is new history: a peer that saw the edits converges on it too.
+    let since = peer.state_vector();
+    peer.apply_remote(
+        vault.encode_changes_since("note.md", &since).unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        peer.document().serialize(EquivalenceMode::Structural),
+        tagged
+    );
+}
+
+#[test]
+fn tags_persist_and_retagging_replaces_the_frontier() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "alpha\n").unwrap();
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    vault.open_document("note.md").unwrap();
+    vault
+        .tag_version("note.md", "first", &StateVector::new())
+        .unwrap();
+    let frontier = vault.state_vector("note.md").unwrap();
+    vault.tag_version("note.md", "second", &frontier).unwrap();
+    vault.tag_version("note.md", "first", &frontier).unwrap();
+    drop(vault);
+
+    let mut vault = VaultSession::open(dir.path()).unwrap();
+    let tags = vault.list_tags("note.md").unwrap();
+    let names: Vec<_> = tags.iter().map(|tag| tag.name.as_str()).collect();
+    assert_eq!(names, ["second", "first"]);
+    assert!(tags.iter().all(|tag| tag.state_vector == frontier));
+
+    let missing = vault.restore("note.md", "nope").unwrap_err();
+    assert!(matches!(missing, VaultError::UnknownTag(name) if name == "nope"));
+}
+
+#[test]
+fn storage_finds_the_newest_tag_as_of_a_time() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    let before = SystemTime::now() - Duration::from_secs(60);
+    let mut frontier = StateVector::new();
+    frontier.set(1, 3);
+    let tag = storage.tag_version("v1", &frontier).unwrap();
+
+    assert_eq!(storage.tag_as_of(before).unwrap(), None);
+    assert_eq!(storage.tag_as_of(SystemTime::now()).unwrap(), Some(tag));
+    assert!(storage.delete_tag("v1").unwrap());
+    assert!(!storage.delete_tag("v1").unwrap());
+    assert!(storage.list_tags().unwrap().is_empty());
+
+    storage.tag_version("v2", &frontier).unwrap();
+    let path = dir.path().join("tags.bin");
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[0] ^= 0xff;
+    fs::write(&path, bytes).unwrap();
+    assert!(storage.list_tags().is_err());
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "filesync")]
+
+use md_crdt::filesync::{Vault, VaultWatcher, WatchEvent};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+#[test]
+fn poll_reports_created_written_and_removed_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("kept.md"), "kept").unwrap();
+    fs::write(dir.path().join("gone.md"), "gone").unwrap();
+    let vault = Vault::open(dir.path()).unwrap();
+    let mut watcher = VaultWatcher::new(&vault);
+    assert!(watcher.poll().is_empty());
+
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub").join("new.md"), "new").unwrap();
+    fs::write(dir.path().join("kept.md"), "kept, then edited").unwrap();
+    fs::remove_file(dir.path().join("gone.md")).unwrap();
+    fs::write(dir.path().join("ignored.txt"), "not markdown").unwrap();
+    assert_eq!(
+        watcher.poll(),
+        vec![
+            WatchEvent::Changed(PathBuf::from("kept.md")),
+            WatchEvent::Changed(PathBuf::from("sub").join("new.md")),
+            WatchEvent::Removed(PathBuf::from("gone.md")),
+        ]
+    );
+    assert!(watcher.poll().is_empty());
+}
@@ -52,6 +52,7 @@ fn sync_validation_facade_is_reexported_with_stable_defaults() {
     let empty = ChangeMessage {
         since: StateVector::new(),
         ops: Vec::new(),
+        gaps: Vec::new(),
     };
     validate_changes(&empty, &limits, 0).expect("empty change set validates");
 
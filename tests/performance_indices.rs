@@ -1,5 +1,8 @@
 use md_crdt::core::{OpId, Sequence, SequenceOp, StateVector};
-use md_crdt::doc::{Block, BlockKind, Document, ListItem, block_id_from_op};
+use md_crdt::doc::{
+    Block, BlockKind, Document, EquivalenceMode, ListItem, Parser, block_id_from_op,
+    units_from_str_at,
+};
 use md_crdt::sync::{IntegrateResult, Operation, SyncState};
 use std::sync::Arc;
 
@@ -173,6 +176,58 @@ fn block_index_repairs_after_public_sequence_replacement() {
     );
 }
 
+#[test]
+fn cached_block_renders_follow_top_level_and_nested_mutations() {
+    let mut document = Parser::parse("first\n\n> quoted\n\nlast\n");
+    let fresh = |document: &Document| document.clone().serialize(EquivalenceMode::Structural);
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "first\n\n> quoted\n\nlast"
+    );
+
+    let first = document.blocks.iter_asc().next().unwrap().id;
+    document.insert_text(first, 5, "!", op(100, 9)).unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        fresh(&document)
+    );
+
+    let BlockKind::BlockQuote { children } = &document.blocks_in_order()[1].kind else {
+        panic!("expected a blockquote");
+    };
+    let nested = children.iter_asc().next().unwrap().elem_id;
+    document
+        .with_block_mut(nested, |block| {
+            block.kind = BlockKind::Paragraph {
+                text: units_from_str_at("rewritten", op(200, 9)),
+            }
+        })
+        .unwrap();
+    let rendered = document.serialize(EquivalenceMode::Structural);
+    assert_eq!(rendered, "first!\n\n> rewritten\n\nlast");
+    assert_eq!(rendered, fresh(&document));
+
+    let last = document.blocks_in_order()[2].elem_id;
+    document.delete_block_at(None, last, op(300, 9));
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "first!\n\n> rewritten"
+    );
+
+    // Documents parsed from different text reuse element ids.
+    let mut other = Parser::parse("other\n\n> text\n");
+    other.serialize(EquivalenceMode::Structural);
+    std::mem::swap(&mut document.blocks, &mut other.blocks);
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "other\n\n> text"
+    );
+    assert_eq!(
+        other.serialize(EquivalenceMode::Structural),
+        "first!\n\n> rewritten"
+    );
+}
+
 #[test]
 fn encoded_changes_share_immutable_payload_storage() {
     let mut sync = SyncState::new();
@@ -209,7 +264,8 @@ fn cached_state_vector_updates_on_every_applied_path() {
     sync.add_local_op(Operation {
         id: op(4, 2),
         payload: vec![2].into(),
-    });
+    })
+    .unwrap();
     assert_eq!(sync.state_vector().get(1), Some(2));
     assert_eq!(sync.state_vector().get(2), Some(4));
 
@@ -252,7 +308,8 @@ fn cached_state_vector_matches_independent_recompute() {
     sync.add_local_op(Operation {
         id: op(3, 1),
         payload: vec![1].into(),
-    });
+    })
+    .unwrap();
     sync.apply_op(Operation {
         id: op(5, 2),
         payload: vec![2].into(),
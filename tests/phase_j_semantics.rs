@@ -219,7 +219,7 @@ fn inline_mark_styles_and_remove_exchange() {
     a.set_mark(
         block_id,
         0..6,
-        MarkKind::Custom("annotation".into()),
+        MarkKind::custom("app", "annotation").unwrap(),
         BTreeMap::new(),
     )
     .unwrap();
@@ -0,0 +1,148 @@
+use md_crdt::{
+    BlockAcl, BlockDraft, BlockKind, CollaborativeDocument, EditError, EquivalenceMode, OpId,
+    SemanticConflict, SessionError, StructuredEditLimits, ValidationLimits, block_id_from_op,
+};
+
+fn deliver(
+    from: &CollaborativeDocument,
+    to: &mut CollaborativeDocument,
+) -> Result<md_crdt::SessionApplyResult, SessionError> {
+    let message = from.encode_changes_since(&to.state_vector()).unwrap();
+    to.apply_remote(message, &ValidationLimits::default())
+}
+
+#[test]
+fn an_enforcing_relay_rejects_edits_to_a_locked_block() {
+    let mut owner = CollaborativeDocument::new(1);
+    let header = block_id_from_op(owner.insert_paragraph(None, "Agenda").unwrap());
+    owner
+        .set_block_acl(header, Some(BlockAcl::locked([1])))
+        .unwrap();
+
+    let mut relay = CollaborativeDocument::new(9);
+    relay.set_acl_enforcement(true);
+    deliver(&owner, &mut relay).unwrap();
+    assert_eq!(
+        relay.document().block_acl(header),
+        Some(&BlockAcl::locked([1]))
+    );
+
+    let mut guest = CollaborativeDocument::new(2);
+    deliver(&owner, &mut guest).unwrap();
+    guest.insert_text(header, 6, "!").unwrap();
+    let before = relay.state_vector();
+    assert!(matches!(
+        deliver(&guest, &mut relay),
+        Err(SessionError::BlockLocked { block_id, peer: 2 }) if block_id == header
+    ));
+    assert_eq!(relay.state_vector(), before);
+
+    // The owner may unlock and edit in one message.
+    owner.set_block_acl(header, None).unwrap();
+    owner.insert_text(header, 6, ":").unwrap();
+    deliver(&owner, &mut relay).unwrap();
+    assert_eq!(
+        relay.document().serialize(EquivalenceMode::Structural),
+        "Agenda:"
+    );
+}
+
+#[test]
+fn local_edits_respect_acls_on_the_block_and_its_containers() {
+    let mut doc = CollaborativeDocument::new(2);
+    let quote_elem = doc
+        .insert_draft_in(
+            None,
+            None,
+            &BlockDraft::BlockQuote {
+                children: vec![BlockDraft::Paragraph {
+                    text: "template".into(),
+                }],
+            },
+            StructuredEditLimits::default(),
+        )
+        .unwrap();
+    let quote = block_id_from_op(quote_elem);
+    let child = match &doc.document().find_block_by_id(quote).unwrap().kind {
+        BlockKind::BlockQuote { children } => children.iter().next().unwrap().id,
+        _ => unreachable!(),
+    };
+    doc.set_block_acl(quote, Some(BlockAcl::owned_by([1])))
+        .unwrap();
+
+    // ACLs are stored but not enforced until enforcement is on.
+    doc.insert_text(child, 0, "a ").unwrap();
+    doc.set_acl_enforcement(true);
+    let before = doc.state_vector();
+    assert!(matches!(
+        doc.insert_text(child, 0, "b "),
+        Err(SessionError::BlockLocked { block_id, peer: 2 }) if block_id == child
+    ));
+    assert!(matches!(
+        doc.set_block_acl(quote, None),
+        Err(SessionError::BlockLocked { .. })
+    ));
+    assert_eq!(doc.state_vector(), before);
+
+    // The same rule holds for edits applied to a plain document.
+    let mut plain = CollaborativeDocument::new(1);
+    let block = block_id_from_op(plain.insert_paragraph(None, "fixed").unwrap());
+    plain
+        .set_block_acl(block, Some(BlockAcl::owned_by([1])))
+        .unwrap();
+    let mut document = plain.document().clone();
+    let op_id = OpId {
+        counter: 100,
+        peer: 3,
+    };
+    assert_eq!(
+        document.insert_text(block, 0, "c ", op_id),
+        Err(EditError::BlockLocked {
+            block_id: block,
+            peer: 3
+        })
+    );
+    let op_id = OpId {
+        counter: 100,
+        peer: 1,
+    };
+    assert!(document.insert_text(block, 0, "d ", op_id).is_ok());
+}
+
+#[test]
+fn concurrent_acl_changes_resolve_by_lww_and_report_a_conflict() {
+    let mut left = CollaborativeDocument::new(1);
+    let block = block_id_from_op(left.insert_paragraph(None, "notes").unwrap());
+    let mut right = CollaborativeDocument::new(2);
+    deliver(&left, &mut right).unwrap();
+
+    let left_op = left
+        .set_block_acl(block, Some(BlockAcl::locked([1])))
+        .unwrap();
+    let right_op = right
+        .set_block_acl(block, Some(BlockAcl::owned_by([2])))
+        .unwrap();
+    let (winner, loser) = (left_op.max(right_op), left_op.min(right_op));
+
+    let expected = vec![SemanticConflict::AclConflict {
+        block_id: block,
+        winner,
+        loser,
+    }];
+    assert_eq!(deliver(&left, &mut right).unwrap().conflicts, expected);
+    assert_eq!(deliver(&right, &mut left).unwrap().conflicts, expected);
+    assert_eq!(
+        left.document().block_acl(block),
+        right.document().block_acl(block)
+    );
+    assert_eq!(left.document().block_acl_op(block), Some(winner));
+
+    // A later write that observed the winner is not a conflict.
+    left.set_block_acl(block, None).unwrap();
+    assert!(deliver(&left, &mut right).unwrap().conflicts.is_empty());
+    assert_eq!(right.document().block_acl(block), None);
+
+    let restored =
+        CollaborativeDocument::restore_from_snapshot(right.save_snapshot().unwrap()).unwrap();
+    assert_eq!(restored.document(), right.document());
+}
@@ -0,0 +1,134 @@
+use md_crdt::core::OpId;
+use md_crdt::doc::{BlockKind, ColumnAlignment, ColumnDef, block_id_from_op};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::ValidationLimits;
+use md_crdt::{ChangeEntry, StateVector};
+
+fn exchange(source: &CollaborativeDocument, target: &mut CollaborativeDocument) {
+    let message = source.encode_changes_since(&target.state_vector()).unwrap();
+    target
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+fn heading(session: &mut CollaborativeDocument, text: &str) -> OpId {
+    let start = session.peek_next_id();
+    let elem = session
+        .insert_block(None, BlockKind::heading(1, "", start))
+        .unwrap();
+    session
+        .insert_text(block_id_from_op(elem), 0, text)
+        .unwrap();
+    elem
+}
+
+/// Peer 1 writes a heading and a one-row table; returns the table and row elems.
+fn seeded() -> (CollaborativeDocument, OpId, OpId, OpId) {
+    let mut session = CollaborativeDocument::new(1);
+    let title = heading(&mut session, "Heading X");
+    let table = session
+        .insert_table(
+            Some(title),
+            vec![ColumnDef {
+                alignment: ColumnAlignment::Left,
+            }],
+            vec!["name".into()],
+        )
+        .unwrap();
+    let row = session
+        .insert_table_row(block_id_from_op(table), None, vec!["value".into()])
+        .unwrap();
+    (session, title, table, row)
+}
+
+#[test]
+fn changelog_attributes_a_range_of_ops_to_each_peer() {
+    let (author, title, table, row) = seeded();
+    let mut editor = CollaborativeDocument::new(3);
+    exchange(&author, &mut editor);
+    let since = editor.state_vector();
+
+    editor
+        .insert_text(block_id_from_op(title), 9, " revised")
+        .unwrap();
+    let first = editor.insert_paragraph(Some(title), "intro").unwrap();
+    editor.insert_paragraph(Some(first), "more").unwrap();
+    editor
+        .delete_table_row(block_id_from_op(table), row)
+        .unwrap();
+
+    let log = editor.changelog(&since, &editor.state_vector()).unwrap();
+    assert_eq!(log.len(), 1);
+    let entry = &log[0];
+    assert_eq!(entry.peer, 3);
+    assert_eq!(entry.blocks_added, 2);
+    assert_eq!(entry.edited, ["Heading X revised"]);
+    assert_eq!(entry.table_rows_deleted, 1);
+    assert_eq!(
+        entry.summary,
+        "peer 3 added 2 blocks, edited 'Heading X revised', deleted a table row"
+    );
+
+    let json = serde_json::to_value(entry).unwrap();
+    assert_eq!(json["summary"], entry.summary);
+    let back: ChangeEntry = serde_json::from_value(json).unwrap();
+    assert_eq!(&back, entry);
+}
+
+#[test]
+fn changelog_is_bounded_by_both_frontiers() {
+    let (mut author, _, _, _) = seeded();
+    let middle = author.state_vector();
+    heading(&mut author, "Later");
+
+    let everything = author
+        .changelog(&StateVector::new(), &author.state_vector())
+        .unwrap();
+    assert_eq!(everything.len(), 1);
+    // Text and rows added to blocks the range inserted fold into the insert.
+    assert_eq!(everything[0].blocks_added, 3);
+    assert_eq!(everything[0].blocks_edited, 0);
+    assert_eq!(everything[0].summary, "peer 1 added 3 blocks");
+
+    let early = author.changelog(&StateVector::new(), &middle).unwrap();
+    assert_eq!(early[0].blocks_added, 2);
+    let late = author.changelog(&middle, &author.state_vector()).unwrap();
+    assert_eq!(late[0].summary, "peer 1 added a block");
+    assert!(author.changelog(&middle, &middle).unwrap().is_empty());
+}
+
+#[cfg(feature = "filesync")]
+#[test]
+#[allow(deprecated)]
+fn log_command_prints_json_entries() {
+    use assert_cmd::prelude::*;
+    use std::process::Command;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("note.md"), "# Title\n\nbody\n").unwrap();
+    Command::cargo_bin("md-crdt")
+        .unwrap()
+        .arg("ingest")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["log", "note.md", "--json"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["path"], "note.md");
+    let entries = json["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["blocks_added"], 2);
+    assert!(
+        entries[0]["summary"]
+            .as_str()
+            .unwrap()
+            .ends_with("added 2 blocks")
+    );
+}
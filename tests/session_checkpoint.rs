@@ -156,6 +156,7 @@ fn checkpoint_frontier_survives_snapshot_round_trip() {
     )
     .unwrap();
     assert_eq!(restored.state_vector(), source.state_vector());
+    assert_eq!(restored.handshake(), source.handshake());
     assert!(restored.encode_changes_since(&StateVector::new()).is_err());
     assert!(matches!(
         restored.sync_since(&StateVector::new()).unwrap(),
@@ -163,6 +164,34 @@ fn checkpoint_frontier_survives_snapshot_round_trip() {
     ));
 }
 
+#[test]
+fn handshake_advertises_the_frontier_floor() {
+    let mut sync = SyncState::new();
+    assert!(sync.handshake().can_serve(&StateVector::new()));
+    for counter in 1..=4 {
+        sync.apply_op(operation(counter));
+    }
+    sync.checkpoint(&CheckpointRequest {
+        max_retained_ops: 2,
+        active_peer_leases: Vec::new(),
+        tombstones: DocumentTombstonePolicy::KeepAll,
+    })
+    .unwrap();
+
+    let handshake = sync.handshake();
+    assert_eq!(handshake.state_vector, sync.state_vector());
+    assert_eq!(&handshake.frontier_floor, sync.delta_floor());
+    let mut stale = StateVector::new();
+    stale.set(1, 1);
+    assert!(!handshake.can_serve(&stale));
+    assert!(handshake.can_serve(&handshake.frontier_floor));
+    assert!(handshake.can_serve(&sync.state_vector()));
+
+    let error = sync.encode_changes_since(&stale).unwrap_err();
+    assert_eq!(error.delta_floor, handshake.frontier_floor);
+    assert!(error.to_string().contains("bootstrap from a snapshot"));
+}
+
 #[test]
 fn checkpoint_uses_the_minimum_acknowledgement_across_multiple_leases() {
     let mut sync = SyncState::new();
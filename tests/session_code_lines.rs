@@ -0,0 +1,127 @@
+use md_crdt::doc::CodeFenceStyle;
+use md_crdt::{
+    BlockDraft, CollaborativeDocument, EquivalenceMode, OpId, Parser, SessionError,
+    StructuredEditLimits, ValidationLimits, block_id_from_op,
+};
+
+fn deliver(from: &CollaborativeDocument, to: &mut CollaborativeDocument) {
+    let message = from.encode_changes_since(&to.state_vector()).unwrap();
+    to.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+fn code_fence(doc: &mut CollaborativeDocument, text: &str) -> md_crdt::BlockId {
+    let elem = doc
+        .insert_draft_in(
+            None,
+            None,
+            &BlockDraft::CodeFence {
+                style: CodeFenceStyle::default(),
+                info: Some("rust".into()),
+                text: text.into(),
+            },
+            StructuredEditLimits::default(),
+        )
+        .unwrap();
+    block_id_from_op(elem)
+}
+
+fn rendered(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn concurrent_edits_to_different_lines_both_survive() {
+    let mut a = CollaborativeDocument::new(1);
+    let fence = code_fence(&mut a, "fn main() {\n    old();\n}");
+    let mut b = CollaborativeDocument::new(2);
+    deliver(&a, &mut b);
+
+    a.insert_code_line(fence, 1, "    first();").unwrap();
+    b.delete_code_line(fence, 1).unwrap();
+    b.insert_code_line(fence, 1, "    second();").unwrap();
+
+    deliver(&a, &mut b);
+    deliver(&b, &mut a);
+    // Inserts at the same spot order by op id, the higher peer first.
+    let expected = "```rust\nfn main() {\n    second();\n    first();\n}\n```";
+    assert_eq!(rendered(&a), expected);
+    assert_eq!(rendered(&b), expected);
+    assert_eq!(a.document(), b.document());
+
+    let restored =
+        CollaborativeDocument::restore_from_snapshot(a.save_snapshot().unwrap()).unwrap();
+    assert_eq!(restored.document(), a.document());
+}
+
+#[test]
+fn a_whole_text_write_supersedes_concurrent_line_edits() {
+    let mut a = CollaborativeDocument::new(1);
+    let fence = code_fence(&mut a, "one\ntwo");
+    let mut b = CollaborativeDocument::new(2);
+    deliver(&a, &mut b);
+
+    a.insert_code_line(fence, 2, "three").unwrap();
+    b.set_code_fence(
+        fence,
+        CodeFenceStyle::default(),
+        Some("rust".into()),
+        "replaced".into(),
+    )
+    .unwrap();
+    deliver(&a, &mut b);
+    deliver(&b, &mut a);
+    assert_eq!(rendered(&a), "```rust\nreplaced\n```");
+    assert_eq!(a.document(), b.document());
+
+    // Line edits resume on top of the new text.
+    a.insert_code_line(fence, 0, "// header").unwrap();
+    deliver(&a, &mut b);
+    assert_eq!(rendered(&b), "```rust\n// header\nreplaced\n```");
+    assert_eq!(
+        a.document().code_line_ids(fence).unwrap().len(),
+        b.document().code_line_ids(fence).unwrap().len()
+    );
+}
+
+#[test]
+fn line_edits_are_validated() {
+    let mut doc = CollaborativeDocument::new(1);
+    let fence = code_fence(&mut doc, "x");
+    assert!(matches!(
+        doc.insert_code_line(fence, 2, "y"),
+        Err(SessionError::InvalidOffset)
+    ));
+    assert!(matches!(
+        doc.insert_code_line(fence, 0, "a\nb"),
+        Err(SessionError::InvalidCodeLine)
+    ));
+    assert!(matches!(
+        doc.insert_code_line(fence, 0, "```"),
+        Err(SessionError::StructuredEdit(_))
+    ));
+    let paragraph = block_id_from_op(doc.insert_paragraph(None, "text").unwrap());
+    assert!(matches!(
+        doc.delete_code_line(paragraph, 0),
+        Err(SessionError::NotCodeFence)
+    ));
+}
+
+#[test]
+fn document_line_edits_replay_on_another_replica() {
+    let mut left = Parser::parse("```\na\nb\n```\n");
+    let mut right = left.clone();
+    let fence = left.blocks.iter_asc().next().unwrap().id;
+    let op_id = |counter| OpId { counter, peer: 5 };
+
+    let delete = left.delete_code_line(fence, 0, op_id(100)).unwrap();
+    let insert = left.insert_code_line(fence, 1, "c", op_id(101)).unwrap();
+    right.raw_apply_op(delete, true).unwrap();
+    right.raw_apply_op(insert, true).unwrap();
+
+    assert_eq!(left, right);
+    assert_eq!(
+        right.serialize(EquivalenceMode::Structural),
+        "```\nb\nc\n```"
+    );
+}
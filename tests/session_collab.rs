@@ -122,6 +122,7 @@ fn out_of_order_remote_ops_buffer_then_apply() {
             ChangeMessage {
                 since: md_crdt::core::StateVector::new(),
                 ops: vec![op_hi.clone()],
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
@@ -136,6 +137,7 @@ fn out_of_order_remote_ops_buffer_then_apply() {
             ChangeMessage {
                 since: md_crdt::core::StateVector::new(),
                 ops: rest,
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
@@ -149,6 +151,7 @@ fn unknown_wire_version_rejected_without_mutation() {
     let mut b = CollaborativeDocument::new(2);
     let mut bad = Envelope {
         version: WIRE_VERSION + 9,
+        meta: None,
         body: OpBody::Doc(DocOp::DeleteBlock {
             parent: None,
             target: OpId {
@@ -172,6 +175,7 @@ fn unknown_wire_version_rejected_without_mutation() {
             },
             payload: payload.into(),
         }],
+        gaps: Vec::new(),
     };
     let err = b
         .apply_remote(msg, &ValidationLimits::default())
@@ -199,6 +203,7 @@ fn unit_mode_rejects_non_empty_paragraph_on_insert_block() {
 
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -229,6 +234,7 @@ fn unit_mode_rejects_non_empty_paragraph_on_insert_block() {
             },
             payload: payload.into(),
         }],
+        gaps: Vec::new(),
     };
     let err = b
         .apply_remote(msg, &ValidationLimits::default())
@@ -301,6 +307,7 @@ fn remote_operation_id_not_max_rejected() {
     };
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -322,6 +329,7 @@ fn remote_operation_id_not_max_rejected() {
             },
             payload: payload.into(),
         }],
+        gaps: Vec::new(),
     };
     let err = b
         .apply_remote(msg, &ValidationLimits::default())
@@ -344,6 +352,7 @@ fn remote_peer_mismatch_in_nested_child_rejected() {
     };
     let env = Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertBlock {
             parent: None,
             after: None,
@@ -374,6 +383,7 @@ fn remote_peer_mismatch_in_nested_child_rejected() {
             id: top,
             payload: payload.into(),
         }],
+        gaps: Vec::new(),
     };
     let err = b
         .apply_remote(msg, &ValidationLimits::default())
@@ -0,0 +1,142 @@
+//! Concurrent splits of one block, applied in every order.
+
+use md_crdt::OpId;
+use md_crdt::doc::{BlockKind, EquivalenceMode, paragraph_visible_ids, paragraph_visible_string};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::{ChangeMessage, ValidationLimits};
+
+fn exchange(from: &CollaborativeDocument, to: &mut CollaborativeDocument) {
+    let message = from.encode_changes_since(&to.state_vector()).unwrap();
+    to.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+/// Visible text and unit ids of every top-level block, in document order.
+fn blocks(doc: &CollaborativeDocument) -> Vec<(String, Vec<OpId>)> {
+    doc.document()
+        .blocks_in_order()
+        .into_iter()
+        .map(|block| match &block.kind {
+            BlockKind::Paragraph { text } => {
+                (paragraph_visible_string(text), paragraph_visible_ids(text))
+            }
+            _ => panic!("expected paragraphs only"),
+        })
+        .collect()
+}
+
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+    let mut all = Vec::new();
+    for rest in permutations(n - 1) {
+        for at in 0..=rest.len() {
+            let mut order = rest.clone();
+            order.insert(at, n - 1);
+            all.push(order);
+        }
+    }
+    all
+}
+
+/// Each peer makes its `splits` one after another on its own replica, each
+/// naming the index of the block to split in the peer's current document and
+/// a grapheme offset. Every arrival order of the peers' changes must settle
+/// on one document in which the text reads as before, split into one more
+/// block per split with every unit shown exactly once.
+fn settle(text: &str, splits: &[&[(usize, usize)]]) -> Vec<String> {
+    let mut base = CollaborativeDocument::new(1);
+    base.insert_paragraph(None, text).unwrap();
+    let (_, original) = blocks(&base).remove(0);
+
+    let messages: Vec<ChangeMessage> = splits
+        .iter()
+        .enumerate()
+        .map(|(index, edits)| {
+            let mut peer = CollaborativeDocument::new(10 + index as u64);
+            exchange(&base, &mut peer);
+            for &(nth, offset) in *edits {
+                let block = peer.document().blocks_in_order()[nth].id;
+                peer.split_block(block, offset).unwrap();
+            }
+            peer.encode_changes_since(&base.state_vector()).unwrap()
+        })
+        .collect();
+
+    let mut settled: Option<CollaborativeDocument> = None;
+    for order in permutations(messages.len()) {
+        let mut replica = CollaborativeDocument::new(99);
+        exchange(&base, &mut replica);
+        for &index in &order {
+            replica
+                .apply_remote(messages[index].clone(), &ValidationLimits::default())
+                .unwrap();
+        }
+
+        let shown = blocks(&replica);
+        let count: usize = splits.iter().map(|edits| edits.len()).sum();
+        assert_eq!(shown.len(), 1 + count, "order {order:?}");
+        let ids: Vec<OpId> = shown.iter().flat_map(|(_, ids)| ids.clone()).collect();
+        assert_eq!(ids, original, "order {order:?}");
+        let joined: String = shown.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(joined, text, "order {order:?}");
+
+        match &settled {
+            None => settled = Some(replica),
+            Some(first) => {
+                assert_eq!(replica.document(), first.document(), "order {order:?}");
+                assert_eq!(
+                    replica.document().serialize(EquivalenceMode::Structural),
+                    first.document().serialize(EquivalenceMode::Structural)
+                );
+            }
+        }
+    }
+    blocks(&settled.unwrap())
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+#[test]
+fn two_splits_at_the_same_offset_leave_one_empty_block() {
+    assert_eq!(settle("alpha", &[&[(0, 2)], &[(0, 2)]]), ["al", "", "pha"]);
+    assert_eq!(settle("alpha", &[&[(0, 0)], &[(0, 0)]]), ["", "", "alpha"]);
+    assert_eq!(settle("alpha", &[&[(0, 5)], &[(0, 5)]]), ["alpha", "", ""]);
+}
+
+#[test]
+fn two_splits_at_different_offsets_partition_the_text() {
+    for splits in [[(0, 2), (0, 4)], [(0, 4), (0, 2)]] {
+        let settled = settle("alpha", &[&splits[..1], &splits[1..]]);
+        assert!(
+            settled == ["al", "ph", "a"] || settled == ["al", "", "pha"],
+            "{settled:?}"
+        );
+    }
+}
+
+#[test]
+fn three_peers_splitting_converge_in_every_order() {
+    settle("concurrency", &[&[(0, 3)], &[(0, 3)], &[(0, 3)]]);
+    settle("concurrency", &[&[(0, 2)], &[(0, 6)], &[(0, 9)]]);
+    settle("concurrency", &[&[(0, 9)], &[(0, 6)], &[(0, 2)]]);
+    settle("concurrency", &[&[(0, 0)], &[(0, 11)], &[(0, 5)]]);
+}
+
+#[test]
+fn splits_of_a_split_block_settle_alongside_concurrent_ones() {
+    // The second split of each peer lands in the block its first one made.
+    settle("split me twice", &[&[(0, 6), (1, 3)], &[(0, 6)]]);
+    settle("split me twice", &[&[(0, 2), (1, 7)], &[(0, 4)], &[(0, 9)]]);
+    settle("split me twice", &[&[(0, 2), (1, 0)], &[(0, 2), (1, 0)]]);
+    // A second split of the original block, before the first.
+    settle("split me twice", &[&[(0, 9), (0, 3)], &[(0, 6)]]);
+}
+
+#[test]
+fn a_lone_split_is_untouched() {
+    assert_eq!(settle("alpha", &[&[(0, 2)]]), ["al", "pha"]);
+    assert_eq!(settle("alpha", &[&[]]), ["alpha"]);
+}
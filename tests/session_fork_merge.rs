@@ -0,0 +1,64 @@
+use md_crdt::doc::{EquivalenceMode, block_id_from_op};
+use md_crdt::session::{CollaborativeDocument, SessionError};
+
+fn markdown(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn forked_draft_merges_back_alongside_main_line_edits() {
+    let mut main = CollaborativeDocument::new(1);
+    let intro = main.insert_paragraph(None, "intro").unwrap();
+
+    let mut draft = main.fork(2).unwrap();
+    assert_eq!(draft.peer(), 2);
+    assert_eq!(draft.state_vector(), main.state_vector());
+    assert_eq!(markdown(&draft), "intro");
+
+    draft
+        .insert_text(block_id_from_op(intro), 5, " rewritten")
+        .unwrap();
+    draft.insert_paragraph(Some(intro), "new section").unwrap();
+    main.insert_text(block_id_from_op(intro), 0, "An ").unwrap();
+    assert_eq!(markdown(&main), "An intro");
+
+    let merged = main.merge_from(&draft).unwrap();
+    assert!(merged.buffered.is_empty());
+    assert_eq!(main.state_vector().get(2), draft.state_vector().get(2));
+    draft.merge_from(&main).unwrap();
+
+    assert_eq!(markdown(&main), "An intro rewritten\n\nnew section");
+    assert_eq!(markdown(&main), markdown(&draft));
+    assert_eq!(main.state_vector(), draft.state_vector());
+
+    // Merging again imports nothing.
+    assert!(main.merge_from(&draft).unwrap().applied.is_empty());
+}
+
+#[test]
+fn merge_imports_edits_from_peers_the_target_never_saw() {
+    let mut main = CollaborativeDocument::new(1);
+    let block = main.insert_paragraph(None, "base").unwrap();
+    let mut draft = main.fork(2).unwrap();
+    let mut nested = draft.fork(3).unwrap();
+    nested
+        .insert_text(block_id_from_op(block), 4, " from three")
+        .unwrap();
+    draft.merge_from(&nested).unwrap();
+
+    main.merge_from(&draft).unwrap();
+    assert_eq!(markdown(&main), "base from three");
+    assert!(main.state_vector().get(3).is_some());
+}
+
+#[test]
+fn fork_refuses_a_peer_already_in_the_history() {
+    let mut main = CollaborativeDocument::new(1);
+    main.insert_paragraph(None, "x").unwrap();
+    let mut other = main.fork(2).unwrap();
+    other.insert_paragraph(None, "y").unwrap();
+    main.merge_from(&other).unwrap();
+
+    assert!(matches!(main.fork(1), Err(SessionError::PeerInUse(1))));
+    assert!(matches!(main.fork(2), Err(SessionError::PeerInUse(2))));
+}
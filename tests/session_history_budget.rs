@@ -0,0 +1,73 @@
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, HistoryBudget, StateVector, SyncResponse,
+    ValidationLimits, block_id_from_op,
+};
+
+fn serialize(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn history_budget_caps_the_log_and_raises_the_floor() {
+    let mut doc = CollaborativeDocument::new(1);
+    doc.set_history_budget(Some(HistoryBudget::ops(3)));
+    let block = block_id_from_op(doc.insert_paragraph(None, "alpha").unwrap());
+    for word in [" beta", " gamma", " delta", " epsilon"] {
+        let end = serialize(&doc).chars().count();
+        doc.insert_text(block, end, word).unwrap();
+    }
+
+    let snapshot = doc.save_snapshot().unwrap();
+    assert_eq!(snapshot.ops.len(), 3);
+    assert!(doc.handshake().frontier_floor.get(1).is_some());
+    assert_eq!(serialize(&doc), "alpha beta gamma delta epsilon");
+
+    // A peer that never synced must rebase, then deltas flow again.
+    let checkpoint = match doc.sync_since(&StateVector::new()).unwrap() {
+        SyncResponse::Rebase { checkpoint } => checkpoint,
+        SyncResponse::Delta(_) => panic!("history below the floor was folded away"),
+    };
+    let mut peer = CollaborativeDocument::rebase_from_snapshot(*checkpoint, 2).unwrap();
+    doc.insert_text(block, 0, ">").unwrap();
+    let delta = match doc.sync_since(&peer.state_vector()).unwrap() {
+        SyncResponse::Delta(delta) => delta,
+        SyncResponse::Rebase { .. } => panic!("rebased peer should accept deltas"),
+    };
+    peer.apply_remote(delta, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(serialize(&peer), serialize(&doc));
+}
+
+#[test]
+fn byte_budget_drops_unanchored_text_tombstones() {
+    let mut doc = CollaborativeDocument::new(1);
+    let block = block_id_from_op(doc.insert_paragraph(None, "keep this, drop that").unwrap());
+    doc.delete_text(block, 9, 11).unwrap();
+    assert_eq!(serialize(&doc), "keep this");
+    let before = doc.document().stats().elements.tombstones;
+    assert_eq!(before, 11);
+
+    doc.set_history_budget(Some(HistoryBudget::bytes(0)));
+    let report = doc.enforce_history_budget();
+    assert_eq!(report, None, "already folded when the budget was set");
+    assert_eq!(doc.document().stats().elements.tombstones, 0);
+    assert_eq!(serialize(&doc), "keep this");
+
+    doc.insert_text(block, 9, "!").unwrap();
+    assert_eq!(serialize(&doc), "keep this!");
+    assert_eq!(doc.save_snapshot().unwrap().ops.len(), 1);
+}
+
+#[test]
+fn no_budget_keeps_full_history() {
+    let mut doc = CollaborativeDocument::new(1);
+    let block = block_id_from_op(doc.insert_paragraph(None, "one").unwrap());
+    doc.insert_text(block, 3, " two").unwrap();
+    assert_eq!(doc.history_budget(), None);
+    assert_eq!(doc.enforce_history_budget(), None);
+    assert!(doc.handshake().frontier_floor.is_empty());
+    assert!(matches!(
+        doc.sync_since(&StateVector::new()).unwrap(),
+        SyncResponse::Delta(_)
+    ));
+}
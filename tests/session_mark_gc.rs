@@ -0,0 +1,110 @@
+use md_crdt::{
+    CheckpointRequest, CollaborativeDocument, DocumentTombstonePolicy, EquivalenceMode, MarkKind,
+    PeerLease, ValidationLimits, block_id_from_op,
+};
+use std::collections::BTreeMap;
+
+fn deliver(from: &CollaborativeDocument, to: &mut CollaborativeDocument) {
+    let message = from.encode_changes_since(&to.state_vector()).unwrap();
+    to.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+fn collect(doc: &mut CollaborativeDocument, leases: &[&CollaborativeDocument]) -> usize {
+    doc.checkpoint_history(&CheckpointRequest {
+        max_retained_ops: usize::MAX,
+        active_peer_leases: leases
+            .iter()
+            .map(|peer| PeerLease {
+                peer: peer.peer(),
+                acknowledged: peer.state_vector(),
+            })
+            .collect(),
+        tombstones: DocumentTombstonePolicy::CollectRemovedMarks,
+    })
+    .unwrap()
+    .collected_marks
+}
+
+#[test]
+fn removed_marks_are_collected_once_every_lease_observed_the_removal() {
+    let mut a = CollaborativeDocument::new(1);
+    let elem = a.insert_paragraph(None, "hello world").unwrap();
+    let block_id = block_id_from_op(elem);
+    let bold = a
+        .set_mark(block_id, 0..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    a.set_mark(block_id, 6..11, MarkKind::Italic, BTreeMap::new())
+        .unwrap();
+    let mut b = CollaborativeDocument::new(2);
+    deliver(&a, &mut b);
+
+    a.remove_mark(block_id, bold).unwrap();
+    let rendered = a.document().serialize(EquivalenceMode::Structural);
+
+    // `b` has not seen the removal yet.
+    assert_eq!(collect(&mut a, &[&b]), 0);
+    assert_eq!(a.document().stats().removed_marks, 1);
+
+    deliver(&a, &mut b);
+    assert_eq!(collect(&mut a, &[&b]), 1);
+    let stats = a.document().stats();
+    assert_eq!((stats.active_marks, stats.removed_marks), (1, 0));
+    assert_eq!(
+        a.document().serialize(EquivalenceMode::Structural),
+        rendered
+    );
+
+    // Keep-all checkpoints leave mark history alone.
+    deliver(&a, &mut b);
+    b.checkpoint_history(&CheckpointRequest {
+        max_retained_ops: usize::MAX,
+        active_peer_leases: Vec::new(),
+        tombstones: DocumentTombstonePolicy::KeepAll,
+    })
+    .unwrap();
+    assert_eq!(b.document().stats().removed_marks, 1);
+}
+
+#[test]
+fn late_concurrent_ops_converge_after_collection() {
+    let mut a = CollaborativeDocument::new(1);
+    let elem = a.insert_paragraph(None, "hello world").unwrap();
+    let block_id = block_id_from_op(elem);
+    let bold = a
+        .set_mark(block_id, 0..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    let mut b = CollaborativeDocument::new(2);
+    let mut c = CollaborativeDocument::new(3);
+    deliver(&a, &mut b);
+    deliver(&a, &mut c);
+
+    // `c` removes the same mark and types inside it, but its ops arrive late.
+    c.remove_mark(block_id, bold).unwrap();
+    c.insert_text(block_id, 2, "y").unwrap();
+
+    a.remove_mark(block_id, bold).unwrap();
+    deliver(&a, &mut b);
+    deliver(&a, &mut c);
+    assert_eq!(collect(&mut a, &[&b, &c]), 1);
+    assert_eq!(a.document().stats().removed_marks, 0);
+
+    deliver(&c, &mut a);
+    deliver(&c, &mut b);
+    deliver(&a, &mut c);
+    let expected = "heyllo world";
+    for doc in [&a, &b, &c] {
+        assert_eq!(
+            doc.document().serialize(EquivalenceMode::Structural),
+            expected
+        );
+    }
+    assert!(a.document().structurally_equal(b.document()));
+
+    // `b` collects the same interval later, from both removals.
+    assert_eq!(collect(&mut b, &[&a, &c]), 1);
+    assert_eq!(
+        a.document().serialize(EquivalenceMode::Structural),
+        b.document().serialize(EquivalenceMode::Structural)
+    );
+}
@@ -0,0 +1,138 @@
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, MarkAttrType, MarkKind, MarkSchema, MarkSchemaError,
+    MarkValue, Parser, SchemaMode, SessionError, StateVector, ValidationLimits, block_id_from_op,
+};
+use std::collections::BTreeMap;
+
+fn attrs(pairs: &[(&str, MarkValue)]) -> BTreeMap<String, MarkValue> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect()
+}
+
+#[test]
+fn strict_and_lenient_schemas_differ_only_on_undeclared_attributes() {
+    let valid = attrs(&[("href", MarkValue::String("https://example.com".into()))]);
+    for mode in [SchemaMode::Strict, SchemaMode::Lenient] {
+        let schema = MarkSchema::markdown(mode);
+        assert_eq!(schema.validate(&MarkKind::Link, &valid), Ok(()));
+        assert_eq!(
+            schema.validate(&MarkKind::Link, &attrs(&[])),
+            Err(MarkSchemaError::MissingAttribute {
+                kind: MarkKind::Link,
+                key: "href".into(),
+            })
+        );
+        assert_eq!(
+            schema.validate(&MarkKind::Link, &attrs(&[("href", MarkValue::Bool(true))])),
+            Err(MarkSchemaError::WrongType {
+                kind: MarkKind::Link,
+                key: "href".into(),
+                expected: MarkAttrType::String,
+            })
+        );
+    }
+
+    let mut link = valid;
+    link.insert("rel".into(), MarkValue::String("nofollow".into()));
+    assert!(
+        MarkSchema::markdown(SchemaMode::Lenient)
+            .validate(&MarkKind::Link, &link)
+            .is_ok()
+    );
+    assert_eq!(
+        MarkSchema::markdown(SchemaMode::Strict).validate(&MarkKind::Link, &link),
+        Err(MarkSchemaError::UndeclaredAttribute {
+            kind: MarkKind::Link,
+            key: "rel".into(),
+        })
+    );
+
+    let custom = MarkKind::custom("app", "comment").unwrap();
+    let schema =
+        MarkSchema::new(SchemaMode::Strict).allow(custom.clone(), "resolved", MarkAttrType::Bool);
+    assert!(
+        schema
+            .validate(&custom, &attrs(&[("resolved", MarkValue::Bool(false))]))
+            .is_ok()
+    );
+    assert!(
+        schema
+            .validate(
+                &MarkKind::Bold,
+                &attrs(&[("resolved", MarkValue::Bool(false))])
+            )
+            .is_err()
+    );
+}
+
+#[test]
+fn local_marks_are_checked_against_the_session_schema() {
+    let mut doc = CollaborativeDocument::new(1);
+    let elem = doc.insert_paragraph(None, "see docs").unwrap();
+    let block_id = block_id_from_op(elem);
+    doc.set_mark_schema(Some(MarkSchema::markdown(SchemaMode::Strict)));
+
+    let before = doc.state_vector();
+    assert!(matches!(
+        doc.set_mark(block_id, 4..8, MarkKind::Link, BTreeMap::new()),
+        Err(SessionError::InvalidMark(
+            MarkSchemaError::MissingAttribute { .. }
+        ))
+    ));
+    assert_eq!(doc.state_vector(), before);
+
+    let href = attrs(&[("href", MarkValue::String("/docs".into()))]);
+    let interval_id = doc.set_mark(block_id, 4..8, MarkKind::Link, href).unwrap();
+    let block = doc.document().find_block_by_id(block_id).unwrap();
+    let interval = block.marks.interval(&interval_id).unwrap();
+    assert_eq!(interval.href(), Some("/docs"));
+    assert_eq!(interval.bool_attr("href"), None);
+    assert_eq!(
+        doc.document().serialize(EquivalenceMode::Structural),
+        "see [docs](/docs)"
+    );
+}
+
+#[test]
+fn remote_marks_are_caught_before_serializing() {
+    let mut sender = CollaborativeDocument::new(1);
+    let elem = sender.insert_paragraph(None, "broken link").unwrap();
+    let block_id = block_id_from_op(elem);
+    let interval_id = sender
+        .set_mark(block_id, 0..6, MarkKind::Link, BTreeMap::new())
+        .unwrap();
+
+    let mut receiver = CollaborativeDocument::new(2);
+    receiver.set_mark_schema(Some(MarkSchema::markdown(SchemaMode::Lenient)));
+    receiver
+        .apply_remote(
+            sender.encode_changes_since(&StateVector::new()).unwrap(),
+            &ValidationLimits::default(),
+        )
+        .unwrap();
+
+    let schema = receiver.mark_schema().unwrap();
+    let invalid = receiver.document().validate_marks(schema).unwrap_err();
+    assert_eq!(invalid.block_id, block_id);
+    assert_eq!(invalid.interval_id, interval_id);
+    assert!(matches!(
+        invalid.error,
+        MarkSchemaError::MissingAttribute { .. }
+    ));
+    assert_eq!(
+        receiver
+            .document()
+            .serialize_validated(EquivalenceMode::Structural, schema),
+        Err(invalid)
+    );
+
+    // Everything the parser produces satisfies the strict Markdown schema.
+    let parsed = Parser::parse("> **bold** and [link](x)\n\n- *item* `code` ~~gone~~\n");
+    assert!(
+        parsed
+            .validate_marks(&MarkSchema::markdown(SchemaMode::Strict))
+            .is_ok()
+    );
+}
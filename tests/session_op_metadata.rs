@@ -0,0 +1,121 @@
+use md_crdt::codec::{Envelope, JsonOpCodec, OpCodec};
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, MetadataStamp, MetadataVerifier, SessionError,
+    StateVector, SyncResponse, ValidationLimits, block_id_from_op,
+};
+
+fn delta(from: &CollaborativeDocument, to: &CollaborativeDocument) -> md_crdt::ChangeMessage {
+    match from.sync_since(&to.state_vector()).unwrap() {
+        SyncResponse::Delta(delta) => delta,
+        SyncResponse::Rebase { .. } => panic!("no checkpoint taken"),
+    }
+}
+
+/// A toy signature: the byte sum of the signed bytes, keyed.
+fn toy_sign(key: u8) -> impl Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static {
+    move |bytes| vec![bytes.iter().fold(key, |sum, byte| sum.wrapping_add(*byte))]
+}
+
+#[test]
+fn metadata_travels_through_sync_and_snapshots() {
+    let mut a = CollaborativeDocument::new(1);
+    a.set_metadata_stamp(Some(
+        MetadataStamp::new()
+            .author("Ada")
+            .client_version("editor/1.2"),
+    ));
+    let block = a.insert_paragraph(None, "hello").unwrap();
+
+    let meta = a.op_metadata(block).unwrap().expect("stamped");
+    assert_eq!(meta.author.as_deref(), Some("Ada"));
+    assert_eq!(meta.client_version.as_deref(), Some("editor/1.2"));
+    assert!(meta.timestamp_ms > 0);
+    assert_eq!(meta.signature, None);
+
+    let mut b = CollaborativeDocument::new(2);
+    b.apply_remote(delta(&a, &b), &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(b.op_metadata(block).unwrap(), Some(meta.clone()));
+
+    let restored =
+        CollaborativeDocument::restore_from_snapshot(b.save_snapshot().unwrap()).unwrap();
+    assert_eq!(restored.op_metadata(block).unwrap(), Some(meta.clone()));
+
+    let entries = b.changelog(&StateVector::new(), &b.state_vector()).unwrap();
+    assert_eq!(entries[0].authors, vec!["Ada".to_string()]);
+    assert!(
+        entries[0]
+            .first_timestamp_ms
+            .is_some_and(|t| t >= meta.timestamp_ms)
+    );
+    assert!(entries[0].first_timestamp_ms <= entries[0].last_timestamp_ms);
+}
+
+#[test]
+fn unstamped_operations_encode_without_metadata() {
+    let mut a = CollaborativeDocument::new(1);
+    let block = a.insert_paragraph(None, "plain").unwrap();
+    assert_eq!(a.op_metadata(block).unwrap(), None);
+    let ops = delta(&a, &CollaborativeDocument::new(2)).ops;
+    assert!(
+        ops.iter()
+            .all(|op| !String::from_utf8_lossy(&op.payload).contains("meta"))
+    );
+}
+
+#[test]
+fn metadata_does_not_affect_convergence() {
+    let mut stamped = CollaborativeDocument::new(1);
+    stamped.set_metadata_stamp(Some(MetadataStamp::new().author("Ada")));
+    let mut plain = CollaborativeDocument::new(2);
+    let block = block_id_from_op(stamped.insert_paragraph(None, "shared").unwrap());
+    plain
+        .apply_remote(delta(&stamped, &plain), &ValidationLimits::default())
+        .unwrap();
+    stamped.insert_text(block, 6, " text").unwrap();
+    plain.insert_text(block, 0, "a ").unwrap();
+
+    let to_plain = delta(&stamped, &plain);
+    let to_stamped = delta(&plain, &stamped);
+    plain
+        .apply_remote(to_plain, &ValidationLimits::default())
+        .unwrap();
+    stamped
+        .apply_remote(to_stamped, &ValidationLimits::default())
+        .unwrap();
+    let render =
+        |doc: &CollaborativeDocument| doc.document().serialize(EquivalenceMode::Structural);
+    assert_eq!(render(&stamped), render(&plain));
+    assert_eq!(render(&plain), "a shared text");
+}
+
+#[test]
+fn verifier_rejects_forged_metadata() {
+    let mut signed = CollaborativeDocument::new(1);
+    signed.set_metadata_stamp(Some(MetadataStamp::new().author("Ada").signer(toy_sign(7))));
+    let block = signed.insert_paragraph(None, "signed").unwrap();
+    let meta = signed.op_metadata(block).unwrap().unwrap();
+    assert_eq!(meta.signature.as_ref().map(Vec::len), Some(1));
+
+    let verifier = MetadataVerifier::new(|bytes, signature| toy_sign(7)(bytes) == signature);
+    let mut checked = CollaborativeDocument::new(2);
+    checked.set_metadata_verifier(Some(verifier.clone()));
+    checked
+        .apply_remote(delta(&signed, &checked), &ValidationLimits::default())
+        .unwrap();
+
+    // Relabel the author without re-signing.
+    let mut forged = delta(&signed, &CollaborativeDocument::new(3));
+    let op = &mut forged.ops[0];
+    let mut envelope: Envelope = JsonOpCodec.decode(&op.payload).unwrap();
+    envelope.meta.as_mut().unwrap().author = Some("Mallory".into());
+    op.payload = JsonOpCodec.encode(&envelope).unwrap().into();
+    let mut strict = CollaborativeDocument::new(3);
+    strict.set_metadata_verifier(Some(verifier));
+    let id = op.id;
+    assert!(matches!(
+        strict.apply_remote(forged, &ValidationLimits::default()),
+        Err(SessionError::MetadataSignature(rejected)) if rejected == id
+    ));
+    assert!(strict.document().blocks_in_order().is_empty());
+}
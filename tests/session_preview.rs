@@ -0,0 +1,98 @@
+use md_crdt::core::mark::MarkKind;
+use md_crdt::doc::{EquivalenceMode, block_id_from_op};
+use md_crdt::session::{CollaborativeDocument, TextChange};
+use md_crdt::sync::ValidationLimits;
+use std::collections::BTreeMap;
+
+fn exchange(source: &CollaborativeDocument, target: &mut CollaborativeDocument) {
+    let message = source.encode_changes_since(&target.state_vector()).unwrap();
+    target
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+#[test]
+fn preview_reports_incoming_changes_without_applying_them() {
+    let mut local = CollaborativeDocument::new(1);
+    let kept = block_id_from_op(local.insert_paragraph(None, "hello world").unwrap());
+    let dropped_elem = local.insert_paragraph(None, "obsolete").unwrap();
+    let mut remote = CollaborativeDocument::new(2);
+    exchange(&local, &mut remote);
+
+    remote.insert_text(kept, 6, "brave ").unwrap();
+    let mark = remote
+        .set_mark(kept, 0..5, MarkKind::Bold, BTreeMap::new())
+        .unwrap();
+    remote.delete_block(dropped_elem).unwrap();
+    let added = block_id_from_op(remote.insert_paragraph(None, "new").unwrap());
+    remote
+        .set_frontmatter_field("status", Some("draft".into()))
+        .unwrap();
+
+    let before = local.document().serialize(EquivalenceMode::Exact);
+    let vector = local.state_vector();
+    let message = remote.encode_changes_since(&vector).unwrap();
+    let preview = local
+        .preview_changes(&message, &ValidationLimits::default())
+        .unwrap();
+
+    assert_eq!(local.document().serialize(EquivalenceMode::Exact), before);
+    assert_eq!(local.state_vector(), vector);
+    assert_eq!(preview.applied.len(), message.ops.len());
+    assert!(preview.buffered.is_empty());
+    assert_eq!(preview.blocks_added, vec![added]);
+    assert_eq!(preview.blocks_removed, vec![block_id_from_op(dropped_elem)]);
+    assert_eq!(preview.blocks_modified, vec![kept]);
+    assert_eq!(
+        preview.text_changes,
+        vec![TextChange {
+            block_id: kept,
+            range: 6..6,
+            replacement: "brave ".into(),
+        }]
+    );
+    assert_eq!(preview.mark_changes.len(), 1);
+    assert_eq!(preview.mark_changes[0].added, vec![mark]);
+    assert!(preview.frontmatter_changed);
+    assert!(!preview.is_empty());
+    assert!(preview.summary().contains("added a block"));
+
+    local
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(
+        local.document().serialize(EquivalenceMode::Exact),
+        remote.document().serialize(EquivalenceMode::Exact)
+    );
+}
+
+#[test]
+fn preview_of_known_or_gapped_ops_changes_nothing() {
+    let mut first = CollaborativeDocument::new(1);
+    first.insert_paragraph(None, "one").unwrap();
+    let mut second = CollaborativeDocument::new(2);
+    exchange(&first, &mut second);
+
+    let repeat = first.encode_changes_since(&Default::default()).unwrap();
+    let preview = second
+        .preview_changes(&repeat, &ValidationLimits::default())
+        .unwrap();
+    assert!(preview.is_empty());
+    assert_eq!(preview.summary(), "0 ops: no visible change");
+
+    let since = first.state_vector();
+    first.insert_paragraph(None, "two").unwrap();
+    let after_gap = first.state_vector();
+    first.insert_paragraph(None, "three").unwrap();
+    let mut message = first.encode_changes_since(&after_gap).unwrap();
+    message
+        .ops
+        .retain(|op| op.id.counter > after_gap.get(1).unwrap());
+    let gapped = second
+        .preview_changes(&message, &ValidationLimits::default())
+        .unwrap();
+    assert!(gapped.applied.is_empty());
+    assert_eq!(gapped.buffered.len(), message.ops.len());
+    assert!(gapped.is_empty());
+    assert_eq!(second.state_vector(), since);
+}
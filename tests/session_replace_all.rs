@@ -0,0 +1,110 @@
+//! Clearing or replacing a whole document with one range delete.
+
+use md_crdt::codec::DocOp;
+use md_crdt::core::{OpId, PeerClock};
+use md_crdt::doc::{EquivalenceMode, Parser};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::{ValidationLimits, block_id_from_op};
+
+fn markdown(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Structural)
+}
+
+#[test]
+fn document_replace_all_clears_with_one_range_and_keeps_ids_in_the_reservation() {
+    let mut document = Parser::parse_with_ids("# Old\n\nfirst\n\nsecond\n", 1, &mut 1);
+    let clock = PeerClock::starting_at(2, 1);
+    let mut ids = clock.reserve(100).unwrap();
+
+    let replaced = document
+        .replace_all("# New\n\n- a\n- b\n", &mut ids)
+        .unwrap();
+    let cleared = replaced.cleared.unwrap();
+    assert_eq!(
+        cleared.id,
+        OpId {
+            counter: 1,
+            peer: 2
+        }
+    );
+    assert_eq!(replaced.inserted.len(), 2);
+    assert!(replaced.inserted.iter().all(|id| id.peer == 2));
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "# New\n\n- a\n- b"
+    );
+    assert_eq!(document.blocks().iter_all().count(), 5);
+    assert!(ids.start() > replaced.inserted[1].counter);
+
+    let mut tiny = clock.reserve(1).unwrap();
+    assert!(document.replace_all("lost", &mut tiny).is_err());
+    assert_eq!(tiny.remaining(), 1);
+}
+
+#[test]
+fn replacing_everything_ships_one_delete_for_the_old_blocks() {
+    let mut local = CollaborativeDocument::new(1);
+    let first = local.insert_paragraph(None, "one").unwrap();
+    let second = local.insert_paragraph(Some(first), "two").unwrap();
+    local.insert_paragraph(Some(second), "three").unwrap();
+    let mut remote = local.fork(2).unwrap();
+    let before = local.state_vector();
+
+    let replaced = local
+        .replace_all("# Pasted\n\n> quoted\n\n| a | b |\n| - | - |\n| 1 | 2 |\n")
+        .unwrap();
+    assert_eq!(replaced.inserted.len(), 3);
+    assert_eq!(
+        markdown(&local),
+        "# Pasted\n\n> quoted\n\n| a | b |\n| - | - |\n| 1 | 2 |"
+    );
+
+    let message = local.encode_changes_since(&before).unwrap();
+    let deletes = message
+        .ops
+        .iter()
+        .filter(|op| {
+            let envelope: md_crdt::codec::Envelope = serde_json::from_slice(&op.payload).unwrap();
+            let md_crdt::codec::OpBody::Doc(body) = envelope.body;
+            matches!(
+                body,
+                DocOp::DeleteBlock { .. }
+                    | DocOp::DeleteBlockById { .. }
+                    | DocOp::DeleteBlockRange { .. }
+            )
+        })
+        .count();
+    assert_eq!(deletes, 1);
+
+    remote
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(markdown(&remote), markdown(&local));
+    let changes = local.changelog(&before, &local.state_vector()).unwrap();
+    assert_eq!(changes[0].blocks_deleted, 3);
+}
+
+#[test]
+fn a_concurrent_insert_survives_the_clear() {
+    let mut local = CollaborativeDocument::new(1);
+    let first = local.insert_paragraph(None, "old").unwrap();
+    let mut remote = local.fork(2).unwrap();
+    remote.insert_paragraph(Some(first), "concurrent").unwrap();
+
+    assert!(local.delete_all_blocks_in(None).unwrap().is_some());
+    let next = local.peek_next_id();
+    assert!(local.delete_all_blocks_in(None).unwrap().is_none());
+    assert_eq!(local.peek_next_id(), next);
+    assert_eq!(markdown(&local), "");
+
+    local.merge_from(&remote).unwrap();
+    remote.merge_from(&local).unwrap();
+    assert_eq!(markdown(&local), "concurrent");
+    assert_eq!(markdown(&remote), "concurrent");
+    assert!(
+        local
+            .document()
+            .find_block_by_id(block_id_from_op(first))
+            .is_none()
+    );
+}
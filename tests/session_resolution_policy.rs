@@ -0,0 +1,204 @@
+use md_crdt::doc::{
+    BlockKind, ColumnAlignment, ColumnDef, RegisterKey, ResolutionPolicy, ResolutionTarget,
+    block_id_from_op,
+};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::{SemanticConflict, ValidationLimits};
+
+fn exchange(source: &CollaborativeDocument, target: &mut CollaborativeDocument) {
+    let message = source.encode_changes_since(&target.state_vector()).unwrap();
+    target
+        .apply_remote(message, &ValidationLimits::default())
+        .expect("apply remote changes");
+}
+
+fn status(session: &CollaborativeDocument) -> Option<String> {
+    session
+        .document()
+        .frontmatter
+        .as_ref()
+        .and_then(|frontmatter| frontmatter.get("status"))
+        .map(str::to_string)
+}
+
+/// Two replicas that both wrote `status` without seeing the other's write.
+fn concurrent_status(policy: ResolutionPolicy) -> (CollaborativeDocument, CollaborativeDocument) {
+    let mut first = CollaborativeDocument::new(1);
+    let mut second = CollaborativeDocument::new(2);
+    first
+        .set_frontmatter_field("status", Some("draft".into()))
+        .unwrap();
+    exchange(&first, &mut second);
+    let target = ResolutionTarget::FrontmatterField("status".into());
+    first.set_resolution_policy(target.clone(), policy);
+    second.set_resolution_policy(target, policy);
+
+    first
+        .set_frontmatter_field("status", Some("review".into()))
+        .unwrap();
+    second
+        .set_frontmatter_field("status", Some("done".into()))
+        .unwrap();
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+    (first, second)
+}
+
+#[test]
+fn manual_policy_lists_both_values_until_resolved() {
+    let (mut first, mut second) = concurrent_status(ResolutionPolicy::Manual);
+    let key = RegisterKey::FrontmatterField("status".into());
+    for session in [&first, &second] {
+        let conflicts = session.register_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, key);
+        let mut values: Vec<_> = conflicts[0]
+            .writes
+            .iter()
+            .map(|write| write.value.as_deref())
+            .collect();
+        values.sort();
+        assert_eq!(values, vec![Some("done"), Some("review")]);
+    }
+    assert_eq!(status(&first), status(&second));
+
+    first
+        .resolve_register(&key, Some("shipped".into()))
+        .unwrap();
+    exchange(&first, &mut second);
+    assert!(first.register_conflicts().is_empty());
+    assert!(second.register_conflicts().is_empty());
+    assert_eq!(status(&second).as_deref(), Some("shipped"));
+
+    second.set_resolution_policy(
+        ResolutionTarget::FrontmatterField("status".into()),
+        ResolutionPolicy::Lww,
+    );
+    assert_eq!(
+        second.resolution_policy(&ResolutionTarget::FrontmatterField("status".into())),
+        ResolutionPolicy::Lww
+    );
+}
+
+#[test]
+fn prefer_local_and_prefer_remote_pick_by_origin() {
+    let (first, second) = concurrent_status(ResolutionPolicy::PreferLocal);
+    assert_eq!(status(&first).as_deref(), Some("review"));
+    assert_eq!(status(&second).as_deref(), Some("done"));
+    assert!(first.register_conflicts().is_empty());
+
+    let (first, second) = concurrent_status(ResolutionPolicy::PreferRemote);
+    assert_eq!(status(&first).as_deref(), Some("done"));
+    assert_eq!(status(&second).as_deref(), Some("review"));
+}
+
+#[test]
+fn remote_apply_reports_the_register_conflict() {
+    let mut first = CollaborativeDocument::new(1);
+    let mut second = CollaborativeDocument::new(2);
+    let target = ResolutionTarget::FrontmatterField("status".into());
+    first.set_resolution_policy(target.clone(), ResolutionPolicy::PreferLocal);
+    second.set_resolution_policy(target, ResolutionPolicy::PreferLocal);
+    let local = first
+        .set_frontmatter_field("status", Some("mine".into()))
+        .unwrap();
+    let remote = second
+        .set_frontmatter_field("status", Some("theirs".into()))
+        .unwrap();
+
+    let message = second.encode_changes_since(&first.state_vector()).unwrap();
+    let result = first
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(
+        result.conflicts,
+        vec![SemanticConflict::RegisterConflict {
+            key: RegisterKey::FrontmatterField("status".into()),
+            policy: ResolutionPolicy::PreferLocal,
+            writes: vec![local, remote],
+            shown: local,
+        }]
+    );
+    assert_eq!(status(&first).as_deref(), Some("mine"));
+}
+
+#[test]
+fn table_header_policy_applies_to_every_header_cell() {
+    let mut first = CollaborativeDocument::new(1);
+    let table_elem = first
+        .insert_table(
+            None,
+            vec![ColumnDef {
+                alignment: ColumnAlignment::Left,
+            }],
+            vec!["name".into()],
+        )
+        .unwrap();
+    let table_id = block_id_from_op(table_elem);
+    let mut second = CollaborativeDocument::new(2);
+    exchange(&first, &mut second);
+    let header = |session: &CollaborativeDocument| match &session
+        .document()
+        .find_block_by_id(table_id)
+        .unwrap()
+        .kind
+    {
+        BlockKind::Table { table } => {
+            let column = table.columns_in_order()[0].id;
+            (
+                column,
+                table
+                    .cell_value(table.header_row_id(), column)
+                    .unwrap_or_default()
+                    .to_string(),
+            )
+        }
+        _ => unreachable!(),
+    };
+    let (column_id, _) = header(&first);
+    for session in [&mut first, &mut second] {
+        session.set_resolution_policy(
+            ResolutionTarget::TableHeader(table_id),
+            ResolutionPolicy::Manual,
+        );
+    }
+
+    first
+        .set_table_cell(table_id, table_id, column_id, "title".into())
+        .unwrap();
+    second
+        .set_table_cell(table_id, table_id, column_id, "label".into())
+        .unwrap();
+    exchange(&first, &mut second);
+    exchange(&second, &mut first);
+
+    let key = RegisterKey::TableHeaderCell {
+        table_id,
+        column_id,
+    };
+    assert_eq!(header(&first), header(&second));
+    let conflicts = first.register_conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].key, key);
+
+    second
+        .resolve_register(&key, Some("heading".into()))
+        .unwrap();
+    exchange(&second, &mut first);
+    assert!(first.register_conflicts().is_empty());
+    assert_eq!(header(&first).1, "heading");
+}
+
+#[test]
+fn concurrent_writes_survive_a_snapshot_round_trip() {
+    let (first, _) = concurrent_status(ResolutionPolicy::Manual);
+    let snapshot = first.save_snapshot().unwrap();
+    let mut restored = CollaborativeDocument::restore_from_snapshot(snapshot).unwrap();
+    // Policies are replica settings and have to be attached again.
+    assert!(restored.register_conflicts().is_empty());
+    restored.set_resolution_policy(
+        ResolutionTarget::FrontmatterField("status".into()),
+        ResolutionPolicy::Manual,
+    );
+    assert_eq!(restored.register_conflicts(), first.register_conflicts());
+}
@@ -0,0 +1,121 @@
+//! Documents and sessions pinned to older grapheme cluster rules.
+
+use md_crdt::SegmentationRules::{Unicode8, Unicode16};
+use md_crdt::core::{OpId, StateVector};
+use md_crdt::doc::{BlockKind, EditError, Parser, block_id_from_op, paragraph_visible_string};
+use md_crdt::session::{CollaborativeDocument, SessionSnapshot};
+use md_crdt::sync::ValidationLimits;
+
+const THUMBS_UP_MEDIUM: &str = "\u{1F44D}\u{1F3FD}";
+
+fn units(doc: &CollaborativeDocument) -> usize {
+    match &doc.document().blocks_in_order()[0].kind {
+        BlockKind::Paragraph { text } => text.len_visible(),
+        _ => panic!("expected a paragraph"),
+    }
+}
+
+fn exchange(from: &CollaborativeDocument, to: &mut CollaborativeDocument) {
+    let message = from.encode_changes_since(&to.state_vector()).unwrap();
+    to.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+}
+
+#[test]
+fn remote_offsets_inside_a_current_cluster_apply_under_older_rules() {
+    let op = |counter| OpId { counter, peer: 7 };
+    let mut author = Parser::parse("ab\n");
+    author.set_segmentation(Unicode8);
+    let block = author.blocks_in_order()[0].id;
+    let mut replica = author.clone();
+
+    let thumbs = author.insert_text(block, 1, THUMBS_UP_MEDIUM, op(100));
+    // Between the thumb and its skin tone: a boundary only under Unicode 8.
+    let inside = author.insert_text(block, 2, "x", op(102)).unwrap();
+    for edit in thumbs.unwrap().into_iter().chain(inside.clone()) {
+        replica.raw_apply_op(edit, true).unwrap();
+    }
+    assert_eq!(replica, author);
+    let text = match &replica.blocks_in_order()[0].kind {
+        BlockKind::Paragraph { text } => paragraph_visible_string(text),
+        _ => unreachable!(),
+    };
+    assert_eq!(text, "a\u{1F44D}x\u{1F3FD}b");
+
+    let mut current = Parser::parse("ab\n");
+    current.set_segmentation(Unicode16);
+    let mut thumbs = current.clone();
+    thumbs
+        .insert_text(block, 1, THUMBS_UP_MEDIUM, op(100))
+        .unwrap();
+    let err = thumbs.raw_apply_op(inside[0].clone(), true).unwrap_err();
+    assert!(matches!(err, EditError::InvalidGraphemeBoundary { .. }));
+}
+
+#[test]
+fn peers_sharing_older_rules_agree_on_units() {
+    let mut a = CollaborativeDocument::new(1);
+    let mut b = CollaborativeDocument::new(2);
+    a.set_segmentation(Unicode8);
+    b.set_segmentation(Unicode8);
+    let elem = a
+        .insert_paragraph(None, &format!("a{THUMBS_UP_MEDIUM}b"))
+        .unwrap();
+    exchange(&a, &mut b);
+    assert_eq!(units(&a), 4);
+    assert_eq!(units(&b), 4);
+
+    let block = block_id_from_op(elem);
+    b.insert_text(block, 2, "x").unwrap();
+    exchange(&b, &mut a);
+    assert_eq!(a.state_vector(), b.state_vector());
+    assert_eq!(a.document(), b.document());
+
+    let mut current = CollaborativeDocument::new(3);
+    current
+        .insert_paragraph(None, &format!("a{THUMBS_UP_MEDIUM}b"))
+        .unwrap();
+    assert_eq!(units(&current), 3);
+}
+
+#[test]
+fn snapshots_keep_the_rules_and_default_rules_stay_unwritten() {
+    let mut doc = CollaborativeDocument::new(1);
+    doc.insert_paragraph(None, "text").unwrap();
+    let plain = doc.save_snapshot().unwrap().to_bytes().unwrap();
+    assert!(!String::from_utf8_lossy(&plain).contains("segmentation"));
+
+    doc.set_segmentation(Unicode8);
+    let bytes = doc.save_snapshot().unwrap().to_bytes().unwrap();
+    let restored =
+        CollaborativeDocument::restore_from_snapshot(SessionSnapshot::from_bytes(&bytes).unwrap())
+            .unwrap();
+    assert_eq!(restored.segmentation(), Unicode8);
+    let reloaded =
+        CollaborativeDocument::restore_from_snapshot(SessionSnapshot::from_bytes(&plain).unwrap())
+            .unwrap();
+    assert_eq!(reloaded.segmentation(), Unicode16);
+}
+
+#[test]
+fn text_runs_only_go_out_whole_when_every_version_splits_them_alike() {
+    let mut doc = CollaborativeDocument::new(1);
+    doc.insert_paragraph(None, "hello").unwrap();
+    doc.insert_paragraph(None, THUMBS_UP_MEDIUM).unwrap();
+
+    let message = doc.encode_changes_since(&StateVector::new()).unwrap();
+    let payloads: Vec<String> = message
+        .ops
+        .iter()
+        .map(|op| String::from_utf8_lossy(&op.payload).into_owned())
+        .collect();
+    let [_, word, _, emoji] = payloads.as_slice() else {
+        panic!("expected a block and a text op per paragraph");
+    };
+    assert!(word.contains("\"text\":\"hello\""));
+    assert!(emoji.contains("\"grapheme\":"));
+
+    let mut peer = CollaborativeDocument::new(2);
+    exchange(&doc, &mut peer);
+    assert_eq!(peer.document(), doc.document());
+}
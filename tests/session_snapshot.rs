@@ -237,6 +237,7 @@ fn snapshot_preserves_list_insert_waiting_for_a_missing_anchor() {
                     .into_iter()
                     .filter(|operation| operation.id.peer == 2)
                     .collect(),
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
@@ -301,6 +302,7 @@ fn snapshot_preserves_list_delete_waiting_for_a_missing_item() {
                     .into_iter()
                     .filter(|operation| operation.id.peer == 2)
                     .collect(),
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
@@ -381,6 +383,43 @@ fn snapshot_bytes_round_trip() {
     assert_eq!(loaded.ops.len(), snap.ops.len());
 }
 
+#[test]
+fn snapshot_ids_use_a_peer_table_and_report_savings() {
+    let (left, right) = (0xdead_beef_0000_0001, 0xdead_beef_0000_0002);
+    let mut a = CollaborativeDocument::new(left);
+    let mut b = CollaborativeDocument::new(right);
+    let block = block_id_from_op(a.insert_paragraph(None, "shared").unwrap());
+    exchange(&a, &mut b);
+    b.insert_text(block, 6, " text").unwrap();
+    exchange(&b, &mut a);
+
+    let snap = a.save_snapshot().unwrap();
+    let bytes = snap.to_bytes().unwrap();
+    let json = std::str::from_utf8(&bytes).unwrap();
+    assert!(
+        json.contains(&format!(r#""ops":[[{left},{right}],[[[0,1],"#)),
+        "{json}"
+    );
+    assert!(
+        json.contains(&format!(
+            r#""elements":[[{left},{right}],[[[0,2],{{"grapheme":"s"}},null,null]"#
+        )),
+        "{json}"
+    );
+    assert_eq!(SessionSnapshot::from_bytes(&bytes).unwrap(), snap);
+
+    let stats = a.storage_stats().unwrap();
+    assert_eq!(stats.snapshot_bytes, bytes.len());
+    // Three ops plus the block element and eleven text units with their anchors.
+    assert!(stats.compact_ids > 3 + 1 + 11, "{stats:?}");
+    assert!(stats.peer_table_entries >= 2, "{stats:?}");
+    assert!(stats.bytes_saved() > stats.compact_ids * 10, "{stats:?}");
+    assert_eq!(
+        stats.full_id_bytes - stats.bytes_saved(),
+        stats.snapshot_bytes
+    );
+}
+
 #[test]
 fn non_current_snapshot_versions_require_reinitialize() {
     let mut session = CollaborativeDocument::new(3);
@@ -432,3 +471,21 @@ fn storage_write_read_round_trip() {
     );
     assert_eq!(a.state_vector(), b.state_vector());
 }
+
+#[test]
+fn snapshots_and_messages_share_payloads_with_the_op_log() {
+    let mut doc = CollaborativeDocument::new(1);
+    doc.insert_paragraph(None, "shared").unwrap();
+    let snapshot = doc.save_snapshot().unwrap();
+    let message = doc
+        .encode_changes_since(&md_crdt::StateVector::new())
+        .unwrap();
+    assert_eq!(snapshot.ops.len(), message.ops.len());
+    for ((id, payload), op) in snapshot.ops.iter().zip(&message.ops) {
+        assert_eq!(*id, op.id);
+        assert!(std::sync::Arc::ptr_eq(payload, &op.payload));
+    }
+
+    let restored = SessionSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+    assert_eq!(restored, snapshot);
+}
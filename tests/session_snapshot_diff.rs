@@ -0,0 +1,126 @@
+//! Operations between two encoded snapshots of one document.
+
+use md_crdt::sync::ChangeMessage;
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, HistoryBudget, SessionSnapshot, SnapshotDiffError,
+    SnapshotError, ValidationLimits, diff_snapshots,
+};
+
+fn bytes(doc: &CollaborativeDocument) -> Vec<u8> {
+    doc.save_snapshot().unwrap().to_bytes().unwrap()
+}
+
+fn markdown(doc: &CollaborativeDocument) -> String {
+    doc.document().serialize(EquivalenceMode::Exact)
+}
+
+#[test]
+fn a_backup_catches_up_with_the_ops_it_lacks() {
+    let mut live = CollaborativeDocument::new(1);
+    live.insert_paragraph(None, "kept").unwrap();
+    let backup = bytes(&live);
+
+    let mut peer = CollaborativeDocument::new(2);
+    peer.merge_from(&live).unwrap();
+    peer.insert_paragraph(None, "from a peer").unwrap();
+    live.merge_from(&peer).unwrap();
+    live.insert_paragraph(None, "newer").unwrap();
+    let current = bytes(&live);
+
+    let ops = diff_snapshots(&backup, &current).unwrap();
+    let old = SessionSnapshot::from_bytes(&backup).unwrap();
+    assert!(
+        ops.iter()
+            .all(|op| old.ops.iter().all(|(id, _)| *id != op.id))
+    );
+    assert!(ops.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+    let mut restored = CollaborativeDocument::restore_from_snapshot(old.clone()).unwrap();
+    restored
+        .apply_remote(
+            ChangeMessage {
+                since: old.state_vector,
+                ops,
+                gaps: Vec::new(),
+            },
+            &ValidationLimits::default(),
+        )
+        .unwrap();
+    assert_eq!(markdown(&restored), markdown(&live));
+    assert_eq!(restored.state_vector(), live.state_vector());
+}
+
+#[test]
+fn an_offline_copy_and_the_server_diff_both_ways() {
+    let mut server = CollaborativeDocument::new(1);
+    server.insert_paragraph(None, "shared").unwrap();
+    let mut offline = CollaborativeDocument::new(2);
+    offline.merge_from(&server).unwrap();
+
+    offline.insert_paragraph(None, "written offline").unwrap();
+    server.insert_paragraph(None, "written online").unwrap();
+    let (server_bytes, offline_bytes) = (bytes(&server), bytes(&offline));
+
+    let to_push = diff_snapshots(&server_bytes, &offline_bytes).unwrap();
+    let to_pull = diff_snapshots(&offline_bytes, &server_bytes).unwrap();
+    assert!(to_push.iter().all(|op| op.id.peer == 2));
+    assert!(to_pull.iter().all(|op| op.id.peer == 1));
+    assert!(!to_push.is_empty() && !to_pull.is_empty());
+    assert!(
+        diff_snapshots(&server_bytes, &server_bytes)
+            .unwrap()
+            .is_empty()
+    );
+
+    let limits = ValidationLimits::default();
+    server
+        .apply_remote(
+            ChangeMessage {
+                since: server.state_vector(),
+                ops: to_push,
+                gaps: Vec::new(),
+            },
+            &limits,
+        )
+        .unwrap();
+    offline
+        .apply_remote(
+            ChangeMessage {
+                since: offline.state_vector(),
+                ops: to_pull,
+                gaps: Vec::new(),
+            },
+            &limits,
+        )
+        .unwrap();
+    assert_eq!(markdown(&server), markdown(&offline));
+}
+
+#[test]
+fn history_checkpointed_past_the_old_snapshot_requires_a_rebase() {
+    let mut doc = CollaborativeDocument::new(1);
+    doc.insert_paragraph(None, "one").unwrap();
+    let old = bytes(&doc);
+    for text in ["two", "three", "four"] {
+        doc.insert_paragraph(None, text).unwrap();
+    }
+    doc.set_history_budget(Some(HistoryBudget::ops(2)));
+    doc.insert_paragraph(None, "five").unwrap();
+
+    let err = diff_snapshots(&old, &bytes(&doc)).unwrap_err();
+    assert!(matches!(err, SnapshotDiffError::Rebase(_)));
+    // The newer snapshot still diffs against older ones that saw everything pruned.
+    let recent = bytes(&doc);
+    doc.insert_paragraph(None, "six").unwrap();
+    assert_eq!(diff_snapshots(&recent, &bytes(&doc)).unwrap().len(), 2);
+}
+
+#[test]
+fn undecodable_snapshots_are_reported() {
+    let doc = CollaborativeDocument::new(1);
+    let err = diff_snapshots(b"not a snapshot", &bytes(&doc)).unwrap_err();
+    assert!(matches!(
+        err,
+        SnapshotDiffError::Snapshot(SnapshotError::Serde(_))
+    ));
+}
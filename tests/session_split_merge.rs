@@ -242,6 +242,7 @@ fn remote_merge_rejects_foreign_replacement_ids() {
                     id: operation.id,
                     payload: operation.payload.clone(),
                 }],
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
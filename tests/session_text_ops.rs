@@ -349,6 +349,7 @@ fn remote_insert_text_peer_mismatch_rejected() {
     // a nested unit with a foreign peer must still trip PeerMismatch.
     let env = md_crdt::codec::Envelope {
         version: WIRE_VERSION,
+        meta: None,
         body: OpBody::Doc(DocOp::InsertText {
             block_elem: elem,
             block_id: bid,
@@ -387,6 +388,7 @@ fn remote_insert_text_peer_mismatch_rejected() {
             },
             payload: payload.into(),
         }],
+        gaps: Vec::new(),
     };
     let err = b
         .apply_remote(msg, &ValidationLimits::default())
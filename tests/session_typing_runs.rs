@@ -0,0 +1,154 @@
+//! Typed characters coalesce into one InsertText run, sent in run-encoded form.
+
+use md_crdt::codec::{DocOp, Envelope, JsonOpCodec, OpBody, OpCodec, TextUnitWire, WIRE_VERSION};
+use md_crdt::core::{OpId, StateVector};
+use md_crdt::doc::{BlockId, EquivalenceMode, block_id_from_op};
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::sync::{Operation, SyncState, ValidationLimits};
+
+fn type_chars(doc: &mut CollaborativeDocument, block: BlockId, offset: usize, text: &str) {
+    for (i, ch) in text.chars().enumerate() {
+        doc.insert_text(block, offset + i, &ch.to_string()).unwrap();
+    }
+}
+
+#[test]
+fn typing_run_leaves_as_one_operation() {
+    let mut a = CollaborativeDocument::new(1);
+    let block = block_id_from_op(a.insert_paragraph(None, "").unwrap());
+    type_chars(&mut a, block, 0, "hello world");
+
+    let message = a.encode_changes_since(&StateVector::new()).unwrap();
+    assert_eq!(message.ops.len(), 2);
+    let text_op = &message.ops[1];
+    assert_eq!(
+        text_op.id,
+        OpId {
+            counter: a.peek_next_id().counter - 1,
+            peer: 1,
+        }
+    );
+    let raw = std::str::from_utf8(&text_op.payload).unwrap();
+    assert!(raw.contains(r#""text":"hello world""#), "{raw}");
+    assert!(!raw.contains("grapheme"), "{raw}");
+
+    let mut b = CollaborativeDocument::new(2);
+    b.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(
+        b.document().serialize(EquivalenceMode::Exact),
+        a.document().serialize(EquivalenceMode::Exact)
+    );
+    assert_eq!(b.state_vector(), a.state_vector());
+}
+
+#[test]
+fn pastes_and_jumps_start_new_operations() {
+    let mut a = CollaborativeDocument::new(1);
+    let block = block_id_from_op(a.insert_paragraph(None, "ab").unwrap());
+    type_chars(&mut a, block, 2, "c");
+    a.insert_text(block, 3, "de").unwrap();
+    type_chars(&mut a, block, 0, "x");
+    type_chars(&mut a, block, 6, "y");
+
+    let message = a.encode_changes_since(&StateVector::new()).unwrap();
+    // Block, "abc", paste "de", "x" at the start, "y" at the end.
+    assert_eq!(message.ops.len(), 5);
+
+    let mut b = CollaborativeDocument::new(2);
+    b.apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(b.document().serialize(EquivalenceMode::Exact), "xabcdey");
+}
+
+#[test]
+fn typing_after_a_sync_still_converges() {
+    let mut a = CollaborativeDocument::new(1);
+    let block = block_id_from_op(a.insert_paragraph(None, "").unwrap());
+    type_chars(&mut a, block, 0, "abc");
+    let mut b = CollaborativeDocument::new(2);
+    b.apply_remote(
+        a.encode_changes_since(&b.state_vector()).unwrap(),
+        &ValidationLimits::default(),
+    )
+    .unwrap();
+
+    type_chars(&mut a, block, 3, "def");
+    type_chars(&mut b, block, 3, "!");
+    let to_b = a.encode_changes_since(&b.state_vector()).unwrap();
+    let to_a = b.encode_changes_since(&a.state_vector()).unwrap();
+    b.apply_remote(to_b, &ValidationLimits::default()).unwrap();
+    a.apply_remote(to_a, &ValidationLimits::default()).unwrap();
+
+    assert_eq!(
+        a.document().serialize(EquivalenceMode::Exact),
+        b.document().serialize(EquivalenceMode::Exact)
+    );
+    assert_eq!(a.state_vector(), b.state_vector());
+}
+
+#[test]
+fn sent_operations_are_not_coalesced() {
+    let id = |counter| OpId { counter, peer: 1 };
+    let mut sync = SyncState::new();
+    sync.add_local_op(Operation {
+        id: id(1),
+        payload: vec![1].into(),
+    })
+    .unwrap();
+    assert!(sync.coalesce_local_op(
+        id(1),
+        Operation {
+            id: id(2),
+            payload: vec![2].into(),
+        }
+    ));
+    assert_eq!(sync.unsent(id(2)), Some(&[2u8][..]));
+    assert!(!sync.contains(id(1)));
+
+    sync.mark_sent(&[id(2)]);
+    assert!(!sync.coalesce_local_op(
+        id(2),
+        Operation {
+            id: id(3),
+            payload: vec![3].into(),
+        }
+    ));
+    assert_eq!(sync.outbox(), Vec::new());
+}
+
+#[test]
+fn run_encoding_falls_back_when_units_do_not_chain_or_resegment() {
+    let unit = |counter, after: Option<u64>, grapheme: &str| TextUnitWire {
+        id: OpId { counter, peer: 4 },
+        after: after.map(|counter| OpId { counter, peer: 4 }),
+        right_origin: None,
+        grapheme: grapheme.into(),
+    };
+    let envelope = |units| Envelope {
+        version: WIRE_VERSION,
+        meta: None,
+        body: OpBody::Doc(DocOp::InsertText {
+            block_elem: OpId {
+                counter: 1,
+                peer: 4,
+            },
+            block_id: BlockId::nil(),
+            units,
+        }),
+    };
+    let cases = [
+        (vec![unit(2, None, "a"), unit(3, Some(2), "b")], true),
+        // Typed separately, "e" and a combining accent would segment as one grapheme.
+        (vec![unit(2, None, "e"), unit(3, Some(2), "\u{301}")], false),
+        (vec![unit(2, None, "a"), unit(4, Some(2), "b")], false),
+        (vec![unit(2, None, "a"), unit(3, None, "b")], false),
+    ];
+    for (units, as_run) in cases {
+        let envelope = envelope(units);
+        let bytes = JsonOpCodec.encode(&envelope).unwrap();
+        let raw = std::str::from_utf8(&bytes).unwrap();
+        assert_eq!(raw.contains(r#""text":"#), as_run, "{raw}");
+        assert_eq!(JsonOpCodec.decode(&bytes).unwrap(), envelope);
+    }
+}
@@ -1,9 +1,33 @@
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
 use std::fs;
+use std::path::Path;
 use std::process::Command;
 use tempfile::tempdir;
 
+#[allow(deprecated)]
+fn flush(vault: &Path) {
+    Command::cargo_bin("md-crdt")
+        .unwrap()
+        .arg("flush")
+        .current_dir(vault)
+        .assert()
+        .success();
+}
+
+#[allow(deprecated)]
+fn status_json(vault: &Path) -> (Option<i32>, serde_json::Value) {
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .arg("status")
+        .arg("--json")
+        .current_dir(vault)
+        .output()
+        .unwrap();
+    let json = serde_json::from_slice(&output.stdout).unwrap();
+    (output.status.code(), json)
+}
+
 #[test]
 #[allow(deprecated)]
 fn test_status_untracked_files() {
@@ -44,16 +68,8 @@ fn test_status_json_output() {
 #[allow(deprecated)]
 fn test_status_clean_vault() {
     let dir = tempdir().unwrap();
-    fs::create_dir_all(dir.path().join(".mdcrdt").join("state")).unwrap();
     fs::write(dir.path().join("file1.md"), "content1").unwrap();
-    fs::write(
-        dir.path()
-            .join(".mdcrdt")
-            .join("state")
-            .join("file1.mdcrdt"),
-        "state",
-    )
-    .unwrap();
+    flush(dir.path());
 
     let mut cmd = Command::cargo_bin("md-crdt").unwrap();
     cmd.arg("status").current_dir(dir.path());
@@ -68,16 +84,8 @@ fn test_status_clean_vault() {
 #[allow(deprecated)]
 fn test_status_json_clean_vault() {
     let dir = tempdir().unwrap();
-    fs::create_dir_all(dir.path().join(".mdcrdt").join("state")).unwrap();
     fs::write(dir.path().join("file1.md"), "content1").unwrap();
-    fs::write(
-        dir.path()
-            .join(".mdcrdt")
-            .join("state")
-            .join("file1.mdcrdt"),
-        "state",
-    )
-    .unwrap();
+    flush(dir.path());
 
     let mut cmd = Command::cargo_bin("md-crdt").unwrap();
     cmd.arg("status").arg("--json").current_dir(dir.path());
@@ -97,17 +105,9 @@ fn test_status_json_clean_vault() {
 #[allow(deprecated)]
 fn test_status_multiple_files_mixed() {
     let dir = tempdir().unwrap();
-    fs::create_dir_all(dir.path().join(".mdcrdt").join("state")).unwrap();
     fs::write(dir.path().join("tracked.md"), "content1").unwrap();
+    flush(dir.path());
     fs::write(dir.path().join("untracked.md"), "content2").unwrap();
-    fs::write(
-        dir.path()
-            .join(".mdcrdt")
-            .join("state")
-            .join("tracked.mdcrdt"),
-        "state",
-    )
-    .unwrap();
 
     let mut cmd = Command::cargo_bin("md-crdt").unwrap();
     cmd.arg("status").current_dir(dir.path());
@@ -117,3 +117,63 @@ fn test_status_multiple_files_mixed() {
         .code(1)
         .stdout(predicate::str::contains("Untracked: untracked.md"));
 }
+
+#[test]
+#[allow(deprecated)]
+fn test_status_reports_modified_files_and_changed_blocks() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.md"), "# Title\n\nfirst\n\nsecond\n").unwrap();
+    flush(dir.path());
+    fs::write(
+        dir.path().join("notes.md"),
+        "# Title\n\nfirst, reworded entirely\n\nsecond\n\nthird\n",
+    )
+    .unwrap();
+
+    let (code, json) = status_json(dir.path());
+    assert_eq!(code, Some(1));
+    assert_eq!(json["dirty"], true);
+    assert_eq!(json["conflicted"], false);
+    let file = &json["files"][0];
+    assert_eq!(file["status"], "Modified");
+    assert_eq!(file["tracked"], true);
+    assert_eq!(file["modified"], true);
+    assert_eq!(file["changed_blocks"], 2);
+    assert!(file["last_flush"].as_u64().unwrap() > 0);
+
+    Command::cargo_bin("md-crdt")
+        .unwrap()
+        .arg("status")
+        .current_dir(dir.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "Modified: notes.md (2 changed block(s), last flush",
+        ));
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_status_flags_files_changed_on_disk_and_in_the_session() {
+    use md_crdt::filesync::VaultSession;
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.md"), "first\n").unwrap();
+    let mut session = VaultSession::open(dir.path()).unwrap();
+    session.ingest_all().unwrap();
+    session.save_all_state().unwrap();
+
+    // Disk edits alone are only modifications.
+    fs::write(dir.path().join("notes.md"), "first\n\nfrom disk\n").unwrap();
+    let (code, json) = status_json(dir.path());
+    assert_eq!(code, Some(1));
+    assert_eq!(json["files"][0]["status"], "Modified");
+
+    let doc = session.session_mut("notes.md").unwrap();
+    doc.insert_paragraph(None, "from a peer").unwrap();
+    session.save_all_state().unwrap();
+    let (code, json) = status_json(dir.path());
+    assert_eq!(code, Some(3));
+    assert_eq!(json["conflicted"], true);
+    assert_eq!(json["files"][0]["status"], "Conflicted");
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "storage")]
+
+use md_crdt::storage::BlockKindTag;
+use md_crdt::{EquivalenceMode, OpId, Parser, StorageError, access_document, encode_document};
+
+#[test]
+fn archived_document_answers_queries_without_decoding() {
+    let input = "#  Plan\n\nalpha beta\n\n- [ ] ship\n\n| a | b |\n| --- | --- |\n| 1 | 2 |\n";
+    let mut document = Parser::parse(input);
+    let paragraph = document.blocks_in_order()[1].id;
+    document
+        .insert_text(
+            paragraph,
+            6,
+            "brave ",
+            OpId {
+                counter: 1,
+                peer: 4,
+            },
+        )
+        .unwrap();
+
+    let bytes = encode_document(&document).unwrap();
+    let archived = access_document(&bytes).unwrap();
+
+    assert_eq!(
+        archived.serialize(EquivalenceMode::Exact),
+        document.serialize(EquivalenceMode::Exact)
+    );
+    assert_eq!(
+        archived.serialize(EquivalenceMode::Structural),
+        document.serialize(EquivalenceMode::Structural)
+    );
+    let blocks = document.blocks_in_order();
+    let records = archived.blocks_in_order();
+    assert_eq!(records.len(), blocks.len());
+    for (record, block) in records.iter().zip(&blocks) {
+        assert_eq!(record.id(), block.id);
+        assert_eq!(record.elem_id(), block.elem_id);
+    }
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| record.kind())
+            .collect::<Vec<_>>(),
+        vec![
+            BlockKindTag::Heading { level: 1 },
+            BlockKindTag::Paragraph,
+            BlockKindTag::List,
+            BlockKindTag::Table,
+        ]
+    );
+    assert_eq!(records[1].markdown(), "alpha brave beta");
+
+    assert_eq!(archived.to_document().unwrap(), document);
+}
+
+#[test]
+fn corrupt_archives_are_rejected() {
+    let bytes = encode_document(&Parser::parse("alpha\n")).unwrap();
+    assert!(matches!(
+        access_document(&bytes[..bytes.len() / 2]),
+        Err(StorageError::Corrupt(_))
+    ));
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "storage")]
+
+use md_crdt::storage::{BlockKindTag, LazyEditError};
+use md_crdt::{
+    CollaborativeDocument, Document, EditError, EquivalenceMode, OpId, Parser, Storage,
+    StorageError,
+};
+use tempfile::tempdir;
+
+fn op(counter: u64) -> OpId {
+    OpId { counter, peer: 9 }
+}
+
+fn long_note(sections: usize) -> String {
+    (0..sections)
+        .map(|section| format!("## Section {section}\n\nbody of section {section}\n"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn open_lazy_reads_the_outline_without_any_block_body() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    let document = Parser::parse(&long_note(50));
+    storage.write_block_index(&document).unwrap();
+
+    let lazy = Document::open_lazy(&storage).unwrap();
+
+    assert_eq!(lazy.len(), 100);
+    assert_eq!(lazy.hydrated_len(), 0);
+    let headings: Vec<_> = lazy
+        .blocks()
+        .iter()
+        .filter_map(|block| block.heading.as_deref())
+        .take(2)
+        .collect();
+    assert_eq!(headings, ["Section 0", "Section 1"]);
+    assert_eq!(lazy.blocks()[0].kind, BlockKindTag::Heading { level: 2 });
+    assert_eq!(lazy.blocks()[1].kind, BlockKindTag::Paragraph);
+    for (outline, block) in lazy.blocks().iter().zip(document.blocks_in_order()) {
+        assert_eq!((outline.id, outline.elem_id), (block.id, block.elem_id));
+    }
+}
+
+#[test]
+fn edits_hydrate_only_the_touched_block() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    let mut document = Parser::parse(&long_note(20));
+    storage.write_block_index(&document).unwrap();
+
+    let mut lazy = Document::open_lazy(&storage).unwrap();
+    let (heading, body) = (lazy.blocks()[6].id, lazy.blocks()[7].id);
+    let read = lazy.block(body).unwrap().unwrap().clone();
+    assert_eq!(&read, document.find_block_by_id(body).unwrap());
+    lazy.insert_text(heading, 0, "Renamed ", op(1)).unwrap();
+
+    assert_eq!(lazy.hydrated_len(), 2);
+    assert!(lazy.is_hydrated(heading) && lazy.is_hydrated(body));
+    assert!(!lazy.is_hydrated(lazy.blocks()[0].id));
+    assert_eq!(
+        lazy.blocks()[6].heading.as_deref(),
+        Some("Renamed Section 3")
+    );
+
+    document.insert_text(heading, 0, "Renamed ", op(1)).unwrap();
+    let expected = document.serialize(EquivalenceMode::Exact);
+    let assembled = lazy.into_document().unwrap();
+    assert_eq!(assembled.serialize(EquivalenceMode::Exact), expected);
+    assert!(expected.contains("## Renamed Section 3\n"));
+}
+
+#[test]
+fn edit_errors_match_the_eager_document() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    storage
+        .write_block_index(&Parser::parse("```\ncode\n```\n"))
+        .unwrap();
+    let mut lazy = Document::open_lazy(&storage).unwrap();
+    let fence = lazy.blocks()[0].id;
+
+    assert!(matches!(
+        lazy.insert_text(fence, 0, "x", op(1)),
+        Err(LazyEditError::Edit(EditError::NotTextBlock { .. }))
+    ));
+}
+
+#[test]
+fn session_storage_can_be_opened_lazily() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    assert!(matches!(
+        Document::open_lazy(&storage),
+        Err(StorageError::Missing)
+    ));
+
+    let mut session = CollaborativeDocument::new(3);
+    session.insert_paragraph(None, "first").unwrap();
+    session.write_to_storage(&storage).unwrap();
+
+    let lazy = Document::open_lazy(&storage).unwrap();
+    assert_eq!(lazy.blocks()[0].kind, BlockKindTag::Paragraph);
+    assert_eq!(
+        lazy.into_document()
+            .unwrap()
+            .serialize(EquivalenceMode::Exact),
+        session.document().serialize(EquivalenceMode::Exact)
+    );
+}
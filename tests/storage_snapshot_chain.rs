@@ -0,0 +1,91 @@
+#![cfg(feature = "storage")]
+
+use md_crdt::session::CollaborativeDocument;
+use md_crdt::{StateVector, Storage, StorageError, TombstoneRetention};
+use tempfile::tempdir;
+
+fn frontier(pairs: &[(u64, u64)]) -> StateVector {
+    let mut state_vector = StateVector::new();
+    for &(peer, counter) in pairs {
+        state_vector.set(peer, counter);
+    }
+    state_vector
+}
+
+#[test]
+fn unchained_storage_verifies_as_none() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    storage.write_snapshot(b"state", &[], false).unwrap();
+    assert!(!storage.has_chain());
+    assert_eq!(storage.verify_chain().unwrap(), None);
+}
+
+#[test]
+fn links_follow_snapshots_and_compactions() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    storage.write_snapshot(b"one", &[], false).unwrap();
+    let first = storage.record_snapshot_link(&frontier(&[(1, 2)])).unwrap();
+    storage
+        .compact(b"two", &[], false, TombstoneRetention::KeepAll, &[])
+        .unwrap();
+    let second = storage
+        .record_snapshot_link(&frontier(&[(1, 2), (2, 1)]))
+        .unwrap();
+
+    assert_eq!(first.index, 0);
+    assert_eq!(first.previous, [0; 32]);
+    assert_eq!(second.previous, first.hash());
+    assert_eq!(storage.chain().unwrap(), vec![first, second.clone()]);
+    assert_eq!(storage.verify_chain().unwrap(), Some(second.hash()));
+}
+
+#[test]
+fn replaced_snapshot_breaks_the_chain() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    storage.write_snapshot(b"audited", &[], false).unwrap();
+    storage.record_snapshot_link(&frontier(&[(1, 1)])).unwrap();
+
+    storage.write_snapshot(b"rewritten", &[], false).unwrap();
+    assert!(matches!(
+        storage.verify_chain(),
+        Err(StorageError::ChainBroken { index: 0, .. })
+    ));
+}
+
+#[test]
+fn regressed_frontier_breaks_the_chain() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    storage.write_snapshot(b"full", &[], false).unwrap();
+    storage.record_snapshot_link(&frontier(&[(1, 5)])).unwrap();
+    storage.write_snapshot(b"truncated", &[], false).unwrap();
+    storage.record_snapshot_link(&frontier(&[(1, 3)])).unwrap();
+
+    let error = storage.verify_chain().unwrap_err();
+    assert!(
+        matches!(error, StorageError::ChainBroken { index: 1, reason } if reason.contains("regressed"))
+    );
+}
+
+#[test]
+fn session_writes_extend_a_started_chain() {
+    let dir = tempdir().unwrap();
+    let storage = Storage::open(dir.path()).unwrap();
+    let mut document = CollaborativeDocument::new(1);
+    document.insert_paragraph(None, "first").unwrap();
+    document.write_to_storage(&storage).unwrap();
+    assert!(!storage.has_chain());
+    storage
+        .record_snapshot_link(&document.state_vector())
+        .unwrap();
+
+    document.insert_paragraph(None, "second").unwrap();
+    document.write_to_storage(&storage).unwrap();
+    let chain = storage.chain().unwrap();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[1].state_vector, document.state_vector());
+    assert!(storage.verify_chain().unwrap().is_some());
+}
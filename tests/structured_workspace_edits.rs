@@ -844,6 +844,7 @@ fn snapshot_preserves_structured_write_waiting_for_observed_source_history() {
                     .into_iter()
                     .filter(|operation| operation.id.peer == 21)
                     .collect(),
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
@@ -1056,6 +1057,7 @@ fn delayed_list_move_survives_arrival_before_insert_and_snapshot() {
                     .into_iter()
                     .filter(|operation| operation.id.peer == 2)
                     .collect(),
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
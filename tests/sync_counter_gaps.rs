@@ -0,0 +1,209 @@
+//! Peers that skipped counters: gaps declared by the sender and checked by the
+//! receiver, gap-tolerant readiness, and strict mode.
+
+use md_crdt::core::CounterRanges;
+use md_crdt::sync::{CausalMode, ChangeMessage, OpClass, OpFilter, Operation, SyncState};
+use md_crdt::{OpId, StateVector, ValidationError};
+
+fn op(peer: u64, counter: u64) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: vec![counter as u8].into(),
+    }
+}
+
+/// A sender whose log for peer 1 holds counters 1, 2, 5 and 6; it kept 3 and 4
+/// to itself.
+fn sender_with_gap() -> SyncState {
+    let mut sender = SyncState::new();
+    sender.set_op_filter(OpFilter::new(|op| {
+        if (3..=4).contains(&op.id.counter) {
+            OpClass::LocalOnly
+        } else {
+            OpClass::Shared
+        }
+    }));
+    for counter in 1..=6 {
+        sender.add_local_op(op(1, counter)).unwrap();
+    }
+    sender
+}
+
+#[test]
+fn counter_ranges_merge_touching_ranges() {
+    let mut ranges = CounterRanges::new();
+    ranges.insert(5, 6);
+    ranges.insert(1, 2);
+    ranges.insert(3, 4);
+    ranges.insert(9, 9);
+    assert_eq!(ranges.iter().collect::<Vec<_>>(), vec![1..=6, 9..=9]);
+    assert!(ranges.contains(4));
+    assert!(!ranges.contains(7));
+    assert_eq!(ranges.len(), 7);
+    assert_eq!(ranges.max(), Some(9));
+}
+
+#[test]
+fn the_sender_declares_the_counters_it_kept_back() {
+    let message = sender_with_gap()
+        .encode_changes_since(&StateVector::new())
+        .unwrap();
+    assert_eq!(message.gaps, vec![(op(1, 5).id, 2)]);
+
+    let bytes = message.to_compact_bytes();
+    assert_eq!(ChangeMessage::from_compact_bytes(&bytes).unwrap(), message);
+    let json = serde_json::to_string(&message).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ChangeMessage>(&json).unwrap(),
+        message
+    );
+}
+
+#[test]
+fn skipped_counters_do_not_hold_back_later_ops() {
+    let sender = sender_with_gap();
+    let mut receiver = SyncState::new();
+    let result = receiver
+        .apply_changes(sender.encode_changes_since(&StateVector::new()).unwrap())
+        .unwrap();
+
+    assert_eq!(result.applied.len(), 4);
+    assert_eq!(receiver.pending_count(), 0);
+    assert_eq!(receiver.state_vector().get(1), Some(6));
+    let applied: Vec<_> = receiver.applied_ranges(1).unwrap().iter().collect();
+    assert_eq!(applied, vec![1..=2, 5..=6]);
+
+    // A replica relaying the operations passes the declaration on.
+    let mut relayed = SyncState::new();
+    let message = receiver.encode_changes_since(&StateVector::new()).unwrap();
+    assert_eq!(message.gaps, vec![(op(1, 5).id, 2)]);
+    relayed.apply_changes(message).unwrap();
+    assert_eq!(relayed.pending_count(), 0);
+}
+
+#[test]
+fn gap_tolerance_is_the_default_and_strict_mode_stalls() {
+    assert_eq!(SyncState::new().causal_mode(), CausalMode::GapTolerant);
+
+    let sender = sender_with_gap();
+    let mut receiver = SyncState::new();
+    receiver.set_causal_mode(CausalMode::Strict);
+    let result = receiver
+        .apply_changes(sender.encode_changes_since(&StateVector::new()).unwrap())
+        .unwrap();
+
+    assert_eq!(result.applied, vec![op(1, 1).id, op(1, 2).id]);
+    assert_eq!(result.buffered, vec![op(1, 5).id, op(1, 6).id]);
+
+    // Switching releases them.
+    receiver.set_causal_mode(CausalMode::GapTolerant);
+    let promoted = receiver.promote_ready_pending();
+    assert_eq!(promoted, vec![op(1, 5), op(1, 6)]);
+}
+
+#[test]
+fn an_operation_missing_from_a_delta_is_not_skipped() {
+    let mut sender = SyncState::new();
+    for counter in 1..=3 {
+        sender.add_local_op(op(1, counter)).unwrap();
+    }
+    let mut delta = sender.encode_changes_since(&StateVector::new()).unwrap();
+    delta.ops.remove(1);
+
+    let mut receiver = SyncState::new();
+    let result = receiver.apply_changes(delta).unwrap();
+    assert_eq!(result.applied, vec![op(1, 1).id]);
+    assert_eq!(result.buffered, vec![op(1, 3).id]);
+    assert_eq!(receiver.state_vector().get(1), Some(1));
+
+    // The frontier still asks for the lost operation.
+    let resent = sender
+        .encode_changes_since(&receiver.state_vector())
+        .unwrap();
+    assert_eq!(resent.ops, vec![op(1, 2), op(1, 3)]);
+    let result = receiver.apply_changes(resent).unwrap();
+    assert_eq!(result.applied, vec![op(1, 2).id, op(1, 3).id]);
+    assert_eq!(receiver.pending_count(), 0);
+}
+
+#[test]
+fn a_delta_ahead_of_its_predecessor_still_waits() {
+    let sender = sender_with_gap();
+    let mut first = StateVector::new();
+    first.set(1, 2);
+    let later = sender.encode_changes_since(&first).unwrap();
+    let earlier = sender.encode_changes_since(&StateVector::new()).unwrap();
+
+    let mut receiver = SyncState::new();
+    let result = receiver.apply_changes(later).unwrap();
+    assert!(result.applied.is_empty());
+    // Counter 5 follows 2, which in turn waits on the first missing counter.
+    let summary = receiver.pending_summary(std::time::Instant::now());
+    assert_eq!(summary.awaited[0].id, op(1, 1).id);
+
+    let result = receiver.apply_changes(earlier).unwrap();
+    assert_eq!(
+        result.applied,
+        vec![op(1, 1).id, op(1, 2).id, op(1, 5).id, op(1, 6).id]
+    );
+    assert_eq!(receiver.pending_count(), 0);
+}
+
+#[test]
+fn a_gap_over_counters_the_receiver_holds_is_refused() {
+    let mut receiver = SyncState::new();
+    receiver
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(1, 1), op(1, 2), op(1, 4)],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    assert_eq!(receiver.pending_count(), 1);
+
+    // Counter 2 is applied here and counter 4 buffered, so neither was skipped.
+    for (after, counter) in [(1, 2), (2, 4)] {
+        let error = receiver
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops: vec![op(1, 5)],
+                gaps: vec![(op(1, 5).id, after)],
+            })
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ValidationError::ContradictedGap {
+                op_id: op(1, 5).id,
+                counter,
+            }
+        );
+    }
+
+    // Nor is a counter the message carries itself.
+    let error = receiver
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(2, 1), op(2, 2)],
+            gaps: vec![(op(2, 2).id, 0)],
+        })
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ValidationError::ContradictedGap { counter: 1, .. }
+    ));
+    assert_eq!(receiver.state_vector().get(1), Some(2));
+    assert_eq!(receiver.state_vector().get(2), None);
+}
+
+#[test]
+fn a_gap_at_or_after_its_operation_is_malformed() {
+    let mut receiver = SyncState::new();
+    let error = receiver
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(1, 3)],
+            gaps: vec![(op(1, 3).id, 3)],
+        })
+        .unwrap_err();
+    assert!(matches!(error, ValidationError::MalformedOperation { .. }));
+}
@@ -45,15 +45,15 @@ proptest! {
         for op in &ops1 {
             doc_a.apply_changes(ChangeMessage {
                 since: doc_a.state_vector(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle_a.apply(op.id, op.payload.to_vec());
         }
         for op in &ops2 {
             doc_a.apply_changes(ChangeMessage {
                 since: doc_a.state_vector(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle_a.apply(op.id, op.payload.to_vec());
         }
 
@@ -61,15 +61,15 @@ proptest! {
         for op in &ops2 {
             doc_b.apply_changes(ChangeMessage {
                 since: doc_b.state_vector(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle_b.apply(op.id, op.payload.to_vec());
         }
         for op in &ops1 {
             doc_b.apply_changes(ChangeMessage {
                 since: doc_b.state_vector(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle_b.apply(op.id, op.payload.to_vec());
         }
 
@@ -115,8 +115,8 @@ proptest! {
         for op in ops1.iter().chain(ops2.iter()) {
             doc.apply_changes(ChangeMessage {
                 since: doc.state_vector(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle.apply(op.id, op.payload.to_vec());
         }
 
@@ -158,8 +158,8 @@ proptest! {
         for op in ops.iter().rev() {
             doc.apply_changes(ChangeMessage {
                 since: md_crdt::core::StateVector::new(),
-                ops: vec![op.clone()],
-            });
+                ops: vec![op.clone()], gaps: Vec::new(),
+            }).unwrap();
             oracle.apply(op.id, op.payload.to_vec());
         }
 
@@ -211,25 +211,26 @@ fn test_two_peer_sync_simulation() {
     };
 
     // Peer 1 applies its own operations
-    peer1_doc.add_local_op(peer1_op1.clone());
-    peer1_doc.add_local_op(peer1_op2.clone());
+    peer1_doc.add_local_op(peer1_op1.clone()).unwrap();
+    peer1_doc.add_local_op(peer1_op2.clone()).unwrap();
 
     // Peer 2 applies its own operation
-    peer2_doc.add_local_op(peer2_op1.clone());
+    peer2_doc.add_local_op(peer2_op1.clone()).unwrap();
 
     // Simulate sync: peer 1 sends to peer 2
     let peer1_outbox = peer1_doc.outbox();
     let message_to_peer2 = ChangeMessage {
         since: peer2_doc.state_vector(),
         ops: peer1_outbox,
+        gaps: Vec::new(),
     };
-    peer2_doc.apply_changes(message_to_peer2);
+    peer2_doc.apply_changes(message_to_peer2).unwrap();
 
     // Simulate sync: peer 2 sends to peer 1
     let peer2_changes = peer2_doc
         .encode_changes_since(&peer1_doc.state_vector())
         .unwrap();
-    peer1_doc.apply_changes(peer2_changes);
+    peer1_doc.apply_changes(peer2_changes).unwrap();
 
     // Both should now have the same state
     assert_eq!(peer1_doc.state_vector(), peer2_doc.state_vector());
@@ -0,0 +1,66 @@
+use md_crdt::sync::{ChangeMessage, Operation, SemanticConflict, SyncState};
+use md_crdt::{CollaborativeDocument, OpId, StateVector, ValidationLimits};
+
+fn op(counter: u64, payload: u8) -> Operation {
+    Operation {
+        id: OpId { counter, peer: 1 },
+        payload: vec![payload].into(),
+    }
+}
+
+fn message(ops: Vec<Operation>) -> ChangeMessage {
+    ChangeMessage {
+        since: StateVector::new(),
+        ops,
+        gaps: Vec::new(),
+    }
+}
+
+#[test]
+fn a_reused_id_with_another_payload_is_reported_and_ignored() {
+    let mut state = SyncState::new();
+    state
+        .apply_changes(message(vec![op(1, 1), op(3, 3)]))
+        .unwrap();
+
+    // Same payloads are plain redelivery.
+    let result = state
+        .apply_changes(message(vec![op(1, 1), op(3, 3)]))
+        .unwrap();
+    assert!(result.conflicts.is_empty());
+
+    // Applied and buffered ids are both checked.
+    let result = state
+        .apply_changes(message(vec![op(1, 9), op(3, 9)]))
+        .unwrap();
+    assert_eq!(
+        result.conflicts,
+        vec![
+            SemanticConflict::DuplicateOpMismatch { id: op(1, 0).id },
+            SemanticConflict::DuplicateOpMismatch { id: op(3, 0).id },
+        ]
+    );
+    assert_eq!(state.get(op(1, 0).id), Some(&[1][..]));
+    assert_eq!(state.pending(), vec![op(3, 3)]);
+}
+
+#[test]
+fn sessions_report_mismatched_redelivery() {
+    let mut author = CollaborativeDocument::new(1);
+    author.insert_paragraph(None, "text").unwrap();
+    let delta = author.encode_changes_since(&StateVector::new()).unwrap();
+    let mut reader = CollaborativeDocument::new(2);
+    let limits = ValidationLimits::default();
+    reader.apply_remote(delta.clone(), &limits).unwrap();
+    let before = reader.document().clone();
+
+    let mut forged = delta;
+    let id = forged.ops[0].id;
+    forged.ops[0].payload = b"forged".to_vec().into();
+    let result = reader.apply_remote(forged, &limits).unwrap();
+    assert_eq!(
+        result.conflicts,
+        vec![SemanticConflict::DuplicateOpMismatch { id }]
+    );
+    assert_eq!(reader.document(), &before);
+}
@@ -0,0 +1,174 @@
+//! Connection negotiation: protocol versions, capabilities and keep-alive.
+
+use md_crdt::sync::{
+    Capabilities, Hello, HelloError, KeepAlive, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn current_peers_agree_on_everything_this_build_implements() {
+    let negotiated = Hello::new(1).negotiate(&Hello::new(2)).unwrap();
+    assert_eq!(negotiated.remote_peer, 2);
+    assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(negotiated.capabilities, Capabilities::all());
+    assert!(negotiated.supports(Capabilities::KEEP_ALIVE));
+    assert_eq!(negotiated.keep_alive, None);
+}
+
+#[test]
+fn reserved_features_are_not_advertised_or_negotiated() {
+    let reserved = Capabilities::COMPRESSION
+        | Capabilities::AUTH
+        | Capabilities::AWARENESS
+        | Capabilities::CHUNKED_BACKFILL;
+    assert!(Hello::new(1).capabilities.intersection(reserved).is_empty());
+
+    let mut local = Hello::new(1);
+    local.capabilities = Capabilities::all() | reserved;
+    let mut remote = Hello::new(2);
+    remote.capabilities = local.capabilities;
+    let negotiated = local.negotiate(&remote).unwrap();
+    assert_eq!(negotiated.capabilities, Capabilities::KEEP_ALIVE);
+    assert!(!negotiated.supports(Capabilities::COMPRESSION));
+    assert_eq!(
+        format!("{:?}", local.capabilities),
+        "{compression, auth, awareness, chunked_backfill, keep_alive}"
+    );
+}
+
+#[test]
+fn both_sides_settle_on_the_common_features_and_the_lower_version() {
+    let mut local = Hello::new(1);
+    local.protocol_version = PROTOCOL_VERSION + 1;
+    local.capabilities = Capabilities::COMPRESSION | Capabilities::KEEP_ALIVE;
+    let mut remote = Hello::new(2);
+    remote.capabilities = Capabilities::KEEP_ALIVE | Capabilities::CHUNKED_BACKFILL;
+
+    let here = local.negotiate(&remote).unwrap();
+    let there = remote.negotiate(&local).unwrap();
+    assert_eq!(here.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(here.capabilities, Capabilities::KEEP_ALIVE);
+    assert_eq!(here.capabilities, there.capabilities);
+    assert_eq!(here.protocol_version, there.protocol_version);
+    assert!(!here.supports(Capabilities::COMPRESSION));
+
+    remote.capabilities = Capabilities::NONE;
+    assert_eq!(
+        local.negotiate(&remote).unwrap().capabilities,
+        Capabilities::NONE
+    );
+}
+
+#[test]
+fn disjoint_protocol_ranges_fail_on_both_sides() {
+    let mut newer = Hello::new(1);
+    newer.protocol_version = PROTOCOL_VERSION + 2;
+    newer.min_protocol_version = PROTOCOL_VERSION + 1;
+    let older = Hello::new(2);
+
+    let err = newer.negotiate(&older).unwrap_err();
+    assert_eq!(
+        err,
+        HelloError::ProtocolMismatch {
+            local: PROTOCOL_VERSION + 1..=PROTOCOL_VERSION + 2,
+            remote: MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION,
+        }
+    );
+    assert!(matches!(
+        older.negotiate(&newer),
+        Err(HelloError::ProtocolMismatch { .. })
+    ));
+}
+
+#[test]
+fn a_required_capability_the_other_side_lacks_is_named() {
+    let mut strict = Hello::new(1);
+    strict.required = Capabilities::AUTH;
+    let mut plain = Hello::new(2);
+    plain.capabilities = Capabilities::COMPRESSION;
+
+    assert_eq!(
+        strict.negotiate(&plain),
+        Err(HelloError::RemoteLacks(Capabilities::AUTH))
+    );
+    assert_eq!(
+        plain.negotiate(&strict),
+        Err(HelloError::LocalLacks(Capabilities::AUTH))
+    );
+    assert!(
+        strict
+            .negotiate(&plain)
+            .unwrap_err()
+            .to_string()
+            .contains("auth")
+    );
+}
+
+#[test]
+fn a_peer_cannot_connect_to_its_own_id() {
+    assert_eq!(
+        Hello::new(7).negotiate(&Hello::new(7)),
+        Err(HelloError::SamePeer(7))
+    );
+}
+
+#[test]
+fn features_from_a_newer_build_are_carried_but_not_negotiated() {
+    let unknown = Capabilities::from_bits(1 << 20);
+    let mut newer = Hello::new(1);
+    newer.capabilities = Capabilities::all() | unknown;
+    let mut echo = Hello::new(2);
+    echo.capabilities = newer.capabilities;
+
+    let negotiated = newer.negotiate(&echo).unwrap();
+    assert_eq!(negotiated.capabilities, Capabilities::all());
+    assert!(format!("{:?}", newer.capabilities).contains("0x100000"));
+}
+
+#[test]
+fn hellos_round_trip_through_serde() {
+    let mut hello = Hello::new(3);
+    hello.required = Capabilities::KEEP_ALIVE;
+    hello.keep_alive_ms = 15_000;
+    let json = serde_json::to_string(&hello).unwrap();
+    assert_eq!(serde_json::from_str::<Hello>(&json).unwrap(), hello);
+}
+
+#[test]
+fn keep_alive_uses_the_shorter_interval_when_both_sides_want_it() {
+    let mut local = Hello::new(1);
+    local.keep_alive_ms = 30_000;
+    let mut remote = Hello::new(2);
+    remote.keep_alive_ms = 10_000;
+    assert_eq!(
+        local.negotiate(&remote).unwrap().keep_alive,
+        Some(Duration::from_secs(10))
+    );
+
+    remote.keep_alive_ms = 0;
+    assert_eq!(
+        local.negotiate(&remote).unwrap().keep_alive,
+        Some(Duration::from_secs(30))
+    );
+
+    remote.keep_alive_ms = 10_000;
+    remote.capabilities = Capabilities::all().difference(Capabilities::KEEP_ALIVE);
+    assert_eq!(local.negotiate(&remote).unwrap().keep_alive, None);
+}
+
+#[test]
+fn keep_alive_pings_at_half_the_interval_and_expires_after_it() {
+    let start = Instant::now();
+    let interval = Duration::from_secs(10);
+    let mut keep_alive = KeepAlive::new(interval, start);
+    assert!(!keep_alive.ping_due(start + Duration::from_secs(4)));
+    assert!(keep_alive.ping_due(start + Duration::from_secs(5)));
+
+    keep_alive.sent(start + Duration::from_secs(5));
+    assert!(!keep_alive.ping_due(start + Duration::from_secs(9)));
+
+    assert!(!keep_alive.expired(start + interval));
+    keep_alive.heard(start + Duration::from_secs(8));
+    assert!(!keep_alive.expired(start + Duration::from_secs(18)));
+    assert!(keep_alive.expired(start + Duration::from_secs(19)));
+}
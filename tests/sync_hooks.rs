@@ -0,0 +1,221 @@
+//! Outgoing and incoming operation hooks.
+
+use md_crdt::sync::{ChangeMessage, Decision, Operation, SyncState};
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, OpId, Origin, StateVector, ValidationLimits,
+};
+use std::sync::{Arc, Mutex};
+
+fn id(peer: u64, counter: u64) -> OpId {
+    OpId { counter, peer }
+}
+
+fn op(peer: u64, counter: u64, payload: &[u8]) -> Operation {
+    Operation {
+        id: id(peer, counter),
+        payload: payload.into(),
+    }
+}
+
+fn message(ops: Vec<Operation>) -> ChangeMessage {
+    ChangeMessage {
+        since: StateVector::new(),
+        ops,
+        gaps: Vec::new(),
+    }
+}
+
+fn ids(ops: &[Operation]) -> Vec<u64> {
+    ops.iter().map(|op| op.id.counter).collect()
+}
+
+#[test]
+fn outgoing_hooks_redact_per_recipient_and_leave_the_log_alone() {
+    let mut state = SyncState::new();
+    for (counter, payload) in [(1, "public"), (2, "secret"), (3, "public")] {
+        state
+            .add_local_op(op(1, counter, payload.as_bytes()))
+            .unwrap();
+    }
+    state.on_outgoing(|op, to| (to != Some(3) || &op.payload[..] != b"secret").then_some(op));
+
+    let trusted = state.encode_changes_for(2, &StateVector::new()).unwrap();
+    let redacted = state.encode_changes_for(3, &StateVector::new()).unwrap();
+    assert_eq!(ids(&trusted.ops), vec![1, 2, 3]);
+    assert_eq!(ids(&redacted.ops), vec![1, 3]);
+    let raw = state.encode_changes_since(&StateVector::new()).unwrap();
+    assert_eq!(ids(&raw.ops), vec![1, 2, 3]);
+    assert_eq!(state.applied_ops().len(), 3);
+}
+
+#[test]
+fn outgoing_hooks_rewrite_payloads_in_registration_order() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1, b"a")).unwrap();
+    state.on_outgoing(|mut op, _| {
+        op.payload = [&op.payload[..], b"b"].concat().into();
+        Some(op)
+    });
+    state.on_outgoing(|mut op, _| {
+        op.payload = [&op.payload[..], b"c"].concat().into();
+        Some(op)
+    });
+    assert_eq!(&state.outbox()[0].payload[..], b"abc");
+    assert_eq!(state.unsent(id(1, 1)), Some(&b"a"[..]));
+}
+
+#[test]
+fn a_hook_that_changes_an_id_has_its_operation_withheld() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1, b"a")).unwrap();
+    state.add_local_op(op(1, 2, b"b")).unwrap();
+    state.on_outgoing(|mut op, _| {
+        if op.id.counter == 2 {
+            op.id.counter = 9;
+        }
+        Some(op)
+    });
+    assert_eq!(ids(&state.outbox()), vec![1]);
+}
+
+#[test]
+fn withheld_operations_still_leave_the_outbox_with_their_batch() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1, b"keep")).unwrap();
+    state.add_local_op(op(1, 2, b"drop")).unwrap();
+    state.on_outgoing(|op, _| (&op.payload[..] != b"drop").then_some(op));
+
+    let batch = state.drain_outbox(10, usize::MAX);
+    assert_eq!(ids(&batch.ops), vec![1]);
+    assert_eq!(batch.bytes, 4);
+    assert_eq!(batch.token.op_ids().len(), 2);
+    state.mark_batch_sent(batch.token);
+    assert!(state.outbox().is_empty());
+}
+
+#[test]
+fn rejected_operations_are_not_logged_and_hold_back_what_follows_them() {
+    let mut state = SyncState::new();
+    state.on_incoming(|op, _| {
+        if &op.payload[..] == b"bad" {
+            Decision::Reject
+        } else {
+            Decision::Accept
+        }
+    });
+
+    let result = state
+        .apply_changes(message(vec![
+            op(2, 1, b"ok"),
+            op(2, 2, b"bad"),
+            op(2, 3, b"ok"),
+        ]))
+        .unwrap();
+    assert_eq!(result.rejected, vec![id(2, 2)]);
+    assert_eq!(result.applied, vec![id(2, 1)]);
+    assert_eq!(result.buffered, vec![id(2, 3)]);
+    assert!(!state.contains(id(2, 2)));
+    assert_eq!(state.state_vector().get(2), Some(1));
+}
+
+#[test]
+fn replacements_are_logged_and_redeliveries_match_them() {
+    let mut state = SyncState::new();
+    let seen: Arc<Mutex<Vec<Origin>>> = Arc::default();
+    let sink = Arc::clone(&seen);
+    state.on_incoming(move |op, origin| {
+        sink.lock().unwrap().push(origin);
+        Decision::Replace(Operation {
+            id: op.id,
+            payload: op.payload.to_ascii_uppercase().into(),
+        })
+    });
+
+    state.apply_changes(message(vec![op(2, 1, b"hi")])).unwrap();
+    assert_eq!(state.get(id(2, 1)), Some(&b"HI"[..]));
+    let replay = state.apply_changes(message(vec![op(2, 1, b"hi")])).unwrap();
+    assert!(replay.conflicts.is_empty());
+    assert_eq!(*seen.lock().unwrap(), vec![Origin::Remote(2); 2]);
+}
+
+#[test]
+fn a_replacement_under_another_id_is_rejected() {
+    let mut state = SyncState::new();
+    state.on_incoming(|op, _| {
+        Decision::Replace(Operation {
+            id: OpId {
+                counter: op.id.counter + 1,
+                peer: op.id.peer,
+            },
+            payload: op.payload.clone(),
+        })
+    });
+    let result = state.apply_changes(message(vec![op(2, 1, b"x")])).unwrap();
+    assert_eq!(result.rejected, vec![id(2, 1)]);
+    assert!(result.applied.is_empty());
+    assert_eq!(state.applied_count(), 0);
+}
+
+#[test]
+fn removed_hooks_stop_running_and_clones_keep_theirs() {
+    let mut state = SyncState::new();
+    let hook = state.on_incoming(|_, _| Decision::Reject);
+    let clone = state.clone();
+    assert!(state.remove_op_hook(hook));
+    assert!(!state.remove_op_hook(hook));
+
+    let result = state.apply_changes(message(vec![op(2, 1, b"x")])).unwrap();
+    assert_eq!(result.applied.len(), 1);
+    let mut clone = clone;
+    let result = clone.apply_changes(message(vec![op(2, 1, b"x")])).unwrap();
+    assert_eq!(result.rejected.len(), 1);
+}
+
+#[test]
+fn a_session_redacts_for_one_peer_and_rejects_from_another() {
+    let mut author = CollaborativeDocument::new(1);
+    author.insert_paragraph(None, "public").unwrap();
+    author.insert_paragraph(None, "secret").unwrap();
+    author.on_outgoing(|op, to| {
+        let confidential = String::from_utf8_lossy(&op.payload).contains("secret");
+        (to != Some(3) || !confidential).then_some(op)
+    });
+
+    let mut trusted = CollaborativeDocument::new(2);
+    let mut outsider = CollaborativeDocument::new(3);
+    let limits = ValidationLimits::default();
+    trusted
+        .apply_remote(
+            author.encode_changes_for(2, &StateVector::new()).unwrap(),
+            &limits,
+        )
+        .unwrap();
+    outsider
+        .apply_remote(
+            author.encode_changes_for(3, &StateVector::new()).unwrap(),
+            &limits,
+        )
+        .unwrap();
+    let shown = |doc: &CollaborativeDocument| doc.document().serialize(EquivalenceMode::Exact);
+    assert!(shown(&trusted).contains("secret"));
+    assert!(shown(&outsider).contains("public"));
+    assert!(!shown(&outsider).contains("secret"));
+
+    let mut guarded = CollaborativeDocument::new(4);
+    guarded.on_incoming(|_, origin| {
+        if origin == Origin::Remote(1) {
+            Decision::Reject
+        } else {
+            Decision::Accept
+        }
+    });
+    let result = guarded
+        .apply_remote(
+            author.encode_changes_since(&StateVector::new()).unwrap(),
+            &limits,
+        )
+        .unwrap();
+    assert!(result.applied.is_empty());
+    assert_eq!(result.rejected.len(), 4);
+    assert!(guarded.document().blocks_in_order().is_empty());
+}
@@ -0,0 +1,223 @@
+#![cfg(feature = "metrics")]
+
+use md_crdt::metrics;
+use md_crdt::sync::{ChangeMessage, Operation, SyncState, ValidationLimits, validate_changes};
+use md_crdt::{OpId, StateVector};
+use std::sync::{Arc, Mutex};
+
+/// Held by tests that buffer operations, since the pending depth gauge is
+/// process-wide.
+static PENDING_GAUGE: Mutex<()> = Mutex::new(());
+
+fn op(peer: u64, counter: u64, payload: &[u8]) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: Arc::from(payload),
+    }
+}
+
+#[test]
+fn sync_activity_moves_the_process_counters() {
+    let _gauge = PENDING_GAUGE.lock().unwrap();
+    let before = metrics::snapshot();
+
+    let mut state = SyncState::new();
+    state
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(1, 2, b"bb"), op(1, 3, b"ccc")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    assert_eq!(metrics::snapshot().pending_depth, 2);
+    state
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(1, 1, b"a")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    let sent = state.encode_changes_since(&StateVector::new()).unwrap();
+    assert_eq!(sent.ops.len(), 3);
+
+    let rejected = ChangeMessage {
+        since: StateVector::new(),
+        ops: vec![op(1, 4, b"")],
+        gaps: Vec::new(),
+    };
+    assert!(validate_changes(&rejected, &ValidationLimits::default(), 0).is_err());
+
+    let after = metrics::snapshot();
+    assert!(after.ops_applied >= before.ops_applied + 3);
+    assert!(after.ops_buffered >= before.ops_buffered + 2);
+    assert!(after.bytes_received >= before.bytes_received + 6);
+    assert!(after.bytes_sent >= before.bytes_sent + 6);
+    assert!(after.messages_rejected > before.messages_rejected);
+    assert_eq!(after.pending_depth, 0);
+}
+
+#[test]
+fn pending_depth_adds_up_every_document() {
+    let _gauge = PENDING_GAUGE.lock().unwrap();
+    let buffering = |ops| {
+        let mut state = SyncState::new();
+        state
+            .apply_changes(ChangeMessage {
+                since: StateVector::new(),
+                ops,
+                gaps: Vec::new(),
+            })
+            .unwrap();
+        state
+    };
+
+    let first = buffering(vec![op(1, 2, b"b"), op(1, 3, b"c")]);
+    let mut second = buffering(vec![op(2, 2, b"b")]);
+    assert_eq!(metrics::snapshot().pending_depth, 3);
+    let copy = first.clone();
+    assert_eq!(metrics::snapshot().pending_depth, 5);
+
+    second
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(2, 1, b"a")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    assert_eq!(metrics::snapshot().pending_depth, 4);
+    drop(copy);
+    drop(first);
+    assert_eq!(metrics::snapshot().pending_depth, 0);
+}
+
+#[test]
+fn operations_are_counted_by_origin() {
+    use md_crdt::Origin;
+
+    let before = metrics::snapshot().by_origin;
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1, b"a")).unwrap();
+    state
+        .add_local_op_from(op(1, 2, b"b"), Origin::Undo)
+        .unwrap();
+    state
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(2, 1, b"c"), op(2, 2, b"d")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+
+    let after = metrics::snapshot().by_origin;
+    assert!(after.local > before.local);
+    assert!(after.undo > before.undo);
+    assert!(after.remote >= before.remote + 2);
+}
+
+#[test]
+fn pending_metrics_show_depth_per_peer_and_the_oldest_wait() {
+    let _gauge = PENDING_GAUGE.lock().unwrap();
+    use std::time::{Duration, Instant};
+
+    let mut state = SyncState::new();
+    let started = Instant::now();
+    assert_eq!(
+        state.pending_metrics(started),
+        metrics::PendingMetrics::default()
+    );
+
+    let mut since = StateVector::new();
+    since.set(1, 1);
+    since.set(2, 4);
+    state
+        .apply_changes(ChangeMessage {
+            since,
+            ops: vec![op(1, 2, b"a"), op(1, 3, b"b"), op(2, 5, b"c")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    let later = Instant::now() + Duration::from_secs(30);
+    let pending = state.pending_metrics(later);
+    assert_eq!(pending.depth, 3);
+    assert_eq!(
+        pending.depth_by_peer.into_iter().collect::<Vec<_>>(),
+        vec![(1, 2), (2, 1)]
+    );
+    let pending = state.pending_metrics(later);
+    assert!(pending.oldest_age >= Some(Duration::from_secs(30)));
+    assert!(pending.is_stalled(Duration::from_secs(10)));
+    assert!(!pending.is_stalled(Duration::from_secs(3600)));
+
+    let mut samples = Vec::new();
+    pending.export(|sample| samples.push(sample));
+    let by_peer: Vec<_> = samples
+        .iter()
+        .filter(|sample| sample.name == "md_crdt_document_pending_depth_by_peer")
+        .map(|sample| (sample.labels[0].1.as_str(), sample.value))
+        .collect();
+    assert_eq!(by_peer, vec![("1", 2), ("2", 1)]);
+}
+
+#[test]
+fn promotions_record_how_long_operations_waited() {
+    let _gauge = PENDING_GAUGE.lock().unwrap();
+    use std::time::Duration;
+
+    let before = metrics::snapshot();
+    let mut state = SyncState::new();
+    let mut since = StateVector::new();
+    since.set(7, 1);
+    state
+        .apply_changes(ChangeMessage {
+            since,
+            ops: vec![op(7, 2, b"b")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    state
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(7, 1, b"a")],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+
+    let after = metrics::snapshot();
+    assert!(after.promotion_latency.count() > before.promotion_latency.count());
+    assert!(after.promotion_latency.mean().is_some());
+    let bounds: Vec<_> = after
+        .promotion_latency
+        .buckets()
+        .map(|(bound, _)| bound)
+        .collect();
+    assert_eq!(bounds.first(), Some(&Some(Duration::from_millis(1))));
+    assert_eq!(bounds.last(), Some(&None));
+    assert!(after.ops_applied_per_sec(&before, Duration::from_millis(500)) >= 4.0);
+    assert_eq!(after.ops_applied_per_sec(&before, Duration::ZERO), 0.0);
+
+    let mut samples = Vec::new();
+    after.export(|sample| samples.push(sample));
+    let buckets: Vec<_> = samples
+        .iter()
+        .filter(|sample| sample.kind == metrics::SampleKind::Bucket)
+        .collect();
+    assert_eq!(buckets.len(), metrics::LATENCY_BUCKETS_MS.len() + 1);
+    assert_eq!(
+        buckets.last().unwrap().labels,
+        vec![("le", "+Inf".to_owned())]
+    );
+    assert!(
+        buckets
+            .windows(2)
+            .all(|pair| pair[0].value <= pair[1].value)
+    );
+    let count = samples
+        .iter()
+        .find(|sample| sample.name == "md_crdt_promotion_latency_ms_count")
+        .unwrap();
+    assert_eq!(count.value, buckets.last().unwrap().value);
+    assert!(samples.iter().any(|sample| {
+        sample.name == "md_crdt_ops_by_origin_total"
+            && sample.labels == vec![("origin", "remote".to_owned())]
+    }));
+}
@@ -0,0 +1,61 @@
+use md_crdt::sync::{ChangeMessage, OpClass, OpFilter, Operation, ReplicaMode, SyncState};
+use md_crdt::{OpId, StateVector};
+
+fn op(peer: u64, counter: u64, payload: u8) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: vec![payload].into(),
+    }
+}
+
+#[test]
+fn local_only_ops_stay_out_of_deltas_and_the_op_log() {
+    // Peer 1 edits; view state is minted under peer 101.
+    let mut editor = SyncState::new();
+    editor.set_op_filter(OpFilter::local_peers([101]));
+    editor.add_local_op(op(1, 1, 1)).unwrap();
+    editor.add_local_op(op(101, 1, 80)).unwrap();
+    editor.add_local_op(op(1, 2, 2)).unwrap();
+
+    assert_eq!(editor.state_vector().get(101), None);
+    assert_eq!(editor.outbox(), vec![op(1, 1, 1), op(1, 2, 2)]);
+    assert_eq!(editor.local_only_ops(), vec![op(101, 1, 80)]);
+    assert_eq!(editor.applied_ops().len(), 2);
+
+    let delta = editor.encode_changes_since(&StateVector::new()).unwrap();
+    let mut collaborator = SyncState::new();
+    let result = collaborator.apply_changes(delta).unwrap();
+    assert_eq!(result.applied.len(), 2);
+    assert!(result.buffered.is_empty());
+    assert_eq!(collaborator.state_vector(), editor.state_vector());
+
+    editor.discard_local_only(&[op(101, 1, 80).id]);
+    assert!(editor.local_only_ops().is_empty());
+}
+
+#[test]
+fn custom_filters_classify_by_payload_and_bypass_read_only_mode() {
+    let mut viewer = SyncState::new();
+    viewer.set_op_filter(OpFilter::new(|op| {
+        if op.payload.first() == Some(&0xff) {
+            OpClass::LocalOnly
+        } else {
+            OpClass::Shared
+        }
+    }));
+    viewer.set_mode(ReplicaMode::ReadOnly);
+    viewer.add_local_op(op(7, 1, 0xff)).unwrap();
+    assert!(viewer.add_local_op(op(7, 1, 1)).is_err());
+    assert_eq!(viewer.local_only_ops().len(), 1);
+
+    // Remote ops are never filtered.
+    viewer
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(2, 1, 0xff)],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    assert_eq!(viewer.state_vector().get(2), Some(1));
+    assert_eq!(viewer.local_only_ops().len(), 1);
+}
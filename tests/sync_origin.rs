@@ -0,0 +1,105 @@
+//! Update listeners learn where applied operations came from.
+
+use md_crdt::sync::{ChangeMessage, Operation, SyncState, SyncUpdate};
+use md_crdt::{CollaborativeDocument, OpId, Origin, StateVector, ValidationLimits};
+use std::sync::{Arc, Mutex};
+
+fn op(peer: u64, counter: u64) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: vec![counter as u8].into(),
+    }
+}
+
+fn record(state: &mut SyncState) -> Arc<Mutex<Vec<SyncUpdate>>> {
+    let seen: Arc<Mutex<Vec<SyncUpdate>>> = Arc::default();
+    let sink = Arc::clone(&seen);
+    state.on_update(move |update| sink.lock().unwrap().push(update.clone()));
+    seen
+}
+
+#[test]
+fn sync_updates_name_the_origin_of_their_ops() {
+    let mut state = SyncState::new();
+    let seen = record(&mut state);
+
+    state.add_local_op(op(1, 1)).unwrap();
+    state.add_local_op_from(op(1, 2), Origin::Undo).unwrap();
+    state
+        .apply_changes(ChangeMessage {
+            since: StateVector::new(),
+            ops: vec![op(7, 1)],
+            gaps: Vec::new(),
+        })
+        .unwrap();
+    state
+        .apply_changes_from(
+            ChangeMessage {
+                since: StateVector::new(),
+                ops: vec![op(8, 1)],
+                gaps: Vec::new(),
+            },
+            Origin::Import,
+        )
+        .unwrap();
+
+    let seen = seen.lock().unwrap();
+    let origins: Vec<Origin> = seen.iter().map(|update| update.origin).collect();
+    assert_eq!(
+        origins,
+        vec![
+            Origin::Local,
+            Origin::Undo,
+            Origin::Remote(7),
+            Origin::Import
+        ]
+    );
+    assert_eq!(seen[1].applied, vec![op(1, 2)]);
+    assert_eq!(seen[1].state_vector.get(1), Some(2));
+    assert!(Origin::Undo.is_local());
+    assert!(!Origin::Remote(7).is_local());
+}
+
+#[test]
+fn a_session_tells_its_own_edits_from_a_peers() {
+    let mut local = CollaborativeDocument::new(1);
+    let mut peer = CollaborativeDocument::new(2);
+    let seen: Arc<Mutex<Vec<(Origin, usize)>>> = Arc::default();
+    let sink = Arc::clone(&seen);
+    let listener = local.on_update(move |update| {
+        sink.lock()
+            .unwrap()
+            .push((update.origin, update.applied.len()))
+    });
+
+    local.insert_paragraph(None, "mine").unwrap();
+    peer.insert_paragraph(None, "theirs").unwrap();
+    let message = peer.encode_changes_since(&local.state_vector()).unwrap();
+    local
+        .apply_remote(message, &ValidationLimits::default())
+        .unwrap();
+    // A paragraph is a block insert and a text insert.
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            (Origin::Local, 1),
+            (Origin::Local, 1),
+            (Origin::Remote(2), 2)
+        ]
+    );
+
+    // A replay applies nothing and reports nothing.
+    let replay = peer.encode_changes_since(&StateVector::new()).unwrap();
+    local
+        .apply_remote(replay, &ValidationLimits::default())
+        .unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 3);
+
+    peer.insert_paragraph(None, "later").unwrap();
+    local.merge_from(&peer).unwrap();
+    assert_eq!(seen.lock().unwrap()[3], (Origin::Import, 2));
+
+    assert!(local.remove_update_listener(listener));
+    local.insert_paragraph(None, "quiet").unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 4);
+}
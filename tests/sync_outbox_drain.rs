@@ -0,0 +1,71 @@
+use md_crdt::OpId;
+use md_crdt::sync::{Operation, SyncState};
+use std::sync::Arc;
+use std::time::Instant;
+
+fn op(counter: u64, len: usize) -> Operation {
+    Operation {
+        id: OpId { counter, peer: 1 },
+        payload: vec![counter as u8; len].into(),
+    }
+}
+
+fn ids(ops: &[Operation]) -> Vec<u64> {
+    ops.iter().map(|op| op.id.counter).collect()
+}
+
+#[test]
+fn outbox_drains_in_bounded_batches_until_marked_sent() {
+    let mut state = SyncState::new();
+    for counter in 1..=5 {
+        state.add_local_op(op(counter, 10)).unwrap();
+    }
+
+    let batch = state.drain_outbox(3, 25);
+    assert_eq!(ids(&batch.ops), vec![1, 2]);
+    assert_eq!((batch.bytes, batch.remaining), (20, 3));
+    // Draining again without marking returns the same batch.
+    assert_eq!(state.drain_outbox(3, 25), batch);
+    assert_eq!(batch.token.op_ids(), &[op(1, 0).id, op(2, 0).id]);
+
+    state.mark_batch_sent_at(batch.token, Instant::now());
+    let batch = state.drain_outbox(2, usize::MAX);
+    assert_eq!(ids(&batch.ops), vec![3, 4]);
+    assert_eq!(batch.remaining, 1);
+    state.mark_batch_sent(batch.token);
+
+    let last = state.drain_outbox(10, 10);
+    assert_eq!(ids(&last.ops), vec![5]);
+    assert_eq!(last.remaining, 0);
+    state.mark_batch_sent(last.token);
+    assert!(state.drain_outbox(10, 10).is_empty());
+    assert!(state.outbox().is_empty());
+    assert_eq!(state.ack_tracker().len(), 5);
+}
+
+#[test]
+fn oversized_operations_still_drain_and_payloads_are_shared() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 100)).unwrap();
+    state.add_local_op(op(2, 1)).unwrap();
+
+    let batch = state.drain_outbox(10, 8);
+    assert_eq!(ids(&batch.ops), vec![1]);
+    let stored = state.outbox();
+    assert!(Arc::ptr_eq(&batch.ops[0].payload, &stored[0].payload));
+}
+
+#[test]
+fn stale_tokens_only_mark_operations_still_unsent() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1)).unwrap();
+    state.add_local_op(op(2, 1)).unwrap();
+    let batch = state.drain_outbox(10, 10);
+
+    state.mark_sent(&[op(1, 0).id]);
+    state.mark_confirmed(&[op(1, 0).id]);
+    state.mark_batch_sent(batch.token);
+    assert!(state.outbox().is_empty());
+    assert_eq!(state.ack_tracker().len(), 1);
+    assert!(state.ack_tracker().record(op(1, 0).id).is_none());
+}
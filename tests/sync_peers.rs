@@ -0,0 +1,135 @@
+use md_crdt::sync::{MembershipError, PeerRegistry, PeerStatus, epoch_peer_id, split_peer_id};
+use md_crdt::{OpId, OrSetOp, StateVector};
+
+/// Registry write `counter` of peer 1.
+fn write(counter: u64) -> OpId {
+    OpId { counter, peer: 1 }
+}
+
+#[test]
+fn retired_peer_ids_cannot_issue_or_reregister() {
+    let mut registry = PeerRegistry::new();
+    assert!(registry.register(7, write(1)).unwrap().is_some());
+    assert_eq!(registry.register(7, write(2)), Ok(None));
+    registry
+        .observe(OpId {
+            counter: 3,
+            peer: 7,
+        })
+        .unwrap();
+    assert_eq!(
+        registry.observe(OpId {
+            counter: 1,
+            peer: 8
+        }),
+        Err(MembershipError::UnknownPeer(8))
+    );
+
+    assert!(registry.retire(7, write(2)).unwrap().is_some());
+    assert_eq!(registry.retire(7, write(3)), Ok(None));
+    assert_eq!(
+        registry.retire(8, write(3)),
+        Err(MembershipError::UnknownPeer(8))
+    );
+    assert_eq!(registry.record(7).unwrap().status, PeerStatus::Retired);
+    assert_eq!(
+        registry.register(7, write(3)),
+        Err(MembershipError::Retired(7))
+    );
+    assert_eq!(
+        registry.observe(OpId {
+            counter: 4,
+            peer: 7
+        }),
+        Err(MembershipError::Retired(7))
+    );
+    assert_eq!(registry.active_peers().count(), 0);
+}
+
+#[test]
+fn resuming_below_the_high_water_mark_is_a_counter_reset() {
+    let mut applied = StateVector::new();
+    applied.set(5, 40);
+    let registry = PeerRegistry::from_state_vector(&applied);
+
+    assert_eq!(
+        registry.check_resume(5, 1),
+        Err(MembershipError::CounterReset {
+            peer: 5,
+            max_counter: 40,
+            next_counter: 1,
+        })
+    );
+    registry.check_resume(5, 41).unwrap();
+}
+
+#[test]
+fn rejoin_moves_a_device_to_its_next_epoch() {
+    let first = epoch_peer_id(3, 0).unwrap();
+    assert_eq!(split_peer_id(first), (3, 0));
+
+    let mut registry = PeerRegistry::new();
+    registry.register(first, write(1)).unwrap();
+    registry
+        .observe(OpId {
+            counter: 9,
+            peer: first,
+        })
+        .unwrap();
+
+    let (second, ops) = registry.rejoin(first, write(2)).unwrap();
+    assert_eq!(ops.len(), 2);
+    assert_eq!(split_peer_id(second), (3, 1));
+    assert_eq!(registry.record(first).unwrap().status, PeerStatus::Retired);
+    registry.check_resume(second, 1).unwrap();
+    registry
+        .observe(OpId {
+            counter: 1,
+            peer: second,
+        })
+        .unwrap();
+
+    // Rejoining from a stale id still advances past the newest epoch.
+    let (third, _) = registry.rejoin(first, write(3)).unwrap();
+    assert_eq!(split_peer_id(third), (3, 2));
+    assert_eq!(registry.active_peers().collect::<Vec<_>>(), vec![third]);
+
+    let last = epoch_peer_id(4, u16::MAX).unwrap();
+    registry.register(last, write(4)).unwrap();
+    assert_eq!(
+        registry.rejoin(last, write(5)),
+        Err(MembershipError::EpochExhausted(4))
+    );
+    assert_eq!(epoch_peer_id(u64::MAX, 0), None);
+}
+
+#[test]
+fn registries_converge_by_exchanging_writes() {
+    let mut first = PeerRegistry::new();
+    let mut second = PeerRegistry::new();
+    let register = first.register(7, write(1)).unwrap().unwrap();
+    second.apply(register);
+    let retire = second
+        .retire(
+            7,
+            OpId {
+                counter: 1,
+                peer: 2,
+            },
+        )
+        .unwrap()
+        .unwrap();
+    // A registration of 8 on the first registry crosses the retirement of 7.
+    first.register(8, write(2)).unwrap();
+
+    for op in second.ops_since(&first.state_vector()) {
+        first.apply(op);
+    }
+    for op in first.ops_since(&second.state_vector()) {
+        second.apply(op);
+    }
+    assert!(matches!(retire, OrSetOp::Remove { element: 7, .. }));
+    assert_eq!(first, second);
+    assert_eq!(first.active_peers().collect::<Vec<_>>(), vec![8]);
+    assert_eq!(first.record(7).unwrap().status, PeerStatus::Retired);
+}
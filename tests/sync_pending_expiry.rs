@@ -0,0 +1,95 @@
+use md_crdt::sync::{ChangeMessage, Operation, SyncState};
+use md_crdt::{CollaborativeDocument, OpId, StateVector, ValidationLimits};
+use std::time::{Duration, Instant};
+
+fn op(peer: u64, counter: u64) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: vec![counter as u8].into(),
+    }
+}
+
+fn message(ops: Vec<Operation>) -> ChangeMessage {
+    ChangeMessage {
+        since: StateVector::new(),
+        ops,
+        gaps: Vec::new(),
+    }
+}
+
+#[test]
+fn summary_names_the_first_missing_counter_of_each_peer() {
+    let mut state = SyncState::new();
+    state
+        .apply_changes(message(vec![op(1, 1), op(1, 3), op(1, 4), op(2, 5)]))
+        .unwrap();
+
+    let summary = state.pending_summary(Instant::now());
+    assert_eq!(summary.blocked, 3);
+    let awaited: Vec<_> = summary
+        .awaited
+        .iter()
+        .map(|dependency| (dependency.id, dependency.blocked))
+        .collect();
+    // Ids order by counter first.
+    assert_eq!(awaited, vec![(op(2, 1).id, 1), (op(1, 2).id, 2)]);
+}
+
+#[test]
+fn a_pending_ttl_expires_stale_ops_before_the_next_batch() {
+    let mut state = SyncState::new();
+    state.apply_changes(message(vec![op(1, 2)])).unwrap();
+    assert!(
+        state
+            .expire_pending(Instant::now(), Duration::from_secs(60))
+            .is_empty()
+    );
+
+    let expired = state.expire_pending(
+        Instant::now() + Duration::from_secs(60),
+        Duration::from_secs(60),
+    );
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].id, op(1, 2).id);
+    assert_eq!(expired[0].awaited, op(1, 1).id);
+    assert!(expired[0].age >= Duration::from_secs(60));
+    assert_eq!(state.pending_count(), 0);
+
+    // With a TTL set, `apply_changes` expires on its own and reports the drops.
+    state.set_pending_ttl(Some(Duration::ZERO));
+    state.apply_changes(message(vec![op(1, 3)])).unwrap();
+    let result = state.apply_changes(message(vec![op(1, 1)])).unwrap();
+    assert_eq!(
+        result.expired.iter().map(|op| op.id).collect::<Vec<_>>(),
+        vec![op(1, 3).id]
+    );
+    assert_eq!(result.applied, vec![op(1, 1).id]);
+    assert!(state.pending().is_empty());
+}
+
+#[test]
+fn an_expired_session_op_applies_once_retransmitted() {
+    let mut author = CollaborativeDocument::new(1);
+    author.insert_paragraph(None, "first").unwrap();
+    author.insert_paragraph(None, "second").unwrap();
+
+    // Lose the first operation; everything after it waits.
+    let mut delta = author.encode_changes_since(&StateVector::new()).unwrap();
+    let lost = delta.ops.remove(0).id;
+    let waiting = delta.ops.len();
+    let mut reader = CollaborativeDocument::new(2);
+    let limits = ValidationLimits::default();
+    reader.apply_remote(delta, &limits).unwrap();
+    let summary = reader.pending_summary(Instant::now());
+    assert_eq!(summary.blocked, waiting);
+    assert_eq!(summary.awaited[0].id, lost);
+
+    let expired = reader.expire_pending(Instant::now(), Duration::ZERO);
+    assert_eq!(expired.len(), waiting);
+    assert!(expired.iter().all(|op| op.awaited == lost));
+    assert!(reader.pending_summary(Instant::now()).is_empty());
+
+    let retransmitted = author.encode_changes_since(&reader.state_vector()).unwrap();
+    reader.apply_remote(retransmitted, &limits).unwrap();
+    assert_eq!(reader.document(), author.document());
+}
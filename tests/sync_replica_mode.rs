@@ -0,0 +1,102 @@
+use md_crdt::sync::{
+    ChangeMessage, Operation, ReadOnlyReplica, ReplicaMode, SyncState, SyncUpdate,
+};
+use md_crdt::{
+    CollaborativeDocument, EquivalenceMode, OpId, SessionError, StateVector, ValidationLimits,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+fn op(peer: u64, counter: u64) -> Operation {
+    Operation {
+        id: OpId { counter, peer },
+        payload: vec![counter as u8].into(),
+    }
+}
+
+fn message(ops: Vec<Operation>) -> ChangeMessage {
+    ChangeMessage {
+        since: StateVector::new(),
+        ops,
+        gaps: Vec::new(),
+    }
+}
+
+#[test]
+fn a_read_only_replica_applies_remote_changes_but_refuses_local_ops() {
+    let mut state = SyncState::new();
+    state.add_local_op(op(1, 1)).unwrap();
+    state.add_local_op(op(1, 2)).unwrap();
+    state.mark_sent_at(&[op(1, 1).id], Instant::now());
+
+    state.set_mode(ReplicaMode::ReadOnly);
+    assert!(state.outbox().is_empty());
+    assert!(state.ack_tracker().is_empty());
+    assert_eq!(
+        state.add_local_op(op(1, 3)),
+        Err(ReadOnlyReplica { op_id: op(1, 3).id })
+    );
+    assert_eq!(state.state_vector().get(1), Some(2));
+
+    let result = state.apply_changes(message(vec![op(2, 1)])).unwrap();
+    assert_eq!(result.applied, vec![op(2, 1).id]);
+    assert!(state.outbox().is_empty());
+
+    state.set_mode(ReplicaMode::ReadWrite);
+    state.add_local_op(op(1, 3)).unwrap();
+    assert_eq!(state.outbox(), vec![op(1, 3)]);
+}
+
+#[test]
+fn update_listeners_see_applied_and_promoted_ops() {
+    let mut state = SyncState::new();
+    let seen: Arc<Mutex<Vec<SyncUpdate>>> = Arc::default();
+    let sink = Arc::clone(&seen);
+    let listener = state.on_update(move |update| sink.lock().unwrap().push(update.clone()));
+
+    // Buffered only: nothing applied, no callback.
+    state.apply_changes(message(vec![op(2, 2)])).unwrap();
+    assert!(seen.lock().unwrap().is_empty());
+
+    state.apply_changes(message(vec![op(2, 1)])).unwrap();
+    {
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].applied, vec![op(2, 1), op(2, 2)]);
+        assert_eq!(seen[0].state_vector.get(2), Some(2));
+    }
+
+    // Clones start without listeners.
+    let mut fork = state.clone();
+    fork.apply_changes(message(vec![op(3, 1)])).unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    assert!(state.remove_update_listener(listener));
+    assert!(!state.remove_update_listener(listener));
+    state.apply_changes(message(vec![op(3, 1)])).unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn a_read_only_session_follows_without_editing() {
+    let mut writer = CollaborativeDocument::new(1);
+    writer.insert_paragraph(None, "status: ok").unwrap();
+
+    let mut follower = CollaborativeDocument::new(1);
+    follower.set_replica_mode(ReplicaMode::ReadOnly);
+    let changes = writer
+        .encode_changes_since(&follower.state_vector())
+        .unwrap();
+    follower
+        .apply_remote(changes, &ValidationLimits::default())
+        .unwrap();
+    assert!(matches!(
+        follower.insert_paragraph(None, "oops"),
+        Err(SessionError::ReadOnly(_))
+    ));
+    assert_eq!(follower.state_vector(), writer.state_vector());
+    assert_eq!(
+        follower.document().serialize(EquivalenceMode::Structural),
+        "status: ok"
+    );
+}
@@ -0,0 +1,77 @@
+use md_crdt::OpId;
+use md_crdt::sync::{Operation, RetryPolicy, SyncState};
+use std::time::{Duration, Instant};
+
+fn op(counter: u64) -> Operation {
+    Operation {
+        id: OpId { counter, peer: 1 },
+        payload: vec![counter as u8].into(),
+    }
+}
+
+fn ids(ops: &[Operation]) -> Vec<u64> {
+    ops.iter().map(|op| op.id.counter).collect()
+}
+
+#[test]
+fn unacknowledged_ops_come_back_with_exponential_backoff() {
+    let mut state = SyncState::new();
+    state.set_retry_policy(RetryPolicy {
+        initial_timeout: Duration::from_secs(1),
+        backoff_factor: 2,
+        max_timeout: Duration::from_secs(3),
+        max_attempts: None,
+    });
+    state.add_local_op(op(1)).unwrap();
+    state.add_local_op(op(2)).unwrap();
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    state.mark_sent_at(&[op(1).id, op(2).id], start);
+    assert!(state.outbox().is_empty());
+    assert!(state.ops_needing_resend(start).is_empty());
+
+    state.mark_confirmed(&[op(2).id]);
+    assert_eq!(ids(&state.ops_needing_resend(at(1))), vec![1]);
+
+    // Second wait doubles to 2s, the third is capped at 3s.
+    state.mark_sent_at(&[op(1).id], at(1));
+    assert!(state.ops_needing_resend(at(2)).is_empty());
+    assert_eq!(ids(&state.ops_needing_resend(at(3))), vec![1]);
+    state.mark_sent_at(&[op(1).id], at(3));
+    let record = *state.ack_tracker().record(op(1).id).unwrap();
+    assert_eq!(record.attempts, 3);
+    assert_eq!(record.deadline, at(6));
+
+    state.mark_confirmed(&[op(1).id]);
+    assert!(state.ops_needing_resend(at(100)).is_empty());
+    assert!(state.ack_tracker().is_empty());
+}
+
+#[test]
+fn ops_stop_resending_after_max_attempts() {
+    let mut state = SyncState::new();
+    state.set_retry_policy(RetryPolicy {
+        initial_timeout: Duration::from_secs(1),
+        max_attempts: Some(2),
+        ..RetryPolicy::default()
+    });
+    state.add_local_op(op(1)).unwrap();
+
+    let start = Instant::now();
+    state.mark_sent_at(&[op(1).id], start);
+    state.mark_sent_at(&[op(1).id], start + Duration::from_secs(1));
+    assert!(
+        state
+            .ops_needing_resend(start + Duration::from_secs(60))
+            .is_empty()
+    );
+    assert_eq!(state.ack_tracker().exhausted(), vec![op(1).id]);
+}
+
+#[test]
+fn marking_unknown_ops_sent_does_not_track_them() {
+    let mut state = SyncState::new();
+    state.mark_sent_at(&[op(7).id], Instant::now());
+    assert!(state.ack_tracker().is_empty());
+}
@@ -186,6 +186,7 @@ fn a_cell_edit_delivered_before_its_row_is_retained() {
             .into_iter()
             .filter(|operation| operation.id.peer == 2)
             .collect(),
+        gaps: Vec::new(),
     };
     delayed
         .apply_remote(edit_first, &ValidationLimits::default())
@@ -448,6 +449,7 @@ fn snapshot_preserves_pending_column_operations() {
                     .into_iter()
                     .filter(|operation| operation.id.peer == 2)
                     .collect(),
+                gaps: Vec::new(),
             },
             &ValidationLimits::default(),
         )
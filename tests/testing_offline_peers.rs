@@ -0,0 +1,114 @@
+#![cfg(feature = "storage")]
+
+use md_crdt::core::StateVector;
+use md_crdt::doc::block_id_from_op;
+use md_crdt::testing::{OfflinePeer, assert_converged};
+
+fn sync_all(peers: &mut [OfflinePeer]) {
+    for _ in 0..2 {
+        for right in 1..peers.len() {
+            let (low, high) = peers.split_at_mut(right);
+            for left in low.iter_mut() {
+                left.sync_with(&mut high[0]).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn offline_edits_and_backlog_meet_after_restart() {
+    let mut a = OfflinePeer::new(1).unwrap();
+    let mut b = OfflinePeer::new(2).unwrap();
+    let block = block_id_from_op(a.session_mut().insert_paragraph(None, "shared").unwrap());
+    a.sync_with(&mut b).unwrap();
+
+    b.session_mut().insert_text(block, 6, " offline").unwrap();
+    b.session_mut().insert_paragraph(None, "drafted").unwrap();
+    let unsent = b.unacknowledged().unwrap();
+    assert_eq!(unsent.len(), 3);
+    b.go_offline().unwrap();
+    assert!(!b.is_online());
+
+    a.session_mut().insert_text(block, 0, "> ").unwrap();
+    a.session_mut()
+        .insert_paragraph(None, "while away")
+        .unwrap();
+
+    b.resume().unwrap();
+    assert_eq!(b.unacknowledged().unwrap(), unsent);
+    a.send_to(&mut b).unwrap();
+    b.send_to(&mut a).unwrap();
+    assert!(b.unacknowledged().unwrap().is_empty());
+    assert_converged(&[&a, &b]);
+}
+
+#[test]
+fn buffered_backlog_survives_restart_and_drains_on_the_rest() {
+    let mut a = OfflinePeer::new(1).unwrap();
+    let mut c = OfflinePeer::new(3).unwrap();
+    a.session_mut().insert_paragraph(None, "one").unwrap();
+    let gap = a.session().state_vector();
+    a.session_mut().insert_paragraph(None, "two").unwrap();
+
+    let late = a.session().encode_changes_since(&gap).unwrap();
+    let result = c.receive(late).unwrap();
+    assert!(result.applied.is_empty());
+    assert!(!result.buffered.is_empty());
+
+    c.go_offline().unwrap();
+    c.resume().unwrap();
+    assert!(
+        !c.session()
+            .pending_summary(std::time::Instant::now())
+            .is_empty()
+    );
+
+    let early = a
+        .session()
+        .encode_changes_since(&StateVector::new())
+        .unwrap();
+    let result = c.receive(early).unwrap();
+    assert!(!result.applied.is_empty());
+    assert_converged(&[&a, &c]);
+}
+
+#[test]
+fn repeated_restarts_keep_three_peers_converging() {
+    let mut peers: Vec<_> = (1..=3)
+        .map(|peer| OfflinePeer::new(peer).unwrap())
+        .collect();
+    let block = block_id_from_op(
+        peers[0]
+            .session_mut()
+            .insert_paragraph(None, "start")
+            .unwrap(),
+    );
+    sync_all(&mut peers);
+
+    for round in 0..3 {
+        let offline = round % 3;
+        peers[offline].go_offline().unwrap();
+        for (index, peer) in peers.iter_mut().enumerate() {
+            if index != offline {
+                peer.session_mut()
+                    .insert_text(block, 0, &format!("{round}{index} "))
+                    .unwrap();
+            }
+        }
+        peers[offline].resume().unwrap();
+        peers[offline]
+            .session_mut()
+            .insert_text(block, 0, &format!("{round}{offline}! "))
+            .unwrap();
+        sync_all(&mut peers);
+    }
+    assert_converged(&peers.iter().collect::<Vec<_>>());
+}
+
+#[test]
+#[should_panic(expected = "is offline")]
+fn offline_peer_has_no_session() {
+    let mut peer = OfflinePeer::new(4).unwrap();
+    peer.go_offline().unwrap();
+    peer.session();
+}
@@ -0,0 +1,83 @@
+//! The corpus harness passes real-world notes and shrinks failing inputs.
+
+use md_crdt::testing::{RoundTripInvariant, check_roundtrip, roundtrip_corpus};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+#[derive(Deserialize)]
+struct SpecExample {
+    markdown: String,
+    example: u32,
+}
+
+/// Spec examples whose structural output parses to a different tree: a lone
+/// `****`, empty blockquotes, empty list items, list items indented past a
+/// marker, and links with empty text.
+const KNOWN_TREE_CHANGES: [u32; 9] = [77, 109, 239, 240, 280, 311, 313, 484, 487];
+
+#[test]
+fn real_world_notes_round_trip() {
+    let report = roundtrip_corpus("tests/fixtures/corpus").unwrap();
+    assert_eq!(report.files_checked, 3);
+    assert!(report.is_clean(), "{report}");
+}
+
+#[test]
+fn commonmark_spec_cases_fail_only_where_known() {
+    let spec = std::fs::read_to_string("tests/fixtures/commonmark-spec.json").unwrap();
+    let examples: Vec<SpecExample> = serde_json::from_str(&spec).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    for example in &examples {
+        let path = dir
+            .path()
+            .join(format!("example-{:03}.md", example.example));
+        std::fs::write(path, &example.markdown).unwrap();
+    }
+
+    let report = roundtrip_corpus(dir.path()).unwrap();
+    assert_eq!(report.files_checked, examples.len());
+    let failed: BTreeSet<u32> = report
+        .failures
+        .iter()
+        .map(|failure| {
+            assert_eq!(
+                failure.invariant,
+                RoundTripInvariant::StructuralTree,
+                "{report}"
+            );
+            let stem = failure.path.file_stem().unwrap().to_str().unwrap();
+            stem.trim_start_matches("example-").parse().unwrap()
+        })
+        .collect();
+    assert_eq!(failed, BTreeSet::from(KNOWN_TREE_CHANGES), "{report}");
+}
+
+#[test]
+fn failing_files_are_minimized_and_dumped() {
+    let corpus = tempfile::tempdir().unwrap();
+    let note = "# Links\n\nSome prose.\n\n- a list\n- of items\n\n[]()\n\nMore prose.\n";
+    std::fs::write(corpus.path().join("links.md"), note).unwrap();
+    std::fs::write(corpus.path().join("fine.md"), "# Fine\n\ntext\n").unwrap();
+    std::fs::write(corpus.path().join("notes.txt"), "[]()\n").unwrap();
+    std::fs::create_dir(corpus.path().join(".obsidian")).unwrap();
+    std::fs::write(corpus.path().join(".obsidian/hidden.md"), "[]()\n").unwrap();
+
+    let report = roundtrip_corpus(corpus.path()).unwrap();
+    assert_eq!(report.files_checked, 2);
+    let [failure] = report.failures.as_slice() else {
+        panic!("expected one failure: {report}");
+    };
+    assert!(failure.path.ends_with("links.md"));
+    assert_eq!(failure.invariant, RoundTripInvariant::StructuralTree);
+    assert_eq!(failure.minimized, "[]()\n");
+    assert_eq!(
+        check_roundtrip(&failure.minimized),
+        Err(RoundTripInvariant::StructuralTree)
+    );
+
+    let dump = tempfile::tempdir().unwrap();
+    let written = report.write_failures(&dump.path().join("cases")).unwrap();
+    assert_eq!(written.len(), 1);
+    assert!(written[0].ends_with("000-links.md"));
+    assert_eq!(std::fs::read_to_string(&written[0]).unwrap(), "[]()\n");
+}
@@ -0,0 +1,212 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn parse_events(stdout: &[u8]) -> Vec<serde_json::Value> {
+    stdout
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect()
+}
+
+/// Answer one push with 204, returning its request line, `Host` header and body.
+fn accept_push(listener: TcpListener) -> std::thread::JoinHandle<(String, String, Vec<u8>)> {
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut length = 0;
+        let mut host = String::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header == "\r\n" {
+                break;
+            }
+            let lowercase = header.to_ascii_lowercase();
+            if let Some(value) = lowercase.strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            } else if lowercase.starts_with("host:") {
+                host = header["host:".len()..].trim().to_string();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        (request_line, host, body)
+    })
+}
+
+#[test]
+#[allow(deprecated)]
+fn watch_once_ingests_every_file_and_stops() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "hello\n\nworld\n").unwrap();
+
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["watch", "--once"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let events = parse_events(&output.stdout);
+    assert_eq!(events[0]["event"], "ingested");
+    assert_eq!(events[0]["path"], "note.md");
+    assert!(events[0]["ops"].as_u64().unwrap() > 0);
+    assert_eq!(events.last().unwrap()["event"], "stopped");
+
+    // Nothing changed since, so a second pass only stops.
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["watch", "--once"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(parse_events(&output.stdout).len(), 1);
+}
+
+#[test]
+#[allow(deprecated)]
+fn watch_pushes_new_operations_to_the_remote() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "hello\n").unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/sync", listener.local_addr().unwrap());
+    let server = accept_push(listener);
+
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["watch", "--once", "--remote", &url])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let (request_line, _, body) = server.join().unwrap();
+    assert_eq!(request_line, "POST /sync HTTP/1.1\r\n");
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["path"], "note.md");
+    let pushed = body["message"]["ops"].as_array().unwrap().len();
+    assert!(pushed > 0);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let events = parse_events(&output.stdout);
+    let push = events
+        .iter()
+        .find(|event| event["event"] == "pushed")
+        .unwrap();
+    assert_eq!(push["ops"], pushed);
+}
+
+#[test]
+#[allow(deprecated)]
+fn watch_pushes_to_an_ipv6_remote() {
+    // Skip where the loopback has no IPv6 address.
+    let Ok(listener) = TcpListener::bind("[::1]:0") else {
+        return;
+    };
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "hello\n").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = accept_push(listener);
+
+    let output = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args([
+            "watch",
+            "--once",
+            "--remote",
+            &format!("http://[::1]:{port}/sync"),
+        ])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let (request_line, host, _) = server.join().unwrap();
+    assert!(output.status.success());
+    assert_eq!(request_line, "POST /sync HTTP/1.1\r\n");
+    assert_eq!(host, format!("[::1]:{port}"));
+}
+
+#[test]
+#[allow(deprecated)]
+fn watch_warns_about_plaintext_to_another_host() {
+    // Nothing to push in an empty vault, so the host is never contacted.
+    let dir = tempdir().unwrap();
+    Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["watch", "--once", "--remote", "http://sync.example/sync"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("over plain HTTP"));
+}
+
+#[test]
+#[allow(deprecated)]
+fn watch_rejects_unsupported_remotes() {
+    let dir = tempdir().unwrap();
+    for remote in [
+        "https://example.com/sync",
+        "http://::1:8080/sync",
+        "http://[::1/sync",
+        "http://[::1]8080/sync",
+        "http://example.com:port/sync",
+    ] {
+        Command::cargo_bin("md-crdt")
+            .unwrap()
+            .args(["watch", "--once", "--remote", remote])
+            .current_dir(dir.path())
+            .assert()
+            .code(2);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[allow(deprecated)]
+fn watch_ingests_changes_until_interrupted() {
+    use std::time::{Duration, Instant};
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.md"), "hello\n").unwrap();
+    let mut child = Command::cargo_bin("md-crdt")
+        .unwrap()
+        .args(["watch", "--debounce-ms", "50", "--poll-ms", "20"])
+        .current_dir(dir.path())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut next_event =
+        || -> serde_json::Value { serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() };
+    assert_eq!(next_event()["event"], "ingested");
+    assert_eq!(next_event()["event"], "watching");
+
+    fs::write(dir.path().join("note.md"), "hello\n\nagain\n").unwrap();
+    assert_eq!(next_event()["event"], "changed");
+    let ingested = next_event();
+    assert_eq!(ingested["event"], "ingested");
+    assert_eq!(ingested["path"], "note.md");
+
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(next_event()["event"], "stopped");
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        assert!(started.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(child.wait().unwrap().success());
+}
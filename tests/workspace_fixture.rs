@@ -118,7 +118,7 @@ fn contract_fixture() -> Value {
     acknowledged.set(7, 9);
 
     json!({
-        "contract_version": 5,
+        "contract_version": 6,
         "document_handle": DocumentHandle {
             vault_id,
             document_id,
@@ -189,11 +189,11 @@ fn versioned_workspace_contract_fixture_matches_public_serialization() {
     let actual = contract_fixture();
     if std::env::var_os("MD_CRDT_UPDATE_FIXTURES").is_some() {
         let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/workspace-contract-v5.json");
+            .join("tests/fixtures/workspace-contract-v6.json");
         std::fs::write(path, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
         return;
     }
-    let frozen: Value = serde_json::from_str(include_str!("fixtures/workspace-contract-v5.json"))
+    let frozen: Value = serde_json::from_str(include_str!("fixtures/workspace-contract-v6.json"))
         .expect("valid frozen workspace contract fixture");
     assert_eq!(frozen, actual);
 }
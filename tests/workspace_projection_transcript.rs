@@ -160,7 +160,7 @@ fn transcript() -> Value {
         + restart_bytes
         + affected_read.bytes_used;
     json!({
-        "fixture_version": 3,
+        "fixture_version": 4,
         "map": map,
         "map_continuation": map_continuation,
         "initial_read": initial_read,
@@ -185,12 +185,12 @@ fn deterministic_map_read_edit_read_transcript_matches_fixture() {
     let actual = transcript();
     if std::env::var_os("MD_CRDT_UPDATE_FIXTURES").is_some() {
         let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/workspace-projection-transcript-v3.json");
+            .join("tests/fixtures/workspace-projection-transcript-v4.json");
         fs::write(path, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
         return;
     }
     let frozen: Value = serde_json::from_str(include_str!(
-        "fixtures/workspace-projection-transcript-v3.json"
+        "fixtures/workspace-projection-transcript-v4.json"
     ))
     .expect("valid frozen projection transcript");
     assert_eq!(frozen, actual);
@@ -0,0 +1,306 @@
+#![cfg(feature = "yrs-interop")]
+
+use md_crdt::yjs::{YjsError, document_delta, export_update, import_update, text_delta};
+use md_crdt::{EquivalenceMode, MarkKind, OpId, Parser};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use yrs::any::Number;
+use yrs::types::Attrs;
+use yrs::types::text::YChange;
+use yrs::updates::decoder::Decode;
+use yrs::{Any, ClientID, Doc, Options, Out, ReadTxn, StateVector, Text, Transact, Update};
+
+/// Root `Y.Text` named `text`.
+const ROOT: &[u8] = &[1, 4, b't', b'e', b'x', b't'];
+
+/// One struct list for `client` starting at `clock`.
+fn client_structs(client: u8, clock: u8, structs: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![structs.len() as u8, client, clock];
+    for item in structs {
+        bytes.extend(item);
+    }
+    bytes
+}
+
+fn root_string(text: &str) -> Vec<u8> {
+    let mut item = vec![4];
+    item.extend(ROOT);
+    item.push(text.len() as u8);
+    item.extend(text.as_bytes());
+    item
+}
+
+fn plain(update: &[u8]) -> String {
+    text_delta(update, "text")
+        .unwrap()
+        .into_iter()
+        .map(|run| run.insert)
+        .collect()
+}
+
+#[test]
+fn export_matches_the_yjs_encoding_of_a_single_insert() {
+    let document = Parser::parse("ab");
+    let update = export_update(&document, "text", 1);
+    // `ydoc.getText('text').insert(0, 'ab\n')` with clientID 1.
+    let mut expected = client_structs(1, 0, &[root_string("ab\n")]);
+    expected.insert(0, 1);
+    expected.push(0);
+    assert_eq!(update, expected);
+}
+
+#[test]
+fn concurrent_root_inserts_order_by_client_in_any_struct_order() {
+    let a = client_structs(1, 0, &[root_string("a")]);
+    let b = client_structs(2, 0, &[root_string("b")]);
+    for (first, second) in [(&a, &b), (&b, &a)] {
+        let mut update = vec![2];
+        update.extend(first);
+        update.extend(second);
+        update.push(0);
+        assert_eq!(plain(&update), "ab");
+    }
+
+    // Delete set: client 1, one range at clock 0 of length 1.
+    let mut update = vec![2];
+    update.extend(&a);
+    update.extend(&b);
+    update.extend([1, 1, 1, 0, 1]);
+    assert_eq!(plain(&update), "b");
+}
+
+#[test]
+fn inserts_anchored_inside_an_item_split_it() {
+    let base = client_structs(1, 0, &[root_string("ace")]);
+    // "b" between (1, 0) and (1, 1); "d" between (1, 1) and (1, 2).
+    let b = vec![0xc4, 1, 0, 1, 1, 1, b'b'];
+    let d = vec![0xc4, 1, 1, 1, 2, 1, b'd'];
+    let mut update = vec![2];
+    update.extend(client_structs(2, 0, &[b, d]));
+    update.extend(&base);
+    update.push(0);
+    assert_eq!(plain(&update), "abcde");
+}
+
+#[test]
+fn marks_and_headings_round_trip_through_yjs() {
+    let document = Parser::parse("# Title\n\nplain **bold** [site](https://example.com) *it*\n");
+    let update = export_update(&document, "content", 7);
+
+    let delta = text_delta(&update, "content").unwrap();
+    assert_eq!(delta, document_delta(&document));
+    assert!(delta.iter().any(|run| run.insert == "bold"
+        && run.attributes.get("bold") == Some(&serde_json::Value::Bool(true))));
+    assert!(delta.iter().any(|run| run.insert == "site"
+        && run.attributes.get("link") == Some(&serde_json::json!("https://example.com"))));
+
+    let imported = import_update(&update, "content").unwrap();
+    assert_eq!(
+        imported.serialize(EquivalenceMode::Structural),
+        document.serialize(EquivalenceMode::Structural)
+    );
+    assert_eq!(import_update(&update, "content").unwrap(), imported);
+    assert!(text_delta(&update, "other").unwrap().is_empty());
+}
+
+#[test]
+fn custom_marks_map_to_namespaced_kinds() {
+    let mut document = Parser::parse("note flag\n");
+    let block = document.blocks_in_order()[0].id;
+    for (counter, (range, kind)) in [
+        (0..4, MarkKind::custom("yjs", "comment").unwrap()),
+        (5..9, MarkKind::custom("app", "flag").unwrap()),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let id = OpId {
+            counter: 100 + counter as u64,
+            peer: 3,
+        };
+        let (start, end) = document.grapheme_range_to_anchors(block, range).unwrap();
+        document
+            .set_mark(block, id, kind, start, end, BTreeMap::new(), id)
+            .unwrap();
+    }
+    let update = export_update(&document, "content", 7);
+
+    let delta = text_delta(&update, "content").unwrap();
+    let keys: Vec<_> = delta
+        .iter()
+        .flat_map(|run| run.attributes.keys().map(String::as_str))
+        .collect();
+    assert_eq!(keys, ["comment", "app:flag"]);
+
+    let imported = import_update(&update, "content").unwrap();
+    let kinds: Vec<_> = imported.blocks_in_order()[0]
+        .marks
+        .iter_active_intervals()
+        .map(|interval| interval.kind.to_string())
+        .collect();
+    assert_eq!(kinds.len(), 2);
+    assert!(kinds.contains(&"yjs:comment".to_string()));
+    assert!(kinds.contains(&"app:flag".to_string()));
+}
+
+#[test]
+fn malformed_updates_are_rejected() {
+    let update = export_update(&Parser::parse("abc"), "text", 1);
+    assert_eq!(
+        text_delta(&update[..update.len() - 3], "text"),
+        Err(YjsError::Truncated)
+    );
+    // "b" anchored on a struct the update does not contain.
+    let mut dangling = vec![1];
+    dangling.extend(client_structs(2, 0, &[vec![0x84, 1, 5, 1, b'b']]));
+    dangling.push(0);
+    assert_eq!(
+        text_delta(&dangling, "text"),
+        Err(YjsError::MissingDependency {
+            client: 1,
+            clock: 5
+        })
+    );
+}
+
+// The tests below check the bridge against yrs, the Rust port of Yjs, rather
+// than against hand-encoded bytes.
+
+/// A yrs document writing as `client`.
+fn yrs_doc(client: u64) -> Doc {
+    Doc::with_options(Options {
+        client_id: ClientID::new(client),
+        ..Options::default()
+    })
+}
+
+fn yrs_update(doc: &Doc) -> Vec<u8> {
+    doc.transact()
+        .encode_state_as_update_v1(&StateVector::default())
+}
+
+fn yrs_apply(doc: &Doc, update: &[u8]) {
+    doc.transact_mut()
+        .apply_update(Update::decode_v1(update).unwrap())
+        .unwrap();
+}
+
+fn json(value: &Any) -> serde_json::Value {
+    match value {
+        Any::Null | Any::Undefined => serde_json::Value::Null,
+        Any::Bool(value) => serde_json::Value::Bool(*value),
+        Any::Number(Number::Int(value)) => serde_json::json!(value),
+        Any::Number(Number::Float(value)) => serde_json::json!(value),
+        Any::String(value) => serde_json::Value::String(value.to_string()),
+        other => panic!("unexpected attribute value {other:?}"),
+    }
+}
+
+/// `Y.Text.toDelta()` as yrs computes it, in [`text_delta`]'s shape.
+fn yrs_delta(doc: &Doc, root: &str) -> Vec<(String, BTreeMap<String, serde_json::Value>)> {
+    let text = doc.get_or_insert_text(root);
+    text.diff(&doc.transact(), YChange::identity)
+        .into_iter()
+        .map(|diff| {
+            let Out::Any(Any::String(insert)) = diff.insert else {
+                panic!("unexpected embed {:?}", diff.insert);
+            };
+            let attributes = diff
+                .attributes
+                .into_iter()
+                .flat_map(|attributes| *attributes)
+                .map(|(key, value)| (key.to_string(), json(&value)))
+                .collect();
+            (insert.to_string(), attributes)
+        })
+        .collect()
+}
+
+fn delta(update: &[u8], root: &str) -> Vec<(String, BTreeMap<String, serde_json::Value>)> {
+    text_delta(update, root)
+        .unwrap()
+        .into_iter()
+        .map(|run| (run.insert, run.attributes))
+        .collect()
+}
+
+fn attrs(entries: &[(&str, Any)]) -> Attrs {
+    entries
+        .iter()
+        .map(|(key, value)| (Arc::from(*key), value.clone()))
+        .collect()
+}
+
+#[test]
+fn updates_written_by_yrs_import() {
+    let a = yrs_doc(1);
+    let text = a.get_or_insert_text("content");
+    {
+        let mut txn = a.transact_mut();
+        text.insert(&mut txn, 0, "Title\nHello brave world\n");
+        text.format(&mut txn, 5, 1, attrs(&[("header", Any::from(2))]));
+        text.format(&mut txn, 6, 5, attrs(&[("bold", Any::Bool(true))]));
+    }
+
+    // A second client deletes a word, links another and adds a paragraph while
+    // the first extends the bold run and flags the title.
+    let b = yrs_doc(2);
+    yrs_apply(&b, &yrs_update(&a));
+    {
+        let text = b.get_or_insert_text("content");
+        let mut txn = b.transact_mut();
+        text.remove_range(&mut txn, 12, 6);
+        text.format(
+            &mut txn,
+            12,
+            5,
+            attrs(&[("link", Any::from("https://example.com"))]),
+        );
+        text.insert(&mut txn, 18, "More text\n");
+    }
+    {
+        let mut txn = a.transact_mut();
+        text.insert(&mut txn, 11, "!");
+        text.format(&mut txn, 0, 5, attrs(&[("app:flag", Any::Bool(true))]));
+    }
+    yrs_apply(&a, &yrs_update(&b));
+    let update = yrs_update(&a);
+
+    assert_eq!(delta(&update, "content"), yrs_delta(&a, "content"));
+    let document = import_update(&update, "content").unwrap();
+    assert_eq!(
+        document.serialize(EquivalenceMode::Structural),
+        "## Title\n\n**Hello!** [world](https://example.com)\n\nMore text"
+    );
+}
+
+#[test]
+fn exports_apply_in_yrs() {
+    let document = Parser::parse("# Title\n\nplain **bold** [site](https://example.com) *it*\n");
+    let update = export_update(&document, "content", 7);
+
+    let doc = yrs_doc(9);
+    yrs_apply(&doc, &update);
+    let expected: Vec<_> = document_delta(&document)
+        .into_iter()
+        .map(|run| (run.insert, run.attributes))
+        .collect();
+    assert_eq!(yrs_delta(&doc, "content"), expected);
+
+    // yrs builds on the export, and the result imports back.
+    {
+        let text = doc.get_or_insert_text("content");
+        let mut txn = doc.transact_mut();
+        let end = text.len(&txn);
+        text.insert(&mut txn, end, "tail\n");
+        text.remove_range(&mut txn, 0, 2);
+    }
+    let update = yrs_update(&doc);
+    assert_eq!(delta(&update, "content"), yrs_delta(&doc, "content"));
+    assert_eq!(
+        import_update(&update, "content")
+            .unwrap()
+            .serialize(EquivalenceMode::Structural),
+        "# tle\n\nplain **bold** [site](https://example.com) *it*\n\ntail"
+    );
+}